@@ -0,0 +1,72 @@
+// SPDX-License-Identifier: LGPL-3.0-only
+
+//! Preprocessing throughput on wall-of-text messages.
+//!
+//! `single_pass` is what notifications go through. `separate_passes` runs the standalone
+//! extractors one after another, the way `preprocess_message` used to build its result,
+//! as the baseline to compare against.
+
+use SC_Sub_Poster::MessagePreprocessor;
+use criterion::{BenchmarkId, Criterion, Throughput, criterion_group, criterion_main};
+use std::hint::black_box;
+
+/// Paragraphs of the kind pasted into busy rooms, mixing prose, markup and mentions.
+const PARAGRAPHS: &[&str] = &[
+    "Patch notes for tonight's server restart: the map rotation now includes \
+     cp_process and koth_harvest, and the votekick cooldown went from 5 to 10 minutes. ",
+    "@[U:1:1531059355] can you check the [url=https://kether.pl/bans]ban list[/url]? \
+     Someone appealed with ːsteamsadː and a very long essay. ",
+    "[quote=76561198012345678;1699999999;3]is the event still on saturday?[/quote] \
+     Yes, 20:00 CET, see https://kether.pl/events (sign-ups close friday). ",
+    "[spoiler]the secret map is [b]not[/b] ready yet[/spoiler] [emoticon]steamhappy[/emoticon] ",
+    "[code]sv_cheats 0; mp_timelimit 30; // @all ignore this[/code] \
+     Config is in the pinned message, @here please read it before asking. ",
+    "Zażółć gęślą jaźń — non-ASCII text shows up in most rooms too. \
+     [og url=\"https://kether.pl\" title=\"Kether\"][/og] ",
+];
+
+/// A message of at least `len` bytes built from `PARAGRAPHS`.
+fn message_of(len: usize) -> String {
+    let mut message = String::with_capacity(len + 256);
+    for paragraph in PARAGRAPHS.iter().cycle() {
+        if message.len() >= len {
+            break;
+        }
+        message.push_str(paragraph);
+    }
+    message
+}
+
+fn preprocess(c: &mut Criterion) {
+    let mut group = c.benchmark_group("preprocess");
+    for len in [512, 4096, 8192] {
+        let message = message_of(len);
+        group.throughput(Throughput::Bytes(message.len() as u64));
+
+        group.bench_with_input(BenchmarkId::new("single_pass", len), &message, |b, m| {
+            b.iter(|| MessagePreprocessor::preprocess_message(black_box(m)))
+        });
+
+        group.bench_with_input(
+            BenchmarkId::new("separate_passes", len),
+            &message,
+            |b, m| {
+                b.iter(|| {
+                    let m = black_box(m);
+                    (
+                        MessagePreprocessor::parse_bbcode(m),
+                        MessagePreprocessor::strip_bbcode(m),
+                        MessagePreprocessor::extract_emoticons(m),
+                        MessagePreprocessor::extract_mentions(m),
+                        m.to_string(),
+                        m.to_string(),
+                    )
+                })
+            },
+        );
+    }
+    group.finish();
+}
+
+criterion_group!(benches, preprocess);
+criterion_main!(benches);
@@ -1,10 +1,13 @@
 use SC_Sub_Poster::{
-    ChatRoomClient, EnhancedGroupChatMessage, FriendMessage, LogOn, MessagePreprocessor,
-    ReactionEvent, ReactionType, SendGroupMessageParams, chat_helpers, preprocessing_helpers,
+    ChatRoomClient, EnhancedGroupChatMessage, FriendMessage, GroupInvite, LogOn,
+    MessagePreprocessor, ReactionEvent, ReactionType, SendGroupMessageParams, chat_helpers,
+    preprocessing_helpers,
 };
+use std::collections::HashSet;
 use std::time::Duration;
 use std::{env, error::Error};
 use tokio::time::sleep;
+use tokio_stream::StreamExt;
 
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn Error>> {
@@ -81,7 +84,7 @@ async fn main() -> Result<(), Box<dyn Error>> {
         let preprocessed = MessagePreprocessor::preprocess_message(message);
 
         println!("     Original: {}", preprocessed.original_message);
-        println!("     Modified: {}", preprocessed.modified_message);
+        println!("     Modified: {}", preprocessed.modified_message());
 
         if let Some(mentions) = &preprocessed.mentions {
             println!(
@@ -110,8 +113,12 @@ async fn main() -> Result<(), Box<dyn Error>> {
             let mention = preprocessing_helpers::create_mention(steam_id);
             println!("  Mention: {}", mention);
 
-            let message_with_mention =
-                chat_helpers::create_message_with_mentions("Hello", &[steam_id]);
+            let message_with_mention = chat_helpers::create_message_with_mentions(
+                "Hello",
+                &[steam_id],
+                chat_helpers::MentionPlacement::Suffix,
+                &[],
+            );
             println!("  Message with mention: {}", message_with_mention);
         }
         Err(e) => {
@@ -144,8 +151,11 @@ async fn main() -> Result<(), Box<dyn Error>> {
     let test_message = "test [mention=here]@online[/mention]";
     println!("Sending message: \"{}\"", test_message);
 
-    let params = SendGroupMessageParams::new(group_id, chat_id, test_message);
-    match chat_client.send_group_message(params).await {
+    // Plain `(chat_group_id, chat_id, message)` tuples convert into `SendGroupMessageParams`
+    match chat_client
+        .send_group_message((group_id, chat_id, test_message))
+        .await
+    {
         Ok(preprocessed_response) => {
             println!("✓ Group message sent successfully!");
             println!(
@@ -154,7 +164,7 @@ async fn main() -> Result<(), Box<dyn Error>> {
             );
             println!(
                 "  Modified message: {}",
-                preprocessed_response.modified_message
+                preprocessed_response.modified_message()
             );
             println!(
                 "  Server timestamp: {}",
@@ -283,6 +293,17 @@ async fn main() -> Result<(), Box<dyn Error>> {
         }
     }
 
+    // Borrow the params to send the same message more than once without cloning it
+    let params = SendGroupMessageParams::new(group_id, chat_id, "[b]Demo[/b] reusable message")
+        .with_echo_to_sender(true);
+    let messaging = chat_client.messaging();
+    for attempt in 1..=2 {
+        match messaging.send_group_message_ref(&params).await {
+            Ok(sent) => println!("✓ Borrowed send #{}: {}", attempt, sent.modified_message()),
+            Err(e) => println!("✗ Borrowed send #{} failed: {:?}", attempt, e),
+        }
+    }
+
     println!("\n5. Setting up enhanced message listeners...");
 
     // Spawn a task to listen for friend messages
@@ -358,6 +379,80 @@ async fn main() -> Result<(), Box<dyn Error>> {
         }
     });
 
+    // Stream-based alternative: no 'static callback, composable with select!
+    let stream_chat_client = ChatRoomClient::new(logon.connection().clone());
+    tokio::spawn(async move {
+        println!("  Listening for group messages via stream...");
+        let messages = stream_chat_client.group_message_stream();
+        tokio::pin!(messages);
+        let deadline = sleep(Duration::from_secs(5));
+        tokio::pin!(deadline);
+
+        let mut seen = 0;
+        loop {
+            tokio::select! {
+                item = messages.next() => match item {
+                    Some(Ok(msg)) => {
+                        seen += 1;
+                        println!(
+                            "🌊 Streamed Group Message #{} from {}: {}",
+                            seen,
+                            chat_helpers::format_steam_id(msg.sender_steam_id),
+                            msg.message
+                        );
+                    }
+                    Some(Err(e)) => println!("  Stream error (continuing): {}", e),
+                    None => break,
+                },
+                _ = &mut deadline => break,
+            }
+        }
+        println!("  Stream listener saw {} message(s)", seen);
+    });
+
+    // Auto-accept invites to allowlisted groups (comma-separated IDs in AUTO_JOIN_GROUPS)
+    let allowed_groups: HashSet<u64> = env::var("AUTO_JOIN_GROUPS")
+        .unwrap_or_default()
+        .split(',')
+        .filter_map(|id| id.trim().parse().ok())
+        .collect();
+    let invite_chat_client = ChatRoomClient::new(logon.connection().clone());
+    tokio::spawn(async move {
+        println!("  Listening for group invites...");
+        let joiner = invite_chat_client.clone();
+        if let Err(e) = invite_chat_client
+            .listen_for_group_invites(move |invite: GroupInvite| {
+                if !allowed_groups.contains(&invite.chat_group_id) {
+                    println!(
+                        "✉️  Ignoring invite to {} ({})",
+                        invite.group_name, invite.chat_group_id
+                    );
+                    return;
+                }
+                println!(
+                    "✉️  Accepting invite to {} ({})",
+                    invite.group_name, invite.chat_group_id
+                );
+                let joiner = joiner.clone();
+                tokio::spawn(async move {
+                    if let Err(e) = joiner
+                        .join_chat_room(
+                            invite.chat_group_id,
+                            invite.default_chat_id,
+                            invite.invite_code,
+                        )
+                        .await
+                    {
+                        println!("  Failed to join {}: {}", invite.chat_group_id, e);
+                    }
+                });
+            })
+            .await
+        {
+            println!("  Invite listener error: {:?}", e);
+        }
+    });
+
     println!("\n6. Demo completed!");
     println!("The enhanced message listeners are now running in the background.");
     println!("Features demonstrated:");
@@ -367,6 +462,8 @@ async fn main() -> Result<(), Box<dyn Error>> {
     println!("  ✓ Message reactions (add/remove/list/history)");
     println!("  ✓ Real-time reaction event listening");
     println!("  ✓ Real-time message listening with preprocessing");
+    println!("  ✓ Stream-based message listening with select!");
+    println!("  ✓ Auto-accepting group invites from an allowlist");
 
     // Keep the program running for a bit to show the listeners
     println!("\nWaiting 5 seconds to demonstrate listeners...");
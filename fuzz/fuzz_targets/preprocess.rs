@@ -0,0 +1,31 @@
+// SPDX-License-Identifier: LGPL-3.0-only
+
+//! Run with `cargo +nightly fuzz run preprocess`; seeds live in `corpus/preprocess`.
+
+#![no_main]
+
+use SC_Sub_Poster::preprocessing::{MalformedTagPolicy, MessagePreprocessor};
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|data: &[u8]| {
+    // Steam delivers UTF-8, with invalid sequences replaced before they reach us.
+    let message = String::from_utf8_lossy(data);
+
+    let preprocessed = MessagePreprocessor::preprocess_message(&message);
+    assert_eq!(preprocessed.original_message, message);
+
+    for policy in [
+        MalformedTagPolicy::KeepAsText,
+        MalformedTagPolicy::Drop,
+        MalformedTagPolicy::Error,
+    ] {
+        let preprocessor = MessagePreprocessor::default().with_malformed_tag_policy(policy);
+        let _ = preprocessor.try_parse(&message);
+        let _ = preprocessor.validate(&message);
+    }
+
+    // Rendering is canonical, so parsing the rendered text must give it back unchanged.
+    let rendered = MessagePreprocessor::render_bbcode(&preprocessed.message_bbcode_parsed);
+    let reparsed = MessagePreprocessor::parse_bbcode(&rendered);
+    assert_eq!(MessagePreprocessor::render_bbcode(&reparsed), rendered);
+});
@@ -0,0 +1,500 @@
+// SPDX-License-Identifier: LGPL-3.0-only
+
+use crate::chatroom::{ChatError, ChatRoomClient, EnhancedGroupChatMessage, ResubscribePolicy};
+use crate::errors::{RetryDisposition, classify_network_error};
+use crate::filter::RoomFilter;
+use regex::{Regex, RegexSet};
+use std::collections::HashSet;
+use std::ops::Range;
+use std::sync::{Arc, Mutex, MutexGuard};
+use steamid_ng::SteamID;
+use tokio::sync::Notify;
+use tokio::sync::broadcast::{self, error::RecvError};
+use tokio::time::sleep;
+use tokio_stream::StreamExt;
+
+/// Messages buffered per subscriber before it starts lagging.
+const BUS_CAPACITY: usize = 256;
+
+/// Fan-out of incoming group messages to any number of subscribers.
+///
+/// Obtained from `ChatRoomClient::message_bus`. A single background task consumes the
+/// group message notifications, preprocesses each message once, and broadcasts it to
+/// every receiver created with `subscribe`. Stream errors are retried with
+/// `ResubscribePolicy::default()`; when the task gives up, receivers see the end of the bus
+/// and the next `ChatRoomClient::message_bus` call starts a new one. The task also stops,
+/// along with its notification subscription, once every handle and receiver is dropped.
+#[derive(Debug, Clone)]
+pub struct MessageBus {
+    sender: broadcast::WeakSender<Arc<EnhancedGroupChatMessage>>,
+    matchers: Arc<Mutex<MatcherRegistry>>,
+    handles: Arc<Handles>,
+}
+
+/// Shared by every `MessageBus` clone and `BusReceiver`; dropping the last one tells the
+/// bus task to stop, even if no notification ever arrives again.
+#[derive(Debug)]
+struct Handles {
+    closed: Arc<Notify>,
+}
+
+impl Drop for Handles {
+    fn drop(&mut self) {
+        self.closed.notify_one();
+    }
+}
+
+/// Item received from a [`MessageBus`] subscription.
+#[derive(Debug, Clone)]
+pub enum BusEvent {
+    /// An incoming group message, shared between all subscribers.
+    Message(Arc<EnhancedGroupChatMessage>),
+    /// The receiver fell behind and `skipped` messages were dropped for it.
+    Lagged {
+        /// Number of messages this receiver missed.
+        skipped: u64,
+    },
+}
+
+/// Selects the bus messages delivered to a `MessageBus::subscribe_matching` receiver.
+///
+/// A message matches when its room passes the room filter, its sender passes the sender
+/// lists, and, if any keywords or patterns are set, at least one of them matches the
+/// modified message text. Keywords match case-insensitively as substrings. Patterns are
+/// combined with those of every other matching subscription into one `RegexSet`, so each
+/// message is scanned once however many subscriptions exist; put flags such as `(?i)`
+/// in the pattern itself, since builder options are not carried over.
+///
+/// # Example
+///
+/// ```
+/// use SC_Sub_Poster::MessageMatcher;
+/// use regex::Regex;
+/// use steamid_ng::SteamID;
+///
+/// let scam_links = MessageMatcher::new()
+///     .with_pattern(Regex::new(r"(?i)steamcommunlty\.com|free-skins").unwrap())
+///     .with_keywords(["gift", "giveaway"])
+///     .deny_sender(SteamID::from(76561197960287930));
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct MessageMatcher {
+    keywords: Vec<String>,
+    patterns: Vec<Regex>,
+    allowed_senders: HashSet<u64>,
+    denied_senders: HashSet<u64>,
+    rooms: Option<RoomFilter>,
+}
+
+impl MessageMatcher {
+    /// Create a matcher accepting every message.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Match messages containing any of `keywords`, ignoring case.
+    ///
+    /// # Returns
+    ///
+    /// `Self` for method chaining (builder pattern).
+    pub fn with_keywords<I, K>(mut self, keywords: I) -> Self
+    where
+        I: IntoIterator<Item = K>,
+        K: AsRef<str>,
+    {
+        self.keywords.extend(
+            keywords
+                .into_iter()
+                .map(|keyword| keyword.as_ref().to_lowercase()),
+        );
+        self
+    }
+
+    /// Match messages whose text matches `pattern`.
+    ///
+    /// # Returns
+    ///
+    /// `Self` for method chaining (builder pattern).
+    pub fn with_pattern(mut self, pattern: Regex) -> Self {
+        self.patterns.push(pattern);
+        self
+    }
+
+    /// Only match messages from `sender`; may be called repeatedly.
+    ///
+    /// # Returns
+    ///
+    /// `Self` for method chaining (builder pattern).
+    pub fn allow_sender(mut self, sender: SteamID) -> Self {
+        self.allowed_senders.insert(u64::from(sender));
+        self
+    }
+
+    /// Never match messages from `sender`, e.g. moderators; beats `allow_sender`.
+    ///
+    /// # Returns
+    ///
+    /// `Self` for method chaining (builder pattern).
+    pub fn deny_sender(mut self, sender: SteamID) -> Self {
+        self.denied_senders.insert(u64::from(sender));
+        self
+    }
+
+    /// Only match messages from rooms accepted by `filter`.
+    ///
+    /// # Returns
+    ///
+    /// `Self` for method chaining (builder pattern).
+    pub fn filter_rooms(mut self, filter: RoomFilter) -> Self {
+        self.rooms = Some(filter);
+        self
+    }
+
+    /// Everything but the text conditions, which need the shared pattern scan.
+    fn accepts_origin(&self, message: &EnhancedGroupChatMessage) -> bool {
+        let sender = u64::from(message.sender_steam_id);
+        self.rooms
+            .as_ref()
+            .is_none_or(|rooms| rooms.matches(message.chat_group_id, message.chat_id))
+            && !self.denied_senders.contains(&sender)
+            && (self.allowed_senders.is_empty() || self.allowed_senders.contains(&sender))
+    }
+}
+
+/// Matching subscriptions and the combined pattern set evaluated for all of them.
+#[derive(Debug, Default)]
+struct MatcherRegistry {
+    subscriptions: Vec<MatchingSubscription>,
+    patterns: Option<RegexSet>,
+}
+
+#[derive(Debug)]
+struct MatchingSubscription {
+    matcher: MessageMatcher,
+    /// Indices of this matcher's patterns in `MatcherRegistry::patterns`.
+    patterns: Range<usize>,
+    sender: broadcast::Sender<Arc<EnhancedGroupChatMessage>>,
+}
+
+impl MatcherRegistry {
+    fn add(
+        &mut self,
+        matcher: MessageMatcher,
+    ) -> broadcast::Receiver<Arc<EnhancedGroupChatMessage>> {
+        let (sender, receiver) = broadcast::channel(BUS_CAPACITY);
+        self.subscriptions.push(MatchingSubscription {
+            matcher,
+            patterns: 0..0,
+            sender,
+        });
+        self.rebuild();
+        receiver
+    }
+
+    fn rebuild(&mut self) {
+        let mut sources = Vec::new();
+        for subscription in &mut self.subscriptions {
+            let start = sources.len();
+            sources.extend(
+                subscription
+                    .matcher
+                    .patterns
+                    .iter()
+                    .map(|pattern| pattern.as_str().to_owned()),
+            );
+            subscription.patterns = start..sources.len();
+        }
+        self.patterns = if sources.is_empty() {
+            None
+        } else {
+            // Every source already compiled on its own, so the set compiles too.
+            RegexSet::new(&sources).ok()
+        };
+    }
+
+    /// Deliver `message` to every matching subscription, dropping closed ones.
+    fn dispatch(&mut self, message: &Arc<EnhancedGroupChatMessage>) {
+        let before = self.subscriptions.len();
+        self.subscriptions
+            .retain(|subscription| subscription.sender.receiver_count() > 0);
+        if self.subscriptions.len() != before {
+            self.rebuild();
+        }
+        if self.subscriptions.is_empty() {
+            return;
+        }
+
+        let text = message.preprocessed.modified_message();
+        let lowercase = text.to_lowercase();
+        let matched = self
+            .patterns
+            .as_ref()
+            .map(|patterns| patterns.matches(text));
+        for subscription in &self.subscriptions {
+            let matcher = &subscription.matcher;
+            if !matcher.accepts_origin(message) {
+                continue;
+            }
+            let unconditional = matcher.keywords.is_empty() && matcher.patterns.is_empty();
+            let keyword_hit = matcher
+                .keywords
+                .iter()
+                .any(|keyword| lowercase.contains(keyword.as_str()));
+            let pattern_hit = matched.as_ref().is_some_and(|matched| {
+                subscription
+                    .patterns
+                    .clone()
+                    .any(|index| matched.matched(index))
+            });
+            if unconditional || keyword_hit || pattern_hit {
+                // Sending only fails when the receiver was dropped meanwhile.
+                let _ = subscription.sender.send(message.clone());
+            }
+        }
+    }
+}
+
+/// Receiving half of a [`MessageBus`] subscription.
+#[derive(Debug)]
+pub struct BusReceiver {
+    inner: broadcast::Receiver<Arc<EnhancedGroupChatMessage>>,
+    _handles: Arc<Handles>,
+}
+
+impl MessageBus {
+    /// Spawn the broadcast task; `client` should not share the caller's bus slot.
+    pub(crate) fn start(client: ChatRoomClient) -> Self {
+        let (sender, _) = broadcast::channel(BUS_CAPACITY);
+        let (bus, closed) = Self::with_sender(&sender);
+        tokio::spawn(run_bus(client, sender, bus.matchers.clone(), closed));
+        bus
+    }
+
+    /// A bus broadcasting from `sender`, and the signal fired once its last handle drops.
+    fn with_sender(
+        sender: &broadcast::Sender<Arc<EnhancedGroupChatMessage>>,
+    ) -> (Self, Arc<Notify>) {
+        let closed = Arc::new(Notify::new());
+        let bus = Self {
+            sender: sender.downgrade(),
+            matchers: Arc::default(),
+            handles: Arc::new(Handles {
+                closed: closed.clone(),
+            }),
+        };
+        (bus, closed)
+    }
+
+    /// Whether the background task is still broadcasting.
+    pub fn is_running(&self) -> bool {
+        self.sender.upgrade().is_some()
+    }
+
+    /// Subscribe to messages received from now on.
+    ///
+    /// If the bus has already shut down, the receiver is closed immediately.
+    pub fn subscribe(&self) -> BusReceiver {
+        let inner = match self.sender.upgrade() {
+            Some(sender) => sender.subscribe(),
+            None => broadcast::channel(1).1,
+        };
+        self.receiver(inner)
+    }
+
+    /// Subscribe to messages received from now on that `matcher` accepts.
+    ///
+    /// Matching happens once per message in the bus task, so non-matching messages never
+    /// reach the receiver or count towards its lag. If the bus has already shut down, the
+    /// receiver is closed immediately.
+    ///
+    /// # Arguments
+    ///
+    /// * `matcher` - Keywords, patterns, sender lists and rooms to select messages by
+    pub fn subscribe_matching(&self, matcher: MessageMatcher) -> BusReceiver {
+        if !self.is_running() {
+            return self.receiver(broadcast::channel(1).1);
+        }
+        let inner = lock(&self.matchers).add(matcher);
+        self.receiver(inner)
+    }
+
+    fn receiver(&self, inner: broadcast::Receiver<Arc<EnhancedGroupChatMessage>>) -> BusReceiver {
+        BusReceiver {
+            inner,
+            _handles: self.handles.clone(),
+        }
+    }
+}
+
+fn lock(matchers: &Mutex<MatcherRegistry>) -> MutexGuard<'_, MatcherRegistry> {
+    matchers
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner())
+}
+
+impl BusReceiver {
+    /// Wait for the next event.
+    ///
+    /// # Returns
+    ///
+    /// `None` once the bus has shut down and all buffered messages were received.
+    pub async fn recv(&mut self) -> Option<BusEvent> {
+        match self.inner.recv().await {
+            Ok(message) => Some(BusEvent::Message(message)),
+            Err(RecvError::Lagged(skipped)) => Some(BusEvent::Lagged { skipped }),
+            Err(RecvError::Closed) => None,
+        }
+    }
+}
+
+async fn run_bus(
+    client: ChatRoomClient,
+    sender: broadcast::Sender<Arc<EnhancedGroupChatMessage>>,
+    matchers: Arc<Mutex<MatcherRegistry>>,
+    closed: Arc<Notify>,
+) {
+    let policy = ResubscribePolicy::default();
+    let mut attempt = 0;
+    loop {
+        let mut messages = client.group_message_stream();
+        let err = loop {
+            let next = tokio::select! {
+                _ = closed.notified() => {
+                    tracing::debug!("message bus has no handles left; stopping");
+                    return;
+                }
+                next = messages.next() => next,
+            };
+            match next {
+                Some(Ok(message)) => {
+                    attempt = 0;
+                    let message = Arc::new(message);
+                    lock(&matchers).dispatch(&message);
+                    // Sending only fails when nobody is subscribed right now.
+                    let _ = sender.send(message);
+                }
+                Some(Err(err)) => break err,
+                None => return,
+            }
+        };
+
+        attempt += 1;
+        let retryable = match &err {
+            ChatError::Network(network) => {
+                classify_network_error(network).disposition != RetryDisposition::Fatal
+            }
+            _ => false,
+        };
+        if !retryable || attempt > policy.max_attempts {
+            tracing::error!(error = %err, "message bus stream failed; shutting down");
+            return;
+        }
+
+        let delay = policy.delay_for(attempt);
+        tracing::warn!(
+            attempt,
+            ?delay,
+            error = %err,
+            "message bus stream failed; resubscribing"
+        );
+        tokio::select! {
+            _ = closed.notified() => return,
+            _ = sleep(delay) => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures_util::FutureExt;
+
+    #[tokio::test]
+    async fn lagged_receivers_get_a_typed_event() {
+        let (sender, _) = broadcast::channel(1);
+        let (bus, _closed) = MessageBus::with_sender(&sender);
+        let mut receiver = bus.subscribe();
+
+        for _ in 0..3 {
+            sender.send(Arc::new(test_message())).unwrap();
+        }
+        assert!(matches!(
+            receiver.recv().await,
+            Some(BusEvent::Lagged { skipped: 2 })
+        ));
+        assert!(matches!(receiver.recv().await, Some(BusEvent::Message(_))));
+
+        assert!(bus.is_running());
+        drop(sender);
+        assert!(!bus.is_running());
+        assert!(receiver.recv().await.is_none());
+        assert!(bus.subscribe().recv().await.is_none());
+    }
+
+    #[test]
+    fn dropping_the_last_handle_signals_the_bus_task() {
+        let (sender, _) = broadcast::channel(1);
+        let (bus, closed) = MessageBus::with_sender(&sender);
+        let receiver = bus.subscribe_matching(MessageMatcher::new());
+        let clone = bus.clone();
+
+        drop(bus);
+        drop(clone);
+        assert!(closed.notified().now_or_never().is_none());
+
+        drop(receiver);
+        assert!(closed.notified().now_or_never().is_some());
+    }
+
+    #[test]
+    fn matching_subscriptions_share_one_pattern_scan() {
+        let mut registry = MatcherRegistry::default();
+        let mut scams = registry.add(
+            MessageMatcher::new()
+                .with_pattern(Regex::new(r"free-\w+\.ru").unwrap())
+                .deny_sender(SteamID::from(2)),
+        );
+        let mut giveaways = registry.add(MessageMatcher::new().with_keywords(["GIVEAWAY"]));
+        let mut unmatched = registry.add(
+            MessageMatcher::new()
+                .with_pattern(Regex::new("never").unwrap())
+                .filter_rooms(RoomFilter::new().allow_room(1, 2)),
+        );
+        assert_eq!(registry.subscriptions[1].patterns, 1..1);
+        assert_eq!(registry.subscriptions[2].patterns, 1..2);
+
+        for (sender, text) in [
+            (1, "get free-skins.ru"),
+            (2, "free-knives.ru"),
+            (1, "Giveaway!"),
+        ] {
+            let mut message = message_from(text);
+            message.sender_steam_id = SteamID::from(sender);
+            registry.dispatch(&Arc::new(message));
+        }
+
+        assert_eq!(scams.try_recv().unwrap().message, "get free-skins.ru");
+        assert!(scams.try_recv().is_err());
+        assert_eq!(giveaways.try_recv().unwrap().message, "Giveaway!");
+        assert!(giveaways.try_recv().is_err());
+        assert!(unmatched.try_recv().is_err());
+
+        drop(unmatched);
+        registry.dispatch(&Arc::new(message_from("hello")));
+        assert_eq!(registry.subscriptions.len(), 2);
+    }
+
+    fn test_message() -> EnhancedGroupChatMessage {
+        message_from("hello")
+    }
+
+    fn message_from(text: &str) -> EnhancedGroupChatMessage {
+        use steam_vent_proto::steammessages_chat_steamclient::CChatRoom_IncomingChatMessage_Notification;
+
+        let mut notification = CChatRoom_IncomingChatMessage_Notification::new();
+        notification.set_chat_group_id(1);
+        notification.set_chat_id(2);
+        notification.set_message(text.to_string());
+        EnhancedGroupChatMessage::from_notification(&notification)
+    }
+}
@@ -0,0 +1,247 @@
+// SPDX-License-Identifier: LGPL-3.0-only
+
+use crate::chatroom::EnhancedGroupChatMessage;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use thiserror::Error;
+
+/// Position of a message in a room's history.
+///
+/// Positions order by `timestamp` first and `ordinal` second, matching the order Steam
+/// assigns to messages within a room.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+pub struct MessagePosition {
+    /// Server timestamp of the message.
+    pub timestamp: u32,
+    /// Ordinal of the message within `timestamp`.
+    pub ordinal: u32,
+}
+
+impl MessagePosition {
+    /// Position of a received group message.
+    pub fn of(message: &EnhancedGroupChatMessage) -> Self {
+        Self {
+            timestamp: message.timestamp,
+            ordinal: message.ordinal,
+        }
+    }
+}
+
+/// Last seen message per room, used by `ChatRoomClient::catch_up`.
+///
+/// Advance the cursor with every message you handle and persist it (it is
+/// serde-serializable) so the next run can fetch what was missed in between.
+///
+/// # Example
+///
+/// ```
+/// use SC_Sub_Poster::{CatchUpCursor, MessagePosition};
+///
+/// let mut cursor = CatchUpCursor::new();
+/// cursor.record(1, 2, MessagePosition { timestamp: 100, ordinal: 0 });
+/// let saved = serde_json::to_string(&cursor).unwrap();
+/// let restored: CatchUpCursor = serde_json::from_str(&saved).unwrap();
+/// assert_eq!(restored, cursor);
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CatchUpCursor {
+    rooms: BTreeMap<u64, BTreeMap<u64, MessagePosition>>,
+}
+
+impl CatchUpCursor {
+    /// Create a cursor with no rooms tracked.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Last seen position in a room, if it is tracked.
+    pub fn position(&self, chat_group_id: u64, chat_id: u64) -> Option<MessagePosition> {
+        self.rooms
+            .get(&chat_group_id)
+            .and_then(|rooms| rooms.get(&chat_id))
+            .copied()
+    }
+
+    /// Record `position` as seen in a room; positions never move backwards.
+    pub fn record(&mut self, chat_group_id: u64, chat_id: u64, position: MessagePosition) {
+        let seen = self
+            .rooms
+            .entry(chat_group_id)
+            .or_default()
+            .entry(chat_id)
+            .or_insert(position);
+        *seen = (*seen).max(position);
+    }
+
+    /// Record a handled message.
+    pub fn advance(&mut self, message: &EnhancedGroupChatMessage) {
+        self.record(
+            message.chat_group_id,
+            message.chat_id,
+            MessagePosition::of(message),
+        );
+    }
+
+    /// Whether `message` is newer than the last seen position in its room.
+    ///
+    /// Messages from untracked rooms are always new.
+    pub fn is_new(&self, message: &EnhancedGroupChatMessage) -> bool {
+        self.position(message.chat_group_id, message.chat_id)
+            .is_none_or(|seen| MessagePosition::of(message) > seen)
+    }
+}
+
+/// Errors raised by cursor persistence.
+#[derive(Debug, Error)]
+pub enum CursorStoreError {
+    /// Reading or writing the backing file failed.
+    #[error("cursor store I/O error: {0}")]
+    Io(#[from] std::io::Error),
+    /// The persisted cursor could not be encoded or decoded.
+    #[error("cursor store serialization error: {0}")]
+    Serialization(#[from] serde_json::Error),
+    /// A store lock was poisoned by a panicking writer.
+    #[error("cursor store lock poisoned")]
+    Poisoned,
+}
+
+/// Pluggable persistence for the cursor of `ChatRoomClient::resumable_group_stream`.
+pub trait CursorStore: Send + Sync {
+    /// Load the saved cursor; a store that was never saved yields an empty cursor.
+    fn load(&self) -> Result<CatchUpCursor, CursorStoreError>;
+
+    /// Replace the saved cursor.
+    fn save(&self, cursor: &CatchUpCursor) -> Result<(), CursorStoreError>;
+}
+
+/// In-memory cursor store; the cursor does not survive a process restart.
+#[derive(Debug, Default)]
+pub struct MemoryCursorStore {
+    cursor: Mutex<CatchUpCursor>,
+}
+
+impl MemoryCursorStore {
+    /// Create a store holding an empty cursor.
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl CursorStore for MemoryCursorStore {
+    fn load(&self) -> Result<CatchUpCursor, CursorStoreError> {
+        let cursor = self.cursor.lock().map_err(|_| CursorStoreError::Poisoned)?;
+        Ok(cursor.clone())
+    }
+
+    fn save(&self, cursor: &CatchUpCursor) -> Result<(), CursorStoreError> {
+        *self.cursor.lock().map_err(|_| CursorStoreError::Poisoned)? = cursor.clone();
+        Ok(())
+    }
+}
+
+/// Cursor store persisting the cursor as JSON in a single file.
+///
+/// Every save rewrites the file through a temporary sibling and an atomic rename, so a
+/// crash mid-write leaves the previous cursor intact.
+#[derive(Debug)]
+pub struct JsonFileCursorStore {
+    path: PathBuf,
+    lock: Mutex<()>,
+}
+
+impl JsonFileCursorStore {
+    /// Create a store backed by `path`. The file is created lazily on first save.
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        Self {
+            path: path.into(),
+            lock: Mutex::new(()),
+        }
+    }
+
+    /// Path of the backing file.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+}
+
+impl CursorStore for JsonFileCursorStore {
+    fn load(&self) -> Result<CatchUpCursor, CursorStoreError> {
+        let _guard = self.lock.lock().map_err(|_| CursorStoreError::Poisoned)?;
+        match fs::read(&self.path) {
+            Ok(bytes) if bytes.is_empty() => Ok(CatchUpCursor::new()),
+            Ok(bytes) => Ok(serde_json::from_slice(&bytes)?),
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => Ok(CatchUpCursor::new()),
+            Err(err) => Err(err.into()),
+        }
+    }
+
+    fn save(&self, cursor: &CatchUpCursor) -> Result<(), CursorStoreError> {
+        let _guard = self.lock.lock().map_err(|_| CursorStoreError::Poisoned)?;
+        let tmp_path = self.path.with_extension("tmp");
+        fs::write(&tmp_path, serde_json::to_vec_pretty(cursor)?)?;
+        fs::rename(&tmp_path, &self.path)?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use steam_vent_proto::steammessages_chat_steamclient::CChatRoom_IncomingChatMessage_Notification;
+
+    fn message(chat_id: u64, timestamp: u32, ordinal: u32) -> EnhancedGroupChatMessage {
+        let mut notification = CChatRoom_IncomingChatMessage_Notification::new();
+        notification.set_chat_group_id(1);
+        notification.set_chat_id(chat_id);
+        notification.set_timestamp(timestamp);
+        notification.set_ordinal(ordinal);
+        EnhancedGroupChatMessage::from_notification(&notification)
+    }
+
+    #[test]
+    fn cursor_only_moves_forward() {
+        let mut cursor = CatchUpCursor::new();
+        cursor.advance(&message(2, 100, 1));
+        cursor.advance(&message(2, 100, 0));
+
+        assert_eq!(
+            cursor.position(1, 2),
+            Some(MessagePosition {
+                timestamp: 100,
+                ordinal: 1
+            })
+        );
+        assert!(!cursor.is_new(&message(2, 100, 1)));
+        assert!(cursor.is_new(&message(2, 100, 2)));
+        assert!(cursor.is_new(&message(3, 1, 0)));
+    }
+
+    #[test]
+    fn json_store_round_trips_the_cursor() {
+        let path = std::env::temp_dir().join(format!(
+            "kether-cursor-{}-{}.json",
+            std::process::id(),
+            std::time::SystemTime::now()
+                .duration_since(std::time::UNIX_EPOCH)
+                .map_or(0, |elapsed| elapsed.as_nanos())
+        ));
+        let store = JsonFileCursorStore::new(&path);
+        assert_eq!(
+            store.load().expect("load missing file"),
+            CatchUpCursor::new()
+        );
+
+        let mut cursor = CatchUpCursor::new();
+        cursor.advance(&message(2, 100, 3));
+        store.save(&cursor).expect("save");
+        assert_eq!(
+            JsonFileCursorStore::new(&path).load().expect("load"),
+            cursor
+        );
+
+        let _ = fs::remove_file(&path);
+    }
+}
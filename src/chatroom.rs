@@ -1,12 +1,40 @@
 // SPDX-License-Identifier: LGPL-3.0-only
 
-use crate::preprocessing::{MessagePreprocessor, PreprocessedMessage};
+use crate::bus::MessageBus;
+use crate::catchup::{CatchUpCursor, CursorStore, CursorStoreError, MessagePosition};
+use crate::dedupe::{DedupeCache, DuplicateMessageError};
+use crate::errors::{
+    Backoff, ErrorDomain, ErrorInventoryEntry, ErrorReport, KetherError, RetryDisposition,
+    classify_network_error,
+};
+use crate::filter::{MentionLevel, MentionTargets, RoomFilter};
+use crate::listener::{ListenerCounters, ListenerHandle};
+use crate::metrics::{MessagingMetrics, classify_send_error};
+use crate::middleware::{MessageMiddleware, Next};
+use crate::ordering::RoomSendLocks;
+use crate::outbox::{Outbox, OutboxReplayReport};
+use crate::personas::{PERSONA_LOOKUP_BATCH, PersonaCache, ResolvedMention};
+use crate::preprocessing::{
+    BBCodeDiagnostic, BBCodeError, ChatMentions, FRIEND_CHAT_MESSAGE_LIMIT, LengthError,
+    MentionRoster, MessageBuilder, MessagePreprocessor, MessageTemplate, PreprocessedMessage,
+    TagContext, TemplateError, helpers as preprocessing_helpers,
+};
+use crate::readstate::{PendingAcks, ReadState};
+use crate::scheduler::ScheduledHandle;
+use crate::sequence::{GapDetected, GapDetector, Placement, SequentialDispatch};
+use crate::watchdog::{Activity, StreamStalled, Watchdog};
 use futures_util::StreamExt as FuturesStreamExt;
+use std::collections::{HashMap, VecDeque};
 use std::error::Error;
+use std::panic::AssertUnwindSafe;
 use std::pin::Pin;
-use std::time::Duration;
-use steam_vent::ConnectionTrait;
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use steam_vent::{ConnectionTrait, EResult, ServiceMethodRequest};
 use steam_vent_proto::steammessages_chat_steamclient::{
+    CChatRoom_AckChatMessage_Notification, CChatRoom_ChatMessageModified_Notification,
+    CChatRoom_ChatRoomGroupRoomsChange_Notification, CChatRoom_ChatRoomHeaderState_Notification,
     CChatRoom_DeleteChatMessages_Request, CChatRoom_DeleteChatMessages_Response,
     CChatRoom_GetChatRoomGroupState_Request, CChatRoom_GetChatRoomGroupState_Response,
     CChatRoom_GetMessageHistory_Request, CChatRoom_GetMessageHistory_Response,
@@ -14,26 +42,49 @@ use steam_vent_proto::steammessages_chat_steamclient::{
     CChatRoom_GetMyChatRoomGroups_Request, CChatRoom_GetMyChatRoomGroups_Response,
     CChatRoom_IncomingChatMessage_Notification, CChatRoom_JoinChatRoomGroup_Request,
     CChatRoom_JoinChatRoomGroup_Response, CChatRoom_LeaveChatRoomGroup_Request,
-    CChatRoom_LeaveChatRoomGroup_Response, CChatRoom_MessageReaction_Notification,
-    CChatRoom_SendChatMessage_Request, CChatRoom_SendChatMessage_Response,
-    CChatRoom_UpdateMessageReaction_Request, CChatRoom_UpdateMessageReaction_Response,
+    CChatRoom_LeaveChatRoomGroup_Response, CChatRoom_MemberStateChange_Notification,
+    CChatRoom_MessageReaction_Notification, CChatRoom_SendChatMessage_Request,
+    CChatRoom_SendChatMessage_Response, CChatRoom_UpdateMessageReaction_Request,
+    CChatRoom_UpdateMessageReaction_Response,
+    ChatRoomClient_NotifyChatGroupUserStateChanged_Notification, EChatRoomMemberStateChange,
     EChatRoomMessageReactionType, cchat_room_delete_chat_messages_request,
     cchat_room_get_message_history_response,
 };
 use steam_vent_proto::steammessages_friendmessages_steamclient::{
-    CFriendMessages_IncomingMessage_Notification, CFriendMessages_SendMessage_Request,
-    CFriendMessages_SendMessage_Response,
+    CFriendMessages_AckMessage_Notification, CFriendMessages_GetRecentMessages_Request,
+    CFriendMessages_GetRecentMessages_Response, CFriendMessages_IncomingMessage_Notification,
+    CFriendMessages_SendMessage_Request, CFriendMessages_SendMessage_Response,
+    CFriendsMessages_GetActiveMessageSessions_Request,
+    CFriendsMessages_GetActiveMessageSessions_Response,
+    cfriend_messages_get_recent_messages_response,
+    cfriends_messages_get_active_message_sessions_response,
 };
-use steamid_ng::SteamID;
+use steam_vent_proto::steammessages_player_steamclient::{
+    CPlayer_GetPlayerLinkDetails_Request, CPlayer_GetPlayerLinkDetails_Response,
+};
+use steamid_ng::{AccountType, Instance, SteamID, Universe};
 use thiserror::Error;
+use tokio::sync::mpsc;
+use tokio::task::JoinSet;
 use tokio::time::sleep;
 use tokio_stream::{Stream, StreamExt};
 use tracing::{debug, instrument};
 
-type CallbackResult = Result<(), Box<dyn Error + Send + Sync>>;
+pub(crate) type CallbackResult = Result<(), Box<dyn Error + Send + Sync>>;
+
+/// `EChatEntryType::k_EChatEntryTypeChatMsg` — a regular chat message.
+const CHAT_ENTRY_TYPE_CHAT_MSG: i32 = 1;
+/// `EChatEntryType::k_EChatEntryTypeEmote` — a `/me` action line.
+const CHAT_ENTRY_TYPE_EMOTE: i32 = 4;
+
+/// Maximum number of messages sent in a single `DeleteChatMessages` request by `delete_messages`.
+const DELETE_MESSAGES_CHUNK_SIZE: usize = 50;
+
+/// History page size used by `catch_up`.
+const CATCH_UP_PAGE_SIZE: u32 = 100;
 
 /// Chat room information
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub struct ChatRoomInfo {
     /// The unique identifier for the chat group.
     pub chat_group_id: u64,
@@ -60,6 +111,7 @@ pub struct ChatGroupInfo {
 
 /// Friend message information
 #[derive(Debug, Clone)]
+#[non_exhaustive]
 pub struct FriendMessage {
     /// The Steam ID of the friend who sent the message.
     pub steam_id: SteamID,
@@ -69,6 +121,159 @@ pub struct FriendMessage {
     pub timestamp: u32,
     /// The type of chat entry (message type identifier from Steam API).
     pub chat_entry_type: i32,
+    /// Whether the message has not been acknowledged (read) yet.
+    ///
+    /// Live notifications are unread unless they echo our own message; history results
+    /// compare the message time with the conversation's last view time.
+    pub unread: bool,
+    /// Whether this is our own outgoing message, echoed back by Steam (e.g. sent from
+    /// another session) or found in history.
+    ///
+    /// For echoes `steam_id` is the friend the message was sent to.
+    pub is_echo: bool,
+    /// Message ordinal assigned by the server (distinguishes messages within one second).
+    pub ordinal: u32,
+    /// Whether the sender is a limited account, when the notification says so.
+    ///
+    /// Always `None` for history results.
+    pub is_limited_account: Option<bool>,
+    /// Steam's rendering of the message without BBCode, when the notification carries it.
+    ///
+    /// Always `None` for history results.
+    pub message_no_bbcode: Option<String>,
+    /// Whether Steam flagged the message as low priority (it should not raise an alert).
+    pub low_priority: bool,
+}
+
+/// Decoded `EChatEntryType` of a friend message notification.
+///
+/// | Raw | Variant |
+/// |-----|---------|
+/// | 1 | `ChatMsg` |
+/// | 2 | `Typing` |
+/// | 3 | `InviteGame` |
+/// | 4 | `Emote` |
+/// | 6 | `LeftConversation` |
+/// | 7 | `Entered` |
+/// | 8 | `WasKicked` |
+/// | 9 | `WasBanned` |
+/// | 10 | `Disconnected` |
+/// | 11 | `HistoricalChat` |
+/// | 14 | `LinkBlocked` |
+///
+/// Any other value is preserved as `Unknown`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ChatEntryType {
+    /// A regular chat message.
+    ChatMsg,
+    /// The friend is typing; the message body is empty.
+    Typing,
+    /// An invitation to join the friend's game.
+    InviteGame,
+    /// A `/me` action line.
+    Emote,
+    /// The friend closed the conversation.
+    LeftConversation,
+    /// The friend entered the conversation.
+    Entered,
+    /// The user was kicked.
+    WasKicked,
+    /// The user was banned.
+    WasBanned,
+    /// The friend disconnected.
+    Disconnected,
+    /// A message replayed from history.
+    HistoricalChat,
+    /// A message whose link was blocked.
+    LinkBlocked,
+    /// An entry type this crate does not know about.
+    Unknown(i32),
+}
+
+impl ChatEntryType {
+    /// Decode a raw `chat_entry_type` value.
+    pub fn from_raw(raw: i32) -> Self {
+        match raw {
+            CHAT_ENTRY_TYPE_CHAT_MSG => Self::ChatMsg,
+            2 => Self::Typing,
+            3 => Self::InviteGame,
+            CHAT_ENTRY_TYPE_EMOTE => Self::Emote,
+            6 => Self::LeftConversation,
+            7 => Self::Entered,
+            8 => Self::WasKicked,
+            9 => Self::WasBanned,
+            10 => Self::Disconnected,
+            11 => Self::HistoricalChat,
+            14 => Self::LinkBlocked,
+            other => Self::Unknown(other),
+        }
+    }
+
+    /// The raw `chat_entry_type` value.
+    pub fn as_raw(self) -> i32 {
+        match self {
+            Self::ChatMsg => CHAT_ENTRY_TYPE_CHAT_MSG,
+            Self::Typing => 2,
+            Self::InviteGame => 3,
+            Self::Emote => CHAT_ENTRY_TYPE_EMOTE,
+            Self::LeftConversation => 6,
+            Self::Entered => 7,
+            Self::WasKicked => 8,
+            Self::WasBanned => 9,
+            Self::Disconnected => 10,
+            Self::HistoricalChat => 11,
+            Self::LinkBlocked => 14,
+            Self::Unknown(raw) => raw,
+        }
+    }
+}
+
+/// Activity reported by friend message notifications, decoded by entry type.
+#[derive(Debug, Clone)]
+#[non_exhaustive]
+pub enum FriendActivity {
+    /// The friend is typing.
+    Typing(SteamID),
+    /// A chat message or `/me` emote.
+    Message(FriendMessage),
+    /// An invitation to join the friend's game.
+    InviteGame(FriendMessage),
+    /// Any other entry type; see `FriendMessage::entry_type`.
+    Other(FriendMessage),
+}
+
+impl FriendActivity {
+    fn from_message(message: FriendMessage) -> Self {
+        match message.entry_type() {
+            ChatEntryType::Typing => Self::Typing(message.steam_id),
+            ChatEntryType::ChatMsg | ChatEntryType::Emote => Self::Message(message),
+            ChatEntryType::InviteGame => Self::InviteGame(message),
+            _ => Self::Other(message),
+        }
+    }
+}
+
+/// Friend message with preprocessing
+///
+/// Extends `FriendMessage` with preprocessed BBCode and mention information.
+#[derive(Debug, Clone)]
+pub struct EnhancedFriendMessage {
+    /// The Steam ID of the friend who sent the message.
+    pub steam_id: SteamID,
+    /// The message text content.
+    pub message: String,
+    /// Unix timestamp when the message was sent.
+    pub timestamp: u32,
+    /// The type of chat entry (message type identifier from Steam API).
+    pub chat_entry_type: i32,
+    /// Message ordinal assigned by the server (distinguishes messages within one second).
+    pub ordinal: u32,
+    /// Steam's rendering of the message without BBCode, when the notification carries it.
+    pub message_no_bbcode: Option<String>,
+    /// Whether Steam flagged the message as low priority (it should not raise an alert).
+    pub low_priority: bool,
+    /// Preprocessed message data including parsed BBCode and extracted mentions.
+    pub preprocessed: PreprocessedMessage,
 }
 
 /// Group chat message information
@@ -109,6 +314,10 @@ pub struct EnhancedGroupChatMessage {
     pub chat_name: String,
     /// Message ordinal/sequence number assigned by the server.
     pub ordinal: u32,
+    /// Steam's rendering of the message without BBCode, when the notification carries it.
+    ///
+    /// Always `None` for messages loaded from history.
+    pub message_no_bbcode: Option<String>,
     /// Preprocessed message data including parsed BBCode and extracted mentions.
     pub preprocessed: PreprocessedMessage,
 }
@@ -194,10 +403,348 @@ pub struct ReactionEvent {
     pub is_add: bool,
 }
 
+/// Kind of membership change reported by `MemberChangeEvent`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MemberChange {
+    /// The user joined the group.
+    Joined,
+    /// The user left the group.
+    Left,
+    /// The user was kicked from the group.
+    Kicked,
+    /// The user was banned from the group.
+    Banned,
+    /// The user was invited to the group.
+    Invited,
+}
+
+impl MemberChange {
+    fn from_proto(change: EChatRoomMemberStateChange) -> Option<Self> {
+        match change {
+            EChatRoomMemberStateChange::k_EChatRoomMemberStateChange_Joined => Some(Self::Joined),
+            EChatRoomMemberStateChange::k_EChatRoomMemberStateChange_Parted => Some(Self::Left),
+            EChatRoomMemberStateChange::k_EChatRoomMemberStateChange_Kicked => Some(Self::Kicked),
+            EChatRoomMemberStateChange::k_EChatRoomMemberStateChange_Banned => Some(Self::Banned),
+            EChatRoomMemberStateChange::k_EChatRoomMemberStateChange_Invited => Some(Self::Invited),
+            _ => None,
+        }
+    }
+}
+
+/// A real-time group membership change.
+#[derive(Debug, Clone)]
+pub struct MemberChangeEvent {
+    /// Chat group identifier.
+    pub chat_group_id: u64,
+    /// Steam ID of the member whose state changed.
+    pub steam_id: SteamID,
+    /// What happened to the member.
+    pub change: MemberChange,
+    /// Who performed the change, when Steam reports it.
+    ///
+    /// The member-state notification does not currently carry the actor, so this is
+    /// `None` for events received from Steam.
+    pub actor: Option<SteamID>,
+}
+
+impl MemberChangeEvent {
+    fn from_notification(notification: &CChatRoom_MemberStateChange_Notification) -> Option<Self> {
+        let change = MemberChange::from_proto(notification.change())?;
+        Some(Self {
+            chat_group_id: notification.chat_group_id(),
+            steam_id: SteamID::new(
+                notification.member.accountid(),
+                Instance::Desktop,
+                AccountType::Individual,
+                Universe::Public,
+            ),
+            change,
+            actor: None,
+        })
+    }
+}
+
+/// An invitation for the current user to join a chat group.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct GroupInvite {
+    /// Chat group identifier.
+    pub chat_group_id: u64,
+    /// Display name of the group.
+    pub group_name: String,
+    /// Room to pass to `join_chat_room` when accepting.
+    pub default_chat_id: u64,
+    /// Who sent the invite, when Steam reports it.
+    ///
+    /// The user-state notification does not currently carry the inviter, so this is
+    /// `None` for invites received from Steam.
+    pub inviter: Option<SteamID>,
+    /// Invite code to pass to `join_chat_room`, when Steam reports one.
+    ///
+    /// Direct invites do not need a code, so this is `None` for invites received from Steam.
+    pub invite_code: Option<String>,
+}
+
+impl GroupInvite {
+    fn from_notification(
+        notification: &ChatRoomClient_NotifyChatGroupUserStateChanged_Notification,
+    ) -> Option<Self> {
+        if notification.user_action()
+            != EChatRoomMemberStateChange::k_EChatRoomMemberStateChange_Invited
+        {
+            return None;
+        }
+        Some(Self {
+            chat_group_id: notification.chat_group_id(),
+            group_name: notification.group_summary.chat_group_name().to_string(),
+            default_chat_id: notification.group_summary.default_chat_id(),
+            inviter: None,
+            invite_code: None,
+        })
+    }
+}
+
+/// Header state of a chat group, tracked from header-state notifications.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ChatGroupState {
+    /// Chat group identifier.
+    pub chat_group_id: u64,
+    /// Display name of the group.
+    pub name: String,
+    /// Group tagline.
+    pub tagline: String,
+    /// SHA-1 of the group avatar; empty when the group has none.
+    pub avatar_sha: Vec<u8>,
+    /// Room new members land in.
+    pub default_chat_id: u64,
+    /// Rooms in the group.
+    pub rooms: Vec<ChatRoomInfo>,
+}
+
+/// The fields of a `ChatGroupState` that changed, holding their new values.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ChatGroupStateDiff {
+    /// New group name.
+    pub name: Option<String>,
+    /// New tagline.
+    pub tagline: Option<String>,
+    /// New avatar SHA-1.
+    pub avatar_sha: Option<Vec<u8>>,
+    /// New room list.
+    pub rooms: Option<Vec<ChatRoomInfo>>,
+}
+
+impl ChatGroupStateDiff {
+    /// Whether no tracked field changed.
+    pub fn is_empty(&self) -> bool {
+        self.name.is_none()
+            && self.tagline.is_none()
+            && self.avatar_sha.is_none()
+            && self.rooms.is_none()
+    }
+}
+
+/// A real-time change to a group's name, tagline, avatar, or room list.
+///
+/// Diffs are relative to the previous notification seen by the same listener or stream;
+/// the first notification for a group reports every non-empty field it carries.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct GroupHeaderChanged {
+    /// Chat group identifier.
+    pub chat_group_id: u64,
+    /// The fields that changed.
+    pub diff: ChatGroupStateDiff,
+    /// The group state after the change.
+    pub state: ChatGroupState,
+}
+
+/// Last known header state per group, used to turn notifications into diffs.
+#[derive(Debug, Default)]
+struct GroupStateTracker {
+    groups: HashMap<u64, ChatGroupState>,
+}
+
+impl GroupStateTracker {
+    fn apply_header(
+        &mut self,
+        notification: &CChatRoom_ChatRoomHeaderState_Notification,
+    ) -> Option<GroupHeaderChanged> {
+        let header = &notification.header_state;
+        let chat_group_id = header.chat_group_id();
+        let state = self.state_mut(chat_group_id);
+
+        let mut diff = ChatGroupStateDiff::default();
+        if state.name != header.chat_name() {
+            state.name = header.chat_name().to_string();
+            for room in &mut state.rooms {
+                room.chat_group_name = state.name.clone();
+            }
+            diff.name = Some(state.name.clone());
+        }
+        if state.tagline != header.tagline() {
+            state.tagline = header.tagline().to_string();
+            diff.tagline = Some(state.tagline.clone());
+        }
+        if state.avatar_sha != header.avatar_sha() {
+            state.avatar_sha = header.avatar_sha().to_vec();
+            diff.avatar_sha = Some(state.avatar_sha.clone());
+        }
+        Self::changed(state, diff)
+    }
+
+    fn apply_rooms(
+        &mut self,
+        notification: &CChatRoom_ChatRoomGroupRoomsChange_Notification,
+    ) -> Option<GroupHeaderChanged> {
+        let chat_group_id = notification.chat_group_id();
+        let state = self.state_mut(chat_group_id);
+        state.default_chat_id = notification.default_chat_id();
+
+        let rooms: Vec<ChatRoomInfo> = notification
+            .chat_rooms
+            .iter()
+            .map(|room| ChatRoomInfo {
+                chat_group_id,
+                chat_id: room.chat_id(),
+                chat_name: room.chat_name().to_string(),
+                chat_group_name: state.name.clone(),
+                is_joined: true,
+            })
+            .collect();
+
+        let mut diff = ChatGroupStateDiff::default();
+        if state.rooms != rooms {
+            state.rooms = rooms;
+            diff.rooms = Some(state.rooms.clone());
+        }
+        Self::changed(state, diff)
+    }
+
+    fn state_mut(&mut self, chat_group_id: u64) -> &mut ChatGroupState {
+        self.groups
+            .entry(chat_group_id)
+            .or_insert_with(|| ChatGroupState {
+                chat_group_id,
+                ..ChatGroupState::default()
+            })
+    }
+
+    fn changed(state: &ChatGroupState, diff: ChatGroupStateDiff) -> Option<GroupHeaderChanged> {
+        (!diff.is_empty()).then(|| GroupHeaderChanged {
+            chat_group_id: state.chat_group_id,
+            diff,
+            state: state.clone(),
+        })
+    }
+}
+
+/// One message changed by a moderator or its sender.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MessageModification {
+    /// Server timestamp of the modified message.
+    pub server_timestamp: u32,
+    /// Ordinal of the modified message.
+    pub ordinal: u32,
+    /// Whether the message was deleted.
+    pub deleted: bool,
+}
+
+/// A real-time notification that messages in a room were modified or deleted.
+#[derive(Debug, Clone)]
+pub struct MessageModifiedEvent {
+    /// Chat group identifier.
+    pub chat_group_id: u64,
+    /// Chat room identifier.
+    pub chat_id: u64,
+    /// The affected messages.
+    pub changes: Vec<MessageModification>,
+}
+
+impl MessageModifiedEvent {
+    fn from_notification(notification: &CChatRoom_ChatMessageModified_Notification) -> Self {
+        Self {
+            chat_group_id: notification.chat_group_id(),
+            chat_id: notification.chat_id(),
+            changes: notification
+                .messages
+                .iter()
+                .map(|message| MessageModification {
+                    server_timestamp: message.server_timestamp(),
+                    ordinal: message.ordinal(),
+                    deleted: message.deleted(),
+                })
+                .collect(),
+        }
+    }
+
+    /// Find the change affecting a stored message, if any.
+    ///
+    /// A message matches when it was received in the same room and its `timestamp` and
+    /// `ordinal` equal the change's `server_timestamp` and `ordinal`.
+    pub fn change_for(&self, message: &EnhancedGroupChatMessage) -> Option<MessageModification> {
+        if message.chat_group_id != self.chat_group_id || message.chat_id != self.chat_id {
+            return None;
+        }
+        self.changes.iter().copied().find(|change| {
+            change.server_timestamp == message.timestamp && change.ordinal == message.ordinal
+        })
+    }
+
+    /// Pair stored messages with the changes that affect them.
+    ///
+    /// # Arguments
+    ///
+    /// * `messages` - Locally stored messages, e.g. a mirror bot's history
+    ///
+    /// # Returns
+    ///
+    /// Every stored message touched by this event together with its change, in the
+    /// order of `messages`.
+    pub fn affected<'m>(
+        &self,
+        messages: &'m [EnhancedGroupChatMessage],
+    ) -> Vec<(&'m EnhancedGroupChatMessage, MessageModification)> {
+        messages
+            .iter()
+            .filter_map(|message| self.change_for(message).map(|change| (message, change)))
+            .collect()
+    }
+}
+
+/// Any real-time event delivered by `ChatRoomNotifications::event_stream`.
+#[derive(Debug, Clone)]
+#[non_exhaustive]
+pub enum ChatEvent {
+    /// An incoming group chat message.
+    GroupMessage(EnhancedGroupChatMessage),
+    /// A reaction was added to or removed from a message.
+    Reaction(ReactionEvent),
+    /// A member joined, left, or was removed from a group.
+    MemberChange(MemberChangeEvent),
+    /// Messages were modified or deleted.
+    MessageModified(MessageModifiedEvent),
+    /// The current user was invited to a group.
+    GroupInvite(GroupInvite),
+    /// A group's name, tagline, avatar, or room list changed.
+    GroupHeaderChanged(GroupHeaderChanged),
+}
+
 impl EnhancedGroupChatMessage {
     /// Create an enhanced message from a notification, preserving the whole notification object
     pub fn from_notification(notification: &CChatRoom_IncomingChatMessage_Notification) -> Self {
-        let preprocessed = MessagePreprocessor::preprocess_message(notification.message());
+        Self::from_notification_with(notification, MessagePreprocessor::shared())
+    }
+
+    /// Create an enhanced message from a notification using a custom preprocessor.
+    pub fn from_notification_with(
+        notification: &CChatRoom_IncomingChatMessage_Notification,
+        preprocessor: &MessagePreprocessor,
+    ) -> Self {
+        let preprocessed = match &notification.message_no_bbcode {
+            Some(plain_text) => {
+                preprocessor.preprocess_with_plain_text(notification.message(), plain_text)
+            }
+            None => preprocessor.preprocess(notification.message()),
+        };
         Self {
             chat_group_id: notification.chat_group_id(),
             chat_id: notification.chat_id(),
@@ -206,9 +753,160 @@ impl EnhancedGroupChatMessage {
             timestamp: notification.timestamp(),
             chat_name: notification.chat_name().to_string(),
             ordinal: notification.ordinal(),
+            message_no_bbcode: notification.message_no_bbcode.clone(),
+            preprocessed,
+        }
+    }
+
+    /// Whether the message mentions one of `targets`, with `own` as the default SteamID.
+    pub fn mentions(&self, targets: &MentionTargets, own: SteamID) -> bool {
+        self.preprocessed
+            .mentions
+            .as_ref()
+            .is_some_and(|mentions| targets.matches(mentions, own))
+    }
+
+    /// How directly the message pings `targets`, with `own` as the default SteamID.
+    pub fn mention_level(&self, targets: &MentionTargets, own: SteamID) -> MentionLevel {
+        self.preprocessed
+            .mentions
+            .as_ref()
+            .map_or(MentionLevel::None, |mentions| targets.level(mentions, own))
+    }
+
+    /// Whether the message pings `own`, the listening account, by `@all`, `@here` or
+    /// individually (see `PreprocessedMessage::mentions_user`).
+    pub fn mentions_me(&self, own: &SteamID) -> bool {
+        self.preprocessed.mentions_user(*own, true)
+    }
+
+    /// Resolve the accounts this message mentions to persona names and avatars.
+    ///
+    /// Shorthand for `ChatRoomClient::resolve_mentions` on the preprocessed mentions;
+    /// empty when the message mentions nobody.
+    ///
+    /// # Errors
+    ///
+    /// Returns `ChatError::Network` if a lookup request fails.
+    pub async fn resolve_mentions(
+        &self,
+        client: &ChatRoomClient,
+    ) -> Result<Vec<ResolvedMention>, ChatError> {
+        match &self.preprocessed.mentions {
+            Some(mentions) => client.resolve_mentions(mentions).await,
+            None => Ok(Vec::new()),
+        }
+    }
+
+    /// Create an enhanced message from a history entry of the given room.
+    fn from_history(
+        chat_group_id: u64,
+        chat_id: u64,
+        chat_name: &str,
+        entry: ChatMessageHistoryEntry,
+        preprocessor: &MessagePreprocessor,
+    ) -> Self {
+        let preprocessed = preprocessor.preprocess(&entry.message);
+        Self {
+            chat_group_id,
+            chat_id,
+            sender_steam_id: entry.sender,
+            message: entry.message,
+            timestamp: entry.server_timestamp,
+            chat_name: chat_name.to_string(),
+            ordinal: entry.ordinal,
+            message_no_bbcode: None,
+            preprocessed,
+        }
+    }
+}
+
+impl FriendMessage {
+    /// The decoded `chat_entry_type`.
+    pub fn entry_type(&self) -> ChatEntryType {
+        ChatEntryType::from_raw(self.chat_entry_type)
+    }
+
+    fn from_notification(notification: &CFriendMessages_IncomingMessage_Notification) -> Self {
+        Self {
+            steam_id: SteamID::from(notification.steamid_friend()),
+            message: notification.message().to_string(),
+            timestamp: notification.rtime32_server_timestamp(),
+            chat_entry_type: notification.chat_entry_type(),
+            unread: !notification.local_echo(),
+            is_echo: notification.local_echo(),
+            ordinal: notification.ordinal(),
+            is_limited_account: notification.from_limited_account,
+            message_no_bbcode: notification.message_no_bbcode.clone(),
+            low_priority: notification.low_priority(),
+        }
+    }
+
+    fn from_recent_message(
+        message: &cfriend_messages_get_recent_messages_response::FriendMessage,
+        friend_steam_id: SteamID,
+        last_view: Option<u32>,
+    ) -> Self {
+        let sender = SteamID::new(
+            message.accountid(),
+            Instance::Desktop,
+            AccountType::Individual,
+            Universe::Public,
+        );
+        let from_friend = sender.account_id() == friend_steam_id.account_id();
+        Self {
+            steam_id: sender,
+            message: message.message().to_string(),
+            timestamp: message.timestamp(),
+            chat_entry_type: CHAT_ENTRY_TYPE_CHAT_MSG,
+            unread: from_friend && last_view.is_some_and(|viewed| message.timestamp() > viewed),
+            is_echo: !from_friend,
+            ordinal: message.ordinal(),
+            is_limited_account: None,
+            message_no_bbcode: None,
+            low_priority: false,
+        }
+    }
+}
+
+impl EnhancedFriendMessage {
+    /// Create an enhanced friend message from an incoming message notification.
+    pub fn from_notification(notification: &CFriendMessages_IncomingMessage_Notification) -> Self {
+        Self::from_notification_with(notification, MessagePreprocessor::shared())
+    }
+
+    /// Create an enhanced friend message using a custom preprocessor.
+    pub fn from_notification_with(
+        notification: &CFriendMessages_IncomingMessage_Notification,
+        preprocessor: &MessagePreprocessor,
+    ) -> Self {
+        let friend_message = FriendMessage::from_notification(notification);
+        let preprocessed = match &friend_message.message_no_bbcode {
+            Some(plain_text) => {
+                preprocessor.preprocess_with_plain_text(&friend_message.message, plain_text)
+            }
+            None => preprocessor.preprocess(&friend_message.message),
+        };
+        Self {
+            steam_id: friend_message.steam_id,
+            message: friend_message.message,
+            timestamp: friend_message.timestamp,
+            chat_entry_type: friend_message.chat_entry_type,
+            ordinal: friend_message.ordinal,
+            message_no_bbcode: friend_message.message_no_bbcode,
+            low_priority: friend_message.low_priority,
             preprocessed,
         }
     }
+
+    /// The mentions in the message, found as in group chats: SteamIDs such as
+    /// `[U:1:xxxxx]`, `[mention]` tags, `@all` and `@here`.
+    ///
+    /// A one-on-one chat has nobody to notify, so Steam does nothing with these. `@all`
+    /// and `@here` are still recorded for bots that give them a meaning of their own.
+    pub fn mentions(&self) -> Option<&ChatMentions> {
+        self.preprocessed.mentions.as_ref()
+    }
 }
 
 impl MessageReactionInfo {
@@ -268,1077 +966,1630 @@ impl ReactionEvent {
     }
 }
 
-/// Chat room client for Steam group chat functionality
-pub struct ChatRoomClient {
-    connection: steam_vent::Connection,
+/// Outcome of deleting one message with `delete_messages`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum MessageDeletionOutcome {
+    /// Steam accepted the deletion.
+    Deleted,
+    /// The message belongs to another room than the one targeted; it was not sent to Steam.
+    WrongRoom {
+        /// The chat group the message was received in.
+        chat_group_id: u64,
+        /// The chat room the message was received in.
+        chat_id: u64,
+    },
+    /// The message has no usable identifier (`timestamp` is zero).
+    InvalidIdentifier,
+    /// The deletion request containing this message failed.
+    Failed(String),
 }
 
-/// Group-related operations for chat rooms.
-pub struct ChatRoomGroups<'a> {
-    connection: &'a steam_vent::Connection,
+/// Per-message result reported by `delete_messages`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MessageDeletion {
+    /// Server timestamp of the message.
+    pub server_timestamp: u32,
+    /// Message ordinal.
+    pub ordinal: u32,
+    /// What happened to the message.
+    pub outcome: MessageDeletionOutcome,
 }
 
-/// Message sending helpers for chats and friends.
-pub struct ChatRoomMessaging<'a> {
-    connection: &'a steam_vent::Connection,
+/// A group message paired with its echo notification by `send_group_message_with_echo`.
+#[derive(Debug, Clone)]
+pub struct EchoedGroupMessage {
+    /// The sent message, carrying the echo's `server_timestamp` and `ordinal`.
+    pub preprocessed: PreprocessedMessage,
+    /// `server_timestamp` reported by the send response.
+    pub response_timestamp: u32,
+    /// `ordinal` reported by the send response, if it was set.
+    pub response_ordinal: Option<u32>,
+    /// Timestamp of the matched echo notification.
+    pub echo_timestamp: u32,
+    /// Ordinal of the matched echo notification.
+    pub echo_ordinal: u32,
 }
 
-/// Notification listeners for chat and friend messages.
-pub struct ChatRoomNotifications<'a> {
-    connection: &'a steam_vent::Connection,
+impl EchoedGroupMessage {
+    /// Whether the send response and the echo reported different identifiers.
+    pub fn identifiers_disagree(&self) -> bool {
+        self.response_timestamp != self.echo_timestamp
+            || self
+                .response_ordinal
+                .is_some_and(|ordinal| ordinal != self.echo_ordinal)
+    }
 }
 
-/// Parameters for sending a group message
-#[derive(Debug, Clone)]
-pub struct SendGroupMessageParams {
-    /// The unique identifier for the chat group.
-    pub chat_group_id: u64,
-    /// The unique identifier for the specific chat room within the group.
-    pub chat_id: u64,
-    /// The message text to send.
-    pub message: String,
-    /// Whether the message should be echoed back to the sender.
-    pub echo_to_sender: bool,
+/// How far (in seconds) an echo's timestamp may trail the send response's timestamp.
+const ECHO_MATCH_WINDOW_SECS: u32 = 2;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum EchoMatch {
+    Exact,
+    InWindow,
 }
 
-impl SendGroupMessageParams {
-    /// Create a new `SendGroupMessageParams` with default settings.
-    ///
-    /// # Arguments
-    ///
-    /// * `chat_group_id` - The unique identifier for the chat group
-    /// * `chat_id` - The unique identifier for the specific chat room
-    /// * `message` - The message text to send (any type that can be converted to `String`)
-    ///
-    /// # Defaults
-    ///
-    /// * `echo_to_sender` is set to `false` by default. Use `with_echo_to_sender()` to change it.
-    pub fn new(chat_group_id: u64, chat_id: u64, message: impl Into<String>) -> Self {
+/// What the echo of a just-sent group message must look like.
+struct EchoExpectation {
+    chat_group_id: u64,
+    chat_id: u64,
+    sender_account_id: u32,
+    sent_message: String,
+    modified_message: String,
+    response_timestamp: u32,
+    response_ordinal: Option<u32>,
+}
+
+impl EchoExpectation {
+    fn classify(
+        &self,
+        notification: &CChatRoom_IncomingChatMessage_Notification,
+    ) -> Option<EchoMatch> {
+        let same_room = notification.chat_group_id() == self.chat_group_id
+            && notification.chat_id() == self.chat_id;
+        let same_sender =
+            SteamID::from(notification.steamid_sender()).account_id() == self.sender_account_id;
+        let same_text = notification.message() == self.sent_message
+            || (!self.modified_message.is_empty()
+                && notification.message() == self.modified_message);
+        if !(same_room && same_sender && same_text) {
+            return None;
+        }
+
+        let timestamp = notification.timestamp();
+        if timestamp == self.response_timestamp {
+            Some(EchoMatch::Exact)
+        } else if timestamp > self.response_timestamp
+            && timestamp - self.response_timestamp <= ECHO_MATCH_WINDOW_SECS
+        {
+            Some(EchoMatch::InWindow)
+        } else {
+            None
+        }
+    }
+}
+
+/// What happened to the original message during `replace_message`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OriginalDeletion {
+    /// The original message was deleted.
+    Deleted,
+    /// Steam reported the original message as already deleted or unknown.
+    AlreadyGone,
+}
+
+/// Result of replacing a group message (delete + resend).
+#[derive(Debug, Clone)]
+pub struct ReplacedMessage {
+    /// The newly sent message, including its `server_timestamp` and `ordinal`.
+    pub replacement: PreprocessedMessage,
+    /// Outcome of deleting the original message.
+    pub original_deletion: OriginalDeletion,
+}
+
+/// Retry behavior for transient failures when sending group messages, logging on
+/// (`LogOn::new_with_retry`) or reconnecting (`ChatRoomClient::reconnect_with`).
+///
+/// Only errors classified as `ImmediateRetry` or `BackoffRetry` are retried; the latter
+/// wait for the delay `backoff` gives for the attempt.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RetryPolicy {
+    /// Total attempts including the first one; `1` disables retrying.
+    pub max_attempts: u32,
+    /// Delays before backoff retries; see `Backoff::linear` for delays growing by a
+    /// fixed step.
+    pub backoff: Backoff,
+}
+
+impl RetryPolicy {
+    /// A policy that never retries.
+    pub const fn none() -> Self {
         Self {
-            chat_group_id,
-            chat_id,
-            message: message.into(),
-            echo_to_sender: false,
+            max_attempts: 1,
+            backoff: Backoff::new(Duration::ZERO, Duration::ZERO),
         }
     }
 
-    /// Set whether the message should be echoed back to the sender.
+    /// Create a retry policy.
+    ///
+    /// Backoff retries wait `backoff` multiplied by the attempt number, uncapped (see
+    /// `Backoff::linear`); use `with_backoff` for exponential delays, a cap or jitter.
     ///
     /// # Arguments
     ///
-    /// * `echo` - If `true`, the message will be echoed back to the sender
+    /// * `max_attempts` - Total attempts including the first one
+    /// * `backoff` - Delay before the first retry of errors that call for backoff
+    pub const fn new(max_attempts: u32, backoff: Duration) -> Self {
+        Self {
+            max_attempts,
+            backoff: Backoff::linear(backoff, Duration::MAX),
+        }
+    }
+
+    /// Replace the delays used before backoff retries.
     ///
     /// # Returns
     ///
     /// `Self` for method chaining (builder pattern).
-    pub fn with_echo_to_sender(mut self, echo: bool) -> Self {
-        self.echo_to_sender = echo;
+    pub fn with_backoff(mut self, backoff: Backoff) -> Self {
+        self.backoff = backoff;
         self
     }
+
+    /// Run `operation` until it succeeds, retrying the failures this policy allows.
+    ///
+    /// Failures are classified with `KetherError::inventory`; the last error is returned
+    /// once `max_attempts` is reached or the error is not retryable.
+    pub(crate) async fn run<T, F, Fut>(
+        &self,
+        name: &str,
+        mut operation: F,
+    ) -> Result<T, KetherError>
+    where
+        F: FnMut() -> Fut,
+        Fut: Future<Output = Result<T, KetherError>>,
+    {
+        let mut attempt = 1;
+        loop {
+            match operation().await {
+                Ok(value) => return Ok(value),
+                Err(err) => {
+                    let disposition = err.inventory().disposition;
+                    let Some(delay) = self.delay_before(attempt, disposition) else {
+                        return Err(err);
+                    };
+                    tracing::warn!(attempt, ?delay, error = %err, "retrying {name}");
+                    sleep(delay).await;
+                    attempt += 1;
+                }
+            }
+        }
+    }
+
+    fn delay_before(&self, attempt: u32, disposition: RetryDisposition) -> Option<Duration> {
+        if attempt >= self.max_attempts {
+            return None;
+        }
+        match disposition {
+            RetryDisposition::ImmediateRetry => Some(Duration::ZERO),
+            RetryDisposition::BackoffRetry => Some(self.backoff.delay_for(attempt)),
+            RetryDisposition::Reauthenticate | RetryDisposition::Fatal => None,
+        }
+    }
 }
 
-struct NotificationStream<'a, T> {
-    inner: Pin<Box<dyn Stream<Item = Result<T, steam_vent::NetworkError>> + Send + 'a>>,
-    backoff: Duration,
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self::none()
+    }
 }
 
-#[derive(Debug, Error)]
-enum NotificationDispatchError {
-    #[error("notification stream error: {0}")]
-    Stream(#[from] steam_vent::NetworkError),
-    #[error("notification callback failed")]
-    Callback {
-        #[source]
-        source: Box<dyn Error + Send + Sync>,
-    },
+/// How `listen_for_group_messages_resilient` resubscribes after stream errors.
+///
+/// Delays come from `backoff`, which by default doubles from the initial delay up to
+/// the cap. The attempt counter resets once a notification is delivered on the new
+/// subscription.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ResubscribePolicy {
+    /// Consecutive resubscriptions allowed before the listener gives up.
+    pub max_attempts: u32,
+    /// Delays between resubscriptions.
+    pub backoff: Backoff,
 }
 
-impl ChatRoomClient {
-    /// Create a new chat room client from an existing connection.
+impl ResubscribePolicy {
+    /// Create a resubscribe policy.
     ///
     /// # Arguments
     ///
-    /// * `connection` - An established Steam connection from `LogOn::connection()`
-    pub fn new(connection: steam_vent::Connection) -> Self {
-        Self { connection }
+    /// * `max_attempts` - Consecutive resubscriptions allowed before giving up
+    /// * `initial_backoff` - Delay before the first resubscription
+    /// * `max_backoff` - Upper bound for the delay between resubscriptions
+    pub const fn new(max_attempts: u32, initial_backoff: Duration, max_backoff: Duration) -> Self {
+        Self {
+            max_attempts,
+            backoff: Backoff::new(initial_backoff, max_backoff),
+        }
     }
 
-    /// Access group-related operations (joining, leaving, listing chat rooms).
+    /// Replace the delays between resubscriptions, e.g. to add jitter.
     ///
     /// # Returns
     ///
-    /// A `ChatRoomGroups` handle for performing group operations.
-    pub fn groups(&self) -> ChatRoomGroups<'_> {
-        ChatRoomGroups {
-            connection: &self.connection,
-        }
+    /// `Self` for method chaining (builder pattern).
+    pub fn with_backoff(mut self, backoff: Backoff) -> Self {
+        self.backoff = backoff;
+        self
     }
 
-    /// Access message sending helpers for group chats and friend messages.
+    /// Delay before resubscription number `attempt` (starting at 1).
+    pub fn delay_for(&self, attempt: u32) -> Duration {
+        self.backoff.delay_for(attempt)
+    }
+}
+
+impl Default for ResubscribePolicy {
+    fn default() -> Self {
+        Self::new(5, Duration::from_secs(1), Duration::from_secs(60))
+    }
+}
+
+/// Client-wide defaults applied to every group send.
+///
+/// Settings made with the `SendGroupMessageParams::with_*` builders take precedence
+/// over these defaults.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct MessagingDefaults {
+    /// Default for `SendGroupMessageParams::echo_to_sender`.
+    pub echo_to_sender: bool,
+    /// When set, `send_group_message` waits up to this long for the echo notification
+    /// (see `send_group_message_with_echo`) so returned messages carry reliable ordinals.
+    pub echo_wait: Option<Duration>,
+    /// Retry behavior for transient send failures.
+    pub retry: RetryPolicy,
+    /// Default for `SendGroupMessageParams::strict_bbcode`.
+    pub strict_bbcode: bool,
+}
+
+impl MessagingDefaults {
+    /// Set the default `echo_to_sender` value.
     ///
     /// # Returns
     ///
-    /// A `ChatRoomMessaging` handle for sending messages.
-    pub fn messaging(&self) -> ChatRoomMessaging<'_> {
-        ChatRoomMessaging {
-            connection: &self.connection,
-        }
+    /// `Self` for method chaining (builder pattern).
+    pub fn with_echo_to_sender(mut self, echo: bool) -> Self {
+        self.echo_to_sender = echo;
+        self
     }
 
-    /// Access notification listeners for incoming messages.
+    /// Wait up to `timeout` for each sent message's echo notification.
     ///
     /// # Returns
     ///
-    /// A `ChatRoomNotifications` handle for setting up message listeners.
-    pub fn notifications(&self) -> ChatRoomNotifications<'_> {
-        ChatRoomNotifications {
-            connection: &self.connection,
-        }
+    /// `Self` for method chaining (builder pattern).
+    pub fn with_echo_wait(mut self, timeout: Duration) -> Self {
+        self.echo_wait = Some(timeout);
+        self
     }
 
-    /// Get all chat room groups that the user is a member of.
+    /// Set the retry policy for transient send failures.
     ///
     /// # Returns
     ///
-    /// A list of `ChatRoomInfo` structures describing each chat room group the user belongs to.
+    /// `Self` for method chaining (builder pattern).
+    pub fn with_retry(mut self, retry: RetryPolicy) -> Self {
+        self.retry = retry;
+        self
+    }
+
+    /// Set the default `strict_bbcode` value.
     ///
-    /// # Errors
+    /// # Returns
     ///
-    /// Returns an error if the Steam API request fails.
-    pub async fn get_my_chat_rooms(&self) -> Result<Vec<ChatRoomInfo>, Box<dyn Error>> {
-        self.groups().get_my_chat_rooms().await
+    /// `Self` for method chaining (builder pattern).
+    pub fn with_strict_bbcode(mut self, strict: bool) -> Self {
+        self.strict_bbcode = strict;
+        self
     }
+}
 
-    /// Get all chat groups the user belongs to, including every chat room in each group.
-    ///
-    /// Uses the `GetMyChatRoomGroups` response directly, which already includes
-    /// per-group chat room lists in `group_summary.chat_rooms`.
-    pub async fn get_my_chat_groups(&self) -> Result<Vec<ChatGroupInfo>, Box<dyn Error>> {
-        self.groups().get_my_chat_groups().await
+/// Chat room client for Steam group chat functionality
+#[derive(Clone)]
+pub struct ChatRoomClient {
+    connection: steam_vent::Connection,
+    outbox: Option<Outbox>,
+    dedupe: Option<Arc<DedupeCache>>,
+    send_order: Arc<RoomSendLocks>,
+    defaults: MessagingDefaults,
+    metrics: Option<Arc<dyn MessagingMetrics>>,
+    notification_options: NotificationOptions,
+    bus: Arc<Mutex<Option<MessageBus>>>,
+    read_state: Option<Arc<ReadState>>,
+    watchdog: Option<Watchdog>,
+    activity: Arc<Activity>,
+    preprocessor: Arc<MessagePreprocessor>,
+    personas: Arc<PersonaCache>,
+}
+
+/// Group-related operations for chat rooms.
+pub struct ChatRoomGroups<'a> {
+    connection: &'a steam_vent::Connection,
+}
+
+/// Message sending helpers for chats and friends.
+pub struct ChatRoomMessaging<'a> {
+    connection: &'a steam_vent::Connection,
+    dedupe: Option<Arc<DedupeCache>>,
+    send_order: Arc<RoomSendLocks>,
+    defaults: MessagingDefaults,
+    metrics: Option<Arc<dyn MessagingMetrics>>,
+    read_state: Option<Arc<ReadState>>,
+    preprocessor: Arc<MessagePreprocessor>,
+}
+
+/// Group message sending that preserves submission order within each room.
+///
+/// Obtained from `ChatRoomMessaging::ordered()`.
+pub struct OrderedMessaging<'a> {
+    messaging: ChatRoomMessaging<'a>,
+}
+
+/// Notification listeners for chat and friend messages.
+pub struct ChatRoomNotifications<'a> {
+    connection: &'a steam_vent::Connection,
+    metrics: Option<Arc<dyn MessagingMetrics>>,
+    options: NotificationOptions,
+    read_state: Option<Arc<ReadState>>,
+    watchdog: Option<Watchdog>,
+    activity: Arc<Activity>,
+    preprocessor: Arc<MessagePreprocessor>,
+}
+
+/// Tuning for the notification streams behind listeners and message streams.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct NotificationOptions {
+    /// Minimum spacing between delivered notifications; `None` disables throttling.
+    pub throttle: Option<Duration>,
+    /// Delay before a listener returns a stream error.
+    pub error_backoff: Duration,
+    /// Drop group messages sent by this connection's own account, and echoes of our own
+    /// friend messages, before they reach listeners and streams.
+    ///
+    /// Enabled by default so bots using `echo_to_sender` or chatting from several
+    /// sessions don't answer themselves. Echo correlation in
+    /// `send_group_message_with_echo` is unaffected.
+    pub ignore_own_messages: bool,
+    /// Capacity of the bounded channel between a listener's notification stream and its
+    /// callback; `0` is treated as `1`.
+    ///
+    /// Only used when `overflow` is not `OverflowPolicy::Block`.
+    pub buffer_capacity: usize,
+    /// What a listener does when its buffer is full.
+    pub overflow: OverflowPolicy,
+    /// What a listener does when its callback panics.
+    pub on_panic: PanicPolicy,
+    /// Acknowledge group messages once a listener's callback returned `Ok` for them, like
+    /// `ChatRoomMessaging::ack_chat_message`, which also moves an installed `ReadState`.
+    ///
+    /// Acks are sent in the background, one per room for the newest handled timestamp,
+    /// and failures are logged. Pull-based streams are never acknowledged.
+    pub auto_ack: bool,
+}
+
+/// How a listener handles a panicking callback.
+///
+/// Panics are caught either way and reported as a callback error with the panic message,
+/// instead of unwinding through the listener.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum PanicPolicy {
+    /// Stop the listener with the panic as its error, like a returned callback error.
+    #[default]
+    Stop,
+    /// Log the panic and keep delivering notifications.
+    LogAndContinue,
+}
+
+/// How a listener handles notifications arriving faster than its callback returns.
+///
+/// Applies to the callback listeners; pull-based streams are consumed at the caller's pace.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum OverflowPolicy {
+    /// Pull the next notification only after the callback returns; nothing is buffered
+    /// or dropped by the listener.
+    #[default]
+    Block,
+    /// Keep draining the stream into the buffer and discard the oldest buffered
+    /// notification when full.
+    DropOldest,
+    /// Keep draining the stream into the buffer and discard new arrivals when full.
+    DropNewest,
+    /// Stop the listener with an error when the buffer overflows.
+    Fail,
+}
+
+impl Default for NotificationOptions {
+    fn default() -> Self {
+        Self {
+            throttle: Some(Duration::from_millis(25)),
+            error_backoff: Duration::from_millis(250),
+            ignore_own_messages: true,
+            buffer_capacity: 1024,
+            overflow: OverflowPolicy::Block,
+            on_panic: PanicPolicy::Stop,
+            auto_ack: false,
+        }
     }
+}
 
-    /// Join a chat room group.
+/// Parameters for sending a group message
+#[derive(Debug, Clone)]
+pub struct SendGroupMessageParams {
+    /// The unique identifier for the chat group.
+    pub chat_group_id: u64,
+    /// The unique identifier for the specific chat room within the group.
+    pub chat_id: u64,
+    /// The message text to send.
+    pub message: String,
+    /// Whether the message should be echoed back to the sender.
+    pub echo_to_sender: bool,
+    /// Whether the message is sent as a `/me` emote.
+    pub emote: bool,
+    /// Whether Steam may attach preview cards to links in the message.
+    pub link_previews: bool,
+    /// Whether the message is rejected locally if it contains invalid BBCode.
+    pub strict_bbcode: bool,
+    /// Whether backslash escapes such as `\[b\]` are sent as written.
+    pub preserve_escapes: bool,
+    /// Whether plain mentions are sent as `[mention]` pings so Steam notifies members.
+    pub server_mentions: bool,
+    overrides: ParamOverrides,
+}
+
+/// Which client defaults were overridden through the `with_*` builders.
+#[derive(Debug, Clone, Copy, Default)]
+struct ParamOverrides {
+    echo_to_sender: bool,
+    strict_bbcode: bool,
+}
+
+impl SendGroupMessageParams {
+    /// Create a new `SendGroupMessageParams` with default settings.
     ///
     /// # Arguments
     ///
     /// * `chat_group_id` - The unique identifier for the chat group
     /// * `chat_id` - The unique identifier for the specific chat room
-    /// * `invite_code` - Optional invite code required for private chat rooms
-    ///
-    /// # Returns
-    ///
-    /// The Steam API response containing join confirmation details.
+    /// * `message` - The message text to send (any type that can be converted to `String`)
     ///
-    /// # Errors
+    /// # Defaults
     ///
-    /// Returns an error if the join request fails or if an invalid invite code is provided.
-    pub async fn join_chat_room(
-        &self,
-        chat_group_id: u64,
-        chat_id: u64,
-        invite_code: Option<String>,
-    ) -> Result<CChatRoom_JoinChatRoomGroup_Response, Box<dyn Error>> {
-        self.groups()
-            .join_chat_room(chat_group_id, chat_id, invite_code)
-            .await
+    /// * `echo_to_sender` and `strict_bbcode` are `false` unless the client's
+    ///   `MessagingDefaults` say otherwise. Use `with_echo_to_sender()` or
+    ///   `with_strict_bbcode()` to override the client defaults for this call.
+    pub fn new(chat_group_id: u64, chat_id: u64, message: impl Into<String>) -> Self {
+        Self {
+            chat_group_id,
+            chat_id,
+            message: message.into(),
+            echo_to_sender: false,
+            emote: false,
+            link_previews: true,
+            strict_bbcode: false,
+            preserve_escapes: false,
+            server_mentions: true,
+            overrides: ParamOverrides::default(),
+        }
     }
 
-    /// Leave a chat room group.
+    /// Set whether the message should be echoed back to the sender.
     ///
     /// # Arguments
     ///
-    /// * `chat_group_id` - The unique identifier for the chat group to leave
+    /// * `echo` - If `true`, the message will be echoed back to the sender
     ///
-    /// # Errors
+    /// # Returns
     ///
-    /// Returns an error if the leave request fails.
-    pub async fn leave_chat_room(&self, chat_group_id: u64) -> Result<(), Box<dyn Error>> {
-        self.groups().leave_chat_room(chat_group_id).await
+    /// `Self` for method chaining (builder pattern).
+    pub fn with_echo_to_sender(mut self, echo: bool) -> Self {
+        self.echo_to_sender = echo;
+        self.overrides.echo_to_sender = true;
+        self
     }
 
-    /// Send a message to a group chat with preprocessing.
+    /// Set whether the message is sent as a `/me` emote.
     ///
-    /// The message will be preprocessed to extract BBCode and mentions before sending.
+    /// When enabled the `/me` prefix is added before sending (it is never duplicated).
     ///
     /// # Arguments
     ///
-    /// * `params` - Parameters for sending the message (see `SendGroupMessageParams`)
+    /// * `emote` - If `true`, the message is rendered as an action line
     ///
     /// # Returns
     ///
-    /// A `PreprocessedMessage` containing the original message, server-modified version,
-    /// parsed BBCode, and extracted mentions.
+    /// `Self` for method chaining (builder pattern).
+    pub fn with_emote(mut self, emote: bool) -> Self {
+        self.emote = emote;
+        self
+    }
+
+    /// Set whether Steam may attach link preview cards to URLs in the message.
     ///
-    /// # Errors
+    /// Disabling previews wraps bare links with `suppress_link_previews`. Steam has no
+    /// request-level switch for this, so links inside existing markup are not affected
+    /// and clients that ignore `[noembed]` may still show their own preview.
     ///
-    /// Returns an error if the message sending fails.
-    #[instrument(
-        name = "kether.chat.send_group_message",
-        skip(self, params),
-        fields(chat_group_id = params.chat_group_id, chat_id = params.chat_id)
-    )]
-    pub async fn send_group_message(
-        &self,
-        params: SendGroupMessageParams,
-    ) -> Result<PreprocessedMessage, Box<dyn Error>> {
-        self.messaging().send_group_message(params).await
+    /// # Arguments
+    ///
+    /// * `enabled` - If `false`, bare links are sent without preview cards
+    ///
+    /// # Returns
+    ///
+    /// `Self` for method chaining (builder pattern).
+    pub fn with_link_previews(mut self, enabled: bool) -> Self {
+        self.link_previews = enabled;
+        self
     }
 
-    /// Send a message to a friend.
+    /// Set whether the message is validated with `MessagePreprocessor::validate_bbcode`.
+    ///
+    /// When enabled, messages with unsupported tags (e.g. `[sub]`), unbalanced tags, or
+    /// malformed attributes are rejected with `ChatError::InvalidBBCode`, carrying every
+    /// diagnostic, before any network call, instead of being silently mangled by Steam.
     ///
     /// # Arguments
     ///
-    /// * `friend_steam_id` - The Steam ID of the friend to send the message to
-    /// * `message` - The message text to send
-    /// * `chat_entry_type` - The type of chat entry (message type identifier from Steam API)
+    /// * `strict` - If `true`, invalid BBCode fails the send
     ///
     /// # Returns
     ///
-    /// The Steam API response containing message confirmation details.
+    /// `Self` for method chaining (builder pattern).
+    pub fn with_strict_bbcode(mut self, strict: bool) -> Self {
+        self.strict_bbcode = strict;
+        self.overrides.strict_bbcode = true;
+        self
+    }
+
+    /// Set whether backslash-escaped brackets are sent as written.
     ///
-    /// # Errors
+    /// By default the message goes through `MessagePreprocessor::normalize_outgoing`,
+    /// which turns `\[b\]` into live `[b]` markup; that stays the default for
+    /// compatibility. Enable this to keep the escapes so Steam shows the brackets
+    /// literally, and use `MessagePreprocessor::escape_for_sending` to escape untrusted
+    /// text.
     ///
-    /// Returns an error if the message sending fails.
-    pub async fn send_friend_message(
-        &self,
-        friend_steam_id: SteamID,
-        message: &str,
-        chat_entry_type: i32,
-    ) -> Result<CFriendMessages_SendMessage_Response, Box<dyn Error>> {
-        self.messaging()
-            .send_friend_message(friend_steam_id, message, chat_entry_type)
-            .await
+    /// # Arguments
+    ///
+    /// * `preserve` - If `true`, escapes are not removed before sending
+    ///
+    /// # Returns
+    ///
+    /// `Self` for method chaining (builder pattern).
+    pub fn with_preserve_escapes(mut self, preserve: bool) -> Self {
+        self.preserve_escapes = preserve;
+        self
     }
 
-    /// Delete one or more group chat messages.
+    /// Set whether mentions in the message notify the mentioned members.
     ///
-    /// Messages are identified by their `server_timestamp` and `ordinal` values,
-    /// which are returned when sending messages via `send_group_message()`.
+    /// `CChatRoom_SendChatMessage_Request` has no mention fields; Steam fills in the
+    /// mentions of a sent message from its `[mention]` markup, as written by the Steam
+    /// client. When enabled (the default), plain `@all`, `@here` and SteamID mentions
+    /// are rewritten with `preprocessing::helpers::tag_mentions` before sending.
     ///
     /// # Arguments
     ///
-    /// * `chat_group_id` - The unique identifier for the chat group
-    /// * `chat_id` - The unique identifier for the specific chat room within the group
-    /// * `messages` - A vector of (server_timestamp, ordinal) tuples identifying messages to delete
+    /// * `enabled` - If `false`, mentions are sent as plain text and ping nobody
     ///
     /// # Returns
     ///
-    /// The Steam API response confirming the deletion.
-    ///
-    /// # Errors
-    ///
-    /// Returns an error if the deletion request fails. Note that attempting to delete
-    /// messages that have already been deleted or don't exist may not result in an error.
-    #[instrument(
-        name = "kether.chat.delete_group_messages",
-        skip(self, messages),
-        fields(chat_group_id, chat_id, message_count = messages.len())
-    )]
-    pub async fn delete_group_messages(
-        &self,
-        chat_group_id: u64,
-        chat_id: u64,
-        messages: Vec<(u32, u32)>,
-    ) -> Result<CChatRoom_DeleteChatMessages_Response, Box<dyn Error>> {
-        self.messaging()
-            .delete_group_messages(chat_group_id, chat_id, messages)
-            .await
+    /// `Self` for method chaining (builder pattern).
+    pub fn with_server_mentions(mut self, enabled: bool) -> Self {
+        self.server_mentions = enabled;
+        self
     }
 
-    /// Delete group chat messages from `PreprocessedMessage` objects.
+    /// Create parameters for a message composed with a `MessageBuilder`.
     ///
-    /// This is a convenience method that extracts message identifiers from
-    /// `PreprocessedMessage` objects (returned by `send_group_message()`) and deletes them.
-    /// Any messages where `server_timestamp` or `ordinal` is `None` will be skipped with a warning.
+    /// The builder escapes plain text itself, so escapes are preserved when sending (see
+    /// `with_preserve_escapes`).
     ///
     /// # Arguments
     ///
     /// * `chat_group_id` - The unique identifier for the chat group
-    /// * `chat_id` - The unique identifier for the specific chat room within the group
-    /// * `messages` - A vector of `PreprocessedMessage` objects to delete
-    ///
-    /// # Returns
-    ///
-    /// The Steam API response confirming the deletion, or an error if no valid messages
-    /// were found or the deletion request fails.
+    /// * `chat_id` - The unique identifier for the specific chat room
+    /// * `builder` - The composed message
     ///
     /// # Errors
     ///
-    /// Returns an error if no valid message identifiers are found (all messages had
-    /// missing `server_timestamp` or `ordinal`), if the messages list is empty, or if
-    /// the deletion request fails.
-    #[instrument(
-        name = "kether.chat.delete_group_messages_from_preprocessed",
-        skip(self, messages),
-        fields(chat_group_id, chat_id, input_message_count = messages.len())
-    )]
-    pub async fn delete_group_messages_from_preprocessed(
-        &self,
+    /// Returns a `LengthError` if the message exceeds the builder's length limit.
+    pub fn from_builder(
         chat_group_id: u64,
         chat_id: u64,
-        messages: Vec<PreprocessedMessage>,
-    ) -> Result<CChatRoom_DeleteChatMessages_Response, Box<dyn Error>> {
-        self.messaging()
-            .delete_group_messages_from_preprocessed(chat_group_id, chat_id, messages)
-            .await
+        builder: MessageBuilder,
+    ) -> Result<Self, LengthError> {
+        Ok(Self::new(chat_group_id, chat_id, builder.build()?).with_preserve_escapes(true))
     }
 
-    /// Add a reaction to a group chat message.
+    /// Create parameters for a rendered `MessageTemplate`.
+    ///
+    /// The template escapes its values, so escapes are preserved when sending (see
+    /// `with_preserve_escapes`); otherwise a value's `\[url\]` would go out as live markup.
     ///
     /// # Arguments
     ///
     /// * `chat_group_id` - The unique identifier for the chat group
     /// * `chat_id` - The unique identifier for the specific chat room
-    /// * `server_timestamp` - The message server timestamp
-    /// * `ordinal` - The message ordinal (can be 0)
-    /// * `reaction_type` - Reaction category (emoticon or sticker)
-    /// * `reaction` - Reaction value (e.g. `:steamhappy:` for emoticons)
+    /// * `template` - A template validated by `MessageTemplate::new`
+    /// * `values` - Placeholder values
     ///
-    /// # Returns
+    /// # Errors
     ///
-    /// The updated number of reactors for this reaction.
-    #[instrument(
-        name = "kether.chat.add_message_reaction",
-        skip(self, reaction),
-        fields(chat_group_id, chat_id, server_timestamp, ordinal, reaction_type = ?reaction_type)
-    )]
-    pub async fn add_message_reaction(
-        &self,
+    /// Returns a `TemplateError` if a value is missing or the message is too long.
+    pub fn from_template(
         chat_group_id: u64,
         chat_id: u64,
-        server_timestamp: u32,
-        ordinal: u32,
-        reaction_type: ReactionType,
-        reaction: &str,
-    ) -> Result<u32, Box<dyn Error>> {
-        self.messaging()
-            .add_message_reaction(
-                chat_group_id,
-                chat_id,
-                server_timestamp,
-                ordinal,
-                reaction_type,
-                reaction,
-            )
-            .await
+        template: &MessageTemplate,
+        values: &HashMap<&str, String>,
+    ) -> Result<Self, TemplateError> {
+        Ok(Self::new(chat_group_id, chat_id, template.render(values)?).with_preserve_escapes(true))
     }
 
-    /// Remove a reaction from a group chat message.
-    ///
-    /// # Returns
-    ///
-    /// The updated number of reactors for this reaction after removal.
-    #[instrument(
-        name = "kether.chat.remove_message_reaction",
-        skip(self, reaction),
-        fields(chat_group_id, chat_id, server_timestamp, ordinal, reaction_type = ?reaction_type)
-    )]
-    pub async fn remove_message_reaction(
-        &self,
-        chat_group_id: u64,
-        chat_id: u64,
-        server_timestamp: u32,
-        ordinal: u32,
-        reaction_type: ReactionType,
-        reaction: &str,
-    ) -> Result<u32, Box<dyn Error>> {
-        self.messaging()
-            .remove_message_reaction(
-                chat_group_id,
-                chat_id,
-                server_timestamp,
-                ordinal,
-                reaction_type,
-                reaction,
-            )
-            .await
+    /// `echo_to_sender` if it was set through `with_echo_to_sender`, `None` when the
+    /// client default applies.
+    pub(crate) fn echo_to_sender_override(&self) -> Option<bool> {
+        self.overrides.echo_to_sender.then_some(self.echo_to_sender)
     }
 
-    /// List users who reacted with a specific reaction on a message.
-    ///
-    /// # Arguments
-    ///
-    /// * `limit` - Optional max number of reactors to return
-    ///
-    /// # Returns
-    ///
-    /// A list of reactor Steam IDs.
-    #[instrument(
-        name = "kether.chat.get_message_reaction_reactors",
-        skip(self, reaction),
-        fields(chat_group_id, chat_id, server_timestamp, ordinal, reaction_type = ?reaction_type, limit = ?limit)
-    )]
-    #[allow(clippy::too_many_arguments)]
-    pub async fn get_message_reaction_reactors(
-        &self,
-        chat_group_id: u64,
-        chat_id: u64,
-        server_timestamp: u32,
-        ordinal: u32,
-        reaction_type: ReactionType,
-        reaction: &str,
-        limit: Option<u32>,
-    ) -> Result<Vec<SteamID>, Box<dyn Error>> {
-        self.messaging()
-            .get_message_reaction_reactors(
-                chat_group_id,
-                chat_id,
-                server_timestamp,
-                ordinal,
-                reaction_type,
-                reaction,
-                limit,
-            )
-            .await
+    /// `strict_bbcode` if it was set through `with_strict_bbcode`, `None` when the
+    /// client default applies.
+    pub(crate) fn strict_bbcode_override(&self) -> Option<bool> {
+        self.overrides.strict_bbcode.then_some(self.strict_bbcode)
     }
 
-    /// Get message history for a chat room, including per-message reaction summaries.
-    #[instrument(
-        name = "kether.chat.get_message_history",
-        skip(self),
-        fields(chat_group_id, chat_id, max_count = ?max_count)
-    )]
-    pub async fn get_message_history(
-        &self,
-        chat_group_id: u64,
-        chat_id: u64,
-        max_count: Option<u32>,
-    ) -> Result<Vec<ChatMessageHistoryEntry>, Box<dyn Error>> {
-        self.messaging()
-            .get_message_history(chat_group_id, chat_id, max_count)
-            .await
+    fn effective_echo_to_sender(&self, defaults: &MessagingDefaults) -> bool {
+        if self.overrides.echo_to_sender {
+            self.echo_to_sender
+        } else {
+            defaults.echo_to_sender
+        }
     }
 
-    /// Get the current state of a chat room group.
-    ///
-    /// # Arguments
-    ///
-    /// * `chat_group_id` - The unique identifier for the chat group
-    ///
-    /// # Returns
-    ///
-    /// The Steam API response containing the current state of the chat room group.
-    ///
-    /// # Errors
-    ///
-    /// Returns an error if the state request fails.
-    pub async fn get_chat_room_state(
-        &self,
-        chat_group_id: u64,
-    ) -> Result<CChatRoom_GetChatRoomGroupState_Response, Box<dyn Error>> {
-        self.groups().get_chat_room_state(chat_group_id).await
+    fn effective_strict_bbcode(&self, defaults: &MessagingDefaults) -> bool {
+        if self.overrides.strict_bbcode {
+            self.strict_bbcode
+        } else {
+            defaults.strict_bbcode
+        }
     }
+}
 
-    /// Listen for incoming group chat messages with preprocessing.
-    ///
-    /// Messages are automatically preprocessed to extract BBCode and mentions.
-    ///
-    /// # Arguments
+impl From<(u64, u64, &str)> for SendGroupMessageParams {
+    /// Build params from `(chat_group_id, chat_id, message)` with default settings.
+    fn from((chat_group_id, chat_id, message): (u64, u64, &str)) -> Self {
+        Self::new(chat_group_id, chat_id, message)
+    }
+}
+
+impl From<(u64, u64, String, bool)> for SendGroupMessageParams {
+    /// Build params from `(chat_group_id, chat_id, message, echo_to_sender)`.
+    fn from((chat_group_id, chat_id, message, echo_to_sender): (u64, u64, String, bool)) -> Self {
+        Self::new(chat_group_id, chat_id, message).with_echo_to_sender(echo_to_sender)
+    }
+}
+
+struct NotificationStream<'a, T> {
+    inner: Pin<Box<dyn Stream<Item = Result<T, steam_vent::NetworkError>> + Send + 'a>>,
+    options: NotificationOptions,
+    metrics: Option<Arc<dyn MessagingMetrics>>,
+    watchdog: Option<(Watchdog, &'a steam_vent::Connection)>,
+}
+
+/// Stream of incoming group chat messages returned by `group_message_stream`.
+///
+/// Messages are preprocessed lazily as they are polled. Transport errors are yielded as
+/// `ChatError::Network` items and do not end the stream.
+pub struct GroupMessageStream<'a> {
+    notifications: NotificationStream<'a, CChatRoom_IncomingChatMessage_Notification>,
+    filter: Option<RoomFilter>,
+    mentions: Option<MentionTargets>,
+    own_steam_id: u64,
+    ignore_own: bool,
+    preprocessor: Arc<MessagePreprocessor>,
+}
+
+impl<'a> GroupMessageStream<'a> {
+    /// Only yield messages from rooms accepted by `filter`.
     ///
-    /// * `callback` - A closure that will be called for each incoming message
+    /// Rejected notifications are dropped before preprocessing, so they cost no BBCode
+    /// parsing or mention extraction.
     ///
-    /// # Errors
+    /// # Returns
     ///
-    /// Returns an error if the notification stream fails or the callback panics.
-    pub async fn listen_for_group_messages<F>(&self, callback: F) -> Result<(), Box<dyn Error>>
-    where
-        F: FnMut(EnhancedGroupChatMessage) + Send + 'static,
-    {
-        self.notifications()
-            .listen_for_group_messages(callback)
-            .await
+    /// `Self` for method chaining (builder pattern).
+    pub fn filter_rooms(mut self, filter: RoomFilter) -> Self {
+        self.filter = Some(filter);
+        self
     }
 
-    /// Listen for incoming message reaction events.
+    /// Override `NotificationOptions::ignore_own_messages` for this stream.
     ///
     /// # Arguments
     ///
-    /// * `callback` - A closure that will be called for each reaction event
+    /// * `ignore` - Whether messages sent by this connection's own account are dropped
     ///
-    /// # Errors
+    /// # Returns
     ///
-    /// Returns an error if the notification stream fails or the callback panics.
-    pub async fn listen_for_reactions<F>(&self, callback: F) -> Result<(), Box<dyn Error>>
-    where
-        F: FnMut(ReactionEvent) + Send + 'static,
-    {
-        self.notifications().listen_for_reactions(callback).await
+    /// `Self` for method chaining (builder pattern).
+    pub fn ignore_own_messages(mut self, ignore: bool) -> Self {
+        self.ignore_own = ignore;
+        self
     }
 
-    /// Listen for incoming friend messages.
+    /// Only yield messages that mention one of `targets`.
+    ///
+    /// Use `MentionTargets::with_minimum_level` to drop weaker pings, e.g. `@here`.
     ///
     /// # Arguments
     ///
-    /// * `callback` - A closure that will be called for each incoming friend message
+    /// * `targets` - The mentions to keep; see `MentionTargets`
     ///
-    /// # Errors
+    /// # Returns
     ///
-    /// Returns an error if the notification stream fails or the callback panics.
-    pub async fn listen_for_friend_messages<F>(&self, callback: F) -> Result<(), Box<dyn Error>>
-    where
-        F: FnMut(FriendMessage) + Send + 'static,
-    {
-        self.notifications()
-            .listen_for_friend_messages(callback)
-            .await
+    /// `Self` for method chaining (builder pattern).
+    pub fn mentions_only(mut self, targets: MentionTargets) -> Self {
+        self.mentions = Some(targets);
+        self
     }
+}
 
-    /// Get the underlying Steam connection for advanced operations.
-    ///
-    /// This provides direct access to the `steam-vent` connection, allowing
-    /// you to perform operations not covered by the high-level API.
-    pub fn connection(&self) -> &steam_vent::Connection {
-        &self.connection
-    }
+impl Stream for GroupMessageStream<'_> {
+    type Item = Result<EnhancedGroupChatMessage, ChatError>;
 
-    /// Get a mutable reference to the underlying Steam connection.
-    ///
-    /// This provides mutable access to the `steam-vent` connection for advanced use cases.
-    pub fn connection_mut(&mut self) -> &mut steam_vent::Connection {
-        &mut self.connection
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        loop {
+            let notification = match self.notifications.inner.as_mut().poll_next(cx) {
+                Poll::Ready(Some(Ok(notification))) => notification,
+                Poll::Ready(Some(Err(err))) => {
+                    return Poll::Ready(Some(Err(ChatError::Network(err))));
+                }
+                Poll::Ready(None) => return Poll::Ready(None),
+                Poll::Pending => return Poll::Pending,
+            };
+            if let Some(filter) = &self.filter
+                && !filter.matches(notification.chat_group_id(), notification.chat_id())
+            {
+                continue;
+            }
+            if self.ignore_own && notification.steamid_sender() == self.own_steam_id {
+                continue;
+            }
+            let message =
+                EnhancedGroupChatMessage::from_notification_with(&notification, &self.preprocessor);
+            if let Some(targets) = &self.mentions
+                && !message.mentions(targets, SteamID::from(self.own_steam_id))
+            {
+                continue;
+            }
+            if let Some(metrics) = &self.notifications.metrics {
+                metrics.record_notification();
+            }
+            return Poll::Ready(Some(Ok(message)));
+        }
     }
 }
 
-impl<'a, T> NotificationStream<'a, T>
-where
-    T: Send + 'static,
-{
-    fn new<S>(stream: S, backoff: Duration) -> Self
-    where
-        S: Stream<Item = Result<T, steam_vent::NetworkError>> + Send + 'a,
-    {
-        Self {
-            inner: FuturesStreamExt::boxed(stream),
-            backoff,
+/// State threaded through the stream returned by `ChatRoomClient::catch_up`.
+struct CatchUpState<'a> {
+    client: &'a ChatRoomClient,
+    live: GroupMessageStream<'a>,
+    backlog: Option<VecDeque<EnhancedGroupChatMessage>>,
+    cursor: CatchUpCursor,
+}
+
+struct ResumeState<'a, S> {
+    messages: Option<
+        Pin<Box<dyn Stream<Item = Result<EnhancedGroupChatMessage, ChatError>> + Send + 'a>>,
+    >,
+    load_error: Option<CursorStoreError>,
+    store: S,
+    cursor: CatchUpCursor,
+    handed_out: Option<EnhancedGroupChatMessage>,
+}
+
+/// Errors raised by chat operations.
+#[derive(Debug, Error)]
+pub enum ChatError {
+    /// The message failed strict BBCode validation.
+    #[error("invalid BBCode at byte {position}: {reason}")]
+    InvalidBBCode {
+        /// Byte offset of the offending tag in the outgoing message.
+        position: usize,
+        /// What is wrong with the tag.
+        reason: String,
+        /// Every problem in the message, as reported by
+        /// `MessagePreprocessor::validate_bbcode`; `position` and `reason` describe the
+        /// first. Empty when converted from a lone `BBCodeError`.
+        diagnostics: Vec<BBCodeDiagnostic>,
+    },
+    /// No matching echo notification arrived for a sent message.
+    #[error("no echo for message in chat {chat_group_id}/{chat_id} within {waited:?}")]
+    EchoTimeout {
+        /// The unique identifier for the chat group.
+        chat_group_id: u64,
+        /// The unique identifier for the specific chat room within the group.
+        chat_id: u64,
+        /// How long the echo was awaited.
+        waited: Duration,
+    },
+    /// The Steam connection reported an error.
+    #[error("network error: {0}")]
+    Network(#[from] steam_vent::NetworkError),
+    /// Loading or saving a resumable stream's cursor failed.
+    #[error(transparent)]
+    CursorStore(#[from] CursorStoreError),
+    /// The message is longer than Steam accepts.
+    #[error(transparent)]
+    MessageTooLong(#[from] LengthError),
+    /// The send was suppressed because an identical message was sent recently.
+    #[error(transparent)]
+    Duplicate(#[from] DuplicateMessageError),
+    /// The arguments were rejected before anything was sent to Steam.
+    #[error("invalid input: {0}")]
+    InvalidInput(String),
+}
+
+impl From<BBCodeError> for ChatError {
+    fn from(value: BBCodeError) -> Self {
+        ChatError::InvalidBBCode {
+            position: value.position,
+            reason: value.reason,
+            diagnostics: Vec::new(),
         }
     }
+}
 
-    async fn for_each<F>(mut self, mut handler: F) -> Result<(), NotificationDispatchError>
-    where
-        F: FnMut(T) -> CallbackResult + Send + 'static,
-    {
-        while let Some(result) = StreamExt::next(&mut self.inner).await {
-            match result {
-                Ok(item) => handler(item)
-                    .map_err(|source| NotificationDispatchError::Callback { source })?,
-                Err(err) => {
-                    sleep(self.backoff).await;
-                    return Err(NotificationDispatchError::Stream(err));
-                }
-            }
+impl ChatError {
+    /// Get the error inventory entry containing classification and retry guidance.
+    ///
+    /// Network failures, including error results returned by the Steam API, go through
+    /// `classify_network_error`. Errors raised before anything reached Steam are fatal,
+    /// since resending the same request fails the same way.
+    pub fn inventory(&self) -> ErrorInventoryEntry {
+        match self {
+            ChatError::Network(network) => classify_network_error(network),
+            ChatError::EchoTimeout {
+                chat_group_id,
+                chat_id,
+                ..
+            } => ErrorInventoryEntry::new(
+                ErrorDomain::Transport,
+                RetryDisposition::BackoffRetry,
+                "sent message was not echoed",
+            )
+            .with_context(format!("chat {chat_group_id}/{chat_id}")),
+            ChatError::CursorStore(_) => ErrorInventoryEntry::new(
+                ErrorDomain::Application,
+                RetryDisposition::BackoffRetry,
+                "cursor store unavailable",
+            ),
+            ChatError::Duplicate(duplicate) => ErrorInventoryEntry::new(
+                ErrorDomain::Application,
+                RetryDisposition::Fatal,
+                "duplicate message suppressed",
+            )
+            .with_context(format!(
+                "chat {}/{}",
+                duplicate.chat_group_id, duplicate.chat_id
+            )),
+            ChatError::InvalidBBCode { .. }
+            | ChatError::MessageTooLong(_)
+            | ChatError::InvalidInput(_) => ErrorInventoryEntry::new(
+                ErrorDomain::Application,
+                RetryDisposition::Fatal,
+                "request rejected before reaching Steam",
+            ),
         }
+    }
 
-        Ok(())
+    /// Report this error, raised during `operation`, with its own classification.
+    pub fn report(&self, operation: &str) -> ErrorReport {
+        ErrorReport::new(operation, self, self.inventory())
     }
-}
 
-impl<'a> ChatRoomGroups<'a> {
-    fn chats_from_group_summary(
-        summary: &steam_vent_proto::steammessages_chat_steamclient::CChatRoom_GetChatRoomGroupSummary_Response,
-    ) -> Vec<ChatRoomInfo> {
-        let group_id = summary.chat_group_id();
-        let group_name = summary.chat_group_name().to_string();
-        let chats: Vec<ChatRoomInfo> = summary
-            .chat_rooms
-            .iter()
-            .map(|room| ChatRoomInfo {
-                chat_group_id: group_id,
-                chat_id: room.chat_id(),
-                chat_name: room.chat_name().to_string(),
-                chat_group_name: group_name.clone(),
-                is_joined: true,
-            })
-            .collect();
+    /// Fail strict BBCode validation with `diagnostics`, or pass when there are none.
+    fn check_bbcode(diagnostics: Vec<BBCodeDiagnostic>) -> Result<(), Self> {
+        let Some(first) = diagnostics.first() else {
+            return Ok(());
+        };
+        Err(ChatError::InvalidBBCode {
+            position: first.byte_range.start,
+            reason: first.detail.clone(),
+            diagnostics,
+        })
+    }
+}
 
-        if chats.is_empty() {
-            vec![ChatRoomInfo {
-                chat_group_id: group_id,
-                chat_id: summary.default_chat_id(),
-                chat_name: group_name.clone(),
-                chat_group_name: group_name,
-                is_joined: true,
-            }]
-        } else {
-            chats
-        }
+/// Text of a caught panic payload.
+fn panic_message(payload: &(dyn std::any::Any + Send)) -> String {
+    if let Some(message) = payload.downcast_ref::<&str>() {
+        (*message).to_string()
+    } else if let Some(message) = payload.downcast_ref::<String>() {
+        message.clone()
+    } else {
+        "non-string panic payload".to_string()
     }
+}
 
-    /// Get all chat groups the user belongs to, including every chat room in each group.
-    pub async fn get_my_chat_groups(&self) -> Result<Vec<ChatGroupInfo>, Box<dyn Error>> {
-        let req = CChatRoom_GetMyChatRoomGroups_Request::new();
-        let response: CChatRoom_GetMyChatRoomGroups_Response =
-            self.connection.service_method(req).await?;
+/// Why a notification listener stopped.
+///
+/// Returned by the `listen_for_*` methods so callers can tell a broken stream from a
+/// failing callback without matching on strings. Use `classify_dispatch_error` to decide
+/// whether restarting the listener is worthwhile.
+#[derive(Debug, Error)]
+#[non_exhaustive]
+pub enum NotificationDispatchError {
+    /// The notification stream failed.
+    #[error("notification stream error: {0}")]
+    Stream(#[from] steam_vent::NetworkError),
+    /// The callback returned an error or panicked.
+    #[error("notification callback failed")]
+    Callback {
+        /// The callback's error.
+        #[source]
+        source: Box<dyn Error + Send + Sync>,
+    },
+    /// The listener's buffer was full under `OverflowPolicy::Fail`.
+    #[error("notification buffer overflowed its capacity of {capacity}")]
+    Overflow {
+        /// The configured `NotificationOptions::buffer_capacity`.
+        capacity: usize,
+    },
+}
 
-        let mut groups = Vec::new();
-        for pair in &response.chat_room_groups {
-            if let Some(summary) = pair.group_summary.as_ref() {
-                groups.push(ChatGroupInfo {
-                    chat_group_id: summary.chat_group_id(),
-                    chat_group_name: summary.chat_group_name().to_string(),
-                    chats: Self::chats_from_group_summary(summary),
-                });
-            }
-        }
+impl NotificationDispatchError {
+    /// Whether the notification stream failed, as opposed to the callback.
+    pub fn is_stream_error(&self) -> bool {
+        matches!(self, Self::Stream(_))
+    }
 
-        Ok(groups)
+    /// The error returned by the callback, if the callback failed.
+    pub fn callback_source(&self) -> Option<&(dyn Error + Send + Sync + 'static)> {
+        match self {
+            Self::Callback { source } => Some(source.as_ref()),
+            _ => None,
+        }
     }
 
-    /// Get all chat room groups that the user is a member of.
-    ///
-    /// # Returns
-    ///
-    /// A list of `ChatRoomInfo` structures describing each chat room group the user belongs to.
+    /// Take ownership of the callback's error, e.g. to downcast it.
     ///
     /// # Errors
     ///
-    /// Returns an error if the Steam API request fails.
-    pub async fn get_my_chat_rooms(&self) -> Result<Vec<ChatRoomInfo>, Box<dyn Error>> {
-        let req = CChatRoom_GetMyChatRoomGroups_Request::new();
-        let response: CChatRoom_GetMyChatRoomGroups_Response =
-            self.connection.service_method(req).await?;
+    /// Returns `self` unchanged if the callback did not fail.
+    pub fn into_callback_source(self) -> Result<Box<dyn Error + Send + Sync>, Self> {
+        match self {
+            Self::Callback { source } => Ok(source),
+            other => Err(other),
+        }
+    }
 
-        let mut chat_rooms = Vec::new();
-        for pair in &response.chat_room_groups {
-            if let Some(group_summary) = pair.group_summary.as_ref() {
-                let chat_room = ChatRoomInfo {
-                    chat_group_id: group_summary.chat_group_id(),
-                    chat_id: group_summary.default_chat_id(),
-                    chat_name: group_summary.chat_group_name().to_string(),
-                    chat_group_name: group_summary.chat_group_name().to_string(),
-                    is_joined: true,
-                };
-                chat_rooms.push(chat_room);
-            }
+    /// The stream's network error, if the stream failed.
+    pub fn network_error(&self) -> Option<&steam_vent::NetworkError> {
+        match self {
+            Self::Stream(err) => Some(err),
+            _ => None,
         }
+    }
 
-        Ok(chat_rooms)
+    /// Map an error yielded by a message stream; only network errors are expected there.
+    fn from_stream(err: ChatError) -> Self {
+        match err {
+            ChatError::Network(err) => Self::Stream(err),
+            other => Self::Callback {
+                source: Box::new(other),
+            },
+        }
     }
+}
 
-    /// Join a chat room group.
+impl ChatRoomClient {
+    /// Create a new chat room client from an existing connection.
     ///
     /// # Arguments
     ///
-    /// * `chat_group_id` - The unique identifier for the chat group
-    /// * `chat_id` - The unique identifier for the specific chat room
-    /// * `invite_code` - Optional invite code required for private chat rooms
+    /// * `connection` - An established Steam connection from `LogOn::connection()`
+    pub fn new(connection: steam_vent::Connection) -> Self {
+        Self {
+            connection,
+            outbox: None,
+            dedupe: None,
+            send_order: Arc::new(RoomSendLocks::default()),
+            defaults: MessagingDefaults::default(),
+            metrics: None,
+            notification_options: NotificationOptions::default(),
+            bus: Arc::default(),
+            read_state: None,
+            watchdog: None,
+            activity: Arc::new(Activity::default()),
+            preprocessor: Arc::new(MessagePreprocessor::default()),
+            personas: Arc::new(PersonaCache::default()),
+        }
+    }
+
+    /// Route group message sends through a persistent outbox.
     ///
-    /// # Returns
+    /// # Arguments
     ///
-    /// The Steam API response containing join confirmation details.
+    /// * `outbox` - The outbox used to persist and replay pending sends
     ///
-    /// # Errors
+    /// # Returns
     ///
-    /// Returns an error if the join request fails or if an invalid invite code is provided.
-    pub async fn join_chat_room(
-        &self,
-        chat_group_id: u64,
-        chat_id: u64,
-        invite_code: Option<String>,
-    ) -> Result<CChatRoom_JoinChatRoomGroup_Response, Box<dyn Error>> {
-        let mut req = CChatRoom_JoinChatRoomGroup_Request::new();
-        req.set_chat_group_id(chat_group_id);
-        req.set_chat_id(chat_id);
-
-        if let Some(code) = invite_code {
-            req.set_invite_code(code);
-        }
-
-        let response: CChatRoom_JoinChatRoomGroup_Response =
-            self.connection.service_method(req).await?;
-        Ok(response)
+    /// `Self` for method chaining (builder pattern).
+    pub fn with_outbox(mut self, outbox: Outbox) -> Self {
+        self.outbox = Some(outbox);
+        self
     }
 
-    /// Leave a chat room group.
+    /// Suppress identical group messages sent to the same room within `window`.
+    ///
+    /// The cache is shared by every `messaging()` handle created from this client.
+    /// Suppressed sends fail with a `DuplicateMessageError`.
     ///
     /// # Arguments
     ///
-    /// * `chat_group_id` - The unique identifier for the chat group to leave
+    /// * `window` - How long an identical message is considered a duplicate
     ///
-    /// # Errors
+    /// # Returns
     ///
-    /// Returns an error if the leave request fails.
-    pub async fn leave_chat_room(&self, chat_group_id: u64) -> Result<(), Box<dyn Error>> {
-        let mut req = CChatRoom_LeaveChatRoomGroup_Request::new();
-        req.set_chat_group_id(chat_group_id);
+    /// `Self` for method chaining (builder pattern).
+    pub fn with_dedupe(mut self, window: Duration) -> Self {
+        self.dedupe = Some(Arc::new(DedupeCache::new(window)));
+        self
+    }
 
-        let _response: CChatRoom_LeaveChatRoomGroup_Response =
-            self.connection.service_method(req).await?;
-        Ok(())
+    /// Set client-wide defaults for group sends.
+    ///
+    /// # Arguments
+    ///
+    /// * `defaults` - Defaults inherited by every `SendGroupMessageParams` unless overridden
+    ///
+    /// # Returns
+    ///
+    /// `Self` for method chaining (builder pattern).
+    pub fn with_messaging_defaults(mut self, defaults: MessagingDefaults) -> Self {
+        self.defaults = defaults;
+        self
     }
 
-    /// Get the current state of a chat room group.
+    /// Record send, delete, and notification metrics with `metrics`.
+    ///
+    /// Without a recorder no timing or error classification is performed.
     ///
     /// # Arguments
     ///
-    /// * `chat_group_id` - The unique identifier for the chat group
+    /// * `metrics` - The recorder, e.g. an `Arc<AtomicMetrics>` kept by the caller for snapshots
     ///
     /// # Returns
     ///
-    /// The Steam API response containing the current state of the chat room group.
+    /// `Self` for method chaining (builder pattern).
+    pub fn with_metrics(mut self, metrics: Arc<dyn MessagingMetrics>) -> Self {
+        self.metrics = Some(metrics);
+        self
+    }
+
+    /// Set the throttle and error backoff used by this client's notification listeners.
     ///
-    /// # Errors
+    /// # Arguments
     ///
-    /// Returns an error if the state request fails.
-    pub async fn get_chat_room_state(
-        &self,
-        chat_group_id: u64,
-    ) -> Result<CChatRoom_GetChatRoomGroupState_Response, Box<dyn Error>> {
-        let mut req = CChatRoom_GetChatRoomGroupState_Request::new();
-        req.set_chat_group_id(chat_group_id);
+    /// * `options` - Options applied to every `notifications()` handle and message stream
+    ///
+    /// # Returns
+    ///
+    /// `Self` for method chaining (builder pattern).
+    pub fn with_notification_options(mut self, options: NotificationOptions) -> Self {
+        self.notification_options = options;
+        self
+    }
 
-        let response: CChatRoom_GetChatRoomGroupState_Response =
-            self.connection.service_method(req).await?;
-        Ok(response)
+    /// Get the client-wide defaults for group sends.
+    pub fn messaging_defaults(&self) -> &MessagingDefaults {
+        &self.defaults
     }
-}
 
-impl<'a> ChatRoomMessaging<'a> {
-    /// Send a message to a group chat with preprocessing.
+    /// Track unread counts and read cursors per room with `read_state`.
     ///
-    /// The message will be preprocessed to extract BBCode and mentions before sending.
+    /// Group messages from other users seen by this client's listeners and streams are
+    /// recorded as unread; `ack_chat_message` marks them read.
     ///
     /// # Arguments
     ///
-    /// * `params` - Parameters for sending the message (see `SendGroupMessageParams`)
+    /// * `read_state` - The tracker, e.g. restored from a previous run with serde
     ///
     /// # Returns
     ///
-    /// A `PreprocessedMessage` containing the original message, server-modified version,
-    /// parsed BBCode, and extracted mentions.
+    /// `Self` for method chaining (builder pattern).
+    pub fn with_read_state(mut self, read_state: Arc<ReadState>) -> Self {
+        self.read_state = Some(read_state);
+        self
+    }
+
+    /// Probe the connection when a listener receives no notifications for a while.
     ///
-    /// # Errors
+    /// Applies to every listener started from this client, including restartable ones,
+    /// which resubscribe when the probe fails.
     ///
-    /// Returns an error if the message sending fails.
-    pub async fn send_group_message(
-        &self,
-        params: SendGroupMessageParams,
-    ) -> Result<PreprocessedMessage, Box<dyn Error>> {
-        let req = Self::build_send_message_request(&params);
-        let response: CChatRoom_SendChatMessage_Response =
-            self.connection.service_method(req).await?;
-        let final_preprocessed = Self::process_send_message_response(&params, &response);
+    /// # Arguments
+    ///
+    /// * `watchdog` - The idle window and where to report `StreamStalled` events
+    ///
+    /// # Returns
+    ///
+    /// `Self` for method chaining (builder pattern).
+    pub fn with_watchdog(mut self, watchdog: Watchdog) -> Self {
+        self.watchdog = Some(watchdog);
+        self
+    }
 
-        // According to DrMcKay's wiki, the response has both server_timestamp and ordinal.
-        // Ordinal can be 0 (and can be omitted in deletion requests if 0).
-        // The response values are sufficient for deletion, so we don't need to wait for the notification.
-        // This avoids unnecessary delays.
+    /// Use a custom `MessagePreprocessor` for sends and notifications.
+    ///
+    /// Applies to validation and preprocessing of outgoing messages as well as to the
+    /// enhanced messages delivered by listeners, streams and the message bus.
+    ///
+    /// # Arguments
+    ///
+    /// * `preprocessor` - The preprocessor to use, e.g. one restricted with
+    ///   `MessagePreprocessor::with_allowed_tags`
+    ///
+    /// # Returns
+    ///
+    /// `Self` for method chaining (builder pattern).
+    pub fn with_preprocessor(mut self, preprocessor: MessagePreprocessor) -> Self {
+        self.preprocessor = Arc::new(preprocessor);
+        self
+    }
 
-        debug!(
-            chat_group_id = params.chat_group_id,
-            chat_id = params.chat_id,
-            ordinal = final_preprocessed.ordinal.unwrap_or(0),
-            "group message dispatched"
-        );
+    /// The preprocessor used for sends and notifications.
+    pub fn preprocessor(&self) -> &MessagePreprocessor {
+        &self.preprocessor
+    }
 
-        Ok(final_preprocessed)
+    /// Reuse personas resolved by `resolve_mentions` for `ttl` before looking them up again.
+    ///
+    /// # Arguments
+    ///
+    /// * `ttl` - How long a resolved persona name and avatar stay cached
+    ///
+    /// # Returns
+    ///
+    /// `Self` for method chaining (builder pattern).
+    pub fn with_persona_cache_ttl(mut self, ttl: Duration) -> Self {
+        self.personas = Arc::new(PersonaCache::new(ttl));
+        self
     }
 
-    fn build_send_message_request(
-        params: &SendGroupMessageParams,
-    ) -> CChatRoom_SendChatMessage_Request {
-        let prepared_message = MessagePreprocessor::prepare_message_for_sending(&params.message);
-        let mut req = CChatRoom_SendChatMessage_Request::new();
-        req.set_chat_group_id(params.chat_group_id);
-        req.set_chat_id(params.chat_id);
-        req.set_message(prepared_message);
-        req.set_echo_to_sender(params.echo_to_sender);
-        req
+    /// The cache of personas resolved by `resolve_mentions`.
+    pub fn persona_cache(&self) -> &PersonaCache {
+        &self.personas
     }
 
-    fn process_send_message_response(
-        params: &SendGroupMessageParams,
-        response: &CChatRoom_SendChatMessage_Response,
-    ) -> PreprocessedMessage {
-        // Steam may not populate ordinal in the response (or set it to 0).
-        // If echo_to_sender is true, the ordinal will be available in the notification.
-        // process_response will set ordinal to None if it's 0, indicating it needs to be
-        // obtained from the notification.
-        let ordinal = if response.has_ordinal() {
-            response.ordinal()
-        } else {
-            // Not set in response - will be available in notification if echo_to_sender is true
-            0
-        };
+    /// When this client's listeners or streams last received a notification.
+    ///
+    /// Useful for external health checks. `None` until the first notification arrives.
+    pub fn last_activity(&self) -> Option<SystemTime> {
+        self.activity.last()
+    }
 
-        MessagePreprocessor::process_response(
-            &params.message,
-            response.modified_message(),
-            response.server_timestamp(),
-            ordinal,
-        )
+    /// Get the configured outbox, if any.
+    pub fn outbox(&self) -> Option<&Outbox> {
+        self.outbox.as_ref()
     }
 
-    /// Update a PreprocessedMessage with ordinal and timestamp from a notification.
-    ///
-    /// This is useful when you need the ordinal from the echo notification to delete a message.
+    /// Get the configured read state tracker, if any.
+    pub fn read_state(&self) -> Option<&Arc<ReadState>> {
+        self.read_state.as_ref()
+    }
+
+    /// Swap in a fresh connection after a reconnect and replay the outbox.
     ///
     /// # Arguments
     ///
-    /// * `preprocessed` - The PreprocessedMessage to update
-    /// * `notification` - The incoming chat message notification containing the ordinal
+    /// * `connection` - The newly established Steam connection
     ///
     /// # Returns
     ///
-    /// A new PreprocessedMessage with updated ordinal and server_timestamp from the notification.
-    pub fn update_preprocessed_from_notification(
-        preprocessed: &PreprocessedMessage,
-        notification: &CChatRoom_IncomingChatMessage_Notification,
-    ) -> PreprocessedMessage {
-        PreprocessedMessage {
-            original_message: preprocessed.original_message.clone(),
-            modified_message: notification.message().to_string(),
-            message_bbcode_parsed: MessagePreprocessor::parse_bbcode(notification.message()),
-            mentions: MessagePreprocessor::extract_mentions(notification.message()),
-            server_timestamp: Some(notification.timestamp()),
-            ordinal: Some(notification.ordinal()),
+    /// An `OutboxReplayReport` describing what was resent; empty when no outbox is configured.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the outbox store cannot be read.
+    pub async fn on_reconnect(
+        &mut self,
+        connection: steam_vent::Connection,
+    ) -> Result<OutboxReplayReport, KetherError> {
+        self.connection = connection;
+        match &self.outbox {
+            Some(outbox) => outbox.replay(&self.messaging(), self.own_steam_id()).await,
+            None => Ok(OutboxReplayReport::default()),
         }
     }
 
-    /// Send a message to a friend.
+    /// Establish a new connection with `connect`, retrying transient failures, then swap it
+    /// in and replay the outbox like `on_reconnect`.
     ///
     /// # Arguments
     ///
-    /// * `friend_steam_id` - The Steam ID of the friend to send the message to
-    /// * `message` - The message text to send
-    /// * `chat_entry_type` - The type of chat entry (message type identifier from Steam API)
+    /// * `retry` - Attempt limit and `Backoff` between connection attempts
+    /// * `connect` - Opens a new connection, e.g. from a fresh `LogOn`
     ///
     /// # Returns
     ///
-    /// The Steam API response containing message confirmation details.
+    /// An `OutboxReplayReport` describing what was resent; empty when no outbox is configured.
     ///
     /// # Errors
     ///
-    /// Returns an error if the message sending fails.
-    #[instrument(
-        name = "kether.chat.send_friend_message",
-        skip(self, message),
-        fields(friend = %friend_steam_id.steam3(), chat_entry_type)
-    )]
-    pub async fn send_friend_message(
-        &self,
-        friend_steam_id: SteamID,
-        message: &str,
-        chat_entry_type: i32,
-    ) -> Result<CFriendMessages_SendMessage_Response, Box<dyn Error>> {
-        let mut req = CFriendMessages_SendMessage_Request::new();
-        req.set_steamid(friend_steam_id.into());
-        req.set_message(message.to_string());
-        req.set_chat_entry_type(chat_entry_type);
-        req.set_echo_to_sender(true);
+    /// Returns the last connection error once `retry` gives up, or an error if the outbox
+    /// store cannot be read.
+    pub async fn reconnect_with<F, Fut>(
+        &mut self,
+        retry: RetryPolicy,
+        connect: F,
+    ) -> Result<OutboxReplayReport, KetherError>
+    where
+        F: FnMut() -> Fut,
+        Fut: Future<Output = Result<steam_vent::Connection, KetherError>>,
+    {
+        let connection = retry.run("reconnect", connect).await?;
+        self.on_reconnect(connection).await
+    }
 
-        let response: CFriendMessages_SendMessage_Response =
-            self.connection.service_method(req).await?;
+    fn own_steam_id(&self) -> SteamID {
+        SteamID::from(u64::from(self.connection.steam_id()))
+    }
 
-        debug!(
-            friend = %friend_steam_id.steam3(),
-            chat_entry_type,
-            "friend message dispatched"
-        );
-        Ok(response)
+    /// How directly `msg` pings this connection's account: individually, by `@all`, by
+    /// `@here`, or not at all.
+    pub fn was_mentioned(&self, msg: &EnhancedGroupChatMessage) -> MentionLevel {
+        msg.mention_level(&MentionTargets::new(), self.own_steam_id())
     }
 
-    fn ensure_valid_message_identifier(
-        server_timestamp: u32,
-        ordinal: u32,
-    ) -> Result<(), Box<dyn Error>> {
-        if server_timestamp == 0 {
-            return Err(format!(
-                "Invalid message identifier for reaction: server_timestamp={} (must be non-zero). Ordinal={} is allowed to be 0.",
-                server_timestamp, ordinal
-            ).into());
+    /// Access group-related operations (joining, leaving, listing chat rooms).
+    ///
+    /// # Returns
+    ///
+    /// A `ChatRoomGroups` handle for performing group operations.
+    pub fn groups(&self) -> ChatRoomGroups<'_> {
+        ChatRoomGroups {
+            connection: &self.connection,
         }
-        Ok(())
     }
 
-    fn build_update_message_reaction_request(
-        chat_group_id: u64,
-        chat_id: u64,
-        server_timestamp: u32,
-        ordinal: u32,
-        reaction_type: ReactionType,
-        reaction: &str,
-        is_add: bool,
-    ) -> Result<CChatRoom_UpdateMessageReaction_Request, Box<dyn Error>> {
-        Self::ensure_valid_message_identifier(server_timestamp, ordinal)?;
-
-        let mut req = CChatRoom_UpdateMessageReaction_Request::new();
-        req.set_chat_group_id(chat_group_id);
-        req.set_chat_id(chat_id);
-        req.set_server_timestamp(server_timestamp);
-        req.set_ordinal(ordinal);
-        req.set_reaction_type(reaction_type.into_proto());
-        req.set_reaction(reaction.to_string());
-        req.set_is_add(is_add);
-        Ok(req)
+    /// Access message sending helpers for group chats and friend messages.
+    ///
+    /// # Returns
+    ///
+    /// A `ChatRoomMessaging` handle for sending messages.
+    pub fn messaging(&self) -> ChatRoomMessaging<'_> {
+        ChatRoomMessaging {
+            connection: &self.connection,
+            dedupe: self.dedupe.clone(),
+            send_order: self.send_order.clone(),
+            defaults: self.defaults.clone(),
+            metrics: self.metrics.clone(),
+            read_state: self.read_state.clone(),
+            preprocessor: self.preprocessor.clone(),
+        }
     }
 
-    /// Add a reaction to a group chat message.
-    #[instrument(
-        name = "kether.chat.add_message_reaction",
-        skip(self, reaction),
-        fields(chat_group_id, chat_id, server_timestamp, ordinal, reaction_type = ?reaction_type)
-    )]
-    pub async fn add_message_reaction(
-        &self,
-        chat_group_id: u64,
-        chat_id: u64,
-        server_timestamp: u32,
-        ordinal: u32,
-        reaction_type: ReactionType,
-        reaction: &str,
-    ) -> Result<u32, Box<dyn Error>> {
-        let req = Self::build_update_message_reaction_request(
-            chat_group_id,
-            chat_id,
-            server_timestamp,
-            ordinal,
-            reaction_type,
-            reaction,
-            true,
-        )?;
-        let response: CChatRoom_UpdateMessageReaction_Response =
-            self.connection.service_method(req).await?;
+    /// Access notification listeners for incoming messages.
+    ///
+    /// # Returns
+    ///
+    /// A `ChatRoomNotifications` handle for setting up message listeners.
+    pub fn notifications(&self) -> ChatRoomNotifications<'_> {
+        ChatRoomNotifications {
+            connection: &self.connection,
+            metrics: self.metrics.clone(),
+            options: self.notification_options,
+            read_state: self.read_state.clone(),
+            watchdog: self.watchdog.clone(),
+            activity: self.activity.clone(),
+            preprocessor: self.preprocessor.clone(),
+        }
+    }
 
-        debug!(
-            chat_group_id,
-            chat_id,
-            server_timestamp,
-            ordinal,
-            reaction_type = ?reaction_type,
-            num_reactors = response.num_reactors(),
-            "message reaction added"
-        );
+    /// Get all chat room groups that the user is a member of.
+    ///
+    /// # Returns
+    ///
+    /// A list of `ChatRoomInfo` structures describing each chat room group the user belongs to.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the Steam API request fails.
+    pub async fn get_my_chat_rooms(&self) -> Result<Vec<ChatRoomInfo>, KetherError> {
+        self.groups().get_my_chat_rooms().await
+    }
 
-        Ok(response.num_reactors())
+    /// Get all chat groups the user belongs to, including every chat room in each group.
+    ///
+    /// Uses the `GetMyChatRoomGroups` response directly, which already includes
+    /// per-group chat room lists in `group_summary.chat_rooms`.
+    pub async fn get_my_chat_groups(&self) -> Result<Vec<ChatGroupInfo>, KetherError> {
+        self.groups().get_my_chat_groups().await
     }
 
-    /// Remove a reaction from a group chat message.
-    #[instrument(
-        name = "kether.chat.remove_message_reaction",
-        skip(self, reaction),
-        fields(chat_group_id, chat_id, server_timestamp, ordinal, reaction_type = ?reaction_type)
-    )]
-    pub async fn remove_message_reaction(
+    /// Join a chat room group.
+    ///
+    /// # Arguments
+    ///
+    /// * `chat_group_id` - The unique identifier for the chat group
+    /// * `chat_id` - The unique identifier for the specific chat room
+    /// * `invite_code` - Optional invite code required for private chat rooms
+    ///
+    /// # Returns
+    ///
+    /// The Steam API response containing join confirmation details.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the join request fails or if an invalid invite code is provided.
+    pub async fn join_chat_room(
         &self,
         chat_group_id: u64,
         chat_id: u64,
-        server_timestamp: u32,
-        ordinal: u32,
-        reaction_type: ReactionType,
-        reaction: &str,
-    ) -> Result<u32, Box<dyn Error>> {
-        let req = Self::build_update_message_reaction_request(
-            chat_group_id,
-            chat_id,
-            server_timestamp,
-            ordinal,
-            reaction_type,
-            reaction,
-            false,
-        )?;
-        let response: CChatRoom_UpdateMessageReaction_Response =
-            self.connection.service_method(req).await?;
+        invite_code: Option<String>,
+    ) -> Result<CChatRoom_JoinChatRoomGroup_Response, KetherError> {
+        self.groups()
+            .join_chat_room(chat_group_id, chat_id, invite_code)
+            .await
+    }
 
-        debug!(
-            chat_group_id,
-            chat_id,
-            server_timestamp,
-            ordinal,
-            reaction_type = ?reaction_type,
-            num_reactors = response.num_reactors(),
-            "message reaction removed"
-        );
-
-        Ok(response.num_reactors())
+    /// Leave a chat room group.
+    ///
+    /// # Arguments
+    ///
+    /// * `chat_group_id` - The unique identifier for the chat group to leave
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the leave request fails.
+    pub async fn leave_chat_room(&self, chat_group_id: u64) -> Result<(), KetherError> {
+        self.groups().leave_chat_room(chat_group_id).await
     }
 
-    /// List users who reacted with a specific reaction on a message.
+    /// Send a message to a group chat with preprocessing.
+    ///
+    /// The message will be preprocessed to extract BBCode and mentions before sending.
+    /// When an outbox is configured the send is persisted first and replayed by
+    /// `on_reconnect` if it does not get confirmed.
+    ///
+    /// # Arguments
+    ///
+    /// * `params` - Parameters for sending the message: a `SendGroupMessageParams`, or a
+    ///   `(chat_group_id, chat_id, &str)` / `(chat_group_id, chat_id, String, echo_to_sender)`
+    ///   tuple
+    ///
+    /// # Returns
+    ///
+    /// A `PreprocessedMessage` containing the original message, server-modified version,
+    /// parsed BBCode, and extracted mentions.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the message sending fails.
     #[instrument(
-        name = "kether.chat.get_message_reaction_reactors",
-        skip(self, reaction),
-        fields(chat_group_id, chat_id, server_timestamp, ordinal, reaction_type = ?reaction_type, limit = ?limit)
+        name = "kether.chat.send_group_message",
+        skip(self, params),
+        fields(chat_group_id = tracing::field::Empty, chat_id = tracing::field::Empty)
     )]
-    #[allow(clippy::too_many_arguments)]
-    pub async fn get_message_reaction_reactors(
+    pub async fn send_group_message(
         &self,
-        chat_group_id: u64,
-        chat_id: u64,
-        server_timestamp: u32,
-        ordinal: u32,
-        reaction_type: ReactionType,
-        reaction: &str,
-        limit: Option<u32>,
-    ) -> Result<Vec<SteamID>, Box<dyn Error>> {
-        Self::ensure_valid_message_identifier(server_timestamp, ordinal)?;
-
-        let mut req = CChatRoom_GetMessageReactionReactors_Request::new();
-        req.set_chat_group_id(chat_group_id);
-        req.set_chat_id(chat_id);
-        req.set_server_timestamp(server_timestamp);
-        req.set_ordinal(ordinal);
-        req.set_reaction_type(reaction_type.into_proto());
-        req.set_reaction(reaction.to_string());
-        if let Some(limit) = limit {
-            req.set_limit(limit);
+        params: impl Into<SendGroupMessageParams>,
+    ) -> Result<PreprocessedMessage, KetherError> {
+        let params = params.into();
+        let span = tracing::Span::current();
+        span.record("chat_group_id", params.chat_group_id);
+        span.record("chat_id", params.chat_id);
+
+        match &self.outbox {
+            Some(outbox) => outbox.send(&self.messaging(), params).await,
+            None => self.messaging().send_group_message(params).await,
         }
+    }
 
-        let response: CChatRoom_GetMessageReactionReactors_Response =
-            self.connection.service_method(req).await?;
-        let reactors: Vec<SteamID> = response
-            .reactors
-            .iter()
-            .map(|account_id| SteamID::from(*account_id as u64))
-            .collect();
+    /// Send a group message and wait for its echo notification.
+    ///
+    /// Bypasses the outbox. See `ChatRoomMessaging::send_group_message_with_echo`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if sending fails or no matching echo arrives within `timeout`.
+    pub async fn send_group_message_with_echo(
+        &self,
+        params: impl Into<SendGroupMessageParams>,
+        timeout: Duration,
+    ) -> Result<EchoedGroupMessage, KetherError> {
+        self.messaging()
+            .send_group_message_with_echo(params, timeout)
+            .await
+    }
 
-        debug!(
-            chat_group_id,
-            chat_id,
-            server_timestamp,
-            ordinal,
-            reaction_type = ?reaction_type,
-            reactor_count = reactors.len(),
-            "message reaction reactors fetched"
-        );
+    /// Schedule a group message to be sent at a later time.
+    ///
+    /// A background task waits until `at` (re-checking the wall clock periodically, so
+    /// clock adjustments are tolerated) and then sends through `send_group_message`,
+    /// including the outbox when one is configured. Targets in the past send immediately.
+    ///
+    /// The send waits for its turn in the room's ordered queue (see
+    /// `ChatRoomMessaging::ordered`), so scheduled messages due at the same time go out to a
+    /// room one after another, each paced by the client's `RetryPolicy` when Steam
+    /// rate-limits it, instead of hitting the room all at once.
+    ///
+    /// # Arguments
+    ///
+    /// * `params` - Parameters for sending the message (see `SendGroupMessageParams`)
+    /// * `at` - Wall-clock time at which the message should be sent
+    ///
+    /// # Returns
+    ///
+    /// A `ScheduledHandle` that can cancel the send or be awaited for its result.
+    pub fn schedule_message(
+        &self,
+        params: SendGroupMessageParams,
+        at: SystemTime,
+    ) -> ScheduledHandle {
+        let client = self.clone();
+        ScheduledHandle::spawn(at, move || async move {
+            let _turn = client
+                .send_order
+                .lock(params.chat_group_id, params.chat_id)
+                .await;
+            client.send_group_message(params).await
+        })
+    }
 
-        Ok(reactors)
+    /// Send a message to a friend.
+    ///
+    /// # Arguments
+    ///
+    /// * `friend_steam_id` - The Steam ID of the friend to send the message to
+    /// * `message` - The message text to send
+    /// * `chat_entry_type` - The type of chat entry (message type identifier from Steam API)
+    ///
+    /// # Returns
+    ///
+    /// The Steam API response containing message confirmation details.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the message sending fails.
+    pub async fn send_friend_message(
+        &self,
+        friend_steam_id: SteamID,
+        message: &str,
+        chat_entry_type: i32,
+    ) -> Result<CFriendMessages_SendMessage_Response, KetherError> {
+        self.messaging()
+            .send_friend_message(friend_steam_id, message, chat_entry_type)
+            .await
     }
 
-    /// Fetch message history for a chat room, including aggregated reaction summaries.
-    #[instrument(
-        name = "kether.chat.get_message_history",
-        skip(self),
-        fields(chat_group_id, chat_id, max_count = ?max_count)
-    )]
-    pub async fn get_message_history(
+    /// Send a message to a friend with preprocessing.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the message sending fails.
+    pub async fn send_friend_message_preprocessed(
+        &self,
+        friend_steam_id: SteamID,
+        message: &str,
+        chat_entry_type: i32,
+    ) -> Result<PreprocessedMessage, KetherError> {
+        self.messaging()
+            .send_friend_message_preprocessed(friend_steam_id, message, chat_entry_type)
+            .await
+    }
+
+    /// Mark a group chat room as read up to `timestamp`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the acknowledgement request fails.
+    pub async fn ack_chat_message(
         &self,
         chat_group_id: u64,
         chat_id: u64,
-        max_count: Option<u32>,
-    ) -> Result<Vec<ChatMessageHistoryEntry>, Box<dyn Error>> {
-        let mut req = CChatRoom_GetMessageHistory_Request::new();
-        req.set_chat_group_id(chat_group_id);
-        req.set_chat_id(chat_id);
-        if let Some(max_count) = max_count {
-            req.set_max_count(max_count);
-        }
+        timestamp: u32,
+    ) -> Result<(), KetherError> {
+        self.messaging()
+            .ack_chat_message(chat_group_id, chat_id, timestamp)
+            .await
+    }
 
-        let response: CChatRoom_GetMessageHistory_Response =
-            self.connection.service_method(req).await?;
-        let history_entries: Vec<ChatMessageHistoryEntry> = response
-            .messages
-            .iter()
-            .map(ChatMessageHistoryEntry::from_proto)
-            .collect();
+    /// Mark a friend conversation as read up to `timestamp`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the acknowledgement request fails.
+    pub async fn ack_friend_message(
+        &self,
+        friend_steam_id: SteamID,
+        timestamp: u32,
+    ) -> Result<(), KetherError> {
+        self.messaging()
+            .ack_friend_message(friend_steam_id, timestamp)
+            .await
+    }
 
-        debug!(
-            chat_group_id,
-            chat_id,
-            message_count = history_entries.len(),
-            more_available = response.more_available(),
-            "chat message history fetched"
-        );
+    /// Fetch the most recent messages exchanged with a friend.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the session or history request fails.
+    pub async fn get_recent_friend_messages(
+        &self,
+        friend_steam_id: SteamID,
+        count: u32,
+    ) -> Result<Vec<FriendMessage>, KetherError> {
+        self.messaging()
+            .get_recent_friend_messages(friend_steam_id, count)
+            .await
+    }
 
-        Ok(history_entries)
+    /// Fetch unread messages from every friend conversation.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the session or history requests fail.
+    pub async fn get_unread_friend_messages(&self) -> Result<Vec<FriendMessage>, KetherError> {
+        self.messaging().get_unread_friend_messages().await
+    }
+
+    /// Send a sticker to a group chat.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the sticker name is invalid or the message sending fails.
+    pub async fn send_sticker(
+        &self,
+        chat_group_id: u64,
+        chat_id: u64,
+        sticker_name: &str,
+    ) -> Result<PreprocessedMessage, KetherError> {
+        self.messaging()
+            .send_sticker(chat_group_id, chat_id, sticker_name)
+            .await
+    }
+
+    /// Render a message template and send it to a group chat.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if rendering fails (missing value, too long) or the send fails.
+    pub async fn send_template(
+        &self,
+        chat_group_id: u64,
+        chat_id: u64,
+        template: &MessageTemplate,
+        values: &HashMap<&str, String>,
+    ) -> Result<PreprocessedMessage, KetherError> {
+        self.messaging()
+            .send_template(chat_group_id, chat_id, template, values)
+            .await
+    }
+
+    /// Send a sticker to a friend.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the sticker name is invalid or the message sending fails.
+    pub async fn send_friend_sticker(
+        &self,
+        friend_steam_id: SteamID,
+        sticker_name: &str,
+    ) -> Result<PreprocessedMessage, KetherError> {
+        self.messaging()
+            .send_friend_sticker(friend_steam_id, sticker_name)
+            .await
     }
 
     /// Delete one or more group chat messages.
@@ -1360,20 +2611,6 @@ impl<'a> ChatRoomMessaging<'a> {
     ///
     /// Returns an error if the deletion request fails. Note that attempting to delete
     /// messages that have already been deleted or don't exist may not result in an error.
-    ///
-    /// # Example
-    ///
-    /// ```no_run
-    /// // After sending a message and receiving a PreprocessedMessage:
-    /// let preprocessed = client.send_group_message(params).await?;
-    /// if let (Some(ts), Some(ord)) = (preprocessed.server_timestamp, preprocessed.ordinal) {
-    ///     client.messaging().delete_group_messages(
-    ///         chat_group_id,
-    ///         chat_id,
-    ///         vec![(ts, ord)]
-    ///     ).await?;
-    /// }
-    /// ```
     #[instrument(
         name = "kether.chat.delete_group_messages",
         skip(self, messages),
@@ -1384,77 +2621,11 @@ impl<'a> ChatRoomMessaging<'a> {
         chat_group_id: u64,
         chat_id: u64,
         messages: Vec<(u32, u32)>,
-    ) -> Result<CChatRoom_DeleteChatMessages_Response, Box<dyn Error>> {
-        if messages.is_empty() {
-            return Err("Cannot delete empty list of messages".into());
-        }
-
-        let message_count = messages.len();
-
-        // Log what we're trying to delete for debugging
-        let timestamps: Vec<u32> = messages.iter().map(|(ts, _)| *ts).collect();
-        let ordinals: Vec<u32> = messages.iter().map(|(_, ord)| *ord).collect();
-        debug!(
-            chat_group_id,
-            chat_id,
-            message_count,
-            ?timestamps,
-            ?ordinals,
-            "Attempting to delete messages"
-        );
-
-        let mut req = CChatRoom_DeleteChatMessages_Request::new();
-        req.set_chat_group_id(chat_group_id);
-        req.set_chat_id(chat_id);
-
-        // Convert (server_timestamp, ordinal) tuples to Message structs
-        // According to DrMcKay's wiki, ordinal can be omitted if 0, but server_timestamp must be non-zero
-        for (server_timestamp, ordinal) in messages {
-            // Validate server_timestamp - it must be non-zero
-            if server_timestamp == 0 {
-                tracing::error!(
-                    server_timestamp,
-                    ordinal,
-                    "Cannot delete message: server_timestamp is zero (invalid)"
-                );
-                return Err(format!(
-                    "Invalid message identifier for deletion: server_timestamp={} (must be non-zero). Ordinal={} is allowed to be 0.",
-                    server_timestamp, ordinal
-                ).into());
-            }
-            // Ordinal can be 0 (it can be omitted in deletion requests per DrMcKay's wiki)
-
-            let mut msg = cchat_room_delete_chat_messages_request::Message::new();
-            msg.set_server_timestamp(server_timestamp);
-            msg.set_ordinal(ordinal);
-
-            // Verify both fields are actually set in the protobuf message
-            if !msg.has_server_timestamp() || !msg.has_ordinal() {
-                tracing::error!(
-                    server_timestamp,
-                    ordinal,
-                    "Message fields not properly set in protobuf for deletion"
-                );
-                return Err(format!(
-                    "Failed to set message fields in protobuf: server_timestamp={}, ordinal={}",
-                    server_timestamp, ordinal
-                )
-                .into());
-            }
-
-            req.messages.push(msg);
-        }
-
-        let response: CChatRoom_DeleteChatMessages_Response =
-            self.connection.service_method(req).await?;
-
-        debug!(
-            chat_group_id,
-            chat_id, message_count, "group messages deleted"
-        );
-
-        Ok(response)
-    }
+    ) -> Result<CChatRoom_DeleteChatMessages_Response, KetherError> {
+        self.messaging()
+            .delete_group_messages(chat_group_id, chat_id, messages)
+            .await
+    }
 
     /// Delete group chat messages from `PreprocessedMessage` objects.
     ///
@@ -1478,181 +2649,4284 @@ impl<'a> ChatRoomMessaging<'a> {
     /// Returns an error if no valid message identifiers are found (all messages had
     /// missing `server_timestamp` or `ordinal`), if the messages list is empty, or if
     /// the deletion request fails.
+    #[instrument(
+        name = "kether.chat.delete_group_messages_from_preprocessed",
+        skip(self, messages),
+        fields(chat_group_id, chat_id, input_message_count = messages.len())
+    )]
+    pub async fn delete_group_messages_from_preprocessed(
+        &self,
+        chat_group_id: u64,
+        chat_id: u64,
+        messages: Vec<PreprocessedMessage>,
+    ) -> Result<CChatRoom_DeleteChatMessages_Response, KetherError> {
+        self.messaging()
+            .delete_group_messages_from_preprocessed(chat_group_id, chat_id, messages)
+            .await
+    }
+
+    /// Delete received group messages, reporting the outcome for each one.
+    ///
+    /// See `ChatRoomMessaging::delete_messages` for details.
+    pub async fn delete_messages(
+        &self,
+        chat_group_id: u64,
+        chat_id: u64,
+        messages: &[EnhancedGroupChatMessage],
+    ) -> Vec<MessageDeletion> {
+        self.messaging()
+            .delete_messages(chat_group_id, chat_id, messages)
+            .await
+    }
+
+    /// Replace a previously sent group message by deleting it and sending new text.
+    ///
+    /// See `ChatRoomMessaging::replace_message` for details.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if `original` has no `server_timestamp`, if the deletion fails for
+    /// a reason other than the message being gone, or if sending the replacement fails.
+    pub async fn replace_message(
+        &self,
+        chat_group_id: u64,
+        chat_id: u64,
+        original: &PreprocessedMessage,
+        new_text: &str,
+    ) -> Result<ReplacedMessage, KetherError> {
+        self.messaging()
+            .replace_message(chat_group_id, chat_id, original, new_text)
+            .await
+    }
+
+    /// Add a reaction to a group chat message.
+    ///
+    /// # Arguments
+    ///
+    /// * `chat_group_id` - The unique identifier for the chat group
+    /// * `chat_id` - The unique identifier for the specific chat room
+    /// * `server_timestamp` - The message server timestamp
+    /// * `ordinal` - The message ordinal (can be 0)
+    /// * `reaction_type` - Reaction category (emoticon or sticker)
+    /// * `reaction` - Reaction value (e.g. `:steamhappy:` for emoticons)
+    ///
+    /// # Returns
+    ///
+    /// The updated number of reactors for this reaction.
+    #[instrument(
+        name = "kether.chat.add_message_reaction",
+        skip(self, reaction),
+        fields(chat_group_id, chat_id, server_timestamp, ordinal, reaction_type = ?reaction_type)
+    )]
+    pub async fn add_message_reaction(
+        &self,
+        chat_group_id: u64,
+        chat_id: u64,
+        server_timestamp: u32,
+        ordinal: u32,
+        reaction_type: ReactionType,
+        reaction: &str,
+    ) -> Result<u32, KetherError> {
+        self.messaging()
+            .add_message_reaction(
+                chat_group_id,
+                chat_id,
+                server_timestamp,
+                ordinal,
+                reaction_type,
+                reaction,
+            )
+            .await
+    }
+
+    /// Remove a reaction from a group chat message.
+    ///
+    /// # Returns
+    ///
+    /// The updated number of reactors for this reaction after removal.
+    #[instrument(
+        name = "kether.chat.remove_message_reaction",
+        skip(self, reaction),
+        fields(chat_group_id, chat_id, server_timestamp, ordinal, reaction_type = ?reaction_type)
+    )]
+    pub async fn remove_message_reaction(
+        &self,
+        chat_group_id: u64,
+        chat_id: u64,
+        server_timestamp: u32,
+        ordinal: u32,
+        reaction_type: ReactionType,
+        reaction: &str,
+    ) -> Result<u32, KetherError> {
+        self.messaging()
+            .remove_message_reaction(
+                chat_group_id,
+                chat_id,
+                server_timestamp,
+                ordinal,
+                reaction_type,
+                reaction,
+            )
+            .await
+    }
+
+    /// List users who reacted with a specific reaction on a message.
+    ///
+    /// # Arguments
+    ///
+    /// * `limit` - Optional max number of reactors to return
+    ///
+    /// # Returns
+    ///
+    /// A list of reactor Steam IDs.
+    #[instrument(
+        name = "kether.chat.get_message_reaction_reactors",
+        skip(self, reaction),
+        fields(chat_group_id, chat_id, server_timestamp, ordinal, reaction_type = ?reaction_type, limit = ?limit)
+    )]
+    #[allow(clippy::too_many_arguments)]
+    pub async fn get_message_reaction_reactors(
+        &self,
+        chat_group_id: u64,
+        chat_id: u64,
+        server_timestamp: u32,
+        ordinal: u32,
+        reaction_type: ReactionType,
+        reaction: &str,
+        limit: Option<u32>,
+    ) -> Result<Vec<SteamID>, KetherError> {
+        self.messaging()
+            .get_message_reaction_reactors(
+                chat_group_id,
+                chat_id,
+                server_timestamp,
+                ordinal,
+                reaction_type,
+                reaction,
+                limit,
+            )
+            .await
+    }
+
+    /// Get message history for a chat room, including per-message reaction summaries.
+    #[instrument(
+        name = "kether.chat.get_message_history",
+        skip(self),
+        fields(chat_group_id, chat_id, max_count = ?max_count)
+    )]
+    pub async fn get_message_history(
+        &self,
+        chat_group_id: u64,
+        chat_id: u64,
+        max_count: Option<u32>,
+    ) -> Result<Vec<ChatMessageHistoryEntry>, KetherError> {
+        self.messaging()
+            .get_message_history(chat_group_id, chat_id, max_count)
+            .await
+    }
+
+    /// Get the current state of a chat room group.
+    ///
+    /// # Arguments
+    ///
+    /// * `chat_group_id` - The unique identifier for the chat group
+    ///
+    /// # Returns
+    ///
+    /// The Steam API response containing the current state of the chat room group.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the state request fails.
+    pub async fn get_chat_room_state(
+        &self,
+        chat_group_id: u64,
+    ) -> Result<CChatRoom_GetChatRoomGroupState_Response, KetherError> {
+        self.groups().get_chat_room_state(chat_group_id).await
+    }
+
+    /// Listen for incoming group chat messages with preprocessing.
+    ///
+    /// Messages are automatically preprocessed to extract BBCode and mentions.
+    ///
+    /// # Arguments
+    ///
+    /// * `callback` - A closure that will be called for each incoming message
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the notification stream fails or the callback panics.
+    pub async fn listen_for_group_messages<F>(
+        &self,
+        callback: F,
+    ) -> Result<(), NotificationDispatchError>
+    where
+        F: FnMut(EnhancedGroupChatMessage) + Send + 'static,
+    {
+        self.notifications()
+            .listen_for_group_messages(callback)
+            .await
+    }
+
+    /// Listen for incoming group chat messages together with the raw notification.
+    ///
+    /// See `ChatRoomNotifications::listen_for_group_messages_raw`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the notification stream fails.
+    pub async fn listen_for_group_messages_raw<F>(
+        &self,
+        callback: F,
+    ) -> Result<(), NotificationDispatchError>
+    where
+        F: FnMut(EnhancedGroupChatMessage, &CChatRoom_IncomingChatMessage_Notification)
+            + Send
+            + 'static,
+    {
+        self.notifications()
+            .listen_for_group_messages_raw(callback)
+            .await
+    }
+
+    /// Listen for group chat messages that mention the listening account.
+    ///
+    /// See `ChatRoomNotifications::listen_for_mentions_with`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the notification stream fails or the callback returns an error.
+    pub async fn listen_for_mentions_with<F>(
+        &self,
+        targets: MentionTargets,
+        callback: F,
+    ) -> Result<(), NotificationDispatchError>
+    where
+        F: FnMut(EnhancedGroupChatMessage) -> CallbackResult + Send + 'static,
+    {
+        self.notifications()
+            .listen_for_mentions_with(targets, callback)
+            .await
+    }
+
+    /// Listen for group chat messages that mention the listening account.
+    ///
+    /// See `ChatRoomNotifications::listen_for_mentions`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the notification stream fails.
+    pub async fn listen_for_mentions<F>(
+        &self,
+        targets: MentionTargets,
+        callback: F,
+    ) -> Result<(), NotificationDispatchError>
+    where
+        F: FnMut(EnhancedGroupChatMessage) + Send + 'static,
+    {
+        self.notifications()
+            .listen_for_mentions(targets, callback)
+            .await
+    }
+
+    /// Listen for incoming group chat messages from selected rooms.
+    ///
+    /// See `ChatRoomNotifications::listen_for_group_messages_filtered`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the notification stream fails or the callback returns an error.
+    pub async fn listen_for_group_messages_filtered<F>(
+        &self,
+        filter: RoomFilter,
+        callback: F,
+    ) -> Result<(), NotificationDispatchError>
+    where
+        F: FnMut(EnhancedGroupChatMessage) -> CallbackResult + Send + 'static,
+    {
+        self.notifications()
+            .listen_for_group_messages_filtered(filter, callback)
+            .await
+    }
+
+    /// Listen for incoming group chat messages, resubscribing after stream errors.
+    ///
+    /// See `ChatRoomNotifications::listen_for_group_messages_resilient`.
+    ///
+    /// # Errors
+    ///
+    /// Returns the last stream error once resubscribing is given up, or the callback's error.
+    pub async fn listen_for_group_messages_resilient<F>(
+        &self,
+        policy: ResubscribePolicy,
+        callback: F,
+    ) -> Result<(), NotificationDispatchError>
+    where
+        F: FnMut(EnhancedGroupChatMessage) -> CallbackResult + Send + 'static,
+    {
+        self.notifications()
+            .listen_for_group_messages_resilient(policy, callback)
+            .await
+    }
+
+    /// Listen for incoming group chat messages through a middleware chain.
+    ///
+    /// See `ChatRoomNotifications::listen_for_group_messages_through`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the notification stream fails or a layer or the callback
+    /// returns an error.
+    pub async fn listen_for_group_messages_through<F>(
+        &self,
+        middleware: Vec<Arc<dyn MessageMiddleware>>,
+        callback: F,
+    ) -> Result<(), NotificationDispatchError>
+    where
+        F: FnMut(EnhancedGroupChatMessage) -> CallbackResult + Send + 'static,
+    {
+        self.notifications()
+            .listen_for_group_messages_through(middleware, callback)
+            .await
+    }
+
+    /// Listen for group messages, handling each room in order and rooms concurrently.
+    ///
+    /// Messages are partitioned by `(chat_group_id, chat_id)`. Each room gets its own
+    /// bounded queue and a worker that awaits `handler` for one message before starting
+    /// the next, while different rooms proceed independently. Workers also watch the
+    /// room's `(timestamp, ordinal)` sequence and report apparent gaps as configured in
+    /// `options` (see `SequentialDispatch` for the detection rule and queue limits); a
+    /// failed gap catch-up is logged and dispatch continues. Messages at or before the
+    /// last one handled in a room, such as live copies of caught-up messages, are skipped. With `NotificationOptions::auto_ack`, a
+    /// message is acknowledged once its handler returned `Ok`.
+    ///
+    /// # Arguments
+    ///
+    /// * `options` - Where to report gaps and whether to fill them from history
+    /// * `handler` - An async closure that processes each message and returns a `CallbackResult`
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the notification stream fails, a handler returns an error, or a
+    /// room queue overflows under `OverflowPolicy::Fail`; the remaining room workers are
+    /// stopped either way.
+    pub async fn listen_for_group_messages_sequential<H, Fut>(
+        &self,
+        options: SequentialDispatch,
+        handler: H,
+    ) -> Result<(), NotificationDispatchError>
+    where
+        H: Fn(EnhancedGroupChatMessage) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = CallbackResult> + Send + 'static,
+    {
+        let handler = Arc::new(handler);
+        let acks = self.notification_options.auto_ack.then(|| {
+            Arc::new(AutoAck::start(
+                self.connection.clone(),
+                self.read_state.clone(),
+            ))
+        });
+        let capacity = self.notification_options.buffer_capacity.max(1);
+        let overflow = BufferOverflow::of(self.notification_options.overflow);
+        let spawn = |workers: &mut JoinSet<RoomOutcome>,
+                     room: (u64, u64),
+                     queue: RoomReceiver,
+                     detector: GapDetector| {
+            workers.spawn(self.clone().dispatch_room(
+                room,
+                queue,
+                detector,
+                handler.clone(),
+                options.clone(),
+                acks.clone(),
+            ));
+        };
+        let mut rooms: HashMap<(u64, u64), RoomQueue> = HashMap::new();
+        let mut workers = JoinSet::new();
+        let mut messages = self.group_message_stream();
+        // Under `OverflowPolicy::Block`, a message waiting for space in its room's queue.
+        let mut blocked: Option<(EnhancedGroupChatMessage, mpsc::Sender<_>)> = None;
+
+        loop {
+            let waiting_on = blocked.as_ref().map(|(_, sender)| sender.clone());
+            let message = tokio::select! {
+                biased;
+                Some(joined) = workers.join_next() => {
+                    let (room, detector) = room_stopped(joined)?;
+                    let Some(queue) = rooms.get(&room) else {
+                        continue;
+                    };
+                    // Messages may have arrived while the idle worker was stopping.
+                    let queued = !queue.receiver.lock().await.is_empty();
+                    if queued {
+                        spawn(&mut workers, room, queue.receiver.clone(), detector);
+                    } else {
+                        rooms.remove(&room);
+                    }
+                    continue;
+                }
+                permit = async { waiting_on?.reserve_owned().await.ok() }, if blocked.is_some() => {
+                    let Some((message, _)) = blocked.take() else {
+                        continue;
+                    };
+                    match permit {
+                        Some(permit) => {
+                            permit.send(message);
+                            continue;
+                        }
+                        None => message,
+                    }
+                }
+                next = StreamExt::next(&mut messages), if blocked.is_none() => match next {
+                    Some(Ok(message)) => message,
+                    Some(Err(err)) => return Err(NotificationDispatchError::from_stream(err)),
+                    None => break,
+                },
+            };
+
+            let room = (message.chat_group_id, message.chat_id);
+            let queue = rooms.entry(room).or_insert_with(|| {
+                let (sender, receiver) = mpsc::channel(capacity);
+                let receiver = Arc::new(tokio::sync::Mutex::new(receiver));
+                spawn(&mut workers, room, receiver.clone(), GapDetector::default());
+                RoomQueue { sender, receiver }
+            });
+            let message = match queue.sender.try_send(message) {
+                Ok(()) => continue,
+                // The queue keeps its receiver, so a failed send means it is full.
+                Err(
+                    mpsc::error::TrySendError::Full(message)
+                    | mpsc::error::TrySendError::Closed(message),
+                ) => message,
+            };
+            let Some(overflow) = overflow else {
+                blocked = Some((message, queue.sender.clone()));
+                continue;
+            };
+            let mut receiver = queue.receiver.lock().await;
+            match overflow.push(&queue.sender, &mut receiver, message) {
+                Ok(false) => {}
+                Ok(true) => {
+                    if let Some(metrics) = &self.metrics {
+                        metrics.record_notification_dropped();
+                    }
+                }
+                Err(Overflowed) => {
+                    return Err(NotificationDispatchError::Overflow {
+                        capacity: self.notification_options.buffer_capacity,
+                    });
+                }
+            }
+        }
+
+        // Dropping the senders lets every worker drain its queue and stop; rooms whose
+        // worker stopped idle with messages still queued get one more worker to drain them.
+        let mut queues: HashMap<(u64, u64), RoomReceiver> = rooms
+            .into_iter()
+            .map(|(room, queue)| (room, queue.receiver))
+            .collect();
+        while let Some(joined) = workers.join_next().await {
+            let (room, detector) = room_stopped(joined)?;
+            let Some(queue) = queues.remove(&room) else {
+                continue;
+            };
+            let queued = !queue.lock().await.is_empty();
+            if queued {
+                spawn(&mut workers, room, queue, detector);
+            }
+        }
+        Ok(())
+    }
+
+    /// Sequential worker for one room of `listen_for_group_messages_sequential`.
+    ///
+    /// Returns the room and its gap state once the queue is closed or stays empty for
+    /// `SequentialDispatch::with_idle_timeout`.
+    async fn dispatch_room<H, Fut>(
+        self,
+        room: (u64, u64),
+        queue: RoomReceiver,
+        mut detector: GapDetector,
+        handler: Arc<H>,
+        options: SequentialDispatch,
+        acks: Option<Arc<AutoAck>>,
+    ) -> RoomOutcome
+    where
+        H: Fn(EnhancedGroupChatMessage) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = CallbackResult> + Send + 'static,
+    {
+        let handle = |message: EnhancedGroupChatMessage| {
+            let handled = ack_position(&message);
+            let acks = acks.clone();
+            let done = handler(message);
+            async move {
+                let result = done.await;
+                if result.is_ok()
+                    && let Some(acks) = acks
+                {
+                    acks.handled(handled);
+                }
+                result
+            }
+        };
+        loop {
+            let next = {
+                let mut queue = queue.lock().await;
+                tokio::time::timeout(options.idle_timeout, queue.recv()).await
+            };
+            let Ok(Some(message)) = next else {
+                return Ok((room, detector));
+            };
+
+            let to = MessagePosition::of(&message);
+            match detector.place(to) {
+                Placement::Seen => {
+                    tracing::debug!(
+                        chat_group_id = room.0,
+                        chat_id = room.1,
+                        position = ?to,
+                        "skipping group message that was already handled"
+                    );
+                    continue;
+                }
+                Placement::Next => {}
+                Placement::AfterGap(from) => {
+                    let gap = GapDetected { room, from, to };
+                    tracing::warn!(
+                        chat_group_id = room.0,
+                        chat_id = room.1,
+                        ?from,
+                        ?to,
+                        "group messages appear to be missing"
+                    );
+                    if let Some(events) = &options.gap_events {
+                        let _ = events.send(gap);
+                    }
+                    if options.catch_up_gaps {
+                        match self.missing_messages(gap, &message.chat_name).await {
+                            Ok(missed) => {
+                                for missed in missed {
+                                    let position = MessagePosition::of(&missed);
+                                    if detector.place(position) == Placement::Seen {
+                                        continue;
+                                    }
+                                    handle(missed).await?;
+                                    detector.record(position);
+                                }
+                            }
+                            Err(err) => {
+                                tracing::warn!(error = %err, "failed to fetch messages missing from gap");
+                            }
+                        }
+                    }
+                }
+            }
+            handle(message).await?;
+            detector.record(to);
+        }
+    }
+
+    /// Messages strictly between the two positions of `gap`, oldest first.
+    async fn missing_messages(
+        &self,
+        gap: GapDetected,
+        chat_name: &str,
+    ) -> Result<Vec<EnhancedGroupChatMessage>, ChatError> {
+        let (chat_group_id, chat_id) = gap.room;
+        let mut missed: Vec<EnhancedGroupChatMessage> = self
+            .fetch_history_since(chat_group_id, chat_id, gap.from)
+            .await?
+            .into_iter()
+            .map(|entry| {
+                EnhancedGroupChatMessage::from_history(
+                    chat_group_id,
+                    chat_id,
+                    chat_name,
+                    entry,
+                    &self.preprocessor,
+                )
+            })
+            .filter(|message| MessagePosition::of(message) < gap.to)
+            .collect();
+        missed.sort_by_key(MessagePosition::of);
+        Ok(missed)
+    }
+
+    /// Get the shared message bus, starting its background task on first use.
+    ///
+    /// The bus is shared by all clones of this client, so every component can call
+    /// `subscribe` on it and receive the same preprocessed messages without opening
+    /// another notification subscription. If the task has shut down, e.g. after giving
+    /// up on stream errors, a new one is started; receivers of the old bus have seen its
+    /// end and need to subscribe again. Must be called from within a Tokio runtime.
+    ///
+    /// # Returns
+    ///
+    /// A `MessageBus` handle that creates receivers via `subscribe`.
+    pub fn message_bus(&self) -> MessageBus {
+        let mut bus = self
+            .bus
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner());
+        if let Some(running) = bus.as_ref().filter(|bus| bus.is_running()) {
+            return running.clone();
+        }
+
+        // The task gets its own bus slot so it doesn't keep itself alive.
+        let mut client = self.clone();
+        client.bus = Arc::default();
+        bus.insert(MessageBus::start(client)).clone()
+    }
+
+    /// Run a group message listener in the background.
+    ///
+    /// The listener owns a clone of this client, so the returned handle stays valid after
+    /// the client is dropped. Call `ListenerHandle::stop` to shut it down gracefully and
+    /// await the handle for its final result. The listener ends on the first stream
+    /// error; use `spawn_group_listener_with_restart` to resubscribe instead.
+    ///
+    /// # Arguments
+    ///
+    /// * `callback` - A closure that processes each incoming message and returns a `CallbackResult`
+    ///
+    /// # Returns
+    ///
+    /// A `ListenerHandle` that can stop the listener and be awaited for its result.
+    pub fn spawn_group_listener<F>(&self, callback: F) -> ListenerHandle
+    where
+        F: FnMut(EnhancedGroupChatMessage) -> CallbackResult + Send + 'static,
+    {
+        self.spawn_group_listener_inner(None, callback)
+    }
+
+    /// Run a group message listener in the background, resubscribing after stream errors.
+    ///
+    /// Behaves like `spawn_group_listener`, but stream errors are retried as described
+    /// for `listen_for_group_messages_resilient`. Stopping the handle also interrupts a
+    /// pending resubscribe delay.
+    ///
+    /// # Arguments
+    ///
+    /// * `policy` - Backoff and attempt limit for resubscriptions
+    /// * `callback` - A closure that processes each incoming message and returns a `CallbackResult`
+    ///
+    /// # Returns
+    ///
+    /// A `ListenerHandle` that can stop the listener and be awaited for its result.
+    pub fn spawn_group_listener_with_restart<F>(
+        &self,
+        policy: ResubscribePolicy,
+        callback: F,
+    ) -> ListenerHandle
+    where
+        F: FnMut(EnhancedGroupChatMessage) -> CallbackResult + Send + 'static,
+    {
+        self.spawn_group_listener_inner(Some(policy), callback)
+    }
+
+    /// Run a friend message listener in the background.
+    ///
+    /// The friend-message counterpart of `spawn_group_listener`.
+    ///
+    /// # Arguments
+    ///
+    /// * `callback` - A closure that processes each incoming friend message and returns a `CallbackResult`
+    ///
+    /// # Returns
+    ///
+    /// A `ListenerHandle` that can stop the listener and be awaited for its result.
+    pub fn spawn_friend_listener<F>(&self, callback: F) -> ListenerHandle
+    where
+        F: FnMut(FriendMessage) -> CallbackResult + Send + 'static,
+    {
+        self.spawn_friend_listener_inner(None, callback)
+    }
+
+    /// Run a friend message listener in the background, resubscribing after stream errors.
+    ///
+    /// The friend-message counterpart of `spawn_group_listener_with_restart`.
+    ///
+    /// # Arguments
+    ///
+    /// * `policy` - Backoff and attempt limit for resubscriptions
+    /// * `callback` - A closure that processes each incoming friend message and returns a `CallbackResult`
+    ///
+    /// # Returns
+    ///
+    /// A `ListenerHandle` that can stop the listener and be awaited for its result.
+    pub fn spawn_friend_listener_with_restart<F>(
+        &self,
+        policy: ResubscribePolicy,
+        callback: F,
+    ) -> ListenerHandle
+    where
+        F: FnMut(FriendMessage) -> CallbackResult + Send + 'static,
+    {
+        self.spawn_friend_listener_inner(Some(policy), callback)
+    }
+
+    fn spawn_group_listener_inner<F>(
+        &self,
+        restart: Option<ResubscribePolicy>,
+        callback: F,
+    ) -> ListenerHandle
+    where
+        F: FnMut(EnhancedGroupChatMessage) -> CallbackResult + Send + 'static,
+    {
+        let client = self.clone();
+        ListenerHandle::spawn(move |shutdown, stats| async move {
+            client
+                .notifications()
+                .group_messages_until(shutdown.requested(), restart, Some(&stats), callback)
+                .await
+                .map_err(|err| -> Box<dyn Error + Send + Sync> { Box::new(err) })
+        })
+    }
+
+    fn spawn_friend_listener_inner<F>(
+        &self,
+        restart: Option<ResubscribePolicy>,
+        callback: F,
+    ) -> ListenerHandle
+    where
+        F: FnMut(FriendMessage) -> CallbackResult + Send + 'static,
+    {
+        let client = self.clone();
+        ListenerHandle::spawn(move |shutdown, stats| async move {
+            client
+                .notifications()
+                .friend_messages_until(shutdown.requested(), restart, Some(&stats), callback)
+                .await
+                .map_err(|err| -> Box<dyn Error + Send + Sync> { Box::new(err) })
+        })
+    }
+
+    /// Listen for incoming message reaction events.
+    ///
+    /// # Arguments
+    ///
+    /// * `callback` - A closure that will be called for each reaction event
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the notification stream fails or the callback panics.
+    pub async fn listen_for_reactions<F>(
+        &self,
+        callback: F,
+    ) -> Result<(), NotificationDispatchError>
+    where
+        F: FnMut(ReactionEvent) + Send + 'static,
+    {
+        self.notifications().listen_for_reactions(callback).await
+    }
+
+    /// Listen for group membership changes.
+    ///
+    /// # Arguments
+    ///
+    /// * `callback` - A closure that will be called for each membership change
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the notification stream fails.
+    pub async fn listen_for_member_changes<F>(
+        &self,
+        callback: F,
+    ) -> Result<(), NotificationDispatchError>
+    where
+        F: FnMut(MemberChangeEvent) + Send + 'static,
+    {
+        self.notifications()
+            .listen_for_member_changes(callback)
+            .await
+    }
+
+    /// Listen for message modifications and deletions.
+    ///
+    /// # Arguments
+    ///
+    /// * `callback` - A closure that will be called for each modification event
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the notification stream fails.
+    pub async fn listen_for_message_modifications<F>(
+        &self,
+        callback: F,
+    ) -> Result<(), NotificationDispatchError>
+    where
+        F: FnMut(MessageModifiedEvent) + Send + 'static,
+    {
+        self.notifications()
+            .listen_for_message_modifications(callback)
+            .await
+    }
+
+    /// Listen for invitations to join chat groups.
+    ///
+    /// # Arguments
+    ///
+    /// * `callback` - A closure that will be called for each invite
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the notification stream fails.
+    pub async fn listen_for_group_invites<F>(
+        &self,
+        callback: F,
+    ) -> Result<(), NotificationDispatchError>
+    where
+        F: FnMut(GroupInvite) + Send + 'static,
+    {
+        self.notifications()
+            .listen_for_group_invites(callback)
+            .await
+    }
+
+    /// Listen for changes to group names, taglines, avatars, and room lists.
+    ///
+    /// # Arguments
+    ///
+    /// * `callback` - A closure that will be called for each header change
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the notification stream fails.
+    pub async fn listen_for_group_header_changes<F>(
+        &self,
+        callback: F,
+    ) -> Result<(), NotificationDispatchError>
+    where
+        F: FnMut(GroupHeaderChanged) + Send + 'static,
+    {
+        self.notifications()
+            .listen_for_group_header_changes(callback)
+            .await
+    }
+
+    /// Stream every supported real-time event as a `ChatEvent`.
+    ///
+    /// See `ChatRoomNotifications::event_stream`.
+    pub fn event_stream(&self) -> impl Stream<Item = Result<ChatEvent, ChatError>> + Send + '_ {
+        self.notifications().event_stream()
+    }
+
+    /// Listen for friend activity decoded by entry type.
+    ///
+    /// # Arguments
+    ///
+    /// * `callback` - A closure that will be called for each activity
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the notification stream fails.
+    pub async fn listen_for_friend_activity<F>(
+        &self,
+        callback: F,
+    ) -> Result<(), NotificationDispatchError>
+    where
+        F: FnMut(FriendActivity) + Send + 'static,
+    {
+        self.notifications()
+            .listen_for_friend_activity(callback)
+            .await
+    }
+
+    /// Listen for incoming friend messages.
+    ///
+    /// # Arguments
+    ///
+    /// * `callback` - A closure that will be called for each incoming friend message
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the notification stream fails or the callback panics.
+    pub async fn listen_for_friend_messages<F>(
+        &self,
+        callback: F,
+    ) -> Result<(), NotificationDispatchError>
+    where
+        F: FnMut(FriendMessage) + Send + 'static,
+    {
+        self.notifications()
+            .listen_for_friend_messages(callback)
+            .await
+    }
+
+    /// Listen for incoming friend messages with preprocessing.
+    ///
+    /// # Arguments
+    ///
+    /// * `callback` - A closure that will be called for each incoming friend message
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the notification stream fails.
+    pub async fn listen_for_friend_messages_enhanced<F>(
+        &self,
+        callback: F,
+    ) -> Result<(), NotificationDispatchError>
+    where
+        F: FnMut(EnhancedFriendMessage) + Send + 'static,
+    {
+        self.notifications()
+            .listen_for_friend_messages_enhanced(callback)
+            .await
+    }
+
+    /// Stream incoming group chat messages with preprocessing.
+    ///
+    /// See `ChatRoomNotifications::group_message_stream`.
+    pub fn group_message_stream(&self) -> GroupMessageStream<'_> {
+        self.notifications().group_message_stream()
+    }
+
+    /// Stream incoming friend messages.
+    ///
+    /// See `ChatRoomNotifications::friend_message_stream`.
+    pub fn friend_message_stream(
+        &self,
+    ) -> impl Stream<Item = Result<FriendMessage, ChatError>> + Send + '_ {
+        self.notifications().friend_message_stream()
+    }
+
+    /// Stream group messages missed since `since`, then continue with live messages.
+    ///
+    /// The live subscription is opened first. History newer than the cursor is then
+    /// fetched for every room of every joined group and yielded in chronological order,
+    /// after which live messages follow. Live messages at or before the last position
+    /// already yielded for their room are dropped, so there are no gaps or duplicates at
+    /// the boundary. Rooms the cursor does not track have no backlog and start live.
+    ///
+    /// Advance and persist your own copy of the cursor with every handled message so the
+    /// next run can resume from it.
+    ///
+    /// # Arguments
+    ///
+    /// * `since` - Last seen position per room, typically restored from the previous run
+    ///
+    /// # Returns
+    ///
+    /// A stream of messages. A failed history fetch is yielded as one `ChatError::Network`
+    /// item before the live messages; live transport errors do not end the stream.
+    pub fn catch_up(
+        &self,
+        since: CatchUpCursor,
+    ) -> impl Stream<Item = Result<EnhancedGroupChatMessage, ChatError>> + Send + '_ {
+        let state = CatchUpState {
+            client: self,
+            live: self.group_message_stream(),
+            backlog: None,
+            cursor: since,
+        };
+
+        futures_util::stream::unfold(state, |mut state| async move {
+            if state.backlog.is_none() {
+                match state.client.fetch_backlog(&state.cursor).await {
+                    Ok(backlog) => state.backlog = Some(backlog),
+                    Err(err) => {
+                        state.backlog = Some(VecDeque::new());
+                        return Some((Err(err), state));
+                    }
+                }
+            }
+            if let Some(message) = state.backlog.as_mut().and_then(VecDeque::pop_front) {
+                state.cursor.advance(&message);
+                return Some((Ok(message), state));
+            }
+
+            loop {
+                match StreamExt::next(&mut state.live).await? {
+                    Ok(message) if state.cursor.is_new(&message) => {
+                        state.cursor.advance(&message);
+                        return Some((Ok(message), state));
+                    }
+                    Ok(_) => continue,
+                    Err(err) => return Some((Err(err), state)),
+                }
+            }
+        })
+    }
+
+    /// Stream group messages from where the previous run stopped, persisting progress.
+    ///
+    /// The cursor is loaded from `store` and passed to `catch_up`, so missed history is
+    /// replayed before live messages. A message's position is saved once the stream is
+    /// polled again, i.e. after the consumer finished with it. Delivery is therefore
+    /// at-least-once: the last message handed out before a crash or before the stream is
+    /// dropped is delivered again on the next run, so handlers should tolerate that one
+    /// duplicate per room.
+    ///
+    /// # Arguments
+    ///
+    /// * `store` - Where the cursor is loaded from and saved to
+    ///
+    /// # Returns
+    ///
+    /// A stream of messages. A cursor that cannot be loaded is yielded as a single
+    /// `ChatError::CursorStore` item that ends the stream; failed saves are yielded as
+    /// items and retried with the next message.
+    pub fn resumable_group_stream<S>(
+        &self,
+        store: S,
+    ) -> impl Stream<Item = Result<EnhancedGroupChatMessage, ChatError>> + Send + '_
+    where
+        S: CursorStore + 'static,
+    {
+        let state = match store.load() {
+            Ok(cursor) => ResumeState {
+                messages: Some(FuturesStreamExt::boxed(self.catch_up(cursor.clone()))),
+                load_error: None,
+                store,
+                cursor,
+                handed_out: None,
+            },
+            Err(err) => ResumeState {
+                messages: None,
+                load_error: Some(err),
+                store,
+                cursor: CatchUpCursor::new(),
+                handed_out: None,
+            },
+        };
+
+        futures_util::stream::unfold(state, |mut state| async move {
+            if let Some(err) = state.load_error.take() {
+                return Some((Err(err.into()), state));
+            }
+            if let Some(message) = state.handed_out.take() {
+                state.cursor.advance(&message);
+                if let Err(err) = state.store.save(&state.cursor) {
+                    return Some((Err(err.into()), state));
+                }
+            }
+
+            let item = StreamExt::next(state.messages.as_mut()?).await?;
+            if let Ok(message) = &item {
+                state.handed_out = Some(message.clone());
+            }
+            Some((item, state))
+        })
+    }
+
+    /// History newer than `cursor` for every tracked room, oldest first.
+    async fn fetch_backlog(
+        &self,
+        cursor: &CatchUpCursor,
+    ) -> Result<VecDeque<EnhancedGroupChatMessage>, ChatError> {
+        let response: CChatRoom_GetMyChatRoomGroups_Response = self
+            .connection
+            .service_method(CChatRoom_GetMyChatRoomGroups_Request::new())
+            .await?;
+
+        let mut backlog = Vec::new();
+        for summary in response
+            .chat_room_groups
+            .iter()
+            .filter_map(|pair| pair.group_summary.as_ref())
+        {
+            for room in ChatRoomGroups::chats_from_group_summary(summary) {
+                let Some(seen) = cursor.position(room.chat_group_id, room.chat_id) else {
+                    continue;
+                };
+                let entries = self
+                    .fetch_history_since(room.chat_group_id, room.chat_id, seen)
+                    .await?;
+                debug!(
+                    chat_group_id = room.chat_group_id,
+                    chat_id = room.chat_id,
+                    missed = entries.len(),
+                    "fetched catch-up history"
+                );
+                backlog.extend(entries.into_iter().map(|entry| {
+                    EnhancedGroupChatMessage::from_history(
+                        room.chat_group_id,
+                        room.chat_id,
+                        &room.chat_name,
+                        entry,
+                        &self.preprocessor,
+                    )
+                }));
+            }
+        }
+
+        backlog.sort_by_key(|message| (message.timestamp, message.ordinal));
+        Ok(backlog.into())
+    }
+
+    /// Page backwards through a room's history until reaching `seen`.
+    async fn fetch_history_since(
+        &self,
+        chat_group_id: u64,
+        chat_id: u64,
+        seen: MessagePosition,
+    ) -> Result<Vec<ChatMessageHistoryEntry>, ChatError> {
+        let mut entries = Vec::new();
+        let mut before: Option<MessagePosition> = None;
+        loop {
+            let mut req = CChatRoom_GetMessageHistory_Request::new();
+            req.set_chat_group_id(chat_group_id);
+            req.set_chat_id(chat_id);
+            req.set_start_time(seen.timestamp);
+            req.set_start_ordinal(seen.ordinal);
+            req.set_max_count(CATCH_UP_PAGE_SIZE);
+            if let Some(before) = before {
+                req.set_last_time(before.timestamp);
+                req.set_last_ordinal(before.ordinal);
+            }
+
+            let response: CChatRoom_GetMessageHistory_Response =
+                self.connection.service_method(req).await?;
+            let page: Vec<ChatMessageHistoryEntry> = response
+                .messages
+                .iter()
+                .map(ChatMessageHistoryEntry::from_proto)
+                .collect();
+
+            let position = |entry: &ChatMessageHistoryEntry| MessagePosition {
+                timestamp: entry.server_timestamp,
+                ordinal: entry.ordinal,
+            };
+            let oldest = page.iter().map(position).min();
+            entries.extend(
+                page.into_iter()
+                    .filter(|entry| !entry.deleted && position(entry) > seen),
+            );
+
+            match oldest {
+                Some(oldest) if response.more_available() && oldest > seen => before = Some(oldest),
+                _ => return Ok(entries),
+            }
+        }
+    }
+
+    /// Resolve the accounts mentioned in `mentions` to persona names and avatars.
+    ///
+    /// Accounts not in the persona cache are looked up in batches of
+    /// `PERSONA_LOOKUP_BATCH`; results are cached for the configured TTL (see
+    /// `with_persona_cache_ttl`). Accounts Steam does not return, such as deleted ones,
+    /// resolve to a `ResolvedMention::placeholder` instead of failing the batch.
+    ///
+    /// # Returns
+    ///
+    /// One entry per mentioned SteamID, in mention order.
+    ///
+    /// # Errors
+    ///
+    /// Returns `ChatError::Network` if a lookup request fails.
+    #[instrument(skip_all, fields(mentioned = mentions.mention_steamids.len()))]
+    pub async fn resolve_mentions(
+        &self,
+        mentions: &ChatMentions,
+    ) -> Result<Vec<ResolvedMention>, ChatError> {
+        let steam_ids: Vec<SteamID> = mentions
+            .mention_steamids
+            .iter()
+            .map(|mention| mention.as_inner())
+            .collect();
+        self.resolve_personas(steam_ids).await
+    }
+
+    /// Build a `MentionRoster` of a group's members from their persona names.
+    ///
+    /// Names are resolved like `resolve_mentions`, through the persona cache. Members
+    /// whose persona could not be resolved are left out.
+    ///
+    /// # Arguments
+    ///
+    /// * `chat_group_id` - The unique identifier for the chat group
+    ///
+    /// # Errors
+    ///
+    /// Returns `ChatError::Network` if fetching the member list or a persona lookup fails.
+    #[instrument(skip(self))]
+    pub async fn member_roster(&self, chat_group_id: u64) -> Result<MentionRoster, ChatError> {
+        let mut req = CChatRoom_GetChatRoomGroupState_Request::new();
+        req.set_chat_group_id(chat_group_id);
+        let response: CChatRoom_GetChatRoomGroupState_Response =
+            self.connection.service_method(req).await?;
+
+        let members: Vec<SteamID> = response
+            .state
+            .members
+            .iter()
+            .map(|member| {
+                SteamID::new(
+                    member.accountid(),
+                    Instance::Desktop,
+                    AccountType::Individual,
+                    Universe::Public,
+                )
+            })
+            .collect();
+        let personas = self.resolve_personas(members).await?;
+        Ok(personas
+            .iter()
+            .filter(|persona| persona.found)
+            .map(|persona| (persona.persona_name.as_str(), persona.steam_id))
+            .collect())
+    }
+
+    /// Resolve `steam_ids` through the persona cache, looking up the missing ones.
+    async fn resolve_personas(
+        &self,
+        steam_ids: Vec<SteamID>,
+    ) -> Result<Vec<ResolvedMention>, ChatError> {
+        let mut resolved: HashMap<u64, ResolvedMention> = HashMap::new();
+        let mut missing: Vec<SteamID> = Vec::new();
+        for &steam_id in &steam_ids {
+            let key = u64::from(steam_id);
+            if resolved.contains_key(&key) || missing.contains(&steam_id) {
+                continue;
+            }
+            match self.personas.get(steam_id) {
+                Some(cached) => {
+                    resolved.insert(key, cached);
+                }
+                None => missing.push(steam_id),
+            }
+        }
+
+        for batch in missing.chunks(PERSONA_LOOKUP_BATCH) {
+            let mut req = CPlayer_GetPlayerLinkDetails_Request::new();
+            req.steamids = batch.iter().map(|steam_id| u64::from(*steam_id)).collect();
+            let response: CPlayer_GetPlayerLinkDetails_Response =
+                self.connection.service_method(req).await?;
+            debug!(
+                requested = batch.len(),
+                returned = response.accounts.len(),
+                "resolved personas"
+            );
+
+            let mut found: HashMap<u64, ResolvedMention> = response
+                .accounts
+                .iter()
+                .map(|account| &account.public_data)
+                .filter(|data| data.has_persona_name())
+                .map(|data| {
+                    let persona = ResolvedMention::new(
+                        SteamID::from(data.steamid()),
+                        data.persona_name(),
+                        data.sha_digest_avatar(),
+                    );
+                    (data.steamid(), persona)
+                })
+                .collect();
+            for &steam_id in batch {
+                let key = u64::from(steam_id);
+                let persona = found
+                    .remove(&key)
+                    .unwrap_or_else(|| ResolvedMention::placeholder(steam_id));
+                self.personas.insert(persona.clone());
+                resolved.insert(key, persona);
+            }
+        }
+
+        Ok(steam_ids
+            .into_iter()
+            .map(|steam_id| {
+                resolved
+                    .get(&u64::from(steam_id))
+                    .cloned()
+                    .unwrap_or_else(|| ResolvedMention::placeholder(steam_id))
+            })
+            .collect())
+    }
+
+    /// Get the underlying Steam connection for advanced operations.
+    ///
+    /// This provides direct access to the `steam-vent` connection, allowing
+    /// you to perform operations not covered by the high-level API.
+    pub fn connection(&self) -> &steam_vent::Connection {
+        &self.connection
+    }
+
+    /// Get a mutable reference to the underlying Steam connection.
+    ///
+    /// This provides mutable access to the `steam-vent` connection for advanced use cases.
+    pub fn connection_mut(&mut self) -> &mut steam_vent::Connection {
+        &mut self.connection
+    }
+}
+
+impl<'a, T> NotificationStream<'a, T>
+where
+    T: Send + 'static,
+{
+    fn new<S>(
+        stream: S,
+        options: NotificationOptions,
+        metrics: Option<Arc<dyn MessagingMetrics>>,
+    ) -> Self
+    where
+        S: Stream<Item = Result<T, steam_vent::NetworkError>> + Send + 'a,
+    {
+        Self {
+            inner: FuturesStreamExt::boxed(stream),
+            options,
+            metrics,
+            watchdog: None,
+        }
+    }
+
+    /// Probe `connection` whenever the stream stays silent for the watchdog's window.
+    fn with_watchdog(mut self, watchdog: Watchdog, connection: &'a steam_vent::Connection) -> Self {
+        self.watchdog = Some((watchdog, connection));
+        self
+    }
+
+    /// Space the items of the stream itself by the configured throttle.
+    ///
+    /// Used by pull-based streams and blocking listeners; buffered listeners space their
+    /// handler calls instead, so the stream keeps being drained into their buffer.
+    fn throttled(mut self) -> Self {
+        if let Some(period) = self.options.throttle.take() {
+            self.inner = FuturesStreamExt::boxed(self.inner.throttle(period));
+        }
+        self
+    }
+
+    /// Return stream errors immediately instead of after `error_backoff`.
+    ///
+    /// For callers that wait out their own delay before subscribing again.
+    fn without_error_backoff(mut self) -> Self {
+        self.options.error_backoff = Duration::ZERO;
+        self
+    }
+
+    fn filter_items<P>(self, mut predicate: P) -> Self
+    where
+        P: FnMut(&T) -> bool + Send + 'a,
+    {
+        NotificationStream {
+            inner: FuturesStreamExt::boxed(FuturesStreamExt::filter(self.inner, move |item| {
+                std::future::ready(match item {
+                    Ok(value) => predicate(value),
+                    Err(_) => true,
+                })
+            })),
+            options: self.options,
+            metrics: self.metrics,
+            watchdog: self.watchdog,
+        }
+    }
+
+    fn filter_map_items<U, F>(self, mut f: F) -> NotificationStream<'a, U>
+    where
+        U: Send + 'static,
+        F: FnMut(T) -> Option<U> + Send + 'a,
+    {
+        NotificationStream {
+            inner: FuturesStreamExt::boxed(FuturesStreamExt::filter_map(self.inner, move |item| {
+                std::future::ready(match item {
+                    Ok(value) => f(value).map(Ok),
+                    Err(err) => Some(Err(err)),
+                })
+            })),
+            options: self.options,
+            metrics: self.metrics,
+            watchdog: self.watchdog,
+        }
+    }
+
+    fn map_items<U, F>(self, mut f: F) -> NotificationStream<'a, U>
+    where
+        U: Send + 'static,
+        F: FnMut(T) -> U + Send + 'a,
+    {
+        NotificationStream {
+            inner: FuturesStreamExt::boxed(FuturesStreamExt::map(self.inner, move |item| {
+                item.map(&mut f)
+            })),
+            options: self.options,
+            metrics: self.metrics,
+            watchdog: self.watchdog,
+        }
+    }
+
+    /// Interleave two notification streams in arrival order.
+    fn merge(self, other: NotificationStream<'a, T>) -> Self {
+        NotificationStream {
+            inner: FuturesStreamExt::boxed(futures_util::stream::select(self.inner, other.inner)),
+            options: self.options,
+            metrics: self.metrics,
+            watchdog: self.watchdog,
+        }
+    }
+
+    /// Expose the notifications as a plain stream; stream errors are yielded, not fatal.
+    fn into_stream(self) -> impl Stream<Item = Result<T, ChatError>> + Send + 'a {
+        let stream = self.throttled();
+        let metrics = stream.metrics;
+        FuturesStreamExt::map(stream.inner, move |item| {
+            if item.is_ok()
+                && let Some(metrics) = &metrics
+            {
+                metrics.record_notification();
+            }
+            item.map_err(ChatError::Network)
+        })
+    }
+
+    async fn for_each<F>(self, handler: F) -> Result<(), NotificationDispatchError>
+    where
+        F: FnMut(T) -> CallbackResult + Send,
+    {
+        self.for_each_until(std::future::pending(), handler).await
+    }
+
+    /// Like `for_each`, but returns `Ok(())` once `shutdown` resolves.
+    ///
+    /// Shutdown is only observed between notifications, so a running handler always
+    /// completes before the loop exits. Unless the overflow policy is `Block`, the stream
+    /// is drained into a bounded channel of `buffer_capacity` notifications while the
+    /// handler catches up, and the policy decides what happens when it is full; the
+    /// throttle then spaces the handler calls instead of the stream.
+    async fn for_each_until<F, S>(
+        self,
+        shutdown: S,
+        handler: F,
+    ) -> Result<(), NotificationDispatchError>
+    where
+        F: FnMut(T) -> CallbackResult + Send,
+        S: Future<Output = ()>,
+    {
+        match BufferOverflow::of(self.options.overflow) {
+            None => {
+                self.throttled()
+                    .dispatch_unbuffered(shutdown, handler)
+                    .await
+            }
+            Some(overflow) => self.dispatch_buffered(overflow, shutdown, handler).await,
+        }
+    }
+
+    /// Pull the next notification only once the handler returned.
+    async fn dispatch_unbuffered<F, S>(
+        mut self,
+        shutdown: S,
+        mut handler: F,
+    ) -> Result<(), NotificationDispatchError>
+    where
+        F: FnMut(T) -> CallbackResult + Send,
+        S: Future<Output = ()>,
+    {
+        let mut shutdown = std::pin::pin!(shutdown);
+        loop {
+            let idle = self.idle_window();
+            let next = tokio::select! {
+                biased;
+                _ = &mut shutdown => return Ok(()),
+                next = StreamExt::next(&mut self.inner) => next,
+                _ = sleep(idle.unwrap_or_default()), if idle.is_some() => {
+                    if let Some((watchdog, connection)) = self.watchdog.clone() {
+                        probe_stalled(&watchdog, connection, self.options.error_backoff).await?;
+                    }
+                    continue;
+                }
+            };
+            match next {
+                Some(Ok(item)) => self.dispatch(&mut handler, item)?,
+                Some(Err(err)) => {
+                    sleep(self.options.error_backoff).await;
+                    return Err(NotificationDispatchError::Stream(err));
+                }
+                None => return Ok(()),
+            }
+        }
+    }
+
+    /// Drain the stream into a bounded channel while the handler consumes it.
+    async fn dispatch_buffered<F, S>(
+        mut self,
+        overflow: BufferOverflow,
+        shutdown: S,
+        mut handler: F,
+    ) -> Result<(), NotificationDispatchError>
+    where
+        F: FnMut(T) -> CallbackResult + Send,
+        S: Future<Output = ()>,
+    {
+        enum Event<I> {
+            Pulled(Option<Result<I, steam_vent::NetworkError>>),
+            Ready(Option<I>),
+            Idle,
+        }
+
+        let capacity = self.options.buffer_capacity.max(1);
+        let (sender, mut receiver) = mpsc::channel(capacity);
+        let mut sender = Some(sender);
+        let mut stream_error = None;
+        let mut next_delivery = tokio::time::Instant::now();
+        let mut shutdown = std::pin::pin!(shutdown);
+        loop {
+            let idle = self.idle_window().filter(|_| sender.is_some());
+            let event = tokio::select! {
+                biased;
+                _ = &mut shutdown => return Ok(()),
+                item = async {
+                    tokio::time::sleep_until(next_delivery).await;
+                    receiver.recv().await
+                } => Event::Ready(item),
+                next = StreamExt::next(&mut self.inner), if sender.is_some() => Event::Pulled(next),
+                _ = sleep(idle.unwrap_or_default()), if idle.is_some() => Event::Idle,
+            };
+            match event {
+                Event::Ready(Some(item)) => {
+                    self.dispatch(&mut handler, item)?;
+                    if let Some(period) = self.options.throttle {
+                        next_delivery = tokio::time::Instant::now() + period;
+                    }
+                }
+                // The stream ended or failed and everything buffered was handled.
+                Event::Ready(None) => {
+                    return match stream_error {
+                        Some(err) => {
+                            sleep(self.options.error_backoff).await;
+                            Err(NotificationDispatchError::Stream(err))
+                        }
+                        None => Ok(()),
+                    };
+                }
+                Event::Pulled(Some(Ok(item))) => {
+                    if let Some(sender) = &sender {
+                        self.enqueue(overflow, sender, &mut receiver, item)?;
+                    }
+                }
+                Event::Pulled(Some(Err(err))) => {
+                    stream_error = Some(err);
+                    sender = None;
+                }
+                Event::Pulled(None) => sender = None,
+                Event::Idle => {
+                    if let Some((watchdog, connection)) = self.watchdog.clone() {
+                        probe_stalled(&watchdog, connection, self.options.error_backoff).await?;
+                    }
+                }
+            }
+        }
+    }
+
+    /// Buffer `item`, applying `overflow` when the channel is full.
+    fn enqueue(
+        &self,
+        overflow: BufferOverflow,
+        sender: &mpsc::Sender<T>,
+        receiver: &mut mpsc::Receiver<T>,
+        item: T,
+    ) -> Result<(), NotificationDispatchError> {
+        match overflow.push(sender, receiver, item) {
+            Ok(false) => Ok(()),
+            Ok(true) => {
+                if let Some(metrics) = &self.metrics {
+                    metrics.record_notification_dropped();
+                }
+                Ok(())
+            }
+            Err(Overflowed) => Err(NotificationDispatchError::Overflow {
+                capacity: self.options.buffer_capacity,
+            }),
+        }
+    }
+
+    /// Hand `item` to the handler, isolating panics according to the panic policy.
+    fn dispatch<F>(&self, handler: &mut F, item: T) -> Result<(), NotificationDispatchError>
+    where
+        F: FnMut(T) -> CallbackResult + Send,
+    {
+        if let Some(metrics) = &self.metrics {
+            metrics.record_notification();
+        }
+        match std::panic::catch_unwind(AssertUnwindSafe(|| handler(item))) {
+            Ok(result) => result.map_err(|source| NotificationDispatchError::Callback { source }),
+            Err(payload) => {
+                let message = panic_message(payload.as_ref());
+                if self.options.on_panic == PanicPolicy::Stop {
+                    return Err(NotificationDispatchError::Callback {
+                        source: format!("callback panicked: {message}").into(),
+                    });
+                }
+                tracing::error!(panic = %message, "notification callback panicked; continuing");
+                Ok(())
+            }
+        }
+    }
+
+    fn idle_window(&self) -> Option<Duration> {
+        self.watchdog
+            .as_ref()
+            .map(|(watchdog, _)| watchdog.idle_window)
+    }
+}
+
+/// Mark a group chat room as read up to `timestamp`, then move `read_state` along.
+async fn send_chat_ack(
+    connection: &steam_vent::Connection,
+    read_state: Option<&ReadState>,
+    chat_group_id: u64,
+    chat_id: u64,
+    timestamp: u32,
+) -> Result<(), KetherError> {
+    let mut req = CChatRoom_AckChatMessage_Notification::new();
+    req.set_chat_group_id(chat_group_id);
+    req.set_chat_id(chat_id);
+    req.set_timestamp(timestamp);
+
+    // AckChatMessage is a notification without a response; the read state only
+    // moves once it has actually been sent.
+    connection.service_method_un_acked(req).await?;
+
+    if let Some(read_state) = read_state {
+        read_state.acknowledge(chat_group_id, chat_id, timestamp);
+    }
+    Ok(())
+}
+
+/// Acknowledges the group messages a listener handled, for `NotificationOptions::auto_ack`.
+///
+/// Dropping it lets the background task send what is still queued, then stop.
+struct AutoAck {
+    pending: Arc<PendingAcks>,
+}
+
+impl AutoAck {
+    fn start(connection: steam_vent::Connection, read_state: Option<Arc<ReadState>>) -> Self {
+        let pending = Arc::new(PendingAcks::default());
+        let queued = pending.clone();
+        tokio::spawn(async move {
+            while let Some(batch) = queued.next_batch().await {
+                for ((chat_group_id, chat_id), timestamp) in batch {
+                    if let Err(err) = send_chat_ack(
+                        &connection,
+                        read_state.as_deref(),
+                        chat_group_id,
+                        chat_id,
+                        timestamp,
+                    )
+                    .await
+                    {
+                        tracing::warn!(
+                            chat_group_id,
+                            chat_id,
+                            error = %err,
+                            "failed to acknowledge handled group messages"
+                        );
+                    }
+                }
+            }
+        });
+        Self { pending }
+    }
+
+    /// Queue an ack of a handled message's room up to its timestamp.
+    fn handled(&self, (chat_group_id, chat_id, timestamp): (u64, u64, u32)) {
+        self.pending.record(chat_group_id, chat_id, timestamp);
+    }
+}
+
+impl Drop for AutoAck {
+    fn drop(&mut self) {
+        self.pending.close();
+    }
+}
+
+/// Room and timestamp an acknowledgement of `message` refers to.
+fn ack_position(message: &EnhancedGroupChatMessage) -> (u64, u64, u32) {
+    (message.chat_group_id, message.chat_id, message.timestamp)
+}
+
+/// Receiving half of a room queue in `listen_for_group_messages_sequential`, shared
+/// between the listener and the room's worker.
+type RoomReceiver = Arc<tokio::sync::Mutex<mpsc::Receiver<EnhancedGroupChatMessage>>>;
+
+/// How a room worker stopped: its room and gap state once idle, or the handler's error.
+type RoomOutcome = Result<((u64, u64), GapDetector), Box<dyn Error + Send + Sync>>;
+
+/// A room's bounded queue in `listen_for_group_messages_sequential`.
+struct RoomQueue {
+    sender: mpsc::Sender<EnhancedGroupChatMessage>,
+    /// Also held by the listener so `OverflowPolicy::DropOldest` can discard from it.
+    receiver: RoomReceiver,
+}
+
+/// The room and gap state of a stopped room worker, or the listener's error if it failed.
+fn room_stopped(
+    joined: Result<RoomOutcome, tokio::task::JoinError>,
+) -> Result<((u64, u64), GapDetector), NotificationDispatchError> {
+    match joined {
+        Ok(Ok(stopped)) => Ok(stopped),
+        Ok(Err(source)) => Err(NotificationDispatchError::Callback { source }),
+        Err(err) => Err(NotificationDispatchError::Callback {
+            source: Box::new(err),
+        }),
+    }
+}
+
+/// What a buffering listener does with a notification that doesn't fit; the
+/// `OverflowPolicy` variants other than `Block`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum BufferOverflow {
+    DropOldest,
+    DropNewest,
+    Fail,
+}
+
+/// The buffer was full under `OverflowPolicy::Fail`.
+#[derive(Debug)]
+struct Overflowed;
+
+impl BufferOverflow {
+    /// How `policy` buffers, or `None` if it doesn't buffer at all.
+    fn of(policy: OverflowPolicy) -> Option<Self> {
+        match policy {
+            OverflowPolicy::Block => None,
+            OverflowPolicy::DropOldest => Some(Self::DropOldest),
+            OverflowPolicy::DropNewest => Some(Self::DropNewest),
+            OverflowPolicy::Fail => Some(Self::Fail),
+        }
+    }
+
+    /// Send `item` into the bounded channel of `receiver`, making room as configured.
+    ///
+    /// # Returns
+    ///
+    /// Whether a buffered or the new item was dropped to keep within capacity.
+    fn push<T>(
+        self,
+        sender: &mpsc::Sender<T>,
+        receiver: &mut mpsc::Receiver<T>,
+        item: T,
+    ) -> Result<bool, Overflowed> {
+        // With the receiver at hand the channel can't be closed, so a failed send always
+        // means it is full.
+        let item = match sender.try_send(item) {
+            Ok(()) => return Ok(false),
+            Err(
+                mpsc::error::TrySendError::Full(item) | mpsc::error::TrySendError::Closed(item),
+            ) => item,
+        };
+        match self {
+            Self::DropOldest => {
+                // Receiving frees a slot right away, so the new item always fits.
+                let _ = receiver.try_recv();
+                let _ = sender.try_send(item);
+                Ok(true)
+            }
+            Self::DropNewest => Ok(true),
+            Self::Fail => Err(Overflowed),
+        }
+    }
+}
+
+/// Check `connection` after the watchdog's idle window passed without notifications.
+///
+/// Returns the probe's error as a stream error so restartable listeners resubscribe.
+async fn probe_stalled(
+    watchdog: &Watchdog,
+    connection: &steam_vent::Connection,
+    error_backoff: Duration,
+) -> Result<(), NotificationDispatchError> {
+    let probe: Result<CFriendsMessages_GetActiveMessageSessions_Response, _> = connection
+        .service_method(CFriendsMessages_GetActiveMessageSessions_Request::new())
+        .await;
+    let stalled = StreamStalled {
+        idle: watchdog.idle_window,
+        connection_alive: probe.is_ok(),
+    };
+    tracing::warn!(
+        idle = ?stalled.idle,
+        connection_alive = stalled.connection_alive,
+        "notification stream stalled"
+    );
+    if let Some(events) = &watchdog.events {
+        let _ = events.send(stalled);
+    }
+    match probe {
+        Ok(_) => Ok(()),
+        Err(err) => {
+            sleep(error_backoff).await;
+            Err(NotificationDispatchError::Stream(err))
+        }
+    }
+}
+
+impl<'a> ChatRoomGroups<'a> {
+    fn chats_from_group_summary(
+        summary: &steam_vent_proto::steammessages_chat_steamclient::CChatRoom_GetChatRoomGroupSummary_Response,
+    ) -> Vec<ChatRoomInfo> {
+        let group_id = summary.chat_group_id();
+        let group_name = summary.chat_group_name().to_string();
+        let chats: Vec<ChatRoomInfo> = summary
+            .chat_rooms
+            .iter()
+            .map(|room| ChatRoomInfo {
+                chat_group_id: group_id,
+                chat_id: room.chat_id(),
+                chat_name: room.chat_name().to_string(),
+                chat_group_name: group_name.clone(),
+                is_joined: true,
+            })
+            .collect();
+
+        if chats.is_empty() {
+            vec![ChatRoomInfo {
+                chat_group_id: group_id,
+                chat_id: summary.default_chat_id(),
+                chat_name: group_name.clone(),
+                chat_group_name: group_name,
+                is_joined: true,
+            }]
+        } else {
+            chats
+        }
+    }
+
+    /// Get all chat groups the user belongs to, including every chat room in each group.
+    pub async fn get_my_chat_groups(&self) -> Result<Vec<ChatGroupInfo>, KetherError> {
+        let req = CChatRoom_GetMyChatRoomGroups_Request::new();
+        let response: CChatRoom_GetMyChatRoomGroups_Response =
+            self.connection.service_method(req).await?;
+
+        let mut groups = Vec::new();
+        for pair in &response.chat_room_groups {
+            if let Some(summary) = pair.group_summary.as_ref() {
+                groups.push(ChatGroupInfo {
+                    chat_group_id: summary.chat_group_id(),
+                    chat_group_name: summary.chat_group_name().to_string(),
+                    chats: Self::chats_from_group_summary(summary),
+                });
+            }
+        }
+
+        Ok(groups)
+    }
+
+    /// Get all chat room groups that the user is a member of.
+    ///
+    /// # Returns
+    ///
+    /// A list of `ChatRoomInfo` structures describing each chat room group the user belongs to.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the Steam API request fails.
+    pub async fn get_my_chat_rooms(&self) -> Result<Vec<ChatRoomInfo>, KetherError> {
+        let req = CChatRoom_GetMyChatRoomGroups_Request::new();
+        let response: CChatRoom_GetMyChatRoomGroups_Response =
+            self.connection.service_method(req).await?;
+
+        let mut chat_rooms = Vec::new();
+        for pair in &response.chat_room_groups {
+            if let Some(group_summary) = pair.group_summary.as_ref() {
+                let chat_room = ChatRoomInfo {
+                    chat_group_id: group_summary.chat_group_id(),
+                    chat_id: group_summary.default_chat_id(),
+                    chat_name: group_summary.chat_group_name().to_string(),
+                    chat_group_name: group_summary.chat_group_name().to_string(),
+                    is_joined: true,
+                };
+                chat_rooms.push(chat_room);
+            }
+        }
+
+        Ok(chat_rooms)
+    }
+
+    /// Join a chat room group.
+    ///
+    /// # Arguments
+    ///
+    /// * `chat_group_id` - The unique identifier for the chat group
+    /// * `chat_id` - The unique identifier for the specific chat room
+    /// * `invite_code` - Optional invite code required for private chat rooms
+    ///
+    /// # Returns
+    ///
+    /// The Steam API response containing join confirmation details.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the join request fails or if an invalid invite code is provided.
+    pub async fn join_chat_room(
+        &self,
+        chat_group_id: u64,
+        chat_id: u64,
+        invite_code: Option<String>,
+    ) -> Result<CChatRoom_JoinChatRoomGroup_Response, KetherError> {
+        let mut req = CChatRoom_JoinChatRoomGroup_Request::new();
+        req.set_chat_group_id(chat_group_id);
+        req.set_chat_id(chat_id);
+
+        if let Some(code) = invite_code {
+            req.set_invite_code(code);
+        }
+
+        let response: CChatRoom_JoinChatRoomGroup_Response =
+            self.connection.service_method(req).await?;
+        Ok(response)
+    }
+
+    /// Leave a chat room group.
+    ///
+    /// # Arguments
+    ///
+    /// * `chat_group_id` - The unique identifier for the chat group to leave
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the leave request fails.
+    pub async fn leave_chat_room(&self, chat_group_id: u64) -> Result<(), KetherError> {
+        let mut req = CChatRoom_LeaveChatRoomGroup_Request::new();
+        req.set_chat_group_id(chat_group_id);
+
+        let _response: CChatRoom_LeaveChatRoomGroup_Response =
+            self.connection.service_method(req).await?;
+        Ok(())
+    }
+
+    /// Get the current state of a chat room group.
+    ///
+    /// # Arguments
+    ///
+    /// * `chat_group_id` - The unique identifier for the chat group
+    ///
+    /// # Returns
+    ///
+    /// The Steam API response containing the current state of the chat room group.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the state request fails.
+    pub async fn get_chat_room_state(
+        &self,
+        chat_group_id: u64,
+    ) -> Result<CChatRoom_GetChatRoomGroupState_Response, KetherError> {
+        let mut req = CChatRoom_GetChatRoomGroupState_Request::new();
+        req.set_chat_group_id(chat_group_id);
+
+        let response: CChatRoom_GetChatRoomGroupState_Response =
+            self.connection.service_method(req).await?;
+        Ok(response)
+    }
+}
+
+impl<'a> ChatRoomMessaging<'a> {
+    /// Suppress identical group messages sent to the same room within `window`.
+    ///
+    /// Messages are keyed by `(chat_group_id, chat_id, prepared text)`; the cache is
+    /// bounded (`DEFAULT_DEDUPE_CAPACITY` entries, least recently used evicted first).
+    /// Suppressed sends fail with a `DuplicateMessageError` without touching the network;
+    /// sends that fail are forgotten, so retrying them is not suppressed.
+    ///
+    /// # Arguments
+    ///
+    /// * `window` - How long an identical message is considered a duplicate
+    ///
+    /// # Returns
+    ///
+    /// `Self` for method chaining (builder pattern).
+    pub fn with_dedupe(self, window: Duration) -> Self {
+        self.with_dedupe_cache(Arc::new(DedupeCache::new(window)))
+    }
+
+    /// Use an existing dedupe cache, e.g. one shared between several handles.
+    ///
+    /// # Returns
+    ///
+    /// `Self` for method chaining (builder pattern).
+    pub fn with_dedupe_cache(mut self, cache: Arc<DedupeCache>) -> Self {
+        self.dedupe = Some(cache);
+        self
+    }
+
+    /// Switch to ordered sending.
+    ///
+    /// Sends made through the returned handle to the same `(chat_group_id, chat_id)` are
+    /// dispatched strictly one after another, in the order they start waiting (the order
+    /// the futures are first polled, e.g. the order passed to `join_all`). Sends to
+    /// different rooms still run concurrently. The ordering is shared by every `messaging()`
+    /// handle created from the same `ChatRoomClient`.
+    ///
+    /// # Returns
+    ///
+    /// An `OrderedMessaging` handle with the group send API.
+    pub fn ordered(self) -> OrderedMessaging<'a> {
+        OrderedMessaging { messaging: self }
+    }
+
+    /// Send a message to a group chat with preprocessing.
+    ///
+    /// The message will be preprocessed to extract BBCode and mentions before sending.
+    ///
+    /// # Arguments
+    ///
+    /// * `params` - Parameters for sending the message (see `SendGroupMessageParams`)
+    ///
+    /// # Returns
+    ///
+    /// A `PreprocessedMessage` containing the original message, server-modified version,
+    /// parsed BBCode, and extracted mentions.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the message sending fails, or a `DuplicateMessageError` when
+    /// deduplication is enabled and an identical message was sent within the window.
+    pub async fn send_group_message(
+        &self,
+        params: impl Into<SendGroupMessageParams>,
+    ) -> Result<PreprocessedMessage, KetherError> {
+        self.send_group_message_ref(&params.into()).await
+    }
+
+    /// Send a message to a group chat without taking ownership of the parameters.
+    ///
+    /// Behaves like `send_group_message`, but borrows `params` so the same value can be
+    /// reused for retries or broadcasts without cloning the message text.
+    ///
+    /// # Arguments
+    ///
+    /// * `params` - Parameters for sending the message (see `SendGroupMessageParams`)
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the message sending fails, a `ChatError::InvalidBBCode` when
+    /// `strict_bbcode` is set and the message is malformed for the client's preprocessor
+    /// (`TagContext::GroupChat` unless replaced), or a `DuplicateMessageError`
+    /// when deduplication is enabled and an identical message was sent within the window.
+    pub async fn send_group_message_ref(
+        &self,
+        params: &SendGroupMessageParams,
+    ) -> Result<PreprocessedMessage, KetherError> {
+        self.observe_send(self.send_group_message_unobserved(params))
+            .await
+    }
+
+    async fn send_group_message_unobserved(
+        &self,
+        params: &SendGroupMessageParams,
+    ) -> Result<PreprocessedMessage, KetherError> {
+        if let Some(timeout) = self.defaults.echo_wait {
+            return self
+                .send_with_echo_ref(params, timeout)
+                .await
+                .map(|echoed| echoed.preprocessed);
+        }
+
+        let (final_preprocessed, _) = self.dispatch_group_message(params, false).await?;
+
+        // According to DrMcKay's wiki, the response has both server_timestamp and ordinal.
+        // Ordinal can be 0 (and can be omitted in deletion requests if 0).
+        // The response values are sufficient for deletion, so we don't need to wait for the notification.
+        // This avoids unnecessary delays.
+
+        debug!(
+            chat_group_id = params.chat_group_id,
+            chat_id = params.chat_id,
+            ordinal = final_preprocessed.ordinal.unwrap_or(0),
+            "group message dispatched"
+        );
+
+        Ok(final_preprocessed)
+    }
+
+    /// Time `send` and report the outcome to the metrics recorder, if one is installed.
+    async fn observe_send<T>(
+        &self,
+        send: impl Future<Output = Result<T, KetherError>>,
+    ) -> Result<T, KetherError> {
+        let Some(metrics) = &self.metrics else {
+            return send.await;
+        };
+
+        let started = Instant::now();
+        let result = send.await;
+        match &result {
+            Ok(_) => metrics.record_send_ok(started.elapsed()),
+            Err(err) => metrics.record_send_err(classify_send_error(err)),
+        }
+        result
+    }
+
+    async fn dispatch_group_message(
+        &self,
+        params: &SendGroupMessageParams,
+        force_echo: bool,
+    ) -> Result<(PreprocessedMessage, EchoExpectation), KetherError> {
+        let mut req = Self::checked_send_request(params, &self.defaults, &self.preprocessor)?;
+        if force_echo {
+            req.set_echo_to_sender(true);
+        }
+        if let Some(dedupe) = &self.dedupe {
+            dedupe.check_and_record(params.chat_group_id, params.chat_id, req.message())?;
+        }
+        let sent_message = req.message().to_string();
+        let response = self
+            .send_chat_message_with_retry(req)
+            .await
+            .inspect_err(|_| {
+                // A failed send must not suppress its own retry or outbox replay.
+                if let Some(dedupe) = &self.dedupe {
+                    dedupe.forget(params.chat_group_id, params.chat_id, &sent_message);
+                }
+            })?;
+
+        let expectation = EchoExpectation {
+            chat_group_id: params.chat_group_id,
+            chat_id: params.chat_id,
+            sender_account_id: self.connection.steam_id().account_id(),
+            sent_message,
+            modified_message: response.modified_message().to_string(),
+            response_timestamp: response.server_timestamp(),
+            response_ordinal: response.has_ordinal().then(|| response.ordinal()),
+        };
+        Ok((
+            self.process_send_message_response(params, &response),
+            expectation,
+        ))
+    }
+
+    async fn send_chat_message_with_retry(
+        &self,
+        req: CChatRoom_SendChatMessage_Request,
+    ) -> Result<CChatRoom_SendChatMessage_Response, steam_vent::NetworkError> {
+        let mut attempt = 1;
+        loop {
+            match self.connection.service_method(req.clone()).await {
+                Ok(response) => return Ok(response),
+                Err(err) => {
+                    let disposition = classify_network_error(&err).disposition;
+                    let Some(delay) = self.defaults.retry.delay_before(attempt, disposition) else {
+                        return Err(err);
+                    };
+                    tracing::warn!(attempt, ?delay, error = %err, "retrying group message send");
+                    sleep(delay).await;
+                    attempt += 1;
+                }
+            }
+        }
+    }
+
+    /// Send a group message and wait for its echo notification.
+    ///
+    /// The send response does not always carry a usable `ordinal`, so this enables
+    /// `echo_to_sender` and pairs the send with the echoed `IncomingChatMessage`. A
+    /// notification only matches if it comes from this account in the same room with the
+    /// same text, and its timestamp is at or after the response's `server_timestamp` and
+    /// within a two-second window. An exact timestamp match is preferred, so two quick
+    /// identical sends are not paired with each other's echo.
+    ///
+    /// # Arguments
+    ///
+    /// * `params` - Parameters for sending the message; `echo_to_sender` is forced on
+    /// * `timeout` - How long to wait for the echo after the send completes
+    ///
+    /// # Returns
+    ///
+    /// An `EchoedGroupMessage` with the echo's identifiers applied to the preprocessed
+    /// message, plus the response-derived identifiers for comparison.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if sending fails, the notification stream fails, or
+    /// `ChatError::EchoTimeout` if no matching echo arrives within `timeout`.
+    #[instrument(
+        name = "kether.chat.send_group_message_with_echo",
+        skip(self, params),
+        fields(chat_group_id = tracing::field::Empty, chat_id = tracing::field::Empty)
+    )]
+    pub async fn send_group_message_with_echo(
+        &self,
+        params: impl Into<SendGroupMessageParams>,
+        timeout: Duration,
+    ) -> Result<EchoedGroupMessage, KetherError> {
+        let params = params.into();
+        let span = tracing::Span::current();
+        span.record("chat_group_id", params.chat_group_id);
+        span.record("chat_id", params.chat_id);
+
+        self.send_with_echo_ref(&params, timeout).await
+    }
+
+    async fn send_with_echo_ref(
+        &self,
+        params: &SendGroupMessageParams,
+        timeout: Duration,
+    ) -> Result<EchoedGroupMessage, KetherError> {
+        // Subscribe before sending so an echo that beats the response is not missed.
+        let mut echoes = FuturesStreamExt::boxed(
+            self.connection
+                .on_notification::<CChatRoom_IncomingChatMessage_Notification>(),
+        );
+        let (mut preprocessed, expectation) = self.dispatch_group_message(params, true).await?;
+        let (echo_timestamp, echo_ordinal) =
+            Self::wait_for_echo_notification(&mut echoes, &expectation, timeout).await?;
+
+        preprocessed.server_timestamp = Some(echo_timestamp);
+        preprocessed.ordinal = Some(echo_ordinal);
+
+        let echoed = EchoedGroupMessage {
+            preprocessed,
+            response_timestamp: expectation.response_timestamp,
+            response_ordinal: expectation.response_ordinal,
+            echo_timestamp,
+            echo_ordinal,
+        };
+        if echoed.identifiers_disagree() {
+            tracing::warn!(
+                response_timestamp = echoed.response_timestamp,
+                response_ordinal = ?echoed.response_ordinal,
+                echo_timestamp,
+                echo_ordinal,
+                "send response and echo notification disagree on message identifiers"
+            );
+        }
+        Ok(echoed)
+    }
+
+    async fn wait_for_echo_notification<S>(
+        echoes: &mut S,
+        expectation: &EchoExpectation,
+        timeout: Duration,
+    ) -> Result<(u32, u32), KetherError>
+    where
+        S: Stream<
+                Item = Result<CChatRoom_IncomingChatMessage_Notification, steam_vent::NetworkError>,
+            > + Unpin,
+    {
+        let deadline = tokio::time::Instant::now() + timeout;
+        let mut fallback = None;
+
+        loop {
+            match tokio::time::timeout_at(deadline, StreamExt::next(echoes)).await {
+                Ok(Some(Ok(notification))) => match expectation.classify(&notification) {
+                    Some(EchoMatch::Exact) => {
+                        return Ok((notification.timestamp(), notification.ordinal()));
+                    }
+                    Some(EchoMatch::InWindow) if fallback.is_none() => {
+                        fallback = Some((notification.timestamp(), notification.ordinal()));
+                    }
+                    _ => {}
+                },
+                Ok(Some(Err(err))) => return Err(err.into()),
+                Ok(None) | Err(_) => break,
+            }
+        }
+
+        fallback.ok_or_else(|| {
+            ChatError::EchoTimeout {
+                chat_group_id: expectation.chat_group_id,
+                chat_id: expectation.chat_id,
+                waited: timeout,
+            }
+            .into()
+        })
+    }
+
+    /// The exact text a send of `params` puts on the wire.
+    ///
+    /// # Errors
+    ///
+    /// Returns the `ChatError` the send would be rejected with before reaching Steam.
+    pub(crate) fn prepare_group_message(
+        &self,
+        params: &SendGroupMessageParams,
+    ) -> Result<String, ChatError> {
+        Self::checked_send_request(params, &self.defaults, &self.preprocessor)
+            .map(|req| req.message().to_string())
+    }
+
+    /// Build the request for `params` and run the local checks (length, strict BBCode)
+    /// that reject a send before any network call.
+    pub(crate) fn checked_send_request(
+        params: &SendGroupMessageParams,
+        defaults: &MessagingDefaults,
+        preprocessor: &MessagePreprocessor,
+    ) -> Result<CChatRoom_SendChatMessage_Request, ChatError> {
+        let req = Self::build_send_message_request(params, defaults, preprocessor);
+        MessagePreprocessor::validate_length(req.message()).map_err(ChatError::from)?;
+        if params.effective_strict_bbcode(defaults) {
+            ChatError::check_bbcode(preprocessor.diagnose(req.message()))?;
+        }
+        Ok(req)
+    }
+
+    fn build_send_message_request(
+        params: &SendGroupMessageParams,
+        defaults: &MessagingDefaults,
+        preprocessor: &MessagePreprocessor,
+    ) -> CChatRoom_SendChatMessage_Request {
+        let mut prepared_message = if params.emote {
+            preprocessing_helpers::create_emote_message(&params.message)
+        } else {
+            params.message.clone()
+        };
+        if !params.link_previews {
+            prepared_message = preprocessing_helpers::suppress_link_previews(&prepared_message);
+        }
+        if !params.preserve_escapes {
+            prepared_message = MessagePreprocessor::normalize_outgoing(&prepared_message);
+        }
+        if params.server_mentions {
+            prepared_message = preprocessor.tag_mentions(&prepared_message);
+        }
+        let mut req = CChatRoom_SendChatMessage_Request::new();
+        req.set_chat_group_id(params.chat_group_id);
+        req.set_chat_id(params.chat_id);
+        req.set_message(prepared_message);
+        req.set_echo_to_sender(params.effective_echo_to_sender(defaults));
+        req
+    }
+
+    fn process_send_message_response(
+        &self,
+        params: &SendGroupMessageParams,
+        response: &CChatRoom_SendChatMessage_Response,
+    ) -> PreprocessedMessage {
+        // Steam may not populate ordinal in the response (or set it to 0).
+        // If echo_to_sender is true, the ordinal will be available in the notification.
+        // process_response will set ordinal to None if it's 0, indicating it needs to be
+        // obtained from the notification.
+        let ordinal = if response.has_ordinal() {
+            response.ordinal()
+        } else {
+            // Not set in response - will be available in notification if echo_to_sender is true
+            0
+        };
+
+        self.preprocessor.process(
+            &params.message,
+            response.modified_message(),
+            response.server_timestamp(),
+            ordinal,
+        )
+    }
+
+    /// Update a PreprocessedMessage with ordinal and timestamp from a notification.
+    ///
+    /// This is useful when you need the ordinal from the echo notification to delete a message.
+    ///
+    /// # Arguments
+    ///
+    /// * `preprocessed` - The PreprocessedMessage to update
+    /// * `notification` - The incoming chat message notification containing the ordinal
+    ///
+    /// # Returns
+    ///
+    /// A new PreprocessedMessage with updated ordinal and server_timestamp from the notification.
+    pub fn update_preprocessed_from_notification(
+        preprocessed: &PreprocessedMessage,
+        notification: &CChatRoom_IncomingChatMessage_Notification,
+    ) -> PreprocessedMessage {
+        MessagePreprocessor::process_response(
+            &preprocessed.original_message,
+            notification.message(),
+            notification.timestamp(),
+            notification.ordinal(),
+        )
+    }
+
+    /// Send a message to a friend.
+    ///
+    /// # Arguments
+    ///
+    /// * `friend_steam_id` - The Steam ID of the friend to send the message to
+    /// * `message` - The message text to send
+    /// * `chat_entry_type` - The type of chat entry (message type identifier from Steam API)
+    ///
+    /// # Returns
+    ///
+    /// The Steam API response containing message confirmation details.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the message sending fails, or a `ChatError::InvalidBBCode` when
+    /// the message uses a tag friend chats do not render (see `TagContext::FriendChat`).
+    #[instrument(
+        name = "kether.chat.send_friend_message",
+        skip(self, message),
+        fields(friend = %friend_steam_id.steam3(), chat_entry_type)
+    )]
+    pub async fn send_friend_message(
+        &self,
+        friend_steam_id: SteamID,
+        message: &str,
+        chat_entry_type: i32,
+    ) -> Result<CFriendMessages_SendMessage_Response, KetherError> {
+        Self::check_friend_message(message)?;
+        let req = Self::build_send_friend_message_request(
+            friend_steam_id,
+            message,
+            chat_entry_type,
+            false,
+        );
+
+        let response: CFriendMessages_SendMessage_Response = self
+            .observe_send(async { Ok(self.connection.service_method(req).await?) })
+            .await?;
+
+        debug!(
+            friend = %friend_steam_id.steam3(),
+            chat_entry_type,
+            "friend message dispatched"
+        );
+        Ok(response)
+    }
+
+    /// Run the local checks (length, friend chat tags) that reject a friend send before
+    /// any network call.
+    fn check_friend_message(message: &str) -> Result<(), ChatError> {
+        MessagePreprocessor::validate_length_within(message, FRIEND_CHAT_MESSAGE_LIMIT)?;
+        ChatError::check_bbcode(MessagePreprocessor::validate_for_context(
+            message,
+            TagContext::FriendChat,
+        ))
+    }
+
+    fn build_send_friend_message_request(
+        friend_steam_id: SteamID,
+        message: &str,
+        chat_entry_type: i32,
+        contains_bbcode: bool,
+    ) -> CFriendMessages_SendMessage_Request {
+        let mut req = CFriendMessages_SendMessage_Request::new();
+        req.set_steamid(friend_steam_id.into());
+        req.set_message(message.to_string());
+        req.set_chat_entry_type(chat_entry_type);
+        req.set_echo_to_sender(true);
+        if contains_bbcode {
+            req.set_contains_bbcode(true);
+        }
+        req
+    }
+
+    fn process_send_friend_message_response(
+        &self,
+        original_message: &str,
+        response: &CFriendMessages_SendMessage_Response,
+    ) -> PreprocessedMessage {
+        self.preprocessor.process(
+            original_message,
+            response.modified_message(),
+            response.server_timestamp(),
+            response.ordinal(),
+        )
+    }
+
+    /// Send a message to a friend with preprocessing.
+    ///
+    /// Like `send_friend_message`, but the text is prepared with
+    /// `MessagePreprocessor::normalize_outgoing` and the response is returned as a
+    /// `PreprocessedMessage`, matching what `send_group_message` returns for group chats.
+    ///
+    /// # Arguments
+    ///
+    /// * `friend_steam_id` - The Steam ID of the friend to send the message to
+    /// * `message` - The message text to send
+    /// * `chat_entry_type` - The type of chat entry (message type identifier from Steam API)
+    ///
+    /// # Returns
+    ///
+    /// A `PreprocessedMessage` with the server-modified text, parsed BBCode, mentions, and
+    /// the `server_timestamp`/`ordinal` assigned by Steam.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the message sending fails, or a `ChatError::InvalidBBCode` when
+    /// the message uses a tag friend chats do not render (see `TagContext::FriendChat`).
+    #[instrument(
+        name = "kether.chat.send_friend_message_preprocessed",
+        skip(self, message),
+        fields(friend = %friend_steam_id.steam3(), chat_entry_type)
+    )]
+    pub async fn send_friend_message_preprocessed(
+        &self,
+        friend_steam_id: SteamID,
+        message: &str,
+        chat_entry_type: i32,
+    ) -> Result<PreprocessedMessage, KetherError> {
+        let prepared_message = MessagePreprocessor::normalize_outgoing(message);
+        Self::check_friend_message(&prepared_message)?;
+        let req = Self::build_send_friend_message_request(
+            friend_steam_id,
+            &prepared_message,
+            chat_entry_type,
+            false,
+        );
+
+        let response: CFriendMessages_SendMessage_Response = self
+            .observe_send(async { Ok(self.connection.service_method(req).await?) })
+            .await?;
+
+        debug!(
+            friend = %friend_steam_id.steam3(),
+            chat_entry_type,
+            "friend message dispatched"
+        );
+        Ok(self.process_send_friend_message_response(message, &response))
+    }
+
+    /// Send a `/me` emote to a friend.
+    ///
+    /// The message is sent with the emote chat entry type and without the `/me` prefix,
+    /// which is how Steam encodes friend emotes on the wire.
+    ///
+    /// # Arguments
+    ///
+    /// * `friend_steam_id` - The Steam ID of the friend to send the emote to
+    /// * `text` - The action text (a leading `/me` is stripped)
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the message sending fails.
+    pub async fn send_friend_emote(
+        &self,
+        friend_steam_id: SteamID,
+        text: &str,
+    ) -> Result<CFriendMessages_SendMessage_Response, KetherError> {
+        let action = MessagePreprocessor::strip_emote_prefix(text).unwrap_or(text);
+        self.send_friend_message(friend_steam_id, action.trim(), CHAT_ENTRY_TYPE_EMOTE)
+            .await
+    }
+
+    /// Mark a group chat room as read up to `timestamp`.
+    ///
+    /// This clears the room's unread badge in other Steam clients and, when a
+    /// `ReadState` is installed, its unread count.
+    ///
+    /// # Arguments
+    ///
+    /// * `chat_group_id` - The unique identifier for the chat group
+    /// * `chat_id` - The unique identifier for the specific chat room within the group
+    /// * `timestamp` - Server timestamp of the newest message that has been read
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the acknowledgement request fails.
+    #[instrument(name = "kether.chat.ack_chat_message", skip(self))]
+    pub async fn ack_chat_message(
+        &self,
+        chat_group_id: u64,
+        chat_id: u64,
+        timestamp: u32,
+    ) -> Result<(), KetherError> {
+        send_chat_ack(
+            self.connection,
+            self.read_state.as_deref(),
+            chat_group_id,
+            chat_id,
+            timestamp,
+        )
+        .await?;
+        debug!(
+            chat_group_id,
+            chat_id, timestamp, "chat message acknowledged"
+        );
+        Ok(())
+    }
+
+    /// Mark a friend conversation as read up to `timestamp`.
+    ///
+    /// This clears the unread badge shown by other Steam clients (e.g. the mobile app)
+    /// for messages the bot has already processed.
+    ///
+    /// # Arguments
+    ///
+    /// * `friend_steam_id` - The Steam ID of the friend whose conversation is acknowledged
+    /// * `timestamp` - Server timestamp of the newest message that has been read
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the acknowledgement request fails.
+    #[instrument(
+        name = "kether.chat.ack_friend_message",
+        skip(self),
+        fields(friend = %friend_steam_id.steam3())
+    )]
+    pub async fn ack_friend_message(
+        &self,
+        friend_steam_id: SteamID,
+        timestamp: u32,
+    ) -> Result<(), KetherError> {
+        let mut req = CFriendMessages_AckMessage_Notification::new();
+        req.set_steamid_partner(friend_steam_id.into());
+        req.set_timestamp(timestamp);
+
+        // AckMessage is a notification: Steam never answers it, so waiting for a
+        // response would only time out.
+        self.connection.service_method_un_acked(req).await?;
+
+        debug!(friend = %friend_steam_id.steam3(), timestamp, "friend message acknowledged");
+        Ok(())
+    }
+
+    /// Fetch the most recent messages exchanged with a friend.
+    ///
+    /// # Arguments
+    ///
+    /// * `friend_steam_id` - The Steam ID of the friend
+    /// * `count` - Maximum number of messages to return
+    ///
+    /// # Returns
+    ///
+    /// Messages ordered oldest first. `steam_id` is the sender (the friend or this
+    /// account), and `unread` is set for friend messages newer than the conversation's
+    /// last view time.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the session or history request fails.
+    #[instrument(
+        name = "kether.chat.get_recent_friend_messages",
+        skip(self),
+        fields(friend = %friend_steam_id.steam3())
+    )]
+    pub async fn get_recent_friend_messages(
+        &self,
+        friend_steam_id: SteamID,
+        count: u32,
+    ) -> Result<Vec<FriendMessage>, KetherError> {
+        let last_view = self
+            .get_active_message_sessions()
+            .await?
+            .iter()
+            .find(|session| session.accountid_friend() == friend_steam_id.account_id())
+            .map(|session| session.last_view());
+
+        self.fetch_recent_friend_messages(friend_steam_id, count, last_view)
+            .await
+    }
+
+    /// Fetch unread messages from every friend conversation.
+    ///
+    /// Uses the active message sessions to find conversations with unread messages, then
+    /// fetches just those messages, so the bot can catch up on DMs received while offline.
+    ///
+    /// # Returns
+    ///
+    /// Unread friend messages, grouped per friend and ordered oldest first.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the session or history requests fail.
+    #[instrument(name = "kether.chat.get_unread_friend_messages", skip(self))]
+    pub async fn get_unread_friend_messages(&self) -> Result<Vec<FriendMessage>, KetherError> {
+        let mut unread = Vec::new();
+
+        for session in self.get_active_message_sessions().await? {
+            if session.unread_message_count() == 0 {
+                continue;
+            }
+
+            let friend_steam_id = SteamID::new(
+                session.accountid_friend(),
+                Instance::Desktop,
+                AccountType::Individual,
+                Universe::Public,
+            );
+            let messages = self
+                .fetch_recent_friend_messages(
+                    friend_steam_id,
+                    session.unread_message_count(),
+                    Some(session.last_view()),
+                )
+                .await?;
+            unread.extend(messages.into_iter().filter(|message| message.unread));
+        }
+
+        debug!(
+            unread_count = unread.len(),
+            "unread friend messages fetched"
+        );
+        Ok(unread)
+    }
+
+    async fn get_active_message_sessions(
+        &self,
+    ) -> Result<
+        Vec<cfriends_messages_get_active_message_sessions_response::FriendMessageSession>,
+        KetherError,
+    > {
+        let mut req = CFriendsMessages_GetActiveMessageSessions_Request::new();
+        req.set_only_sessions_with_messages(true);
+
+        let response: CFriendsMessages_GetActiveMessageSessions_Response =
+            self.connection.service_method(req).await?;
+        Ok(response.message_sessions)
+    }
+
+    async fn fetch_recent_friend_messages(
+        &self,
+        friend_steam_id: SteamID,
+        count: u32,
+        last_view: Option<u32>,
+    ) -> Result<Vec<FriendMessage>, KetherError> {
+        let mut req = CFriendMessages_GetRecentMessages_Request::new();
+        req.set_steamid1(u64::from(self.connection.steam_id()));
+        req.set_steamid2(friend_steam_id.into());
+        req.set_count(count);
+        req.set_most_recent_conversation(false);
+        req.set_bbcode_format(true);
+
+        let response: CFriendMessages_GetRecentMessages_Response =
+            self.connection.service_method(req).await?;
+
+        let mut messages: Vec<FriendMessage> = response
+            .messages
+            .iter()
+            .map(|message| FriendMessage::from_recent_message(message, friend_steam_id, last_view))
+            .collect();
+        messages.sort_by_key(|message| message.timestamp);
+
+        debug!(
+            friend = %friend_steam_id.steam3(),
+            message_count = messages.len(),
+            more_available = response.more_available(),
+            "recent friend messages fetched"
+        );
+        Ok(messages)
+    }
+
+    fn sticker_message(sticker_name: &str) -> Result<String, KetherError> {
+        preprocessing_helpers::create_sticker_message(sticker_name).ok_or_else(|| {
+            ChatError::InvalidInput(format!("Invalid sticker name: {:?}", sticker_name)).into()
+        })
+    }
+
+    /// Send a sticker to a group chat.
+    ///
+    /// # Arguments
+    ///
+    /// * `chat_group_id` - The unique identifier for the chat group
+    /// * `chat_id` - The unique identifier for the specific chat room
+    /// * `sticker_name` - The sticker identifier (validated with `is_valid_sticker_name`)
+    ///
+    /// # Returns
+    ///
+    /// A `PreprocessedMessage` whose parsed BBCode contains the `sticker` node.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the sticker name is invalid or the message sending fails.
+    #[instrument(
+        name = "kether.chat.send_sticker",
+        skip(self),
+        fields(chat_group_id, chat_id)
+    )]
+    pub async fn send_sticker(
+        &self,
+        chat_group_id: u64,
+        chat_id: u64,
+        sticker_name: &str,
+    ) -> Result<PreprocessedMessage, KetherError> {
+        let message = Self::sticker_message(sticker_name)?;
+        self.send_group_message(SendGroupMessageParams::new(chat_group_id, chat_id, message))
+            .await
+    }
+
+    /// Render a message template and send it to a group chat.
+    ///
+    /// # Arguments
+    ///
+    /// * `chat_group_id` - The unique identifier for the chat group
+    /// * `chat_id` - The unique identifier for the specific chat room
+    /// * `template` - A template validated by `MessageTemplate::new`
+    /// * `values` - Placeholder values; they are escaped so they cannot inject BBCode
+    ///
+    /// # Returns
+    ///
+    /// The `PreprocessedMessage` for the rendered message.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if rendering fails (missing value, too long) or the send fails.
+    #[instrument(
+        name = "kether.chat.send_template",
+        skip(self, template, values),
+        fields(chat_group_id, chat_id)
+    )]
+    pub async fn send_template(
+        &self,
+        chat_group_id: u64,
+        chat_id: u64,
+        template: &MessageTemplate,
+        values: &HashMap<&str, String>,
+    ) -> Result<PreprocessedMessage, KetherError> {
+        let params =
+            SendGroupMessageParams::from_template(chat_group_id, chat_id, template, values)?;
+        self.send_group_message(params).await
+    }
+
+    /// Send a sticker to a friend.
+    ///
+    /// The request is flagged as containing BBCode so the friend's client renders the sticker.
+    ///
+    /// # Arguments
+    ///
+    /// * `friend_steam_id` - The Steam ID of the friend to send the sticker to
+    /// * `sticker_name` - The sticker identifier (validated with `is_valid_sticker_name`)
+    ///
+    /// # Returns
+    ///
+    /// A `PreprocessedMessage` whose parsed BBCode contains the `sticker` node.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the sticker name is invalid or the message sending fails.
+    #[instrument(
+        name = "kether.chat.send_friend_sticker",
+        skip(self),
+        fields(friend = %friend_steam_id.steam3())
+    )]
+    pub async fn send_friend_sticker(
+        &self,
+        friend_steam_id: SteamID,
+        sticker_name: &str,
+    ) -> Result<PreprocessedMessage, KetherError> {
+        let message = Self::sticker_message(sticker_name)?;
+        let req = Self::build_send_friend_message_request(
+            friend_steam_id,
+            &message,
+            CHAT_ENTRY_TYPE_CHAT_MSG,
+            true,
+        );
+
+        let response: CFriendMessages_SendMessage_Response =
+            self.connection.service_method(req).await?;
+
+        Ok(self.process_send_friend_message_response(&message, &response))
+    }
+
+    fn ensure_valid_message_identifier(
+        server_timestamp: u32,
+        ordinal: u32,
+    ) -> Result<(), KetherError> {
+        if server_timestamp == 0 {
+            return Err(ChatError::InvalidInput(format!(
+                "Invalid message identifier for reaction: server_timestamp={} (must be non-zero). Ordinal={} is allowed to be 0.",
+                server_timestamp, ordinal
+            )).into());
+        }
+        Ok(())
+    }
+
+    fn build_update_message_reaction_request(
+        chat_group_id: u64,
+        chat_id: u64,
+        server_timestamp: u32,
+        ordinal: u32,
+        reaction_type: ReactionType,
+        reaction: &str,
+        is_add: bool,
+    ) -> Result<CChatRoom_UpdateMessageReaction_Request, KetherError> {
+        Self::ensure_valid_message_identifier(server_timestamp, ordinal)?;
+
+        let mut req = CChatRoom_UpdateMessageReaction_Request::new();
+        req.set_chat_group_id(chat_group_id);
+        req.set_chat_id(chat_id);
+        req.set_server_timestamp(server_timestamp);
+        req.set_ordinal(ordinal);
+        req.set_reaction_type(reaction_type.into_proto());
+        req.set_reaction(reaction.to_string());
+        req.set_is_add(is_add);
+        Ok(req)
+    }
+
+    /// Add a reaction to a group chat message.
+    #[instrument(
+        name = "kether.chat.add_message_reaction",
+        skip(self, reaction),
+        fields(chat_group_id, chat_id, server_timestamp, ordinal, reaction_type = ?reaction_type)
+    )]
+    pub async fn add_message_reaction(
+        &self,
+        chat_group_id: u64,
+        chat_id: u64,
+        server_timestamp: u32,
+        ordinal: u32,
+        reaction_type: ReactionType,
+        reaction: &str,
+    ) -> Result<u32, KetherError> {
+        let req = Self::build_update_message_reaction_request(
+            chat_group_id,
+            chat_id,
+            server_timestamp,
+            ordinal,
+            reaction_type,
+            reaction,
+            true,
+        )?;
+        let response: CChatRoom_UpdateMessageReaction_Response =
+            self.connection.service_method(req).await?;
+
+        debug!(
+            chat_group_id,
+            chat_id,
+            server_timestamp,
+            ordinal,
+            reaction_type = ?reaction_type,
+            num_reactors = response.num_reactors(),
+            "message reaction added"
+        );
+
+        Ok(response.num_reactors())
+    }
+
+    /// Remove a reaction from a group chat message.
+    #[instrument(
+        name = "kether.chat.remove_message_reaction",
+        skip(self, reaction),
+        fields(chat_group_id, chat_id, server_timestamp, ordinal, reaction_type = ?reaction_type)
+    )]
+    pub async fn remove_message_reaction(
+        &self,
+        chat_group_id: u64,
+        chat_id: u64,
+        server_timestamp: u32,
+        ordinal: u32,
+        reaction_type: ReactionType,
+        reaction: &str,
+    ) -> Result<u32, KetherError> {
+        let req = Self::build_update_message_reaction_request(
+            chat_group_id,
+            chat_id,
+            server_timestamp,
+            ordinal,
+            reaction_type,
+            reaction,
+            false,
+        )?;
+        let response: CChatRoom_UpdateMessageReaction_Response =
+            self.connection.service_method(req).await?;
+
+        debug!(
+            chat_group_id,
+            chat_id,
+            server_timestamp,
+            ordinal,
+            reaction_type = ?reaction_type,
+            num_reactors = response.num_reactors(),
+            "message reaction removed"
+        );
+
+        Ok(response.num_reactors())
+    }
+
+    /// List users who reacted with a specific reaction on a message.
+    #[instrument(
+        name = "kether.chat.get_message_reaction_reactors",
+        skip(self, reaction),
+        fields(chat_group_id, chat_id, server_timestamp, ordinal, reaction_type = ?reaction_type, limit = ?limit)
+    )]
+    #[allow(clippy::too_many_arguments)]
+    pub async fn get_message_reaction_reactors(
+        &self,
+        chat_group_id: u64,
+        chat_id: u64,
+        server_timestamp: u32,
+        ordinal: u32,
+        reaction_type: ReactionType,
+        reaction: &str,
+        limit: Option<u32>,
+    ) -> Result<Vec<SteamID>, KetherError> {
+        Self::ensure_valid_message_identifier(server_timestamp, ordinal)?;
+
+        let mut req = CChatRoom_GetMessageReactionReactors_Request::new();
+        req.set_chat_group_id(chat_group_id);
+        req.set_chat_id(chat_id);
+        req.set_server_timestamp(server_timestamp);
+        req.set_ordinal(ordinal);
+        req.set_reaction_type(reaction_type.into_proto());
+        req.set_reaction(reaction.to_string());
+        if let Some(limit) = limit {
+            req.set_limit(limit);
+        }
+
+        let response: CChatRoom_GetMessageReactionReactors_Response =
+            self.connection.service_method(req).await?;
+        let reactors: Vec<SteamID> = response
+            .reactors
+            .iter()
+            .map(|account_id| SteamID::from(*account_id as u64))
+            .collect();
+
+        debug!(
+            chat_group_id,
+            chat_id,
+            server_timestamp,
+            ordinal,
+            reaction_type = ?reaction_type,
+            reactor_count = reactors.len(),
+            "message reaction reactors fetched"
+        );
+
+        Ok(reactors)
+    }
+
+    /// Fetch message history for a chat room, including aggregated reaction summaries.
+    #[instrument(
+        name = "kether.chat.get_message_history",
+        skip(self),
+        fields(chat_group_id, chat_id, max_count = ?max_count)
+    )]
+    pub async fn get_message_history(
+        &self,
+        chat_group_id: u64,
+        chat_id: u64,
+        max_count: Option<u32>,
+    ) -> Result<Vec<ChatMessageHistoryEntry>, KetherError> {
+        let mut req = CChatRoom_GetMessageHistory_Request::new();
+        req.set_chat_group_id(chat_group_id);
+        req.set_chat_id(chat_id);
+        if let Some(max_count) = max_count {
+            req.set_max_count(max_count);
+        }
+
+        let response: CChatRoom_GetMessageHistory_Response =
+            self.connection.service_method(req).await?;
+        let history_entries: Vec<ChatMessageHistoryEntry> = response
+            .messages
+            .iter()
+            .map(ChatMessageHistoryEntry::from_proto)
+            .collect();
+
+        debug!(
+            chat_group_id,
+            chat_id,
+            message_count = history_entries.len(),
+            more_available = response.more_available(),
+            "chat message history fetched"
+        );
+
+        Ok(history_entries)
+    }
+
+    /// Delete one or more group chat messages.
+    ///
+    /// Messages are identified by their `server_timestamp` and `ordinal` values,
+    /// which are returned when sending messages via `send_group_message()`.
+    ///
+    /// # Arguments
+    ///
+    /// * `chat_group_id` - The unique identifier for the chat group
+    /// * `chat_id` - The unique identifier for the specific chat room within the group
+    /// * `messages` - A vector of (server_timestamp, ordinal) tuples identifying messages to delete
+    ///
+    /// # Returns
+    ///
+    /// The Steam API response confirming the deletion.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the deletion request fails. Note that attempting to delete
+    /// messages that have already been deleted or don't exist may not result in an error.
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// // After sending a message and receiving a PreprocessedMessage:
+    /// let preprocessed = client.send_group_message(params).await?;
+    /// if let (Some(ts), Some(ord)) = (preprocessed.server_timestamp, preprocessed.ordinal) {
+    ///     client.messaging().delete_group_messages(
+    ///         chat_group_id,
+    ///         chat_id,
+    ///         vec![(ts, ord)]
+    ///     ).await?;
+    /// }
+    /// ```
+    #[instrument(
+        name = "kether.chat.delete_group_messages",
+        skip(self, messages),
+        fields(chat_group_id, chat_id, message_count = messages.len())
+    )]
+    pub async fn delete_group_messages(
+        &self,
+        chat_group_id: u64,
+        chat_id: u64,
+        messages: Vec<(u32, u32)>,
+    ) -> Result<CChatRoom_DeleteChatMessages_Response, KetherError> {
+        if messages.is_empty() {
+            return Err(ChatError::InvalidInput(
+                "Cannot delete empty list of messages".to_string(),
+            )
+            .into());
+        }
+
+        let message_count = messages.len();
+
+        // Log what we're trying to delete for debugging
+        let timestamps: Vec<u32> = messages.iter().map(|(ts, _)| *ts).collect();
+        let ordinals: Vec<u32> = messages.iter().map(|(_, ord)| *ord).collect();
+        debug!(
+            chat_group_id,
+            chat_id,
+            message_count,
+            ?timestamps,
+            ?ordinals,
+            "Attempting to delete messages"
+        );
+
+        let mut req = CChatRoom_DeleteChatMessages_Request::new();
+        req.set_chat_group_id(chat_group_id);
+        req.set_chat_id(chat_id);
+
+        // Convert (server_timestamp, ordinal) tuples to Message structs
+        // According to DrMcKay's wiki, ordinal can be omitted if 0, but server_timestamp must be non-zero
+        for (server_timestamp, ordinal) in messages {
+            // Validate server_timestamp - it must be non-zero
+            if server_timestamp == 0 {
+                tracing::error!(
+                    server_timestamp,
+                    ordinal,
+                    "Cannot delete message: server_timestamp is zero (invalid)"
+                );
+                return Err(ChatError::InvalidInput(format!(
+                    "Invalid message identifier for deletion: server_timestamp={} (must be non-zero). Ordinal={} is allowed to be 0.",
+                    server_timestamp, ordinal
+                )).into());
+            }
+            // Ordinal can be 0 (it can be omitted in deletion requests per DrMcKay's wiki)
+
+            let mut msg = cchat_room_delete_chat_messages_request::Message::new();
+            msg.set_server_timestamp(server_timestamp);
+            msg.set_ordinal(ordinal);
+
+            // Verify both fields are actually set in the protobuf message
+            if !msg.has_server_timestamp() || !msg.has_ordinal() {
+                tracing::error!(
+                    server_timestamp,
+                    ordinal,
+                    "Message fields not properly set in protobuf for deletion"
+                );
+                return Err(ChatError::InvalidInput(format!(
+                    "Failed to set message fields in protobuf: server_timestamp={}, ordinal={}",
+                    server_timestamp, ordinal
+                ))
+                .into());
+            }
+
+            req.messages.push(msg);
+        }
+
+        let response: CChatRoom_DeleteChatMessages_Response =
+            self.connection.service_method(req).await?;
+
+        if let Some(metrics) = &self.metrics {
+            metrics.record_delete(message_count);
+        }
+        debug!(
+            chat_group_id,
+            chat_id, message_count, "group messages deleted"
+        );
+
+        Ok(response)
+    }
+
+    /// Delete group chat messages from `PreprocessedMessage` objects.
+    ///
+    /// This is a convenience method that extracts message identifiers from
+    /// `PreprocessedMessage` objects (returned by `send_group_message()`) and deletes them.
+    /// Any messages where `server_timestamp` or `ordinal` is `None` will be skipped with a warning.
+    ///
+    /// # Arguments
+    ///
+    /// * `chat_group_id` - The unique identifier for the chat group
+    /// * `chat_id` - The unique identifier for the specific chat room within the group
+    /// * `messages` - A vector of `PreprocessedMessage` objects to delete
+    ///
+    /// # Returns
+    ///
+    /// The Steam API response confirming the deletion, or an error if no valid messages
+    /// were found or the deletion request fails.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if no valid message identifiers are found (all messages had
+    /// missing `server_timestamp` or `ordinal`), if the messages list is empty, or if
+    /// the deletion request fails.
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// // Send a message and store it
+    /// let sent_message = client.send_group_message(params).await?;
+    ///
+    /// // Later, delete it
+    /// client.messaging().delete_group_messages_from_preprocessed(
+    ///     chat_group_id,
+    ///     chat_id,
+    ///     vec![sent_message]
+    /// ).await?;
+    /// ```
+    #[instrument(
+        name = "kether.chat.delete_group_messages_from_preprocessed",
+        skip(self, messages),
+        fields(chat_group_id, chat_id, input_message_count = messages.len())
+    )]
+    pub async fn delete_group_messages_from_preprocessed(
+        &self,
+        chat_group_id: u64,
+        chat_id: u64,
+        messages: Vec<PreprocessedMessage>,
+    ) -> Result<CChatRoom_DeleteChatMessages_Response, KetherError> {
+        // Extract valid (server_timestamp, ordinal) pairs, filtering out None values
+        let mut message_identifiers = Vec::new();
+        let mut skipped_count = 0;
+
+        for msg in &messages {
+            match (msg.server_timestamp, msg.ordinal) {
+                (Some(ts), Some(ord)) if ts > 0 => {
+                    // server_timestamp must be non-zero, but ordinal can be 0 (per DrMcKay's wiki)
+                    message_identifiers.push((ts, ord));
+                }
+                (Some(ts), Some(ord)) if ts == 0 => {
+                    skipped_count += 1;
+                    tracing::warn!(
+                        server_timestamp = ts,
+                        ordinal = ord,
+                        "Skipping message deletion: server_timestamp is zero (invalid)"
+                    );
+                }
+                (Some(ts), None) if ts > 0 => {
+                    // server_timestamp is valid, ordinal is None - use 0 (ordinal can be omitted if 0)
+                    message_identifiers.push((ts, 0));
+                }
+                _ => {
+                    skipped_count += 1;
+                    tracing::warn!("Skipping message deletion: missing server_timestamp");
+                }
+            }
+        }
+
+        if message_identifiers.is_empty() {
+            if skipped_count > 0 {
+                return Err(ChatError::InvalidInput(format!(
+                    "All {} message(s) had missing server_timestamp or ordinal",
+                    messages.len()
+                ))
+                .into());
+            } else {
+                return Err(ChatError::InvalidInput(
+                    "Cannot delete empty list of messages".to_string(),
+                )
+                .into());
+            }
+        }
+
+        if skipped_count > 0 {
+            tracing::warn!(
+                skipped_count,
+                total = messages.len(),
+                "Skipped messages with missing identifiers"
+            );
+        }
+
+        self.delete_group_messages(chat_group_id, chat_id, message_identifiers)
+            .await
+    }
+
+    /// Delete received group messages, e.g. ones collected from a message listener.
+    ///
+    /// Identifiers are taken from each message's `timestamp` and `ordinal`. Messages are
+    /// deleted in chunks of up to 50 per request. Messages received in a different room
+    /// than `chat_group_id`/`chat_id` are refused, since Steam silently ignores such
+    /// deletions.
+    ///
+    /// # Arguments
+    ///
+    /// * `chat_group_id` - The unique identifier for the chat group
+    /// * `chat_id` - The unique identifier for the specific chat room within the group
+    /// * `messages` - Messages received from `listen_for_group_messages`
+    ///
+    /// # Returns
+    ///
+    /// One `MessageDeletion` per input message, in input order.
+    #[instrument(
+        name = "kether.chat.delete_messages",
+        skip(self, messages),
+        fields(chat_group_id, chat_id, input_message_count = messages.len())
+    )]
+    pub async fn delete_messages(
+        &self,
+        chat_group_id: u64,
+        chat_id: u64,
+        messages: &[EnhancedGroupChatMessage],
+    ) -> Vec<MessageDeletion> {
+        let mut results: Vec<MessageDeletion> = messages
+            .iter()
+            .map(|message| MessageDeletion {
+                server_timestamp: message.timestamp,
+                ordinal: message.ordinal,
+                outcome: Self::precheck_deletion(chat_group_id, chat_id, message),
+            })
+            .collect();
+
+        let deletable: Vec<usize> = results
+            .iter()
+            .enumerate()
+            .filter(|(_, result)| result.outcome == MessageDeletionOutcome::Deleted)
+            .map(|(index, _)| index)
+            .collect();
+
+        for chunk in deletable.chunks(DELETE_MESSAGES_CHUNK_SIZE) {
+            let identifiers = chunk
+                .iter()
+                .map(|&index| (results[index].server_timestamp, results[index].ordinal))
+                .collect();
+
+            if let Err(err) = self
+                .delete_group_messages(chat_group_id, chat_id, identifiers)
+                .await
+            {
+                tracing::warn!(
+                    chat_group_id,
+                    chat_id,
+                    chunk_len = chunk.len(),
+                    error = %err,
+                    "Deletion chunk failed"
+                );
+                for &index in chunk {
+                    results[index].outcome = MessageDeletionOutcome::Failed(err.to_string());
+                }
+            }
+        }
+
+        results
+    }
+
+    fn precheck_deletion(
+        chat_group_id: u64,
+        chat_id: u64,
+        message: &EnhancedGroupChatMessage,
+    ) -> MessageDeletionOutcome {
+        if message.chat_group_id != chat_group_id || message.chat_id != chat_id {
+            MessageDeletionOutcome::WrongRoom {
+                chat_group_id: message.chat_group_id,
+                chat_id: message.chat_id,
+            }
+        } else if message.timestamp == 0 {
+            MessageDeletionOutcome::InvalidIdentifier
+        } else {
+            MessageDeletionOutcome::Deleted
+        }
+    }
+
+    /// Replace a previously sent group message by deleting it and sending new text.
+    ///
+    /// Steam has no message editing, so this deletes `original` (identified by its
+    /// `server_timestamp` and `ordinal`) and then sends `new_text` to the same room.
+    /// If Steam reports that the original no longer exists, the replacement is still
+    /// sent and the result records `OriginalDeletion::AlreadyGone`.
+    ///
+    /// # Arguments
+    ///
+    /// * `chat_group_id` - The unique identifier for the chat group
+    /// * `chat_id` - The unique identifier for the specific chat room within the group
+    /// * `original` - The message to replace, as returned by `send_group_message()`
+    /// * `new_text` - The replacement message text
+    ///
+    /// # Returns
+    ///
+    /// A `ReplacedMessage` holding the replacement's `PreprocessedMessage` (with its new
+    /// identifiers) and what happened to the original.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if `original` has no `server_timestamp`, if the deletion fails for
+    /// a reason other than the message being gone, or if sending the replacement fails.
+    #[instrument(
+        name = "kether.chat.replace_message",
+        skip(self, original, new_text),
+        fields(chat_group_id, chat_id)
+    )]
+    pub async fn replace_message(
+        &self,
+        chat_group_id: u64,
+        chat_id: u64,
+        original: &PreprocessedMessage,
+        new_text: &str,
+    ) -> Result<ReplacedMessage, KetherError> {
+        let server_timestamp = match original.server_timestamp {
+            Some(ts) if ts > 0 => ts,
+            _ => {
+                return Err(ChatError::InvalidInput(
+                    "Cannot replace message: original has no server_timestamp".to_string(),
+                )
+                .into());
+            }
+        };
+        let ordinal = original.ordinal.unwrap_or(0);
+
+        let original_deletion = match self
+            .delete_group_messages(chat_group_id, chat_id, vec![(server_timestamp, ordinal)])
+            .await
+        {
+            Ok(_) => OriginalDeletion::Deleted,
+            Err(err) if Self::is_missing_message_error(&err) => {
+                tracing::warn!(
+                    server_timestamp,
+                    ordinal,
+                    error = %err,
+                    "Original message already gone; sending replacement anyway"
+                );
+                OriginalDeletion::AlreadyGone
+            }
+            Err(err) => return Err(err),
+        };
+
+        let replacement = self
+            .send_group_message(SendGroupMessageParams::new(
+                chat_group_id,
+                chat_id,
+                new_text,
+            ))
+            .await?;
+
+        debug!(
+            chat_group_id,
+            chat_id,
+            original_deletion = ?original_deletion,
+            "group message replaced"
+        );
+
+        Ok(ReplacedMessage {
+            replacement,
+            original_deletion,
+        })
+    }
+
+    fn is_missing_message_error(err: &KetherError) -> bool {
+        matches!(
+            err.network_error(),
+            Some(steam_vent::NetworkError::ApiError(
+                EResult::NoMatch | EResult::FileNotFound | EResult::InvalidState
+            ))
+        )
+    }
+}
+
+impl<'a> OrderedMessaging<'a> {
+    /// Send a message to a group chat after earlier ordered sends to the same room.
+    ///
+    /// See `ChatRoomMessaging::send_group_message`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the message sending fails.
+    pub async fn send_group_message(
+        &self,
+        params: impl Into<SendGroupMessageParams>,
+    ) -> Result<PreprocessedMessage, KetherError> {
+        self.send_group_message_ref(&params.into()).await
+    }
+
+    /// Borrowing variant of `send_group_message`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the message sending fails.
+    #[instrument(
+        name = "kether.chat.send_group_message_ordered",
+        skip(self, params),
+        fields(chat_group_id = params.chat_group_id, chat_id = params.chat_id)
+    )]
+    pub async fn send_group_message_ref(
+        &self,
+        params: &SendGroupMessageParams,
+    ) -> Result<PreprocessedMessage, KetherError> {
+        let _turn = self
+            .messaging
+            .send_order
+            .lock(params.chat_group_id, params.chat_id)
+            .await;
+        self.messaging.send_group_message_ref(params).await
+    }
+
+    /// Send a sticker to a group chat after earlier ordered sends to the same room.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the sticker name is invalid or the message sending fails.
+    pub async fn send_sticker(
+        &self,
+        chat_group_id: u64,
+        chat_id: u64,
+        sticker_name: &str,
+    ) -> Result<PreprocessedMessage, KetherError> {
+        let message = ChatRoomMessaging::sticker_message(sticker_name)?;
+        self.send_group_message((chat_group_id, chat_id, message.as_str()))
+            .await
+    }
+
+    /// Render a template and send it after earlier ordered sends to the same room.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if rendering fails (missing value, too long) or the send fails.
+    pub async fn send_template(
+        &self,
+        chat_group_id: u64,
+        chat_id: u64,
+        template: &MessageTemplate,
+        values: &HashMap<&str, String>,
+    ) -> Result<PreprocessedMessage, KetherError> {
+        let params =
+            SendGroupMessageParams::from_template(chat_group_id, chat_id, template, values)?;
+        self.send_group_message(params).await
+    }
+}
+
+impl<'a> ChatRoomNotifications<'a> {
+    /// Override the notification options for listeners started from this handle.
+    ///
+    /// # Arguments
+    ///
+    /// * `options` - Throttle and error backoff for the notification streams
+    ///
+    /// # Returns
+    ///
+    /// `Self` for method chaining (builder pattern).
+    pub fn with_options(mut self, options: NotificationOptions) -> Self {
+        self.options = options;
+        self
+    }
+
+    /// Listen for incoming group chat messages with preprocessing and error handling.
+    ///
+    /// Messages are automatically preprocessed to extract BBCode and mentions.
+    /// The callback can return an error to stop the listener, or `Ok(())` to continue.
+    ///
+    /// # Arguments
+    ///
+    /// * `callback` - A closure that processes each incoming message and returns a `CallbackResult`
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the notification stream fails or the callback returns an error.
+    pub async fn listen_for_group_messages_with<F>(
+        &self,
+        callback: F,
+    ) -> Result<(), NotificationDispatchError>
+    where
+        F: FnMut(EnhancedGroupChatMessage) -> CallbackResult + Send + 'static,
+    {
+        self.listen_for_group_messages_until(std::future::pending(), callback)
+            .await
+    }
+
+    /// Listen for incoming group chat messages together with the raw notification.
+    ///
+    /// For consumers that need protobuf fields `EnhancedGroupChatMessage` does not carry
+    /// (mention lists, server message info, and similar) without opening a second
+    /// subscription on the connection. The callback can return an error to stop the
+    /// listener, or `Ok(())` to continue.
+    ///
+    /// # Arguments
+    ///
+    /// * `callback` - A closure that processes each parsed message and its notification and returns a `CallbackResult`
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the notification stream fails or the callback returns an error.
+    pub async fn listen_for_group_messages_raw_with<F>(
+        &self,
+        callback: F,
+    ) -> Result<(), NotificationDispatchError>
+    where
+        F: FnMut(
+                EnhancedGroupChatMessage,
+                &CChatRoom_IncomingChatMessage_Notification,
+            ) -> CallbackResult
+            + Send
+            + 'static,
+    {
+        let mut user_callback = callback;
+        let preprocessor = self.preprocessor.clone();
+        let callback = self.acknowledged(
+            |notification: &CChatRoom_IncomingChatMessage_Notification| {
+                (
+                    notification.chat_group_id(),
+                    notification.chat_id(),
+                    notification.timestamp(),
+                )
+            },
+            move |notification| {
+                let message =
+                    EnhancedGroupChatMessage::from_notification_with(&notification, &preprocessor);
+                user_callback(message, &notification)
+            },
+        );
+        self.group_stream().for_each(callback).await
+    }
+
+    /// Listen for incoming group chat messages together with the raw notification.
+    ///
+    /// This is a convenience wrapper that ignores callback errors.
+    ///
+    /// # Arguments
+    ///
+    /// * `callback` - A closure that will be called for each parsed message and its notification
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the notification stream fails.
+    pub async fn listen_for_group_messages_raw<F>(
+        &self,
+        mut callback: F,
+    ) -> Result<(), NotificationDispatchError>
+    where
+        F: FnMut(EnhancedGroupChatMessage, &CChatRoom_IncomingChatMessage_Notification)
+            + Send
+            + 'static,
+    {
+        self.listen_for_group_messages_raw_with(move |message, notification| {
+            callback(message, notification);
+            Ok(())
+        })
+        .await
+    }
+
+    /// Listen for group chat messages that mention the listening account.
+    ///
+    /// Messages that don't match `targets` are dropped before the callback runs. Use
+    /// `MentionTargets::new()` to match `@all`, `@here` and mentions of this connection's
+    /// own SteamID.
+    ///
+    /// # Arguments
+    ///
+    /// * `targets` - The mentions to listen for (see `MentionTargets`)
+    /// * `callback` - A closure that processes each matching message and returns a `CallbackResult`
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the notification stream fails or the callback returns an error.
+    pub async fn listen_for_mentions_with<F>(
+        &self,
+        targets: MentionTargets,
+        callback: F,
+    ) -> Result<(), NotificationDispatchError>
+    where
+        F: FnMut(EnhancedGroupChatMessage) -> CallbackResult + Send + 'static,
+    {
+        let own_steam_id = SteamID::from(u64::from(self.connection.steam_id()));
+        let preprocessor = self.preprocessor.clone();
+        self.group_stream()
+            .filter_map_items(move |notification| {
+                let message =
+                    EnhancedGroupChatMessage::from_notification_with(&notification, &preprocessor);
+                message.mentions(&targets, own_steam_id).then_some(message)
+            })
+            .for_each(self.acknowledged(ack_position, callback))
+            .await
+    }
+
+    /// Listen for group chat messages that mention the listening account.
+    ///
+    /// This is a convenience wrapper that ignores callback errors.
+    ///
+    /// # Arguments
+    ///
+    /// * `targets` - The mentions to listen for (see `MentionTargets`)
+    /// * `callback` - A closure that will be called for each matching message
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the notification stream fails.
+    pub async fn listen_for_mentions<F>(
+        &self,
+        targets: MentionTargets,
+        mut callback: F,
+    ) -> Result<(), NotificationDispatchError>
+    where
+        F: FnMut(EnhancedGroupChatMessage) + Send + 'static,
+    {
+        self.listen_for_mentions_with(targets, move |message| {
+            callback(message);
+            Ok(())
+        })
+        .await
+    }
+
+    /// Listen for incoming group chat messages from selected rooms.
+    ///
+    /// The filter is applied to the raw notification, before
+    /// `EnhancedGroupChatMessage::from_notification`, so messages from other rooms skip
+    /// preprocessing entirely.
+    ///
+    /// # Arguments
+    ///
+    /// * `filter` - The rooms to listen to (see `RoomFilter`)
+    /// * `callback` - A closure that processes each incoming message and returns a `CallbackResult`
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the notification stream fails or the callback returns an error.
+    pub async fn listen_for_group_messages_filtered<F>(
+        &self,
+        filter: RoomFilter,
+        callback: F,
+    ) -> Result<(), NotificationDispatchError>
+    where
+        F: FnMut(EnhancedGroupChatMessage) -> CallbackResult + Send + 'static,
+    {
+        let preprocessor = self.preprocessor.clone();
+        self.group_stream()
+            .filter_items(move |notification| {
+                filter.matches(notification.chat_group_id(), notification.chat_id())
+            })
+            .map_items(move |notification| {
+                EnhancedGroupChatMessage::from_notification_with(&notification, &preprocessor)
+            })
+            .for_each(self.acknowledged(ack_position, callback))
+            .await
+    }
+
+    /// Listen for incoming group chat messages, resubscribing after stream errors.
+    ///
+    /// Unlike `listen_for_group_messages_with`, a notification stream error does not end
+    /// the listener: it subscribes again via `on_notification` after the delay given by
+    /// `policy`, which replaces `NotificationOptions::error_backoff`, logging a warning and
+    /// reporting `record_resubscribe` to the metrics recorder for every attempt. It gives up
+    /// once `policy.max_attempts` consecutive resubscriptions have failed or when
+    /// `classify_network_error` deems the error `Fatal`. Callback errors still stop the
+    /// listener immediately.
+    ///
+    /// # Arguments
+    ///
+    /// * `policy` - Backoff and attempt limit for resubscriptions
+    /// * `callback` - A closure that processes each incoming message and returns a `CallbackResult`
+    ///
+    /// # Errors
+    ///
+    /// Returns the last stream error once resubscribing is given up, or the callback's error.
+    pub async fn listen_for_group_messages_resilient<F>(
+        &self,
+        policy: ResubscribePolicy,
+        callback: F,
+    ) -> Result<(), NotificationDispatchError>
+    where
+        F: FnMut(EnhancedGroupChatMessage) -> CallbackResult + Send + 'static,
+    {
+        self.group_messages_until(std::future::pending(), Some(policy), None, callback)
+            .await
+    }
+
+    /// Listen for incoming group chat messages until `shutdown` resolves.
+    ///
+    /// Shutdown is graceful: once `shutdown` completes no further notifications are
+    /// pulled from the stream, a callback that is already running finishes, and the
+    /// listener returns `Ok(())`. Any future works as the signal, e.g.
+    /// `tokio_util::sync::CancellationToken::cancelled()` or a `oneshot` receiver.
+    ///
+    /// # Arguments
+    ///
+    /// * `shutdown` - Future that resolves when the listener should stop
+    /// * `callback` - A closure that processes each incoming message and returns a `CallbackResult`
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the notification stream fails or the callback returns an error.
+    pub async fn listen_for_group_messages_until<S, F>(
+        &self,
+        shutdown: S,
+        callback: F,
+    ) -> Result<(), NotificationDispatchError>
+    where
+        S: Future<Output = ()>,
+        F: FnMut(EnhancedGroupChatMessage) -> CallbackResult + Send + 'static,
+    {
+        let preprocessor = self.preprocessor.clone();
+        self.group_stream()
+            .map_items(move |notification| {
+                EnhancedGroupChatMessage::from_notification_with(&notification, &preprocessor)
+            })
+            .for_each_until(shutdown, self.acknowledged(ack_position, callback))
+            .await
+    }
+
+    /// Listen for incoming group chat messages with preprocessing.
+    ///
+    /// Messages are automatically preprocessed to extract BBCode and mentions.
+    /// This is a convenience wrapper that ignores callback errors.
+    ///
+    /// # Arguments
+    ///
+    /// * `callback` - A closure that will be called for each incoming message
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the notification stream fails.
+    pub async fn listen_for_group_messages<F>(
+        &self,
+        mut callback: F,
+    ) -> Result<(), NotificationDispatchError>
+    where
+        F: FnMut(EnhancedGroupChatMessage) + Send + 'static,
+    {
+        self.listen_for_group_messages_with(move |message| {
+            callback(message);
+            Ok(())
+        })
+        .await
+    }
+
+    /// Listen for incoming reaction events with error handling.
+    ///
+    /// The callback can return an error to stop the listener, or `Ok(())` to continue.
+    pub async fn listen_for_reactions_with<F>(
+        &self,
+        callback: F,
+    ) -> Result<(), NotificationDispatchError>
+    where
+        F: FnMut(ReactionEvent) -> CallbackResult + Send + 'static,
+    {
+        let mut user_callback = callback;
+        self.reaction_stream()
+            .for_each(
+                move |notification| match ReactionEvent::from_notification(&notification) {
+                    Some(event) => user_callback(event),
+                    None => {
+                        tracing::warn!(
+                            chat_group_id = notification.chat_group_id(),
+                            chat_id = notification.chat_id(),
+                            server_timestamp = notification.server_timestamp(),
+                            ordinal = notification.ordinal(),
+                            reaction_type = ?notification.reaction_type(),
+                            "Skipping unsupported reaction notification type"
+                        );
+                        Ok(())
+                    }
+                },
+            )
+            .await
+    }
+
+    /// Listen for incoming reaction events.
+    ///
+    /// This is a convenience wrapper that ignores callback errors.
+    pub async fn listen_for_reactions<F>(
+        &self,
+        mut callback: F,
+    ) -> Result<(), NotificationDispatchError>
+    where
+        F: FnMut(ReactionEvent) + Send + 'static,
+    {
+        self.listen_for_reactions_with(move |event| {
+            callback(event);
+            Ok(())
+        })
+        .await
+    }
+
+    /// Listen for group membership changes with error handling.
+    ///
+    /// Joins, leaves, kicks, bans, and invites are delivered as `MemberChangeEvent`s;
+    /// other state changes (rank, roles, mutes) are skipped.
+    /// The callback can return an error to stop the listener, or `Ok(())` to continue.
+    ///
+    /// # Arguments
+    ///
+    /// * `callback` - A closure that processes each membership change and returns a `CallbackResult`
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the notification stream fails or the callback returns an error.
+    pub async fn listen_for_member_changes_with<F>(
+        &self,
+        callback: F,
+    ) -> Result<(), NotificationDispatchError>
+    where
+        F: FnMut(MemberChangeEvent) -> CallbackResult + Send + 'static,
+    {
+        let mut user_callback = callback;
+        self.member_stream()
+            .for_each(move |notification| {
+                match MemberChangeEvent::from_notification(&notification) {
+                    Some(event) => user_callback(event),
+                    None => {
+                        debug!(
+                            chat_group_id = notification.chat_group_id(),
+                            change = ?notification.change(),
+                            "Skipping unsupported member state change"
+                        );
+                        Ok(())
+                    }
+                }
+            })
+            .await
+    }
+
+    /// Listen for group membership changes.
+    ///
+    /// This is a convenience wrapper that ignores callback errors.
+    ///
+    /// # Arguments
+    ///
+    /// * `callback` - A closure that will be called for each membership change
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the notification stream fails.
+    pub async fn listen_for_member_changes<F>(
+        &self,
+        mut callback: F,
+    ) -> Result<(), NotificationDispatchError>
+    where
+        F: FnMut(MemberChangeEvent) + Send + 'static,
+    {
+        self.listen_for_member_changes_with(move |event| {
+            callback(event);
+            Ok(())
+        })
+        .await
+    }
+
+    /// Listen for message modifications and deletions with error handling.
+    ///
+    /// Use `MessageModifiedEvent::affected` to find the locally stored messages that
+    /// need to be redacted or updated.
+    /// The callback can return an error to stop the listener, or `Ok(())` to continue.
+    ///
+    /// # Arguments
+    ///
+    /// * `callback` - A closure that processes each modification event and returns a `CallbackResult`
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the notification stream fails or the callback returns an error.
+    pub async fn listen_for_message_modifications_with<F>(
+        &self,
+        callback: F,
+    ) -> Result<(), NotificationDispatchError>
+    where
+        F: FnMut(MessageModifiedEvent) -> CallbackResult + Send + 'static,
+    {
+        self.modification_stream()
+            .map_items(|notification| MessageModifiedEvent::from_notification(&notification))
+            .for_each(callback)
+            .await
+    }
+
+    /// Listen for message modifications and deletions.
+    ///
+    /// This is a convenience wrapper that ignores callback errors.
+    ///
+    /// # Arguments
+    ///
+    /// * `callback` - A closure that will be called for each modification event
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the notification stream fails.
+    pub async fn listen_for_message_modifications<F>(
+        &self,
+        mut callback: F,
+    ) -> Result<(), NotificationDispatchError>
+    where
+        F: FnMut(MessageModifiedEvent) + Send + 'static,
+    {
+        self.listen_for_message_modifications_with(move |event| {
+            callback(event);
+            Ok(())
+        })
+        .await
+    }
+
+    /// Listen for invitations to join chat groups with error handling.
+    ///
+    /// Invites are decoded from the current user's group state notifications; other
+    /// state changes for the user are skipped. Accept an invite by passing its
+    /// `chat_group_id`, `default_chat_id`, and `invite_code` to `join_chat_room`.
+    /// The callback can return an error to stop the listener, or `Ok(())` to continue.
+    ///
+    /// # Arguments
+    ///
+    /// * `callback` - A closure that processes each invite and returns a `CallbackResult`
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the notification stream fails or the callback returns an error.
+    pub async fn listen_for_group_invites_with<F>(
+        &self,
+        callback: F,
+    ) -> Result<(), NotificationDispatchError>
+    where
+        F: FnMut(GroupInvite) -> CallbackResult + Send + 'static,
+    {
+        self.user_state_stream()
+            .filter_map_items(|notification| GroupInvite::from_notification(&notification))
+            .for_each(callback)
+            .await
+    }
+
+    /// Listen for invitations to join chat groups.
+    ///
+    /// This is a convenience wrapper that ignores callback errors.
+    ///
+    /// # Arguments
+    ///
+    /// * `callback` - A closure that will be called for each invite
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the notification stream fails.
+    pub async fn listen_for_group_invites<F>(
+        &self,
+        mut callback: F,
+    ) -> Result<(), NotificationDispatchError>
+    where
+        F: FnMut(GroupInvite) + Send + 'static,
+    {
+        self.listen_for_group_invites_with(move |invite| {
+            callback(invite);
+            Ok(())
+        })
+        .await
+    }
+
+    /// Listen for changes to group names, taglines, avatars, and room lists with error handling.
+    ///
+    /// Header-state and room-list notifications are merged and diffed against the last
+    /// state this listener saw for the group; notifications that change none of the
+    /// tracked fields (for example role updates) are skipped.
+    /// The callback can return an error to stop the listener, or `Ok(())` to continue.
+    ///
+    /// # Arguments
+    ///
+    /// * `callback` - A closure that processes each header change and returns a `CallbackResult`
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the notification stream fails or the callback returns an error.
+    pub async fn listen_for_group_header_changes_with<F>(
+        &self,
+        callback: F,
+    ) -> Result<(), NotificationDispatchError>
+    where
+        F: FnMut(GroupHeaderChanged) -> CallbackResult + Send + 'static,
+    {
+        self.header_change_stream().for_each(callback).await
+    }
+
+    /// Listen for changes to group names, taglines, avatars, and room lists.
+    ///
+    /// This is a convenience wrapper that ignores callback errors.
+    ///
+    /// # Arguments
+    ///
+    /// * `callback` - A closure that will be called for each header change
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the notification stream fails.
+    pub async fn listen_for_group_header_changes<F>(
+        &self,
+        mut callback: F,
+    ) -> Result<(), NotificationDispatchError>
+    where
+        F: FnMut(GroupHeaderChanged) + Send + 'static,
+    {
+        self.listen_for_group_header_changes_with(move |event| {
+            callback(event);
+            Ok(())
+        })
+        .await
+    }
+
+    /// Stream every supported real-time event as a `ChatEvent`.
+    ///
+    /// Group messages, reactions, membership changes, message modifications, group
+    /// invites, and group header changes are merged into one stream in arrival order.
+    /// Unsupported reaction types and member state changes are skipped.
+    /// Transport errors are yielded as `ChatError::Network` items and do not end the
+    /// stream.
+    pub fn event_stream(
+        &self,
+    ) -> impl Stream<Item = Result<ChatEvent, ChatError>> + Send + use<'a> {
+        let preprocessor = self.preprocessor.clone();
+        let messages = self
+            .group_stream()
+            .map_items(move |notification| {
+                ChatEvent::GroupMessage(EnhancedGroupChatMessage::from_notification_with(
+                    &notification,
+                    &preprocessor,
+                ))
+            })
+            .into_stream();
+        let reactions = self
+            .reaction_stream()
+            .filter_map_items(|notification| {
+                ReactionEvent::from_notification(&notification).map(ChatEvent::Reaction)
+            })
+            .into_stream();
+        let members = self
+            .member_stream()
+            .filter_map_items(|notification| {
+                MemberChangeEvent::from_notification(&notification).map(ChatEvent::MemberChange)
+            })
+            .into_stream();
+        let modifications = self
+            .modification_stream()
+            .map_items(|notification| {
+                ChatEvent::MessageModified(MessageModifiedEvent::from_notification(&notification))
+            })
+            .into_stream();
+        let invites = self
+            .user_state_stream()
+            .filter_map_items(|notification| {
+                GroupInvite::from_notification(&notification).map(ChatEvent::GroupInvite)
+            })
+            .into_stream();
+        let headers = self
+            .header_change_stream()
+            .map_items(ChatEvent::GroupHeaderChanged)
+            .into_stream();
+
+        futures_util::stream::select_all([
+            FuturesStreamExt::boxed(messages),
+            FuturesStreamExt::boxed(reactions),
+            FuturesStreamExt::boxed(members),
+            FuturesStreamExt::boxed(modifications),
+            FuturesStreamExt::boxed(invites),
+            FuturesStreamExt::boxed(headers),
+        ])
+    }
+
+    /// Listen for incoming friend messages with error handling.
+    ///
+    /// The callback can return an error to stop the listener, or `Ok(())` to continue.
+    ///
+    /// # Arguments
     ///
-    /// # Example
+    /// * `callback` - A closure that processes each incoming message and returns a `CallbackResult`
     ///
-    /// ```no_run
-    /// // Send a message and store it
-    /// let sent_message = client.send_group_message(params).await?;
+    /// # Errors
     ///
-    /// // Later, delete it
-    /// client.messaging().delete_group_messages_from_preprocessed(
-    ///     chat_group_id,
-    ///     chat_id,
-    ///     vec![sent_message]
-    /// ).await?;
-    /// ```
-    #[instrument(
-        name = "kether.chat.delete_group_messages_from_preprocessed",
-        skip(self, messages),
-        fields(chat_group_id, chat_id, input_message_count = messages.len())
-    )]
-    pub async fn delete_group_messages_from_preprocessed(
+    /// Returns an error if the notification stream fails or the callback returns an error.
+    pub async fn listen_for_friend_messages_with<F>(
         &self,
-        chat_group_id: u64,
-        chat_id: u64,
-        messages: Vec<PreprocessedMessage>,
-    ) -> Result<CChatRoom_DeleteChatMessages_Response, Box<dyn Error>> {
-        // Extract valid (server_timestamp, ordinal) pairs, filtering out None values
-        let mut message_identifiers = Vec::new();
-        let mut skipped_count = 0;
-
-        for msg in &messages {
-            match (msg.server_timestamp, msg.ordinal) {
-                (Some(ts), Some(ord)) if ts > 0 => {
-                    // server_timestamp must be non-zero, but ordinal can be 0 (per DrMcKay's wiki)
-                    message_identifiers.push((ts, ord));
-                }
-                (Some(ts), Some(ord)) if ts == 0 => {
-                    skipped_count += 1;
-                    tracing::warn!(
-                        server_timestamp = ts,
-                        ordinal = ord,
-                        "Skipping message deletion: server_timestamp is zero (invalid)"
-                    );
-                }
-                (Some(ts), None) if ts > 0 => {
-                    // server_timestamp is valid, ordinal is None - use 0 (ordinal can be omitted if 0)
-                    message_identifiers.push((ts, 0));
-                }
-                _ => {
-                    skipped_count += 1;
-                    tracing::warn!("Skipping message deletion: missing server_timestamp");
-                }
-            }
-        }
-
-        if message_identifiers.is_empty() {
-            if skipped_count > 0 {
-                return Err(format!(
-                    "All {} message(s) had missing server_timestamp or ordinal",
-                    messages.len()
-                )
-                .into());
-            } else {
-                return Err("Cannot delete empty list of messages".into());
-            }
-        }
-
-        if skipped_count > 0 {
-            tracing::warn!(
-                skipped_count,
-                total = messages.len(),
-                "Skipped messages with missing identifiers"
-            );
-        }
-
-        self.delete_group_messages(chat_group_id, chat_id, message_identifiers)
+        callback: F,
+    ) -> Result<(), NotificationDispatchError>
+    where
+        F: FnMut(FriendMessage) -> CallbackResult + Send + 'static,
+    {
+        self.listen_for_friend_messages_until(std::future::pending(), callback)
             .await
     }
-}
 
-impl<'a> ChatRoomNotifications<'a> {
-    /// Listen for incoming group chat messages with preprocessing and error handling.
+    /// Listen for incoming friend messages until `shutdown` resolves.
     ///
-    /// Messages are automatically preprocessed to extract BBCode and mentions.
-    /// The callback can return an error to stop the listener, or `Ok(())` to continue.
+    /// See `listen_for_group_messages_until` for the shutdown semantics.
     ///
     /// # Arguments
     ///
+    /// * `shutdown` - Future that resolves when the listener should stop
     /// * `callback` - A closure that processes each incoming message and returns a `CallbackResult`
     ///
     /// # Errors
     ///
     /// Returns an error if the notification stream fails or the callback returns an error.
-    pub async fn listen_for_group_messages_with<F>(&self, callback: F) -> Result<(), Box<dyn Error>>
+    pub async fn listen_for_friend_messages_until<S, F>(
+        &self,
+        shutdown: S,
+        callback: F,
+    ) -> Result<(), NotificationDispatchError>
     where
-        F: FnMut(EnhancedGroupChatMessage) -> CallbackResult + Send + 'static,
+        S: Future<Output = ()>,
+        F: FnMut(FriendMessage) -> CallbackResult + Send + 'static,
     {
-        let mut user_callback = callback;
-        self.group_stream()
-            .for_each(move |notification| {
-                let enhanced_message = EnhancedGroupChatMessage::from_notification(&notification);
-                user_callback(enhanced_message)
-            })
+        self.friend_stream()
+            .map_items(|notification| FriendMessage::from_notification(&notification))
+            .for_each_until(shutdown, callback)
             .await
-            .map_err(|err| -> Box<dyn Error> { Box::new(err) })
     }
 
-    /// Listen for incoming group chat messages with preprocessing.
+    /// Listen for incoming friend messages.
     ///
-    /// Messages are automatically preprocessed to extract BBCode and mentions.
-    /// This is a convenience wrapper that ignores callback errors.
+    /// This is a convenience wrapper that ignores callback errors. Every notification is
+    /// delivered, including typing notices with an empty body; use
+    /// `listen_for_friend_activity` to receive them decoded by entry type.
     ///
     /// # Arguments
     ///
-    /// * `callback` - A closure that will be called for each incoming message
+    /// * `callback` - A closure that will be called for each incoming friend message
     ///
     /// # Errors
     ///
     /// Returns an error if the notification stream fails.
-    pub async fn listen_for_group_messages<F>(&self, mut callback: F) -> Result<(), Box<dyn Error>>
+    pub async fn listen_for_friend_messages<F>(
+        &self,
+        mut callback: F,
+    ) -> Result<(), NotificationDispatchError>
     where
-        F: FnMut(EnhancedGroupChatMessage) + Send + 'static,
+        F: FnMut(FriendMessage) + Send + 'static,
     {
-        self.listen_for_group_messages_with(move |message| {
+        self.listen_for_friend_messages_with(move |message| {
             callback(message);
             Ok(())
         })
         .await
     }
 
-    /// Listen for incoming reaction events with error handling.
+    /// Listen for friend activity with error handling.
     ///
+    /// Friend message notifications are decoded by their `ChatEntryType`: typing notices
+    /// become `FriendActivity::Typing`, chat messages and emotes `FriendActivity::Message`,
+    /// and game invites `FriendActivity::InviteGame`.
     /// The callback can return an error to stop the listener, or `Ok(())` to continue.
-    pub async fn listen_for_reactions_with<F>(&self, callback: F) -> Result<(), Box<dyn Error>>
+    ///
+    /// # Arguments
+    ///
+    /// * `callback` - A closure that processes each activity and returns a `CallbackResult`
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the notification stream fails or the callback returns an error.
+    pub async fn listen_for_friend_activity_with<F>(
+        &self,
+        callback: F,
+    ) -> Result<(), NotificationDispatchError>
     where
-        F: FnMut(ReactionEvent) -> CallbackResult + Send + 'static,
+        F: FnMut(FriendActivity) -> CallbackResult + Send + 'static,
     {
-        let mut user_callback = callback;
-        self.reaction_stream()
-            .for_each(
-                move |notification| match ReactionEvent::from_notification(&notification) {
-                    Some(event) => user_callback(event),
-                    None => {
-                        tracing::warn!(
-                            chat_group_id = notification.chat_group_id(),
-                            chat_id = notification.chat_id(),
-                            server_timestamp = notification.server_timestamp(),
-                            ordinal = notification.ordinal(),
-                            reaction_type = ?notification.reaction_type(),
-                            "Skipping unsupported reaction notification type"
-                        );
-                        Ok(())
-                    }
-                },
-            )
+        self.friend_stream()
+            .map_items(|notification| {
+                FriendActivity::from_message(FriendMessage::from_notification(&notification))
+            })
+            .for_each(callback)
             .await
-            .map_err(|err| -> Box<dyn Error> { Box::new(err) })
     }
 
-    /// Listen for incoming reaction events.
+    /// Listen for friend activity.
     ///
     /// This is a convenience wrapper that ignores callback errors.
-    pub async fn listen_for_reactions<F>(&self, mut callback: F) -> Result<(), Box<dyn Error>>
+    ///
+    /// # Arguments
+    ///
+    /// * `callback` - A closure that will be called for each activity
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the notification stream fails.
+    pub async fn listen_for_friend_activity<F>(
+        &self,
+        mut callback: F,
+    ) -> Result<(), NotificationDispatchError>
     where
-        F: FnMut(ReactionEvent) + Send + 'static,
+        F: FnMut(FriendActivity) + Send + 'static,
     {
-        self.listen_for_reactions_with(move |event| {
-            callback(event);
+        self.listen_for_friend_activity_with(move |activity| {
+            callback(activity);
             Ok(())
         })
         .await
     }
 
-    /// Listen for incoming friend messages with error handling.
+    /// Listen for incoming friend messages with preprocessing and error handling.
     ///
-    /// The callback can return an error to stop the listener, or `Ok(())` to continue.
+    /// Messages are preprocessed the same way as group messages, extracting BBCode and
+    /// mentions. The callback can return an error to stop the listener, or `Ok(())` to
+    /// continue.
     ///
     /// # Arguments
     ///
@@ -1661,29 +6935,23 @@ impl<'a> ChatRoomNotifications<'a> {
     /// # Errors
     ///
     /// Returns an error if the notification stream fails or the callback returns an error.
-    pub async fn listen_for_friend_messages_with<F>(
+    pub async fn listen_for_friend_messages_enhanced_with<F>(
         &self,
         callback: F,
-    ) -> Result<(), Box<dyn Error>>
+    ) -> Result<(), NotificationDispatchError>
     where
-        F: FnMut(FriendMessage) -> CallbackResult + Send + 'static,
+        F: FnMut(EnhancedFriendMessage) -> CallbackResult + Send + 'static,
     {
-        let mut user_callback = callback;
+        let preprocessor = self.preprocessor.clone();
         self.friend_stream()
-            .for_each(move |notification| {
-                let friend_message = FriendMessage {
-                    steam_id: SteamID::from(notification.steamid_friend()),
-                    message: notification.message().to_string(),
-                    timestamp: notification.rtime32_server_timestamp(),
-                    chat_entry_type: notification.chat_entry_type(),
-                };
-                user_callback(friend_message)
+            .map_items(move |notification| {
+                EnhancedFriendMessage::from_notification_with(&notification, &preprocessor)
             })
+            .for_each(callback)
             .await
-            .map_err(|err| -> Box<dyn Error> { Box::new(err) })
     }
 
-    /// Listen for incoming friend messages.
+    /// Listen for incoming friend messages with preprocessing.
     ///
     /// This is a convenience wrapper that ignores callback errors.
     ///
@@ -1694,41 +6962,386 @@ impl<'a> ChatRoomNotifications<'a> {
     /// # Errors
     ///
     /// Returns an error if the notification stream fails.
-    pub async fn listen_for_friend_messages<F>(&self, mut callback: F) -> Result<(), Box<dyn Error>>
+    pub async fn listen_for_friend_messages_enhanced<F>(
+        &self,
+        mut callback: F,
+    ) -> Result<(), NotificationDispatchError>
     where
-        F: FnMut(FriendMessage) + Send + 'static,
+        F: FnMut(EnhancedFriendMessage) + Send + 'static,
     {
-        self.listen_for_friend_messages_with(move |message| {
+        self.listen_for_friend_messages_enhanced_with(move |message| {
             callback(message);
             Ok(())
         })
         .await
     }
 
-    fn group_stream(&self) -> NotificationStream<'_, CChatRoom_IncomingChatMessage_Notification> {
-        let stream = self
-            .connection
-            .on_notification::<CChatRoom_IncomingChatMessage_Notification>()
-            .throttle(Duration::from_millis(25));
-        NotificationStream::new(stream, Duration::from_millis(250))
+    /// Listen for incoming group chat messages through a middleware chain.
+    ///
+    /// Every message passes through `middleware` in order before reaching `callback`;
+    /// see `MessageMiddleware` for how layers pass messages on or short-circuit. Each
+    /// message is handled completely before the next one is received.
+    ///
+    /// # Arguments
+    ///
+    /// * `middleware` - The layers to apply, outermost first
+    /// * `callback` - The terminal closure that processes each message passed through the chain
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the notification stream fails or a layer or the callback
+    /// returns an error.
+    pub async fn listen_for_group_messages_through<F>(
+        &self,
+        middleware: Vec<Arc<dyn MessageMiddleware>>,
+        callback: F,
+    ) -> Result<(), NotificationDispatchError>
+    where
+        F: FnMut(EnhancedGroupChatMessage) -> CallbackResult + Send + 'static,
+    {
+        let mut callback = self.acknowledged(ack_position, callback);
+        let mut messages = self.group_message_stream();
+        while let Some(next) = StreamExt::next(&mut messages).await {
+            let message = next.map_err(NotificationDispatchError::from_stream)?;
+            Next::new(&middleware, &mut callback)
+                .run(message)
+                .await
+                .map_err(|source| NotificationDispatchError::Callback { source })?;
+        }
+        Ok(())
+    }
+
+    /// Stream incoming group chat messages with preprocessing.
+    ///
+    /// This is the pull-based counterpart of `listen_for_group_messages`: the stream
+    /// borrows the connection instead of requiring a `'static` callback, so it can be
+    /// polled with `.next().await` or combined with other streams and `select!`.
+    /// Transport errors are yielded as `ChatError::Network` items and do not end the
+    /// stream; the caller decides whether to keep polling. Use
+    /// `GroupMessageStream::filter_rooms` to restrict it to specific rooms.
+    pub fn group_message_stream(&self) -> GroupMessageStream<'a> {
+        GroupMessageStream {
+            notifications: self.observed_group_stream().throttled(),
+            filter: None,
+            mentions: None,
+            own_steam_id: u64::from(self.connection.steam_id()),
+            ignore_own: self.options.ignore_own_messages,
+            preprocessor: self.preprocessor.clone(),
+        }
+    }
+
+    /// Stream incoming friend messages.
+    ///
+    /// The pull-based counterpart of `listen_for_friend_messages`; see
+    /// `group_message_stream` for how errors are reported.
+    pub fn friend_message_stream(
+        &self,
+    ) -> impl Stream<Item = Result<FriendMessage, ChatError>> + Send + use<'a> {
+        self.friend_stream()
+            .map_items(|notification| FriendMessage::from_notification(&notification))
+            .into_stream()
+    }
+
+    /// Group messages for listeners, without our own messages unless configured otherwise.
+    fn group_stream(&self) -> NotificationStream<'a, CChatRoom_IncomingChatMessage_Notification> {
+        let stream = self.observed_group_stream();
+        if !self.options.ignore_own_messages {
+            return stream;
+        }
+
+        let own_steam_id = u64::from(self.connection.steam_id());
+        stream.filter_items(move |notification| notification.steamid_sender() != own_steam_id)
+    }
+
+    /// Every group message, recorded in the read state if one is installed.
+    fn observed_group_stream(
+        &self,
+    ) -> NotificationStream<'a, CChatRoom_IncomingChatMessage_Notification> {
+        let stream = self.subscribe::<CChatRoom_IncomingChatMessage_Notification>();
+        let Some(read_state) = self.read_state.clone() else {
+            return stream;
+        };
+
+        let own_steam_id = u64::from(self.connection.steam_id());
+        stream.map_items(move |notification| {
+            if notification.steamid_sender() != own_steam_id {
+                read_state.observe(
+                    notification.chat_group_id(),
+                    notification.chat_id(),
+                    MessagePosition {
+                        timestamp: notification.timestamp(),
+                        ordinal: notification.ordinal(),
+                    },
+                );
+            }
+            notification
+        })
     }
 
+    /// Friend messages for listeners, without echoes of our own unless configured otherwise.
     fn friend_stream(
         &self,
-    ) -> NotificationStream<'_, CFriendMessages_IncomingMessage_Notification> {
-        let stream = self
-            .connection
-            .on_notification::<CFriendMessages_IncomingMessage_Notification>()
-            .throttle(Duration::from_millis(25));
-        NotificationStream::new(stream, Duration::from_millis(250))
+    ) -> NotificationStream<'a, CFriendMessages_IncomingMessage_Notification> {
+        let stream = self.subscribe::<CFriendMessages_IncomingMessage_Notification>();
+        if !self.options.ignore_own_messages {
+            return stream;
+        }
+        stream.filter_items(|notification| !notification.local_echo())
+    }
+
+    fn reaction_stream(&self) -> NotificationStream<'a, CChatRoom_MessageReaction_Notification> {
+        self.subscribe()
+    }
+
+    fn member_stream(&self) -> NotificationStream<'a, CChatRoom_MemberStateChange_Notification> {
+        self.subscribe()
+    }
+
+    fn modification_stream(
+        &self,
+    ) -> NotificationStream<'a, CChatRoom_ChatMessageModified_Notification> {
+        self.subscribe()
+    }
+
+    fn user_state_stream(
+        &self,
+    ) -> NotificationStream<'a, ChatRoomClient_NotifyChatGroupUserStateChanged_Notification> {
+        self.subscribe()
+    }
+
+    /// Header-state and room-list notifications diffed through one shared tracker.
+    fn header_change_stream(&self) -> NotificationStream<'a, GroupHeaderChanged> {
+        let tracker = Arc::new(Mutex::new(GroupStateTracker::default()));
+        let room_tracker = tracker.clone();
+
+        let headers = self
+            .subscribe::<CChatRoom_ChatRoomHeaderState_Notification>()
+            .filter_map_items(move |notification| {
+                tracker
+                    .lock()
+                    .unwrap_or_else(|poisoned| poisoned.into_inner())
+                    .apply_header(&notification)
+            });
+        let rooms = self
+            .subscribe::<CChatRoom_ChatRoomGroupRoomsChange_Notification>()
+            .filter_map_items(move |notification| {
+                room_tracker
+                    .lock()
+                    .unwrap_or_else(|poisoned| poisoned.into_inner())
+                    .apply_rooms(&notification)
+            });
+        headers.merge(rooms)
+    }
+
+    /// Group message listener behind `spawn_group_listener` and the resilient listener.
+    async fn group_messages_until<S, F>(
+        &self,
+        shutdown: S,
+        restart: Option<ResubscribePolicy>,
+        stats: Option<&ListenerCounters>,
+        callback: F,
+    ) -> Result<(), NotificationDispatchError>
+    where
+        S: Future<Output = ()>,
+        F: FnMut(EnhancedGroupChatMessage) -> CallbackResult + Send,
+    {
+        let mut subscribed = false;
+        self.run_until(
+            || {
+                if subscribed && let Some(stats) = stats {
+                    stats.record_reconnect();
+                }
+                subscribed = true;
+                let preprocessor = self.preprocessor.clone();
+                self.group_stream().map_items(move |notification| {
+                    EnhancedGroupChatMessage::from_notification_with(&notification, &preprocessor)
+                })
+            },
+            shutdown,
+            restart,
+            self.observed(
+                stats,
+                |message: &EnhancedGroupChatMessage| message.timestamp,
+                self.acknowledged(ack_position, callback),
+            ),
+        )
+        .await
+    }
+
+    /// Friend message listener behind `spawn_friend_listener`.
+    async fn friend_messages_until<S, F>(
+        &self,
+        shutdown: S,
+        restart: Option<ResubscribePolicy>,
+        stats: Option<&ListenerCounters>,
+        callback: F,
+    ) -> Result<(), NotificationDispatchError>
+    where
+        S: Future<Output = ()>,
+        F: FnMut(FriendMessage) -> CallbackResult + Send,
+    {
+        let mut subscribed = false;
+        self.run_until(
+            || {
+                if subscribed && let Some(stats) = stats {
+                    stats.record_reconnect();
+                }
+                subscribed = true;
+                self.friend_stream()
+                    .map_items(|notification| FriendMessage::from_notification(&notification))
+            },
+            shutdown,
+            restart,
+            self.observed(stats, |message: &FriendMessage| message.timestamp, callback),
+        )
+        .await
+    }
+
+    /// Wrap `callback` to acknowledge the room and timestamp `position` gives for every
+    /// item it handled, when `NotificationOptions::auto_ack` is set.
+    fn acknowledged<'s, T, F>(
+        &self,
+        position: fn(&T) -> (u64, u64, u32),
+        mut callback: F,
+    ) -> impl FnMut(T) -> CallbackResult + Send + 's
+    where
+        T: 's,
+        F: FnMut(T) -> CallbackResult + Send + 's,
+    {
+        let acks = self
+            .options
+            .auto_ack
+            .then(|| AutoAck::start(self.connection.clone(), self.read_state.clone()));
+        move |item| {
+            let handled = position(&item);
+            callback(item)?;
+            if let Some(acks) = &acks {
+                acks.handled(handled);
+            }
+            Ok(())
+        }
+    }
+
+    /// Wrap `callback` to report dispatch lag and callback outcomes to `stats` and the
+    /// metrics recorder.
+    fn observed<'s, T, F>(
+        &self,
+        stats: Option<&'s ListenerCounters>,
+        sent_at: fn(&T) -> u32,
+        mut callback: F,
+    ) -> impl FnMut(T) -> CallbackResult + Send + 's
+    where
+        T: 's,
+        F: FnMut(T) -> CallbackResult + Send + 's,
+    {
+        let metrics = self.metrics.clone();
+        move |item| {
+            let sent_at = UNIX_EPOCH + Duration::from_secs(u64::from(sent_at(&item)));
+            let lag = SystemTime::now()
+                .duration_since(sent_at)
+                .unwrap_or_default();
+            if let Some(stats) = stats {
+                stats.record_notification(lag);
+            }
+            if let Some(metrics) = &metrics {
+                metrics.record_dispatch_lag(lag);
+            }
+
+            let result = callback(item);
+            if let Some(stats) = stats {
+                stats.record_callback(result.is_ok());
+            }
+            if let Some(metrics) = &metrics {
+                metrics.record_callback(result.is_ok());
+            }
+            result
+        }
+    }
+
+    /// Dispatch notifications until `shutdown`, resubscribing after stream errors when
+    /// `restart` is set.
+    ///
+    /// The attempt count resets once a notification has been delivered. Fatal errors,
+    /// callback errors, and exhausting `max_attempts` end the listener.
+    async fn run_until<U, M, S, F>(
+        &self,
+        mut subscribe: M,
+        shutdown: S,
+        restart: Option<ResubscribePolicy>,
+        mut callback: F,
+    ) -> Result<(), NotificationDispatchError>
+    where
+        U: Send + 'static,
+        M: FnMut() -> NotificationStream<'a, U>,
+        S: Future<Output = ()>,
+        F: FnMut(U) -> CallbackResult + Send,
+    {
+        let mut shutdown = std::pin::pin!(shutdown);
+        let mut attempt = 0;
+        loop {
+            let mut delivered = false;
+            let mut stream = subscribe();
+            if restart.is_some() {
+                // The resubscribe delay below replaces the listener's own error backoff.
+                stream = stream.without_error_backoff();
+            }
+            let result = stream
+                .for_each_until(shutdown.as_mut(), |item| {
+                    delivered = true;
+                    callback(item)
+                })
+                .await;
+
+            let err = match result {
+                Err(NotificationDispatchError::Stream(err)) => err,
+                other => return other,
+            };
+            let Some(policy) = restart else {
+                return Err(NotificationDispatchError::Stream(err));
+            };
+            if delivered {
+                attempt = 0;
+            }
+            attempt += 1;
+            let disposition = classify_network_error(&err).disposition;
+            if disposition == RetryDisposition::Fatal || attempt > policy.max_attempts {
+                return Err(NotificationDispatchError::Stream(err));
+            }
+
+            let delay = policy.delay_for(attempt);
+            tracing::warn!(
+                attempt,
+                ?delay,
+                error = %err,
+                "notification stream failed; resubscribing"
+            );
+            if let Some(metrics) = &self.metrics {
+                metrics.record_resubscribe();
+            }
+            tokio::select! {
+                biased;
+                _ = shutdown.as_mut() => return Ok(()),
+                _ = sleep(delay) => {}
+            }
+        }
     }
 
-    fn reaction_stream(&self) -> NotificationStream<'_, CChatRoom_MessageReaction_Notification> {
-        let stream = self
-            .connection
-            .on_notification::<CChatRoom_MessageReaction_Notification>()
-            .throttle(Duration::from_millis(25));
-        NotificationStream::new(stream, Duration::from_millis(250))
+    fn subscribe<T>(&self) -> NotificationStream<'a, T>
+    where
+        T: ServiceMethodRequest + Send + 'static,
+    {
+        let activity = self.activity.clone();
+        let notifications =
+            FuturesStreamExt::inspect(self.connection.on_notification::<T>(), move |item| {
+                if item.is_ok() {
+                    activity.touch();
+                }
+            });
+        let stream = NotificationStream::new(notifications, self.options, self.metrics.clone());
+        match &self.watchdog {
+            Some(watchdog) => stream.with_watchdog(watchdog.clone(), self.connection),
+            None => stream,
+        }
     }
 }
 
@@ -1747,17 +7360,81 @@ pub mod helpers {
     }
 
     /// Parse a Steam ID from string
-    pub fn parse_steam_id(steam_id_str: &str) -> Result<SteamID, Box<dyn Error>> {
-        Ok(SteamID::try_from(steam_id_str)?)
+    pub fn parse_steam_id(steam_id_str: &str) -> Result<SteamID, KetherError> {
+        SteamID::try_from(steam_id_str).map_err(KetherError::other)
+    }
+
+    /// Marks where `MentionPlacement::Inline` puts the mentions.
+    pub const MENTIONS_PLACEHOLDER: &str = "{mentions}";
+
+    /// Where `create_message_with_mentions` puts the mentions.
+    #[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+    pub enum MentionPlacement {
+        /// Before the message text.
+        Prefix,
+        /// After the message text.
+        #[default]
+        Suffix,
+        /// In place of each `{mentions}` in the message, or after it when it has none.
+        Inline,
     }
 
-    /// Create a message with mentions
-    pub fn create_message_with_mentions(message: &str, steam_ids: &[SteamID]) -> String {
-        let mut result = message.to_string();
-        for steam_id in steam_ids {
-            result.push_str(&format!(" @{}", steam_id.steam3()));
+    /// Create a message that pings `steam_ids`.
+    ///
+    /// Accounts found in `names` are mentioned with a `[mention]` tag showing their
+    /// persona name, as the Steam client does, and the rest as `@[U:1:xxxxx]`, which
+    /// sends turn into a ping (see `SendGroupMessageParams::with_server_mentions`).
+    /// Each account is mentioned once, in the given order, so extracting the mentions
+    /// of the result gives exactly `steam_ids` when `message` mentions nobody itself.
+    ///
+    /// # Arguments
+    ///
+    /// * `message` - The message text
+    /// * `steam_ids` - The accounts to ping
+    /// * `placement` - Where the mentions go
+    /// * `names` - Personas to take display names from, e.g. from
+    ///   `ChatRoomClient::resolve_mentions`; may be empty
+    pub fn create_message_with_mentions(
+        message: &str,
+        steam_ids: &[SteamID],
+        placement: MentionPlacement,
+        names: &[ResolvedMention],
+    ) -> String {
+        let mut mentioned = Vec::with_capacity(steam_ids.len());
+        for &steam_id in steam_ids {
+            if mentioned.contains(&steam_id) {
+                continue;
+            }
+            mentioned.push(steam_id);
+        }
+        let mentions = mentioned
+            .into_iter()
+            .map(|steam_id| {
+                match names
+                    .iter()
+                    .find(|resolved| resolved.found && resolved.steam_id == steam_id)
+                {
+                    Some(resolved) => {
+                        preprocessing_helpers::create_mention_tag(steam_id, &resolved.persona_name)
+                    }
+                    None => preprocessing_helpers::create_mention(steam_id),
+                }
+            })
+            .collect::<Vec<_>>()
+            .join(" ");
+
+        let around = |first: &str, second: &str| match (first.is_empty(), second.is_empty()) {
+            (true, _) => second.to_string(),
+            (_, true) => first.to_string(),
+            _ => format!("{first} {second}"),
+        };
+        match placement {
+            MentionPlacement::Prefix => around(&mentions, message),
+            MentionPlacement::Inline if message.contains(MENTIONS_PLACEHOLDER) => {
+                message.replace(MENTIONS_PLACEHOLDER, &mentions)
+            }
+            MentionPlacement::Suffix | MentionPlacement::Inline => around(message, &mentions),
         }
-        result
     }
 
     /// Create a message with @all mention
@@ -1828,10 +7505,1104 @@ mod tests {
         assert_eq!(formatted, steam_id_str);
     }
 
+    /// The request `params` are sent as with the default client settings.
+    fn send_request(params: &SendGroupMessageParams) -> CChatRoom_SendChatMessage_Request {
+        ChatRoomMessaging::build_send_message_request(
+            params,
+            &MessagingDefaults::default(),
+            MessagePreprocessor::shared(),
+        )
+    }
+
+    #[test]
+    fn test_template_values_stay_escaped_when_sent() {
+        let samples = HashMap::from([("title", "Weekly Cup".to_string())]);
+        let template = MessageTemplate::new("{title} starts soon", &samples).unwrap();
+        let values = HashMap::from([(
+            "title",
+            "[url=https://evil.example]free skins[/url] @all".to_string(),
+        )]);
+
+        let params = SendGroupMessageParams::from_template(1, 2, &template, &values)
+            .unwrap()
+            .with_server_mentions(true);
+        let req = send_request(&params);
+        assert!(req.message().contains(r"\[url=https://evil.example\]"));
+        assert!(!req.message().replace(r"\[", "").contains("[url="));
+        assert!(!req.message().contains("[mention"));
+    }
+
+    #[test]
+    fn test_emote_params_prefix_message_once() {
+        let params = SendGroupMessageParams::new(1, 2, "waves").with_emote(true);
+        let req = send_request(&params);
+        assert_eq!(req.message(), "/me waves");
+
+        let params = SendGroupMessageParams::new(1, 2, "/me waves").with_emote(true);
+        let req = send_request(&params);
+        assert_eq!(req.message(), "/me waves");
+    }
+
+    #[test]
+    fn test_send_params_from_tuples() {
+        let params = SendGroupMessageParams::from((1, 2, "!sub"));
+        assert_eq!(params.message, "!sub");
+        assert!(!params.echo_to_sender);
+
+        let params = SendGroupMessageParams::from((1, 2, "!sub".to_string(), true));
+        assert_eq!((params.chat_group_id, params.chat_id), (1, 2));
+        assert!(params.echo_to_sender);
+        assert!(params.link_previews);
+    }
+
+    #[test]
+    fn test_enhanced_friend_message_from_notification() {
+        let mut notification = CFriendMessages_IncomingMessage_Notification::new();
+        notification.set_steamid_friend(76561199491325083);
+        notification.set_chat_entry_type(CHAT_ENTRY_TYPE_CHAT_MSG);
+        notification.set_message("hey @[U:1:1531059355] [spoiler]gg[/spoiler]".to_string());
+        notification.set_rtime32_server_timestamp(1_700_000_000);
+        notification.set_ordinal(2);
+
+        let message = EnhancedFriendMessage::from_notification(&notification);
+        assert_eq!(u64::from(message.steam_id), 76561199491325083);
+        assert_eq!(message.timestamp, 1_700_000_000);
+        assert_eq!(message.ordinal, 2);
+        assert_eq!(message.preprocessed.original_message, message.message);
+        assert!(message.preprocessed.mentions.is_some());
+        assert_eq!(
+            message.preprocessed.message_bbcode_parsed,
+            MessagePreprocessor::parse_bbcode(&message.message)
+        );
+    }
+
+    #[test]
+    fn test_friend_message_mentions() {
+        use crate::preprocessing::MentionSteamId;
+
+        let invitee = SteamID::from(76561199491325083);
+        let other = SteamID::from(76561198012345678);
+        let mut notification = CFriendMessages_IncomingMessage_Notification::new();
+        notification.set_steamid_friend(76561197960287930);
+        notification.set_message(
+            "@all invite [U:1:1531059355] and [mention=76561198012345678]@Jane[/mention]"
+                .to_string(),
+        );
+
+        let message = EnhancedFriendMessage::from_notification(&notification);
+        let mentions = message.mentions().unwrap();
+        assert!(mentions.mention_all);
+        assert!(!mentions.mention_here);
+        assert_eq!(
+            mentions.mention_steamids,
+            vec![MentionSteamId(invitee), MentionSteamId(other)]
+        );
+
+        notification.set_message("no pings here".to_string());
+        let message = EnhancedFriendMessage::from_notification(&notification);
+        assert_eq!(message.mentions(), None);
+    }
+
+    #[test]
+    fn test_friend_message_reports_echo_and_ordinal() {
+        let mut notification = CFriendMessages_IncomingMessage_Notification::new();
+        notification.set_steamid_friend(76561199491325083);
+        notification.set_ordinal(3);
+        notification.set_local_echo(true);
+
+        let message = FriendMessage::from_notification(&notification);
+        assert!(message.is_echo);
+        assert!(!message.unread);
+        assert_eq!(message.ordinal, 3);
+        assert_eq!(message.is_limited_account, None);
+
+        notification.set_local_echo(false);
+        notification.set_from_limited_account(true);
+        let message = FriendMessage::from_notification(&notification);
+        assert!(!message.is_echo);
+        assert_eq!(message.is_limited_account, Some(true));
+    }
+
+    #[test]
+    fn test_message_no_bbcode_becomes_plain_text() {
+        let mut notification = CFriendMessages_IncomingMessage_Notification::new();
+        notification.set_message("[spoiler]hi[/spoiler] there".to_string());
+        notification.set_message_no_bbcode("hi there (server)".to_string());
+        notification.set_low_priority(true);
+
+        let message = EnhancedFriendMessage::from_notification(&notification);
+        assert!(message.low_priority);
+        assert_eq!(
+            message.message_no_bbcode.as_deref(),
+            Some("hi there (server)")
+        );
+        assert_eq!(message.preprocessed.plain_text, "hi there (server)");
+
+        let mut group = CChatRoom_IncomingChatMessage_Notification::new();
+        group.set_message("[spoiler]hi[/spoiler] there".to_string());
+        let message = EnhancedGroupChatMessage::from_notification(&group);
+        assert_eq!(message.message_no_bbcode, None);
+        assert_eq!(message.preprocessed.plain_text, "hi there");
+
+        group.set_message_no_bbcode("hi there".to_string());
+        let message = EnhancedGroupChatMessage::from_notification(&group);
+        assert_eq!(message.message_no_bbcode.as_deref(), Some("hi there"));
+    }
+
+    #[test]
+    fn test_recent_friend_message_unread_flag() {
+        let friend = SteamID::try_from("[U:1:1531059355]").unwrap();
+        let mut message = cfriend_messages_get_recent_messages_response::FriendMessage::new();
+        message.set_accountid(friend.account_id());
+        message.set_timestamp(200);
+        message.set_message("you there?".to_string());
+
+        let parsed = FriendMessage::from_recent_message(&message, friend, Some(100));
+        assert_eq!(parsed.steam_id.account_id(), friend.account_id());
+        assert!(parsed.unread);
+        assert!(!FriendMessage::from_recent_message(&message, friend, Some(200)).unread);
+        assert!(!FriendMessage::from_recent_message(&message, friend, None).unread);
+
+        message.set_accountid(42);
+        let own = FriendMessage::from_recent_message(&message, friend, Some(100));
+        assert!(!own.unread);
+        assert!(own.is_echo);
+    }
+
+    #[test]
+    fn test_delete_precheck_refuses_other_rooms() {
+        let mut notification = CChatRoom_IncomingChatMessage_Notification::new();
+        notification.set_chat_group_id(1);
+        notification.set_chat_id(2);
+        notification.set_timestamp(1_700_000_000);
+        notification.set_message("spam".to_string());
+        let message = EnhancedGroupChatMessage::from_notification(&notification);
+
+        assert_eq!(
+            ChatRoomMessaging::precheck_deletion(1, 2, &message),
+            MessageDeletionOutcome::Deleted
+        );
+        assert_eq!(
+            ChatRoomMessaging::precheck_deletion(1, 3, &message),
+            MessageDeletionOutcome::WrongRoom {
+                chat_group_id: 1,
+                chat_id: 2
+            }
+        );
+
+        notification.set_timestamp(0);
+        let message = EnhancedGroupChatMessage::from_notification(&notification);
+        assert_eq!(
+            ChatRoomMessaging::precheck_deletion(1, 2, &message),
+            MessageDeletionOutcome::InvalidIdentifier
+        );
+    }
+
+    #[test]
+    fn test_friend_sends_reject_group_only_tags() {
+        let message = "[roomeffect type=\"confetti\"][/roomeffect]";
+        let err = ChatRoomMessaging::check_friend_message(message).unwrap_err();
+        assert!(matches!(err, ChatError::InvalidBBCode { position: 0, .. }));
+        assert!(ChatRoomMessaging::check_friend_message("[wip] [spoiler]x[/spoiler]").is_ok());
+    }
+
+    #[test]
+    fn test_strict_bbcode_rejects_before_sending() {
+        let params = SendGroupMessageParams::new(1, 2, "[sub]hi[/sub]");
+        let check = |params: &SendGroupMessageParams| {
+            ChatRoomMessaging::checked_send_request(
+                params,
+                &MessagingDefaults::default(),
+                MessagePreprocessor::shared(),
+            )
+        };
+        assert!(check(&params).is_ok());
+
+        let err = check(&params.with_strict_bbcode(true)).unwrap_err();
+        let ChatError::InvalidBBCode {
+            position,
+            diagnostics,
+            ..
+        } = err
+        else {
+            panic!("expected ChatError::InvalidBBCode");
+        };
+        assert_eq!(position, 0);
+        assert_eq!(
+            diagnostics
+                .iter()
+                .map(|diagnostic| diagnostic.byte_range.clone())
+                .collect::<Vec<_>>(),
+            [0..5, 7..13]
+        );
+    }
+
+    fn echo_notification(
+        timestamp: u32,
+        ordinal: u32,
+    ) -> CChatRoom_IncomingChatMessage_Notification {
+        let mut notification = CChatRoom_IncomingChatMessage_Notification::new();
+        notification.set_chat_group_id(1);
+        notification.set_chat_id(2);
+        notification.set_steamid_sender(76561199491325083);
+        notification.set_message("!sub".to_string());
+        notification.set_timestamp(timestamp);
+        notification.set_ordinal(ordinal);
+        notification
+    }
+
+    #[test]
+    fn test_mentions_me_matches_mention_targets() {
+        let own = SteamID::from(76561197960287930);
+        for (text, expected) in [
+            ("@here gg", true),
+            ("[mention=76561197960287930]@me[/mention]", true),
+            ("@[U:1:1531059355]", false),
+            ("gg", false),
+        ] {
+            let mut notification = echo_notification(1, 1);
+            notification.set_message(text.to_string());
+            let message = EnhancedGroupChatMessage::from_notification(&notification);
+            assert_eq!(message.mentions_me(&own), expected, "{text}");
+            assert_eq!(
+                message.mentions(&MentionTargets::new(), own),
+                expected,
+                "{text}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_mention_level_ranks_direct_pings_first() {
+        let own = SteamID::from(76561197960287930);
+        for (text, level) in [
+            ("@here gg", MentionLevel::Here),
+            ("@all @here gg", MentionLevel::All),
+            (
+                "@all [mention=76561197960287930]@me[/mention]",
+                MentionLevel::Direct,
+            ),
+            ("@[U:1:1531059355]", MentionLevel::None),
+            ("gg", MentionLevel::None),
+        ] {
+            let mut notification = echo_notification(1, 1);
+            notification.set_message(text.to_string());
+            let message = EnhancedGroupChatMessage::from_notification(&notification);
+            assert_eq!(
+                message.mention_level(&MentionTargets::new(), own),
+                level,
+                "{text}"
+            );
+        }
+    }
+
+    fn echo_expectation(response_timestamp: u32) -> EchoExpectation {
+        EchoExpectation {
+            chat_group_id: 1,
+            chat_id: 2,
+            sender_account_id: SteamID::from(76561199491325083).account_id(),
+            sent_message: "!sub".to_string(),
+            modified_message: String::new(),
+            response_timestamp,
+            response_ordinal: None,
+        }
+    }
+
+    #[test]
+    fn test_echo_classification_uses_response_timestamp() {
+        let expectation = echo_expectation(100);
+        assert_eq!(
+            expectation.classify(&echo_notification(100, 0)),
+            Some(EchoMatch::Exact)
+        );
+        assert_eq!(
+            expectation.classify(&echo_notification(102, 0)),
+            Some(EchoMatch::InWindow)
+        );
+        assert_eq!(expectation.classify(&echo_notification(99, 0)), None);
+        assert_eq!(expectation.classify(&echo_notification(103, 0)), None);
+
+        let mut other_room = echo_notification(100, 0);
+        other_room.set_chat_id(3);
+        assert_eq!(expectation.classify(&other_room), None);
+    }
+
+    #[tokio::test]
+    async fn test_wait_for_echo_prefers_exact_timestamp() {
+        let mut echoes = tokio_stream::iter(vec![
+            Ok::<_, steam_vent::NetworkError>(echo_notification(99, 1)),
+            Ok(echo_notification(101, 2)),
+            Ok(echo_notification(100, 3)),
+        ]);
+        let matched = ChatRoomMessaging::wait_for_echo_notification(
+            &mut echoes,
+            &echo_expectation(100),
+            Duration::from_secs(1),
+        )
+        .await
+        .unwrap();
+        assert_eq!(matched, (100, 3));
+
+        let mut echoes = tokio_stream::iter(vec![Ok::<_, steam_vent::NetworkError>(
+            echo_notification(99, 1),
+        )]);
+        let err = ChatRoomMessaging::wait_for_echo_notification(
+            &mut echoes,
+            &echo_expectation(100),
+            Duration::from_millis(10),
+        )
+        .await
+        .unwrap_err();
+        assert!(matches!(
+            err,
+            KetherError::Chat(ChatError::EchoTimeout { .. })
+        ));
+    }
+
+    fn unthrottled() -> NotificationOptions {
+        NotificationOptions {
+            throttle: None,
+            error_backoff: Duration::ZERO,
+            ignore_own_messages: true,
+            buffer_capacity: 1024,
+            overflow: OverflowPolicy::Block,
+            on_panic: PanicPolicy::Stop,
+            auto_ack: false,
+        }
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_notification_options_propagate_to_stream() {
+        let options = NotificationOptions {
+            throttle: Some(Duration::from_secs(1)),
+            error_backoff: Duration::from_secs(5),
+            ignore_own_messages: false,
+            buffer_capacity: 1,
+            overflow: OverflowPolicy::DropNewest,
+            on_panic: PanicPolicy::LogAndContinue,
+            auto_ack: true,
+        };
+        let notifications = tokio_stream::iter(vec![
+            Ok(echo_notification(100, 1)),
+            Ok(echo_notification(101, 2)),
+        ]);
+        let stream = NotificationStream::new(notifications, options, None);
+        assert_eq!(stream.options, options);
+        let mut stream = stream.throttled();
+
+        let started = tokio::time::Instant::now();
+        assert!(StreamExt::next(&mut stream.inner).await.is_some());
+        assert!(StreamExt::next(&mut stream.inner).await.is_some());
+        assert!(started.elapsed() >= Duration::from_secs(1));
+
+        let defaults = NotificationOptions::default();
+        assert_eq!(defaults.throttle, Some(Duration::from_millis(25)));
+        assert_eq!(defaults.error_backoff, Duration::from_millis(250));
+        assert!(defaults.ignore_own_messages);
+        assert_eq!(defaults.overflow, OverflowPolicy::Block);
+        assert_eq!(defaults.on_panic, PanicPolicy::Stop);
+    }
+
+    #[test]
+    fn test_friend_activity_decodes_entry_types() {
+        let mut notification = CFriendMessages_IncomingMessage_Notification::new();
+        notification.set_steamid_friend(76561199491325083);
+        notification.set_chat_entry_type(2);
+        let typing = FriendActivity::from_message(FriendMessage::from_notification(&notification));
+        assert!(matches!(typing, FriendActivity::Typing(id) if id.account_id() == 1531059355));
+
+        notification.set_chat_entry_type(CHAT_ENTRY_TYPE_EMOTE);
+        notification.set_message("waves".to_string());
+        let emote = FriendActivity::from_message(FriendMessage::from_notification(&notification));
+        assert!(
+            matches!(emote, FriendActivity::Message(msg) if msg.entry_type() == ChatEntryType::Emote)
+        );
+
+        for raw in [1, 2, 3, 4, 6, 7, 8, 9, 10, 11, 14, 42] {
+            assert_eq!(ChatEntryType::from_raw(raw).as_raw(), raw);
+        }
+        assert_eq!(ChatEntryType::from_raw(42), ChatEntryType::Unknown(42));
+    }
+
+    #[test]
+    fn test_member_change_event_from_notification() {
+        let mut notification = CChatRoom_MemberStateChange_Notification::new();
+        notification.set_chat_group_id(7);
+        notification
+            .member
+            .mut_or_insert_default()
+            .set_accountid(42);
+        notification.set_change(EChatRoomMemberStateChange::k_EChatRoomMemberStateChange_Parted);
+
+        let event = MemberChangeEvent::from_notification(&notification).unwrap();
+        assert_eq!(event.chat_group_id, 7);
+        assert_eq!(event.steam_id.account_id(), 42);
+        assert_eq!(event.change, MemberChange::Left);
+        assert!(event.actor.is_none());
+
+        notification
+            .set_change(EChatRoomMemberStateChange::k_EChatRoomMemberStateChange_RankChanged);
+        assert!(MemberChangeEvent::from_notification(&notification).is_none());
+    }
+
+    #[test]
+    fn test_group_invite_from_notification() {
+        let mut notification = ChatRoomClient_NotifyChatGroupUserStateChanged_Notification::new();
+        notification.set_chat_group_id(9);
+        notification
+            .set_user_action(EChatRoomMemberStateChange::k_EChatRoomMemberStateChange_Invited);
+        let summary = notification.group_summary.mut_or_insert_default();
+        summary.set_chat_group_name("Admins".to_string());
+        summary.set_default_chat_id(3);
+
+        let invite = GroupInvite::from_notification(&notification).unwrap();
+        assert_eq!(invite.chat_group_id, 9);
+        assert_eq!(invite.group_name, "Admins");
+        assert_eq!(invite.default_chat_id, 3);
+        assert!(invite.inviter.is_none() && invite.invite_code.is_none());
+
+        notification
+            .set_user_action(EChatRoomMemberStateChange::k_EChatRoomMemberStateChange_Joined);
+        assert!(GroupInvite::from_notification(&notification).is_none());
+    }
+
+    #[test]
+    fn test_group_state_tracker_reports_diffs() {
+        use steam_vent_proto::steammessages_chat_steamclient::CChatRoomState;
+
+        let mut tracker = GroupStateTracker::default();
+
+        let mut header = CChatRoom_ChatRoomHeaderState_Notification::new();
+        let state = header.header_state.mut_or_insert_default();
+        state.set_chat_group_id(5);
+        state.set_chat_name("Lobby".to_string());
+        let first = tracker.apply_header(&header).unwrap();
+        assert_eq!(first.diff.name.as_deref(), Some("Lobby"));
+        assert!(first.diff.tagline.is_none());
+
+        assert!(tracker.apply_header(&header).is_none());
+
+        header
+            .header_state
+            .mut_or_insert_default()
+            .set_tagline("Welcome".to_string());
+        let changed = tracker.apply_header(&header).unwrap();
+        assert_eq!(
+            changed.diff,
+            ChatGroupStateDiff {
+                tagline: Some("Welcome".to_string()),
+                ..ChatGroupStateDiff::default()
+            }
+        );
+
+        let mut rooms = CChatRoom_ChatRoomGroupRoomsChange_Notification::new();
+        rooms.set_chat_group_id(5);
+        rooms.set_default_chat_id(1);
+        let mut room = CChatRoomState::new();
+        room.set_chat_id(1);
+        room.set_chat_name("general".to_string());
+        rooms.chat_rooms.push(room);
+        let changed = tracker.apply_rooms(&rooms).unwrap();
+        let new_rooms = changed.diff.rooms.unwrap();
+        assert_eq!(new_rooms.len(), 1);
+        assert_eq!(new_rooms[0].chat_group_name, "Lobby");
+        assert_eq!(changed.state.default_chat_id, 1);
+        assert_eq!(changed.state.tagline, "Welcome");
+        assert!(tracker.apply_rooms(&rooms).is_none());
+    }
+
+    #[test]
+    fn test_message_modified_event_matches_stored_messages() {
+        let mut notification = CChatRoom_ChatMessageModified_Notification::new();
+        notification.set_chat_group_id(1);
+        notification.set_chat_id(2);
+        use steam_vent_proto::steammessages_chat_steamclient::cchat_room_chat_message_modified_notification::ChatMessage;
+
+        let mut deleted = ChatMessage::new();
+        deleted.set_server_timestamp(100);
+        deleted.set_ordinal(1);
+        deleted.set_deleted(true);
+        notification.messages.push(deleted);
+        let event = MessageModifiedEvent::from_notification(&notification);
+
+        let stored = vec![
+            EnhancedGroupChatMessage::from_notification(&echo_notification(100, 0)),
+            EnhancedGroupChatMessage::from_notification(&echo_notification(100, 1)),
+        ];
+        let affected = event.affected(&stored);
+        assert_eq!(affected.len(), 1);
+        assert_eq!(affected[0].0.ordinal, 1);
+        assert!(affected[0].1.deleted);
+
+        let mut other_room = echo_notification(100, 1);
+        other_room.set_chat_id(3);
+        let other_room = EnhancedGroupChatMessage::from_notification(&other_room);
+        assert!(event.change_for(&other_room).is_none());
+    }
+
+    #[tokio::test]
+    async fn test_message_stream_yields_errors_without_ending() {
+        let metrics = Arc::new(crate::AtomicMetrics::new());
+        let notifications = tokio_stream::iter(vec![
+            Ok(echo_notification(100, 1)),
+            Err(steam_vent::NetworkError::Timeout),
+            Ok(echo_notification(101, 2)),
+        ]);
+        let recorder: Arc<dyn MessagingMetrics> = metrics.clone();
+        let mut stream = NotificationStream::new(notifications, unthrottled(), Some(recorder))
+            .map_items(|notification| EnhancedGroupChatMessage::from_notification(&notification))
+            .into_stream();
+
+        let first = StreamExt::next(&mut stream).await.unwrap().unwrap();
+        assert_eq!(first.ordinal, 1);
+        assert!(matches!(
+            StreamExt::next(&mut stream).await,
+            Some(Err(ChatError::Network(steam_vent::NetworkError::Timeout)))
+        ));
+        let second = StreamExt::next(&mut stream).await.unwrap().unwrap();
+        assert_eq!(second.ordinal, 2);
+        assert!(StreamExt::next(&mut stream).await.is_none());
+        assert_eq!(metrics.snapshot().notifications, 2);
+    }
+
+    #[tokio::test]
+    async fn test_group_message_stream_filters_rooms_before_preprocessing() {
+        let metrics = Arc::new(crate::AtomicMetrics::new());
+        let mut other_room = echo_notification(100, 1);
+        other_room.set_chat_id(3);
+        let notifications = tokio_stream::iter(vec![Ok(other_room), Ok(echo_notification(101, 2))]);
+        let recorder: Arc<dyn MessagingMetrics> = metrics.clone();
+        let mut stream = GroupMessageStream {
+            notifications: NotificationStream::new(notifications, unthrottled(), Some(recorder)),
+            filter: None,
+            mentions: None,
+            own_steam_id: 0,
+            ignore_own: true,
+            preprocessor: Arc::new(MessagePreprocessor::default()),
+        }
+        .filter_rooms(RoomFilter::new().allow_room(1, 2));
+
+        let message = StreamExt::next(&mut stream).await.unwrap().unwrap();
+        assert_eq!((message.chat_id, message.ordinal), (2, 2));
+        assert!(StreamExt::next(&mut stream).await.is_none());
+        assert_eq!(metrics.snapshot().notifications, 1);
+    }
+
+    #[tokio::test]
+    async fn test_group_message_stream_drops_own_messages() {
+        let own_steam_id = 76561199491325083;
+        let mut other_sender = echo_notification(101, 2);
+        other_sender.set_steamid_sender(76561197960287930);
+        let stream_of = |ignore_own| GroupMessageStream {
+            notifications: NotificationStream::new(
+                tokio_stream::iter(vec![
+                    Ok(echo_notification(100, 1)),
+                    Ok(other_sender.clone()),
+                ]),
+                unthrottled(),
+                None,
+            ),
+            filter: None,
+            mentions: None,
+            own_steam_id,
+            ignore_own,
+            preprocessor: Arc::new(MessagePreprocessor::default()),
+        };
+
+        let ordinals = |stream: GroupMessageStream<'static>| async move {
+            FuturesStreamExt::collect::<Vec<_>>(stream)
+                .await
+                .into_iter()
+                .map(|message| message.unwrap().ordinal)
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(ordinals(stream_of(true)).await, vec![2]);
+        assert_eq!(
+            ordinals(stream_of(true).ignore_own_messages(false)).await,
+            vec![1, 2]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_group_message_stream_keeps_only_mentions() {
+        let notifications = ["hello", "ping [U:1:22202]", "@here standup", "[U:1:5]"]
+            .into_iter()
+            .enumerate()
+            .map(|(ordinal, text)| {
+                let mut notification = echo_notification(100, ordinal as u32);
+                notification.set_message(text.to_string());
+                Ok(notification)
+            })
+            .collect::<Vec<_>>();
+        let stream = GroupMessageStream {
+            notifications: NotificationStream::new(
+                tokio_stream::iter(notifications),
+                unthrottled(),
+                None,
+            ),
+            filter: None,
+            mentions: None,
+            own_steam_id: 76561197960287930,
+            ignore_own: true,
+            preprocessor: Arc::new(MessagePreprocessor::default()),
+        }
+        .mentions_only(MentionTargets::new());
+
+        let ordinals = FuturesStreamExt::collect::<Vec<_>>(stream)
+            .await
+            .into_iter()
+            .map(|message| message.unwrap().ordinal)
+            .collect::<Vec<_>>();
+        assert_eq!(ordinals, vec![1, 2]);
+    }
+
+    #[test]
+    fn test_buffer_overflow_makes_room_as_configured() {
+        let pushed = |overflow: BufferOverflow| {
+            let (sender, mut receiver) = mpsc::channel(2);
+            let results: Vec<_> = (1..=3)
+                .map(|item| {
+                    overflow
+                        .push(&sender, &mut receiver, item)
+                        .is_ok_and(|dropped| !dropped)
+                })
+                .collect();
+            let mut buffered = Vec::new();
+            while let Ok(item) = receiver.try_recv() {
+                buffered.push(item);
+            }
+            (results, buffered)
+        };
+
+        assert_eq!(BufferOverflow::of(OverflowPolicy::Block), None);
+        assert_eq!(
+            pushed(BufferOverflow::DropOldest),
+            (vec![true, true, false], vec![2, 3])
+        );
+        assert_eq!(
+            pushed(BufferOverflow::DropNewest),
+            (vec![true, true, false], vec![1, 2])
+        );
+
+        let (sender, mut receiver) = mpsc::channel(1);
+        assert!(BufferOverflow::Fail.push(&sender, &mut receiver, 1).is_ok());
+        assert!(
+            BufferOverflow::Fail
+                .push(&sender, &mut receiver, 2)
+                .is_err()
+        );
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_overflow_policies_bound_the_listener_buffer() {
+        // The default throttle spaces the callbacks, so the burst piles up in the buffer.
+        async fn handled_with(
+            overflow: OverflowPolicy,
+        ) -> (Vec<u32>, crate::MetricsSnapshot, bool) {
+            let metrics = Arc::new(crate::AtomicMetrics::new());
+            let recorder: Arc<dyn MessagingMetrics> = metrics.clone();
+            let options = NotificationOptions {
+                buffer_capacity: 2,
+                overflow,
+                ..NotificationOptions::default()
+            };
+            let notifications =
+                tokio_stream::iter((1..=5).map(|ordinal| Ok(echo_notification(100, ordinal))));
+            let mut handled = Vec::new();
+            let result = NotificationStream::new(notifications, options, Some(recorder))
+                .for_each(|notification| {
+                    handled.push(notification.ordinal());
+                    Ok(())
+                })
+                .await;
+            (handled, metrics.snapshot(), result.is_ok())
+        }
+
+        let started = tokio::time::Instant::now();
+        let (handled, _, ok) = handled_with(OverflowPolicy::Block).await;
+        assert_eq!(handled, vec![1, 2, 3, 4, 5]);
+        assert!(ok);
+        assert!(started.elapsed() >= Duration::from_millis(100));
+
+        let (handled, snapshot, ok) = handled_with(OverflowPolicy::DropOldest).await;
+        assert_eq!(handled, vec![1, 4, 5]);
+        assert_eq!(snapshot.notifications_dropped, 2);
+        assert!(ok);
+
+        let (handled, snapshot, ok) = handled_with(OverflowPolicy::DropNewest).await;
+        assert_eq!(handled, vec![1, 2, 3]);
+        assert_eq!(snapshot.notifications_dropped, 2);
+        assert!(ok);
+
+        let (handled, _, ok) = handled_with(OverflowPolicy::Fail).await;
+        assert_eq!(handled, vec![1]);
+        assert!(!ok);
+    }
+
+    #[tokio::test]
+    async fn test_panicking_callback_is_isolated() {
+        let run = |on_panic| async move {
+            let notifications =
+                tokio_stream::iter((1..=2).map(|ordinal| Ok(echo_notification(100, ordinal))));
+            let options = NotificationOptions {
+                on_panic,
+                ..unthrottled()
+            };
+            let mut handled = Vec::new();
+            let result = NotificationStream::new(notifications, options, None)
+                .for_each(|notification| {
+                    if notification.ordinal() == 1 {
+                        panic!("boom on {}", notification.ordinal());
+                    }
+                    handled.push(notification.ordinal());
+                    Ok(())
+                })
+                .await;
+            (handled, result)
+        };
+
+        let (handled, result) = run(PanicPolicy::LogAndContinue).await;
+        assert_eq!(handled, vec![2]);
+        assert!(result.is_ok());
+
+        let (handled, result) = run(PanicPolicy::Stop).await;
+        assert!(handled.is_empty());
+        match result {
+            Err(NotificationDispatchError::Callback { source }) => {
+                assert_eq!(source.to_string(), "callback panicked: boom on 1");
+            }
+            other => panic!("unexpected result: {other:?}"),
+        }
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_without_error_backoff_returns_stream_errors_immediately() {
+        let options = NotificationOptions {
+            error_backoff: Duration::from_secs(30),
+            ..unthrottled()
+        };
+        let failing = || {
+            tokio_stream::iter(vec![Err::<CChatRoom_IncomingChatMessage_Notification, _>(
+                steam_vent::NetworkError::Timeout,
+            )])
+        };
+
+        let started = tokio::time::Instant::now();
+        let result = NotificationStream::new(failing(), options, None)
+            .without_error_backoff()
+            .for_each(|_| Ok(()))
+            .await;
+        assert!(matches!(result, Err(NotificationDispatchError::Stream(_))));
+        assert_eq!(started.elapsed(), Duration::ZERO);
+
+        let result = NotificationStream::new(failing(), options, None)
+            .for_each(|_| Ok(()))
+            .await;
+        assert!(matches!(result, Err(NotificationDispatchError::Stream(_))));
+        assert!(started.elapsed() >= Duration::from_secs(30));
+    }
+
+    #[tokio::test]
+    async fn test_for_each_until_stops_between_notifications() {
+        let (stop_tx, stop_rx) = tokio::sync::oneshot::channel::<()>();
+        let mut stop_tx = Some(stop_tx);
+        let notifications = FuturesStreamExt::chain(
+            tokio_stream::iter(vec![Ok(echo_notification(100, 1))]),
+            futures_util::stream::pending(),
+        );
+        let handled = Arc::new(std::sync::Mutex::new(Vec::new()));
+        let seen = handled.clone();
+
+        let result = NotificationStream::new(notifications, unthrottled(), None).for_each_until(
+            async move {
+                let _ = stop_rx.await;
+            },
+            move |notification| {
+                seen.lock().unwrap().push(notification.ordinal());
+                if let Some(stop) = stop_tx.take() {
+                    let _ = stop.send(());
+                }
+                Ok(())
+            },
+        );
+        tokio::time::timeout(Duration::from_secs(1), result)
+            .await
+            .expect("listener stops")
+            .unwrap();
+        assert_eq!(*handled.lock().unwrap(), vec![1]);
+    }
+
+    #[test]
+    fn test_per_call_overrides_beat_messaging_defaults() {
+        let defaults = MessagingDefaults::default()
+            .with_echo_to_sender(true)
+            .with_strict_bbcode(true);
+
+        let inherited = SendGroupMessageParams::new(1, 2, "!sub");
+        let req = ChatRoomMessaging::build_send_message_request(
+            &inherited,
+            &defaults,
+            MessagePreprocessor::shared(),
+        );
+        assert!(req.echo_to_sender());
+        assert!(inherited.effective_strict_bbcode(&defaults));
+
+        let overridden = SendGroupMessageParams::new(1, 2, "!sub")
+            .with_echo_to_sender(false)
+            .with_strict_bbcode(false);
+        let req = ChatRoomMessaging::build_send_message_request(
+            &overridden,
+            &defaults,
+            MessagePreprocessor::shared(),
+        );
+        assert!(!req.echo_to_sender());
+        assert!(!overridden.effective_strict_bbcode(&defaults));
+
+        let explicit = SendGroupMessageParams::new(1, 2, "!sub").with_echo_to_sender(true);
+        let req = send_request(&explicit);
+        assert!(req.echo_to_sender());
+    }
+
+    #[test]
+    fn test_resubscribe_policy_backs_off_exponentially_up_to_cap() {
+        let policy = ResubscribePolicy::new(10, Duration::from_millis(500), Duration::from_secs(3));
+        let delays: Vec<_> = (1..=5).map(|attempt| policy.delay_for(attempt)).collect();
+        assert_eq!(
+            delays,
+            vec![
+                Duration::from_millis(500),
+                Duration::from_secs(1),
+                Duration::from_secs(2),
+                Duration::from_secs(3),
+                Duration::from_secs(3),
+            ]
+        );
+        assert_eq!(policy.delay_for(u32::MAX), Duration::from_secs(3));
+    }
+
+    #[test]
+    fn test_retry_policy_only_retries_transient_failures() {
+        let retry = RetryPolicy::new(3, Duration::from_millis(100));
+        assert_eq!(
+            retry.delay_before(1, RetryDisposition::BackoffRetry),
+            Some(Duration::from_millis(100))
+        );
+        assert_eq!(
+            retry.delay_before(2, RetryDisposition::ImmediateRetry),
+            Some(Duration::ZERO)
+        );
+        assert_eq!(
+            retry.delay_before(2, RetryDisposition::BackoffRetry),
+            Some(Duration::from_millis(200))
+        );
+        assert_eq!(retry.delay_before(3, RetryDisposition::BackoffRetry), None);
+        assert_eq!(retry.delay_before(1, RetryDisposition::Fatal), None);
+        assert_eq!(
+            RetryPolicy::none().delay_before(1, RetryDisposition::ImmediateRetry),
+            None
+        );
+    }
+
+    #[test]
+    fn test_retry_policy_new_keeps_linear_delays() {
+        let linear = RetryPolicy::new(5, Duration::from_millis(100));
+        let delays: Vec<_> = (1..=4)
+            .filter_map(|attempt| linear.delay_before(attempt, RetryDisposition::BackoffRetry))
+            .collect();
+        assert_eq!(
+            delays,
+            [100, 200, 300, 400].map(Duration::from_millis).to_vec()
+        );
+
+        let exponential = linear.with_backoff(Backoff::new(
+            Duration::from_millis(100),
+            Duration::from_millis(250),
+        ));
+        assert_eq!(
+            exponential.delay_before(3, RetryDisposition::BackoffRetry),
+            Some(Duration::from_millis(250))
+        );
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_retry_policy_runs_until_success_or_fatal_error() {
+        let retry = RetryPolicy::new(3, Duration::from_millis(100));
+        let mut attempts = 0;
+        let started = tokio::time::Instant::now();
+        let result = retry
+            .run("test", || {
+                attempts += 1;
+                let attempt = attempts;
+                async move {
+                    if attempt < 3 {
+                        Err(KetherError::from(steam_vent::NetworkError::EOF))
+                    } else {
+                        Ok(attempt)
+                    }
+                }
+            })
+            .await;
+        assert_eq!(result.unwrap(), 3);
+        assert!(started.elapsed() >= Duration::from_millis(300));
+
+        let mut attempts = 0;
+        let result: Result<(), _> = retry
+            .run("test", || {
+                attempts += 1;
+                async { Err(KetherError::other("invalid")) }
+            })
+            .await;
+        assert!(result.is_err());
+        assert_eq!(attempts, 1);
+    }
+
+    #[test]
+    fn test_link_previews_disabled_wraps_links() {
+        let params = SendGroupMessageParams::new(1, 2, "news: https://kether.pl");
+        let req = send_request(&params);
+        assert_eq!(req.message(), "news: https://kether.pl");
+
+        let params = params.with_link_previews(false);
+        let req = send_request(&params);
+        assert_eq!(req.message(), "news: [noembed]https://kether.pl[/noembed]");
+    }
+
+    #[test]
+    fn test_preserve_escapes_keeps_literal_brackets() {
+        let params = SendGroupMessageParams::new(1, 2, r"type \[b\] for bold");
+        let req = send_request(&params);
+        assert_eq!(req.message(), "type [b] for bold");
+
+        let params = params.with_preserve_escapes(true);
+        let req = send_request(&params);
+        assert_eq!(req.message(), r"type \[b\] for bold");
+    }
+
+    #[test]
+    fn test_server_mentions_are_sent_as_tags() {
+        let params = SendGroupMessageParams::new(
+            1,
+            2,
+            "@all event starting, @[U:1:1531059355] hosts [code]@here[/code] \
+             [mention=here]@online[/mention]",
+        );
+        let req = send_request(&params);
+        assert_eq!(
+            req.message(),
+            "[mention=all]@all[/mention] event starting, \
+             [mention=76561199491325083]@[U:1:1531059355][/mention] hosts [code]@here[/code] \
+             [mention=here]@online[/mention]"
+        );
+        let mentions = MessagePreprocessor::extract_mentions(req.message()).unwrap();
+        assert!(mentions.mention_all && mentions.mention_here);
+
+        let params = params.with_server_mentions(false);
+        let req = send_request(&params);
+        assert!(req.message().starts_with("@all event starting"));
+    }
+
+    #[test]
+    fn test_from_builder_preserves_escapes() {
+        let builder = MessageBuilder::new().text("type [b] for bold");
+        let params = SendGroupMessageParams::from_builder(1, 2, builder).unwrap();
+        let req = send_request(&params);
+        assert_eq!(req.message(), r"type \[b\] for bold");
+
+        let builder = MessageBuilder::new().with_length_limit(4).text("too long");
+        assert!(SendGroupMessageParams::from_builder(1, 2, builder).is_err());
+    }
+
+    #[test]
+    fn test_rate_limited_send_is_backoff_retry() {
+        fn send() -> Result<(), KetherError> {
+            Err(steam_vent::NetworkError::ApiError(
+                EResult::RateLimitExceeded,
+            ))?;
+            Ok(())
+        }
+
+        let err = send().unwrap_err();
+        let KetherError::Chat(chat) = &err else {
+            panic!("expected a chat error, got {err:?}");
+        };
+        assert_eq!(chat.inventory().disposition, RetryDisposition::BackoffRetry);
+        assert_eq!(chat.inventory().code, Some(84));
+        assert_eq!(err.inventory(), chat.inventory());
+        assert_eq!(
+            classify_send_error(&err).disposition,
+            RetryDisposition::BackoffRetry
+        );
+
+        let rejected = ChatError::InvalidInput("Cannot delete empty list of messages".into());
+        assert_eq!(rejected.inventory().disposition, RetryDisposition::Fatal);
+        let timed_out = ChatError::EchoTimeout {
+            chat_group_id: 1,
+            chat_id: 2,
+            waited: Duration::from_secs(5),
+        };
+        assert_eq!(timed_out.inventory().domain, ErrorDomain::Transport);
+        assert_eq!(timed_out.inventory().context.as_deref(), Some("chat 1/2"));
+    }
+
+    #[test]
+    fn test_missing_message_errors_allow_replacement() {
+        let gone = KetherError::from(steam_vent::NetworkError::ApiError(EResult::NoMatch));
+        assert!(ChatRoomMessaging::is_missing_message_error(&gone));
+
+        let denied = KetherError::from(steam_vent::NetworkError::ApiError(EResult::AccessDenied));
+        assert!(!ChatRoomMessaging::is_missing_message_error(&denied));
+
+        let other = KetherError::other("timeout");
+        assert!(!ChatRoomMessaging::is_missing_message_error(&other));
+    }
+
     #[test]
     fn test_message_with_mentions() {
-        let steam_id = SteamID::try_from("[U:1:1531059355]").unwrap();
-        let message = helpers::create_message_with_mentions("Hello", &[steam_id]);
-        assert!(message.contains("@"));
+        use crate::preprocessing::MentionSteamId;
+        use helpers::MentionPlacement;
+
+        let kyle = SteamID::try_from("[U:1:1531059355]").unwrap();
+        let jane = SteamID::from(76561198012345678);
+        let ids = [kyle, jane, kyle];
+        let mentioned = |message: &str| {
+            MessagePreprocessor::extract_mentions(message)
+                .map(|mentions| mentions.mention_steamids)
+                .unwrap_or_default()
+        };
+        let expected = vec![MentionSteamId(kyle), MentionSteamId(jane)];
+
+        let message =
+            helpers::create_message_with_mentions("Hello", &ids, MentionPlacement::Suffix, &[]);
+        assert_eq!(message, "Hello @[U:1:1531059355] @[U:1:52079950]");
+        assert_eq!(mentioned(&message), expected);
+
+        let names = [
+            ResolvedMention::new(kyle, "Kyle [PL]", &[]),
+            ResolvedMention::placeholder(jane),
+        ];
+        let message =
+            helpers::create_message_with_mentions("Hello", &ids, MentionPlacement::Prefix, &names);
+        assert_eq!(
+            message,
+            r"[mention=76561199491325083]@Kyle \[PL\][/mention] @[U:1:52079950] Hello"
+        );
+        assert_eq!(mentioned(&message), expected);
+
+        let message = helpers::create_message_with_mentions(
+            "Raid in 5, {mentions}!",
+            &ids,
+            MentionPlacement::Inline,
+            &names,
+        );
+        assert_eq!(
+            message,
+            r"Raid in 5, [mention=76561199491325083]@Kyle \[PL\][/mention] @[U:1:52079950]!"
+        );
+        assert_eq!(mentioned(&message), expected);
+
+        assert_eq!(
+            helpers::create_message_with_mentions("Hi", &[], MentionPlacement::Inline, &[]),
+            "Hi"
+        );
     }
 }
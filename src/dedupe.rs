@@ -0,0 +1,331 @@
+// SPDX-License-Identifier: LGPL-3.0-only
+
+use std::collections::hash_map::DefaultHasher;
+use std::collections::{HashMap, VecDeque};
+use std::hash::{Hash, Hasher};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use thiserror::Error;
+
+/// Default number of recent sends remembered by a [`DedupeCache`].
+pub const DEFAULT_DEDUPE_CAPACITY: usize = 1024;
+
+/// Time source used by [`DedupeCache`]; replaceable so tests can drive the clock.
+pub trait DedupeClock: Send + Sync {
+    /// Current monotonic time.
+    fn now(&self) -> Instant;
+}
+
+/// [`DedupeClock`] backed by `Instant::now`.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct SystemDedupeClock;
+
+impl DedupeClock for SystemDedupeClock {
+    fn now(&self) -> Instant {
+        Instant::now()
+    }
+}
+
+/// Returned when a send is suppressed because an identical message was sent recently.
+#[derive(Debug, Clone, Error)]
+#[error(
+    "duplicate message to chat {chat_group_id}/{chat_id} suppressed (previous send {age:?} ago)"
+)]
+pub struct DuplicateMessageError {
+    /// The unique identifier for the chat group.
+    pub chat_group_id: u64,
+    /// The unique identifier for the specific chat room within the group.
+    pub chat_id: u64,
+    /// Time elapsed since the identical message was sent.
+    pub age: Duration,
+}
+
+/// Bounded, time-windowed memory of recently sent messages.
+///
+/// Messages are keyed by a hash of `(chat_group_id, chat_id, prepared text)`. An entry
+/// expires once `window` has elapsed; when more than `capacity` entries are remembered,
+/// expired entries are dropped first and then the least recently used one is evicted
+/// (an entry is used when it is recorded and whenever it suppresses a duplicate).
+pub struct DedupeCache {
+    window: Duration,
+    capacity: usize,
+    clock: Arc<dyn DedupeClock>,
+    state: Mutex<DedupeState>,
+}
+
+#[derive(Default)]
+struct DedupeState {
+    seen: HashMap<u64, SeenMessage>,
+    /// Keys from least to most recently used, tagged with the use they record; tags that
+    /// no longer match `SeenMessage::used` are stale and skipped.
+    recency: VecDeque<(u64, u64)>,
+    uses: u64,
+}
+
+#[derive(Clone, Copy)]
+struct SeenMessage {
+    sent_at: Instant,
+    used: u64,
+}
+
+impl std::fmt::Debug for DedupeCache {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("DedupeCache")
+            .field("window", &self.window)
+            .field("capacity", &self.capacity)
+            .finish_non_exhaustive()
+    }
+}
+
+impl DedupeCache {
+    /// Create a cache remembering sends for `window`, bounded to `DEFAULT_DEDUPE_CAPACITY`.
+    pub fn new(window: Duration) -> Self {
+        Self::with_clock(window, DEFAULT_DEDUPE_CAPACITY, Arc::new(SystemDedupeClock))
+    }
+
+    /// Create a cache with an explicit capacity and time source.
+    pub fn with_clock(window: Duration, capacity: usize, clock: Arc<dyn DedupeClock>) -> Self {
+        Self {
+            window,
+            capacity: capacity.max(1),
+            clock,
+            state: Mutex::new(DedupeState::default()),
+        }
+    }
+
+    /// The suppression window.
+    pub fn window(&self) -> Duration {
+        self.window
+    }
+
+    /// Number of live entries.
+    pub fn len(&self) -> usize {
+        let now = self.clock.now();
+        self.state
+            .lock()
+            .map(|state| {
+                state
+                    .seen
+                    .values()
+                    .filter(|seen| self.is_live(seen, now))
+                    .count()
+            })
+            .unwrap_or_default()
+    }
+
+    /// Whether the cache holds no live entries.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Record a send, or report it as a duplicate of one made within the window.
+    ///
+    /// Call `forget` if the recorded send then fails, so a retry is not suppressed.
+    ///
+    /// # Returns
+    ///
+    /// `Ok(())` if the send may proceed (it is now remembered), or a
+    /// `DuplicateMessageError` if an identical send happened within the window.
+    pub fn check_and_record(
+        &self,
+        chat_group_id: u64,
+        chat_id: u64,
+        message: &str,
+    ) -> Result<(), DuplicateMessageError> {
+        let key = Self::key(chat_group_id, chat_id, message);
+        let now = self.clock.now();
+        let Ok(mut guard) = self.state.lock() else {
+            // A poisoned cache must never block sending.
+            return Ok(());
+        };
+        let state = &mut *guard;
+        state.uses += 1;
+        let used = state.uses;
+
+        if let Some(seen) = state.seen.get_mut(&key)
+            && self.is_live(seen, now)
+        {
+            seen.used = used;
+            let age = now.saturating_duration_since(seen.sent_at);
+            state.recency.push_back((key, used));
+            self.compact(state);
+            return Err(DuplicateMessageError {
+                chat_group_id,
+                chat_id,
+                age,
+            });
+        }
+
+        state.seen.insert(key, SeenMessage { sent_at: now, used });
+        state.recency.push_back((key, used));
+        if state.seen.len() > self.capacity {
+            state.seen.retain(|_, seen| self.is_live(seen, now));
+        }
+        while state.seen.len() > self.capacity {
+            let Some((oldest, tag)) = state.recency.pop_front() else {
+                break;
+            };
+            if state.seen.get(&oldest).is_some_and(|seen| seen.used == tag) {
+                state.seen.remove(&oldest);
+            }
+        }
+        self.compact(state);
+
+        Ok(())
+    }
+
+    /// Forget a recorded send, e.g. because it failed and may be retried.
+    pub fn forget(&self, chat_group_id: u64, chat_id: u64, message: &str) {
+        let key = Self::key(chat_group_id, chat_id, message);
+        if let Ok(mut state) = self.state.lock() {
+            state.seen.remove(&key);
+        }
+    }
+
+    fn is_live(&self, seen: &SeenMessage, now: Instant) -> bool {
+        now.saturating_duration_since(seen.sent_at) < self.window
+    }
+
+    /// Drop stale recency tags once they outnumber the entries.
+    fn compact(&self, state: &mut DedupeState) {
+        if state.recency.len() > self.capacity.saturating_mul(2) {
+            let seen = &state.seen;
+            state
+                .recency
+                .retain(|(key, tag)| seen.get(key).is_some_and(|seen| seen.used == *tag));
+        }
+    }
+
+    fn key(chat_group_id: u64, chat_id: u64, message: &str) -> u64 {
+        let mut hasher = DefaultHasher::new();
+        chat_group_id.hash(&mut hasher);
+        chat_id.hash(&mut hasher);
+        message.hash(&mut hasher);
+        hasher.finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct ManualClock {
+        start: Instant,
+        offset: Mutex<Duration>,
+    }
+
+    impl ManualClock {
+        fn new() -> Arc<Self> {
+            Arc::new(Self {
+                start: Instant::now(),
+                offset: Mutex::new(Duration::ZERO),
+            })
+        }
+
+        fn advance(&self, by: Duration) {
+            *self.offset.lock().unwrap() += by;
+        }
+    }
+
+    impl DedupeClock for ManualClock {
+        fn now(&self) -> Instant {
+            self.start + *self.offset.lock().unwrap()
+        }
+    }
+
+    #[test]
+    fn duplicate_within_window_is_rejected() {
+        let clock = ManualClock::new();
+        let cache = DedupeCache::with_clock(Duration::from_secs(5), 16, clock.clone());
+
+        cache.check_and_record(1, 2, "!sub").expect("first send");
+        clock.advance(Duration::from_secs(2));
+
+        let duplicate = cache.check_and_record(1, 2, "!sub").unwrap_err();
+        assert_eq!(duplicate.age, Duration::from_secs(2));
+
+        // Different room or text is not a duplicate.
+        cache.check_and_record(1, 3, "!sub").expect("other room");
+        cache
+            .check_and_record(1, 2, "!sub now")
+            .expect("other text");
+    }
+
+    #[test]
+    fn entries_expire_after_window() {
+        let clock = ManualClock::new();
+        let cache = DedupeCache::with_clock(Duration::from_secs(5), 16, clock.clone());
+
+        cache.check_and_record(1, 2, "!sub").expect("first send");
+        clock.advance(Duration::from_secs(5));
+
+        cache
+            .check_and_record(1, 2, "!sub")
+            .expect("window elapsed");
+        assert_eq!(cache.len(), 1);
+    }
+
+    #[test]
+    fn capacity_evicts_oldest_entry() {
+        let clock = ManualClock::new();
+        let cache = DedupeCache::with_clock(Duration::from_secs(60), 2, clock.clone());
+
+        cache.check_and_record(1, 1, "a").unwrap();
+        cache.check_and_record(1, 1, "b").unwrap();
+        cache.check_and_record(1, 1, "c").unwrap();
+
+        assert_eq!(cache.len(), 2);
+        cache
+            .check_and_record(1, 1, "a")
+            .expect("evicted entry is forgotten");
+        assert!(cache.check_and_record(1, 1, "c").is_err());
+    }
+
+    #[test]
+    fn capacity_evicts_least_recently_used_entry() {
+        let clock = ManualClock::new();
+        let cache = DedupeCache::with_clock(Duration::from_secs(60), 2, clock.clone());
+
+        cache.check_and_record(1, 1, "a").unwrap();
+        cache.check_and_record(1, 1, "b").unwrap();
+        // Suppressing a duplicate of "a" makes "b" the least recently used entry.
+        assert!(cache.check_and_record(1, 1, "a").is_err());
+        cache.check_and_record(1, 1, "c").unwrap();
+
+        assert!(cache.check_and_record(1, 1, "a").is_err());
+        cache
+            .check_and_record(1, 1, "b")
+            .expect("least recently used entry is evicted");
+    }
+
+    #[test]
+    fn expired_entries_are_evicted_before_live_ones() {
+        let clock = ManualClock::new();
+        let cache = DedupeCache::with_clock(Duration::from_secs(5), 2, clock.clone());
+
+        cache.check_and_record(1, 1, "a").unwrap();
+        clock.advance(Duration::from_secs(3));
+        cache.check_and_record(1, 1, "b").unwrap();
+        clock.advance(Duration::from_secs(3));
+        cache.check_and_record(1, 1, "c").unwrap();
+        cache.check_and_record(1, 1, "d").unwrap();
+
+        assert_eq!(cache.len(), 2);
+        assert!(cache.check_and_record(1, 1, "c").is_err());
+        assert!(cache.check_and_record(1, 1, "d").is_err());
+    }
+
+    #[test]
+    fn forgotten_sends_may_be_retried() {
+        let clock = ManualClock::new();
+        let cache = DedupeCache::with_clock(Duration::from_secs(60), 16, clock);
+
+        cache.check_and_record(1, 2, "!sub").expect("first send");
+        cache.forget(1, 2, "!sub");
+
+        cache
+            .check_and_record(1, 2, "!sub")
+            .expect("retry after failure");
+        assert!(cache.check_and_record(1, 2, "!sub").is_err());
+    }
+}
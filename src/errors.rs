@@ -1,9 +1,22 @@
 // SPDX-License-Identifier: LGPL-3.0-only
 
+use crate::catchup::CursorStoreError;
+use crate::chatroom::{ChatError, NotificationDispatchError};
+use crate::dedupe::DuplicateMessageError;
+use crate::logon::LogonError;
+use crate::outbox::OutboxError;
+use crate::preprocessing::{
+    BBCodeError, FormatError, LengthError, PreprocessingError, SchemaError, TemplateError,
+};
+use serde::Serialize;
+use std::collections::hash_map::RandomState;
+use std::hash::{BuildHasher, Hasher};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
 use steam_vent::{ConnectionError, EResult, LoginError, NetworkError};
+use thiserror::Error;
 
 /// How callers should react to a failure when retrying an operation.
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
 pub enum RetryDisposition {
     /// Safe to retry immediately without backoff.
     ImmediateRetry,
@@ -16,7 +29,7 @@ pub enum RetryDisposition {
 }
 
 /// High-level component where an error originated.
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
 pub enum ErrorDomain {
     /// Error occurred during authentication or authorization.
     Authentication,
@@ -29,14 +42,23 @@ pub enum ErrorDomain {
 }
 
 /// Summary describing how an upstream error should be treated.
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+///
+/// Entries carry the Steam result code and free-form context when they are known, so
+/// the type is `Clone` but no longer `Copy`; code that copied entries out of a
+/// reference needs a `.clone()`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
 pub struct ErrorInventoryEntry {
     /// The domain where the error originated.
     pub domain: ErrorDomain,
     /// How the caller should handle retrying this error.
     pub disposition: RetryDisposition,
-    /// Human-readable description of the error.
-    pub description: &'static str,
+    /// Fixed, human-readable summary of the error class.
+    pub summary: &'static str,
+    /// Numeric code reported by Steam, such as the `EResult` of a failed API call.
+    pub code: Option<i32>,
+    /// Detail about this occurrence, such as the chat room involved. Never contains
+    /// account names or credentials.
+    pub context: Option<String>,
 }
 
 impl ErrorInventoryEntry {
@@ -46,7 +68,7 @@ impl ErrorInventoryEntry {
     ///
     /// * `domain` - The domain where the error originated
     /// * `disposition` - How the caller should handle retrying
-    /// * `description` - Human-readable description of the error
+    /// * `summary` - Human-readable summary of the error class
     ///
     /// # Returns
     ///
@@ -54,14 +76,361 @@ impl ErrorInventoryEntry {
     pub const fn new(
         domain: ErrorDomain,
         disposition: RetryDisposition,
-        description: &'static str,
+        summary: &'static str,
     ) -> Self {
         Self {
             domain,
             disposition,
-            description,
+            summary,
+            code: None,
+            context: None,
         }
     }
+
+    /// Attach the numeric code Steam reported.
+    ///
+    /// # Returns
+    ///
+    /// `Self` for method chaining (builder pattern).
+    pub fn with_code(mut self, code: i32) -> Self {
+        self.code = Some(code);
+        self
+    }
+
+    /// Attach detail about this occurrence.
+    ///
+    /// # Returns
+    ///
+    /// `Self` for method chaining (builder pattern).
+    pub fn with_context(mut self, context: impl Into<String>) -> Self {
+        self.context = Some(context.into());
+        self
+    }
+}
+
+impl std::fmt::Display for ErrorInventoryEntry {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.summary)?;
+        if let Some(code) = self.code {
+            write!(f, " (code {code})")?;
+        }
+        if let Some(context) = &self.context {
+            write!(f, ": {context}")?;
+        }
+        Ok(())
+    }
+}
+
+/// A failure flattened for a logging or telemetry service.
+///
+/// Build one with `ErrorReport::new`, or with the `report` method of `KetherError`,
+/// `LogonError` and `ChatError`, which classify the error themselves.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct ErrorReport {
+    /// What the caller was doing when the error happened, e.g. `"send_group_message"`.
+    pub operation: String,
+    /// The error's message followed by those of its sources, outermost first.
+    ///
+    /// A source whose message repeats the one before it is left out.
+    pub chain: Vec<String>,
+    /// How the error is classified.
+    #[serde(flatten)]
+    pub inventory: ErrorInventoryEntry,
+    /// Unix timestamp (milliseconds) when the report was created.
+    pub timestamp_ms: u64,
+}
+
+impl ErrorReport {
+    /// Report `err`, classified as `inventory`, raised during `operation`.
+    pub fn new(
+        operation: impl Into<String>,
+        err: &(dyn std::error::Error + 'static),
+        inventory: ErrorInventoryEntry,
+    ) -> Self {
+        let mut chain: Vec<String> = Vec::new();
+        let mut next = Some(err);
+        while let Some(current) = next {
+            let message = current.to_string();
+            if chain.last() != Some(&message) {
+                chain.push(message);
+            }
+            next = current.source();
+        }
+
+        Self {
+            operation: operation.into(),
+            chain,
+            inventory,
+            timestamp_ms: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map(|duration| u64::try_from(duration.as_millis()).unwrap_or(u64::MAX))
+                .unwrap_or(0),
+        }
+    }
+
+    /// Serialize the report as a single JSON object.
+    ///
+    /// The classification fields (`domain`, `disposition`, `summary`, `code`, `context`)
+    /// sit next to `operation`, `chain` and `timestamp_ms`.
+    ///
+    /// # Errors
+    ///
+    /// Returns a `serde_json::Error` if serialization fails.
+    pub fn to_json(&self) -> Result<String, serde_json::Error> {
+        serde_json::to_string(self)
+    }
+}
+
+/// How much randomness `Backoff` adds to each delay.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum JitterMode {
+    /// Use the computed delay as is.
+    #[default]
+    None,
+    /// Pick uniformly between zero and the computed delay.
+    Full,
+    /// Keep half of the computed delay and pick the other half uniformly.
+    Equal,
+}
+
+/// Exponential backoff with an upper bound and optional jitter.
+///
+/// The delay before retry number `attempt` (starting at 1) is
+/// `initial * multiplier^(attempt - 1)`, or `initial * attempt` for `Backoff::linear`,
+/// capped at `max`, with `jitter` applied to the capped value. Jitter is random unless a
+/// seed is set with `with_seed`, in which case the same attempt always gets the same delay.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Backoff {
+    /// Delay before the first retry.
+    pub initial: Duration,
+    /// Upper bound for any delay, before jitter.
+    pub max: Duration,
+    /// Growth factor between consecutive delays; `1` keeps them constant.
+    pub multiplier: u32,
+    /// Randomness added to each delay.
+    pub jitter: JitterMode,
+    seed: Option<u64>,
+    linear: bool,
+}
+
+impl Backoff {
+    /// Create a backoff doubling from `initial` up to `max`, without jitter.
+    pub const fn new(initial: Duration, max: Duration) -> Self {
+        Self {
+            initial,
+            max,
+            multiplier: 2,
+            jitter: JitterMode::None,
+            seed: None,
+            linear: false,
+        }
+    }
+
+    /// Create a backoff growing by `step` per attempt up to `max`, without jitter.
+    ///
+    /// `multiplier` is ignored by linear backoffs.
+    pub const fn linear(step: Duration, max: Duration) -> Self {
+        Self {
+            linear: true,
+            ..Self::new(step, max)
+        }
+    }
+
+    /// Set the growth factor between consecutive delays; `0` is treated as `1`.
+    ///
+    /// # Returns
+    ///
+    /// `Self` for method chaining (builder pattern).
+    pub fn with_multiplier(mut self, multiplier: u32) -> Self {
+        self.multiplier = multiplier;
+        self
+    }
+
+    /// Set how much randomness is added to each delay.
+    ///
+    /// # Returns
+    ///
+    /// `Self` for method chaining (builder pattern).
+    pub fn with_jitter(mut self, jitter: JitterMode) -> Self {
+        self.jitter = jitter;
+        self
+    }
+
+    /// Derive jitter from `seed` instead of fresh randomness, e.g. in tests.
+    ///
+    /// # Returns
+    ///
+    /// `Self` for method chaining (builder pattern).
+    pub fn with_seed(mut self, seed: u64) -> Self {
+        self.seed = Some(seed);
+        self
+    }
+
+    /// Delay before retry number `attempt` (starting at 1) without jitter.
+    pub fn base_delay(&self, attempt: u32) -> Duration {
+        let factor = if self.linear {
+            attempt.max(1)
+        } else {
+            self.multiplier
+                .max(1)
+                .saturating_pow(attempt.saturating_sub(1))
+        };
+        self.initial.saturating_mul(factor).min(self.max)
+    }
+
+    /// Delay before retry number `attempt` (starting at 1).
+    pub fn delay_for(&self, attempt: u32) -> Duration {
+        let base = self.base_delay(attempt);
+        match self.jitter {
+            JitterMode::None => base,
+            JitterMode::Full => base.mul_f64(self.unit_random(attempt)),
+            JitterMode::Equal => {
+                let half = base / 2;
+                half + (base - half).mul_f64(self.unit_random(attempt))
+            }
+        }
+    }
+
+    /// Delays for attempts 1, 2, 3 and so on.
+    pub fn delays(&self) -> impl Iterator<Item = Duration> + use<> {
+        let backoff = *self;
+        (1..=u32::MAX).map(move |attempt| backoff.delay_for(attempt))
+    }
+
+    /// A value in `[0, 1)`, fixed per attempt when seeded.
+    fn unit_random(&self, attempt: u32) -> f64 {
+        let bits = match self.seed {
+            Some(seed) => splitmix64(seed ^ u64::from(attempt)),
+            None => {
+                let mut hasher = RandomState::new().build_hasher();
+                hasher.write_u32(attempt);
+                hasher.finish()
+            }
+        };
+        (bits >> 11) as f64 / (1u64 << 53) as f64
+    }
+}
+
+fn splitmix64(seed: u64) -> u64 {
+    let mut z = seed.wrapping_add(0x9e37_79b9_7f4a_7c15);
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    z ^ (z >> 31)
+}
+
+/// Crate-wide error returned by `KetherSteamClient`, `ChatRoomClient` and the helpers.
+///
+/// Each variant keeps the typed error it wraps, so callers can match on it, and
+/// `inventory` classifies all of them the same way. The error is `Send + Sync`, so `?`
+/// into `Box<dyn Error>` or `Box<dyn Error + Send + Sync>` keeps working.
+#[derive(Debug, Error)]
+#[non_exhaustive]
+pub enum KetherError {
+    /// Logging on, or a request on the logged-on connection, failed.
+    #[error(transparent)]
+    Logon(#[from] LogonError),
+    /// A chat operation failed.
+    #[error(transparent)]
+    Chat(#[from] ChatError),
+    /// Message text could not be prepared.
+    #[error(transparent)]
+    Preprocessing(#[from] PreprocessingError),
+    /// Any other failure, such as an outbox that could not be written.
+    #[error("{0}")]
+    Other(#[source] Box<dyn std::error::Error + Send + Sync>),
+}
+
+impl KetherError {
+    /// Wrap an error that has no dedicated variant.
+    pub fn other(err: impl Into<Box<dyn std::error::Error + Send + Sync>>) -> Self {
+        KetherError::Other(err.into())
+    }
+
+    /// Get the error inventory entry containing classification and retry guidance.
+    pub fn inventory(&self) -> ErrorInventoryEntry {
+        match self {
+            KetherError::Logon(err) => err.inventory(),
+            KetherError::Chat(err) => err.inventory(),
+            KetherError::Preprocessing(_) => ErrorInventoryEntry::new(
+                ErrorDomain::Application,
+                RetryDisposition::Fatal,
+                "message could not be prepared",
+            ),
+            KetherError::Other(_) => ErrorInventoryEntry::new(
+                ErrorDomain::Unknown,
+                RetryDisposition::Fatal,
+                "unclassified error",
+            ),
+        }
+    }
+
+    /// Report this error, raised during `operation`, with its own classification.
+    pub fn report(&self, operation: &str) -> ErrorReport {
+        ErrorReport::new(operation, self, self.inventory())
+    }
+
+    /// The Steam network error behind this error, if there is one.
+    pub fn network_error(&self) -> Option<&NetworkError> {
+        match self {
+            KetherError::Chat(ChatError::Network(err)) => Some(err),
+            KetherError::Logon(LogonError::Request { source, .. }) => Some(source),
+            _ => None,
+        }
+    }
+}
+
+impl From<NetworkError> for KetherError {
+    fn from(value: NetworkError) -> Self {
+        KetherError::Chat(value.into())
+    }
+}
+
+impl From<DuplicateMessageError> for KetherError {
+    fn from(value: DuplicateMessageError) -> Self {
+        KetherError::Chat(value.into())
+    }
+}
+
+impl From<CursorStoreError> for KetherError {
+    fn from(value: CursorStoreError) -> Self {
+        KetherError::Chat(value.into())
+    }
+}
+
+impl From<OutboxError> for KetherError {
+    fn from(value: OutboxError) -> Self {
+        KetherError::other(value)
+    }
+}
+
+impl From<BBCodeError> for KetherError {
+    fn from(value: BBCodeError) -> Self {
+        KetherError::Preprocessing(value.into())
+    }
+}
+
+impl From<LengthError> for KetherError {
+    fn from(value: LengthError) -> Self {
+        KetherError::Preprocessing(value.into())
+    }
+}
+
+impl From<TemplateError> for KetherError {
+    fn from(value: TemplateError) -> Self {
+        KetherError::Preprocessing(value.into())
+    }
+}
+
+impl From<FormatError> for KetherError {
+    fn from(value: FormatError) -> Self {
+        KetherError::Preprocessing(value.into())
+    }
+}
+
+impl From<SchemaError> for KetherError {
+    fn from(value: SchemaError) -> Self {
+        KetherError::Preprocessing(value.into())
+    }
 }
 
 /// Classify a top-level connection error returned by steam-vent.
@@ -134,6 +503,27 @@ pub fn classify_network_error(err: &NetworkError) -> ErrorInventoryEntry {
     }
 }
 
+/// Classify why a notification listener stopped.
+///
+/// Stream failures use `classify_network_error`. A failing callback is an application
+/// error that restarting will not fix, while an overflow means the callback fell behind
+/// and a restarted listener may keep up.
+pub fn classify_dispatch_error(err: &NotificationDispatchError) -> ErrorInventoryEntry {
+    match err {
+        NotificationDispatchError::Stream(network) => classify_network_error(network),
+        NotificationDispatchError::Callback { .. } => ErrorInventoryEntry::new(
+            ErrorDomain::Application,
+            RetryDisposition::Fatal,
+            "listener callback failed",
+        ),
+        NotificationDispatchError::Overflow { .. } => ErrorInventoryEntry::new(
+            ErrorDomain::Application,
+            RetryDisposition::BackoffRetry,
+            "listener fell behind its notification buffer",
+        ),
+    }
+}
+
 /// Classify login errors into retry strategies.
 pub fn classify_login_error(err: &LoginError) -> ErrorInventoryEntry {
     match err {
@@ -176,7 +566,7 @@ pub fn classify_login_error(err: &LoginError) -> ErrorInventoryEntry {
 }
 
 fn classify_api_error(result: EResult) -> ErrorInventoryEntry {
-    match result {
+    let entry = match result {
         EResult::Timeout => ErrorInventoryEntry::new(
             ErrorDomain::Transport,
             RetryDisposition::ImmediateRetry,
@@ -214,7 +604,8 @@ fn classify_api_error(result: EResult) -> ErrorInventoryEntry {
             RetryDisposition::BackoffRetry,
             "unmapped Steam error code",
         ),
-    }
+    };
+    entry.with_code(result as i32)
 }
 
 #[cfg(test)]
@@ -227,6 +618,103 @@ mod tests {
         assert_eq!(entry.disposition, RetryDisposition::ImmediateRetry);
     }
 
+    #[test]
+    fn dispatch_errors_separate_stream_from_callback() {
+        let stream = NotificationDispatchError::Stream(NetworkError::Timeout);
+        assert!(stream.is_stream_error());
+        assert!(stream.network_error().is_some());
+        assert_eq!(
+            classify_dispatch_error(&stream).disposition,
+            RetryDisposition::ImmediateRetry
+        );
+
+        let callback = NotificationDispatchError::Callback {
+            source: "bad input".into(),
+        };
+        assert!(!callback.is_stream_error());
+        assert_eq!(callback.callback_source().unwrap().to_string(), "bad input");
+        assert_eq!(
+            classify_dispatch_error(&callback).disposition,
+            RetryDisposition::Fatal
+        );
+        assert_eq!(
+            callback.into_callback_source().unwrap().to_string(),
+            "bad input"
+        );
+    }
+
+    #[test]
+    fn kether_errors_keep_the_typed_error() {
+        let network = KetherError::from(NetworkError::Timeout);
+        assert!(matches!(
+            network,
+            KetherError::Chat(ChatError::Network(NetworkError::Timeout))
+        ));
+        assert_eq!(
+            network.inventory().disposition,
+            RetryDisposition::ImmediateRetry
+        );
+        assert!(network.network_error().is_some());
+
+        let length = KetherError::from(LengthError {
+            limit: 1,
+            actual: 2,
+            counted_in: crate::preprocessing::LengthUnit::Utf16CodeUnits,
+        });
+        assert!(matches!(
+            length,
+            KetherError::Preprocessing(PreprocessingError::Length(_))
+        ));
+        assert_eq!(length.inventory().domain, ErrorDomain::Application);
+
+        let other = KetherError::other("store unavailable");
+        let source = std::error::Error::source(&other).unwrap();
+        assert_eq!(source.to_string(), "store unavailable");
+
+        let boxed: Box<dyn std::error::Error + Send + Sync> = network.into();
+        assert!(boxed.to_string().starts_with("network error"));
+    }
+
+    #[test]
+    fn api_errors_carry_their_result_code() {
+        let entry = classify_network_error(&NetworkError::ApiError(EResult::RateLimitExceeded));
+        assert_eq!(entry.summary, "rate limited by Steam");
+        assert_eq!(entry.code, Some(EResult::RateLimitExceeded as i32));
+        assert_eq!(entry.context, None);
+
+        let entry = entry.with_context("chat 1/2");
+        assert_eq!(
+            entry.to_string(),
+            "rate limited by Steam (code 84): chat 1/2"
+        );
+        assert_eq!(classify_network_error(&NetworkError::Timeout).code, None);
+    }
+
+    #[test]
+    fn reports_flatten_the_chain_and_classification() {
+        let err = KetherError::from(OutboxError::Poisoned);
+        let report = err.report("outbox.send");
+        assert_eq!(report.operation, "outbox.send");
+        assert_eq!(report.chain, ["outbox store lock poisoned"]);
+        assert_eq!(report.inventory, err.inventory());
+        assert!(report.timestamp_ms > 0);
+
+        let json: serde_json::Value = serde_json::from_str(&report.to_json().unwrap()).unwrap();
+        assert_eq!(json["operation"], "outbox.send");
+        assert_eq!(json["domain"], "Unknown");
+        assert_eq!(json["disposition"], "Fatal");
+        assert_eq!(json["summary"], "unclassified error");
+        assert_eq!(json["code"], serde_json::Value::Null);
+        assert_eq!(json["chain"][0], "outbox store lock poisoned");
+
+        let io = std::io::Error::other("disk full");
+        let wrapped = KetherError::from(OutboxError::Io(io));
+        assert_eq!(
+            wrapped.report("outbox.send").chain,
+            ["outbox I/O error: disk full", "disk full"]
+        );
+    }
+
     #[test]
     fn invalid_credentials_are_fatal() {
         let entry = classify_login_error(&LoginError::InvalidCredentials);
@@ -0,0 +1,327 @@
+// SPDX-License-Identifier: LGPL-3.0-only
+
+use crate::preprocessing::ChatMentions;
+use std::collections::HashSet;
+use steamid_ng::SteamID;
+
+type RoomEntry = (u64, Option<u64>);
+
+/// Selects which group chat rooms a listener receives messages from.
+///
+/// Entries are `(chat_group_id, Option<chat_id>)`; `None` matches every room in the
+/// group. A message passes when it matches no deny entry and either the allowlist is
+/// empty or it matches an allow entry, so deny entries always win.
+///
+/// # Example
+///
+/// ```
+/// use SC_Sub_Poster::RoomFilter;
+///
+/// let filter = RoomFilter::new()
+///     .allow_group(100)
+///     .allow_room(200, 1)
+///     .deny_room(100, 7);
+/// assert!(filter.matches(100, 3));
+/// assert!(!filter.matches(100, 7));
+/// assert!(!filter.matches(200, 2));
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct RoomFilter {
+    allowed: HashSet<RoomEntry>,
+    denied: HashSet<RoomEntry>,
+}
+
+impl RoomFilter {
+    /// Create a filter that lets every room through.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Allow every room in `chat_group_id`.
+    ///
+    /// # Returns
+    ///
+    /// `Self` for method chaining (builder pattern).
+    pub fn allow_group(mut self, chat_group_id: u64) -> Self {
+        self.allowed.insert((chat_group_id, None));
+        self
+    }
+
+    /// Allow a single room.
+    ///
+    /// # Returns
+    ///
+    /// `Self` for method chaining (builder pattern).
+    pub fn allow_room(mut self, chat_group_id: u64, chat_id: u64) -> Self {
+        self.allowed.insert((chat_group_id, Some(chat_id)));
+        self
+    }
+
+    /// Deny every room in `chat_group_id`.
+    ///
+    /// # Returns
+    ///
+    /// `Self` for method chaining (builder pattern).
+    pub fn deny_group(mut self, chat_group_id: u64) -> Self {
+        self.denied.insert((chat_group_id, None));
+        self
+    }
+
+    /// Deny a single room.
+    ///
+    /// # Returns
+    ///
+    /// `Self` for method chaining (builder pattern).
+    pub fn deny_room(mut self, chat_group_id: u64, chat_id: u64) -> Self {
+        self.denied.insert((chat_group_id, Some(chat_id)));
+        self
+    }
+
+    /// Whether messages from the given room pass the filter.
+    pub fn matches(&self, chat_group_id: u64, chat_id: u64) -> bool {
+        let listed = |entries: &HashSet<RoomEntry>| {
+            entries.contains(&(chat_group_id, None))
+                || entries.contains(&(chat_group_id, Some(chat_id)))
+        };
+
+        !listed(&self.denied) && (self.allowed.is_empty() || listed(&self.allowed))
+    }
+}
+
+impl FromIterator<RoomEntry> for RoomFilter {
+    /// Build an allowlist from `(chat_group_id, Option<chat_id>)` entries.
+    fn from_iter<I: IntoIterator<Item = RoomEntry>>(iter: I) -> Self {
+        Self {
+            allowed: iter.into_iter().collect(),
+            denied: HashSet::new(),
+        }
+    }
+}
+
+/// How directly a message pings an account, from `MentionTargets::level`.
+///
+/// Levels are ordered from weakest to strongest, so `level >= MentionLevel::All` means
+/// pinged by `@all` or individually.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Default)]
+pub enum MentionLevel {
+    /// Not pinged.
+    #[default]
+    None,
+    /// Pinged by `@here`, along with every member online.
+    Here,
+    /// Pinged by `@all`, along with every member.
+    All,
+    /// Mentioned individually.
+    Direct,
+}
+
+/// Selects group messages that mention the listening account.
+///
+/// A message matches when it mentions `@all` or `@here` (unless disabled) or mentions one
+/// of the configured SteamIDs. With no SteamIDs configured, the listening connection's
+/// own SteamID is used. Matching relies on `PreprocessedMessage::mentions`, so every
+/// mention form the preprocessor extracts is covered. `with_minimum_level` keeps only
+/// the stronger pings, e.g. `@all` and direct mentions but not `@here`.
+///
+/// # Example
+///
+/// ```
+/// use SC_Sub_Poster::{ChatMentions, MentionTargets};
+/// use steamid_ng::SteamID;
+///
+/// let own = SteamID::from(76561197960287930);
+/// let mentions = ChatMentions {
+///     mention_all: false,
+///     mention_here: true,
+///     mention_steamids: Vec::new(),
+///     ..Default::default()
+/// };
+/// assert!(MentionTargets::new().matches(&mentions, own));
+/// assert!(!MentionTargets::new().include_broadcasts(false).matches(&mentions, own));
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MentionTargets {
+    steam_ids: HashSet<u64>,
+    broadcasts: bool,
+    minimum: MentionLevel,
+}
+
+impl Default for MentionTargets {
+    fn default() -> Self {
+        Self {
+            steam_ids: HashSet::new(),
+            broadcasts: true,
+            minimum: MentionLevel::Here,
+        }
+    }
+}
+
+impl MentionTargets {
+    /// Match `@all`, `@here` and mentions of the listening account.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Match mentions of `steam_id` instead of (or, when called again, in addition to)
+    /// the listening account.
+    ///
+    /// # Returns
+    ///
+    /// `Self` for method chaining (builder pattern).
+    pub fn with_steam_id(mut self, steam_id: SteamID) -> Self {
+        self.steam_ids.insert(u64::from(steam_id));
+        self
+    }
+
+    /// Whether `@all` and `@here` mentions match.
+    ///
+    /// # Returns
+    ///
+    /// `Self` for method chaining (builder pattern).
+    pub fn include_broadcasts(mut self, include: bool) -> Self {
+        self.broadcasts = include;
+        self
+    }
+
+    /// Only match pings at least as strong as `level` (default `MentionLevel::Here`,
+    /// any ping).
+    ///
+    /// # Returns
+    ///
+    /// `Self` for method chaining (builder pattern).
+    pub fn with_minimum_level(mut self, level: MentionLevel) -> Self {
+        self.minimum = level;
+        self
+    }
+
+    /// How directly `mentions` pings this selection, with `own` as the default SteamID.
+    ///
+    /// `@all` and `@here` count as `MentionLevel::None` when broadcasts are excluded.
+    pub fn level(&self, mentions: &ChatMentions, own: SteamID) -> MentionLevel {
+        let direct = if self.steam_ids.is_empty() {
+            mentions.contains(own)
+        } else {
+            self.steam_ids
+                .iter()
+                .any(|&steam_id| mentions.contains(SteamID::from(steam_id)))
+        };
+        if direct {
+            MentionLevel::Direct
+        } else if !self.broadcasts {
+            MentionLevel::None
+        } else if mentions.mention_all {
+            MentionLevel::All
+        } else if mentions.mention_here {
+            MentionLevel::Here
+        } else {
+            MentionLevel::None
+        }
+    }
+
+    /// Whether `mentions` targets this selection, with `own` as the default SteamID.
+    ///
+    /// A message matches when its `level` is not `MentionLevel::None` and at least the
+    /// minimum level.
+    pub fn matches(&self, mentions: &ChatMentions, own: SteamID) -> bool {
+        let level = self.level(mentions, own);
+        level != MentionLevel::None && level >= self.minimum
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn empty_filter_allows_everything() {
+        assert!(RoomFilter::new().matches(1, 2));
+    }
+
+    #[test]
+    fn deny_entries_beat_allow_entries() {
+        let filter = RoomFilter::new().allow_group(1).deny_group(1);
+        assert!(!filter.matches(1, 2));
+
+        let filter = RoomFilter::new().deny_room(1, 2);
+        assert!(filter.matches(1, 3));
+        assert!(!filter.matches(1, 2));
+    }
+
+    #[test]
+    fn allowlist_from_entries() {
+        let filter: RoomFilter = [(1, Some(2)), (3, None)].into_iter().collect();
+        assert!(filter.matches(1, 2));
+        assert!(!filter.matches(1, 4));
+        assert!(filter.matches(3, 9));
+        assert!(!filter.matches(5, 2));
+    }
+
+    #[test]
+    fn mention_targets_default_to_own_steam_id() {
+        use crate::preprocessing::MentionSteamId;
+
+        let own = SteamID::from(76561197960287930);
+        let other = SteamID::from(76561199491325083);
+        let mentioning = |steam_id| ChatMentions {
+            mention_all: false,
+            mention_here: false,
+            mention_steamids: vec![MentionSteamId(steam_id)],
+            ..Default::default()
+        };
+
+        assert!(MentionTargets::new().matches(&mentioning(own), own));
+        assert!(!MentionTargets::new().matches(&mentioning(other), own));
+
+        let targets = MentionTargets::new().with_steam_id(other);
+        assert!(targets.matches(&mentioning(other), own));
+        assert!(!targets.matches(&mentioning(own), own));
+    }
+
+    #[test]
+    fn mention_levels_and_minimum() {
+        use crate::preprocessing::MentionSteamId;
+
+        let own = SteamID::from(76561197960287930);
+        let mentions = |all, here, direct| ChatMentions {
+            mention_all: all,
+            mention_here: here,
+            mention_steamids: if direct {
+                vec![MentionSteamId(own)]
+            } else {
+                Vec::new()
+            },
+            ..Default::default()
+        };
+        let targets = MentionTargets::new();
+        assert_eq!(
+            targets.level(&mentions(false, false, false), own),
+            MentionLevel::None
+        );
+        assert_eq!(
+            targets.level(&mentions(false, true, false), own),
+            MentionLevel::Here
+        );
+        assert_eq!(
+            targets.level(&mentions(true, true, false), own),
+            MentionLevel::All
+        );
+        assert_eq!(
+            targets.level(&mentions(true, false, true), own),
+            MentionLevel::Direct
+        );
+        assert_eq!(
+            targets
+                .clone()
+                .include_broadcasts(false)
+                .level(&mentions(true, true, false), own),
+            MentionLevel::None
+        );
+
+        let important = MentionTargets::new().with_minimum_level(MentionLevel::All);
+        assert!(!important.matches(&mentions(false, true, false), own));
+        assert!(important.matches(&mentions(true, false, false), own));
+        assert!(important.matches(&mentions(false, false, true), own));
+        let everything = MentionTargets::new().with_minimum_level(MentionLevel::None);
+        assert!(!everything.matches(&mentions(false, false, false), own));
+    }
+}
@@ -24,30 +24,112 @@ pub type LogOn = KetherSteamClient;
 // Re-export chat room types
 pub use chatroom::helpers as chat_helpers;
 pub use chatroom::{
-    ChatGroupInfo, ChatMessageHistoryEntry, ChatRoomClient, ChatRoomGroups, ChatRoomInfo,
-    ChatRoomMessaging, ChatRoomNotifications, EnhancedGroupChatMessage, FriendMessage,
-    GroupChatMessage, MessageReactionInfo, ReactionEvent, ReactionType, SendGroupMessageParams,
+    ChatEntryType, ChatError, ChatEvent, ChatGroupInfo, ChatGroupState, ChatGroupStateDiff,
+    ChatMessageHistoryEntry, ChatRoomClient, ChatRoomGroups, ChatRoomInfo, ChatRoomMessaging,
+    ChatRoomNotifications, EchoedGroupMessage, EnhancedFriendMessage, EnhancedGroupChatMessage,
+    FriendActivity, FriendMessage, GroupChatMessage, GroupHeaderChanged, GroupInvite,
+    GroupMessageStream, MemberChange, MemberChangeEvent, MessageDeletion, MessageDeletionOutcome,
+    MessageModification, MessageModifiedEvent, MessageReactionInfo, MessagingDefaults,
+    NotificationDispatchError, NotificationOptions, OrderedMessaging, OriginalDeletion,
+    OverflowPolicy, PanicPolicy, ReactionEvent, ReactionType, ReplacedMessage, ResubscribePolicy,
+    RetryPolicy, SendGroupMessageParams,
 };
 
+// Re-export message bus types
+pub use bus::{BusEvent, BusReceiver, MessageBus, MessageMatcher};
+
+// Re-export catch-up types
+pub use catchup::{
+    CatchUpCursor, CursorStore, CursorStoreError, JsonFileCursorStore, MemoryCursorStore,
+    MessagePosition,
+};
+
+// Re-export dedupe types
+pub use dedupe::{DedupeCache, DuplicateMessageError};
+
+// Re-export filter types
+pub use filter::{MentionLevel, MentionTargets, RoomFilter};
+
+// Re-export listener types
+pub use listener::{ListenerError, ListenerHandle, ListenerStats};
+
+// Re-export metrics types
+pub use metrics::{AtomicMetrics, MessagingMetrics, MetricsSnapshot};
+
+// Re-export middleware types
+pub use middleware::{IgnoreOwnMessages, MessageMiddleware, MiddlewareFuture, Next, TraceMessages};
+
+// Re-export outbox types
+pub use outbox::{JsonFileOutboxStore, MemoryOutboxStore, Outbox, OutboxEntry, OutboxStore};
+
+// Re-export persona types
+pub use personas::{PersonaCache, ResolvedMention};
+
+// Re-export read state types
+pub use readstate::{MAX_TRACKED_UNREAD, ReadState};
+
+// Re-export scheduling types
+pub use scheduler::{ScheduleError, ScheduledHandle};
+
+// Re-export sequential dispatch types
+pub use sequence::{GapDetected, SequentialDispatch};
+
+// Re-export watchdog types
+pub use watchdog::{StreamStalled, Watchdog};
+
 // Re-export preprocessing types
 pub use preprocessing::helpers as preprocessing_helpers;
 pub use preprocessing::{
-    BBCodeContent, BBCodeNode, ChatMentions, MentionSteamId, MessagePreprocessor,
-    PreprocessedMessage,
+    BBCodeContent, BBCodeDiagnostic, BBCodeDiagnosticKind, BBCodeError, BBCodeNode, ChatCommand,
+    ChatMentions, EmoticonUse, ExtractedUrl, FormatError, GameInviteInfo, ImageRef, ItemLink,
+    LengthError, LengthUnit, MalformedTagPolicy, MentionKind, MentionPunctuation, MentionRoster,
+    MentionRules, MentionSpan, MentionSteamId, MessageBuilder, MessageChange, MessageChangeKind,
+    MessagePreprocessor, MessageSegment, MessageTemplate, NameMention, ParseLimits,
+    PreprocessedMessage, PreprocessingError, QuoteInfo, RandomResult, RoomEffect, SchemaError,
+    StickerInfo, TagContext, TemplateError, TradeOfferRef, Visitor,
 };
 
+/// Broadcast of incoming group messages to multiple subscribers.
+pub mod bus;
+/// Resuming group message delivery after downtime.
+pub mod catchup;
 /// Steam chat room operations and messaging.
 pub mod chatroom;
+/// Duplicate-send suppression for outgoing messages.
+pub mod dedupe;
 /// Error classification and retry guidance utilities.
 pub mod errors;
+/// Room and mention selection for group message listeners.
+pub mod filter;
+/// Background listeners with graceful shutdown.
+pub mod listener;
 /// Steam authentication and connection management.
 pub mod logon;
+/// Counters and latency metrics for messaging operations.
+pub mod metrics;
+/// Composable layers around group message callbacks.
+pub mod middleware;
+/// Per-room ordering of concurrent sends.
+mod ordering;
+/// Persistent outbox for resending group messages after reconnects.
+pub mod outbox;
+/// Persona name lookups for mentioned accounts.
+pub mod personas;
 /// Message preprocessing utilities for BBCode and mentions.
 pub mod preprocessing;
+/// Unread counts and read cursors for group chat rooms.
+pub mod readstate;
+/// Delayed delivery of group messages.
+pub mod scheduler;
+/// Per-room sequential dispatch and ordinal gap detection.
+pub mod sequence;
+/// Idle detection and connection probing for notification listeners.
+pub mod watchdog;
 
 pub use errors::{
-    ErrorDomain, ErrorInventoryEntry, RetryDisposition, classify_connection_error,
-    classify_login_error, classify_network_error,
+    Backoff, ErrorDomain, ErrorInventoryEntry, ErrorReport, JitterMode, KetherError,
+    RetryDisposition, classify_connection_error, classify_dispatch_error, classify_login_error,
+    classify_network_error,
 };
 
 #[cfg(test)]
@@ -0,0 +1,209 @@
+// SPDX-License-Identifier: LGPL-3.0-only
+
+use std::error::Error;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::task::{Context, Poll};
+use std::time::Duration;
+use thiserror::Error;
+use tokio::sync::Notify;
+use tokio::task::JoinHandle;
+
+/// Errors reported by a [`ListenerHandle`].
+#[derive(Debug, Error)]
+pub enum ListenerError {
+    /// The notification stream failed or the callback returned an error.
+    #[error("listener stopped with an error: {0}")]
+    Failed(#[source] Box<dyn Error + Send + Sync>),
+    /// The background task panicked or was aborted.
+    #[error("listener task failed: {0}")]
+    Task(#[from] tokio::task::JoinError),
+}
+
+/// Point-in-time counters of one spawned listener, from `ListenerHandle::stats`.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ListenerStats {
+    /// Notifications received by the listener.
+    pub notifications: u64,
+    /// Callback calls that returned `Ok`.
+    pub callbacks_succeeded: u64,
+    /// Callback calls that returned an error.
+    pub callbacks_failed: u64,
+    /// Time between the server timestamp of the latest notification and its dispatch.
+    ///
+    /// A growing lag means the callback cannot keep up with room traffic. Server
+    /// timestamps have one-second resolution.
+    pub lag: Duration,
+    /// Resubscriptions after stream errors.
+    pub reconnects: u64,
+}
+
+/// Counters behind `ListenerStats`, updated by the listener task.
+#[derive(Debug, Default)]
+pub(crate) struct ListenerCounters {
+    notifications: AtomicU64,
+    callbacks_succeeded: AtomicU64,
+    callbacks_failed: AtomicU64,
+    lag_ms: AtomicU64,
+    reconnects: AtomicU64,
+}
+
+impl ListenerCounters {
+    pub(crate) fn record_notification(&self, lag: Duration) {
+        self.notifications.fetch_add(1, Ordering::Relaxed);
+        let lag_ms = u64::try_from(lag.as_millis()).unwrap_or(u64::MAX);
+        self.lag_ms.store(lag_ms, Ordering::Relaxed);
+    }
+
+    pub(crate) fn record_callback(&self, succeeded: bool) {
+        let counter = if succeeded {
+            &self.callbacks_succeeded
+        } else {
+            &self.callbacks_failed
+        };
+        counter.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub(crate) fn record_reconnect(&self) {
+        self.reconnects.fetch_add(1, Ordering::Relaxed);
+    }
+
+    fn snapshot(&self) -> ListenerStats {
+        ListenerStats {
+            notifications: self.notifications.load(Ordering::Relaxed),
+            callbacks_succeeded: self.callbacks_succeeded.load(Ordering::Relaxed),
+            callbacks_failed: self.callbacks_failed.load(Ordering::Relaxed),
+            lag: Duration::from_millis(self.lag_ms.load(Ordering::Relaxed)),
+            reconnects: self.reconnects.load(Ordering::Relaxed),
+        }
+    }
+}
+
+/// Handle to a listener started with `ChatRoomClient::spawn_group_listener` or
+/// `ChatRoomClient::spawn_friend_listener`.
+///
+/// `stop` shuts the listener down gracefully: no further notifications are pulled from the
+/// stream, but a callback that is already running finishes first. Awaiting the handle (or
+/// calling `join`) yields the listener's final result, which is `Ok(())` after a stop.
+/// Dropping the handle detaches the listener, which then runs until the stream ends.
+#[derive(Debug)]
+pub struct ListenerHandle {
+    stop: Arc<Notify>,
+    stats: Arc<ListenerCounters>,
+    task: JoinHandle<Result<(), ListenerError>>,
+}
+
+impl ListenerHandle {
+    pub(crate) fn spawn<F, Fut>(listen: F) -> Self
+    where
+        F: FnOnce(Shutdown, Arc<ListenerCounters>) -> Fut,
+        Fut: Future<Output = Result<(), Box<dyn Error + Send + Sync>>> + Send + 'static,
+    {
+        let stop = Arc::new(Notify::new());
+        let stats = Arc::new(ListenerCounters::default());
+        let listener = listen(Shutdown(stop.clone()), stats.clone());
+        let task = tokio::spawn(async move { listener.await.map_err(ListenerError::Failed) });
+
+        Self { stop, stats, task }
+    }
+
+    /// Ask the listener to stop after the in-flight callback, if any, returns.
+    pub fn stop(&self) {
+        self.stop.notify_one();
+    }
+
+    /// Throughput, callback outcomes, dispatch lag and reconnects of this listener.
+    pub fn stats(&self) -> ListenerStats {
+        self.stats.snapshot()
+    }
+
+    /// Whether the listener task is still running.
+    pub fn is_running(&self) -> bool {
+        !self.task.is_finished()
+    }
+
+    /// Wait for the listener to finish.
+    ///
+    /// # Errors
+    ///
+    /// Returns `ListenerError::Failed` if the stream or callback failed, or
+    /// `ListenerError::Task` if the listener task panicked.
+    pub async fn join(self) -> Result<(), ListenerError> {
+        self.await
+    }
+}
+
+impl Future for ListenerHandle {
+    type Output = Result<(), ListenerError>;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        Pin::new(&mut self.task)
+            .poll(cx)
+            .map(|joined| joined.unwrap_or_else(|err| Err(ListenerError::Task(err))))
+    }
+}
+
+/// Stop signal handed to a spawned listener.
+pub(crate) struct Shutdown(Arc<Notify>);
+
+impl Shutdown {
+    /// Resolves once `ListenerHandle::stop` has been called.
+    pub(crate) async fn requested(self) {
+        self.0.notified().await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn stop_resolves_the_listener_with_ok() {
+        let handle = ListenerHandle::spawn(|shutdown, _| async move {
+            shutdown.requested().await;
+            Ok(())
+        });
+        assert!(handle.is_running());
+        handle.stop();
+        tokio::time::timeout(Duration::from_secs(1), handle.join())
+            .await
+            .expect("listener stops")
+            .expect("clean shutdown");
+    }
+
+    #[tokio::test]
+    async fn listener_errors_are_reported() {
+        let handle = ListenerHandle::spawn(|_, _| async { Err("stream closed".into()) });
+        assert!(matches!(handle.await, Err(ListenerError::Failed(_))));
+    }
+
+    #[tokio::test]
+    async fn stats_reflect_the_listener_counters() {
+        let handle = ListenerHandle::spawn(|shutdown, stats| async move {
+            stats.record_notification(Duration::from_secs(2));
+            stats.record_callback(true);
+            stats.record_callback(false);
+            stats.record_reconnect();
+            shutdown.requested().await;
+            Ok(())
+        });
+        while handle.stats().reconnects == 0 {
+            tokio::task::yield_now().await;
+        }
+
+        assert_eq!(
+            handle.stats(),
+            ListenerStats {
+                notifications: 1,
+                callbacks_succeeded: 1,
+                callbacks_failed: 1,
+                lag: Duration::from_secs(2),
+                reconnects: 1,
+            }
+        );
+        handle.stop();
+        handle.join().await.expect("clean shutdown");
+    }
+}
@@ -1,9 +1,10 @@
 // SPDX-License-Identifier: LGPL-3.0-only
 
+use crate::chatroom::RetryPolicy;
 use crate::errors::{
-    ErrorDomain, ErrorInventoryEntry, RetryDisposition, classify_connection_error,
+    ErrorDomain, ErrorInventoryEntry, ErrorReport, KetherError, RetryDisposition,
+    classify_connection_error, classify_network_error,
 };
-use std::error::Error;
 use std::net::IpAddr;
 use steam_vent::auth::{
     AuthConfirmationHandler, ConsoleAuthConfirmationHandler, DeviceConfirmationHandler,
@@ -22,32 +23,60 @@ pub struct KetherSteamClient {
 impl KetherSteamClient {
     /// Create a new Steam client with provided credentials
     #[instrument(name = "kether.logon.new", skip(password))]
-    pub async fn new(account: &str, password: &str) -> Result<Self, Box<dyn Error>> {
+    pub async fn new(account: &str, password: &str) -> Result<Self, KetherError> {
+        let context = |err: LogonError| err.with_context("credential logon");
         let server_list = bootstrap::discover_servers()
             .await
-            .map_err(|err| -> Box<dyn Error> { Box::new(LogonError::from(err)) })?;
+            .map_err(|err| context(err.into()))?;
         let connection = bootstrap::credential_login(&server_list, account, password)
             .await
-            .map_err(|err| -> Box<dyn Error> { Box::new(LogonError::from(err)) })?;
+            .map_err(|err| context(err.into()))?;
 
-        let connection = Self::validate_and_finalize_connection(connection)?;
+        let connection = Self::validate_and_finalize_connection(connection).map_err(context)?;
 
         info!(steam_id = %connection.steam_id().steam3(), "logon successful");
 
         Ok(Self { connection })
     }
 
+    /// Create a new Steam client with provided credentials, retrying transient failures.
+    ///
+    /// Dropped connections, rate limiting and other errors classified as `ImmediateRetry`
+    /// or `BackoffRetry` are retried after the delay `retry.backoff` gives for the
+    /// attempt; invalid credentials and Steam Guard prompts fail right away.
+    ///
+    /// # Arguments
+    ///
+    /// * `account` - Steam account name
+    /// * `password` - Steam account password
+    /// * `retry` - Attempt limit and `Backoff` between logon attempts
+    ///
+    /// # Errors
+    ///
+    /// Returns the last logon error once `retry` gives up or the error is not retryable.
+    #[instrument(name = "kether.logon.new_with_retry", skip(password, retry))]
+    pub async fn new_with_retry(
+        account: &str,
+        password: &str,
+        retry: RetryPolicy,
+    ) -> Result<Self, KetherError> {
+        retry
+            .run("credential logon", || Self::new(account, password))
+            .await
+    }
+
     /// Create an anonymous Steam client for testing
     #[instrument(name = "kether.logon.new_anonymous")]
-    pub async fn new_anonymous() -> Result<Self, Box<dyn Error>> {
+    pub async fn new_anonymous() -> Result<Self, KetherError> {
+        let context = |err: LogonError| err.with_context("anonymous logon");
         let server_list = bootstrap::discover_servers()
             .await
-            .map_err(|err| -> Box<dyn Error> { Box::new(LogonError::from(err)) })?;
+            .map_err(|err| context(err.into()))?;
         let connection = bootstrap::anonymous_login(&server_list)
             .await
-            .map_err(|err| -> Box<dyn Error> { Box::new(LogonError::from(err)) })?;
+            .map_err(|err| context(err.into()))?;
 
-        let connection = Self::validate_and_finalize_connection(connection)?;
+        let connection = Self::validate_and_finalize_connection(connection).map_err(context)?;
 
         info!(steam_id = %connection.steam_id().steam3(), "anonymous logon successful");
 
@@ -55,10 +84,8 @@ impl KetherSteamClient {
     }
 
     /// Common validation and finalization logic for connections
-    fn validate_and_finalize_connection(
-        connection: Connection,
-    ) -> Result<Connection, Box<dyn Error>> {
-        ensure_valid_connection(&connection).map_err(|err| -> Box<dyn Error> { Box::new(err) })?;
+    fn validate_and_finalize_connection(connection: Connection) -> Result<Connection, LogonError> {
+        ensure_valid_connection(&connection)?;
         Ok(connection)
     }
 
@@ -84,7 +111,7 @@ impl KetherSteamClient {
 
     /// Test if the connection is working by requesting app info
     #[instrument(name = "kether.logon.test_connection", skip(self))]
-    pub async fn test_connection(&self) -> Result<(), Box<dyn Error>> {
+    pub async fn test_connection(&self) -> Result<(), KetherError> {
         use steam_vent_proto::steammessages_clientserver_appinfo::{
             CMsgClientPICSProductInfoRequest, CMsgClientPICSProductInfoResponse,
             cmsg_client_picsproduct_info_request,
@@ -102,13 +129,17 @@ impl KetherSteamClient {
             ..Default::default()
         };
 
-        let _response: CMsgClientPICSProductInfoResponse = self.connection.job(req).await?;
+        let _response: CMsgClientPICSProductInfoResponse = self
+            .connection
+            .job(req)
+            .await
+            .map_err(|err| LogonError::from(err).with_context("PICS product info request"))?;
         info!("connection round-trip succeeded");
         Ok(())
     }
 
     /// Get owned games for the logged-in user
-    pub async fn get_owned_games(&self) -> Result<Vec<GameInfo>, Box<dyn Error>> {
+    pub async fn get_owned_games(&self) -> Result<Vec<GameInfo>, KetherError> {
         use steam_vent_proto::steammessages_player_steamclient::CPlayer_GetOwnedGames_Request;
 
         let req = CPlayer_GetOwnedGames_Request {
@@ -118,7 +149,11 @@ impl KetherSteamClient {
             ..CPlayer_GetOwnedGames_Request::default()
         };
 
-        let games = self.connection.service_method(req).await?;
+        let games = self
+            .connection
+            .service_method(req)
+            .await
+            .map_err(|err| LogonError::from(err).with_context("GetOwnedGames request"))?;
 
         let game_info: Vec<GameInfo> = games
             .games
@@ -252,6 +287,15 @@ pub enum LogonError {
         /// Error classification and retry guidance.
         inventory: ErrorInventoryEntry,
     },
+    /// A request on the established connection failed.
+    #[error("request failed: {source}")]
+    Request {
+        /// The underlying network error.
+        #[source]
+        source: steam_vent::NetworkError,
+        /// Error classification and retry guidance.
+        inventory: ErrorInventoryEntry,
+    },
     /// Invalid session state detected after connection (e.g., zero Steam ID or session ID).
     #[error("invalid session state: {message}")]
     InvariantViolation {
@@ -267,15 +311,35 @@ impl LogonError {
     ///
     /// # Returns
     ///
-    /// An `ErrorInventoryEntry` describing the error domain, retry disposition, and description.
+    /// An `ErrorInventoryEntry` describing the error domain, retry disposition, and summary,
+    /// with the failed step as context.
     pub fn inventory(&self) -> ErrorInventoryEntry {
         match self {
             LogonError::Discovery { inventory, .. }
             | LogonError::Connection { inventory, .. }
-            | LogonError::InvariantViolation { inventory, .. } => *inventory,
+            | LogonError::Request { inventory, .. }
+            | LogonError::InvariantViolation { inventory, .. } => inventory.clone(),
         }
     }
 
+    /// Report this error, raised during `operation`, with its own classification.
+    pub fn report(&self, operation: &str) -> ErrorReport {
+        ErrorReport::new(operation, self, self.inventory())
+    }
+
+    /// Record which step failed; `context` must not name the account.
+    fn with_context(mut self, context: &str) -> Self {
+        match &mut self {
+            LogonError::Discovery { inventory, .. }
+            | LogonError::Connection { inventory, .. }
+            | LogonError::Request { inventory, .. }
+            | LogonError::InvariantViolation { inventory, .. } => {
+                inventory.context = Some(context.to_string());
+            }
+        }
+        self
+    }
+
     fn discovery_err(source: steam_vent::ServerDiscoveryError) -> Self {
         LogonError::Discovery {
             source,
@@ -315,6 +379,16 @@ impl From<steam_vent::ConnectionError> for LogonError {
     }
 }
 
+impl From<steam_vent::NetworkError> for LogonError {
+    fn from(value: steam_vent::NetworkError) -> Self {
+        let inventory = classify_network_error(&value);
+        LogonError::Request {
+            source: value,
+            inventory,
+        }
+    }
+}
+
 fn ensure_valid_connection(connection: &Connection) -> Result<(), LogonError> {
     let steam_id = connection.steam_id();
     if steam_id.account_id() == 0 {
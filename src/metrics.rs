@@ -0,0 +1,262 @@
+// SPDX-License-Identifier: LGPL-3.0-only
+
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Duration;
+
+use crate::errors::{
+    ErrorDomain, ErrorInventoryEntry, KetherError, RetryDisposition, classify_network_error,
+};
+
+/// Recorder for messaging counters and send latency.
+///
+/// Install one with `ChatRoomClient::with_metrics`. Every method has an empty default
+/// implementation so recorders only need to handle the events they care about. When no
+/// recorder is installed no timing or classification work is done.
+pub trait MessagingMetrics: Send + Sync {
+    /// A message was sent successfully after `latency`.
+    fn record_send_ok(&self, latency: Duration) {
+        let _ = latency;
+    }
+
+    /// A send failed; `classification` describes the failure.
+    fn record_send_err(&self, classification: ErrorInventoryEntry) {
+        let _ = classification;
+    }
+
+    /// `count` messages were deleted in one request.
+    fn record_delete(&self, count: usize) {
+        let _ = count;
+    }
+
+    /// A notification was delivered to a listener.
+    fn record_notification(&self) {}
+
+    /// A resilient listener resubscribed after its notification stream failed.
+    fn record_resubscribe(&self) {}
+
+    /// A listener dropped a notification because its buffer was full.
+    fn record_notification_dropped(&self) {}
+
+    /// A spawned or resilient listener started handling a message `lag` after its
+    /// server timestamp.
+    fn record_dispatch_lag(&self, lag: Duration) {
+        let _ = lag;
+    }
+
+    /// A spawned or resilient listener's callback returned; `succeeded` is whether it
+    /// returned `Ok`.
+    fn record_callback(&self, succeeded: bool) {
+        let _ = succeeded;
+    }
+}
+
+/// Upper bounds of the send latency buckets in `AtomicMetrics`, in milliseconds.
+///
+/// Sends slower than the last bound are counted in a final overflow bucket.
+pub const LATENCY_BUCKETS_MS: [u64; 7] = [25, 50, 100, 250, 500, 1000, 2500];
+
+const BUCKET_COUNT: usize = LATENCY_BUCKETS_MS.len() + 1;
+
+/// Lock-free `MessagingMetrics` implementation backed by atomic counters.
+#[derive(Debug, Default)]
+pub struct AtomicMetrics {
+    sends_ok: AtomicU64,
+    sends_failed: AtomicU64,
+    retryable_failures: AtomicU64,
+    deletes: AtomicU64,
+    notifications: AtomicU64,
+    resubscribes: AtomicU64,
+    notifications_dropped: AtomicU64,
+    callbacks_succeeded: AtomicU64,
+    callbacks_failed: AtomicU64,
+    dispatch_lag_ms: AtomicU64,
+    latency_total_us: AtomicU64,
+    latency_max_us: AtomicU64,
+    latency_buckets: [AtomicU64; BUCKET_COUNT],
+}
+
+/// Point-in-time copy of the counters in `AtomicMetrics`.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct MetricsSnapshot {
+    /// Successful sends.
+    pub sends_ok: u64,
+    /// Failed sends.
+    pub sends_failed: u64,
+    /// Failed sends whose classification allows a retry.
+    pub retryable_failures: u64,
+    /// Messages deleted.
+    pub deletes: u64,
+    /// Notifications delivered to listeners.
+    pub notifications: u64,
+    /// Notification stream resubscriptions by resilient listeners.
+    pub resubscribes: u64,
+    /// Notifications dropped by listeners with a full buffer.
+    pub notifications_dropped: u64,
+    /// Listener callbacks that returned `Ok`.
+    pub callbacks_succeeded: u64,
+    /// Listener callbacks that returned an error.
+    pub callbacks_failed: u64,
+    /// Dispatch lag of the most recent listener message.
+    pub dispatch_lag: Duration,
+    /// Sum of successful send latencies.
+    pub latency_total: Duration,
+    /// Slowest successful send.
+    pub latency_max: Duration,
+    /// Successful sends per latency bucket, matching `LATENCY_BUCKETS_MS` plus an overflow bucket.
+    pub latency_buckets: [u64; BUCKET_COUNT],
+}
+
+impl MetricsSnapshot {
+    /// Mean latency of successful sends, or `None` if nothing was sent.
+    pub fn mean_latency(&self) -> Option<Duration> {
+        u32::try_from(self.sends_ok)
+            .ok()
+            .filter(|&sends| sends > 0)
+            .map(|sends| self.latency_total / sends)
+    }
+}
+
+impl AtomicMetrics {
+    /// Create a recorder with all counters at zero.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Read the current counter values.
+    pub fn snapshot(&self) -> MetricsSnapshot {
+        MetricsSnapshot {
+            sends_ok: self.sends_ok.load(Ordering::Relaxed),
+            sends_failed: self.sends_failed.load(Ordering::Relaxed),
+            retryable_failures: self.retryable_failures.load(Ordering::Relaxed),
+            deletes: self.deletes.load(Ordering::Relaxed),
+            notifications: self.notifications.load(Ordering::Relaxed),
+            resubscribes: self.resubscribes.load(Ordering::Relaxed),
+            notifications_dropped: self.notifications_dropped.load(Ordering::Relaxed),
+            callbacks_succeeded: self.callbacks_succeeded.load(Ordering::Relaxed),
+            callbacks_failed: self.callbacks_failed.load(Ordering::Relaxed),
+            dispatch_lag: Duration::from_millis(self.dispatch_lag_ms.load(Ordering::Relaxed)),
+            latency_total: Duration::from_micros(self.latency_total_us.load(Ordering::Relaxed)),
+            latency_max: Duration::from_micros(self.latency_max_us.load(Ordering::Relaxed)),
+            latency_buckets: std::array::from_fn(|i| {
+                self.latency_buckets[i].load(Ordering::Relaxed)
+            }),
+        }
+    }
+}
+
+impl MessagingMetrics for AtomicMetrics {
+    fn record_send_ok(&self, latency: Duration) {
+        let micros = u64::try_from(latency.as_micros()).unwrap_or(u64::MAX);
+        let millis = micros / 1000;
+        let bucket = LATENCY_BUCKETS_MS
+            .iter()
+            .position(|&bound| millis <= bound)
+            .unwrap_or(LATENCY_BUCKETS_MS.len());
+
+        self.sends_ok.fetch_add(1, Ordering::Relaxed);
+        self.latency_total_us.fetch_add(micros, Ordering::Relaxed);
+        self.latency_max_us.fetch_max(micros, Ordering::Relaxed);
+        self.latency_buckets[bucket].fetch_add(1, Ordering::Relaxed);
+    }
+
+    fn record_send_err(&self, classification: ErrorInventoryEntry) {
+        self.sends_failed.fetch_add(1, Ordering::Relaxed);
+        if matches!(
+            classification.disposition,
+            RetryDisposition::ImmediateRetry | RetryDisposition::BackoffRetry
+        ) {
+            self.retryable_failures.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    fn record_delete(&self, count: usize) {
+        self.deletes.fetch_add(count as u64, Ordering::Relaxed);
+    }
+
+    fn record_notification(&self) {
+        self.notifications.fetch_add(1, Ordering::Relaxed);
+    }
+
+    fn record_resubscribe(&self) {
+        self.resubscribes.fetch_add(1, Ordering::Relaxed);
+    }
+
+    fn record_notification_dropped(&self) {
+        self.notifications_dropped.fetch_add(1, Ordering::Relaxed);
+    }
+
+    fn record_dispatch_lag(&self, lag: Duration) {
+        let lag_ms = u64::try_from(lag.as_millis()).unwrap_or(u64::MAX);
+        self.dispatch_lag_ms.store(lag_ms, Ordering::Relaxed);
+    }
+
+    fn record_callback(&self, succeeded: bool) {
+        let counter = if succeeded {
+            &self.callbacks_succeeded
+        } else {
+            &self.callbacks_failed
+        };
+        counter.fetch_add(1, Ordering::Relaxed);
+    }
+}
+
+/// Classify a send failure for `MessagingMetrics::record_send_err`.
+///
+/// Network errors use `classify_network_error`; anything else was rejected locally
+/// (invalid BBCode, duplicate suppression) and is reported as a fatal application error.
+pub(crate) fn classify_send_error(err: &KetherError) -> ErrorInventoryEntry {
+    match err.network_error() {
+        Some(network) => classify_network_error(network),
+        None => ErrorInventoryEntry::new(
+            ErrorDomain::Application,
+            RetryDisposition::Fatal,
+            "send rejected before reaching Steam",
+        ),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn atomic_metrics_bucket_latencies_and_count_failures() {
+        let metrics = AtomicMetrics::new();
+        metrics.record_send_ok(Duration::from_millis(10));
+        metrics.record_send_ok(Duration::from_millis(300));
+        metrics.record_send_ok(Duration::from_secs(5));
+        metrics.record_send_err(classify_send_error(
+            &steam_vent::NetworkError::Timeout.into(),
+        ));
+        metrics.record_send_err(classify_send_error(&KetherError::other("duplicate")));
+        metrics.record_delete(3);
+        metrics.record_notification();
+        metrics.record_resubscribe();
+        metrics.record_notification_dropped();
+        metrics.record_dispatch_lag(Duration::from_secs(3));
+        metrics.record_callback(true);
+        metrics.record_callback(false);
+
+        let snapshot = metrics.snapshot();
+        assert_eq!(snapshot.sends_ok, 3);
+        assert_eq!(snapshot.sends_failed, 2);
+        assert_eq!(snapshot.retryable_failures, 1);
+        assert_eq!(snapshot.deletes, 3);
+        assert_eq!(snapshot.notifications, 1);
+        assert_eq!(snapshot.resubscribes, 1);
+        assert_eq!(snapshot.notifications_dropped, 1);
+        assert_eq!(snapshot.callbacks_succeeded, 1);
+        assert_eq!(snapshot.callbacks_failed, 1);
+        assert_eq!(snapshot.dispatch_lag, Duration::from_secs(3));
+        assert_eq!(snapshot.latency_max, Duration::from_secs(5));
+        assert_eq!(snapshot.latency_buckets[0], 1);
+        assert_eq!(snapshot.latency_buckets[4], 1);
+        assert_eq!(snapshot.latency_buckets[BUCKET_COUNT - 1], 1);
+        assert_eq!(snapshot.mean_latency(), Some(Duration::from_millis(1770)));
+    }
+
+    #[test]
+    fn empty_snapshot_has_no_mean_latency() {
+        assert_eq!(AtomicMetrics::new().snapshot().mean_latency(), None);
+    }
+}
@@ -0,0 +1,188 @@
+// SPDX-License-Identifier: LGPL-3.0-only
+
+use crate::chatroom::{CallbackResult, EnhancedGroupChatMessage};
+use std::pin::Pin;
+use std::sync::Arc;
+use steamid_ng::SteamID;
+use tracing::Instrument;
+
+/// Boxed future returned by `MessageMiddleware::handle` and `Next::run`.
+pub type MiddlewareFuture<'a> = Pin<Box<dyn Future<Output = CallbackResult> + Send + 'a>>;
+
+/// A layer around the terminal callback of `listen_for_group_messages_through`.
+///
+/// Middleware runs in the order given to the listener. Each layer receives the message
+/// and a `Next` for the rest of the chain; it can inspect or rewrite the message, call
+/// `next.run(message).await` and inspect the result, or short-circuit by returning
+/// without calling `next`, in which case neither the later layers nor the callback see
+/// the message. Returning an error stops the listener like a callback error.
+///
+/// # Example
+///
+/// ```
+/// use SC_Sub_Poster::{EnhancedGroupChatMessage, MessageMiddleware, MiddlewareFuture, Next};
+///
+/// /// Drops messages that look like bot commands.
+/// struct SkipCommands;
+///
+/// impl MessageMiddleware for SkipCommands {
+///     fn handle<'a>(
+///         &'a self,
+///         message: EnhancedGroupChatMessage,
+///         next: Next<'a>,
+///     ) -> MiddlewareFuture<'a> {
+///         if message.message.starts_with('!') {
+///             return Box::pin(async { Ok(()) });
+///         }
+///         next.run(message)
+///     }
+/// }
+/// ```
+pub trait MessageMiddleware: Send + Sync {
+    /// Handle `message`, usually by passing it on with `next.run(message)`.
+    fn handle<'a>(
+        &'a self,
+        message: EnhancedGroupChatMessage,
+        next: Next<'a>,
+    ) -> MiddlewareFuture<'a>;
+}
+
+/// The remaining middleware and the terminal callback.
+pub struct Next<'a> {
+    chain: &'a [Arc<dyn MessageMiddleware>],
+    terminal: &'a mut (dyn FnMut(EnhancedGroupChatMessage) -> CallbackResult + Send),
+}
+
+impl<'a> Next<'a> {
+    pub(crate) fn new(
+        chain: &'a [Arc<dyn MessageMiddleware>],
+        terminal: &'a mut (dyn FnMut(EnhancedGroupChatMessage) -> CallbackResult + Send),
+    ) -> Self {
+        Self { chain, terminal }
+    }
+
+    /// Pass `message` to the next layer, or to the callback after the last one.
+    pub fn run(self, message: EnhancedGroupChatMessage) -> MiddlewareFuture<'a> {
+        match self.chain.split_first() {
+            Some((layer, rest)) => layer.handle(
+                message,
+                Next {
+                    chain: rest,
+                    terminal: self.terminal,
+                },
+            ),
+            None => Box::pin(async move { (self.terminal)(message) }),
+        }
+    }
+}
+
+/// Runs the rest of the chain inside a `group_message` tracing span.
+///
+/// The span records the room, sender and ordinal, so every event logged while the
+/// message is handled can be attributed to it.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct TraceMessages;
+
+impl MessageMiddleware for TraceMessages {
+    fn handle<'a>(
+        &'a self,
+        message: EnhancedGroupChatMessage,
+        next: Next<'a>,
+    ) -> MiddlewareFuture<'a> {
+        let span = tracing::info_span!(
+            "group_message",
+            chat_group_id = message.chat_group_id,
+            chat_id = message.chat_id,
+            sender = u64::from(message.sender_steam_id),
+            ordinal = message.ordinal,
+        );
+        Box::pin(next.run(message).instrument(span))
+    }
+}
+
+/// Drops messages sent by one account, typically the bot's own.
+///
+/// Useful when `NotificationOptions::ignore_own_messages` is disabled for other
+/// consumers but a particular chain must still skip its own echoes.
+#[derive(Debug, Clone, Copy)]
+pub struct IgnoreOwnMessages {
+    own_steam_id: SteamID,
+}
+
+impl IgnoreOwnMessages {
+    /// Drop messages sent by `own_steam_id`.
+    pub fn new(own_steam_id: SteamID) -> Self {
+        Self { own_steam_id }
+    }
+}
+
+impl MessageMiddleware for IgnoreOwnMessages {
+    fn handle<'a>(
+        &'a self,
+        message: EnhancedGroupChatMessage,
+        next: Next<'a>,
+    ) -> MiddlewareFuture<'a> {
+        if message.sender_steam_id == self.own_steam_id {
+            return Box::pin(async { Ok(()) });
+        }
+        next.run(message)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Mutex;
+    use steam_vent_proto::steammessages_chat_steamclient::CChatRoom_IncomingChatMessage_Notification;
+
+    struct Record(&'static str, Arc<Mutex<Vec<&'static str>>>);
+
+    impl MessageMiddleware for Record {
+        fn handle<'a>(
+            &'a self,
+            message: EnhancedGroupChatMessage,
+            next: Next<'a>,
+        ) -> MiddlewareFuture<'a> {
+            Box::pin(async move {
+                self.1.lock().unwrap().push(self.0);
+                next.run(message).await
+            })
+        }
+    }
+
+    fn message_from(sender: u64) -> EnhancedGroupChatMessage {
+        let mut notification = CChatRoom_IncomingChatMessage_Notification::new();
+        notification.set_steamid_sender(sender);
+        notification.set_message("hello".to_string());
+        EnhancedGroupChatMessage::from_notification(&notification)
+    }
+
+    #[tokio::test]
+    async fn layers_run_in_order_and_can_short_circuit() {
+        let seen = Arc::new(Mutex::new(Vec::new()));
+        let chain: Vec<Arc<dyn MessageMiddleware>> = vec![
+            Arc::new(Record("outer", seen.clone())),
+            Arc::new(IgnoreOwnMessages::new(SteamID::from(76561197960287930))),
+            Arc::new(Record("inner", seen.clone())),
+        ];
+        let delivered = seen.clone();
+        let mut terminal = move |_message: EnhancedGroupChatMessage| -> CallbackResult {
+            delivered.lock().unwrap().push("callback");
+            Ok(())
+        };
+
+        Next::new(&chain, &mut terminal)
+            .run(message_from(76561199491325083))
+            .await
+            .unwrap();
+        Next::new(&chain, &mut terminal)
+            .run(message_from(76561197960287930))
+            .await
+            .unwrap();
+
+        assert_eq!(
+            *seen.lock().unwrap(),
+            vec!["outer", "inner", "callback", "outer"]
+        );
+    }
+}
@@ -0,0 +1,110 @@
+// SPDX-License-Identifier: LGPL-3.0-only
+
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use tokio::sync::{Mutex as AsyncMutex, OwnedMutexGuard};
+
+type RoomKey = (u64, u64);
+
+/// Per-room FIFO locks used by `OrderedMessaging`.
+///
+/// Each `(chat_group_id, chat_id)` pair gets its own fair async mutex, so sends to the same
+/// room are dispatched one at a time in the order they started waiting, while sends to
+/// different rooms never block each other. Idle rooms are dropped from the map.
+#[derive(Debug, Default)]
+pub(crate) struct RoomSendLocks {
+    rooms: Mutex<HashMap<RoomKey, Arc<AsyncMutex<()>>>>,
+}
+
+impl RoomSendLocks {
+    /// Wait for this room's turn; the room stays locked until the guard is dropped.
+    pub(crate) async fn lock(self: &Arc<Self>, chat_group_id: u64, chat_id: u64) -> RoomTurn {
+        let key = (chat_group_id, chat_id);
+        let room = {
+            let mut rooms = self
+                .rooms
+                .lock()
+                .unwrap_or_else(|poisoned| poisoned.into_inner());
+            rooms.entry(key).or_default().clone()
+        };
+
+        RoomTurn {
+            guard: Some(room.lock_owned().await),
+            locks: Arc::clone(self),
+            key,
+        }
+    }
+
+    #[cfg(test)]
+    fn tracked_rooms(&self) -> usize {
+        self.rooms
+            .lock()
+            .map(|rooms| rooms.len())
+            .unwrap_or_default()
+    }
+}
+
+/// Exclusive turn to send to one room, released on drop.
+pub(crate) struct RoomTurn {
+    guard: Option<OwnedMutexGuard<()>>,
+    locks: Arc<RoomSendLocks>,
+    key: RoomKey,
+}
+
+impl Drop for RoomTurn {
+    fn drop(&mut self) {
+        let mut rooms = self
+            .locks
+            .rooms
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner());
+        drop(self.guard.take());
+
+        // Only the map still references the room lock: nobody holds or awaits it.
+        if rooms
+            .get(&self.key)
+            .is_some_and(|room| Arc::strong_count(room) == 1)
+        {
+            rooms.remove(&self.key);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[tokio::test]
+    async fn same_room_sends_run_in_submission_order() {
+        let locks = Arc::new(RoomSendLocks::default());
+        let log = Arc::new(Mutex::new(Vec::new()));
+
+        let mut tasks = Vec::new();
+        for i in 0..5u64 {
+            let turn = locks.lock(1, 2);
+            let log = log.clone();
+            // Earlier submissions sleep longer; ordering must still hold.
+            tasks.push(async move {
+                let _turn = turn.await;
+                tokio::time::sleep(Duration::from_millis(10 * (5 - i))).await;
+                log.lock().unwrap().push(i);
+            });
+        }
+        futures_util::future::join_all(tasks).await;
+
+        assert_eq!(*log.lock().unwrap(), vec![0, 1, 2, 3, 4]);
+        assert_eq!(locks.tracked_rooms(), 0);
+    }
+
+    #[tokio::test]
+    async fn different_rooms_do_not_block_each_other() {
+        let locks = Arc::new(RoomSendLocks::default());
+
+        let _held = locks.lock(1, 2).await;
+        tokio::time::timeout(Duration::from_secs(1), locks.lock(1, 3))
+            .await
+            .expect("other room is free");
+        assert_eq!(locks.tracked_rooms(), 1);
+    }
+}
@@ -0,0 +1,761 @@
+// SPDX-License-Identifier: LGPL-3.0-only
+
+use crate::catchup::MessagePosition;
+use crate::chatroom::{ChatMessageHistoryEntry, ChatRoomMessaging, SendGroupMessageParams};
+use crate::errors::KetherError;
+use crate::preprocessing::PreprocessedMessage;
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::collections::hash_map::RandomState;
+use std::fs;
+use std::hash::{BuildHasher, Hasher};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{SystemTime, UNIX_EPOCH};
+use steamid_ng::SteamID;
+use thiserror::Error;
+use tracing::{debug, instrument, warn};
+
+/// How many recent history entries are inspected before replaying an entry.
+const REPLAY_HISTORY_WINDOW: u32 = 50;
+
+/// Tolerated clock difference (seconds) between the local clock and Steam's server timestamps.
+const REPLAY_CLOCK_SKEW_SECS: u64 = 60;
+
+/// How many delivered entries are kept per room to tell our own messages apart on replay.
+///
+/// Matches `REPLAY_HISTORY_WINDOW`: older deliveries have scrolled out of the inspected
+/// history anyway.
+const DELIVERED_PER_ROOM: usize = REPLAY_HISTORY_WINDOW as usize;
+
+/// Errors raised by outbox persistence.
+#[derive(Debug, Error)]
+pub enum OutboxError {
+    /// Reading or writing the backing file failed.
+    #[error("outbox I/O error: {0}")]
+    Io(#[from] std::io::Error),
+    /// The persisted outbox could not be encoded or decoded.
+    #[error("outbox serialization error: {0}")]
+    Serialization(#[from] serde_json::Error),
+    /// A store lock was poisoned by a panicking writer.
+    #[error("outbox store lock poisoned")]
+    Poisoned,
+}
+
+/// A queued group message awaiting delivery confirmation.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct OutboxEntry {
+    /// Client-generated message identifier (UUID v4 formatted).
+    pub id: String,
+    /// The unique identifier for the chat group.
+    pub chat_group_id: u64,
+    /// The unique identifier for the specific chat room within the group.
+    pub chat_id: u64,
+    /// The message text to send.
+    pub message: String,
+    /// Whether the message should be echoed back to the sender; `None` follows the
+    /// client's `MessagingDefaults`.
+    #[serde(default)]
+    pub echo_to_sender: Option<bool>,
+    /// Whether the message is sent as a `/me` emote.
+    #[serde(default)]
+    pub emote: bool,
+    /// Whether Steam may attach link preview cards (see
+    /// `SendGroupMessageParams::with_link_previews`).
+    #[serde(default = "link_previews_default")]
+    pub link_previews: bool,
+    /// Whether malformed BBCode rejects the send (see
+    /// `SendGroupMessageParams::with_strict_bbcode`); `None` follows the client's
+    /// `MessagingDefaults`.
+    #[serde(default)]
+    pub strict_bbcode: Option<bool>,
+    /// Whether backslash escapes are sent as written (see
+    /// `SendGroupMessageParams::with_preserve_escapes`).
+    #[serde(default)]
+    pub preserve_escapes: bool,
+    /// Whether mentions are sent as `[mention]` pings (see
+    /// `SendGroupMessageParams::with_server_mentions`); entries queued before this
+    /// existed were sent as written.
+    #[serde(default)]
+    pub server_mentions: bool,
+    /// Unix timestamp (seconds) when the entry was enqueued.
+    pub created_at: u64,
+    /// Number of delivery attempts made so far.
+    pub attempts: u32,
+    /// Exact text put on the wire by the latest attempt, recorded before sending.
+    #[serde(default)]
+    pub sent_message: Option<String>,
+    /// Where Steam stored the message once delivery was confirmed; `None` while pending.
+    #[serde(default)]
+    pub delivered_as: Option<MessagePosition>,
+}
+
+impl OutboxEntry {
+    fn from_params(params: &SendGroupMessageParams) -> Self {
+        Self {
+            id: generate_message_id(),
+            chat_group_id: params.chat_group_id,
+            chat_id: params.chat_id,
+            message: params.message.clone(),
+            echo_to_sender: params.echo_to_sender_override(),
+            emote: params.emote,
+            link_previews: params.link_previews,
+            strict_bbcode: params.strict_bbcode_override(),
+            preserve_escapes: params.preserve_escapes,
+            server_mentions: params.server_mentions,
+            created_at: unix_now(),
+            attempts: 0,
+            sent_message: None,
+            delivered_as: None,
+        }
+    }
+
+    /// Whether Steam confirmed this entry.
+    pub fn is_delivered(&self) -> bool {
+        self.delivered_as.is_some()
+    }
+
+    /// Rebuild the send parameters for this entry.
+    ///
+    /// Only the options the caller overrode are set; the rest keep following the
+    /// client's `MessagingDefaults`.
+    pub fn to_params(&self) -> SendGroupMessageParams {
+        let mut params =
+            SendGroupMessageParams::new(self.chat_group_id, self.chat_id, self.message.clone())
+                .with_emote(self.emote)
+                .with_link_previews(self.link_previews)
+                .with_preserve_escapes(self.preserve_escapes)
+                .with_server_mentions(self.server_mentions);
+        if let Some(echo) = self.echo_to_sender {
+            params = params.with_echo_to_sender(echo);
+        }
+        if let Some(strict) = self.strict_bbcode {
+            params = params.with_strict_bbcode(strict);
+        }
+        params
+    }
+}
+
+/// Pluggable persistence for outbox entries.
+///
+/// Implementations must be safe to call from multiple tasks; entries are
+/// keyed by [`OutboxEntry::id`]. Besides pending entries the store holds recently
+/// delivered ones (`OutboxEntry::delivered_as` set), which the outbox uses to tell its
+/// own messages apart when replaying.
+pub trait OutboxStore: Send + Sync {
+    /// Insert or overwrite an entry.
+    fn put(&self, entry: &OutboxEntry) -> Result<(), OutboxError>;
+
+    /// Remove an entry.
+    fn remove(&self, id: &str) -> Result<(), OutboxError>;
+
+    /// Return all stored entries, pending and delivered, oldest first.
+    fn entries(&self) -> Result<Vec<OutboxEntry>, OutboxError>;
+}
+
+/// In-memory outbox store; entries do not survive a process restart.
+#[derive(Debug, Default)]
+pub struct MemoryOutboxStore {
+    entries: Mutex<Vec<OutboxEntry>>,
+}
+
+impl MemoryOutboxStore {
+    /// Create an empty in-memory store.
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl OutboxStore for MemoryOutboxStore {
+    fn put(&self, entry: &OutboxEntry) -> Result<(), OutboxError> {
+        let mut entries = self.entries.lock().map_err(|_| OutboxError::Poisoned)?;
+        upsert(&mut entries, entry);
+        Ok(())
+    }
+
+    fn remove(&self, id: &str) -> Result<(), OutboxError> {
+        let mut entries = self.entries.lock().map_err(|_| OutboxError::Poisoned)?;
+        entries.retain(|entry| entry.id != id);
+        Ok(())
+    }
+
+    fn entries(&self) -> Result<Vec<OutboxEntry>, OutboxError> {
+        let entries = self.entries.lock().map_err(|_| OutboxError::Poisoned)?;
+        Ok(entries.clone())
+    }
+}
+
+/// Outbox store persisting its entries, pending and recently delivered, as a JSON array
+/// in a single file.
+///
+/// Every mutation rewrites the file through a temporary sibling and an atomic rename,
+/// so a crash mid-write leaves the previous state intact.
+#[derive(Debug)]
+pub struct JsonFileOutboxStore {
+    path: PathBuf,
+    lock: Mutex<()>,
+}
+
+impl JsonFileOutboxStore {
+    /// Create a store backed by `path`. The file is created lazily on first write.
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        Self {
+            path: path.into(),
+            lock: Mutex::new(()),
+        }
+    }
+
+    /// Path of the backing file.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    fn read(&self) -> Result<Vec<OutboxEntry>, OutboxError> {
+        match fs::read(&self.path) {
+            Ok(bytes) if bytes.is_empty() => Ok(Vec::new()),
+            Ok(bytes) => Ok(serde_json::from_slice(&bytes)?),
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => Ok(Vec::new()),
+            Err(err) => Err(err.into()),
+        }
+    }
+
+    fn write(&self, entries: &[OutboxEntry]) -> Result<(), OutboxError> {
+        let tmp_path = self.path.with_extension("tmp");
+        fs::write(&tmp_path, serde_json::to_vec_pretty(entries)?)?;
+        fs::rename(&tmp_path, &self.path)?;
+        Ok(())
+    }
+}
+
+impl OutboxStore for JsonFileOutboxStore {
+    fn put(&self, entry: &OutboxEntry) -> Result<(), OutboxError> {
+        let _guard = self.lock.lock().map_err(|_| OutboxError::Poisoned)?;
+        let mut entries = self.read()?;
+        upsert(&mut entries, entry);
+        self.write(&entries)
+    }
+
+    fn remove(&self, id: &str) -> Result<(), OutboxError> {
+        let _guard = self.lock.lock().map_err(|_| OutboxError::Poisoned)?;
+        let mut entries = self.read()?;
+        entries.retain(|entry| entry.id != id);
+        self.write(&entries)
+    }
+
+    fn entries(&self) -> Result<Vec<OutboxEntry>, OutboxError> {
+        let _guard = self.lock.lock().map_err(|_| OutboxError::Poisoned)?;
+        self.read()
+    }
+}
+
+/// Outcome of replaying the outbox after a reconnect.
+#[derive(Debug, Clone, Default)]
+pub struct OutboxReplayReport {
+    /// Entries that were resent and confirmed by Steam.
+    pub resent: Vec<String>,
+    /// Entries found in room history and marked delivered without resending.
+    pub already_delivered: Vec<String>,
+    /// Entries whose resend failed; they stay pending for the next replay.
+    pub failed: Vec<String>,
+}
+
+/// Durable queue in front of group message sends.
+///
+/// Each send is persisted under a client-generated UUID before it hits the network and
+/// marked delivered once `SendChatMessage` returns, which is Steam's acknowledgement; the
+/// entry then records the timestamp and ordinal Steam stored the message at. Anything
+/// still pending after a dropped connection is replayed by [`Outbox::replay`]
+/// (called from `ChatRoomClient::on_reconnect`).
+#[derive(Clone)]
+pub struct Outbox {
+    store: Arc<dyn OutboxStore>,
+}
+
+impl std::fmt::Debug for Outbox {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Outbox").finish_non_exhaustive()
+    }
+}
+
+impl Outbox {
+    /// Create an outbox persisting entries into `store`.
+    pub fn new(store: impl OutboxStore + 'static) -> Self {
+        Self {
+            store: Arc::new(store),
+        }
+    }
+
+    /// Access the underlying store.
+    pub fn store(&self) -> &dyn OutboxStore {
+        self.store.as_ref()
+    }
+
+    /// Persist a new entry for `params` and return it.
+    pub fn enqueue(&self, params: &SendGroupMessageParams) -> Result<OutboxEntry, OutboxError> {
+        let entry = OutboxEntry::from_params(params);
+        self.store.put(&entry)?;
+        Ok(entry)
+    }
+
+    /// Entries that have not been confirmed yet, oldest first.
+    pub fn pending(&self) -> Result<Vec<OutboxEntry>, OutboxError> {
+        let mut entries = self.store.entries()?;
+        entries.retain(|entry| !entry.is_delivered());
+        Ok(entries)
+    }
+
+    /// Send a group message through the outbox.
+    ///
+    /// The entry is persisted first; on success it is marked delivered, on failure it
+    /// stays pending so the next [`Outbox::replay`] picks it up. Messages rejected by the
+    /// local checks (length, strict BBCode) fail without being persisted, since no replay
+    /// could ever deliver them.
+    #[instrument(
+        name = "kether.outbox.send",
+        skip(self, messaging, params),
+        fields(chat_group_id = params.chat_group_id, chat_id = params.chat_id)
+    )]
+    pub async fn send(
+        &self,
+        messaging: &ChatRoomMessaging<'_>,
+        params: SendGroupMessageParams,
+    ) -> Result<PreprocessedMessage, KetherError> {
+        messaging.prepare_group_message(&params)?;
+        let entry = self.enqueue(&params)?;
+        self.deliver(messaging, entry).await
+    }
+
+    async fn deliver(
+        &self,
+        messaging: &ChatRoomMessaging<'_>,
+        mut entry: OutboxEntry,
+    ) -> Result<PreprocessedMessage, KetherError> {
+        let params = entry.to_params();
+        entry.attempts += 1;
+        entry.sent_message = Some(messaging.prepare_group_message(&params)?);
+        self.store.put(&entry)?;
+
+        let preprocessed = messaging.send_group_message_ref(&params).await?;
+        entry.delivered_as = preprocessed
+            .server_timestamp
+            .map(|timestamp| MessagePosition {
+                timestamp,
+                ordinal: preprocessed.ordinal.unwrap_or(0),
+            });
+        self.record_delivery(&entry)?;
+
+        debug!(id = %entry.id, attempts = entry.attempts, "outbox entry delivered");
+        Ok(preprocessed)
+    }
+
+    /// Keep a delivered entry as the owner of its message and forget the oldest
+    /// deliveries of the room beyond `DELIVERED_PER_ROOM`.
+    fn record_delivery(&self, entry: &OutboxEntry) -> Result<(), OutboxError> {
+        if !entry.is_delivered() {
+            return self.store.remove(&entry.id);
+        }
+        self.store.put(entry)?;
+
+        let mut delivered: Vec<_> = self
+            .store
+            .entries()?
+            .into_iter()
+            .filter(|other| {
+                other.chat_group_id == entry.chat_group_id
+                    && other.chat_id == entry.chat_id
+                    && other.is_delivered()
+            })
+            .collect();
+        delivered.sort_by_key(|other| other.delivered_as);
+        let excess = delivered.len().saturating_sub(DELIVERED_PER_ROOM);
+        for stale in &delivered[..excess] {
+            self.store.remove(&stale.id)?;
+        }
+        Ok(())
+    }
+
+    /// Resend every pending entry.
+    ///
+    /// Entries that were attempted before are first looked up in the recent history of
+    /// their room: a message from `own_steam_id` with exactly the text the attempt sent,
+    /// stored after the entry was created and not already owned by another entry of this
+    /// outbox, means the attempt reached Steam before the connection dropped. Such entries
+    /// take that message over and are only marked delivered. Entries are matched oldest
+    /// first, so identical messages queued one after another each claim their own copy.
+    #[instrument(name = "kether.outbox.replay", skip(self, messaging))]
+    pub async fn replay(
+        &self,
+        messaging: &ChatRoomMessaging<'_>,
+        own_steam_id: SteamID,
+    ) -> Result<OutboxReplayReport, KetherError> {
+        let mut report = OutboxReplayReport::default();
+        let entries = self.store.entries()?;
+        let mut claimed: HashSet<_> = entries
+            .iter()
+            .filter_map(|entry| {
+                entry
+                    .delivered_as
+                    .map(|position| (entry.chat_group_id, entry.chat_id, position))
+            })
+            .collect();
+
+        for mut entry in entries.into_iter().filter(|entry| !entry.is_delivered()) {
+            if let Some(position) = self
+                .find_delivery(messaging, &entry, own_steam_id, &claimed)
+                .await
+            {
+                claimed.insert((entry.chat_group_id, entry.chat_id, position));
+                entry.delivered_as = Some(position);
+                self.record_delivery(&entry)?;
+                report.already_delivered.push(entry.id);
+                continue;
+            }
+
+            let id = entry.id.clone();
+            match self.deliver(messaging, entry).await {
+                Ok(_) => report.resent.push(id),
+                Err(err) => {
+                    warn!(id = %id, error = %err, "outbox replay failed");
+                    report.failed.push(id);
+                }
+            }
+        }
+
+        debug!(
+            resent = report.resent.len(),
+            already_delivered = report.already_delivered.len(),
+            failed = report.failed.len(),
+            "outbox replay finished"
+        );
+        Ok(report)
+    }
+
+    async fn find_delivery(
+        &self,
+        messaging: &ChatRoomMessaging<'_>,
+        entry: &OutboxEntry,
+        own_steam_id: SteamID,
+        claimed: &HashSet<(u64, u64, MessagePosition)>,
+    ) -> Option<MessagePosition> {
+        // Never attempted, so it cannot have been delivered.
+        entry.sent_message.as_ref()?;
+
+        let history = match messaging
+            .get_message_history(
+                entry.chat_group_id,
+                entry.chat_id,
+                Some(REPLAY_HISTORY_WINDOW),
+            )
+            .await
+        {
+            Ok(history) => history,
+            Err(err) => {
+                warn!(id = %entry.id, error = %err, "could not inspect history before replay");
+                return None;
+            }
+        };
+
+        find_in_history(entry, &history, own_steam_id, claimed)
+    }
+}
+
+/// Earliest unclaimed message in `history` that `entry`'s last attempt could have produced.
+///
+/// Messages marked `deleted` still match: the send reached Steam and was removed
+/// afterwards, so replaying it would only bring back a message someone deleted.
+fn find_in_history(
+    entry: &OutboxEntry,
+    history: &[ChatMessageHistoryEntry],
+    own_steam_id: SteamID,
+    claimed: &HashSet<(u64, u64, MessagePosition)>,
+) -> Option<MessagePosition> {
+    let sent_message = entry.sent_message.as_deref()?;
+    let earliest = entry.created_at.saturating_sub(REPLAY_CLOCK_SKEW_SECS);
+    history
+        .iter()
+        .filter(|item| {
+            item.sender.account_id() == own_steam_id.account_id()
+                && u64::from(item.server_timestamp) >= earliest
+                && item.message == sent_message
+        })
+        .map(|item| MessagePosition {
+            timestamp: item.server_timestamp,
+            ordinal: item.ordinal,
+        })
+        .filter(|position| !claimed.contains(&(entry.chat_group_id, entry.chat_id, *position)))
+        .min()
+}
+
+/// Entries queued before link previews were persisted used Steam's default, previews on.
+fn link_previews_default() -> bool {
+    true
+}
+
+fn upsert(entries: &mut Vec<OutboxEntry>, entry: &OutboxEntry) {
+    match entries.iter_mut().find(|existing| existing.id == entry.id) {
+        Some(existing) => *existing = entry.clone(),
+        None => entries.push(entry.clone()),
+    }
+}
+
+fn unix_now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|duration| duration.as_secs())
+        .unwrap_or(0)
+}
+
+/// Generate a random UUID v4 formatted identifier without pulling in an RNG crate.
+fn generate_message_id() -> String {
+    static COUNTER: AtomicU64 = AtomicU64::new(0);
+
+    let mut words = [0u64; 2];
+    for word in &mut words {
+        let mut hasher = RandomState::new().build_hasher();
+        hasher.write_u64(COUNTER.fetch_add(1, Ordering::Relaxed));
+        hasher.write_u128(
+            SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map(|duration| duration.as_nanos())
+                .unwrap_or(0),
+        );
+        *word = hasher.finish();
+    }
+
+    let mut bytes = [0u8; 16];
+    bytes[..8].copy_from_slice(&words[0].to_be_bytes());
+    bytes[8..].copy_from_slice(&words[1].to_be_bytes());
+    bytes[6] = (bytes[6] & 0x0f) | 0x40;
+    bytes[8] = (bytes[8] & 0x3f) | 0x80;
+
+    let hex: String = bytes.iter().map(|byte| format!("{:02x}", byte)).collect();
+    format!(
+        "{}-{}-{}-{}-{}",
+        &hex[0..8],
+        &hex[8..12],
+        &hex[12..16],
+        &hex[16..20],
+        &hex[20..32]
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chatroom::{ChatError, MessagingDefaults};
+    use crate::preprocessing::MessagePreprocessor;
+
+    fn sample_params() -> SendGroupMessageParams {
+        SendGroupMessageParams::new(1, 2, "!sub").with_echo_to_sender(true)
+    }
+
+    #[test]
+    fn message_ids_are_unique_uuid_v4() {
+        let first = generate_message_id();
+        let second = generate_message_id();
+
+        assert_ne!(first, second);
+        assert_eq!(first.len(), 36);
+        assert_eq!(&first[14..15], "4");
+    }
+
+    #[test]
+    fn entries_restore_send_options() {
+        let params = sample_params().with_emote(true).with_link_previews(false);
+        let restored = OutboxEntry::from_params(&params).to_params();
+
+        assert!(restored.emote);
+        assert!(!restored.link_previews);
+        assert!(restored.echo_to_sender);
+    }
+
+    #[test]
+    fn entries_only_override_options_the_caller_set() {
+        let defaults = MessagingDefaults::default()
+            .with_echo_to_sender(true)
+            .with_strict_bbcode(true);
+        let params = SendGroupMessageParams::new(1, 2, "[sub]hi[/sub]");
+        let entry = OutboxEntry::from_params(&params);
+        assert_eq!((entry.echo_to_sender, entry.strict_bbcode), (None, None));
+
+        let restored = entry.to_params();
+        assert!(
+            ChatRoomMessaging::checked_send_request(
+                &restored,
+                &defaults,
+                MessagePreprocessor::shared()
+            )
+            .is_err()
+        );
+
+        let params = params.with_strict_bbcode(false);
+        let restored = OutboxEntry::from_params(&params).to_params();
+        let req = ChatRoomMessaging::checked_send_request(
+            &restored,
+            &defaults,
+            MessagePreprocessor::shared(),
+        )
+        .expect("strict check overridden");
+        assert!(req.echo_to_sender());
+    }
+
+    #[test]
+    fn entries_keep_rejecting_invalid_bbcode() {
+        let params = SendGroupMessageParams::new(1, 2, "[sub]hi[/sub]").with_strict_bbcode(true);
+        let restored = OutboxEntry::from_params(&params).to_params();
+
+        let err = ChatRoomMessaging::checked_send_request(
+            &restored,
+            &MessagingDefaults::default(),
+            MessagePreprocessor::shared(),
+        )
+        .expect_err("strict send of invalid BBCode");
+        assert!(matches!(err, ChatError::InvalidBBCode { position: 0, .. }));
+    }
+
+    #[test]
+    fn entries_without_link_previews_keep_them_enabled() {
+        let entry: OutboxEntry = serde_json::from_str(
+            r#"{"id":"x","chat_group_id":1,"chat_id":2,"message":"hi","echo_to_sender":false,
+                "created_at":0,"attempts":0}"#,
+        )
+        .expect("legacy entry");
+
+        assert!(entry.link_previews);
+        assert!(!entry.emote);
+    }
+
+    #[test]
+    fn memory_store_tracks_pending_entries() {
+        let outbox = Outbox::new(MemoryOutboxStore::new());
+        let entry = outbox.enqueue(&sample_params()).expect("enqueue");
+
+        let pending = outbox.pending().expect("pending");
+        assert_eq!(pending, vec![entry.clone()]);
+
+        outbox.store().remove(&entry.id).expect("remove");
+        assert!(outbox.pending().expect("pending").is_empty());
+    }
+
+    fn own_message(steam_id: SteamID, timestamp: u32, ordinal: u32) -> ChatMessageHistoryEntry {
+        ChatMessageHistoryEntry {
+            sender: steam_id,
+            server_timestamp: timestamp,
+            ordinal,
+            message: "!sub".to_string(),
+            deleted: false,
+            reactions: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn replay_matches_identical_messages_to_distinct_entries() {
+        let own = SteamID::from(76561198012345678);
+        let attempted = |created_at| OutboxEntry {
+            created_at,
+            sent_message: Some("!sub".to_string()),
+            ..OutboxEntry::from_params(&sample_params())
+        };
+        let first = attempted(1_000);
+        let second = attempted(1_000);
+        let history = vec![
+            own_message(own, 1_030, 0),
+            own_message(own, 1_020, 0),
+            own_message(SteamID::from(76561198000000001), 1_025, 0),
+            own_message(own, 900, 0),
+        ];
+
+        let mut claimed = HashSet::new();
+        let at_1020 = find_in_history(&first, &history, own, &claimed).expect("first copy");
+        assert_eq!(at_1020.timestamp, 1_020);
+        claimed.insert((1, 2, at_1020));
+
+        let at_1030 = find_in_history(&second, &history, own, &claimed).expect("second copy");
+        assert_eq!(at_1030.timestamp, 1_030);
+        claimed.insert((1, 2, at_1030));
+
+        // Older copies predate the entry; the rest are owned by other entries.
+        assert_eq!(
+            find_in_history(&attempted(1_000), &history, own, &claimed),
+            None
+        );
+
+        let never_sent = OutboxEntry::from_params(&sample_params());
+        assert_eq!(
+            find_in_history(&never_sent, &history, own, &HashSet::new()),
+            None
+        );
+    }
+
+    #[test]
+    fn replay_counts_deleted_copies_as_delivered() {
+        let own = SteamID::from(76561198012345678);
+        let entry = OutboxEntry {
+            created_at: 1_000,
+            sent_message: Some("!sub".to_string()),
+            ..OutboxEntry::from_params(&sample_params())
+        };
+        let history = vec![ChatMessageHistoryEntry {
+            deleted: true,
+            ..own_message(own, 1_010, 0)
+        }];
+
+        let found = find_in_history(&entry, &history, own, &HashSet::new());
+        assert_eq!(
+            found,
+            Some(MessagePosition {
+                timestamp: 1_010,
+                ordinal: 0,
+            })
+        );
+    }
+
+    #[test]
+    fn delivered_entries_are_kept_per_room_up_to_the_window() {
+        let outbox = Outbox::new(MemoryOutboxStore::new());
+        for timestamp in 0..DELIVERED_PER_ROOM as u32 + 3 {
+            let mut entry = outbox.enqueue(&sample_params()).expect("enqueue");
+            entry.delivered_as = Some(MessagePosition {
+                timestamp,
+                ordinal: 0,
+            });
+            outbox.record_delivery(&entry).expect("record");
+        }
+        let pending = outbox.enqueue(&sample_params()).expect("enqueue");
+
+        let entries = outbox.store().entries().expect("entries");
+        assert_eq!(entries.len(), DELIVERED_PER_ROOM + 1);
+        assert_eq!(
+            entries
+                .iter()
+                .filter_map(|entry| entry.delivered_as)
+                .map(|position| position.timestamp)
+                .min(),
+            Some(3)
+        );
+        assert_eq!(outbox.pending().expect("pending"), vec![pending]);
+    }
+
+    #[test]
+    fn json_store_persists_across_instances() {
+        let path =
+            std::env::temp_dir().join(format!("kether-outbox-{}.json", generate_message_id()));
+
+        let entry = {
+            let outbox = Outbox::new(JsonFileOutboxStore::new(&path));
+            outbox.enqueue(&sample_params()).expect("enqueue")
+        };
+
+        let reopened = JsonFileOutboxStore::new(&path);
+        let entries = reopened.entries().expect("entries");
+        assert_eq!(entries.len(), 1);
+        assert_eq!(entries[0].id, entry.id);
+        assert_eq!(entries[0].echo_to_sender, Some(true));
+
+        reopened.remove(&entry.id).expect("remove");
+        assert!(reopened.entries().expect("entries").is_empty());
+
+        let _ = fs::remove_file(&path);
+    }
+}
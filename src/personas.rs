@@ -0,0 +1,197 @@
+// SPDX-License-Identifier: LGPL-3.0-only
+
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+use steamid_ng::SteamID;
+
+/// How long a resolved persona is reused before it is looked up again.
+pub const DEFAULT_PERSONA_CACHE_TTL: Duration = Duration::from_secs(10 * 60);
+
+/// Most accounts asked about in a single persona lookup request.
+pub const PERSONA_LOOKUP_BATCH: usize = 100;
+
+/// A mentioned account with its profile name, from `ChatRoomClient::resolve_mentions`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ResolvedMention {
+    /// The mentioned account.
+    #[serde(with = "steam_id_u64")]
+    pub steam_id: SteamID,
+    /// The account's current persona name, or its Steam3 id when it could not be resolved.
+    pub persona_name: String,
+    /// Hex SHA-1 digest of the avatar image, when the account has one.
+    pub avatar_hash: Option<String>,
+    /// Whether Steam returned the account; `false` for deleted or unknown accounts.
+    pub found: bool,
+}
+
+impl ResolvedMention {
+    /// Stand-in for an account Steam did not return, named by its Steam3 id.
+    pub fn placeholder(steam_id: SteamID) -> Self {
+        Self {
+            steam_id,
+            persona_name: steam_id.steam3(),
+            avatar_hash: None,
+            found: false,
+        }
+    }
+
+    /// Build from a persona name and raw avatar digest; an all-zero or empty digest
+    /// means the account uses the default avatar.
+    pub fn new(steam_id: SteamID, persona_name: &str, avatar_sha: &[u8]) -> Self {
+        let avatar_hash = avatar_sha.iter().any(|byte| *byte != 0).then(|| {
+            avatar_sha
+                .iter()
+                .map(|byte| format!("{byte:02x}"))
+                .collect()
+        });
+        Self {
+            steam_id,
+            persona_name: persona_name.to_string(),
+            avatar_hash,
+            found: true,
+        }
+    }
+}
+
+mod steam_id_u64 {
+    use serde::{Deserialize, Deserializer, Serializer};
+    use steamid_ng::SteamID;
+
+    pub fn serialize<S: Serializer>(steam_id: &SteamID, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_u64(u64::from(*steam_id))
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<SteamID, D::Error> {
+        u64::deserialize(deserializer).map(SteamID::from)
+    }
+}
+
+/// Resolved personas remembered for a fixed time.
+///
+/// Placeholders are cached as well, so deleted accounts are not looked up on every
+/// message that mentions them.
+#[derive(Debug)]
+pub struct PersonaCache {
+    ttl: Duration,
+    entries: Mutex<HashMap<u64, (ResolvedMention, Instant)>>,
+}
+
+impl Default for PersonaCache {
+    fn default() -> Self {
+        Self::new(DEFAULT_PERSONA_CACHE_TTL)
+    }
+}
+
+impl PersonaCache {
+    /// Create a cache reusing entries for `ttl`.
+    pub fn new(ttl: Duration) -> Self {
+        Self {
+            ttl,
+            entries: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// How long an entry is reused.
+    pub fn ttl(&self) -> Duration {
+        self.ttl
+    }
+
+    /// Number of entries, including expired ones not yet replaced.
+    pub fn len(&self) -> usize {
+        self.entries
+            .lock()
+            .map(|entries| entries.len())
+            .unwrap_or_default()
+    }
+
+    /// Whether the cache holds no entries.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// The cached persona for `steam_id`, if it was resolved less than `ttl` ago.
+    pub fn get(&self, steam_id: SteamID) -> Option<ResolvedMention> {
+        self.get_at(steam_id, Instant::now())
+    }
+
+    /// Remember `resolved`, replacing any earlier entry for the same account.
+    pub fn insert(&self, resolved: ResolvedMention) {
+        self.insert_at(resolved, Instant::now());
+    }
+
+    /// Forget every entry.
+    pub fn clear(&self) {
+        if let Ok(mut entries) = self.entries.lock() {
+            entries.clear();
+        }
+    }
+
+    pub(crate) fn get_at(&self, steam_id: SteamID, now: Instant) -> Option<ResolvedMention> {
+        let entries = self.entries.lock().ok()?;
+        let (resolved, at) = entries.get(&u64::from(steam_id))?;
+        (now.saturating_duration_since(*at) < self.ttl).then(|| resolved.clone())
+    }
+
+    pub(crate) fn insert_at(&self, resolved: ResolvedMention, now: Instant) {
+        let Ok(mut entries) = self.entries.lock() else {
+            return;
+        };
+        entries.retain(|_, (_, at)| now.saturating_duration_since(*at) < self.ttl);
+        entries.insert(u64::from(resolved.steam_id), (resolved, now));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn account(id: u64) -> SteamID {
+        SteamID::from(76561197960265728 + id)
+    }
+
+    #[test]
+    fn entries_expire_after_ttl() {
+        let cache = PersonaCache::new(Duration::from_secs(60));
+        let start = Instant::now();
+        cache.insert_at(
+            ResolvedMention::new(account(1), "Kether", &[0xab; 20]),
+            start,
+        );
+
+        let hit = cache.get_at(account(1), start + Duration::from_secs(59));
+        assert_eq!(hit.map(|r| r.persona_name), Some("Kether".to_string()));
+        assert_eq!(
+            cache.get_at(account(1), start + Duration::from_secs(60)),
+            None
+        );
+        assert_eq!(cache.get_at(account(2), start), None);
+
+        cache.insert_at(
+            ResolvedMention::placeholder(account(2)),
+            start + Duration::from_secs(61),
+        );
+        assert_eq!(cache.len(), 1, "expired entries are dropped on insert");
+    }
+
+    #[test]
+    fn placeholders_and_avatar_hashes() {
+        let placeholder = ResolvedMention::placeholder(account(7));
+        assert!(!placeholder.found);
+        assert_eq!(placeholder.persona_name, "[U:1:7]");
+        assert_eq!(placeholder.avatar_hash, None);
+
+        let resolved = ResolvedMention::new(account(7), "Name", &[0x0f, 0xa0]);
+        assert!(resolved.found);
+        assert_eq!(resolved.avatar_hash.as_deref(), Some("0fa0"));
+        assert_eq!(
+            ResolvedMention::new(account(7), "Name", &[0; 20]).avatar_hash,
+            None
+        );
+        assert_eq!(
+            ResolvedMention::new(account(7), "Name", &[]).avatar_hash,
+            None
+        );
+    }
+}
@@ -1,11 +1,15 @@
 // SPDX-License-Identifier: LGPL-3.0-only
 
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
-use steamid_ng::SteamID;
+use std::collections::{HashMap, HashSet};
+use std::ops::Range;
+use std::sync::{Arc, LazyLock};
+use steamid_ng::{AccountType, Instance, SteamID, Universe};
+use thiserror::Error;
 use tracing::trace;
 
-const ALLOWED_BBCODE_TAGS: &[&str] = &[
+/// BBCode tags rendered in group chat rooms; the default tag set.
+pub const GROUP_CHAT_TAGS: &[&str] = &[
     "emoticon",
     "code",
     "pre",
@@ -21,6 +25,30 @@ const ALLOWED_BBCODE_TAGS: &[&str] = &[
     "gameinvite",
     "og",
     "roomeffect",
+    "noembed",
+    "mention",
+];
+
+/// BBCode tags rendered in friend chats.
+///
+/// Room effects only play and pings only notify in group chat rooms, so `[roomeffect]`
+/// and `[mention]` are left out.
+pub const FRIEND_CHAT_TAGS: &[&str] = &[
+    "emoticon",
+    "code",
+    "pre",
+    "img",
+    "url",
+    "spoiler",
+    "quote",
+    "random",
+    "flip",
+    "tradeofferlink",
+    "tradeoffer",
+    "sticker",
+    "gameinvite",
+    "og",
+    "noembed",
 ];
 
 // BBCode formatting type constants
@@ -28,10 +56,80 @@ const ALLOWED_BBCODE_TAGS: &[&str] = &[
 pub const BBCODE_TYPE_SPOILER: &str = "spoiler";
 /// BBCode type constant for code block formatting.
 pub const BBCODE_TYPE_CODE: &str = "code";
+/// BBCode type constant for preformatted text.
+pub const BBCODE_TYPE_PRE: &str = "pre";
 /// BBCode type constant for URL/link formatting.
 pub const BBCODE_TYPE_URL: &str = "url";
 /// BBCode type constant for emoticon formatting.
 pub const BBCODE_TYPE_EMOTICON: &str = "emoticon";
+/// BBCode type constant for sticker payloads.
+pub const BBCODE_TYPE_STICKER: &str = "sticker";
+/// BBCode type constant for Open Graph link previews inserted by Steam.
+pub const BBCODE_TYPE_OG: &str = "og";
+/// BBCode type constant for game invites.
+pub const BBCODE_TYPE_GAMEINVITE: &str = "gameinvite";
+/// BBCode type constant for room effects (confetti, fireworks, ...).
+pub const BBCODE_TYPE_ROOMEFFECT: &str = "roomeffect";
+/// BBCode type constant for trade offers embedded in a message.
+pub const BBCODE_TYPE_TRADEOFFER: &str = "tradeoffer";
+/// BBCode type constant for trade offer links.
+pub const BBCODE_TYPE_TRADEOFFERLINK: &str = "tradeofferlink";
+/// BBCode type constant for quotes, including Steam's reply quotes.
+pub const BBCODE_TYPE_QUOTE: &str = "quote";
+/// BBCode type constant for inline images.
+pub const BBCODE_TYPE_IMG: &str = "img";
+/// BBCode type constant for `/random` results.
+pub const BBCODE_TYPE_RANDOM: &str = "random";
+/// BBCode type constant for `/flip` results.
+pub const BBCODE_TYPE_FLIP: &str = "flip";
+/// BBCode type constant for links that should not be unfurled into a preview card.
+pub const BBCODE_TYPE_NOEMBED: &str = "noembed";
+/// BBCode type constant for pings sent by the Steam client (`[mention=all]@all[/mention]`).
+pub const BBCODE_TYPE_MENTION: &str = "mention";
+
+/// Delimiter of Steam's `ːnameː` emoticon shorthand (U+02D0 MODIFIER LETTER TRIANGULAR COLON).
+const EMOTICON_SHORTHAND_DELIMITER: char = 'ː';
+
+/// Maximum accepted length (in bytes) of a sticker name.
+pub const MAX_STICKER_NAME_LEN: usize = 64;
+
+/// How many tags the parser keeps open at once by default; deeper openers are kept as
+/// text.
+///
+/// Bounds the depth of the parsed tree, which is walked recursively (and by serde when
+/// stored), so a message of nested tags cannot overflow the stack.
+pub const MAX_NESTING_DEPTH: usize = 32;
+
+/// How much structure the parser builds from one message; see
+/// `MessagePreprocessor::with_limits`.
+///
+/// When a limit is reached the rest of the message is kept as text and
+/// `PreprocessedMessage::limits_hit` is set. The defaults leave ample room for any message
+/// Steam accepts (`GROUP_CHAT_MESSAGE_LIMIT` UTF-16 code units, at most three bytes each).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct ParseLimits {
+    /// Tags are parsed only when they end within this many bytes of the message; text
+    /// after them is kept as is.
+    pub max_input_bytes: usize,
+    /// How many nodes the parsed tree may hold; later tags are kept as text.
+    pub max_nodes: usize,
+    /// How many tags may be open at once; deeper openers are kept as text, as are their
+    /// closing tags.
+    ///
+    /// The tree is walked recursively, so raising this far above `MAX_NESTING_DEPTH`
+    /// lets hostile messages use a lot of stack.
+    pub max_depth: usize,
+}
+
+impl Default for ParseLimits {
+    fn default() -> Self {
+        Self {
+            max_input_bytes: 64 * 1024,
+            max_nodes: 4096,
+            max_depth: MAX_NESTING_DEPTH,
+        }
+    }
+}
 
 // Mention token constants
 /// Mention token constant for mentioning all group members.
@@ -39,13 +137,225 @@ pub const MENTION_ALL: &str = "@all";
 /// Mention token constant for mentioning online/active members.
 pub const MENTION_HERE: &str = "@here";
 
-// Punctuation characters to trim from mention tokens
+/// Chat command that triggers a room effect ("/effect confetti").
+pub const ROOM_EFFECT_COMMAND: &str = "/effect";
+
+/// Prefix Steam renders as an emote ("/me waves" is shown as an action line).
+pub const EMOTE_PREFIX: &str = "/me";
+
+// Punctuation `MessageBuilder` lets follow a mention without a separating space
 const MENTION_PUNCTUATION: &str = "!?,.;";
 
+/// Unicode punctuation outside ASCII that commonly wraps or follows a mention.
+const UNICODE_MENTION_PUNCTUATION: &str =
+    "…‥·¡¿«»‹›‘’‚‛“”„‟–—、。，．：；！？（）［］｛｝〈〉《》「」『』【】〔〕";
+
+/// Which characters around a mention token are trimmed before it is recognized.
+///
+/// `@` is never trimmed, and neither are the brackets of a `[U:1:...]` token, so
+/// `(@[U:1:1531059355]).` still mentions the account.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Default)]
+pub enum MentionPunctuation {
+    /// ASCII punctuation and common Unicode punctuation: ellipses, curly quotes,
+    /// dashes and full-width CJK forms (default).
+    #[default]
+    Standard,
+    /// Only the given characters.
+    Custom(String),
+}
+
+impl MentionPunctuation {
+    /// Whether `c` is trimmed from mention tokens.
+    pub fn contains(&self, c: char) -> bool {
+        match self {
+            Self::Standard => {
+                (c.is_ascii_punctuation() || UNICODE_MENTION_PUNCTUATION.contains(c)) && c != '@'
+            }
+            Self::Custom(chars) => chars.contains(c) && c != '@',
+        }
+    }
+}
+
+/// How mention tokens are recognized; see `MessagePreprocessor::with_mention_rules`.
+///
+/// The rules apply alike to `@all`, `@here`, SteamID mentions and roster names.
+/// `[mention]` tags are pings the Steam client already delivered, so they are always
+/// recognized.
+///
+/// # Example
+///
+/// ```
+/// use SC_Sub_Poster::preprocessing::{MentionRules, MessagePreprocessor};
+///
+/// let preprocessor = MessagePreprocessor::default().with_mention_rules(MentionRules {
+///     only_at_line_start: true,
+///     ..MentionRules::default()
+/// });
+/// assert!(preprocessor.find_mentions("@all event starts now").is_some());
+/// assert!(preprocessor.find_mentions("tell @all about it").is_none());
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct MentionRules {
+    /// Characters trimmed around mention tokens.
+    pub punctuation: MentionPunctuation,
+    /// Characters trimmed in addition to `punctuation`, e.g. `"|~"`.
+    pub extra_trim_chars: String,
+    /// Whether a mention must make up the whole token once punctuation is trimmed
+    /// (default `true`). When `false`, mentions glued to other text count too:
+    /// `x@here`, `@allx` and `ping@[U:1:1531059355]`.
+    pub require_word_boundary: bool,
+    /// Whether only mentions that start a line count, the first token of the message
+    /// or after a line break (default `false`).
+    pub only_at_line_start: bool,
+}
+
+impl Default for MentionRules {
+    fn default() -> Self {
+        Self {
+            punctuation: MentionPunctuation::default(),
+            extra_trim_chars: String::new(),
+            require_word_boundary: true,
+            only_at_line_start: false,
+        }
+    }
+}
+
+impl MentionRules {
+    /// Whether `c` is trimmed from mention tokens.
+    fn trims(&self, c: char) -> bool {
+        self.punctuation.contains(c) || (c != '@' && self.extra_trim_chars.contains(c))
+    }
+
+    /// Trim `token` down to the mention it may hold.
+    fn trim<'t>(&self, token: &'t str) -> &'t str {
+        self.trim_end(self.trim_start(token))
+    }
+
+    fn trim_start<'t>(&self, token: &'t str) -> &'t str {
+        let mut token = token;
+        while let Some(c) = token.chars().next()
+            && self.trims(c)
+            && !token.starts_with("[U:1:")
+        {
+            token = &token[c.len_utf8()..];
+        }
+        token
+    }
+
+    fn trim_end<'t>(&self, token: &'t str) -> &'t str {
+        let mut token = token;
+        while let Some(c) = token.chars().next_back()
+            && self.trims(c)
+            && !(c == ']' && ends_with_steam_id(token))
+        {
+            token = &token[..token.len() - c.len_utf8()];
+        }
+        token
+    }
+}
+
+/// Keywords registered with `MessagePreprocessor::register_mention_alias` and
+/// `register_mention_keyword`, lowercase and without the `@`, with what they mention.
+type MentionAliases = HashMap<String, Vec<MentionKind>>;
+
+/// Whether text following `before` starts a line.
+fn starts_line(before: &str) -> bool {
+    before
+        .rsplit('\n')
+        .next()
+        .unwrap_or_default()
+        .trim()
+        .is_empty()
+}
+
+/// The whitespace-separated tokens of `text`, with their byte offsets.
+fn whitespace_tokens(text: &str) -> Vec<(usize, &str)> {
+    let mut tokens = Vec::new();
+    let mut start = None;
+    for (index, c) in text.char_indices() {
+        match (c.is_whitespace(), start) {
+            (true, Some(token_start)) => {
+                tokens.push((token_start, &text[token_start..index]));
+                start = None;
+            }
+            (false, None) => start = Some(index),
+            _ => {}
+        }
+    }
+    if let Some(token_start) = start {
+        tokens.push((token_start, &text[token_start..]));
+    }
+    tokens
+}
+
+/// The SteamID64 of an individual account on the public universe, written in
+/// `digits`.
+fn individual_steam_id64(digits: &str) -> Option<SteamID> {
+    if digits.is_empty() || !digits.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    let raw: u64 = digits.parse().ok()?;
+    // Universe 1, account type 1 (individual), instance 1, non-zero account id.
+    (raw >> 32 == 0x0110_0001 && raw as u32 != 0).then(|| SteamID::from(raw))
+}
+
+/// The account of a `STEAM_X:Y:Z` token.
+fn steam2_id(token: &str) -> Option<SteamID> {
+    let mut parts = token.strip_prefix("STEAM_")?.split(':');
+    let (universe, low_bit, high) = (parts.next()?, parts.next()?, parts.next()?);
+    if parts.next().is_some() || !matches!(universe, "0" | "1") {
+        return None;
+    }
+    let low_bit: u32 = match low_bit {
+        "0" => 0,
+        "1" => 1,
+        _ => return None,
+    };
+    if high.is_empty() || !high.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    let account_id = high
+        .parse::<u32>()
+        .ok()?
+        .checked_mul(2)?
+        .checked_add(low_bit)?;
+    (account_id != 0).then(|| {
+        SteamID::new(
+            account_id,
+            Instance::Desktop,
+            AccountType::Individual,
+            Universe::Public,
+        )
+    })
+}
+
+/// The account of a `steamcommunity.com/profiles/<SteamID64>` link, with or without the
+/// scheme, `www.` or a trailing slash.
+fn profile_link_steam_id(token: &str) -> Option<SteamID> {
+    let link = token
+        .strip_prefix("https://")
+        .or_else(|| token.strip_prefix("http://"))
+        .unwrap_or(token);
+    let link = link.strip_prefix("www.").unwrap_or(link);
+    let path = link.strip_prefix("steamcommunity.com/profiles/")?;
+    let path = path.split(['?', '#']).next().unwrap_or_default();
+    individual_steam_id64(path.strip_suffix('/').unwrap_or(path))
+}
+
+/// Whether `token` ends with a complete `[U:1:...]` token.
+fn ends_with_steam_id(token: &str) -> bool {
+    token
+        .rfind("[U:1:")
+        .is_some_and(|start| token[start..].find(']') == Some(token.len() - start - 1))
+}
+
 /// Represents a BBCode node
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub struct BBCodeNode {
-    /// The BBCode tag name (e.g., "b", "i", "spoiler").
+    /// The BBCode tag name (e.g., "url", "spoiler").
+    ///
+    /// Tag names are matched case-insensitively and stored in lowercase, so `[URL=...]`
+    /// and `[url=...]` both produce `"url"`.
     pub tag: String,
     /// Attributes associated with the tag (e.g., URL value for `[url=...]`).
     pub attrs: HashMap<String, String>,
@@ -54,8 +364,18 @@ pub struct BBCodeNode {
 }
 
 /// Represents BBCode content (either string or node)
-#[derive(Debug, Clone, Serialize, Deserialize)]
-#[serde(untagged)]
+///
+/// `String` segments hold the message text exactly as written, whitespace included:
+/// newlines, indentation and runs of spaces are kept, and there is no separate line break
+/// variant. Renderers decide how to show them (`render_html`, `render_markdown`), while
+/// `plain_text` collapses them for search.
+///
+/// Serialized internally tagged by `type`: text as `{"type":"text","text":"..."}` and nodes
+/// as `{"type":"node","tag":"url","attrs":{...},"content":[...]}`. The untagged form
+/// written before `PreprocessedMessage::SCHEMA_VERSION` 1 (a bare string, or a node object
+/// without `type`) is still accepted when deserializing.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(into = "TaggedContent", from = "ContentRepr")]
 pub enum BBCodeContent {
     /// Plain text content.
     String(String),
@@ -63,15 +383,205 @@ pub enum BBCodeContent {
     Node(BBCodeNode),
 }
 
+impl BBCodeContent {
+    /// Compare two trees by text, tags and nesting.
+    ///
+    /// With `ignore_attrs` set, attributes are not compared, so `[url=a]x[/url]` matches
+    /// `[url=b]x[/url]`; otherwise this is the same as `==`.
+    pub fn structurally_equal(&self, other: &Self, ignore_attrs: bool) -> bool {
+        match (self, other) {
+            (Self::String(a), Self::String(b)) => a == b,
+            (Self::Node(a), Self::Node(b)) => {
+                a.tag == b.tag
+                    && (ignore_attrs || a.attrs == b.attrs)
+                    && match (&a.content, &b.content) {
+                        (Some(a), Some(b)) => {
+                            a.len() == b.len()
+                                && a.iter()
+                                    .zip(b)
+                                    .all(|(a, b)| a.structurally_equal(b, ignore_attrs))
+                        }
+                        (None, None) => true,
+                        _ => false,
+                    }
+            }
+            _ => false,
+        }
+    }
+
+    /// The nodes in this content, depth-first: each node before its children, and
+    /// children before the node's next sibling.
+    pub fn iter_nodes(&self) -> impl Iterator<Item = &BBCodeNode> {
+        let mut stack = vec![self];
+        std::iter::from_fn(move || {
+            while let Some(content) = stack.pop() {
+                if let Self::Node(node) = content {
+                    stack.extend(node.content.iter().flatten().rev());
+                    return Some(node);
+                }
+            }
+            None
+        })
+    }
+
+    /// The text segments in this content, in document order, including text nested in
+    /// nodes.
+    pub fn iter_text(&self) -> impl Iterator<Item = &str> {
+        let mut stack = vec![self];
+        std::iter::from_fn(move || {
+            while let Some(content) = stack.pop() {
+                match content {
+                    Self::String(text) => return Some(text.as_str()),
+                    Self::Node(node) => stack.extend(node.content.iter().flatten().rev()),
+                }
+            }
+            None
+        })
+    }
+}
+
+/// Transforms a parsed BBCode tree into a new one.
+///
+/// Each `visit_*` method returns the content that replaces what it visited, so a visitor
+/// can keep, drop, replace or expand it. The defaults copy text and rebuild nodes from
+/// their visited children; override only what should change.
+///
+/// # Example
+///
+/// ```
+/// use SC_Sub_Poster::preprocessing::{BBCodeContent, BBCodeNode, MessagePreprocessor, Visitor};
+///
+/// struct RedactLinks;
+///
+/// impl Visitor for RedactLinks {
+///     fn visit_node(&mut self, node: &BBCodeNode) -> Vec<BBCodeContent> {
+///         if node.tag == "url" {
+///             return vec![BBCodeContent::String("[link removed]".to_string())];
+///         }
+///         vec![BBCodeContent::Node(self.walk_node(node))]
+///     }
+/// }
+///
+/// let parsed = MessagePreprocessor::parse_bbcode("see [url=https://x.example]this[/url]!");
+/// assert_eq!(
+///     RedactLinks.visit_all(&parsed),
+///     [BBCodeContent::String("see [link removed]!".to_string())]
+/// );
+/// ```
+pub trait Visitor {
+    /// Replace a node; the default keeps it and visits its children.
+    fn visit_node(&mut self, node: &BBCodeNode) -> Vec<BBCodeContent> {
+        vec![BBCodeContent::Node(self.walk_node(node))]
+    }
+
+    /// Replace a text segment; the default keeps it.
+    fn visit_text(&mut self, text: &str) -> Vec<BBCodeContent> {
+        vec![BBCodeContent::String(text.to_string())]
+    }
+
+    /// Copy `node` with its children visited.
+    fn walk_node(&mut self, node: &BBCodeNode) -> BBCodeNode {
+        BBCodeNode {
+            tag: node.tag.clone(),
+            attrs: node.attrs.clone(),
+            content: node
+                .content
+                .as_deref()
+                .map(|children| self.visit_all(children)),
+        }
+    }
+
+    /// Visit each item of `parsed` in order, merging adjacent text in the result.
+    fn visit_all(&mut self, parsed: &[BBCodeContent]) -> Vec<BBCodeContent> {
+        let mut result: Vec<BBCodeContent> = Vec::with_capacity(parsed.len());
+        for content in parsed {
+            let replaced = match content {
+                BBCodeContent::String(text) => self.visit_text(text),
+                BBCodeContent::Node(node) => self.visit_node(node),
+            };
+            for item in replaced {
+                match (result.last_mut(), item) {
+                    (Some(BBCodeContent::String(last)), BBCodeContent::String(text)) => {
+                        last.push_str(&text)
+                    }
+                    (_, BBCodeContent::String(text)) if text.is_empty() => {}
+                    (_, item) => result.push(item),
+                }
+            }
+        }
+        result
+    }
+}
+
+/// Serialized form of `BBCodeContent`.
+#[derive(Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum TaggedContent {
+    Text { text: String },
+    Node(BBCodeNode),
+}
+
+/// Every form `BBCodeContent` is read from: tagged, or legacy untagged.
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum ContentRepr {
+    Tagged(TaggedContent),
+    LegacyText(String),
+    LegacyNode(BBCodeNode),
+}
+
+impl From<BBCodeContent> for TaggedContent {
+    fn from(content: BBCodeContent) -> Self {
+        match content {
+            BBCodeContent::String(text) => Self::Text { text },
+            BBCodeContent::Node(node) => Self::Node(node),
+        }
+    }
+}
+
+impl From<ContentRepr> for BBCodeContent {
+    fn from(repr: ContentRepr) -> Self {
+        match repr {
+            ContentRepr::Tagged(TaggedContent::Text { text }) | ContentRepr::LegacyText(text) => {
+                Self::String(text)
+            }
+            ContentRepr::Tagged(TaggedContent::Node(node)) | ContentRepr::LegacyNode(node) => {
+                Self::Node(node)
+            }
+        }
+    }
+}
+
 /// Represents chat mentions
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
 pub struct ChatMentions {
     /// Whether the message mentions all group members (via `@all`).
     pub mention_all: bool,
     /// Whether the message mentions online/active members (via `@here`).
     pub mention_here: bool,
-    /// List of specific Steam IDs mentioned in the message (via `[U:1:xxxxx]` format).
+    /// List of specific Steam IDs mentioned in the message: via `[U:1:xxxxx]`,
+    /// `STEAM_0:1:xxxxx`, `@<SteamID64>`, profile links or `[mention]` tags, or by name
+    /// through a `MentionRoster`.
+    ///
+    /// Each account is listed once, in the order it is first mentioned in the message,
+    /// however it was written; `occurrences` counts how often it was mentioned.
     pub mention_steamids: Vec<MentionSteamId>,
+    /// The `@name` mentions matched against the preprocessor's `MentionRoster`.
+    ///
+    /// Their accounts are also listed in `mention_steamids`; an account listed here was
+    /// mentioned by name rather than by SteamID.
+    #[serde(default)]
+    pub name_mentions: Vec<NameMention>,
+    /// Where each mention occurs, in message order.
+    ///
+    /// Ranges are byte offsets into the analyzed message (`modified_message()` for a
+    /// processed response) and always lie on `char` boundaries. They cover the mention
+    /// itself, without the punctuation trimmed around it. A name mentioning several
+    /// members has one span per member, all with the same range. Mentions in text that
+    /// could not be located in the message, e.g. around tags dropped by
+    /// `MalformedTagPolicy::Drop`, have no span.
+    #[serde(default)]
+    pub spans: Vec<MentionSpan>,
 }
 
 impl ChatMentions {
@@ -79,6 +589,228 @@ impl ChatMentions {
     fn has_any_mentions(&self) -> bool {
         self.mention_all || self.mention_here || !self.mention_steamids.is_empty()
     }
+
+    /// The mentions found by a scan, with repeated accounts dropped, or `None` if
+    /// there are none.
+    fn into_found(mut self) -> Option<Self> {
+        let mut seen = HashSet::new();
+        self.mention_steamids
+            .retain(|steam_id| seen.insert(u64::from(steam_id.0)));
+        self.has_any_mentions().then_some(self)
+    }
+
+    /// How many times `steam_id` is mentioned individually, counted from `spans`.
+    ///
+    /// A name shared by several members counts once for each of them.
+    pub fn occurrences(&self, steam_id: SteamID) -> usize {
+        self.spans
+            .iter()
+            .filter(|span| span.kind == MentionKind::SteamId(MentionSteamId(steam_id)))
+            .count()
+    }
+
+    /// Whether `steam_id` is mentioned individually, by SteamID or by name.
+    pub fn contains(&self, steam_id: SteamID) -> bool {
+        let steam_id = u64::from(steam_id);
+        self.mention_steamids
+            .iter()
+            .any(|mentioned| u64::from(mentioned.0) == steam_id)
+    }
+
+    /// Whether the user `steam_id` is pinged: by `@all`, by `@here` when
+    /// `treat_here_as_mention` is set, or individually.
+    ///
+    /// `@here` only notifies members who are online, so callers that cannot tell
+    /// whether the user was online may want to leave it out.
+    pub fn mentions_user(&self, steam_id: SteamID, treat_here_as_mention: bool) -> bool {
+        self.mention_all || (treat_here_as_mention && self.mention_here) || self.contains(steam_id)
+    }
+}
+
+/// An `@name` mention resolved through a `MentionRoster`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct NameMention {
+    /// The name as written after the `@`.
+    pub name: String,
+    /// Every roster member with that name; more than one when the name is ambiguous.
+    pub candidates: Vec<MentionSteamId>,
+}
+
+impl NameMention {
+    /// Whether several members share the mentioned name.
+    pub fn is_ambiguous(&self) -> bool {
+        self.candidates.len() > 1
+    }
+}
+
+/// What a `MentionSpan` mentions.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(tag = "type", content = "steam_id", rename_all = "snake_case")]
+pub enum MentionKind {
+    /// `@all`.
+    All,
+    /// `@here`.
+    Here,
+    /// A single account, by SteamID or by name.
+    SteamId(MentionSteamId),
+}
+
+impl MentionKind {
+    /// What a `[mention=value]` tag pings: `all`, `here` or a SteamID, given as a
+    /// SteamID64 or in `[U:1:xxxxx]` form.
+    fn from_tag_value(value: Option<&String>) -> Option<Self> {
+        let value = value?.trim();
+        if value.eq_ignore_ascii_case("all") {
+            return Some(Self::All);
+        }
+        if value.eq_ignore_ascii_case("here") {
+            return Some(Self::Here);
+        }
+        let steam_id = match value.parse::<u64>() {
+            Ok(0) => return None,
+            Ok(raw) => SteamID::from(raw),
+            Err(_) => SteamID::try_from(value).ok()?,
+        };
+        Some(Self::SteamId(MentionSteamId(steam_id)))
+    }
+}
+
+/// A mention and where it occurs in the message.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct MentionSpan {
+    /// What is mentioned.
+    pub kind: MentionKind,
+    /// Byte range of the mention, e.g. of `@[U:1:1531059355]` in `(@[U:1:1531059355]).`
+    pub byte_range: Range<usize>,
+}
+
+/// Display names of chat members, for recognizing `@name` mentions.
+///
+/// Names are matched case-insensitively, with runs of whitespace treated as a single
+/// space, so `@kyle  smith` matches a member called `Kyle Smith`. Several members may
+/// share a name; a mention of it then lists all of them.
+///
+/// Build one by hand with `insert`, or from a group's member list with
+/// `ChatRoomClient::member_roster`, and install it with
+/// `MessagePreprocessor::with_roster`.
+///
+/// # Example
+///
+/// ```
+/// use SC_Sub_Poster::preprocessing::{MentionRoster, MessagePreprocessor};
+/// use steamid_ng::SteamID;
+///
+/// let kyle = SteamID::from(76561199491325083);
+/// let mut roster = MentionRoster::new();
+/// roster.insert("Kyle", kyle);
+///
+/// let mentions = MessagePreprocessor::default()
+///     .with_roster(roster)
+///     .find_mentions("hey @kyle, ready?")
+///     .unwrap();
+/// assert_eq!(mentions.mention_steamids[0].as_inner(), kyle);
+/// assert_eq!(mentions.name_mentions[0].name, "kyle");
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct MentionRoster {
+    names: HashMap<String, Vec<SteamID>>,
+    max_words: usize,
+}
+
+impl MentionRoster {
+    /// Create an empty roster.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add a member under `name`; blank names are ignored.
+    pub fn insert(&mut self, name: &str, steam_id: SteamID) {
+        let key = Self::normalize(name);
+        if key.is_empty() {
+            return;
+        }
+        self.max_words = self.max_words.max(key.split(' ').count());
+        let members = self.names.entry(key).or_default();
+        if !members.contains(&steam_id) {
+            members.push(steam_id);
+        }
+    }
+
+    /// The members called `name`, empty if there are none.
+    pub fn lookup(&self, name: &str) -> &[SteamID] {
+        self.names
+            .get(&Self::normalize(name))
+            .map(Vec::as_slice)
+            .unwrap_or_default()
+    }
+
+    /// Number of distinct names.
+    pub fn len(&self) -> usize {
+        self.names.len()
+    }
+
+    /// Whether the roster holds no names.
+    pub fn is_empty(&self) -> bool {
+        self.names.is_empty()
+    }
+
+    fn normalize(name: &str) -> String {
+        name.split_whitespace()
+            .collect::<Vec<_>>()
+            .join(" ")
+            .to_lowercase()
+    }
+
+    /// Match the `@name` mention starting at `tokens[0]`, preferring the longest name.
+    ///
+    /// `tokens` are `(offset, token)` pairs; the returned range is in the same offsets.
+    fn match_tokens(
+        &self,
+        tokens: &[(usize, &str)],
+        rules: &MentionRules,
+    ) -> Option<(NameMention, Range<usize>)> {
+        let &(offset, token) = tokens.first()?;
+        let first = rules.trim_start(token);
+        let name_start = first.strip_prefix('@')?;
+        if name_start.is_empty() || name_start.starts_with('[') {
+            return None;
+        }
+        if [MENTION_ALL, MENTION_HERE].contains(&rules.trim(first)) {
+            return None;
+        }
+
+        for words in (1..=self.max_words.min(tokens.len())).rev() {
+            let mut written = name_start.to_string();
+            for (_, token) in &tokens[1..words] {
+                written.push(' ');
+                written.push_str(token);
+            }
+            let (last_offset, last) = tokens[words - 1];
+            for name in [written.as_str(), rules.trim_end(&written)] {
+                let candidates = self.lookup(name);
+                if !candidates.is_empty() {
+                    let start = offset + token.len() - first.len();
+                    let end = last_offset + last.len() - (written.len() - name.len());
+                    let mention = NameMention {
+                        name: name.to_string(),
+                        candidates: candidates.iter().copied().map(MentionSteamId).collect(),
+                    };
+                    return Some((mention, start..end));
+                }
+            }
+        }
+        None
+    }
+}
+
+impl<S: AsRef<str>> FromIterator<(S, SteamID)> for MentionRoster {
+    fn from_iter<I: IntoIterator<Item = (S, SteamID)>>(members: I) -> Self {
+        let mut roster = Self::new();
+        for (name, steam_id) in members {
+            roster.insert(name.as_ref(), steam_id);
+        }
+        roster
+    }
 }
 
 /// Wrapper around `SteamID` that supports serde serialization.
@@ -131,471 +863,6733 @@ impl<'de> serde::Deserialize<'de> for MentionSteamId {
     }
 }
 
-/// Preprocessed message with BBCode parsing and mentions
-#[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct PreprocessedMessage {
-    /// The original message text before any processing.
-    pub original_message: String,
-    /// The message text after server-side modifications (if available).
-    pub modified_message: String,
-    /// Parsed BBCode structure extracted from the message.
-    pub message_bbcode_parsed: Vec<BBCodeContent>,
-    /// Extracted mentions from the message, if any were found.
-    pub mentions: Option<ChatMentions>,
-    /// Server timestamp when the message was processed (if available).
-    pub server_timestamp: Option<u32>,
-    /// Message ordinal/sequence number assigned by the server (if available).
-    pub ordinal: Option<u32>,
+/// An emoticon used in a message and how often it appears.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct EmoticonUse {
+    /// The emoticon name without delimiters (e.g. `steamhappy`).
+    pub name: String,
+    /// Number of times the emoticon appears in the message.
+    pub count: usize,
 }
 
-/// Message preprocessor for Steam chat messages.
+/// A sticker sent as a chat message.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct StickerInfo {
+    /// The sticker name from the `type` attribute (e.g. `ChromaticCat`).
+    pub name: String,
+    /// The `[sticker]` node the name was read from.
+    pub raw: BBCodeNode,
+}
+
+impl StickerInfo {
+    /// Read a sticker from a `[sticker type="..."]` node.
+    ///
+    /// # Returns
+    ///
+    /// `None` if the node is not a sticker or has no non-empty `type` (or
+    /// `[sticker=...]` / `[sticker:...]` shorthand) attribute.
+    pub fn from_node(node: &BBCodeNode) -> Option<Self> {
+        if node.tag != BBCODE_TYPE_STICKER {
+            return None;
+        }
+        let name = node
+            .attrs
+            .get("type")
+            .or_else(|| node.attrs.get("value"))
+            .or_else(|| node.attrs.get("name"))?
+            .trim();
+        (!name.is_empty()).then(|| Self {
+            name: name.to_string(),
+            raw: node.clone(),
+        })
+    }
+}
+
+/// A room effect played over the chat when a message arrives.
 ///
-/// Provides utilities for parsing BBCode, extracting mentions, and preparing
-/// messages for sending through the Steam chat API.
-pub struct MessagePreprocessor;
+/// Serialized as its name, so effects this crate does not know yet round-trip through
+/// `Other`.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(from = "String", into = "String")]
+pub enum RoomEffect {
+    /// Falling snow.
+    Snow,
+    /// Rising balloons.
+    Balloons,
+    /// Confetti burst.
+    Confetti,
+    /// Lunar New Year lanterns.
+    Lanterns,
+    /// Fireworks.
+    Fireworks,
+    /// An effect not listed above, by its Steam name.
+    Other(String),
+}
 
-impl MessagePreprocessor {
-    /// Preprocess a message with BBCode parsing and mention detection
-    #[tracing::instrument(name = "kether.preprocess.message", skip(message))]
-    pub fn preprocess_message(message: &str) -> PreprocessedMessage {
-        trace!(original_len = message.len(), "starting preprocessing");
-        let message_bbcode_parsed = Self::parse_bbcode(message);
-        let mentions = Self::extract_mentions(message);
+impl RoomEffect {
+    /// The effect name used in `[roomeffect]` tags and `/effect` commands.
+    pub fn name(&self) -> &str {
+        match self {
+            Self::Snow => "snow",
+            Self::Balloons => "balloons",
+            Self::Confetti => "confetti",
+            Self::Lanterns => "lanterns",
+            Self::Fireworks => "fireworks",
+            Self::Other(name) => name,
+        }
+    }
 
-        PreprocessedMessage {
-            original_message: message.to_string(),
-            modified_message: message.to_string(),
-            message_bbcode_parsed,
-            mentions,
-            server_timestamp: None,
-            ordinal: None,
+    /// Look up an effect by name, ignoring case; unknown names become `Other`.
+    pub fn from_name(name: &str) -> Self {
+        match name.trim().to_ascii_lowercase().as_str() {
+            "snow" => Self::Snow,
+            "balloons" => Self::Balloons,
+            "confetti" => Self::Confetti,
+            "lanterns" => Self::Lanterns,
+            "fireworks" => Self::Fireworks,
+            _ => Self::Other(name.trim().to_string()),
         }
     }
+}
 
-    /// Parse BBCode from a message string
-    pub fn parse_bbcode(message: &str) -> Vec<BBCodeContent> {
-        bbcode::Parser::new(ALLOWED_BBCODE_TAGS).parse(message)
+impl From<String> for RoomEffect {
+    fn from(name: String) -> Self {
+        Self::from_name(&name)
     }
+}
 
-    /// Extract mentions from a message
-    pub fn extract_mentions(message: &str) -> Option<ChatMentions> {
-        let mut mentions = ChatMentions {
-            mention_all: false,
-            mention_here: false,
-            mention_steamids: Vec::new(),
-        };
+impl From<RoomEffect> for String {
+    fn from(effect: RoomEffect) -> Self {
+        effect.name().to_string()
+    }
+}
 
-        for raw_token in message.split_whitespace() {
-            Self::process_mention_token(raw_token, &mut mentions);
-        }
+/// A slash command at the start of a message.
+///
+/// Steam gives messages such as `/flip` or `/random 20` special meaning. A message whose
+/// first non-whitespace characters are `//` is literal text, never a command; use
+/// `helpers::escape_leading_slash` to send text that merely starts with a slash.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ChatCommand {
+    /// `/me <text>`: an emote, see `PreprocessedMessage::is_emote`.
+    Me { text: String },
+    /// `/random`, `/random <max>` or `/random <min>-<max>`: a random number in
+    /// `min..=max`, 1 to 100 by default.
+    Random { min: u32, max: u32 },
+    /// `/flip`: a coin flip.
+    Flip,
+    /// `/giphy <query>`: a GIF search.
+    Giphy { query: String },
+    /// `/code <text>`: the rest of the message as a code block.
+    Code { text: String },
+    /// `/spoiler <text>`: the rest of the message hidden as a spoiler.
+    Spoiler { text: String },
+    /// `/effect <name>`: a room effect, see `PreprocessedMessage::room_effect`.
+    Effect { effect: RoomEffect },
+}
 
-        if mentions.has_any_mentions() {
-            Some(mentions)
-        } else {
-            None
+impl ChatCommand {
+    /// Range `/random` picks from when no bounds are given.
+    pub const DEFAULT_RANDOM_RANGE: (u32, u32) = (1, 100);
+
+    /// The command name without the leading slash.
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Me { .. } => "me",
+            Self::Random { .. } => "random",
+            Self::Flip => "flip",
+            Self::Giphy { .. } => "giphy",
+            Self::Code { .. } => "code",
+            Self::Spoiler { .. } => "spoiler",
+            Self::Effect { .. } => "effect",
         }
     }
 
-    /// Process a single token to detect mentions
-    fn process_mention_token(token: &str, mentions: &mut ChatMentions) {
-        let cleaned_token = token.trim_matches(|c: char| MENTION_PUNCTUATION.contains(c));
+    /// Parse the command a message starts with.
+    ///
+    /// Leading whitespace is ignored and command names match case-insensitively. The
+    /// name must be followed by whitespace or the end of the message, so `/meow` is not
+    /// `/me`. `/code` keeps its text verbatim after the single separating whitespace
+    /// character, so indentation survives; the other commands trim their argument.
+    ///
+    /// # Returns
+    ///
+    /// `None` for messages that do not start with a known command, start with `//`,
+    /// or carry arguments the command does not accept (`/random x`, `/giphy` without a
+    /// query, `/effect` without a name).
+    pub fn parse(message: &str) -> Option<Self> {
+        let rest = message.trim_start().strip_prefix('/')?;
+        let name_end = rest.find(char::is_whitespace).unwrap_or(rest.len());
+        let (name, args) = rest.split_at(name_end);
+        let command = match name.to_ascii_lowercase().as_str() {
+            "me" => Self::Me {
+                text: args.trim().to_string(),
+            },
+            "random" => {
+                let args = args.trim();
+                let (min, max) = if args.is_empty() {
+                    Self::DEFAULT_RANDOM_RANGE
+                } else if let Some((min, max)) = args.split_once('-') {
+                    (min.trim().parse().ok()?, max.trim().parse().ok()?)
+                } else {
+                    (Self::DEFAULT_RANDOM_RANGE.0, args.parse().ok()?)
+                };
+                if min > max {
+                    return None;
+                }
+                Self::Random { min, max }
+            }
+            "flip" => Self::Flip,
+            "giphy" => {
+                let query = args.split_whitespace().collect::<Vec<_>>().join(" ");
+                if query.is_empty() {
+                    return None;
+                }
+                Self::Giphy { query }
+            }
+            "code" => {
+                let mut chars = args.chars();
+                chars.next();
+                Self::Code {
+                    text: chars.as_str().to_string(),
+                }
+            }
+            "spoiler" => Self::Spoiler {
+                text: args.trim().to_string(),
+            },
+            "effect" => Self::Effect {
+                effect: RoomEffect::from_name(args.split_whitespace().next()?),
+            },
+            _ => return None,
+        };
+        Some(command)
+    }
+}
 
-        if cleaned_token == MENTION_ALL {
-            mentions.mention_all = true;
-            return;
+/// The outcome of a `/random` roll posted in a room.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct RandomResult {
+    /// The lowest number the roll could give.
+    pub min: u32,
+    /// The highest number the roll could give.
+    pub max: u32,
+    /// The number rolled.
+    pub result: u32,
+}
+
+impl RandomResult {
+    /// Read a roll from a `[random]` node.
+    ///
+    /// The result comes from the `result` attribute or, failing that, the node's text;
+    /// missing bounds default to `ChatCommand::DEFAULT_RANDOM_RANGE`.
+    ///
+    /// # Returns
+    ///
+    /// `None` if the node is not a roll, has no numeric result, or the result is outside
+    /// the bounds.
+    pub fn from_node(node: &BBCodeNode) -> Option<Self> {
+        if node.tag != BBCODE_TYPE_RANDOM {
+            return None;
         }
+        let bound = |key: &str, default: u32| match node.attrs.get(key) {
+            Some(value) => value.trim().parse().ok(),
+            None => Some(default),
+        };
+        let (min, max) = ChatCommand::DEFAULT_RANDOM_RANGE;
+        let result = match node.attrs.get("result") {
+            Some(result) => result.trim().parse().ok()?,
+            None => MessagePreprocessor::render_plain_text(node.content.as_deref()?)
+                .parse()
+                .ok()?,
+        };
+        Self::checked(bound("min", min)?, bound("max", max)?, result)
+    }
 
-        if cleaned_token == MENTION_HERE {
-            mentions.mention_here = true;
-            return;
+    /// Read a roll from the text older clients are sent instead of the tag, such as
+    /// `Random number from 1 to 100: 42` or `rolled 42 (1-100)`.
+    ///
+    /// The text must mention a random number or a roll and contain either the bounds and
+    /// the result, in that order, or just the upper bound and the result.
+    pub fn from_text(text: &str) -> Option<Self> {
+        let lower = text.to_ascii_lowercase();
+        if !lower.contains("random") && !lower.contains("roll") {
+            return None;
+        }
+        let (numbers, result) = numbers_in(text)?;
+        match numbers[..] {
+            [min, max] => Self::checked(min, max, result),
+            [max] => Self::checked(ChatCommand::DEFAULT_RANDOM_RANGE.0, max, result),
+            _ => None,
+        }
+    }
+
+    fn checked(min: u32, max: u32, result: u32) -> Option<Self> {
+        (min..=max)
+            .contains(&result)
+            .then_some(Self { min, max, result })
+    }
+}
+
+/// The bounds and the result of a roll described in `text`.
+///
+/// A roll reads "from 1 to 100: 42", with the result last, but also "rolled 42 (1-100)",
+/// where the result comes first; that order is recognized by the parentheses around the
+/// bounds.
+fn numbers_in(text: &str) -> Option<(Vec<u32>, u32)> {
+    let numbers = |text: &str| -> Option<Vec<u32>> {
+        text.split(|c: char| !c.is_ascii_digit())
+            .filter(|number| !number.is_empty())
+            .map(|number| number.parse().ok())
+            .collect()
+    };
+    if let Some((before, bounds)) = text.split_once('(')
+        && let [result] = numbers(before)?[..]
+    {
+        return Some((numbers(bounds)?, result));
+    }
+    let mut all = numbers(text)?;
+    let last = all.pop()?;
+    Some((all, last))
+}
+
+/// Read a coin flip, `true` for heads, from a `[flip]` node.
+///
+/// The `result` attribute or the node's text may be `heads`/`tails`, `1`/`0` or
+/// `true`/`false`.
+fn coin_flip_from_node(node: &BBCodeNode) -> Option<bool> {
+    let result = match node.attrs.get("result") {
+        Some(result) => result.trim().to_ascii_lowercase(),
+        None => {
+            MessagePreprocessor::render_plain_text(node.content.as_deref()?).to_ascii_lowercase()
+        }
+    };
+    match result.as_str() {
+        "heads" | "1" | "true" => Some(true),
+        "tails" | "0" | "false" => Some(false),
+        _ => None,
+    }
+}
+
+/// Read a coin flip from the text older clients are sent instead of the `[flip]` tag,
+/// such as `flipped a coin: tails`.
+///
+/// The text must mention a flip or a coin and exactly one of the two sides.
+fn coin_flip_from_text(text: &str) -> Option<bool> {
+    let lower = text.to_ascii_lowercase();
+    if !lower.contains("flip") && !lower.contains("coin") {
+        return None;
+    }
+    let sides: Vec<_> = lower
+        .split(|c: char| !c.is_ascii_alphabetic())
+        .filter(|word| matches!(*word, "heads" | "tails"))
+        .collect();
+    match sides[..] {
+        ["heads"] => Some(true),
+        ["tails"] => Some(false),
+        _ => None,
+    }
+}
+
+/// A piece of a message, from `PreprocessedMessage::segments`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum MessageSegment<'m> {
+    /// Text without mentions, bare links or markup.
+    Text(&'m str),
+    /// A mention, either written as a token such as `@all` or as a `[mention]` tag.
+    Mention {
+        /// What is mentioned, and where.
+        span: MentionSpan,
+        /// The mention as written.
+        source: &'m str,
+    },
+    /// A bare `http(s)://` link.
+    Url(&'m str),
+    /// A node at the top of the message, with everything nested in it.
+    BBCode {
+        /// The parsed node.
+        node: &'m BBCodeNode,
+        /// The node as written, from its opening tag to its closing tag.
+        source: &'m str,
+    },
+}
+
+impl<'m> MessageSegment<'m> {
+    /// The part of the message the segment covers, as written.
+    pub fn source(&self) -> &'m str {
+        match self {
+            Self::Text(text) | Self::Url(text) => text,
+            Self::Mention { source, .. } | Self::BBCode { source, .. } => source,
         }
+    }
+}
+
+/// A URL found in a message.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ExtractedUrl {
+    /// The URL itself.
+    pub url: String,
+    /// Whether the URL comes from an `[og]` preview card Steam attached to the message.
+    pub from_og: bool,
+    /// The link text of a `[url]` tag, or the title of an `[og]` card.
+    pub display_text: Option<String>,
+}
+
+/// An image embedded with `[img]`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ImageRef {
+    /// The image URL, as written.
+    pub url: String,
+    /// Whether the URL comes from an attribute (`[img=...]`, `[img src="..."]`) rather
+    /// than from the tag's content.
+    pub from_attr: bool,
+}
 
-        if Self::is_steam_id_format(cleaned_token)
-            && let Ok(steam_id) = SteamID::try_from(cleaned_token)
+impl ImageRef {
+    /// Read an image from an `[img]` node.
+    ///
+    /// # Returns
+    ///
+    /// `None` if the node is not an image or names no URL.
+    pub fn from_node(node: &BBCodeNode) -> Option<Self> {
+        if node.tag != BBCODE_TYPE_IMG {
+            return None;
+        }
+        let (url, from_attr) = match ["src", "url", "value"]
+            .iter()
+            .find_map(|key| node.attrs.get(*key))
         {
-            mentions
-                .mention_steamids
-                .push(MentionSteamId::from(steam_id));
+            Some(url) => (url.trim().to_string(), true),
+            None => (
+                MessagePreprocessor::render_plain_text(node.content.as_deref()?),
+                false,
+            ),
+        };
+        (!url.is_empty()).then_some(Self { url, from_attr })
+    }
+
+    /// Whether the URL is safe to load: an absolute `http(s)` URL with a host and no
+    /// whitespace or characters that could break out of an HTML attribute.
+    ///
+    /// `data:` URIs, `javascript:` and relative URLs are rejected.
+    pub fn is_valid(&self) -> bool {
+        is_web_url(&self.url)
+    }
+}
+
+/// Whether `url` is an absolute `http(s)` URL that can be put in an HTML attribute.
+fn is_web_url(url: &str) -> bool {
+    let lower = url.to_ascii_lowercase();
+    let Some(rest) = ["http://", "https://"]
+        .iter()
+        .find_map(|scheme| lower.strip_prefix(scheme))
+    else {
+        return false;
+    };
+    !rest.is_empty()
+        && !rest.starts_with(['/', '?', '#'])
+        && !url.contains(|c: char| c.is_whitespace() || c.is_control() || "\"'<>`".contains(c))
+}
+
+/// A game invite sent as `[gameinvite appid="..." lobbyid="..."]`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct GameInviteInfo {
+    /// The Steam app id of the game.
+    pub app_id: u32,
+    /// The lobby to join, if the invite names one.
+    pub lobby_id: Option<u64>,
+    /// The `[gameinvite]` node the invite was read from.
+    pub raw: BBCodeNode,
+}
+
+impl GameInviteInfo {
+    /// Read a game invite from a `[gameinvite]` node.
+    ///
+    /// # Returns
+    ///
+    /// `None` if the node is not a game invite or its `appid` is missing or not a
+    /// number. A missing, zero or unparsable `lobbyid` yields `lobby_id: None`.
+    pub fn from_node(node: &BBCodeNode) -> Option<Self> {
+        if node.tag != BBCODE_TYPE_GAMEINVITE {
+            return None;
         }
+        let app_id = node.attrs.get("appid")?.trim().parse().ok()?;
+        let lobby_id = node
+            .attrs
+            .get("lobbyid")
+            .and_then(|lobby| lobby.trim().parse().ok())
+            .filter(|lobby| *lobby != 0);
+        Some(Self {
+            app_id,
+            lobby_id,
+            raw: node.clone(),
+        })
     }
+}
 
-    /// Check if a token matches Steam ID format [U:1:...]
-    fn is_steam_id_format(token: &str) -> bool {
-        token.starts_with("[U:1:") && token.ends_with(']')
+/// A quote, such as Steam's reply quote `[quote=author;timestamp;ordinal]text[/quote]`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct QuoteInfo {
+    /// The quoted user, from the SteamID64 in the first field.
+    pub author: Option<SteamID>,
+    /// Server timestamp of the quoted message.
+    pub timestamp: Option<u32>,
+    /// Ordinal of the quoted message.
+    pub ordinal: Option<u32>,
+    /// The quoted text without BBCode markup, nested quotes included.
+    pub text: String,
+}
+
+impl QuoteInfo {
+    /// Read a quote from a closed `[quote]` node.
+    ///
+    /// Reply quotes pack the author, timestamp and ordinal into the shorthand value,
+    /// separated by semicolons. Missing or unparsable fields are `None`, so a plain
+    /// `[quote]` or `[quote=Some Name]` yields a quote with only `text`.
+    ///
+    /// # Returns
+    ///
+    /// `None` if the node is not a quote or was never closed.
+    pub fn from_node(node: &BBCodeNode) -> Option<Self> {
+        let children = node.content.as_deref()?;
+        Self::with_text(node, MessagePreprocessor::render_plain_text(children))
     }
 
-    /// Convert a message with mentions to a format suitable for sending
-    pub fn prepare_message_for_sending(message: &str) -> String {
-        // Remove or escape special characters that might cause issues
-        // Basic escaping - in practice you'd want more sophisticated handling
-        message.replace("\\[", "[").replace("\\]", "]")
+    /// Read the quote metadata from a `[quote]` node whose text is already rendered.
+    fn with_text(node: &BBCodeNode, text: String) -> Option<Self> {
+        if node.tag != BBCODE_TYPE_QUOTE {
+            return None;
+        }
+        let mut fields = node
+            .attrs
+            .get("value")
+            .map(String::as_str)
+            .unwrap_or_default()
+            .split(';')
+            .map(str::trim);
+
+        let author = fields
+            .next()
+            .and_then(|author| SteamID::try_from(author).ok())
+            .filter(|author| author.account_type() == AccountType::Individual);
+        let timestamp = fields.next().and_then(|timestamp| timestamp.parse().ok());
+        let ordinal = fields.next().and_then(|ordinal| ordinal.parse().ok());
+
+        Some(Self {
+            author,
+            timestamp,
+            ordinal,
+            text,
+        })
     }
+}
 
-    /// Process a response from Steam with preprocessing
-    pub fn process_response(
-        original_message: &str,
-        modified_message: &str,
-        server_timestamp: u32,
-        ordinal: u32,
-    ) -> PreprocessedMessage {
-        let message_bbcode_parsed = Self::parse_bbcode(modified_message);
-        let mentions = Self::extract_mentions(modified_message);
+/// A trade offer referenced by a `[tradeoffer]` or `[tradeofferlink]` tag.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TradeOfferRef {
+    /// The trade offer id.
+    pub offer_id: u64,
+    /// The other party of the offer, when the tag names one.
+    pub partner: Option<SteamID>,
+    /// The offer URL, when the tag carries one.
+    pub url: Option<String>,
+}
 
-        PreprocessedMessage {
-            original_message: original_message.to_string(),
-            modified_message: modified_message.to_string(),
-            message_bbcode_parsed,
-            mentions,
-            server_timestamp: Some(server_timestamp),
-            // Ordinal can be 0 (per DoctorMcKay's node-steam-user wiki (https://github.com/DoctorMcKay/node-steam-user/wiki/SteamChatRoomClient?utm_source=copilot.com#deletechatmessagesgroupid-chatid-messages-callback), it can be omitted in deletion if 0)
-            // So we keep it as Some(0) instead of None
-            ordinal: Some(ordinal),
+/// A Steam economy link found in a message; see `extract_item_links`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ItemLink {
+    /// A Community Market listing page, `.../market/listings/<appid>/<name>`.
+    MarketListing {
+        /// The app the item belongs to (730 for Counter-Strike).
+        app_id: u32,
+        /// The item's market hash name, percent-decoded.
+        market_hash_name: String,
+        /// The link as written.
+        url: String,
+    },
+    /// An inventory page, optionally pointing at one item with an
+    /// `#<appid>_<contextid>_<assetid>` fragment.
+    Inventory {
+        /// The owner, for `/profiles/<steamid64>/` links.
+        owner: Option<SteamID>,
+        /// The owner's custom URL name, for `/id/<name>/` links.
+        vanity_name: Option<String>,
+        /// The app whose inventory is shown, when the fragment names one.
+        app_id: Option<u32>,
+        /// The inventory context, when the fragment names one.
+        context_id: Option<u64>,
+        /// The selected item, when the fragment names one.
+        asset_id: Option<u64>,
+        /// The link as written.
+        url: String,
+    },
+    /// A Counter-Strike inspect link (`+csgo_econ_action_preview S...A...D...`).
+    Inspect {
+        /// The owner of an inventory item (`S` component).
+        owner: Option<SteamID>,
+        /// The market listing of a listed item (`M` component).
+        market_listing_id: Option<u64>,
+        /// The inspected item (`A` component).
+        asset_id: u64,
+        /// The inspect parameter Steam needs to look the item up (`D` component).
+        d: u64,
+        /// The link as written.
+        url: String,
+    },
+}
+
+/// How the server changed part of a message; see `PreprocessedMessage::server_changes`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum MessageChangeKind {
+    /// Emoticons were rewritten to another syntax, such as `:steamhappy:` to
+    /// `ːsteamhappyː`, with the text around them kept.
+    EmoticonNormalized,
+    /// A link was wrapped in `[url]` or an `[og]` preview card was added for it.
+    LinkExpanded,
+    /// Any other change, including text the server removed or rewrote.
+    TextAltered,
+}
+
+/// A part of a message the server changed.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct MessageChange {
+    /// What kind of change it is.
+    pub kind: MessageChangeKind,
+    /// The changed part of `original_message`, in bytes; empty for insertions.
+    pub original_span: Range<usize>,
+    /// The changed part of `modified_message()`, in bytes; empty for removals.
+    pub modified_span: Range<usize>,
+}
+
+/// Preprocessed message with BBCode parsing and mentions
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PreprocessedMessage {
+    /// The original message text before any processing.
+    pub original_message: String,
+    /// The message text after server-side modifications, or `None` when it is the same as
+    /// `original_message` (see `modified_message()`).
+    ///
+    /// `to_json` always writes the text, so stored blobs keep the string field.
+    #[serde(default)]
+    pub modified_message: Option<String>,
+    /// Parsed BBCode structure extracted from the message.
+    pub message_bbcode_parsed: Vec<BBCodeContent>,
+    /// Extracted mentions from the message, if any were found.
+    pub mentions: Option<ChatMentions>,
+    /// Server timestamp when the message was processed (if available).
+    pub server_timestamp: Option<u32>,
+    /// Message ordinal/sequence number assigned by the server (if available).
+    pub ordinal: Option<u32>,
+    /// Whether the message is a `/me` emote that renderers should italicize.
+    #[serde(default)]
+    pub is_emote: bool,
+    /// The slash command the message starts with, if any.
+    #[serde(default)]
+    pub command: Option<ChatCommand>,
+    /// URLs of the `[og]` preview cards Steam attached to the message.
+    #[serde(default)]
+    pub link_previews: Vec<String>,
+    /// The message text without BBCode markup.
+    ///
+    /// Taken from Steam's `message_no_bbcode` when the notification carries it,
+    /// otherwise rendered from `message_bbcode_parsed` as `MessagePreprocessor::strip_bbcode`
+    /// does.
+    #[serde(default)]
+    pub plain_text: String,
+    /// Emoticons used in the message, in order of first appearance.
+    #[serde(default)]
+    pub emoticons: Vec<EmoticonUse>,
+    /// Game invites in the message.
+    #[serde(default)]
+    pub game_invites: Vec<GameInviteInfo>,
+    /// Quotes in the message, outer quotes before the quotes nested in them.
+    #[serde(default)]
+    pub quotes: Vec<QuoteInfo>,
+    /// The room effect the message triggers, from a `[roomeffect]` tag or an `/effect`
+    /// command.
+    #[serde(default)]
+    pub room_effect: Option<RoomEffect>,
+    /// The roll a `[random]` tag (or the text Steam sends older clients instead) reports.
+    #[serde(default)]
+    pub dice: Option<RandomResult>,
+    /// The coin flip a `[flip]` tag (or the text Steam sends older clients instead)
+    /// reports, `true` for heads.
+    #[serde(default)]
+    pub coin_flip: Option<bool>,
+    /// Images from `[img]` tags, outer images before the ones nested in them.
+    ///
+    /// Every tag is listed, including unsafe ones; check `ImageRef::is_valid` before
+    /// loading a URL.
+    #[serde(default)]
+    pub images: Vec<ImageRef>,
+    /// URLs from `[url]` tags, `[og]` cards and bare `http(s)://` links, in message order.
+    ///
+    /// A link Steam unfurled appears twice: once as written and once with `from_og` set.
+    #[serde(default)]
+    pub urls: Vec<ExtractedUrl>,
+    /// Whether the message reached one of the preprocessor's `ParseLimits`, so part of
+    /// its markup was kept as text.
+    #[serde(default)]
+    pub limits_hit: bool,
+}
+
+impl PreprocessedMessage {
+    /// Whether the message pings the user `steam_id` (see `ChatMentions::mentions_user`).
+    pub fn mentions_user(&self, steam_id: SteamID, treat_here_as_mention: bool) -> bool {
+        self.mentions
+            .as_ref()
+            .is_some_and(|mentions| mentions.mentions_user(steam_id, treat_here_as_mention))
+    }
+
+    /// The message text after server-side modifications, falling back to
+    /// `original_message` when the server kept it unchanged.
+    pub fn modified_message(&self) -> &str {
+        self.modified_message
+            .as_deref()
+            .unwrap_or(&self.original_message)
+    }
+
+    /// The visible text of the message without BBCode markup.
+    pub fn plain_text(&self) -> &str {
+        &self.plain_text
+    }
+
+    /// Split `modified_message()` into text, mentions, bare links and markup, in message
+    /// order.
+    ///
+    /// The segments don't overlap and leave no gaps, so their `source` texts
+    /// concatenate to the message. Only top-level markup becomes a `BBCode` segment;
+    /// mentions and links nested in it are part of that segment. A top-level
+    /// `[mention]` tag is a `Mention` segment.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use SC_Sub_Poster::preprocessing::{MessagePreprocessor, MessageSegment};
+    ///
+    /// let message = MessagePreprocessor::preprocess_message("@all see [b]this[/b]");
+    /// let segments = message.segments();
+    /// assert!(matches!(segments[0], MessageSegment::Mention { .. }));
+    /// assert_eq!(segments[1], MessageSegment::Text(" see "));
+    /// assert_eq!(segments[2].source(), "[b]this[/b]");
+    /// ```
+    pub fn segments(&self) -> Vec<MessageSegment<'_>> {
+        let message = self.modified_message();
+        let spans = self
+            .mentions
+            .as_ref()
+            .map_or(&[][..], |mentions| &mentions.spans[..]);
+        let mut segments = Vec::new();
+        let mut cursor = SourceCursor::new(message);
+        let mut text_start = 0;
+        for content in &self.message_bbcode_parsed {
+            let node = match content {
+                BBCodeContent::String(text) => {
+                    if cursor.text(text).is_none() {
+                        break;
+                    }
+                    continue;
+                }
+                BBCodeContent::Node(node) => node,
+            };
+            // Whatever could not be located is kept as text.
+            let Some(range) = cursor.node(node) else {
+                break;
+            };
+            push_text_segments(message, text_start..range.start, spans, &mut segments);
+            let source = &message[range.clone()];
+            segments.push(match spans.iter().find(|span| span.byte_range == range) {
+                Some(span) => MessageSegment::Mention {
+                    span: span.clone(),
+                    source,
+                },
+                None => MessageSegment::BBCode { node, source },
+            });
+            text_start = range.end;
+        }
+        push_text_segments(message, text_start..message.len(), spans, &mut segments);
+        segments
+    }
+
+    /// The sticker this message carries, if it is a sticker message.
+    ///
+    /// Sticker messages contain nothing but the `[sticker]` tag, so the first sticker
+    /// node found is returned.
+    pub fn sticker(&self) -> Option<StickerInfo> {
+        self.message_bbcode_parsed
+            .iter()
+            .flat_map(BBCodeContent::iter_nodes)
+            .find_map(StickerInfo::from_node)
+    }
+
+    /// Version of the JSON layout written by `to_json`.
+    ///
+    /// Version 1 tags `BBCodeContent` (see its docs). Blobs without a `schema_version`
+    /// field are read as version 0, the untagged layout used before.
+    pub const SCHEMA_VERSION: u32 = 1;
+
+    /// Serialize the message for storage.
+    ///
+    /// The JSON object holds every field plus `schema_version`, with keys in sorted order.
+    ///
+    /// # Errors
+    ///
+    /// Returns a `serde_json::Error` if serialization fails.
+    pub fn to_json(&self) -> Result<String, serde_json::Error> {
+        let mut value = serde_json::to_value(self)?;
+        if let Some(object) = value.as_object_mut() {
+            object.insert("schema_version".to_string(), Self::SCHEMA_VERSION.into());
+            object.insert(
+                "modified_message".to_string(),
+                self.modified_message().into(),
+            );
+        }
+        serde_json::to_string(&value)
+    }
+
+    /// Read a message stored by `to_json`, or a legacy blob without `schema_version`.
+    ///
+    /// # Errors
+    ///
+    /// Returns `SchemaError::UnsupportedVersion` for blobs written by a newer schema, and
+    /// `SchemaError::Json` for malformed JSON.
+    pub fn from_json(json: &str) -> Result<Self, SchemaError> {
+        let mut value: serde_json::Value = serde_json::from_str(json)?;
+        let version = match value
+            .as_object_mut()
+            .and_then(|object| object.remove("schema_version"))
+        {
+            Some(version) => serde_json::from_value(version)?,
+            None => 0,
+        };
+        if version > Self::SCHEMA_VERSION {
+            return Err(SchemaError::UnsupportedVersion {
+                found: version,
+                supported: Self::SCHEMA_VERSION,
+            });
+        }
+        let mut message: Self = serde_json::from_value(value)?;
+        if message.modified_message.as_deref() == Some(message.original_message.as_str()) {
+            message.modified_message = None;
+        }
+        Ok(message)
+    }
+
+    /// Whether the message hides anything behind a spoiler.
+    pub fn has_spoilers(&self) -> bool {
+        !self.spoiler_texts().is_empty()
+    }
+
+    /// The visible text inside each spoiler, outer spoilers before the ones nested in them.
+    ///
+    /// Text is rendered as in `plain_text`, so links show their display text.
+    pub fn spoiler_texts(&self) -> Vec<String> {
+        self.message_bbcode_parsed
+            .iter()
+            .flat_map(BBCodeContent::iter_nodes)
+            .filter(|node| node.tag == BBCODE_TYPE_SPOILER)
+            .filter_map(|node| node.content.as_deref())
+            .map(MessagePreprocessor::render_plain_text)
+            .collect()
+    }
+
+    /// Trade offers referenced in the message; see `extract_trade_offers`.
+    pub fn trade_offers(&self) -> Vec<TradeOfferRef> {
+        extract_trade_offers(&self.message_bbcode_parsed)
+    }
+
+    /// How the server changed the message, in message order.
+    ///
+    /// `original_message` and `modified_message()` are compared token by token (words,
+    /// runs of whitespace and whole tags), and each run of differing tokens becomes one
+    /// change. Filter out `EmoticonNormalized` and `LinkExpanded` to find the changes
+    /// that alter what the message says.
+    ///
+    /// # Returns
+    ///
+    /// An empty list when the server kept the message unchanged.
+    pub fn server_changes(&self) -> Vec<MessageChange> {
+        match &self.modified_message {
+            Some(modified) => diff::changes(&self.original_message, modified),
+            None => Vec::new(),
         }
     }
 }
 
-/// Helper functions for message processing and formatting.
+/// Message preprocessor for Steam chat messages.
 ///
-/// This module provides convenience functions for creating mentions, formatting
-/// messages with BBCode, and checking for mentions in text.
-pub mod helpers {
-    use super::*;
+/// Provides utilities for parsing BBCode, extracting mentions, and preparing
+/// messages for sending through the Steam chat API.
+///
+/// The associated functions (`preprocess_message`, `parse_bbcode`, ...) use the default
+/// tag set, `GROUP_CHAT_TAGS`; `for_context` builds one for another kind of chat. Build an
+/// instance with `with_allowed_tags` to parse a smaller set in untrusted contexts or to
+/// accept a tag Steam introduced later, and install it with
+/// `ChatRoomClient::with_preprocessor` to use it for sends and notifications.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MessagePreprocessor {
+    allowed_tags: Vec<String>,
+    malformed_tags: MalformedTagPolicy,
+    limits: ParseLimits,
+    mention_rules: MentionRules,
+    mention_aliases: Arc<MentionAliases>,
+    roster: Option<Arc<MentionRoster>>,
+}
 
-    /// Create a mention string for a Steam ID in the format `@[U:1:xxxxx]`.
+static DEFAULT_PREPROCESSOR: LazyLock<MessagePreprocessor> =
+    LazyLock::new(MessagePreprocessor::default);
+
+impl Default for MessagePreprocessor {
+    /// A preprocessor for every tag Steam chat supports, the group chat tag set.
+    fn default() -> Self {
+        Self::for_context(TagContext::GroupChat)
+    }
+}
+
+/// The kind of chat a message is written for, which decides the BBCode tags it may use.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum TagContext<'a> {
+    /// Group chat rooms, with every tag in `GROUP_CHAT_TAGS`.
+    GroupChat,
+    /// One-on-one friend chats, with the tags in `FRIEND_CHAT_TAGS`.
+    FriendChat,
+    /// A caller-chosen tag set, e.g. a restricted one for untrusted input.
+    Custom(&'a [&'a str]),
+}
+
+impl<'a> TagContext<'a> {
+    /// The tag names allowed in this context.
+    pub fn tags(&self) -> &'a [&'a str] {
+        match *self {
+            Self::GroupChat => GROUP_CHAT_TAGS,
+            Self::FriendChat => FRIEND_CHAT_TAGS,
+            Self::Custom(tags) => tags,
+        }
+    }
+}
+
+impl MessagePreprocessor {
+    /// Create a preprocessor that only parses and accepts the given BBCode tags.
+    ///
+    /// Other tags are kept as text when parsing and rejected by `validate`. Names are
+    /// matched case-insensitively and stored in lowercase.
     ///
     /// # Arguments
     ///
-    /// * `steam_id` - The Steam ID to create a mention for
+    /// * `tags` - Tag names such as `"url"` or `"spoiler"`
+    pub fn with_allowed_tags(tags: &[&str]) -> Self {
+        Self {
+            allowed_tags: tags.iter().map(|tag| tag.to_ascii_lowercase()).collect(),
+            malformed_tags: MalformedTagPolicy::default(),
+            limits: ParseLimits::default(),
+            mention_rules: MentionRules::default(),
+            mention_aliases: Arc::default(),
+            roster: None,
+        }
+    }
+
+    /// Create a preprocessor for the tags allowed in `context`.
     ///
-    /// # Returns
+    /// # Arguments
     ///
-    /// A string in the format `@[U:1:xxxxx]` that can be used to mention the user.
-    pub fn create_mention(steam_id: SteamID) -> String {
-        format!("@{}", steam_id.steam3())
+    /// * `context` - The chat the messages are written for
+    pub fn for_context(context: TagContext<'_>) -> Self {
+        Self::with_allowed_tags(context.tags())
     }
 
-    /// Create an `@all` mention string.
+    /// Set how malformed tags are parsed (see `MalformedTagPolicy`).
     ///
     /// # Returns
     ///
-    /// The string `"@all"` which mentions all members in a group chat.
-    pub fn create_all_mention() -> String {
-        super::MENTION_ALL.to_string()
+    /// `Self` for method chaining (builder pattern).
+    pub fn with_malformed_tag_policy(mut self, policy: MalformedTagPolicy) -> Self {
+        self.malformed_tags = policy;
+        self
     }
 
-    /// Create an `@here` mention string.
+    /// Set how much structure is parsed from one message (see `ParseLimits`).
     ///
     /// # Returns
     ///
-    /// The string `"@here"` which mentions online/active members in a group chat.
-    pub fn create_here_mention() -> String {
-        super::MENTION_HERE.to_string()
+    /// `Self` for method chaining (builder pattern).
+    pub fn with_limits(mut self, limits: ParseLimits) -> Self {
+        self.limits = limits;
+        self
     }
 
-    /// Check if a message contains any mention tokens.
+    /// Set which characters are trimmed around mention tokens (see
+    /// `MentionPunctuation`).
     ///
-    /// # Arguments
+    /// # Returns
     ///
-    /// * `message` - The message text to check
+    /// `Self` for method chaining (builder pattern).
+    pub fn with_mention_punctuation(mut self, punctuation: MentionPunctuation) -> Self {
+        self.mention_rules.punctuation = punctuation;
+        self
+    }
+
+    /// Set how mention tokens are recognized (see `MentionRules`), including the
+    /// punctuation trimmed around them.
     ///
     /// # Returns
     ///
-    /// `true` if the message contains `@all`, `@here`, or any `@` character.
-    pub fn has_mentions(message: &str) -> bool {
-        message.contains(super::MENTION_ALL)
-            || message.contains(super::MENTION_HERE)
-            || message.contains("@")
+    /// `Self` for method chaining (builder pattern).
+    pub fn with_mention_rules(mut self, rules: MentionRules) -> Self {
+        self.mention_rules = rules;
+        self
     }
 
-    /// Format a message with BBCode tags.
+    /// Recognize `@keyword` as a mention of `kind`, e.g. `@everyone` as `@all` for
+    /// members used to Discord.
     ///
-    /// # Arguments
+    /// Keywords are matched case-insensitively under the same `MentionRules` as the
+    /// built-in mentions, which win over an alias of the same name. A leading `@` in
+    /// `keyword` is ignored, and registering a keyword again replaces it.
     ///
-    /// * `message` - The message content to wrap in BBCode
-    /// * `bbcode_type` - The type of BBCode formatting (e.g., `BBCODE_TYPE_SPOILER`, `BBCODE_TYPE_CODE`)
-    /// * `value` - Optional value for BBCode types that require it (e.g., URL for `BBCODE_TYPE_URL`)
+    /// # Example
+    ///
+    /// ```
+    /// use SC_Sub_Poster::preprocessing::{MentionKind, MessagePreprocessor};
+    ///
+    /// let mut preprocessor = MessagePreprocessor::default();
+    /// preprocessor.register_mention_alias("everyone", MentionKind::All);
+    /// assert!(preprocessor.find_mentions("@Everyone, event starts now").unwrap().mention_all);
+    /// ```
+    pub fn register_mention_alias(&mut self, keyword: &str, kind: MentionKind) {
+        self.register_mention_kinds(keyword, vec![kind]);
+    }
+
+    /// Recognize `@keyword` as a mention of every account in `steam_ids`, e.g. `@admins`.
+    ///
+    /// Each account is added to `ChatMentions::mention_steamids`, with a span over the
+    /// keyword. Matching works as for `register_mention_alias`.
+    pub fn register_mention_keyword(
+        &mut self,
+        keyword: &str,
+        steam_ids: impl IntoIterator<Item = SteamID>,
+    ) {
+        let kinds = steam_ids
+            .into_iter()
+            .map(|steam_id| MentionKind::SteamId(MentionSteamId(steam_id)))
+            .collect();
+        self.register_mention_kinds(keyword, kinds);
+    }
+
+    fn register_mention_kinds(&mut self, keyword: &str, kinds: Vec<MentionKind>) {
+        let keyword = keyword.strip_prefix('@').unwrap_or(keyword).to_lowercase();
+        if !keyword.is_empty() {
+            Arc::make_mut(&mut self.mention_aliases).insert(keyword, kinds);
+        }
+    }
+
+    /// What `@keyword` mentions, if it was registered as an alias or keyword.
+    pub fn mention_alias(&self, keyword: &str) -> Option<&[MentionKind]> {
+        Self::alias_mention(
+            &format!("@{}", keyword.trim_start_matches('@')),
+            &self.mention_aliases,
+        )
+    }
+
+    /// Recognize `@name` mentions of the members in `roster`.
+    ///
+    /// Matched members are added to `ChatMentions::mention_steamids` and described in
+    /// `ChatMentions::name_mentions`.
     ///
     /// # Returns
     ///
-    /// The message wrapped in the appropriate BBCode tags.
-    pub fn format_with_bbcode(message: &str, bbcode_type: &str, value: &str) -> String {
-        super::bbcode::formatting::format_with_bbcode(message, bbcode_type, value)
+    /// `Self` for method chaining (builder pattern).
+    pub fn with_roster(mut self, roster: MentionRoster) -> Self {
+        self.roster = Some(Arc::new(roster));
+        self
+    }
+
+    /// The default preprocessor behind the associated functions.
+    pub(crate) fn shared() -> &'static Self {
+        &DEFAULT_PREPROCESSOR
+    }
+
+    /// The tag names this preprocessor parses.
+    pub fn allowed_tags(&self) -> &[String] {
+        &self.allowed_tags
+    }
+
+    /// How this preprocessor treats malformed tags.
+    pub fn malformed_tag_policy(&self) -> MalformedTagPolicy {
+        self.malformed_tags
+    }
+
+    /// The limits on how much structure is parsed from one message.
+    pub fn limits(&self) -> ParseLimits {
+        self.limits
+    }
+
+    /// The characters trimmed around mention tokens.
+    pub fn mention_punctuation(&self) -> &MentionPunctuation {
+        &self.mention_rules.punctuation
+    }
+
+    /// How mention tokens are recognized.
+    pub fn mention_rules(&self) -> &MentionRules {
+        &self.mention_rules
+    }
+
+    /// The roster `@name` mentions are matched against, if any.
+    pub fn roster(&self) -> Option<&MentionRoster> {
+        self.roster.as_deref()
+    }
+
+    /// Preprocess a message with BBCode parsing and mention detection.
+    #[tracing::instrument(name = "kether.preprocess.message", skip_all)]
+    pub fn preprocess(&self, message: &str) -> PreprocessedMessage {
+        trace!(original_len = message.len(), "starting preprocessing");
+        self.analyze(message, message, None, None)
+    }
+
+    /// Preprocess a message whose BBCode-free text is already known.
+    ///
+    /// Used for notifications that carry Steam's own `message_no_bbcode` rendering, which
+    /// becomes `plain_text` instead of being recomputed from the parsed BBCode.
+    pub fn preprocess_with_plain_text(
+        &self,
+        message: &str,
+        plain_text: &str,
+    ) -> PreprocessedMessage {
+        let mut preprocessed = self.preprocess(message);
+        preprocessed.plain_text = plain_text.to_string();
+        // Steam's plain rendering of a roll or flip is the text older clients are sent.
+        preprocessed.dice = preprocessed
+            .dice
+            .or_else(|| RandomResult::from_text(plain_text));
+        preprocessed.coin_flip = preprocessed
+            .coin_flip
+            .or_else(|| coin_flip_from_text(plain_text));
+        preprocessed
+    }
+
+    /// Preprocess a message sent through Steam, with the server's modified text and ids.
+    pub fn process(
+        &self,
+        original_message: &str,
+        modified_message: &str,
+        server_timestamp: u32,
+        ordinal: u32,
+    ) -> PreprocessedMessage {
+        self.analyze(
+            original_message,
+            modified_message,
+            Some(server_timestamp),
+            // Ordinal can be 0 (per DoctorMcKay's node-steam-user wiki (https://github.com/DoctorMcKay/node-steam-user/wiki/SteamChatRoomClient?utm_source=copilot.com#deletechatmessagesgroupid-chatid-messages-callback), it can be omitted in deletion if 0)
+            // So we keep it as Some(0) instead of None
+            Some(ordinal),
+        )
+    }
+
+    /// Parse BBCode, keeping tags outside the allowed set as text.
+    ///
+    /// # Invariants
+    ///
+    /// These hold for every input, and are checked by `tests/parser_invariants.rs` and
+    /// the `preprocess` fuzz target in `fuzz/`:
+    ///
+    /// - Parsing never panics.
+    /// - The work done is linear in the length of the message: no part of it is scanned
+    ///   again for every bracket or tag before it.
+    /// - At most `ParseLimits::max_depth` tags are open at once (`MAX_NESTING_DEPTH` by
+    ///   default), so the tree depth is bounded.
+    /// - Under `MalformedTagPolicy::KeepAsText` nothing is lost: `render_bbcode` turns the
+    ///   result back into the message, byte for byte, when its tags are written in
+    ///   canonical form.
+    pub fn parse(&self, message: &str) -> Vec<BBCodeContent> {
+        self.parse_limited(message).0
+    }
+
+    /// Parse BBCode, also telling whether one of the `ParseLimits` was reached.
+    fn parse_limited(&self, message: &str) -> (Vec<BBCodeContent>, bool) {
+        bbcode::Parser::new(&self.allowed_tags)
+            .with_policy(self.malformed_tags)
+            .with_limits(self.limits)
+            .parse_limited(message)
+    }
+
+    /// Parse BBCode, failing on malformed tags under `MalformedTagPolicy::Error`.
+    ///
+    /// # Errors
+    ///
+    /// Returns a `BBCodeError` for the first malformed tag when the policy is
+    /// `MalformedTagPolicy::Error`; other policies never fail.
+    pub fn try_parse(&self, message: &str) -> Result<Vec<BBCodeContent>, BBCodeError> {
+        bbcode::Parser::new(&self.allowed_tags)
+            .with_policy(self.malformed_tags)
+            .with_limits(self.limits)
+            .try_parse(message)
+    }
+
+    /// Check that a message only uses well-formed BBCode from the allowed set.
+    ///
+    /// See `validate_only` for the rules.
+    ///
+    /// # Errors
+    ///
+    /// Returns a `BBCodeError` pointing at the first offending tag.
+    pub fn validate(&self, message: &str) -> Result<(), BBCodeError> {
+        bbcode::validate(message, &self.allowed_tags)
+    }
+
+    /// Report every BBCode problem in a message; see `validate_bbcode`.
+    pub fn diagnose(&self, message: &str) -> Vec<BBCodeDiagnostic> {
+        bbcode::diagnose(message, &self.allowed_tags)
+    }
+
+    /// Extract the visible text of a message; see `strip_bbcode`.
+    pub fn strip(&self, message: &str) -> String {
+        Self::render_plain_text(&self.parse(message))
+    }
+
+    /// Count the emoticons used in a message; see `extract_emoticons`.
+    pub fn emoticons(&self, message: &str) -> Vec<EmoticonUse> {
+        Self::collect_emoticons(&self.parse(message))
+    }
+
+    /// Preprocess a message with BBCode parsing and mention detection
+    pub fn preprocess_message(message: &str) -> PreprocessedMessage {
+        DEFAULT_PREPROCESSOR.preprocess(message)
+    }
+
+    /// Preprocess a message whose BBCode-free text is already known.
+    ///
+    /// Used for notifications that carry Steam's own `message_no_bbcode` rendering, which
+    /// becomes `plain_text` instead of being recomputed from the parsed BBCode.
+    pub fn preprocess_message_with_plain_text(
+        message: &str,
+        plain_text: &str,
+    ) -> PreprocessedMessage {
+        DEFAULT_PREPROCESSOR.preprocess_with_plain_text(message, plain_text)
+    }
+
+    /// Build a `PreprocessedMessage` by analyzing the (possibly server-modified) text.
+    fn analyze(
+        &self,
+        original_message: &str,
+        modified_message: &str,
+        server_timestamp: Option<u32>,
+        ordinal: Option<u32>,
+    ) -> PreprocessedMessage {
+        let (message_bbcode_parsed, limits_hit) = self.parse_limited(modified_message);
+        let mut scan = self.scan(modified_message);
+        scan.walk(&message_bbcode_parsed, ScanContext::default());
+        let room_effect = scan
+            .room_effect
+            .take()
+            .or_else(|| Self::room_effect_command(modified_message));
+        let plain_text = collapse_plain_text(&scan.text);
+        let dice = scan.dice.or_else(|| RandomResult::from_text(&plain_text));
+        let coin_flip = scan.coin_flip.or_else(|| coin_flip_from_text(&plain_text));
+
+        PreprocessedMessage {
+            original_message: original_message.to_string(),
+            modified_message: (modified_message != original_message)
+                .then(|| modified_message.to_string()),
+            mentions: scan.mentions.into_found(),
+            server_timestamp,
+            ordinal,
+            is_emote: Self::is_emote(modified_message),
+            command: ChatCommand::parse(modified_message),
+            room_effect,
+            link_previews: scan.link_previews,
+            plain_text,
+            emoticons: scan.emoticons,
+            game_invites: scan.game_invites,
+            quotes: scan.quotes,
+            images: scan.images,
+            urls: scan.urls,
+            dice,
+            coin_flip,
+            message_bbcode_parsed,
+            limits_hit,
+        }
+    }
+
+    /// Extract the visible text of a message, dropping BBCode markup.
+    ///
+    /// Tags are removed but their inner text is kept; emoticons are rendered as
+    /// `:name:` (whichever syntax they used) and links as their display text, falling back to the URL. Runs of
+    /// whitespace (including newlines) collapse to a single space and the result is
+    /// trimmed, so the output is suitable for logging and full-text search. Malformed
+    /// markup is kept as text.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use SC_Sub_Poster::preprocessing::MessagePreprocessor;
+    ///
+    /// let text = "[spoiler]gg[/spoiler]  see [url=https://kether.pl]our site[/url]";
+    /// assert_eq!(MessagePreprocessor::strip_bbcode(text), "gg see our site");
+    /// ```
+    pub fn strip_bbcode(message: &str) -> String {
+        DEFAULT_PREPROCESSOR.strip(message)
+    }
+
+    /// Render parsed BBCode as plain text with collapsed whitespace.
+    fn render_plain_text(parsed: &[BBCodeContent]) -> String {
+        let mut scan = MessageScan {
+            text_only: true,
+            ..MessageScan::default()
+        };
+        scan.walk(parsed, ScanContext::default());
+        collapse_plain_text(&scan.text)
+    }
+
+    /// Count the emoticons used in a message.
+    ///
+    /// Recognizes both the `[emoticon]name[/emoticon]` tag (or `[emoticon=name]`) and
+    /// Steam's `ːnameː` shorthand, which uses U+02D0 MODIFIER LETTER TRIANGULAR COLON
+    /// as the delimiter. Each name is reported once, in order of first appearance, with
+    /// the number of times it was used in either syntax.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use SC_Sub_Poster::preprocessing::MessagePreprocessor;
+    ///
+    /// let uses = MessagePreprocessor::extract_emoticons("ːsteamhappyː gg [emoticon]steamhappy[/emoticon]");
+    /// assert_eq!((uses[0].name.as_str(), uses[0].count), ("steamhappy", 2));
+    /// ```
+    pub fn extract_emoticons(message: &str) -> Vec<EmoticonUse> {
+        DEFAULT_PREPROCESSOR.emoticons(message)
+    }
+
+    fn collect_emoticons(parsed: &[BBCodeContent]) -> Vec<EmoticonUse> {
+        let mut scan = MessageScan::default();
+        scan.walk(parsed, ScanContext::default());
+        scan.emoticons
+    }
+
+    /// The name of an `[emoticon]` node, from its value or its text content.
+    fn emoticon_name(node: &BBCodeNode) -> Option<String> {
+        let name = match node.attrs.get("name").or_else(|| node.attrs.get("value")) {
+            Some(value) => value.clone(),
+            None => node
+                .content
+                .iter()
+                .flatten()
+                .filter_map(|child| match child {
+                    BBCodeContent::String(text) => Some(text.as_str()),
+                    BBCodeContent::Node(_) => None,
+                })
+                .collect(),
+        };
+        let name = name
+            .trim()
+            .trim_matches([':', EMOTICON_SHORTHAND_DELIMITER]);
+        (!name.is_empty()).then(|| name.to_string())
+    }
+
+    /// Find `ːnameː` shorthand emoticons, returning each byte range and name.
+    fn shorthand_emoticons(text: &str) -> Vec<(Range<usize>, &str)> {
+        let delimiter_len = EMOTICON_SHORTHAND_DELIMITER.len_utf8();
+        let mut found = Vec::new();
+        let mut offset = 0;
+
+        while let Some(start) = text[offset..].find(EMOTICON_SHORTHAND_DELIMITER) {
+            let start = offset + start;
+            let name_start = start + delimiter_len;
+            let Some(len) = text[name_start..].find(EMOTICON_SHORTHAND_DELIMITER) else {
+                break;
+            };
+            let name = &text[name_start..name_start + len];
+
+            if !name.is_empty()
+                && name
+                    .chars()
+                    .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-')
+            {
+                let end = name_start + len + delimiter_len;
+                found.push((start..end, name));
+                offset = end;
+            } else {
+                // The closing delimiter may open the next emoticon.
+                offset = name_start + len;
+            }
+        }
+
+        found
+    }
+
+    /// The effect of a leading `/effect` command.
+    fn room_effect_command(message: &str) -> Option<RoomEffect> {
+        let rest = message.trim_start().strip_prefix(ROOM_EFFECT_COMMAND)?;
+        if !rest.starts_with(char::is_whitespace) {
+            return None;
+        }
+        rest.split_whitespace().next().map(RoomEffect::from_name)
+    }
+
+    /// Check whether a message is a `/me` emote.
+    pub fn is_emote(message: &str) -> bool {
+        Self::strip_emote_prefix(message).is_some()
+    }
+
+    /// Return the emote text without the `/me` prefix, or `None` for regular messages.
+    pub fn strip_emote_prefix(message: &str) -> Option<&str> {
+        let rest = message.trim_start().strip_prefix(EMOTE_PREFIX)?;
+        if rest.is_empty() {
+            Some(rest)
+        } else if rest.starts_with(char::is_whitespace) {
+            Some(rest.trim_start())
+        } else {
+            None
+        }
+    }
+
+    /// Parse BBCode from a message string
+    pub fn parse_bbcode(message: &str) -> Vec<BBCodeContent> {
+        DEFAULT_PREPROCESSOR.parse(message)
+    }
+
+    /// Write parsed BBCode back as message text.
+    ///
+    /// Text is written as is, escapes included. Tags are written in canonical form:
+    /// lowercase names, `[tag=value]` for a lone value, `[emoticon:name]` for emoticons
+    /// and stickers, and other attributes sorted by key, quoted when they contain
+    /// whitespace. Nodes with content get a closing tag, leaf nodes don't.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use SC_Sub_Poster::preprocessing::MessagePreprocessor;
+    ///
+    /// let parsed = MessagePreprocessor::parse_bbcode("[SPOILER]gg[/Spoiler] [url]");
+    /// assert_eq!(MessagePreprocessor::render_bbcode(&parsed), "[spoiler]gg[/spoiler] [url]");
+    /// ```
+    pub fn render_bbcode(parsed: &[BBCodeContent]) -> String {
+        let mut rendered = String::new();
+        bbcode::render(parsed, &mut rendered);
+        rendered
+    }
+
+    /// Render parsed BBCode as an HTML fragment.
+    ///
+    /// Text is HTML-escaped after BBCode escapes are removed. Spoilers become
+    /// `<span class="spoiler">`, quotes `<blockquote>`, `[code]` and `[pre]` blocks `<pre>`,
+    /// and emoticons `:name:` text. Links and images are only emitted for `http(s)` URLs
+    /// (see `ImageRef::is_valid`): an image with any other URL is shown as the URL's text,
+    /// and such a link as its display text. Tags without an HTML form, such as `[og]` or
+    /// `[sticker]`, render their content only. Newlines become `<br>`, except inside
+    /// `[code]` and `[pre]` blocks, whose text is kept as is.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use SC_Sub_Poster::preprocessing::MessagePreprocessor;
+    ///
+    /// let parsed = MessagePreprocessor::parse_bbcode("[img]https://x.example/a.png[/img] a<b");
+    /// assert_eq!(
+    ///     MessagePreprocessor::render_html(&parsed),
+    ///     r#"<img src="https://x.example/a.png" alt=""> a&lt;b"#
+    /// );
+    /// ```
+    pub fn render_html(parsed: &[BBCodeContent]) -> String {
+        let mut rendered = String::new();
+        html::render(parsed, &mut rendered);
+        rendered
+    }
+
+    /// Render parsed BBCode as CommonMark.
+    ///
+    /// Text is escaped so it never turns into Markdown syntax, newlines become hard
+    /// breaks (`\` at the end of the line) and leading spaces are kept as `&nbsp;`, so
+    /// separate lines and indentation survive. Quotes become `>` blocks, `[code]` and
+    /// `[pre]` fenced code blocks, and spoilers `||text||`. Links and images follow the
+    /// same `http(s)` rule as `render_html`.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use SC_Sub_Poster::preprocessing::MessagePreprocessor;
+    ///
+    /// let parsed =
+    ///     MessagePreprocessor::parse_bbcode("rules:\n  1. [url=https://x.example]be nice[/url]");
+    /// assert_eq!(
+    ///     MessagePreprocessor::render_markdown(&parsed),
+    ///     "rules:\\\n&nbsp;&nbsp;1\\. [be nice](<https://x.example>)"
+    /// );
+    /// ```
+    pub fn render_markdown(parsed: &[BBCodeContent]) -> String {
+        let mut rendered = String::new();
+        markdown::render(parsed, &mut rendered);
+        rendered
+    }
+
+    /// Check that a message only uses well-formed, supported BBCode.
+    ///
+    /// Rejects tags Steam does not support (e.g. `[sub]`), unterminated tags (`[url`),
+    /// closing tags without a matching opening tag, unclosed container tags (`url`,
+    /// `spoiler`, `code`, `pre`, `quote`, `noembed`), and malformed attributes. Escaped
+    /// brackets (`\[`), mention tokens (`[U:1:xxxxx]`), and anything inside `[code]` or
+    /// `[pre]` are ignored. Intended for pre-checking drafts before they are sent; use
+    /// `validate_bbcode` to get every problem with its kind and span.
+    ///
+    /// # Errors
+    ///
+    /// Returns a `BBCodeError` pointing at the first offending tag.
+    pub fn validate_only(message: &str) -> Result<(), BBCodeError> {
+        DEFAULT_PREPROCESSOR.validate(message)
+    }
+
+    /// Report every BBCode problem in a message, ordered by position.
+    ///
+    /// Checks the same rules as `validate_only`, but keeps going after a problem so a
+    /// composer can highlight all of them at once; `validate_only` fails with the first
+    /// one. An empty result means the message is valid.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use SC_Sub_Poster::preprocessing::{BBCodeDiagnosticKind, MessagePreprocessor};
+    ///
+    /// let message = "żółw [sub]x[/sub] [/url]";
+    /// let diagnostics = MessagePreprocessor::validate_bbcode(message);
+    /// assert_eq!(diagnostics[0].kind, BBCodeDiagnosticKind::UnknownTag);
+    /// assert_eq!(&message[diagnostics[0].byte_range.clone()], "[sub]");
+    /// assert_eq!(diagnostics[2].kind, BBCodeDiagnosticKind::StrayCloser);
+    /// ```
+    pub fn validate_bbcode(message: &str) -> Vec<BBCodeDiagnostic> {
+        DEFAULT_PREPROCESSOR.diagnose(message)
+    }
+
+    /// Find Steam tags that `context` does not render, such as `[roomeffect]` in a friend
+    /// chat.
+    ///
+    /// Steam shows such tags as literal text. Only names from `GROUP_CHAT_TAGS` or
+    /// `FRIEND_CHAT_TAGS` are reported, so bracketed text like `[wip]`, which is text in
+    /// every chat, passes; use `validate_bbcode` to check the markup itself.
+    ///
+    /// # Returns
+    ///
+    /// One `UnknownTag` diagnostic per offending opening or closing tag, in message order.
+    pub fn validate_for_context(message: &str, context: TagContext<'_>) -> Vec<BBCodeDiagnostic> {
+        let mut diagnostics = Self::for_context(context).diagnose(message);
+        diagnostics.retain(|diagnostic| {
+            diagnostic.kind == BBCodeDiagnosticKind::UnknownTag && {
+                let name = bbcode::tag_name(&message[diagnostic.byte_range.clone()]);
+                GROUP_CHAT_TAGS.contains(&name.as_str())
+                    || FRIEND_CHAT_TAGS.contains(&name.as_str())
+            }
+        });
+        diagnostics
+    }
+
+    /// Check that a message fits in a group chat message.
+    ///
+    /// Steam's chat clients are JavaScript and measure messages with `String.length`,
+    /// so the length is counted in UTF-16 code units rather than UTF-8 bytes or
+    /// characters: `é` counts once and `🙂` twice. The check applies to the text as
+    /// sent, markup included.
+    ///
+    /// # Errors
+    ///
+    /// Returns a `LengthError` if the message is longer than `GROUP_CHAT_MESSAGE_LIMIT`.
+    pub fn validate_length(message: &str) -> Result<(), LengthError> {
+        Self::validate_length_within(message, GROUP_CHAT_MESSAGE_LIMIT)
+    }
+
+    /// Check that a message is at most `limit` UTF-16 code units long.
+    ///
+    /// See `validate_length`; use `FRIEND_CHAT_MESSAGE_LIMIT` for friend messages.
+    ///
+    /// # Errors
+    ///
+    /// Returns a `LengthError` if the message is longer than `limit`.
+    pub fn validate_length_within(message: &str, limit: usize) -> Result<(), LengthError> {
+        let actual = message.encode_utf16().count();
+        if actual > limit {
+            return Err(LengthError {
+                limit,
+                actual,
+                counted_in: LengthUnit::Utf16CodeUnits,
+            });
+        }
+        Ok(())
+    }
+
+    /// Extract mentions from a message.
+    ///
+    /// Mentions inside `[code]` and `[pre]` blocks are shown literally and don't notify
+    /// anyone, so they are skipped.
+    pub fn extract_mentions(message: &str) -> Option<ChatMentions> {
+        DEFAULT_PREPROCESSOR.find_mentions(message)
+    }
+
+    /// Extract mentions from a message, including `@name` mentions of roster members.
+    ///
+    /// See `extract_mentions` and `with_roster`.
+    pub fn find_mentions(&self, message: &str) -> Option<ChatMentions> {
+        let mut scan = self.scan(message);
+        scan.walk(&self.parse(message), ScanContext::default());
+        scan.mentions.into_found()
+    }
+
+    /// Rewrite the plain mentions in `message` as `[mention]` pings.
+    ///
+    /// Steam notifies members from the `[mention]` markup of a sent message, which is
+    /// what the Steam client sends; plain `@all` or `@[U:1:xxxxx]` text is shown but
+    /// pings nobody. Mentions already written as tags and text in `[code]` or `[pre]`
+    /// blocks are left alone. A keyword standing for several accounts (see
+    /// `register_mention_keyword`), or a roster name several members share, becomes a
+    /// ping of each of them.
+    ///
+    /// # Returns
+    ///
+    /// The message with e.g. `@all` replaced by `[mention=all]@all[/mention]`.
+    pub fn tag_mentions(&self, message: &str) -> String {
+        let Some(mentions) = self.find_mentions(message) else {
+            return message.to_string();
+        };
+        let mut spans = mentions.spans;
+        spans.sort_by_key(|span| (span.byte_range.start, span.byte_range.end));
+
+        let mut tagged = String::with_capacity(message.len() + 32 * spans.len());
+        let mut copied = 0;
+        for group in spans.chunk_by(|a, b| a.byte_range == b.byte_range) {
+            let byte_range = group[0].byte_range.clone();
+            let written = &message[byte_range.clone()];
+            let is_tag = written
+                .strip_prefix('[')
+                .and_then(|rest| rest.get(..BBCODE_TYPE_MENTION.len()))
+                .is_some_and(|tag| tag.eq_ignore_ascii_case(BBCODE_TYPE_MENTION));
+            if byte_range.start < copied || is_tag {
+                continue;
+            }
+            tagged.push_str(&message[copied..byte_range.start]);
+            for (index, span) in group.iter().enumerate() {
+                if index > 0 {
+                    tagged.push(' ');
+                }
+                tagged.push_str(&match span.kind {
+                    MentionKind::All => helpers::create_mention_all_tag(),
+                    MentionKind::Here => helpers::create_mention_here_tag(),
+                    MentionKind::SteamId(steam_id) if group.len() == 1 => {
+                        helpers::create_mention_tag(steam_id.0, written.trim_start_matches('@'))
+                    }
+                    MentionKind::SteamId(steam_id) => {
+                        helpers::create_mention_tag(steam_id.0, &steam_id.0.steam3())
+                    }
+                });
+            }
+            copied = byte_range.end;
+        }
+        tagged.push_str(&message[copied..]);
+        tagged
+    }
+
+    /// A `MessageScan` of `source` using this preprocessor's mention settings.
+    fn scan<'m>(&self, source: &'m str) -> MessageScan<'m> {
+        MessageScan {
+            mention_rules: self.mention_rules.clone(),
+            mention_aliases: self.mention_aliases.clone(),
+            roster: self.roster.clone(),
+            locator: Some(SourceCursor::new(source)),
+            ..MessageScan::default()
+        }
+    }
+
+    /// Process a single token to detect mentions
+    ///
+    /// `offset` is where the token starts in the message, if known; mentions found in
+    /// it get a span when it is.
+    fn process_mention_token(
+        token: &str,
+        offset: Option<usize>,
+        rules: &MentionRules,
+        aliases: &MentionAliases,
+        mentions: &mut ChatMentions,
+    ) {
+        let leading_trimmed = rules.trim_start(token);
+        let cleaned_token = rules.trim_end(leading_trimmed);
+
+        let found = match Self::token_mention(cleaned_token, aliases) {
+            Some(kinds) => Some((kinds, 0..cleaned_token.len())),
+            None if !rules.require_word_boundary => Self::glued_mention(cleaned_token, aliases),
+            None => None,
+        };
+        let Some((kinds, range)) = found else {
+            return;
+        };
+        for kind in kinds {
+            match kind {
+                MentionKind::All => mentions.mention_all = true,
+                MentionKind::Here => mentions.mention_here = true,
+                MentionKind::SteamId(steam_id) => mentions.mention_steamids.push(steam_id),
+            }
+            if let Some(offset) = offset {
+                let start = offset + token.len() - leading_trimmed.len();
+                mentions.spans.push(MentionSpan {
+                    kind,
+                    byte_range: start + range.start..start + range.end,
+                });
+            }
+        }
+    }
+
+    /// The mentions a whole, trimmed token makes; several for a keyword registered with
+    /// `register_mention_keyword`.
+    fn token_mention(token: &str, aliases: &MentionAliases) -> Option<Vec<MentionKind>> {
+        let kind = match token {
+            MENTION_ALL => MentionKind::All,
+            MENTION_HERE => MentionKind::Here,
+            _ => match Self::mentioned_steam_id(token) {
+                Some(steam_id) => MentionKind::SteamId(MentionSteamId(steam_id)),
+                None => return Self::alias_mention(token, aliases).map(<[_]>::to_vec),
+            },
+        };
+        Some(vec![kind])
+    }
+
+    /// What `token`, an `@` followed by a registered keyword, mentions.
+    fn alias_mention<'a>(token: &str, aliases: &'a MentionAliases) -> Option<&'a [MentionKind]> {
+        let keyword = token
+            .strip_prefix('@')
+            .filter(|keyword| !keyword.is_empty())?;
+        aliases
+            .get(keyword.to_lowercase().as_str())
+            .map(Vec::as_slice)
+    }
+
+    /// The first `@all`, `@here`, registered keyword, `@[U:1:xxxxx]` or `@<SteamID64>`
+    /// inside `token`, with its range in the token, for
+    /// `MentionRules::require_word_boundary` set to `false`.
+    fn glued_mention(
+        token: &str,
+        aliases: &MentionAliases,
+    ) -> Option<(Vec<MentionKind>, Range<usize>)> {
+        token.match_indices('@').find_map(|(at, _)| {
+            let rest = &token[at..];
+            for (keyword, kind) in [
+                (MENTION_ALL, MentionKind::All),
+                (MENTION_HERE, MentionKind::Here),
+            ] {
+                if rest.starts_with(keyword) {
+                    return Some((vec![kind], at..at + keyword.len()));
+                }
+            }
+            // The longest keyword wins, so `@admins` is not read as `@admin`.
+            if let Some((keyword, kinds)) = aliases
+                .iter()
+                .filter(|(keyword, _)| {
+                    rest[1..]
+                        .get(..keyword.len())
+                        .is_some_and(|written| written.to_lowercase() == **keyword)
+                })
+                .max_by_key(|(keyword, _)| keyword.len())
+            {
+                return Some((kinds.clone(), at..at + 1 + keyword.len()));
+            }
+            let len = if rest[1..].starts_with("[U:1:") {
+                rest.find(']')? + 1
+            } else {
+                1 + rest[1..]
+                    .find(|c: char| !c.is_ascii_digit())
+                    .unwrap_or(rest.len() - 1)
+            };
+            let steam_id = Self::mentioned_steam_id(&rest[..len])?;
+            Some((
+                vec![MentionKind::SteamId(MentionSteamId(steam_id))],
+                at..at + len,
+            ))
+        })
+    }
+
+    /// The account a mention token refers to.
+    ///
+    /// Steam renders user mentions as `@[U:1:xxxxx]`; people also write
+    /// `STEAM_0:1:xxxxx` (both with or without the `@`), `@` followed by a SteamID64, or
+    /// paste a `steamcommunity.com/profiles/<SteamID64>` link. A bare SteamID64 is not a
+    /// mention, since any long number would be one, and `/id/` vanity links need the
+    /// Web API to resolve, so they are not recognized here.
+    fn mentioned_steam_id(token: &str) -> Option<SteamID> {
+        if let Some(steam_id) = profile_link_steam_id(token) {
+            return Some(steam_id);
+        }
+        let (prefixed, id) = match token.strip_prefix('@') {
+            Some(id) => (true, id),
+            None => (false, token),
+        };
+        if Self::is_steam_id_format(id) {
+            return SteamID::try_from(id).ok();
+        }
+        if let Some(steam_id) = steam2_id(id) {
+            return Some(steam_id);
+        }
+        if prefixed {
+            return individual_steam_id64(id);
+        }
+        None
+    }
+
+    /// Check if a token matches Steam ID format [U:1:...]
+    fn is_steam_id_format(token: &str) -> bool {
+        token.starts_with("[U:1:") && token.ends_with(']')
+    }
+
+    /// Legacy name for `normalize_outgoing`.
+    ///
+    /// Despite the name this removes escapes rather than adding them: `\[b\]` becomes
+    /// live `[b]` markup. Prefer `escape_for_sending` for untrusted text, or send with
+    /// `SendGroupMessageParams::with_preserve_escapes(true)` to keep escapes intact.
+    pub fn prepare_message_for_sending(message: &str) -> String {
+        Self::normalize_outgoing(message)
+    }
+
+    /// Escape the literal brackets in an outgoing message.
+    ///
+    /// Every `[` and `]` is escaped with a backslash so Steam shows it as written, except
+    /// brackets that are already escaped and `[U:1:xxxxx]` mention tokens. Unlike
+    /// `escape_bbcode`, applying it twice changes nothing, so it is safe on text the
+    /// user may have partly escaped already.
+    pub fn escape_for_sending(message: &str) -> String {
+        let mut escaped = String::with_capacity(message.len());
+        let mut rest = message;
+
+        while let Some(bracket) = rest.find(['[', ']']) {
+            escaped.push_str(&rest[..bracket]);
+            let candidate = &rest[bracket..];
+
+            if candidate.starts_with('[')
+                && let Some(close) = candidate.find(']')
+                && Self::is_steam_id_format(&candidate[..=close])
+            {
+                escaped.push_str(&candidate[..=close]);
+                rest = &candidate[close + 1..];
+                continue;
+            }
+
+            if !escaped.ends_with('\\') {
+                escaped.push('\\');
+            }
+            escaped.push_str(&candidate[..1]);
+            rest = &candidate[1..];
+        }
+
+        escaped.push_str(rest);
+        escaped
+    }
+
+    /// Remove client-side backslash escapes from an outgoing message.
+    ///
+    /// `\[` and `\]` become plain brackets, so escaped markup is rendered by Steam.
+    /// This is what group sends do unless `SendGroupMessageParams::with_preserve_escapes`
+    /// is enabled; only use it on trusted text.
+    pub fn normalize_outgoing(message: &str) -> String {
+        helpers::unescape_bbcode(message)
+    }
+
+    /// Process a response from Steam with preprocessing
+    pub fn process_response(
+        original_message: &str,
+        modified_message: &str,
+        server_timestamp: u32,
+        ordinal: u32,
+    ) -> PreprocessedMessage {
+        DEFAULT_PREPROCESSOR.process(
+            original_message,
+            modified_message,
+            server_timestamp,
+            ordinal,
+        )
+    }
+}
+
+/// Everything `MessagePreprocessor::analyze` reads from a parsed message, gathered in a
+/// single walk over the tree.
+#[derive(Default)]
+struct MessageScan<'m> {
+    /// Only render `text`, leaving the other fields empty.
+    text_only: bool,
+    /// The visible text, before unescaping and whitespace collapsing.
+    text: String,
+    emoticons: Vec<EmoticonUse>,
+    game_invites: Vec<GameInviteInfo>,
+    quotes: Vec<QuoteInfo>,
+    images: Vec<ImageRef>,
+    dice: Option<RandomResult>,
+    coin_flip: Option<bool>,
+    link_previews: Vec<String>,
+    room_effect: Option<RoomEffect>,
+    mentions: ChatMentions,
+    mention_rules: MentionRules,
+    mention_aliases: Arc<MentionAliases>,
+    roster: Option<Arc<MentionRoster>>,
+    urls: Vec<ExtractedUrl>,
+    /// Position in the message the tree was parsed from, or `None` when not locating
+    /// mentions.
+    locator: Option<SourceCursor<'m>>,
+}
+
+/// The nodes enclosing the content `MessageScan` is looking at.
+#[derive(Debug, Clone, Copy, Default)]
+struct ScanContext {
+    /// Inside a `[url]` or `[og]`, whose text is not a bare link.
+    in_link: bool,
+    /// Inside `[code]` or `[pre]`, where mentions don't notify anyone.
+    in_code: bool,
+    /// Inside an `[emoticon]`, whose text is the emoticon name.
+    in_emoticon: bool,
+    /// Inside a `[mention]`, whose text is only the displayed name of the ping.
+    in_mention: bool,
+}
+
+impl MessageScan<'_> {
+    fn walk(&mut self, parsed: &[BBCodeContent], context: ScanContext) {
+        for content in parsed {
+            match content {
+                BBCodeContent::String(text) => self.scan_text(text, context),
+                BBCodeContent::Node(node) => self.scan_node(node, context),
+            }
+        }
+    }
+
+    fn scan_text(&mut self, value: &str, context: ScanContext) {
+        let text_before = self.text.len();
+        let mut last = 0;
+        for (range, name) in MessagePreprocessor::shorthand_emoticons(value) {
+            self.text.push_str(&value[last..range.start]);
+            self.push_emoticon_text(name);
+            if !self.text_only && !context.in_emoticon {
+                self.record_emoticon(name);
+            }
+            last = range.end;
+        }
+        self.text.push_str(&value[last..]);
+
+        if self.text_only {
+            return;
+        }
+        let value_offset = self.locate_text(value);
+        let tokens = whitespace_tokens(value);
+        for (index, &(offset, token)) in tokens.iter().enumerate() {
+            let before = &value[..offset];
+            let at_line_start = || {
+                starts_line(before)
+                    && (before.contains('\n') || starts_line(&self.text[..text_before]))
+            };
+            if !context.in_code
+                && !context.in_mention
+                && (!self.mention_rules.only_at_line_start || at_line_start())
+            {
+                MessagePreprocessor::process_mention_token(
+                    token,
+                    value_offset.map(|start| start + offset),
+                    &self.mention_rules,
+                    &self.mention_aliases,
+                    &mut self.mentions,
+                );
+                if let Some(roster) = &self.roster
+                    && let Some((mention, range)) =
+                        roster.match_tokens(&tokens[index..], &self.mention_rules)
+                {
+                    if let Some(start) = value_offset {
+                        self.mentions
+                            .spans
+                            .extend(mention.candidates.iter().map(|&steam_id| MentionSpan {
+                                kind: MentionKind::SteamId(steam_id),
+                                byte_range: start + range.start..start + range.end,
+                            }));
+                    }
+                    self.mentions
+                        .mention_steamids
+                        .extend(mention.candidates.iter().copied());
+                    self.mentions.name_mentions.push(mention);
+                }
+            }
+            if !context.in_link {
+                self.urls.extend(bare_urls(token).map(|url| ExtractedUrl {
+                    url: url.to_string(),
+                    from_og: false,
+                    display_text: None,
+                }));
+            }
+        }
+    }
+
+    /// Scan a node and its children.
+    ///
+    /// Nodes are recorded before the nodes nested in them. Entries that depend on the
+    /// children's text (`[url]` links, quotes) are inserted at the position they had
+    /// when the node was reached, once the children are rendered.
+    fn scan_node(&mut self, node: &BBCodeNode, context: ScanContext) {
+        let children = node.content.as_deref().unwrap_or_default();
+        let text_start = self.text.len();
+        let url_slot = self.urls.len();
+        let quote_slot = self.quotes.len();
+        let tag = node.tag.as_str();
+        let emoticon = if tag == BBCODE_TYPE_EMOTICON {
+            MessagePreprocessor::emoticon_name(node)
+        } else {
+            None
+        };
+
+        if !self.text_only {
+            self.record_node(node, context, emoticon.as_deref());
+        }
+
+        let tag_start = self.skip_tag("[");
+        self.walk(
+            children,
+            ScanContext {
+                in_link: context.in_link || matches!(tag, BBCODE_TYPE_URL | BBCODE_TYPE_OG),
+                in_code: context.in_code || matches!(tag, BBCODE_TYPE_CODE | BBCODE_TYPE_PRE),
+                in_emoticon: context.in_emoticon || tag == BBCODE_TYPE_EMOTICON,
+                in_mention: context.in_mention || tag == BBCODE_TYPE_MENTION,
+            },
+        );
+        // Nodes with content were closed by a closing tag.
+        if node.content.is_some() {
+            self.skip_tag("[/");
+        }
+        if tag == BBCODE_TYPE_MENTION
+            && !self.text_only
+            && !context.in_mention
+            && let Some(kind) = MentionKind::from_tag_value(node.attrs.get("value"))
+        {
+            match kind {
+                MentionKind::All => self.mentions.mention_all = true,
+                MentionKind::Here => self.mentions.mention_here = true,
+                MentionKind::SteamId(steam_id) => self.mentions.mention_steamids.push(steam_id),
+            }
+            if let (Some(start), Some(locator)) = (tag_start, &self.locator) {
+                self.mentions.spans.push(MentionSpan {
+                    kind,
+                    byte_range: start..locator.at,
+                });
+            }
+        }
+
+        match tag {
+            BBCODE_TYPE_EMOTICON => {
+                self.text.truncate(text_start);
+                if let Some(name) = &emoticon {
+                    self.push_emoticon_text(name);
+                }
+            }
+            BBCODE_TYPE_URL => {
+                let inner = &self.text[text_start..];
+                if !self.text_only {
+                    let text = collapse_plain_text(inner);
+                    let (url, display_text) = match node.attrs.get("value") {
+                        Some(url) => (url.trim().to_string(), Some(text)),
+                        None => (text, None),
+                    };
+                    if !url.is_empty() {
+                        self.urls.insert(
+                            url_slot,
+                            ExtractedUrl {
+                                url,
+                                from_og: false,
+                                display_text: display_text.filter(|text| !text.is_empty()),
+                            },
+                        );
+                    }
+                }
+                if inner.trim().is_empty() {
+                    self.text.truncate(text_start);
+                    self.text
+                        .push_str(node.attrs.get("value").map_or("", String::as_str));
+                }
+            }
+            BBCODE_TYPE_QUOTE if !self.text_only && node.content.is_some() => {
+                let text = collapse_plain_text(&self.text[text_start..]);
+                if let Some(quote) = QuoteInfo::with_text(node, text) {
+                    self.quotes.insert(quote_slot, quote);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    /// Find where the text segment `value` starts in the message, moving past it.
+    fn locate_text(&mut self, value: &str) -> Option<usize> {
+        self.locator.as_mut()?.text(value).map(|range| range.start)
+    }
+
+    /// Move past the next tag starting with `opener`, returning where the tag starts.
+    fn skip_tag(&mut self, opener: &str) -> Option<usize> {
+        self.locator.as_mut()?.tag(opener)
+    }
+
+    /// Record what `node` itself contributes, before its children are scanned.
+    fn record_node(&mut self, node: &BBCodeNode, context: ScanContext, emoticon: Option<&str>) {
+        match node.tag.as_str() {
+            BBCODE_TYPE_EMOTICON => {
+                if let Some(name) = emoticon.filter(|_| !context.in_emoticon) {
+                    self.record_emoticon(name);
+                }
+            }
+            BBCODE_TYPE_OG => {
+                if let Some(url) = node.attrs.get("url").or_else(|| node.attrs.get("value")) {
+                    self.link_previews.push(url.clone());
+                    self.urls.push(ExtractedUrl {
+                        url: url.clone(),
+                        from_og: true,
+                        display_text: node.attrs.get("title").cloned(),
+                    });
+                }
+            }
+            BBCODE_TYPE_GAMEINVITE => self.game_invites.extend(GameInviteInfo::from_node(node)),
+            BBCODE_TYPE_IMG => self.images.extend(ImageRef::from_node(node)),
+            BBCODE_TYPE_RANDOM if self.dice.is_none() => self.dice = RandomResult::from_node(node),
+            BBCODE_TYPE_FLIP if self.coin_flip.is_none() => {
+                self.coin_flip = coin_flip_from_node(node);
+            }
+            BBCODE_TYPE_ROOMEFFECT if self.room_effect.is_none() => {
+                self.room_effect = node
+                    .attrs
+                    .get("type")
+                    .or_else(|| node.attrs.get("value"))
+                    .filter(|name| !name.trim().is_empty())
+                    .map(|name| RoomEffect::from_name(name));
+            }
+            _ => {}
+        }
+    }
+
+    fn push_emoticon_text(&mut self, name: &str) {
+        self.text.push(':');
+        self.text.push_str(name);
+        self.text.push(':');
+    }
+
+    fn record_emoticon(&mut self, name: &str) {
+        match self.emoticons.iter_mut().find(|used| used.name == name) {
+            Some(used) => used.count += 1,
+            None => self.emoticons.push(EmoticonUse {
+                name: name.to_string(),
+                count: 1,
+            }),
+        }
+    }
+}
+
+/// Unescape rendered text and collapse its whitespace runs to single spaces.
+fn collapse_plain_text(text: &str) -> String {
+    helpers::unescape_bbcode(text)
+        .split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// Find bare `http://` and `https://` links in a whitespace-free token.
+///
+/// A link must not be glued to a preceding letter or digit (`xhttps://` is not a link)
+/// and ends at a bracket, angle bracket or quote. Trailing sentence punctuation and a
+/// closing parenthesis without a matching opening one inside the link are left out,
+/// so `(see https://kether.pl/a_(b)).` yields `https://kether.pl/a_(b)`.
+fn bare_urls(token: &str) -> impl Iterator<Item = &str> {
+    bare_url_ranges(token).map(|range| &token[range])
+}
+
+/// Byte ranges of the links `bare_urls` finds in `token`.
+fn bare_url_ranges(token: &str) -> impl Iterator<Item = Range<usize>> {
+    let mut rest = token;
+    let mut consumed = 0;
+    std::iter::from_fn(move || {
+        loop {
+            let start = ["http://", "https://"]
+                .iter()
+                .filter_map(|scheme| rest.find(scheme).map(|start| (start, scheme.len())))
+                .min()?;
+            let (start, scheme_len) = start;
+            let absolute = consumed + start;
+            let glued = token[..absolute]
+                .chars()
+                .next_back()
+                .is_some_and(char::is_alphanumeric);
+
+            let candidate = &rest[start..];
+            let end = candidate
+                .find(['[', ']', '<', '>', '"', '\''])
+                .unwrap_or(candidate.len());
+            let mut url = &candidate[..end];
+            let opening = url.matches('(').count();
+            let mut closing = url.matches(')').count();
+            loop {
+                let trimmed = url.trim_end_matches(['.', ',', ';', ':', '!', '?']);
+                let trimmed = match trimmed.strip_suffix(')') {
+                    Some(inner) if opening < closing => {
+                        closing -= 1;
+                        inner
+                    }
+                    _ => trimmed,
+                };
+                if trimmed.len() == url.len() {
+                    break;
+                }
+                url = trimmed;
+            }
+
+            let advance = start + scheme_len.max(url.len());
+            consumed += advance;
+            rest = &rest[advance..];
+
+            if !glued && url.len() > scheme_len {
+                return Some(absolute..absolute + url.len());
+            }
+        }
+    })
+}
+
+/// Split the part of `message` in `region`, which holds no markup, into text, mentions
+/// and bare links.
+fn push_text_segments<'m>(
+    message: &'m str,
+    region: Range<usize>,
+    spans: &[MentionSpan],
+    segments: &mut Vec<MessageSegment<'m>>,
+) {
+    let inside = |range: &Range<usize>| region.start <= range.start && range.end <= region.end;
+    let mut found: Vec<(Range<usize>, Option<&MentionSpan>)> = spans
+        .iter()
+        .filter(|span| inside(&span.byte_range))
+        .map(|span| (span.byte_range.clone(), Some(span)))
+        .collect();
+    for (offset, token) in whitespace_tokens(&message[region.clone()]) {
+        let start = region.start + offset;
+        found.extend(
+            bare_url_ranges(token).map(|range| (start + range.start..start + range.end, None)),
+        );
+    }
+    // Mentions win over links starting at the same place, such as profile links.
+    found.sort_by_key(|(range, span)| (range.start, span.is_none()));
+
+    let mut at = region.start;
+    for (range, span) in found {
+        if range.start < at || range.is_empty() {
+            continue;
+        }
+        if at < range.start {
+            segments.push(MessageSegment::Text(&message[at..range.start]));
+        }
+        let source = &message[range.clone()];
+        segments.push(match span {
+            Some(span) => MessageSegment::Mention {
+                span: span.clone(),
+                source,
+            },
+            None => MessageSegment::Url(source),
+        });
+        at = range.end;
+    }
+    if at < region.end {
+        segments.push(MessageSegment::Text(&message[at..region.end]));
+    }
+}
+
+/// Follows a parsed message through the text it was parsed from.
+///
+/// Text segments hold the message text exactly and come in message order, so each one
+/// is searched for after the previous segment and the tags already passed.
+struct SourceCursor<'m> {
+    source: &'m str,
+    /// How far into `source` the walk has got.
+    at: usize,
+}
+
+impl<'m> SourceCursor<'m> {
+    fn new(source: &'m str) -> Self {
+        Self { source, at: 0 }
+    }
+
+    /// Find where the text segment `value` is, moving past it.
+    fn text(&mut self, value: &str) -> Option<Range<usize>> {
+        let start = self.at + self.source[self.at..].find(value)?;
+        self.at = start + value.len();
+        Some(start..self.at)
+    }
+
+    /// Move past the next tag starting with `opener`, returning where the tag starts.
+    fn tag(&mut self, opener: &str) -> Option<usize> {
+        let rest = &self.source[self.at..];
+        let start = rest.find(opener)?;
+        let close = rest[start..].find(']')?;
+        let tag_start = self.at + start;
+        self.at = tag_start + close + 1;
+        Some(tag_start)
+    }
+
+    /// Find where `node` is written, from its opening tag to its closing tag.
+    fn node(&mut self, node: &BBCodeNode) -> Option<Range<usize>> {
+        let start = self.tag("[")?;
+        for child in node.content.as_deref().unwrap_or_default() {
+            match child {
+                BBCodeContent::String(text) => {
+                    self.text(text)?;
+                }
+                BBCodeContent::Node(child) => {
+                    self.node(child)?;
+                }
+            }
+        }
+        // Nodes with content were closed by a closing tag.
+        if node.content.is_some() {
+            self.tag("[/")?;
+        }
+        Some(start..self.at)
+    }
+}
+
+/// Collect the trade offers referenced by `[tradeoffer]` and `[tradeofferlink]` nodes.
+///
+/// The offer id is read from the `tradeofferid` or `id` attribute, or from a
+/// `/tradeoffer/<id>` URL in the `url` attribute (or `[tradeofferlink=...]` shorthand).
+/// The partner comes from the `sender` or `partner` attribute, or the URL's `partner`
+/// query parameter, and may be a 64-bit SteamID or a 32-bit account id. Tags without a
+/// valid offer id (such as links for creating a new offer) are skipped, and an
+/// unparsable partner is reported as `None`.
+///
+/// # Example
+///
+/// ```
+/// use SC_Sub_Poster::preprocessing::{MessagePreprocessor, extract_trade_offers};
+///
+/// let parsed = MessagePreprocessor::parse_bbcode(
+///     r#"[tradeoffer sender="1531059355" state="2" tradeofferid="4711"][/tradeoffer]"#,
+/// );
+/// let offers = extract_trade_offers(&parsed);
+/// assert_eq!(offers[0].offer_id, 4711);
+/// assert_eq!(offers[0].partner.map(|partner| partner.account_id()), Some(1531059355));
+/// ```
+pub fn extract_trade_offers(parsed: &[BBCodeContent]) -> Vec<TradeOfferRef> {
+    parsed
+        .iter()
+        .flat_map(BBCodeContent::iter_nodes)
+        .filter(|node| node.tag == BBCODE_TYPE_TRADEOFFER || node.tag == BBCODE_TYPE_TRADEOFFERLINK)
+        .filter_map(trade_offer_from_node)
+        .collect()
+}
+
+fn trade_offer_from_node(node: &BBCodeNode) -> Option<TradeOfferRef> {
+    let url = node
+        .attrs
+        .get("url")
+        .or_else(|| node.attrs.get("value"))
+        .map(|url| url.trim().to_string())
+        .filter(|url| !url.is_empty());
+
+    let offer_id = node
+        .attrs
+        .get("tradeofferid")
+        .or_else(|| node.attrs.get("id"))
+        .map(String::as_str)
+        .or_else(|| url.as_deref().and_then(offer_id_from_url))
+        .and_then(|id| id.trim().parse::<u64>().ok())
+        .filter(|id| *id != 0)?;
+
+    let partner = node
+        .attrs
+        .get("sender")
+        .or_else(|| node.attrs.get("partner"))
+        .map(String::as_str)
+        .or_else(|| url.as_deref().and_then(|url| query_param(url, "partner")))
+        .and_then(partner_steam_id);
+
+    Some(TradeOfferRef {
+        offer_id,
+        partner,
+        url,
+    })
+}
+
+/// The id segment of a `.../tradeoffer/<id>/...` URL.
+fn offer_id_from_url(url: &str) -> Option<&str> {
+    let (_, rest) = url.split_once("/tradeoffer/")?;
+    let id = rest.split(['/', '?', '#']).next()?;
+    (!id.is_empty() && id.bytes().all(|b| b.is_ascii_digit())).then_some(id)
+}
+
+fn query_param<'u>(url: &'u str, name: &str) -> Option<&'u str> {
+    let (_, query) = url.split_once('?')?;
+    let query = query.split('#').next().unwrap_or_default();
+    query.split('&').find_map(|pair| {
+        let (key, value) = pair.split_once('=')?;
+        (key == name).then_some(value)
+    })
+}
+
+/// Market, inventory and inspect links among the URLs of a message.
+///
+/// Reads `message.urls`, so links in `[url]` tags, `[og]` cards and bare `http(s)://`
+/// text are all considered. Components may be percent-encoded. A link Steam unfurled is
+/// reported once.
+///
+/// # Example
+///
+/// ```
+/// use SC_Sub_Poster::preprocessing::{ItemLink, MessagePreprocessor, extract_item_links};
+///
+/// let message = MessagePreprocessor::preprocess_message(
+///     "wts https://steamcommunity.com/market/listings/730/AK-47%20%7C%20Redline%20%28FT%29",
+/// );
+/// let ItemLink::MarketListing { app_id, market_hash_name, .. } = &extract_item_links(&message)[0]
+/// else {
+///     panic!("expected a market listing");
+/// };
+/// assert_eq!((*app_id, market_hash_name.as_str()), (730, "AK-47 | Redline (FT)"));
+/// ```
+pub fn extract_item_links(message: &PreprocessedMessage) -> Vec<ItemLink> {
+    let mut links: Vec<ItemLink> = Vec::new();
+    for url in &message.urls {
+        if let Some(link) = item_link(&url.url)
+            && !links.contains(&link)
+        {
+            links.push(link);
+        }
+    }
+    links
+}
+
+fn item_link(url: &str) -> Option<ItemLink> {
+    let url = url.trim();
+    if let Some((_, preview)) = url.split_once("csgo_econ_action_preview") {
+        return inspect_link(url, &percent_decode(preview));
+    }
+
+    let (_, path) = url.split_once("steamcommunity.com/")?;
+    let (path, fragment) = path.split_once('#').unwrap_or((path, ""));
+    let path = path.split('?').next().unwrap_or_default();
+
+    if let Some(listing) = path.strip_prefix("market/listings/") {
+        let (app_id, name) = listing.split_once('/')?;
+        let market_hash_name = percent_decode(name.trim_end_matches('/'));
+        if market_hash_name.is_empty() {
+            return None;
+        }
+        return Some(ItemLink::MarketListing {
+            app_id: app_id.parse().ok()?,
+            market_hash_name,
+            url: url.to_string(),
+        });
+    }
+
+    let mut segments = path.split('/');
+    let (kind, id) = (segments.next()?, percent_decode(segments.next()?));
+    if segments.next() != Some("inventory") || id.is_empty() {
+        return None;
+    }
+    let (owner, vanity_name) = match kind {
+        "profiles" => (Some(SteamID::from(id.parse::<u64>().ok()?)), None),
+        "id" => (None, Some(id)),
+        _ => return None,
+    };
+    let fragment = percent_decode(fragment);
+    let mut ids = fragment.split('_').map(str::trim);
+    Some(ItemLink::Inventory {
+        owner,
+        vanity_name,
+        app_id: ids.next().and_then(|id| id.parse().ok()),
+        context_id: ids.next().and_then(|id| id.parse().ok()),
+        asset_id: ids.next().and_then(|id| id.parse().ok()),
+        url: url.to_string(),
+    })
+}
+
+/// Read the `S`/`M`, `A` and `D` components that follow `csgo_econ_action_preview`.
+fn inspect_link(url: &str, preview: &str) -> Option<ItemLink> {
+    let preview = preview.trim_start();
+    let mut components = HashMap::new();
+    let mut rest = preview;
+    while let Some(key) = rest.chars().next().filter(char::is_ascii_uppercase) {
+        let digits = rest[1..]
+            .find(|c: char| !c.is_ascii_digit())
+            .unwrap_or(rest.len() - 1);
+        components.insert(key, rest[1..1 + digits].parse::<u64>().ok()?);
+        rest = &rest[1 + digits..];
+    }
+    Some(ItemLink::Inspect {
+        owner: components.get(&'S').map(|&id| SteamID::from(id)),
+        market_listing_id: components.get(&'M').copied(),
+        asset_id: *components.get(&'A')?,
+        d: *components.get(&'D')?,
+        url: url.to_string(),
+    })
+}
+
+/// Decode `%XX` escapes; malformed escapes are kept as written.
+fn percent_decode(text: &str) -> String {
+    let bytes = text.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut index = 0;
+    while index < bytes.len() {
+        match bytes.get(index + 1..index + 3) {
+            Some(&[high, low])
+                if bytes[index] == b'%' && high.is_ascii_hexdigit() && low.is_ascii_hexdigit() =>
+            {
+                let hex = [high, low];
+                let hex = std::str::from_utf8(&hex).expect("hex digits are ASCII");
+                decoded.push(u8::from_str_radix(hex, 16).expect("checked hex digits"));
+                index += 3;
+            }
+            _ => {
+                decoded.push(bytes[index]);
+                index += 1;
+            }
+        }
+    }
+    String::from_utf8_lossy(&decoded).into_owned()
+}
+
+/// Interpret a trade partner as a 64-bit SteamID or a 32-bit account id.
+fn partner_steam_id(raw: &str) -> Option<SteamID> {
+    let raw = raw.trim().parse::<u64>().ok().filter(|raw| *raw != 0)?;
+    Some(match u32::try_from(raw) {
+        Ok(account_id) => SteamID::new(
+            account_id,
+            Instance::Desktop,
+            AccountType::Individual,
+            Universe::Public,
+        ),
+        Err(_) => SteamID::from(raw),
+    })
+}
+
+/// Helper functions for message processing and formatting.
+///
+/// This module provides convenience functions for creating mentions, formatting
+/// messages with BBCode, and checking for mentions in text.
+pub mod helpers {
+    use super::*;
+
+    /// Create a mention string for a Steam ID in the format `@[U:1:xxxxx]`.
+    ///
+    /// # Arguments
+    ///
+    /// * `steam_id` - The Steam ID to create a mention for
+    ///
+    /// # Returns
+    ///
+    /// A string in the format `@[U:1:xxxxx]` that can be used to mention the user.
+    pub fn create_mention(steam_id: SteamID) -> String {
+        format!("@{}", steam_id.steam3())
+    }
+
+    /// Create a `[mention]` ping for a Steam ID, the form the Steam client sends.
+    ///
+    /// Unlike the plain `@[U:1:xxxxx]` from `create_mention`, this shows `display_name`
+    /// in the message.
+    ///
+    /// # Arguments
+    ///
+    /// * `steam_id` - The Steam ID to ping
+    /// * `display_name` - The name shown after the `@`; brackets in it are escaped
+    ///
+    /// # Returns
+    ///
+    /// A string like `[mention=76561199491325083]@Kyle[/mention]`.
+    pub fn create_mention_tag(steam_id: SteamID, display_name: &str) -> String {
+        format!(
+            "[{BBCODE_TYPE_MENTION}={}]@{}[/{BBCODE_TYPE_MENTION}]",
+            u64::from(steam_id),
+            escape_bbcode(display_name)
+        )
+    }
+
+    /// Create the `[mention=all]@all[/mention]` ping the Steam client sends for `@all`.
+    pub fn create_mention_all_tag() -> String {
+        format!("[{BBCODE_TYPE_MENTION}=all]{MENTION_ALL}[/{BBCODE_TYPE_MENTION}]")
+    }
+
+    /// Create the `[mention=here]@here[/mention]` ping the Steam client sends for `@here`.
+    pub fn create_mention_here_tag() -> String {
+        format!("[{BBCODE_TYPE_MENTION}=here]{MENTION_HERE}[/{BBCODE_TYPE_MENTION}]")
+    }
+
+    /// Rewrite the plain mentions in `message` as `[mention]` pings.
+    ///
+    /// See `MessagePreprocessor::tag_mentions`; this uses the default preprocessor.
+    pub fn tag_mentions(message: &str) -> String {
+        MessagePreprocessor::shared().tag_mentions(message)
+    }
+
+    /// Create a mention string for the roster member called `name`.
+    ///
+    /// # Arguments
+    ///
+    /// * `roster` - The members to look the name up in
+    /// * `name` - The member's display name, matched case-insensitively
+    ///
+    /// # Returns
+    ///
+    /// The `@[U:1:xxxxx]` mention of the member, or `None` when no member or more than
+    /// one member has that name (see `MentionRoster::lookup` for the candidates).
+    pub fn create_mention_by_name(roster: &MentionRoster, name: &str) -> Option<String> {
+        match roster.lookup(name) {
+            [steam_id] => Some(create_mention(*steam_id)),
+            _ => None,
+        }
+    }
+
+    /// Wrap bare `http(s)://` links in `[noembed]` so Steam does not attach preview cards.
+    ///
+    /// Steam offers no request flag for disabling link unfurling, so this relies on the
+    /// `[noembed]` markup honored by the Steam chat clients. Links that are already part of
+    /// markup (`[url]`, `[og]`, `[code]`, `[pre]`, `[noembed]`) are left untouched, and
+    /// clients that do not understand `[noembed]` will show the link without a preview card
+    /// but may render the tag literally.
+    ///
+    /// # Arguments
+    ///
+    /// * `message` - The outgoing message text
+    ///
+    /// # Returns
+    ///
+    /// The message with each bare link wrapped; whitespace is preserved exactly.
+    pub fn suppress_link_previews(message: &str) -> String {
+        let mut result = String::with_capacity(message.len());
+        let mut offset = 0;
+
+        for piece in message.split_inclusive(char::is_whitespace) {
+            let token = piece.trim_end();
+            let trailing = &piece[token.len()..];
+
+            if is_bare_url(token) && !inside_link_markup(&message[..offset]) {
+                result.push_str(&format!(
+                    "[{tag}]{token}[/{tag}]",
+                    tag = super::BBCODE_TYPE_NOEMBED
+                ));
+            } else {
+                result.push_str(token);
+            }
+            result.push_str(trailing);
+            offset += piece.len();
+        }
+
+        result
+    }
+
+    fn is_bare_url(token: &str) -> bool {
+        (token.starts_with("http://") || token.starts_with("https://"))
+            && !token.contains(['[', ']'])
+    }
+
+    fn inside_link_markup(before: &str) -> bool {
+        let lower = before.to_ascii_lowercase();
+        ["url", "og", "code", "pre", super::BBCODE_TYPE_NOEMBED]
+            .iter()
+            .any(|tag| {
+                let open = lower.rfind(&format!("[{}", tag));
+                let close = lower.rfind(&format!("[/{}]", tag));
+                match (open, close) {
+                    (Some(open), Some(close)) => open > close,
+                    (Some(_), None) => true,
+                    _ => false,
+                }
+            })
+    }
+
+    /// Create a `/me` emote message.
+    ///
+    /// # Arguments
+    ///
+    /// * `text` - The action text (e.g. `"waves"`); an existing `/me` prefix is not duplicated
+    ///
+    /// # Returns
+    ///
+    /// The message in the form `/me <text>`.
+    pub fn create_emote_message(text: &str) -> String {
+        let action = MessagePreprocessor::strip_emote_prefix(text).unwrap_or(text);
+        format!("{} {}", super::EMOTE_PREFIX, action.trim())
+    }
+
+    /// Create the message text that invokes a slash command.
+    ///
+    /// # Arguments
+    ///
+    /// * `command` - The command to send; its arguments are inserted as-is, except that
+    ///   whitespace in a `Giphy` query is collapsed to single spaces
+    ///
+    /// # Returns
+    ///
+    /// The command string (e.g. `/random 1-6`), which `ChatCommand::parse` turns back into
+    /// `command`, or `None` when the arguments cannot round-trip: a `Random` range with
+    /// `min > max`, an empty `Giphy` query, a `Me` or `Spoiler` text with surrounding
+    /// whitespace, or an `Effect` name that `create_room_effect_message` would reject.
+    pub fn create_command_message(command: &ChatCommand) -> Option<String> {
+        let name = command.name();
+        let trimmed = |text: &str| text.trim() == text;
+        match command {
+            ChatCommand::Me { text } | ChatCommand::Spoiler { text } => {
+                trimmed(text).then(|| format!("/{name} {text}"))
+            }
+            ChatCommand::Random { min, max } => {
+                (min <= max).then(|| format!("/{name} {min}-{max}"))
+            }
+            ChatCommand::Flip => Some(format!("/{name}")),
+            ChatCommand::Giphy { query } => {
+                let query = query.split_whitespace().collect::<Vec<_>>().join(" ");
+                (!query.is_empty()).then(|| format!("/{name} {query}"))
+            }
+            ChatCommand::Code { text } => Some(format!("/{name} {text}")),
+            ChatCommand::Effect { effect } => {
+                create_room_effect_message(effect, "")?;
+                Some(format!("/{name} {}", effect.name()))
+            }
+        }
+    }
+
+    /// Make text that starts with a slash safe to send as a regular message.
+    ///
+    /// A message starting with `//` is never parsed as a command, so one extra `/` is
+    /// inserted before a leading slash. Text without a leading slash is returned
+    /// unchanged.
+    ///
+    /// # Arguments
+    ///
+    /// * `text` - The literal message text
+    ///
+    /// # Returns
+    ///
+    /// The text, with its leading `/` doubled when it has one.
+    pub fn escape_leading_slash(text: &str) -> String {
+        let indent = text.len() - text.trim_start().len();
+        if text[indent..].starts_with('/') {
+            format!("{}/{}", &text[..indent], &text[indent..])
+        } else {
+            text.to_string()
+        }
+    }
+
+    /// Create an `@all` mention string.
+    ///
+    /// # Returns
+    ///
+    /// The string `"@all"` which mentions all members in a group chat.
+    pub fn create_all_mention() -> String {
+        super::MENTION_ALL.to_string()
+    }
+
+    /// Create an `@here` mention string.
+    ///
+    /// # Returns
+    ///
+    /// The string `"@here"` which mentions online/active members in a group chat.
+    pub fn create_here_mention() -> String {
+        super::MENTION_HERE.to_string()
+    }
+
+    /// Check if a message contains any mention tokens.
+    ///
+    /// # Arguments
+    ///
+    /// * `message` - The message text to check
+    ///
+    /// # Returns
+    ///
+    /// `true` if the message contains `@all`, `@here`, or any `@` character.
+    pub fn has_mentions(message: &str) -> bool {
+        message.contains(super::MENTION_ALL)
+            || message.contains(super::MENTION_HERE)
+            || message.contains("@")
+    }
+
+    /// Format a message with BBCode tags.
+    ///
+    /// The content is escaped with `escape_bbcode` first, so untrusted text cannot close
+    /// the tag or add markup of its own. `code` and `pre` content is shown verbatim, so it
+    /// is only escaped when it contains the closing tag. Use `format_with_bbcode_unescaped`
+    /// to wrap content that is already BBCode.
+    ///
+    /// Unsupported types and invalid values are logged as warnings and leave the content
+    /// unwrapped; use `try_format_with_bbcode` to handle them as errors.
+    ///
+    /// # Arguments
+    ///
+    /// * `message` - The message content to wrap in BBCode
+    /// * `bbcode_type` - The type of BBCode formatting (e.g., `BBCODE_TYPE_SPOILER`, `BBCODE_TYPE_CODE`)
+    /// * `value` - Optional value for BBCode types that require it (e.g., URL for `BBCODE_TYPE_URL`)
+    ///
+    /// # Returns
+    ///
+    /// The message wrapped in the appropriate BBCode tags.
+    pub fn format_with_bbcode(message: &str, bbcode_type: &str, value: &str) -> String {
+        let content = escape_content(message, bbcode_type);
+        let result = super::bbcode::formatting::format_with_bbcode(&content, bbcode_type, value);
+        warn_on_error(bbcode_type, value, result).unwrap_or(content)
+    }
+
+    /// Format a message with BBCode tags, reporting unsupported types and values.
+    ///
+    /// Supported types are `spoiler`, `code`, `pre`, `url` (value: the link target),
+    /// `emoticon` (value: the name), `quote` (optional value: the author, as Steam writes
+    /// it, e.g. `76561198012345678;1699999999;3`), `img` (content: the image URL),
+    /// `random` (value: `max` or `min-max`, empty for 1 to 100) and `flip`. `emoticon`,
+    /// `random` and `flip` ignore the content. Content is escaped as in
+    /// `format_with_bbcode`.
+    ///
+    /// # Arguments
+    ///
+    /// * `message` - The message content to wrap in BBCode
+    /// * `bbcode_type` - The type of BBCode formatting
+    /// * `value` - The tag value, empty for types without one
+    ///
+    /// # Errors
+    ///
+    /// Returns `FormatError::UnsupportedType` for other types, and
+    /// `FormatError::InvalidValue` for a missing `url` or `emoticon` value, a value
+    /// containing brackets, quotes or newlines, or a `random` range that does not parse.
+    pub fn try_format_with_bbcode(
+        message: &str,
+        bbcode_type: &str,
+        value: &str,
+    ) -> Result<String, FormatError> {
+        super::bbcode::formatting::format_with_bbcode(
+            &escape_content(message, bbcode_type),
+            bbcode_type,
+            value,
+        )
+    }
+
+    /// Format a message with BBCode tags without escaping the content.
+    ///
+    /// Only for trusted content, e.g. to nest the output of other helpers. Errors are
+    /// handled as in `format_with_bbcode`.
+    pub fn format_with_bbcode_unescaped(message: &str, bbcode_type: &str, value: &str) -> String {
+        let result = super::bbcode::formatting::format_with_bbcode(message, bbcode_type, value);
+        warn_on_error(bbcode_type, value, result).unwrap_or_else(|| message.to_string())
+    }
+
+    /// Wrap a message in several BBCode tags at once.
+    ///
+    /// The content is escaped once, as for the innermost tag in `format_with_bbcode`.
+    ///
+    /// # Arguments
+    ///
+    /// * `message` - The message content to wrap
+    /// * `layers` - `(bbcode_type, value)` pairs, outermost first; e.g.
+    ///   `[(BBCODE_TYPE_SPOILER, ""), (BBCODE_TYPE_CODE, "")]` hides a code block behind a
+    ///   spoiler
+    ///
+    /// # Errors
+    ///
+    /// Returns the first `FormatError` any layer reports, as in `try_format_with_bbcode`.
+    pub fn format_with_bbcode_layers(
+        message: &str,
+        layers: &[(&str, &str)],
+    ) -> Result<String, FormatError> {
+        let Some(((inner_type, inner_value), outer)) = layers.split_last() else {
+            return Ok(escape_bbcode(message));
+        };
+        let mut formatted = try_format_with_bbcode(message, inner_type, inner_value)?;
+        for (bbcode_type, value) in outer.iter().rev() {
+            formatted =
+                super::bbcode::formatting::format_with_bbcode(&formatted, bbcode_type, value)?;
+        }
+        Ok(formatted)
+    }
+
+    /// Escape content for `bbcode_type`; literal blocks only need escaping to stay closed.
+    fn escape_content(message: &str, bbcode_type: &str) -> String {
+        let literal = [BBCODE_TYPE_CODE, BBCODE_TYPE_PRE].contains(&bbcode_type);
+        if literal && super::bbcode::literal_end(message, bbcode_type).is_none() {
+            message.to_string()
+        } else {
+            escape_bbcode(message)
+        }
+    }
+
+    fn warn_on_error(
+        bbcode_type: &str,
+        value: &str,
+        result: Result<String, FormatError>,
+    ) -> Option<String> {
+        result
+            .inspect_err(
+                |err| tracing::warn!(bbcode_type, value, error = %err, "message left unformatted"),
+            )
+            .ok()
+    }
+
+    /// Hide untrusted text behind a spoiler.
+    ///
+    /// The text is escaped with `escape_bbcode` and, if the result would exceed
+    /// `GROUP_CHAT_MESSAGE_LIMIT`, shortened and ended with `…`. Shortening never splits an
+    /// escape sequence or leaves a partial mention behind.
+    ///
+    /// # Arguments
+    ///
+    /// * `text` - The text to hide
+    ///
+    /// # Returns
+    ///
+    /// `[spoiler]<escaped text>[/spoiler]`, at most `GROUP_CHAT_MESSAGE_LIMIT` UTF-16 code
+    /// units long.
+    pub fn spoiler_wrap(text: &str) -> String {
+        let wrapped =
+            |payload: &str| format_with_bbcode_unescaped(payload, BBCODE_TYPE_SPOILER, "");
+        let escaped = escape_bbcode(text);
+        let full = wrapped(&escaped);
+        let limit = super::GROUP_CHAT_MESSAGE_LIMIT;
+        if full.encode_utf16().count() <= limit {
+            return full;
+        }
+
+        let overhead = wrapped("").encode_utf16().count() + '…'.len_utf16();
+        let budget = limit.saturating_sub(overhead);
+        let mut used = 0;
+        let mut end = 0;
+        for (index, c) in escaped.char_indices() {
+            used += c.len_utf16();
+            if used > budget {
+                break;
+            }
+            end = index + c.len_utf8();
+        }
+        let mut payload = &escaped[..end];
+        if let Some(open) = payload.rfind('[')
+            && !payload[open..].contains(']')
+        {
+            payload = &payload[..open];
+        }
+        let trailing_backslashes = payload.len() - payload.trim_end_matches('\\').len();
+        if trailing_backslashes % 2 == 1 {
+            payload = &payload[..payload.len() - 1];
+        }
+        wrapped(&format!("{payload}…"))
+    }
+
+    /// Escape BBCode markup in untrusted text.
+    ///
+    /// Square brackets are prefixed with a backslash, as the Steam client does when a user
+    /// types them, so `[img]` is shown literally instead of being rendered. Mention tokens
+    /// such as `@[U:1:xxxxx]` are kept intact so they still notify the user.
+    ///
+    /// # Arguments
+    ///
+    /// * `text` - The text to embed in a message
+    ///
+    /// # Returns
+    ///
+    /// The escaped text; `unescape_bbcode` restores the original.
+    pub fn escape_bbcode(text: &str) -> String {
+        let mut escaped = String::with_capacity(text.len());
+        let mut rest = text;
+
+        while let Some(open) = rest.find(['[', ']']) {
+            escaped.push_str(&rest[..open]);
+            let candidate = &rest[open..];
+
+            if candidate.starts_with('[')
+                && let Some(close) = candidate.find(']')
+                && MessagePreprocessor::is_steam_id_format(&candidate[..=close])
+            {
+                escaped.push_str(&candidate[..=close]);
+                rest = &candidate[close + 1..];
+                continue;
+            }
+
+            escaped.push('\\');
+            escaped.push_str(&candidate[..1]);
+            rest = &candidate[1..];
+        }
+
+        escaped.push_str(rest);
+        escaped
+    }
+
+    /// Remove the backslash escapes added by `escape_bbcode`.
+    ///
+    /// # Arguments
+    ///
+    /// * `text` - Escaped text
+    ///
+    /// # Returns
+    ///
+    /// The text with `\[` and `\]` turned back into `[` and `]`.
+    pub fn unescape_bbcode(text: &str) -> String {
+        text.replace("\\[", "[").replace("\\]", "]")
+    }
+
+    /// Check whether a sticker name is safe to embed in a `[sticker]` tag.
+    ///
+    /// Names must be non-empty, at most `MAX_STICKER_NAME_LEN` bytes, must not start or end
+    /// with whitespace, and may only contain alphanumerics, spaces, and `_ - ' . : !`.
+    pub fn is_valid_sticker_name(sticker_name: &str) -> bool {
+        !sticker_name.is_empty()
+            && sticker_name.len() <= super::MAX_STICKER_NAME_LEN
+            && sticker_name.trim() == sticker_name
+            && sticker_name.chars().all(|c| {
+                c.is_alphanumeric() || matches!(c, ' ' | '_' | '-' | '\'' | '.' | ':' | '!')
+            })
+    }
+
+    /// Create a sticker message in the form sent by the Steam client.
+    ///
+    /// # Arguments
+    ///
+    /// * `sticker_name` - The sticker identifier (e.g. `"ChromaticCat"`)
+    ///
+    /// # Returns
+    ///
+    /// `[sticker type="<name>" limit="0"][/sticker]`, or `None` if the name fails
+    /// `is_valid_sticker_name`.
+    pub fn create_sticker_message(sticker_name: &str) -> Option<String> {
+        is_valid_sticker_name(sticker_name).then(|| {
+            format!(
+                "[{tag} type=\"{sticker_name}\" limit=\"0\"][/{tag}]",
+                tag = super::BBCODE_TYPE_STICKER
+            )
+        })
+    }
+
+    /// Create a message that plays a room effect.
+    ///
+    /// # Arguments
+    ///
+    /// * `effect` - The effect to play
+    /// * `text` - Message text shown with the effect; inserted as-is, so it may contain
+    ///   BBCode
+    ///
+    /// # Returns
+    ///
+    /// `[roomeffect type="<name>"]<text>[/roomeffect]`, or `None` for an
+    /// `RoomEffect::Other` name that is empty or contains characters other than ASCII
+    /// alphanumerics, `_` and `-`.
+    pub fn create_room_effect_message(effect: &RoomEffect, text: &str) -> Option<String> {
+        let name = effect.name();
+        let valid = !name.is_empty()
+            && name
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-');
+        valid.then(|| {
+            format!(
+                "[{tag} type=\"{name}\"]{text}[/{tag}]",
+                tag = super::BBCODE_TYPE_ROOMEFFECT
+            )
+        })
+    }
+}
+
+/// A BBCode problem found by `MessagePreprocessor::validate_only`.
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+#[error("invalid BBCode at byte {position}: {reason}")]
+pub struct BBCodeError {
+    /// Byte offset of the offending `[` in the message.
+    pub position: usize,
+    /// What is wrong with the tag.
+    pub reason: String,
+}
+
+/// What a `BBCodeDiagnostic` found wrong with a tag.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum BBCodeDiagnosticKind {
+    /// A tag outside the allowed set, such as `[sub]` or an empty `[]`.
+    UnknownTag,
+    /// A container tag that is never closed, or a `[` with no `]` after it.
+    UnclosedTag,
+    /// A closing tag without a matching opening tag.
+    StrayCloser,
+    /// A malformed attribute list, such as `[url=]` or an unterminated quoted value.
+    BadAttribute,
+}
+
+/// A BBCode problem found by `MessagePreprocessor::validate_bbcode`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BBCodeDiagnostic {
+    /// Where the offending tag is, in bytes.
+    ///
+    /// The range covers the tag from `[` to `]` (or to the end of the message when the
+    /// tag is unterminated), so both ends fall on `char` boundaries and
+    /// `&message[byte_range]` is the tag as written.
+    pub byte_range: Range<usize>,
+    /// What is wrong with the tag.
+    pub kind: BBCodeDiagnosticKind,
+    /// A human-readable explanation, the `reason` of the equivalent `BBCodeError`.
+    pub detail: String,
+}
+
+impl From<BBCodeDiagnostic> for BBCodeError {
+    fn from(diagnostic: BBCodeDiagnostic) -> Self {
+        Self {
+            position: diagnostic.byte_range.start,
+            reason: diagnostic.detail,
+        }
+    }
+}
+
+/// How the parser treats malformed BBCode.
+///
+/// Malformed tags are `[url]`, `[spoiler]` and other container tags that are never
+/// closed, closing tags of allowed names without an opener (`[/spoiler]`), empty `[]`,
+/// and a `[` starting an allowed tag with no `]` (`[url=http://x`). Brackets around
+/// unknown names, such as `[[double brackets]]`, are ordinary text under every policy.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum MalformedTagPolicy {
+    /// Keep the malformed tag as text, so no part of the message is lost (default).
+    #[default]
+    KeepAsText,
+    /// Remove the malformed tag; the text around and inside it is kept.
+    Drop,
+    /// Fail `MessagePreprocessor::try_parse` with a `BBCodeError`.
+    ///
+    /// Methods that cannot fail, like `parse` and `preprocess`, keep the tag as text.
+    Error,
+}
+
+/// Maximum message length, in characters, accepted for rendered templates.
+pub const MAX_MESSAGE_LEN: usize = 5000;
+
+/// Longest group chat message Steam accepts, in UTF-16 code units.
+pub const GROUP_CHAT_MESSAGE_LIMIT: usize = 5000;
+
+/// Longest friend chat message Steam accepts, in UTF-16 code units.
+pub const FRIEND_CHAT_MESSAGE_LIMIT: usize = 5000;
+
+/// The unit a message length is measured in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum LengthUnit {
+    /// UTF-16 code units, as counted by JavaScript's `String.length`.
+    ///
+    /// Characters outside the Basic Multilingual Plane, such as most emoji, count twice.
+    Utf16CodeUnits,
+}
+
+impl std::fmt::Display for LengthUnit {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            LengthUnit::Utf16CodeUnits => f.write_str("UTF-16 code units"),
+        }
+    }
+}
+
+/// A message is longer than Steam accepts.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Error)]
+#[error("message is {actual} {counted_in} long, {} over the limit of {limit}", .actual - .limit)]
+pub struct LengthError {
+    /// The maximum allowed length.
+    pub limit: usize,
+    /// The length of the message.
+    pub actual: usize,
+    /// How `limit` and `actual` are counted.
+    pub counted_in: LengthUnit,
+}
+
+impl LengthError {
+    /// How far the message is over the limit.
+    pub fn excess(&self) -> usize {
+        self.actual - self.limit
+    }
+}
+
+/// Errors produced while parsing, validating, or rendering a `MessageTemplate`.
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+pub enum TemplateError {
+    /// A `{` was opened but never closed.
+    #[error("unclosed placeholder starting at byte {position}")]
+    UnclosedPlaceholder {
+        /// Byte offset of the opening brace.
+        position: usize,
+    },
+    /// A lone `}` appeared outside a placeholder (use `}}` for a literal brace).
+    #[error("unmatched '}}' at byte {position}")]
+    UnmatchedBrace {
+        /// Byte offset of the stray brace.
+        position: usize,
+    },
+    /// A placeholder name was empty or contained characters other than `[A-Za-z0-9_]`.
+    #[error("invalid placeholder name {name:?} at byte {position}")]
+    InvalidPlaceholder {
+        /// The rejected name.
+        name: String,
+        /// Byte offset of the opening brace.
+        position: usize,
+    },
+    /// No value was supplied for a placeholder.
+    #[error("missing value for placeholder {0:?}")]
+    MissingValue(String),
+    /// The template uses a BBCode tag Steam does not support.
+    #[error("template uses unsupported BBCode tag [{0}]")]
+    DisallowedTag(String),
+    /// The rendered message exceeds `MAX_MESSAGE_LEN`.
+    #[error("rendered message is {actual} characters, limit is {limit}")]
+    TooLong {
+        /// The maximum allowed length.
+        limit: usize,
+        /// The rendered length.
+        actual: usize,
+    },
+}
+
+/// Error returned by `PreprocessedMessage::from_json`.
+#[derive(Debug, Error)]
+pub enum SchemaError {
+    /// The JSON is malformed or does not match the schema.
+    #[error("invalid preprocessed message JSON: {0}")]
+    Json(#[from] serde_json::Error),
+    /// The blob was written by a newer schema version.
+    #[error("unsupported schema version {found}, newest supported is {supported}")]
+    UnsupportedVersion {
+        /// The version found in the blob.
+        found: u32,
+        /// `PreprocessedMessage::SCHEMA_VERSION`.
+        supported: u32,
+    },
+}
+
+/// A formatting problem reported by `helpers::try_format_with_bbcode`.
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+pub enum FormatError {
+    /// No formatter exists for the BBCode type.
+    #[error("no formatter for BBCode type [{0}]")]
+    UnsupportedType(String),
+    /// The value cannot be used with the BBCode type.
+    #[error("invalid value {value:?} for BBCode type [{bbcode_type}]")]
+    InvalidValue {
+        /// The BBCode type being formatted.
+        bbcode_type: String,
+        /// The rejected value.
+        value: String,
+    },
+}
+
+/// Any of the errors raised while preparing message text, as carried by
+/// `KetherError::Preprocessing`.
+#[derive(Debug, Error)]
+pub enum PreprocessingError {
+    /// The message failed BBCode validation.
+    #[error(transparent)]
+    BBCode(#[from] BBCodeError),
+    /// The message is longer than Steam accepts.
+    #[error(transparent)]
+    Length(#[from] LengthError),
+    /// A value could not be formatted as BBCode.
+    #[error(transparent)]
+    Format(#[from] FormatError),
+    /// A message template could not be parsed or rendered.
+    #[error(transparent)]
+    Template(#[from] TemplateError),
+    /// A serialized preprocessed message could not be read.
+    #[error(transparent)]
+    Schema(#[from] SchemaError),
+}
+
+/// Put a word joiner after the `@` of `@all` and `@here` (in any case), so the text
+/// reads the same but is no longer a mention.
+fn defuse_room_mentions(value: &str) -> String {
+    const WORD_JOINER: char = '\u{2060}';
+
+    let mut defused = String::with_capacity(value.len());
+    let mut rest = value;
+    while let Some(at) = rest.find('@') {
+        defused.push_str(&rest[..=at]);
+        rest = &rest[at + 1..];
+        if ["all", "here"].iter().any(|keyword| {
+            rest.get(..keyword.len())
+                .is_some_and(|word| word.eq_ignore_ascii_case(keyword))
+        }) {
+            defused.push(WORD_JOINER);
+        }
+    }
+    defused.push_str(rest);
+    defused
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum TemplatePart {
+    Literal(String),
+    Placeholder(String),
+}
+
+/// Reusable message template with `{placeholder}` substitution.
+///
+/// Templates are checked once at construction: placeholders must be well-formed, the
+/// literal markup may only use BBCode tags Steam supports, and the message rendered with
+/// the supplied sample values must fit within `MAX_MESSAGE_LEN`. Use `{{` and `}}` for
+/// literal braces.
+///
+/// Values are escaped with `escape_bbcode` when rendered, so user-supplied text cannot
+/// inject BBCode, and a word joiner (U+2060) is put after the `@` of `@all` and `@here`
+/// so it cannot ping the whole room; write those in the template itself instead. Mention
+/// tokens such as `@[U:1:xxxxx]` are kept intact. Use `with_value_escaping(false)` for
+/// values that are trusted markup. Send rendered templates with
+/// `SendGroupMessageParams::from_template`, which keeps the escapes.
+///
+/// # Example
+///
+/// ```
+/// use std::collections::HashMap;
+/// use SC_Sub_Poster::preprocessing::MessageTemplate;
+///
+/// let samples = HashMap::from([("title", "Weekly Cup".to_string())]);
+/// let template = MessageTemplate::new("{title} starts soon", &samples).unwrap();
+///
+/// let values = HashMap::from([("title", "[b]Finals[/b]".to_string())]);
+/// assert_eq!(template.render(&values).unwrap(), r"\[b\]Finals\[/b\] starts soon");
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MessageTemplate {
+    source: String,
+    parts: Vec<TemplatePart>,
+    escape_values: bool,
+}
+
+impl MessageTemplate {
+    /// Parse and validate a template.
+    ///
+    /// # Arguments
+    ///
+    /// * `template` - The template text, e.g. `"{mention_all} {title} starts at {time}"`
+    /// * `samples` - Representative values for every placeholder, used to check the
+    ///   rendered length
+    ///
+    /// # Errors
+    ///
+    /// Returns a `TemplateError` if the template is malformed, uses unsupported BBCode,
+    /// lacks a sample for one of its placeholders, or renders longer than `MAX_MESSAGE_LEN`.
+    pub fn new(template: &str, samples: &HashMap<&str, String>) -> Result<Self, TemplateError> {
+        let parts = Self::parse(template)?;
+
+        for part in &parts {
+            if let TemplatePart::Literal(text) = part {
+                Self::check_tags(text)?;
+            }
+        }
+
+        let template = Self {
+            source: template.to_string(),
+            parts,
+            escape_values: true,
+        };
+        template.render(samples)?;
+
+        Ok(template)
+    }
+
+    /// Set whether substituted values are escaped (the default).
+    ///
+    /// Disable it only for values that are trusted BBCode, such as output of the
+    /// `preprocessing_helpers` builders.
+    ///
+    /// # Returns
+    ///
+    /// `Self` for method chaining (builder pattern).
+    pub fn with_value_escaping(mut self, escape: bool) -> Self {
+        self.escape_values = escape;
+        self
+    }
+
+    /// The template text this was built from.
+    pub fn source(&self) -> &str {
+        &self.source
+    }
+
+    /// Placeholder names in order of first appearance.
+    pub fn placeholders(&self) -> Vec<&str> {
+        let mut names: Vec<&str> = Vec::new();
+        for part in &self.parts {
+            if let TemplatePart::Placeholder(name) = part
+                && !names.contains(&name.as_str())
+            {
+                names.push(name);
+            }
+        }
+        names
+    }
+
+    /// Render the template, escaping each substituted value unless disabled.
+    ///
+    /// # Arguments
+    ///
+    /// * `values` - Values keyed by placeholder name; extra keys are ignored
+    ///
+    /// # Errors
+    ///
+    /// Returns `TemplateError::MissingValue` if a placeholder has no value, or
+    /// `TemplateError::TooLong` if the result exceeds `MAX_MESSAGE_LEN`.
+    pub fn render(&self, values: &HashMap<&str, String>) -> Result<String, TemplateError> {
+        let mut rendered = String::with_capacity(self.source.len());
+
+        for part in &self.parts {
+            match part {
+                TemplatePart::Literal(text) => rendered.push_str(text),
+                TemplatePart::Placeholder(name) => {
+                    let value = values
+                        .get(name.as_str())
+                        .ok_or_else(|| TemplateError::MissingValue(name.clone()))?;
+                    if self.escape_values {
+                        rendered.push_str(&helpers::escape_bbcode(&defuse_room_mentions(value)));
+                    } else {
+                        rendered.push_str(value);
+                    }
+                }
+            }
+        }
+
+        let actual = rendered.chars().count();
+        if actual > MAX_MESSAGE_LEN {
+            return Err(TemplateError::TooLong {
+                limit: MAX_MESSAGE_LEN,
+                actual,
+            });
+        }
+
+        Ok(rendered)
+    }
+
+    fn parse(template: &str) -> Result<Vec<TemplatePart>, TemplateError> {
+        let mut parts = Vec::new();
+        let mut literal = String::new();
+        let mut chars = template.char_indices().peekable();
+
+        while let Some((position, c)) = chars.next() {
+            match c {
+                '{' if chars.peek().map(|&(_, next)| next) == Some('{') => {
+                    chars.next();
+                    literal.push('{');
+                }
+                '}' if chars.peek().map(|&(_, next)| next) == Some('}') => {
+                    chars.next();
+                    literal.push('}');
+                }
+                '{' => {
+                    let rest = &template[position + 1..];
+                    let close = rest
+                        .find('}')
+                        .ok_or(TemplateError::UnclosedPlaceholder { position })?;
+                    let name = &rest[..close];
+                    if name.is_empty()
+                        || !name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+                    {
+                        return Err(TemplateError::InvalidPlaceholder {
+                            name: name.to_string(),
+                            position,
+                        });
+                    }
+
+                    if !literal.is_empty() {
+                        parts.push(TemplatePart::Literal(std::mem::take(&mut literal)));
+                    }
+                    parts.push(TemplatePart::Placeholder(name.to_string()));
+
+                    // Skip the name and the closing brace.
+                    for _ in 0..=name.chars().count() {
+                        chars.next();
+                    }
+                }
+                '}' => return Err(TemplateError::UnmatchedBrace { position }),
+                _ => literal.push(c),
+            }
+        }
+
+        if !literal.is_empty() {
+            parts.push(TemplatePart::Literal(literal));
+        }
+
+        Ok(parts)
+    }
+
+    fn check_tags(literal: &str) -> Result<(), TemplateError> {
+        let mut rest = literal;
+        while let Some(open) = rest.find('[') {
+            let escaped = rest[..open].ends_with('\\');
+            let after = &rest[open + 1..];
+            let Some(close) = after.find(']') else {
+                break;
+            };
+            let tag = &after[..close];
+
+            if !escaped && !MessagePreprocessor::is_steam_id_format(&rest[open..open + close + 2]) {
+                let name = bbcode::tag_name(tag);
+                if !GROUP_CHAT_TAGS.contains(&name.as_str()) {
+                    return Err(TemplateError::DisallowedTag(name));
+                }
+            }
+
+            rest = &after[close + 1..];
+        }
+        Ok(())
+    }
+}
+
+/// Fluent builder for messages that mix text with BBCode.
+///
+/// Plain text is escaped with `escape_bbcode`, so it never turns into markup, and mentions
+/// are kept apart from neighbouring text so Steam recognizes them. `build` checks the
+/// result against the length limit (`GROUP_CHAT_MESSAGE_LIMIT` by default), and `preview`
+/// returns the BBCode tree recipients will parse. Send the result with
+/// `SendGroupMessageParams::from_builder`, which keeps the escapes intact.
+///
+/// # Example
+///
+/// ```
+/// use SC_Sub_Poster::preprocessing::MessageBuilder;
+///
+/// let message = MessageBuilder::new()
+///     .text("Patch notes for [v2]: ")
+///     .url("https://kether.pl/patch", "read here")
+///     .newline()
+///     .spoiler("the boss dies")
+///     .build()
+///     .unwrap();
+/// assert_eq!(
+///     message,
+///     "Patch notes for \\[v2\\]: [url=https://kether.pl/patch]read here[/url]\n[spoiler]the boss dies[/spoiler]"
+/// );
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MessageBuilder {
+    message: String,
+    limit: usize,
+    after_mention: bool,
+}
+
+impl Default for MessageBuilder {
+    fn default() -> Self {
+        Self {
+            message: String::new(),
+            limit: GROUP_CHAT_MESSAGE_LIMIT,
+            after_mention: false,
+        }
+    }
+}
+
+impl MessageBuilder {
+    /// Create an empty builder limited to `GROUP_CHAT_MESSAGE_LIMIT`.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Set the length limit `build` enforces, e.g. `FRIEND_CHAT_MESSAGE_LIMIT`.
+    ///
+    /// # Arguments
+    ///
+    /// * `limit` - Maximum length in UTF-16 code units
+    ///
+    /// # Returns
+    ///
+    /// `Self` for method chaining (builder pattern).
+    pub fn with_length_limit(mut self, limit: usize) -> Self {
+        self.limit = limit;
+        self
+    }
+
+    /// Append plain text; BBCode in it is escaped and shown literally.
+    pub fn text(self, text: &str) -> Self {
+        self.push(&helpers::escape_bbcode(text))
+    }
+
+    /// Append a link.
+    ///
+    /// Whitespace, brackets and quotes in `href` are percent-encoded so the URL cannot
+    /// end the tag early; `label` is escaped like `text`.
+    pub fn url(self, href: &str, label: &str) -> Self {
+        let mut encoded = String::with_capacity(href.len());
+        for c in href.trim().chars() {
+            match c {
+                '[' => encoded.push_str("%5B"),
+                ']' => encoded.push_str("%5D"),
+                '"' => encoded.push_str("%22"),
+                c if c.is_whitespace() => encoded.push_str("%20"),
+                c => encoded.push(c),
+            }
+        }
+        self.push(&helpers::format_with_bbcode(
+            label,
+            BBCODE_TYPE_URL,
+            &encoded,
+        ))
+    }
+
+    /// Append a code block.
+    ///
+    /// Code is shown verbatim, so it is only escaped when it contains a `[/code]` closing
+    /// tag, which would otherwise end the block early.
+    pub fn code(self, code: &str) -> Self {
+        self.push(&helpers::format_with_bbcode(code, BBCODE_TYPE_CODE, ""))
+    }
+
+    /// Append text hidden behind a spoiler; the text is escaped like `text`.
+    pub fn spoiler(self, text: &str) -> Self {
+        self.push(&helpers::format_with_bbcode(text, BBCODE_TYPE_SPOILER, ""))
+    }
+
+    /// Append an emoticon such as `steamhappy`.
+    ///
+    /// Names that are empty or contain characters other than ASCII alphanumerics and `_`
+    /// cannot be an emoticon and are appended as escaped `:name:` text instead.
+    pub fn emoticon(self, name: &str) -> Self {
+        let valid = !name.is_empty() && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_');
+        if valid {
+            self.push(&helpers::format_with_bbcode_unescaped(
+                "",
+                BBCODE_TYPE_EMOTICON,
+                name,
+            ))
+        } else {
+            self.text(&format!(":{name}:"))
+        }
+    }
+
+    /// Append a mention of a user.
+    pub fn mention(self, steam_id: SteamID) -> Self {
+        self.push_mention(&helpers::create_mention(steam_id))
+    }
+
+    /// Append an `@all` mention.
+    pub fn mention_all(self) -> Self {
+        self.push_mention(MENTION_ALL)
+    }
+
+    /// Append a `[mention]` ping of a user showing `display_name`, as the Steam client
+    /// sends it (see `helpers::create_mention_tag`).
+    pub fn mention_tag(self, steam_id: SteamID, display_name: &str) -> Self {
+        self.push_mention(&helpers::create_mention_tag(steam_id, display_name))
+    }
+
+    /// Append a line break.
+    pub fn newline(self) -> Self {
+        self.push("\n")
+    }
+
+    /// The message built so far.
+    pub fn as_str(&self) -> &str {
+        &self.message
+    }
+
+    /// Parse the message built so far the way recipients will.
+    ///
+    /// Escaped text keeps its backslashes in the tree; `MessagePreprocessor::strip_bbcode`
+    /// renders it without them.
+    pub fn preview(&self) -> Vec<BBCodeContent> {
+        MessagePreprocessor::parse_bbcode(&self.message)
+    }
+
+    /// Finish the message.
+    ///
+    /// # Errors
+    ///
+    /// Returns a `LengthError` if the message exceeds the builder's length limit.
+    pub fn build(self) -> Result<String, LengthError> {
+        MessagePreprocessor::validate_length_within(&self.message, self.limit)?;
+        Ok(self.message)
+    }
+
+    fn push(mut self, fragment: &str) -> Self {
+        let starts_word = fragment
+            .chars()
+            .next()
+            .is_some_and(|c| !c.is_whitespace() && !MENTION_PUNCTUATION.contains(c));
+        if self.after_mention && starts_word {
+            self.message.push(' ');
+        }
+        self.message.push_str(fragment);
+        self.after_mention = false;
+        self
+    }
+
+    fn push_mention(mut self, mention: &str) -> Self {
+        if self
+            .message
+            .chars()
+            .next_back()
+            .is_some_and(|c| !c.is_whitespace())
+        {
+            self.message.push(' ');
+        }
+        self.message.push_str(mention);
+        self.after_mention = true;
+        self
+    }
+}
+
+mod bbcode {
+    use super::{
+        BBCODE_TYPE_CODE, BBCODE_TYPE_EMOTICON, BBCODE_TYPE_PRE, BBCODE_TYPE_STICKER,
+        BBCodeContent, BBCodeDiagnostic, BBCodeDiagnosticKind, BBCodeError, BBCodeNode,
+        MalformedTagPolicy, ParseLimits,
+    };
+    use std::collections::HashMap;
+    use std::ops::Range;
+
+    /// Tags that wrap content and must be closed explicitly.
+    const CONTAINER_TAGS: &[&str] = &["url", "spoiler", "code", "pre", "quote", "noembed"];
+
+    /// Tags whose content is shown literally, so nested brackets are not markup.
+    const LITERAL_TAGS: &[&str] = &[BBCODE_TYPE_CODE, BBCODE_TYPE_PRE];
+
+    /// Tags that accept the `[tag:value]` form.
+    const COLON_FORM_TAGS: &[&str] = &[BBCODE_TYPE_EMOTICON, BBCODE_TYPE_STICKER];
+
+    /// The lowercase name of a tag, given with or without its brackets (`[/url]`, `url=x`).
+    pub fn tag_name(tag: &str) -> String {
+        tag.trim_start_matches('[')
+            .trim_start()
+            .trim_start_matches('/')
+            .trim_start()
+            .split(|c: char| c == '=' || c == ':' || c == ']' || c.is_whitespace())
+            .next()
+            .unwrap_or_default()
+            .to_ascii_lowercase()
+    }
+
+    /// Check `message`, failing with the first problem `diagnose` finds.
+    pub fn validate<T: AsRef<str>>(message: &str, allowed_tags: &[T]) -> Result<(), BBCodeError> {
+        match diagnose(message, allowed_tags).into_iter().next() {
+            Some(diagnostic) => Err(diagnostic.into()),
+            None => Ok(()),
+        }
+    }
+
+    /// Find every BBCode problem in `message`, in message order.
+    ///
+    /// Scanning carries on past each problem: an unknown tag is skipped, an allowed tag
+    /// with bad attributes still counts as open, and the tags left unclosed by a closing
+    /// tag are reported where they were opened.
+    pub fn diagnose<T: AsRef<str>>(message: &str, allowed_tags: &[T]) -> Vec<BBCodeDiagnostic> {
+        let mut diagnostics = Vec::new();
+        let mut report = |byte_range: Range<usize>, kind: BBCodeDiagnosticKind, detail: String| {
+            diagnostics.push(BBCodeDiagnostic {
+                byte_range,
+                kind,
+                detail,
+            })
+        };
+        let mut open: Vec<(&str, Range<usize>)> = Vec::new();
+        // How many tags of each name are open, so a stray closer is found without
+        // searching the whole stack.
+        let mut open_counts: HashMap<&str, usize> = HashMap::new();
+        let mut offset = 0;
+        // Brackets inside `[code]` are skipped one at a time; they all share the next `]`,
+        // which is only searched for once.
+        let mut next_close = 0;
+
+        while let Some(found) = message[offset..].find('[') {
+            let position = offset + found;
+            offset = position + 1;
+
+            if message[..position].ends_with('\\') {
+                continue;
+            }
+
+            if next_close <= position {
+                let Some(len) = message[position..].find(']') else {
+                    report(
+                        position..message.len(),
+                        BBCodeDiagnosticKind::UnclosedTag,
+                        "unterminated tag".to_string(),
+                    );
+                    break;
+                };
+                next_close = position + len;
+            }
+            let tag = &message[position + 1..next_close];
+            let next = next_close + 1;
+            let span = position..next;
+
+            if super::MessagePreprocessor::is_steam_id_format(&message[span.clone()]) {
+                offset = next;
+                continue;
+            }
+
+            if let Some((literal, _)) = open.last()
+                && LITERAL_TAGS.contains(literal)
+                && !tag
+                    .trim()
+                    .strip_prefix('/')
+                    .is_some_and(|name| name.trim().eq_ignore_ascii_case(literal))
+            {
+                continue;
+            }
+
+            offset = next;
+
+            if let Some(name) = tag.strip_prefix('/') {
+                let name = name.trim();
+                let Some(allowed) = find_allowed(allowed_tags, name) else {
+                    report(
+                        span,
+                        BBCodeDiagnosticKind::UnknownTag,
+                        format!("unsupported tag [/{}]", name),
+                    );
+                    continue;
+                };
+                if open_counts.get(allowed).copied().unwrap_or_default() == 0 {
+                    report(
+                        span,
+                        BBCodeDiagnosticKind::StrayCloser,
+                        format!("[/{}] has no opening tag", name),
+                    );
+                    continue;
+                }
+                let index = open
+                    .iter()
+                    .rposition(|(opened, _)| *opened == allowed)
+                    .expect("counted tag is open");
+                for (unclosed, at) in open.drain(index..).skip(1) {
+                    if CONTAINER_TAGS.contains(&unclosed) {
+                        report(
+                            at,
+                            BBCodeDiagnosticKind::UnclosedTag,
+                            format!("[{}] is not closed", unclosed),
+                        );
+                    }
+                    *open_counts.entry(unclosed).or_default() -= 1;
+                }
+                *open_counts.entry(allowed).or_default() -= 1;
+                continue;
+            }
+
+            let tag = tag.trim();
+            let (name, rest) = match colon_form(tag) {
+                Some((name, value)) => (name, value),
+                None => {
+                    let name_end = tag
+                        .find(|c: char| c == '=' || c.is_whitespace())
+                        .unwrap_or(tag.len());
+                    tag.split_at(name_end)
+                }
+            };
+
+            let Some(allowed) = find_allowed(allowed_tags, name) else {
+                report(
+                    span,
+                    BBCodeDiagnosticKind::UnknownTag,
+                    format!("unsupported tag [{}]", name),
+                );
+                continue;
+            };
+            let attributes = match colon_form(tag) {
+                Some((_, "")) => Err("missing attribute value".to_string()),
+                Some(_) => Ok(()),
+                None => validate_attributes(rest),
+            };
+            if let Err(reason) = attributes {
+                report(span.clone(), BBCodeDiagnosticKind::BadAttribute, reason);
+            }
+            *open_counts.entry(allowed).or_default() += 1;
+            open.push((allowed, span));
+        }
+
+        for (name, at) in open {
+            if CONTAINER_TAGS.contains(&name) {
+                report(
+                    at,
+                    BBCodeDiagnosticKind::UnclosedTag,
+                    format!("[{}] is not closed", name),
+                );
+            }
+        }
+        diagnostics.sort_by_key(|diagnostic| diagnostic.byte_range.start);
+        diagnostics
+    }
+
+    /// The allowed tag `name` refers to, in the spelling it was allowed with.
+    fn find_allowed<'t, T: AsRef<str>>(allowed_tags: &'t [T], name: &str) -> Option<&'t str> {
+        allowed_tags
+            .iter()
+            .map(AsRef::as_ref)
+            .find(|allowed| allowed.eq_ignore_ascii_case(name))
+    }
+
+    /// Split the `[emoticon:name]` and `[sticker:name]` forms into tag name and value.
+    ///
+    /// Steam and `format_with_bbcode` write emoticons this way; the value is stored
+    /// under `"name"`.
+    fn colon_form(tag_content: &str) -> Option<(&str, &str)> {
+        let (name, value) = tag_content.split_once(':')?;
+        COLON_FORM_TAGS
+            .iter()
+            .any(|tag| tag.eq_ignore_ascii_case(name))
+            .then(|| (name, value.trim()))
+    }
+
+    fn validate_attributes(rest: &str) -> Result<(), String> {
+        tokenize_attributes(rest, &mut Vec::new())
+    }
+
+    /// Split the text after a tag name into attribute pairs.
+    ///
+    /// Handles the `[tag=value]` shorthand (stored under `"value"`), `key="value"`,
+    /// `key='value'` and unquoted `key=value` pairs, and bare `key` flags (stored with
+    /// an empty value). An unquoted shorthand value runs to the end of the tag, so
+    /// `[quote=Some Name]` keeps its spaces; a quoted one may be followed by more pairs.
+    ///
+    /// Pairs are appended to `attrs` as they are read, so on error `attrs` holds
+    /// everything before the offending token.
+    fn tokenize_attributes(rest: &str, attrs: &mut Vec<(String, String)>) -> Result<(), String> {
+        let mut remaining = rest.trim();
+
+        if let Some(shorthand) = remaining.strip_prefix('=') {
+            let shorthand = shorthand.trim_start();
+            if !shorthand.starts_with(['"', '\'']) {
+                if shorthand.is_empty() {
+                    return Err("missing attribute value".to_string());
+                }
+                attrs.push(("value".to_string(), shorthand.to_string()));
+                return Ok(());
+            }
+            let (value, after) = read_value("value", shorthand)?;
+            attrs.push(("value".to_string(), value.to_string()));
+            remaining = after.trim_start();
+        }
+
+        while !remaining.is_empty() {
+            let key_end = remaining
+                .find(|c: char| c == '=' || c.is_whitespace())
+                .unwrap_or(remaining.len());
+            let key = &remaining[..key_end];
+            if key.is_empty() || key.contains(['"', '\'']) {
+                return Err(format!("malformed attribute {:?}", remaining));
+            }
+
+            let after_key = remaining[key_end..].trim_start();
+            let Some(value) = after_key.strip_prefix('=') else {
+                attrs.push((key.to_string(), String::new()));
+                remaining = after_key;
+                continue;
+            };
+
+            let (value, after) = read_value(key, value.trim_start())?;
+            attrs.push((key.to_string(), value.to_string()));
+            remaining = after.trim_start();
+        }
+
+        Ok(())
+    }
+
+    /// Read one attribute value, quoted or running to the next whitespace.
+    fn read_value<'t>(key: &str, text: &'t str) -> Result<(&'t str, &'t str), String> {
+        if let Some(quote) = text.chars().next().filter(|c| *c == '"' || *c == '\'') {
+            let quoted = &text[1..];
+            let close = quoted
+                .find(quote)
+                .ok_or_else(|| format!("unterminated value for attribute {:?}", key))?;
+            return Ok((&quoted[..close], &quoted[close + 1..]));
+        }
+
+        let end = text.find(char::is_whitespace).unwrap_or(text.len());
+        if end == 0 {
+            return Err(format!("missing value for attribute {:?}", key));
+        }
+        Ok(text.split_at(end))
+    }
+
+    /// Find the closing tag of a `[code]` or `[pre]` block in the text after its opener.
+    ///
+    /// Nothing inside these blocks is markup, so the block ends at the first matching
+    /// closer. Returns the length of the body and of the closer, or `None` if `tag` is
+    /// not literal or is never closed.
+    pub(super) fn literal_end(text: &str, tag: &str) -> Option<(usize, usize)> {
+        if !LITERAL_TAGS.contains(&tag) {
+            return None;
+        }
+        let mut close = 0;
+        for (start, _) in text.match_indices('[') {
+            if close <= start {
+                close = start + text[start..].find(']')?;
+            }
+            let is_closer = text[start + 1..close]
+                .trim()
+                .strip_prefix('/')
+                .is_some_and(|name| name.trim().eq_ignore_ascii_case(tag));
+            if is_closer {
+                return Some((start, close - start + 1));
+            }
+        }
+        None
+    }
+
+    /// Write parsed content back as BBCode; see `MessagePreprocessor::render_bbcode`.
+    pub fn render(parsed: &[BBCodeContent], out: &mut String) {
+        for content in parsed {
+            match content {
+                BBCodeContent::String(text) => out.push_str(text),
+                BBCodeContent::Node(node) => {
+                    out.push('[');
+                    out.push_str(&node.tag);
+                    push_attributes(node, out);
+                    out.push(']');
+                    if let Some(children) = &node.content {
+                        render(children, out);
+                        out.push_str("[/");
+                        out.push_str(&node.tag);
+                        out.push(']');
+                    }
+                }
+            }
+        }
+    }
+
+    /// Write the attributes of `node` in the form `parse_tag` reads back.
+    fn push_attributes(node: &BBCodeNode, out: &mut String) {
+        if COLON_FORM_TAGS.contains(&node.tag.as_str())
+            && node.attrs.len() == 1
+            && let Some(name) = node.attrs.get("name")
+            && !name.is_empty()
+            && name.trim() == name
+        {
+            out.push(':');
+            out.push_str(name);
+            return;
+        }
+
+        // An unquoted shorthand runs to the end of the tag, so next to other attributes
+        // it must be quoted; a value holding both quote characters stays a plain pair.
+        let alone = node.attrs.len() == 1;
+        let shorthand = node
+            .attrs
+            .get("value")
+            .filter(|value| alone || !(value.contains('"') && value.contains('\'')));
+        if let Some(value) = shorthand {
+            out.push('=');
+            if alone
+                && !value.is_empty()
+                && value.trim() == value
+                && !value.starts_with(['"', '\''])
+            {
+                out.push_str(value);
+                return;
+            }
+            push_quoted(value, out);
+        }
+
+        let mut attrs: Vec<_> = node
+            .attrs
+            .iter()
+            .filter(|(key, _)| shorthand.is_none() || *key != "value")
+            .collect();
+        attrs.sort();
+        for (key, value) in attrs {
+            out.push(' ');
+            out.push_str(key);
+            if value.is_empty() {
+                continue;
+            }
+            out.push('=');
+            if value.contains(char::is_whitespace) || value.starts_with(['"', '\'']) {
+                push_quoted(value, out);
+            } else {
+                out.push_str(value);
+            }
+        }
+    }
+
+    /// Write `value` in whichever quotes it does not contain.
+    fn push_quoted(value: &str, out: &mut String) {
+        let quote = if value.contains('"') { '\'' } else { '"' };
+        out.push(quote);
+        out.push_str(value);
+        out.push(quote);
+    }
+
+    /// Append `text` to `target`, merging it into a trailing text segment.
+    fn push_text(target: &mut Vec<BBCodeContent>, text: &str) {
+        if text.is_empty() {
+            return;
+        }
+        match target.last_mut() {
+            Some(BBCodeContent::String(last)) => last.push_str(text),
+            _ => target.push(BBCodeContent::String(text.to_string())),
+        }
+    }
+
+    pub mod formatting {
+        use super::super::{
+            BBCODE_TYPE_CODE, BBCODE_TYPE_EMOTICON, BBCODE_TYPE_FLIP, BBCODE_TYPE_IMG,
+            BBCODE_TYPE_PRE, BBCODE_TYPE_QUOTE, BBCODE_TYPE_RANDOM, BBCODE_TYPE_SPOILER,
+            BBCODE_TYPE_URL, ChatCommand, FormatError,
+        };
+
+        /// Trait for formatting messages with BBCode
+        trait BBCodeFormatter {
+            /// Format `message`, or return `None` if `value` is not valid for the tag.
+            fn format(&self, message: &str, value: &str) -> Option<String>;
+        }
+
+        struct SpoilerFormatter;
+        struct CodeFormatter;
+        struct PreFormatter;
+        struct UrlFormatter;
+        struct EmoticonFormatter;
+        struct QuoteFormatter;
+        struct ImgFormatter;
+        struct RandomFormatter;
+        struct FlipFormatter;
+
+        /// Whether `value` can be written as an unquoted `[tag=value]` attribute.
+        fn is_plain_value(value: &str) -> bool {
+            !value.contains(['[', ']', '"', '\n'])
+        }
+
+        impl BBCodeFormatter for SpoilerFormatter {
+            fn format(&self, message: &str, _value: &str) -> Option<String> {
+                Some(format!("[spoiler]{}[/spoiler]", message))
+            }
+        }
+
+        impl BBCodeFormatter for CodeFormatter {
+            fn format(&self, message: &str, _value: &str) -> Option<String> {
+                Some(format!("[code]{}[/code]", message))
+            }
+        }
+
+        impl BBCodeFormatter for PreFormatter {
+            fn format(&self, message: &str, _value: &str) -> Option<String> {
+                Some(format!("[pre]{}[/pre]", message))
+            }
+        }
+
+        impl BBCodeFormatter for UrlFormatter {
+            fn format(&self, message: &str, value: &str) -> Option<String> {
+                (!value.is_empty() && is_plain_value(value))
+                    .then(|| format!("[url={}]{}[/url]", value, message))
+            }
+        }
+
+        impl BBCodeFormatter for EmoticonFormatter {
+            fn format(&self, _message: &str, value: &str) -> Option<String> {
+                (!value.is_empty() && is_plain_value(value))
+                    .then(|| format!("[emoticon:{}]", value))
+            }
+        }
+
+        impl BBCodeFormatter for QuoteFormatter {
+            fn format(&self, message: &str, value: &str) -> Option<String> {
+                if value.is_empty() {
+                    Some(format!("[quote]{}[/quote]", message))
+                } else {
+                    is_plain_value(value).then(|| format!("[quote={}]{}[/quote]", value, message))
+                }
+            }
+        }
+
+        impl BBCodeFormatter for ImgFormatter {
+            fn format(&self, message: &str, _value: &str) -> Option<String> {
+                Some(format!("[img]{}[/img]", message))
+            }
+        }
+
+        impl BBCodeFormatter for RandomFormatter {
+            fn format(&self, _message: &str, value: &str) -> Option<String> {
+                let Some(ChatCommand::Random { min, max }) =
+                    ChatCommand::parse(&format!("/random {}", value))
+                else {
+                    return None;
+                };
+                Some(format!("[random min=\"{}\" max=\"{}\"][/random]", min, max))
+            }
+        }
+
+        impl BBCodeFormatter for FlipFormatter {
+            fn format(&self, _message: &str, _value: &str) -> Option<String> {
+                Some("[flip][/flip]".to_string())
+            }
+        }
+
+        /// Get the appropriate formatter for a BBCode type
+        fn get_formatter(bbcode_type: &str) -> Option<Box<dyn BBCodeFormatter>> {
+            let formatter: Box<dyn BBCodeFormatter> = match bbcode_type {
+                BBCODE_TYPE_SPOILER => Box::new(SpoilerFormatter),
+                BBCODE_TYPE_CODE => Box::new(CodeFormatter),
+                BBCODE_TYPE_PRE => Box::new(PreFormatter),
+                BBCODE_TYPE_URL => Box::new(UrlFormatter),
+                BBCODE_TYPE_EMOTICON => Box::new(EmoticonFormatter),
+                BBCODE_TYPE_QUOTE => Box::new(QuoteFormatter),
+                BBCODE_TYPE_IMG => Box::new(ImgFormatter),
+                BBCODE_TYPE_RANDOM => Box::new(RandomFormatter),
+                BBCODE_TYPE_FLIP => Box::new(FlipFormatter),
+                _ => return None,
+            };
+            Some(formatter)
+        }
+
+        /// Format a message with BBCode using the strategy pattern
+        pub fn format_with_bbcode(
+            message: &str,
+            bbcode_type: &str,
+            value: &str,
+        ) -> Result<String, FormatError> {
+            let formatter = get_formatter(bbcode_type)
+                .ok_or_else(|| FormatError::UnsupportedType(bbcode_type.to_string()))?;
+            formatter
+                .format(message, value)
+                .ok_or_else(|| FormatError::InvalidValue {
+                    bbcode_type: bbcode_type.to_string(),
+                    value: value.to_string(),
+                })
+        }
+    }
+
+    pub struct Parser<'a, T> {
+        allowed_tags: &'a [T],
+        policy: MalformedTagPolicy,
+        limits: ParseLimits,
+    }
+
+    /// A bracketed span found by `Parser::next_tag`.
+    ///
+    /// All offsets are byte offsets into the scanned text. They come from `find` on
+    /// the ASCII brackets, so they always fall on `char` boundaries.
+    struct TagSpan {
+        /// Offset of the opening `[`.
+        open: usize,
+        /// Offset of the closing `]`.
+        close: usize,
+    }
+
+    /// A tag waiting for its closing tag.
+    struct OpenTag<'m> {
+        node: BBCodeNode,
+        children: Vec<BBCodeContent>,
+        /// The tag as written, restored when the policy keeps it as text.
+        raw: &'m str,
+        /// Byte offset of the tag's `[` in the message.
+        position: usize,
+    }
+
+    impl<'a, T: AsRef<str>> Parser<'a, T> {
+        pub fn new(allowed_tags: &'a [T]) -> Self {
+            Self {
+                allowed_tags,
+                policy: MalformedTagPolicy::default(),
+                limits: ParseLimits::default(),
+            }
+        }
+
+        /// Handle malformed tags according to `policy`.
+        pub fn with_policy(mut self, policy: MalformedTagPolicy) -> Self {
+            self.policy = policy;
+            self
+        }
+
+        /// Stop building structure at `limits`.
+        pub fn with_limits(mut self, limits: ParseLimits) -> Self {
+            self.limits = limits;
+            self
+        }
+
+        /// Parse `message` into text and nodes, dropping the limit flag.
+        #[cfg(test)]
+        pub fn parse(&self, message: &str) -> Vec<BBCodeContent> {
+            self.parse_limited(message).0
+        }
+
+        /// Parse `message` into text and nodes, also telling whether a limit was reached.
+        ///
+        /// An allowed tag followed by its closing tag becomes a node whose `content`
+        /// holds everything in between. Tags that don't need closing (`[emoticon]`,
+        /// `[img]`, ...) stay leaf nodes (`content: None`) when left open. `[code]` and
+        /// `[pre]` are literal: everything up to their closing tag becomes a single text
+        /// child. Malformed tags are handled by the policy; with
+        /// `MalformedTagPolicy::Error` they are kept as text here, use `try_parse` to get
+        /// the error instead.
+        pub fn parse_limited(&self, message: &str) -> (Vec<BBCodeContent>, bool) {
+            match self.try_parse_limited(message) {
+                Ok(parsed) => parsed,
+                Err(_) => Parser::new(self.allowed_tags)
+                    .with_policy(MalformedTagPolicy::KeepAsText)
+                    .with_limits(self.limits)
+                    .parse_limited(message),
+            }
+        }
+
+        /// Parse `message`, failing on the first malformed tag under
+        /// `MalformedTagPolicy::Error`.
+        ///
+        /// Malformed tags are container tags left unclosed, closing tags of allowed
+        /// names without an opener, empty `[]`, and a `[` starting an allowed tag that
+        /// has no `]`.
+        pub fn try_parse(&self, message: &str) -> Result<Vec<BBCodeContent>, BBCodeError> {
+            self.try_parse_limited(message).map(|(parsed, _)| parsed)
+        }
+
+        /// Parse `message` like `try_parse`, also telling whether a limit was reached.
+        ///
+        /// Once a tag ends past `max_input_bytes` or would be node number
+        /// `max_nodes + 1`, it and the rest of the message are kept as text, and so are
+        /// the tags still open at that point, whatever the policy.
+        fn try_parse_limited(
+            &self,
+            message: &str,
+        ) -> Result<(Vec<BBCodeContent>, bool), BBCodeError> {
+            if message.is_empty() {
+                return Ok((vec![BBCodeContent::String(message.to_string())], false));
+            }
+
+            let mut root = Vec::new();
+            let mut open: Vec<OpenTag> = Vec::new();
+            let mut rest = message;
+            // Literal tags found to have no closer in the rest of the message; later
+            // openers of the same tag are not searched again.
+            let mut unclosed_literals: Vec<String> = Vec::new();
+            let mut nodes = 0;
+            let mut limits_hit = message.len() > self.limits.max_input_bytes;
+            let mut truncated = false;
+
+            while let Some(TagSpan { open: start, close }) = Self::next_tag(rest) {
+                let position = message.len() - rest.len() + start;
+                if position + close - start >= self.limits.max_input_bytes {
+                    truncated = true;
+                    break;
+                }
+                let raw = &rest[start..=close];
+                let target = open.last_mut().map_or(&mut root, |tag| &mut tag.children);
+                push_text(target, &rest[..start]);
+
+                let tag_content = rest[start + 1..close].trim();
+                if let Some(name) = tag_content.strip_prefix('/') {
+                    let name = name.trim();
+                    match open
+                        .iter()
+                        .rposition(|tag| tag.node.tag.eq_ignore_ascii_case(name))
+                    {
+                        Some(index) => {
+                            while open.len() > index + 1 {
+                                self.close_unterminated(&mut open, &mut root, false)?;
+                            }
+                            let mut tag = open.pop().expect("matched tag is open");
+                            tag.node.content = Some(tag.children);
+                            let target = open.last_mut().map_or(&mut root, |tag| &mut tag.children);
+                            target.push(BBCodeContent::Node(tag.node));
+                        }
+                        None if self.is_allowed(name) => {
+                            let target = open.last_mut().map_or(&mut root, |tag| &mut tag.children);
+                            self.malformed(target, raw, position, || {
+                                format!("[/{}] has no opening tag", name)
+                            })?;
+                        }
+                        None => {
+                            let target = open.last_mut().map_or(&mut root, |tag| &mut tag.children);
+                            push_text(target, raw);
+                        }
+                    }
+                } else if tag_content.is_empty() {
+                    let target = open.last_mut().map_or(&mut root, |tag| &mut tag.children);
+                    self.malformed(target, raw, position, || "empty tag".to_string())?;
+                } else if let Some(mut node) = self.parse_tag(tag_content) {
+                    if open.len() >= self.limits.max_depth {
+                        limits_hit = true;
+                        let target = open.last_mut().map_or(&mut root, |tag| &mut tag.children);
+                        push_text(target, raw);
+                        rest = &rest[close + 1..];
+                        continue;
+                    }
+                    let after = &rest[close + 1..];
+                    let literal = if unclosed_literals.contains(&node.tag) {
+                        None
+                    } else {
+                        literal_end(after, &node.tag)
+                    };
+                    let end = position
+                        + raw.len()
+                        + literal.map_or(0, |(body_len, closer_len)| body_len + closer_len);
+                    if nodes >= self.limits.max_nodes || end > self.limits.max_input_bytes {
+                        rest = &rest[start..];
+                        truncated = true;
+                        break;
+                    }
+                    nodes += 1;
+                    match literal {
+                        Some((body_len, closer_len)) => {
+                            let mut body = Vec::new();
+                            push_text(&mut body, &after[..body_len]);
+                            node.content = Some(body);
+                            let target = open.last_mut().map_or(&mut root, |tag| &mut tag.children);
+                            target.push(BBCodeContent::Node(node));
+                            rest = &after[body_len + closer_len..];
+                            continue;
+                        }
+                        None => {
+                            if LITERAL_TAGS.contains(&node.tag.as_str()) {
+                                unclosed_literals.push(node.tag.clone());
+                            }
+                            open.push(OpenTag {
+                                node,
+                                children: Vec::new(),
+                                raw,
+                                position,
+                            });
+                        }
+                    }
+                } else {
+                    let target = open.last_mut().map_or(&mut root, |tag| &mut tag.children);
+                    push_text(target, raw);
+                }
+
+                rest = &rest[close + 1..];
+            }
+
+            if truncated {
+                while !open.is_empty() {
+                    self.close_unterminated(&mut open, &mut root, true)?;
+                }
+                push_text(&mut root, rest);
+                return Ok((root, true));
+            }
+
+            let target = open.last_mut().map_or(&mut root, |tag| &mut tag.children);
+            match self.unterminated_tag(rest) {
+                Some(start) => {
+                    push_text(target, &rest[..start]);
+                    let position = message.len() - rest.len() + start;
+                    self.malformed(target, &rest[start..], position, || {
+                        "unterminated tag".to_string()
+                    })?;
+                }
+                None => push_text(target, rest),
+            }
+            while !open.is_empty() {
+                self.close_unterminated(&mut open, &mut root, false)?;
+            }
+
+            Ok((root, limits_hit))
+        }
+
+        /// Pop the innermost open tag, moving its children after it.
+        ///
+        /// Tags that don't need closing become leaf nodes; unclosed container tags are
+        /// malformed and handled by the policy, or kept as text when `truncated`, since
+        /// their closing tag may be in the part of the message left unparsed.
+        fn close_unterminated(
+            &self,
+            open: &mut Vec<OpenTag>,
+            root: &mut Vec<BBCodeContent>,
+            truncated: bool,
+        ) -> Result<(), BBCodeError> {
+            let Some(tag) = open.pop() else {
+                return Ok(());
+            };
+            let target = open.last_mut().map_or(root, |tag| &mut tag.children);
+            if truncated && CONTAINER_TAGS.contains(&tag.node.tag.as_str()) {
+                push_text(target, tag.raw);
+            } else if CONTAINER_TAGS.contains(&tag.node.tag.as_str()) {
+                self.malformed(target, tag.raw, tag.position, || {
+                    format!("[{}] is not closed", tag.node.tag)
+                })?;
+            } else {
+                target.push(BBCodeContent::Node(tag.node));
+            }
+            for child in tag.children {
+                match child {
+                    BBCodeContent::String(text) => push_text(target, &text),
+                    node => target.push(node),
+                }
+            }
+            Ok(())
+        }
+
+        /// Apply the policy to a malformed tag written as `raw` at `position`.
+        fn malformed(
+            &self,
+            target: &mut Vec<BBCodeContent>,
+            raw: &str,
+            position: usize,
+            reason: impl FnOnce() -> String,
+        ) -> Result<(), BBCodeError> {
+            match self.policy {
+                MalformedTagPolicy::KeepAsText => push_text(target, raw),
+                MalformedTagPolicy::Drop => {}
+                MalformedTagPolicy::Error => {
+                    return Err(BBCodeError {
+                        position,
+                        reason: reason(),
+                    });
+                }
+            }
+            Ok(())
+        }
+
+        /// Locate the first unescaped `[` that has a later `]`.
+        ///
+        /// A `[` preceded by a backslash (see `escape_bbcode`) is text, as in the Steam
+        /// client.
+        fn next_tag(text: &str) -> Option<TagSpan> {
+            let open = Self::unescaped_brackets(text).next()?;
+            let close = open + text[open..].find(']')?;
+            Some(TagSpan { open, close })
+        }
+
+        /// Find a `[` in `text` that starts an allowed tag but is never closed by `]`.
+        ///
+        /// Only called on the text after the last complete tag, so no `[` in it has a
+        /// matching `]`. Brackets that don't start an allowed tag are ordinary text.
+        fn unterminated_tag(&self, text: &str) -> Option<usize> {
+            // A name longer than every allowed tag is never allowed, so the search for
+            // its end can stop there instead of running through the rest of the text.
+            let longest = self
+                .allowed_tags
+                .iter()
+                .map(|allowed| allowed.as_ref().chars().count())
+                .max()
+                .unwrap_or_default();
+            Self::unescaped_brackets(text).find(|&index| {
+                let tag = text[index + 1..].trim_start();
+                let tag = tag.strip_prefix('/').unwrap_or(tag).trim_start();
+                let name_end = tag
+                    .char_indices()
+                    .take(longest + 1)
+                    .find(|&(_, c)| c == '=' || c.is_whitespace())
+                    .map_or(tag.len(), |(end, _)| end);
+                self.is_allowed(&tag[..name_end])
+            })
+        }
+
+        /// Offsets of the `[` characters in `text` not preceded by a backslash.
+        fn unescaped_brackets(text: &str) -> impl Iterator<Item = usize> {
+            text.match_indices('[')
+                .map(|(index, _)| index)
+                .filter(|&index| !text[..index].ends_with('\\'))
+        }
+
+        fn is_allowed(&self, tag_name: &str) -> bool {
+            self.allowed_tags
+                .iter()
+                .any(|allowed| allowed.as_ref().eq_ignore_ascii_case(tag_name))
+        }
+
+        fn parse_tag(&self, tag_content: &str) -> Option<BBCodeNode> {
+            if let Some((tag_name, value)) = colon_form(tag_content) {
+                return (self.is_allowed(tag_name) && !value.is_empty()).then(|| BBCodeNode {
+                    tag: tag_name.to_ascii_lowercase(),
+                    attrs: HashMap::from([("name".to_string(), value.to_string())]),
+                    content: None,
+                });
+            }
+
+            let name_end = tag_content
+                .find(|c: char| c == '=' || c.is_whitespace())
+                .unwrap_or(tag_content.len());
+            let (tag_name, rest) = tag_content.split_at(name_end);
+
+            if !self.is_allowed(tag_name) {
+                return None;
+            }
+
+            let attrs = Self::extract_tag_attributes(rest);
+
+            Some(BBCodeNode {
+                tag: tag_name.to_ascii_lowercase(),
+                attrs,
+                content: None,
+            })
+        }
+
+        /// Extract attributes following the tag name.
+        ///
+        /// See `tokenize_attributes` for the accepted forms. Malformed trailing
+        /// attributes are dropped; the pairs before them are kept.
+        fn extract_tag_attributes(rest: &str) -> HashMap<String, String> {
+            let mut attrs = Vec::new();
+            let _ = tokenize_attributes(rest, &mut attrs);
+            attrs.into_iter().collect()
+        }
+    }
+}
+
+/// HTML rendering of parsed BBCode; see `MessagePreprocessor::render_html`.
+mod html {
+    use super::{
+        BBCODE_TYPE_CODE, BBCODE_TYPE_EMOTICON, BBCODE_TYPE_IMG, BBCODE_TYPE_PRE,
+        BBCODE_TYPE_QUOTE, BBCODE_TYPE_SPOILER, BBCODE_TYPE_URL, BBCodeContent, BBCodeNode,
+        ImageRef, MessagePreprocessor, helpers, is_web_url,
+    };
+
+    pub fn render(parsed: &[BBCodeContent], out: &mut String) {
+        render_in(parsed, false, out);
+    }
+
+    /// Render `parsed`; inside `<pre>` (`preformatted`) newlines are kept as they are.
+    fn render_in(parsed: &[BBCodeContent], preformatted: bool, out: &mut String) {
+        for content in parsed {
+            match content {
+                BBCodeContent::String(text) => {
+                    push_text(&helpers::unescape_bbcode(text), preformatted, out)
+                }
+                BBCodeContent::Node(node) => render_node(node, preformatted, out),
+            }
+        }
+    }
+
+    fn render_node(node: &BBCodeNode, preformatted: bool, out: &mut String) {
+        let children = node.content.as_deref().unwrap_or_default();
+        let wrap = |out: &mut String, open: &str, close: &str| {
+            out.push_str(open);
+            render_in(
+                children,
+                preformatted || matches!(node.tag.as_str(), BBCODE_TYPE_CODE | BBCODE_TYPE_PRE),
+                out,
+            );
+            out.push_str(close);
+        };
+        match node.tag.as_str() {
+            BBCODE_TYPE_SPOILER => wrap(out, "<span class=\"spoiler\">", "</span>"),
+            BBCODE_TYPE_QUOTE => wrap(out, "<blockquote>", "</blockquote>"),
+            BBCODE_TYPE_CODE => wrap(out, "<pre><code>", "</code></pre>"),
+            BBCODE_TYPE_PRE => wrap(out, "<pre>", "</pre>"),
+            BBCODE_TYPE_EMOTICON => {
+                if let Some(name) = MessagePreprocessor::emoticon_name(node) {
+                    push_escaped(&format!(":{}:", name), out);
+                }
+            }
+            BBCODE_TYPE_IMG => match ImageRef::from_node(node) {
+                Some(image) if image.is_valid() => {
+                    out.push_str("<img src=\"");
+                    push_escaped(&image.url, out);
+                    out.push_str("\" alt=\"\">");
+                }
+                Some(image) => push_escaped(&image.url, out),
+                None => {}
+            },
+            BBCODE_TYPE_URL => {
+                let href = node.attrs.get("value").map(|url| url.trim());
+                let text = MessagePreprocessor::render_plain_text(children);
+                let href = href.unwrap_or(&text);
+                if !is_web_url(href) {
+                    wrap(out, "", "");
+                    return;
+                }
+                out.push_str("<a href=\"");
+                push_escaped(href, out);
+                out.push_str("\">");
+                if children.is_empty() {
+                    push_escaped(href, out);
+                } else {
+                    wrap(out, "", "");
+                }
+                out.push_str("</a>");
+            }
+            _ => wrap(out, "", ""),
+        }
+    }
+
+    /// Write message text, turning newlines into `<br>` outside preformatted blocks.
+    fn push_text(text: &str, preformatted: bool, out: &mut String) {
+        if preformatted {
+            push_escaped(text, out);
+            return;
+        }
+        for (index, line) in text.split('\n').enumerate() {
+            if index > 0 {
+                out.push_str("<br>");
+            }
+            push_escaped(line, out);
+        }
+    }
+
+    fn push_escaped(text: &str, out: &mut String) {
+        for c in text.chars() {
+            match c {
+                '&' => out.push_str("&amp;"),
+                '<' => out.push_str("&lt;"),
+                '>' => out.push_str("&gt;"),
+                '"' => out.push_str("&quot;"),
+                '\'' => out.push_str("&#39;"),
+                c => out.push(c),
+            }
+        }
+    }
+}
+
+/// Markdown rendering of parsed BBCode; see `MessagePreprocessor::render_markdown`.
+mod markdown {
+    use super::{
+        BBCODE_TYPE_CODE, BBCODE_TYPE_EMOTICON, BBCODE_TYPE_IMG, BBCODE_TYPE_PRE,
+        BBCODE_TYPE_QUOTE, BBCODE_TYPE_SPOILER, BBCODE_TYPE_URL, BBCodeContent, BBCodeNode,
+        ImageRef, MessagePreprocessor, helpers, is_web_url,
+    };
+
+    /// Characters escaped with a backslash so text never turns into Markdown syntax.
+    const SPECIAL: &str = "\\`*_{}[]()<>#+-.!|~";
+
+    pub fn render(parsed: &[BBCodeContent], out: &mut String) {
+        for content in parsed {
+            match content {
+                BBCodeContent::String(text) => push_text(&helpers::unescape_bbcode(text), out),
+                BBCodeContent::Node(node) => render_node(node, out),
+            }
+        }
+    }
+
+    fn render_node(node: &BBCodeNode, out: &mut String) {
+        let children = node.content.as_deref().unwrap_or_default();
+        match node.tag.as_str() {
+            BBCODE_TYPE_SPOILER => {
+                out.push_str("||");
+                render(children, out);
+                out.push_str("||");
+            }
+            BBCODE_TYPE_QUOTE => {
+                let mut quoted = String::new();
+                render(children, &mut quoted);
+                start_block(out);
+                for line in quoted.lines() {
+                    out.push_str("> ");
+                    out.push_str(line);
+                    out.push('\n');
+                }
+            }
+            BBCODE_TYPE_CODE | BBCODE_TYPE_PRE => {
+                let code = helpers::unescape_bbcode(&MessagePreprocessor::render_bbcode(children));
+                let fence = "`".repeat(longest_backtick_run(&code).max(2) + 1);
+                start_block(out);
+                out.push_str(&fence);
+                out.push('\n');
+                out.push_str(&code);
+                if !code.ends_with('\n') {
+                    out.push('\n');
+                }
+                out.push_str(&fence);
+                out.push('\n');
+            }
+            BBCODE_TYPE_EMOTICON => {
+                if let Some(name) = MessagePreprocessor::emoticon_name(node) {
+                    push_text(&format!(":{}:", name), out);
+                }
+            }
+            BBCODE_TYPE_IMG => match ImageRef::from_node(node) {
+                Some(image) if image.is_valid() => {
+                    out.push_str("![](<");
+                    out.push_str(&image.url);
+                    out.push_str(">)");
+                }
+                Some(image) => push_text(&image.url, out),
+                None => {}
+            },
+            BBCODE_TYPE_URL => {
+                let href = node.attrs.get("value").map(|url| url.trim());
+                let text = MessagePreprocessor::render_plain_text(children);
+                let href = href.unwrap_or(&text);
+                if !is_web_url(href) {
+                    render(children, out);
+                    return;
+                }
+                out.push('[');
+                if children.is_empty() {
+                    push_text(href, out);
+                } else {
+                    render(children, out);
+                }
+                out.push_str("](<");
+                out.push_str(href);
+                out.push_str(">)");
+            }
+            _ => render(children, out),
+        }
+    }
+
+    /// Write message text with hard line breaks, keeping the indentation of each line.
+    fn push_text(text: &str, out: &mut String) {
+        for (index, line) in text.split('\n').enumerate() {
+            if index > 0 {
+                out.push_str("\\\n");
+            }
+            let body = line.trim_start_matches(' ');
+            for _ in 0..line.len() - body.len() {
+                out.push_str("&nbsp;");
+            }
+            for c in body.chars() {
+                if SPECIAL.contains(c) {
+                    out.push('\\');
+                }
+                out.push(c);
+            }
+        }
+    }
+
+    /// End the current line, if any, so a block starts on a line of its own.
+    fn start_block(out: &mut String) {
+        if !out.is_empty() && !out.ends_with('\n') {
+            out.push('\n');
+        }
+    }
+
+    fn longest_backtick_run(text: &str) -> usize {
+        text.split(|c| c != '`')
+            .map(str::len)
+            .max()
+            .unwrap_or_default()
+    }
+}
+
+/// Token-level comparison of a message as sent and as stored by the server.
+mod diff {
+    use super::{
+        BBCODE_TYPE_OG, BBCODE_TYPE_URL, EMOTICON_SHORTHAND_DELIMITER, MessageChange,
+        MessageChangeKind, MessagePreprocessor, bbcode,
+    };
+    use std::ops::Range;
+
+    /// Above this many table cells the differing middle is reported as a single change
+    /// instead of being aligned token by token.
+    const MAX_TABLE_CELLS: usize = 1 << 20;
+
+    /// Split `text` into tokens covering it without gaps: runs of whitespace, tags from
+    /// `[` to `]`, and words.
+    fn tokenize(text: &str) -> Vec<Range<usize>> {
+        let mut tokens = Vec::new();
+        let mut start = 0;
+        while let Some(first) = text[start..].chars().next() {
+            let rest = &text[start..];
+            let len = if first.is_whitespace() {
+                rest.find(|c: char| !c.is_whitespace())
+                    .unwrap_or(rest.len())
+            } else if first == '['
+                && let Some(close) = rest.find(']')
+                && !rest[1..close].contains('[')
+            {
+                close + 1
+            } else {
+                rest.char_indices()
+                    .skip(1)
+                    .find(|&(_, c)| c.is_whitespace() || c == '[')
+                    .map_or(rest.len(), |(at, _)| at)
+            };
+            tokens.push(start..start + len);
+            start += len;
+        }
+        tokens
+    }
+
+    /// Compare `original` with `modified` and classify every differing run of tokens.
+    pub fn changes(original: &str, modified: &str) -> Vec<MessageChange> {
+        let old = tokenize(original);
+        let new = tokenize(modified);
+        let same = |i: usize, j: usize| original[old[i].clone()] == modified[new[j].clone()];
+
+        let prefix = (0..old.len().min(new.len()))
+            .take_while(|&k| same(k, k))
+            .count();
+        let suffix = (0..old.len().min(new.len()) - prefix)
+            .take_while(|&k| same(old.len() - 1 - k, new.len() - 1 - k))
+            .count();
+        let (n, m) = (old.len() - prefix - suffix, new.len() - prefix - suffix);
+
+        // Pairs of matching token indices, in order, followed by the first pair past the end.
+        let mut matches = Vec::new();
+        if n > 0 && m > 0 && (n + 1) * (m + 1) <= MAX_TABLE_CELLS {
+            // lcs[i * (m + 1) + j]: longest common run of old[prefix + i..] and new[prefix + j..].
+            let mut lcs = vec![0u32; (n + 1) * (m + 1)];
+            for i in (0..n).rev() {
+                for j in (0..m).rev() {
+                    lcs[i * (m + 1) + j] = if same(prefix + i, prefix + j) {
+                        lcs[(i + 1) * (m + 1) + j + 1] + 1
+                    } else {
+                        lcs[(i + 1) * (m + 1) + j].max(lcs[i * (m + 1) + j + 1])
+                    };
+                }
+            }
+            let (mut i, mut j) = (0, 0);
+            while i < n && j < m {
+                if same(prefix + i, prefix + j) {
+                    matches.push((prefix + i, prefix + j));
+                    i += 1;
+                    j += 1;
+                } else if lcs[(i + 1) * (m + 1) + j] >= lcs[i * (m + 1) + j + 1] {
+                    i += 1;
+                } else {
+                    j += 1;
+                }
+            }
+        }
+        matches.push((prefix + n, prefix + m));
+
+        let offset = |tokens: &[Range<usize>], text: &str, index: usize| {
+            tokens.get(index).map_or(text.len(), |token| token.start)
+        };
+        let mut changes = Vec::new();
+        let mut record = |old_tokens: Range<usize>, new_tokens: Range<usize>| {
+            let original_span =
+                offset(&old, original, old_tokens.start)..offset(&old, original, old_tokens.end);
+            let modified_span =
+                offset(&new, modified, new_tokens.start)..offset(&new, modified, new_tokens.end);
+            changes.push(MessageChange {
+                kind: classify(
+                    &original[original_span.clone()],
+                    &modified[modified_span.clone()],
+                ),
+                original_span,
+                modified_span,
+            });
+        };
+        let (mut i, mut j) = (prefix, prefix);
+        for (next_i, next_j) in matches {
+            if next_i > i || next_j > j {
+                // Preview cards appended right after an edit are a change of their own.
+                let cards = next_j - trailing_cards(modified, &new[j..next_j]);
+                if cards > j && cards < next_j {
+                    record(i..next_i, j..cards);
+                    record(next_i..next_i, cards..next_j);
+                } else {
+                    record(i..next_i, j..next_j);
+                }
+            }
+            (i, j) = (next_i + 1, next_j + 1);
+        }
+        changes
+    }
+
+    /// How many of the last `tokens` are whitespace and `[og]` tags, when that run holds at
+    /// least one tag; zero otherwise.
+    fn trailing_cards(text: &str, tokens: &[Range<usize>]) -> usize {
+        let run: Vec<_> = tokens
+            .iter()
+            .rev()
+            .map(|token| &text[token.clone()])
+            .take_while(|token| {
+                token.trim().is_empty()
+                    || (token.starts_with('[') && bbcode::tag_name(token) == BBCODE_TYPE_OG)
+            })
+            .collect();
+        if run.iter().any(|token| token.starts_with('[')) {
+            run.len()
+        } else {
+            0
+        }
+    }
+
+    fn classify(original: &str, modified: &str) -> MessageChangeKind {
+        let with_shorthand =
+            |text: &str| text.replace(':', &EMOTICON_SHORTHAND_DELIMITER.to_string());
+        let emoticons = MessagePreprocessor::extract_emoticons(modified);
+        if !emoticons.is_empty()
+            && MessagePreprocessor::extract_emoticons(&with_shorthand(original)) == emoticons
+            && with_shorthand(&MessagePreprocessor::strip_bbcode(original))
+                == with_shorthand(&MessagePreprocessor::strip_bbcode(modified))
+        {
+            return MessageChangeKind::EmoticonNormalized;
+        }
+
+        // Words outside tags, and whether any tag other than a link tag is present.
+        let words = |text: &str| {
+            let mut words = Vec::new();
+            let mut other_tags = false;
+            for token in tokenize(text) {
+                let token = &text[token];
+                if token.starts_with('[') && token.ends_with(']') && token.len() > 1 {
+                    let name = bbcode::tag_name(token);
+                    other_tags |= name != BBCODE_TYPE_URL && name != BBCODE_TYPE_OG;
+                } else if !token.trim().is_empty() {
+                    words.push(token.to_string());
+                }
+            }
+            (words, other_tags)
+        };
+        let has_link_tag = tokenize(modified).into_iter().any(|token| {
+            let token = &modified[token];
+            token.starts_with('[')
+                && matches!(
+                    bbcode::tag_name(token).as_str(),
+                    BBCODE_TYPE_URL | BBCODE_TYPE_OG
+                )
+        });
+        let (old_words, old_tags) = words(original);
+        let (new_words, new_tags) = words(modified);
+        if has_link_tag && !old_tags && !new_tags && old_words == new_words {
+            return MessageChangeKind::LinkExpanded;
+        }
+        MessageChangeKind::TextAltered
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json;
+
+    fn text(value: &str) -> BBCodeContent {
+        BBCodeContent::String(value.to_string())
+    }
+
+    fn node(
+        tag: &str,
+        attrs: &[(&str, &str)],
+        content: Option<Vec<BBCodeContent>>,
+    ) -> BBCodeContent {
+        BBCodeContent::Node(BBCodeNode {
+            tag: tag.to_string(),
+            attrs: attrs
+                .iter()
+                .map(|(key, value)| (key.to_string(), value.to_string()))
+                .collect(),
+            content,
+        })
+    }
+
+    #[test]
+    fn test_bbcode_parsing() {
+        let message = "Hello [b]world[/b] and [spoiler]hidden [code]x[/code][/spoiler] text";
+        let parsed = MessagePreprocessor::parse_bbcode(message);
+
+        assert_eq!(
+            parsed,
+            [
+                text("Hello [b]world[/b] and "),
+                node(
+                    "spoiler",
+                    &[],
+                    Some(vec![
+                        text("hidden "),
+                        node("code", &[], Some(vec![text("x")]))
+                    ])
+                ),
+                text(" text"),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_structurally_equal_can_ignore_attrs() {
+        let a = node("url", &[("value", "https://a")], Some(vec![text("x")]));
+        let b = node("url", &[("value", "https://b")], Some(vec![text("x")]));
+        assert_ne!(a, b);
+        assert!(!a.structurally_equal(&b, false));
+        assert!(a.structurally_equal(&b, true));
+        assert!(a.structurally_equal(&a.clone(), false));
+
+        let unclosed = node("url", &[("value", "https://a")], None);
+        assert!(!a.structurally_equal(&unclosed, true));
+        assert!(!a.structurally_equal(&text("x"), true));
+        let other_text = node("url", &[], Some(vec![text("y")]));
+        assert!(!a.structurally_equal(&other_text, true));
+    }
+
+    #[test]
+    fn test_mention_extraction() {
+        let message = "Hello @all and @here users!";
+        let mentions = MessagePreprocessor::extract_mentions(message);
+
+        assert!(mentions.is_some());
+        let mentions = mentions.unwrap();
+        assert!(mentions.mention_all);
+        assert!(mentions.mention_here);
+    }
+
+    #[test]
+    fn test_mention_punctuation() {
+        let steam_id = MentionSteamId::from(SteamID::from(76561199491325083));
+        // (message, @all, @here, mentioned account)
+        let cases = [
+            ("(@all)", true, false, false),
+            ("\"@here\"", false, true, false),
+            ("@all:", true, false, false),
+            ("@all…", true, false, false),
+            ("[@here]", false, true, false),
+            ("«@all»", true, false, false),
+            ("（@here）！", false, true, false),
+            ("「@all」", true, false, false),
+            ("'@all',", true, false, false),
+            ("(@[U:1:1531059355])", false, false, true),
+            ("@[U:1:1531059355]]...", false, false, true),
+            ("“@[U:1:1531059355]”", false, false, true),
+            ("@allx", false, false, false),
+            ("x@here", false, false, false),
+        ];
+        for (message, all, here, account) in cases {
+            let mentions = MessagePreprocessor::extract_mentions(message).unwrap_or_default();
+            assert_eq!(
+                (
+                    mentions.mention_all,
+                    mentions.mention_here,
+                    mentions.mention_steamids.contains(&steam_id)
+                ),
+                (all, here, account),
+                "{message}"
+            );
+        }
+
+        let preprocessor = MessagePreprocessor::default()
+            .with_mention_punctuation(MentionPunctuation::Custom("!?,.;".to_string()));
+        assert!(preprocessor.preprocess("@all!").mentions.is_some());
+        assert!(preprocessor.preprocess("(@all)").mentions.is_none());
+    }
+
+    #[test]
+    fn test_steam64_steam2_and_profile_link_mentions() {
+        let account = SteamID::from(76561198012345678);
+        // (message, whether it mentions `account`)
+        let cases = [
+            ("@76561198012345678", true),
+            ("(@76561198012345678).", true),
+            ("STEAM_0:0:26039975", true),
+            ("@STEAM_1:0:26039975,", true),
+            (
+                "https://steamcommunity.com/profiles/76561198012345678",
+                true,
+            ),
+            (
+                "<https://steamcommunity.com/profiles/76561198012345678/>",
+                true,
+            ),
+            (
+                "steamcommunity.com/profiles/76561198012345678?l=polish",
+                true,
+            ),
+            ("76561198012345678", false),
+            ("order 76561198012345678 shipped", false),
+            ("@12345678901234567", false),
+            ("@76561198012345678x", false),
+            ("STEAM_0:2:26039975", false),
+            (
+                "https://steamcommunity.com/profiles/76561198012345678/inventory",
+                false,
+            ),
+            ("https://steamcommunity.com/id/kether", false),
+            ("[code]@76561198012345678[/code]", false),
+        ];
+        for (message, mentioned) in cases {
+            let mentions = MessagePreprocessor::extract_mentions(message).unwrap_or_default();
+            assert_eq!(mentions.contains(account), mentioned, "{message}");
+            assert_eq!(
+                mentions.mention_steamids.len(),
+                mentioned as usize,
+                "{message}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_mentioned_accounts_are_listed_once_in_order() {
+        let kyle = SteamID::from(76561199491325083);
+        let jane = SteamID::from(76561198012345678);
+        let message = "@[U:1:1531059355] @76561198012345678 \
+                       [mention=76561199491325083]@Kyle[/mention] STEAM_0:0:26039975, \
+                       @[U:1:1531059355]";
+        let mentions = MessagePreprocessor::extract_mentions(message).unwrap();
+        assert_eq!(
+            mentions.mention_steamids,
+            vec![MentionSteamId(kyle), MentionSteamId(jane)]
+        );
+        assert_eq!(mentions.occurrences(kyle), 3);
+        assert_eq!(mentions.occurrences(jane), 2);
+        assert_eq!(mentions.occurrences(SteamID::from(76561197960287930)), 0);
+        assert_eq!(
+            serde_json::to_value(&mentions).unwrap()["mention_steamids"],
+            serde_json::json!([76561199491325083u64, 76561198012345678u64])
+        );
+    }
+
+    #[test]
+    fn test_mentions_user() {
+        let own = SteamID::from(76561199491325083);
+        let other = SteamID::from(76561197960287930);
+        let message = |text| MessagePreprocessor::preprocess_message(text);
+
+        assert!(message("@all gg").mentions_user(own, false));
+        assert!(message("@here gg").mentions_user(own, true));
+        assert!(!message("@here gg").mentions_user(own, false));
+        assert!(message("gg @[U:1:1531059355]").mentions_user(own, false));
+        assert!(!message("gg @[U:1:1531059355]").mentions_user(other, true));
+        assert!(!message("gg").mentions_user(own, true));
+
+        let mentions = MessagePreprocessor::extract_mentions("@[U:1:1531059355]").unwrap();
+        assert!(mentions.contains(own));
+        assert!(!mentions.contains(other));
+    }
+
+    #[test]
+    fn test_mention_tags() {
+        let kyle = SteamID::from(76561199491325083);
+        let message = "[mention=all]@all[/mention] [mention=here]@online[/mention] \
+                       [MENTION=76561199491325083]@Kyle[/MENTION] [mention=nobody]@x[/mention]";
+        let mentions = MessagePreprocessor::extract_mentions(message).unwrap();
+        assert!(mentions.mention_all);
+        assert!(mentions.mention_here);
+        assert_eq!(mentions.mention_steamids, vec![MentionSteamId(kyle)]);
+        let spans: Vec<_> = mentions
+            .spans
+            .iter()
+            .map(|span| (span.kind, &message[span.byte_range.clone()]))
+            .collect();
+        assert_eq!(
+            spans,
+            [
+                (MentionKind::All, "[mention=all]@all[/mention]"),
+                (MentionKind::Here, "[mention=here]@online[/mention]"),
+                (
+                    MentionKind::SteamId(MentionSteamId(kyle)),
+                    "[MENTION=76561199491325083]@Kyle[/MENTION]"
+                ),
+            ]
+        );
+
+        let tag = helpers::create_mention_tag(kyle, "[K]yle");
+        assert_eq!(tag, r"[mention=76561199491325083]@\[K\]yle[/mention]");
+        assert_eq!(
+            MessagePreprocessor::extract_mentions(&tag)
+                .unwrap()
+                .mention_steamids,
+            vec![MentionSteamId(kyle)]
+        );
+        assert_eq!(
+            MessagePreprocessor::extract_mentions(&helpers::create_mention_here_tag()),
+            MessagePreprocessor::extract_mentions("[mention=here]@here[/mention]")
+        );
+        assert!(
+            MessagePreprocessor::extract_mentions(&helpers::create_mention_all_tag())
+                .unwrap()
+                .mention_all
+        );
+        let built = MessageBuilder::new()
+            .text("ping")
+            .mention_tag(kyle, "Kyle")
+            .build()
+            .unwrap();
+        assert_eq!(built, "ping [mention=76561199491325083]@Kyle[/mention]");
+        assert!(MessagePreprocessor::validate_only(&built).is_ok());
+    }
+
+    #[test]
+    fn test_message_segments() {
+        let message = "hi @all, see https://kether.pl. [spoiler]@here[/spoiler]\
+                       [mention=76561199491325083]@Kyle[/mention]!";
+        let preprocessed = MessagePreprocessor::preprocess_message(message);
+        let segments = preprocessed.segments();
+        let described: Vec<_> = segments
+            .iter()
+            .map(|segment| match segment {
+                MessageSegment::Text(text) => ("text", *text),
+                MessageSegment::Mention { source, .. } => ("mention", *source),
+                MessageSegment::Url(url) => ("url", *url),
+                MessageSegment::BBCode { node, source } => (node.tag.as_str(), *source),
+            })
+            .collect();
+        assert_eq!(
+            described,
+            [
+                ("text", "hi "),
+                ("mention", "@all"),
+                ("text", ", see "),
+                ("url", "https://kether.pl"),
+                ("text", ". "),
+                ("spoiler", "[spoiler]@here[/spoiler]"),
+                ("mention", "[mention=76561199491325083]@Kyle[/mention]"),
+                ("text", "!"),
+            ]
+        );
+        assert!(matches!(
+            &segments[1],
+            MessageSegment::Mention { span, .. } if span.kind == MentionKind::All
+        ));
+        assert_eq!(
+            segments
+                .iter()
+                .map(MessageSegment::source)
+                .collect::<String>(),
+            message
+        );
+        assert!(
+            MessagePreprocessor::preprocess_message("")
+                .segments()
+                .is_empty()
+        );
+    }
+
+    #[test]
+    fn test_mention_spans() {
+        fn spans<'m>(preprocessor: &MessagePreprocessor, message: &'m str) -> Vec<&'m str> {
+            preprocessor
+                .find_mentions(message)
+                .map(|mentions| mentions.spans)
+                .unwrap_or_default()
+                .into_iter()
+                .map(|span| &message[span.byte_range])
+                .collect()
+        }
+        let preprocessor = MessagePreprocessor::default();
+
+        assert_eq!(
+            spans(&preprocessor, "«@all» (@[U:1:1531059355])."),
+            vec!["@all", "@[U:1:1531059355]"]
+        );
+        assert_eq!(
+            spans(
+                &preprocessor,
+                "[spoiler]@here[/spoiler] [url=@all]@all[/url]"
+            ),
+            vec!["@here", "@all"]
+        );
+        assert_eq!(
+            spans(&preprocessor, "[code]@all[/code] [b]b[/b]b @here"),
+            vec!["@here"]
+        );
+        assert_eq!(
+            spans(&preprocessor, r"zażółć\[b\] [spoiler]…@here…[/spoiler]"),
+            vec!["@here"]
+        );
+
+        let mentions = preprocessor
+            .find_mentions("x [spoiler]@all[/spoiler]")
+            .unwrap();
+        assert_eq!(
+            mentions.spans,
+            vec![MentionSpan {
+                kind: MentionKind::All,
+                byte_range: 11..15,
+            }]
+        );
+
+        let roster: MentionRoster = [("Jane Doe", SteamID::from(76561198012345678))]
+            .into_iter()
+            .collect();
+        let preprocessor = preprocessor.with_roster(roster);
+        assert_eq!(
+            spans(&preprocessor, "hi (@jane   doe)!"),
+            vec!["@jane   doe"]
+        );
+    }
+
+    #[test]
+    fn test_mention_aliases() {
+        let admin = SteamID::from(76561199491325083);
+        let moderator = SteamID::from(76561197960265729);
+        let mut preprocessor = MessagePreprocessor::default();
+        preprocessor.register_mention_alias("everyone", MentionKind::All);
+        preprocessor.register_mention_alias("@Online", MentionKind::Here);
+        preprocessor.register_mention_keyword("admins", [admin, moderator]);
+        assert_eq!(
+            preprocessor.mention_alias("@Everyone"),
+            Some(&[MentionKind::All][..])
+        );
+        assert_eq!(preprocessor.mention_alias("nobody"), None);
+
+        let message = "@EVERYONE, @online and (@Admins)! not x@everyone, @all too";
+        let mentions = preprocessor.find_mentions(message).unwrap();
+        assert!(mentions.mention_all);
+        assert!(mentions.mention_here);
+        assert_eq!(
+            mentions.mention_steamids,
+            vec![MentionSteamId(admin), MentionSteamId(moderator)]
+        );
+        let spans: Vec<_> = mentions
+            .spans
+            .iter()
+            .map(|span| &message[span.byte_range.clone()])
+            .collect();
+        assert_eq!(
+            spans,
+            ["@EVERYONE", "@online", "@Admins", "@Admins", "@all"]
+        );
+        assert!(MessagePreprocessor::extract_mentions("@everyone @admins").is_none());
+
+        let glued = preprocessor.clone().with_mention_rules(MentionRules {
+            require_word_boundary: false,
+            ..MentionRules::default()
+        });
+        assert!(glued.find_mentions("ping@everyone").unwrap().mention_all);
+
+        assert_eq!(
+            preprocessor.tag_mentions("@everyone: @admins"),
+            "[mention=all]@all[/mention]: [mention=76561199491325083]@[U:1:1531059355][/mention] \
+             [mention=76561197960265729]@[U:1:1][/mention]"
+        );
+    }
+
+    #[test]
+    fn test_mention_rules() {
+        let account = MentionSteamId::from(SteamID::from(76561199491325083));
+        let custom = MentionRules {
+            punctuation: MentionPunctuation::Custom("!".to_string()),
+            ..MentionRules::default()
+        };
+        let extra = MentionRules {
+            extra_trim_chars: "«»—@".to_string(),
+            ..custom.clone()
+        };
+        let glued = MentionRules {
+            require_word_boundary: false,
+            ..MentionRules::default()
+        };
+        let line_start = MentionRules {
+            only_at_line_start: true,
+            ..MentionRules::default()
+        };
+        let default = MentionRules::default();
+        // (rules, message, @all, @here, mentioned account)
+        let cases = [
+            (&custom, "«@all»", false, false, false),
+            (&extra, "«@all»!", true, false, false),
+            (&extra, "—@here—", false, true, false),
+            (&extra, "«@[U:1:1531059355]»", false, false, true),
+            (&default, "x@here", false, false, false),
+            (&default, "@allx", false, false, false),
+            (&glued, "x@here", false, true, false),
+            (&glued, "@allx", true, false, false),
+            (&glued, "ping@[U:1:1531059355]!", false, false, true),
+            (&glued, "hey@76561199491325083", false, false, true),
+            (&glued, "mail@example.com", false, false, false),
+            (&line_start, "@all gg", true, false, false),
+            (&line_start, "gg @all", false, false, false),
+            (&line_start, "gg\n  «@here»", false, true, false),
+            (
+                &line_start,
+                "[spoiler]@[U:1:1531059355][/spoiler] hi",
+                false,
+                false,
+                true,
+            ),
+            (
+                &line_start,
+                "hi [spoiler]@[U:1:1531059355][/spoiler]",
+                false,
+                false,
+                false,
+            ),
+            (
+                &line_start,
+                "hi [mention=all]@all[/mention]",
+                true,
+                false,
+                false,
+            ),
+        ];
+        for (rules, message, all, here, mentioned) in cases {
+            let mentions = MessagePreprocessor::default()
+                .with_mention_rules(rules.clone())
+                .find_mentions(message)
+                .unwrap_or_default();
+            assert_eq!(
+                (
+                    mentions.mention_all,
+                    mentions.mention_here,
+                    mentions.mention_steamids.contains(&account)
+                ),
+                (all, here, mentioned),
+                "{message} with {rules:?}"
+            );
+        }
+
+        let mentions = MessagePreprocessor::default()
+            .with_mention_rules(glued)
+            .find_mentions("x@here")
+            .unwrap();
+        assert_eq!(mentions.spans[0].byte_range, 1..6);
+    }
+
+    #[test]
+    fn test_roster_name_mentions() {
+        let kyle = SteamID::from(76561199491325083);
+        let other_kyle = SteamID::from(76561197960287930);
+        let jane = SteamID::from(76561198012345678);
+        let roster: MentionRoster = [
+            ("Kyle", kyle),
+            ("kyle", other_kyle),
+            ("Jane Doe", jane),
+            ("all", jane),
+        ]
+        .into_iter()
+        .collect();
+        let preprocessor = MessagePreprocessor::default().with_roster(roster.clone());
+        let ids = |message: &str| {
+            preprocessor
+                .find_mentions(message)
+                .map(|mentions| {
+                    mentions
+                        .mention_steamids
+                        .iter()
+                        .map(MentionSteamId::as_inner)
+                        .collect::<Vec<_>>()
+                })
+                .unwrap_or_default()
+        };
+
+        assert_eq!(ids("ping @KYLE!"), vec![kyle, other_kyle]);
+        assert_eq!(ids("(@jane  doe), look"), vec![jane]);
+        assert_eq!(ids("@Jane alone"), Vec::<SteamID>::new());
+        assert_eq!(ids("@all"), Vec::<SteamID>::new());
+        assert_eq!(ids("mail@kyle.example"), Vec::<SteamID>::new());
+        assert_eq!(ids("[code]@kyle[/code]"), Vec::<SteamID>::new());
+        assert_eq!(MessagePreprocessor::extract_mentions("@kyle"), None);
+
+        let mentions = preprocessor
+            .preprocess("@Jane Doe and @kyle")
+            .mentions
+            .unwrap();
+        assert_eq!(mentions.name_mentions.len(), 2);
+        assert_eq!(mentions.name_mentions[0].name, "Jane Doe");
+        assert!(!mentions.name_mentions[0].is_ambiguous());
+        assert!(mentions.name_mentions[1].is_ambiguous());
+
+        assert_eq!(
+            helpers::create_mention_by_name(&roster, "jane doe"),
+            Some(helpers::create_mention(jane))
+        );
+        assert_eq!(helpers::create_mention_by_name(&roster, "Kyle"), None);
+        assert_eq!(helpers::create_mention_by_name(&roster, "Nobody"), None);
+    }
+
+    #[test]
+    fn test_message_preprocessing() {
+        let message = "Hello @all with [b]bold[/b] text";
+        let preprocessed = MessagePreprocessor::preprocess_message(message);
+
+        assert_eq!(preprocessed.original_message, message);
+        assert!(preprocessed.mentions.is_some());
+        assert!(!preprocessed.message_bbcode_parsed.is_empty());
+    }
+
+    #[test]
+    fn test_normalize_outgoing_unescapes_brackets() {
+        let message = r"Look at \[b\]escaped brackets\[/b\]";
+        let normalized = MessagePreprocessor::normalize_outgoing(message);
+        assert_eq!(normalized, "Look at [b]escaped brackets[/b]");
+        assert_eq!(
+            MessagePreprocessor::prepare_message_for_sending(message),
+            normalized
+        );
+    }
+
+    #[test]
+    fn test_escape_for_sending_protects_literal_brackets() {
+        let message = r"[b]bold[/b] \[kept\] @[U:1:1531059355] [[x]]";
+        let escaped = MessagePreprocessor::escape_for_sending(message);
+        assert_eq!(
+            escaped,
+            r"\[b\]bold\[/b\] \[kept\] @[U:1:1531059355] \[\[x\]\]"
+        );
+        assert_eq!(MessagePreprocessor::escape_for_sending(&escaped), escaped);
+        assert_eq!(MessagePreprocessor::validate_only(&escaped), Ok(()));
+    }
+
+    #[test]
+    fn test_process_response_round_trips_metadata() {
+        let original = "hello";
+        let modified = "hello world";
+        let processed = MessagePreprocessor::process_response(original, modified, 42, 7);
+
+        assert_eq!(processed.original_message, original);
+        assert_eq!(processed.modified_message(), modified);
+        assert_eq!(processed.server_timestamp, Some(42));
+        assert_eq!(processed.ordinal, Some(7));
+    }
+
+    #[test]
+    fn test_unmodified_message_is_stored_once() {
+        let processed = MessagePreprocessor::process_response("hello", "hello", 42, 7);
+        assert_eq!(processed.modified_message, None);
+        assert_eq!(processed.modified_message(), "hello");
+
+        let json: serde_json::Value = serde_json::from_str(&processed.to_json().unwrap()).unwrap();
+        assert_eq!(json["modified_message"], "hello");
+        assert_eq!(
+            PreprocessedMessage::from_json(&json.to_string()).unwrap(),
+            processed
+        );
+    }
+
+    #[test]
+    fn test_preprocess_matches_standalone_extractors() {
+        let message = "[quote=76561198012345678;1;2]see [url=https://a.example]ːsteamhappyː[/url] \\[b\\][/quote] @[U:1:1531059355] [code]@all https://b.example[/code] [url=https://c.example] [/url] [og url=\"https://c.example\" title=\"C\"][/og] [emoticon]steamsad[/emoticon] [roomeffect type=\"confetti\"][/roomeffect] https://d.example/x).";
+        let preprocessed = MessagePreprocessor::preprocess_message(message);
+        let parsed = MessagePreprocessor::parse_bbcode(message);
+
+        assert_eq!(
+            preprocessed.plain_text,
+            "see :steamhappy: [b] @[U:1:1531059355] @all https://b.example https://c.example :steamsad: https://d.example/x)."
+        );
+        assert_eq!(
+            preprocessed.mentions,
+            MessagePreprocessor::extract_mentions(message)
+        );
+        assert_eq!(
+            preprocessed.emoticons,
+            MessagePreprocessor::extract_emoticons(message)
+        );
+        assert_eq!(preprocessed.quotes[0].text, "see :steamhappy: [b]");
+        assert_eq!(
+            preprocessed.quotes,
+            parsed
+                .iter()
+                .flat_map(BBCodeContent::iter_nodes)
+                .filter_map(QuoteInfo::from_node)
+                .collect::<Vec<_>>()
+        );
+        assert_eq!(preprocessed.link_previews, ["https://c.example"]);
+        assert_eq!(preprocessed.room_effect, Some(RoomEffect::Confetti));
+        let urls: Vec<_> = preprocessed
+            .urls
+            .iter()
+            .map(|url| (url.url.as_str(), url.display_text.as_deref()))
+            .collect();
+        assert_eq!(
+            urls,
+            [
+                ("https://a.example", Some(":steamhappy:")),
+                ("https://b.example", None),
+                ("https://c.example", None),
+                ("https://c.example", Some("C")),
+                ("https://d.example/x", None),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_bbcode_parser_rejects_unknown_tags() {
+        let parser = bbcode::Parser::new(&["b"]);
+        let parsed = parser.parse("[unknown]value[/unknown]");
+
+        assert_eq!(parsed, [text("[unknown]value[/unknown]")]);
+    }
+
+    #[test]
+    fn test_bbcode_parser_handles_unicode_near_tags() {
+        let parser = bbcode::Parser::new(&["spoiler"]);
+        let parsed = parser.parse("zażółć🙂[spoiler]gęślą jaźń[/spoiler]世界");
+
+        assert_eq!(
+            parsed,
+            [
+                text("zażółć🙂"),
+                node("spoiler", &[], Some(vec![text("gęślą jaźń")])),
+                text("世界"),
+            ]
+        );
+    }
+
+    /// Reassemble parsed content, writing attribute-less nodes back as `[tag]...[/tag]`.
+    fn rebuild(parsed: &[BBCodeContent]) -> String {
+        parsed
+            .iter()
+            .map(|content| match content {
+                BBCodeContent::String(text) => text.clone(),
+                BBCodeContent::Node(node) => {
+                    let open = match node.attrs.get("value") {
+                        Some(value) => format!("[{}={}]", node.tag, value),
+                        None => format!("[{}]", node.tag),
+                    };
+                    match &node.content {
+                        Some(children) => format!("{}{}[/{}]", open, rebuild(children), node.tag),
+                        None => open,
+                    }
+                }
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_bbcode_parser_handles_multibyte_text_around_unknown_tags() {
+        let parser = bbcode::Parser::new(&["b"]);
+
+        for message in [
+            "ż[foo]",
+            "[foo]ż",
+            "日本語[foo]テキスト[b]太字",
+            "🙂[foo]🙂[b]🙂",
+            "e\u{301}[foo]e\u{301}",
+            "[ż]",
+            "[🙂",
+            "ż]",
+            "👨‍👩‍👧[b]👨‍👩‍👧",
+        ] {
+            assert_eq!(rebuild(&parser.parse(message)), message);
+        }
+
+        assert_eq!(
+            parser.parse("ż[foo]ż[b]ż"),
+            [text("ż[foo]ż"), node("b", &[], None), text("ż")]
+        );
+        assert_eq!(
+            bbcode::Parser::new(&["url"]).parse("ż[url=żółw]ż[/url]"),
+            [
+                text("ż"),
+                node("url", &[("value", "żółw")], Some(vec![text("ż")]))
+            ]
+        );
+    }
+
+    #[test]
+    fn test_bbcode_parser_round_trips_fragment_combinations() {
+        // Exhaustively combine short fragments so every multibyte character ends up
+        // directly before and after brackets, allowed tags, and unknown tags.
+        const FRAGMENTS: &[&str] = &[
+            "ż", "🙂", "世", "e\u{301}", "[", "]", "[b]", "[/b]", "[foo]", "[世]", "a",
+        ];
+        let parser = bbcode::Parser::new(&["b"]);
+
+        let mut indices = [0usize; 4];
+        loop {
+            let message: String = indices.iter().map(|&index| FRAGMENTS[index]).collect();
+            assert_eq!(rebuild(&parser.parse(&message)), message);
+
+            let Some(position) = indices
+                .iter()
+                .rposition(|&index| index + 1 < FRAGMENTS.len())
+            else {
+                break;
+            };
+            indices[position] += 1;
+            indices[position + 1..].fill(0);
+        }
+    }
+
+    #[test]
+    fn test_bbcode_parser_nests_closed_tags() {
+        let parser = bbcode::Parser::new(&["spoiler", "code", "url"]);
+        let parsed = parser.parse("a [spoiler]b [code]c[/code] [url=x]d[/spoiler] e[/code]");
+
+        // The unclosed [url] and the stray closer after the spoiler are kept as text.
+        assert_eq!(
+            parsed,
+            [
+                text("a "),
+                node(
+                    "spoiler",
+                    &[],
+                    Some(vec![
+                        text("b "),
+                        node("code", &[], Some(vec![text("c")])),
+                        text(" [url=x]d"),
+                    ])
+                ),
+                text(" e[/code]"),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_malformed_tag_policies() {
+        let message = "[url=http://x [/spoiler] a [] [[b]] [spoiler]c [url=http://y";
+        let parse = |policy| {
+            MessagePreprocessor::default()
+                .with_malformed_tag_policy(policy)
+                .parse(message)
+        };
+
+        assert_eq!(
+            MessagePreprocessor::default().malformed_tag_policy(),
+            MalformedTagPolicy::KeepAsText
+        );
+        assert_eq!(rebuild(&parse(MalformedTagPolicy::KeepAsText)), message);
+        assert_eq!(rebuild(&parse(MalformedTagPolicy::Error)), message);
+
+        // The first tag runs to the stray closer's `]`, so the whole `[url=...]` is one
+        // unclosed tag and is dropped with the rest of the markup.
+        assert_eq!(rebuild(&parse(MalformedTagPolicy::Drop)), " a  [[b]] c ");
+
+        let errors = [
+            ("a [/spoiler]", 2, "[/spoiler] has no opening tag"),
+            ("a [] b", 2, "empty tag"),
+            ("see [url=http://x", 4, "unterminated tag"),
+            ("x [spoiler]y", 2, "[spoiler] is not closed"),
+        ];
+        let strict =
+            MessagePreprocessor::default().with_malformed_tag_policy(MalformedTagPolicy::Error);
+        for (message, position, reason) in errors {
+            let err = strict.try_parse(message).unwrap_err();
+            assert_eq!((err.position, err.reason.as_str()), (position, reason));
+        }
+        assert!(
+            strict
+                .try_parse("[[double brackets]] [/unknown] [ x")
+                .is_ok()
+        );
+        assert!(strict.try_parse("[emoticon]steamhappy").is_ok());
+    }
+
+    #[test]
+    fn test_keep_as_text_round_trips_malformed_combinations() {
+        const FRAGMENTS: &[&str] = &[
+            "ż",
+            "[",
+            "]",
+            "[]",
+            "[[x]]",
+            "[spoiler]",
+            "[/spoiler]",
+            "[url=x]",
+            "[/url]",
+            "[url=http://x",
+            "[emoticon]",
+            "[/emoticon]",
+        ];
+        let parser = bbcode::Parser::new(&["spoiler", "url", "emoticon"]);
+
+        let mut indices = [0usize; 4];
+        loop {
+            let message: String = indices.iter().map(|&index| FRAGMENTS[index]).collect();
+            assert_eq!(rebuild(&parser.parse(&message)), message);
+
+            let Some(position) = indices
+                .iter()
+                .rposition(|&index| index + 1 < FRAGMENTS.len())
+            else {
+                break;
+            };
+            indices[position] += 1;
+            indices[position + 1..].fill(0);
+        }
+    }
+
+    #[test]
+    fn test_bbcode_tags_match_case_insensitively() {
+        let message = "[URL=https://kether.pl]site[/url] [Spoiler]a [CODE]b[/Code][/SPOILER]";
+        let parsed = MessagePreprocessor::parse_bbcode(message);
+
+        assert_eq!(
+            parsed,
+            [
+                node(
+                    "url",
+                    &[("value", "https://kether.pl")],
+                    Some(vec![text("site")])
+                ),
+                text(" "),
+                node(
+                    "spoiler",
+                    &[],
+                    Some(vec![text("a "), node("code", &[], Some(vec![text("b")]))])
+                ),
+            ]
+        );
+
+        assert_eq!(MessagePreprocessor::validate_only(message), Ok(()));
+        assert_eq!(
+            MessagePreprocessor::validate_only("[CODE][Sub]literal[/code]"),
+            Ok(())
+        );
+        assert_eq!(MessagePreprocessor::strip_bbcode(message), "site a b");
+
+        let preprocessor = MessagePreprocessor::with_allowed_tags(&["URL"]);
+        assert_eq!(preprocessor.allowed_tags(), ["url"]);
+        assert_eq!(
+            preprocessor.parse("[Url=x]y[/URL]"),
+            [node("url", &[("value", "x")], Some(vec![text("y")]))]
+        );
+    }
+
+    #[test]
+    fn test_code_and_pre_content_is_literal() {
+        let parsed = MessagePreprocessor::parse_bbcode(
+            "[code]if a[b] { [url=x]y[/url] }[/CODE] [pre][spoiler][/pre] [code][/code]",
+        );
+        assert_eq!(
+            parsed,
+            [
+                node("code", &[], Some(vec![text("if a[b] { [url=x]y[/url] }")])),
+                text(" "),
+                node("pre", &[], Some(vec![text("[spoiler]")])),
+                text(" "),
+                node("code", &[], Some(vec![])),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_mentions_inside_code_blocks_are_ignored() {
+        for message in [
+            "[code]@all [U:1:123] @[U:1:123][/code]",
+            "see [pre]@here\n@[U:1:1531059355][/pre] above",
+        ] {
+            assert!(
+                MessagePreprocessor::extract_mentions(message).is_none(),
+                "{message:?}"
+            );
+            assert!(
+                MessagePreprocessor::preprocess_message(message)
+                    .mentions
+                    .is_none()
+            );
+        }
+
+        let mentions =
+            MessagePreprocessor::extract_mentions("@all [code]@here[/code]").expect("mentions");
+        assert!(mentions.mention_all);
+        assert!(!mentions.mention_here);
+    }
+
+    #[test]
+    fn test_strip_bbcode() {
+        let cases = [
+            ("plain  text\n here ", "plain text here"),
+            ("[spoiler]gg[/spoiler] wp", "gg wp"),
+            ("[url=https://kether.pl]our site[/url]", "our site"),
+            ("[url=https://kether.pl][/url]", "https://kether.pl"),
+            ("[emoticon]steamhappy[/emoticon] hi", ":steamhappy: hi"),
+            ("[spoiler][code]x[/code][/spoiler]", "x"),
+            ("[unknown]kept[/unknown]", "[unknown]kept[/unknown]"),
+            ("[spoiler]never closed", "[spoiler]never closed"),
+            ("[url=http://x", "[url=http://x"),
+            ("", ""),
+        ];
+        for (message, expected) in cases {
+            assert_eq!(
+                MessagePreprocessor::strip_bbcode(message),
+                expected,
+                "{message:?}"
+            );
+        }
+
+        let preprocessed = MessagePreprocessor::preprocess_message("[spoiler]gg[/spoiler] wp");
+        assert_eq!(preprocessed.plain_text(), "gg wp");
+    }
+
+    #[test]
+    fn test_preprocessor_with_allowed_tags() {
+        let message = "[url=https://kether.pl]site[/url] [spoiler]gg[/spoiler]";
+        let preprocessor = MessagePreprocessor::with_allowed_tags(&["url"]);
+
+        let parsed = preprocessor.parse(message);
+        assert_eq!(parsed.len(), 2);
+        assert!(matches!(&parsed[0], BBCodeContent::Node(node) if node.tag == "url"));
+        assert!(
+            matches!(&parsed[1], BBCodeContent::String(text) if text == " [spoiler]gg[/spoiler]")
+        );
+        assert_eq!(preprocessor.strip(message), "site [spoiler]gg[/spoiler]");
+        assert!(
+            preprocessor
+                .validate("[url=https://kether.pl]site[/url]")
+                .is_ok()
+        );
+        assert!(preprocessor.validate(message).is_err());
+
+        let default = MessagePreprocessor::default();
+        assert_eq!(default.allowed_tags().len(), GROUP_CHAT_TAGS.len());
+        assert_eq!(
+            default.preprocess(message),
+            MessagePreprocessor::preprocess_message(message)
+        );
+        assert!(default.validate(message).is_ok());
+    }
+
+    #[test]
+    fn test_extract_emoticons_counts_both_syntaxes() {
+        let message = "ːsteamhappyː [spoiler][emoticon]steamsad[/emoticon][/spoiler] \
+                       [emoticon]steamhappy[/emoticon]ːsteamhappyːsteamsadː ːnot validː";
+        assert_eq!(
+            MessagePreprocessor::extract_emoticons(message),
+            vec![
+                EmoticonUse {
+                    name: "steamhappy".to_string(),
+                    count: 3,
+                },
+                EmoticonUse {
+                    name: "steamsad".to_string(),
+                    count: 1,
+                },
+            ]
+        );
+
+        let preprocessed = MessagePreprocessor::preprocess_message("gg ːsteamthumbsupː");
+        assert_eq!(preprocessed.emoticons.len(), 1);
+        assert_eq!(preprocessed.plain_text(), "gg :steamthumbsup:");
+        assert_eq!(
+            MessagePreprocessor::strip_bbcode("gg [emoticon]steamthumbsup[/emoticon]"),
+            preprocessed.plain_text()
+        );
     }
-}
 
-mod bbcode {
-    use super::{BBCodeContent, BBCodeNode};
-    use std::collections::HashMap;
+    #[test]
+    fn test_colon_form_emoticons_round_trip() {
+        let message = helpers::format_with_bbcode("", BBCODE_TYPE_EMOTICON, "steamhappy");
+        assert_eq!(message, "[emoticon:steamhappy]");
 
-    pub mod formatting {
-        use super::super::{
-            BBCODE_TYPE_CODE, BBCODE_TYPE_EMOTICON, BBCODE_TYPE_SPOILER, BBCODE_TYPE_URL,
+        let parsed = MessagePreprocessor::parse_bbcode(&format!("gg {message}"));
+        let BBCodeContent::Node(node) = &parsed[1] else {
+            panic!("expected emoticon node, got {:?}", parsed[1]);
         };
+        assert_eq!(node.tag, BBCODE_TYPE_EMOTICON);
+        assert_eq!(node.attrs["name"], "steamhappy");
+        assert_eq!(
+            MessagePreprocessor::extract_emoticons(&format!("{message} [Emoticon:steamhappy]")),
+            vec![EmoticonUse {
+                name: "steamhappy".to_string(),
+                count: 2,
+            }]
+        );
+        assert_eq!(MessagePreprocessor::strip_bbcode(&message), ":steamhappy:");
+        assert_eq!(MessagePreprocessor::validate_only(&message), Ok(()));
 
-        /// Trait for formatting messages with BBCode
-        trait BBCodeFormatter {
-            fn format(&self, message: &str, value: &str) -> String;
+        let preprocessed = MessagePreprocessor::preprocess_message("[sticker:ChromaticCat]");
+        assert_eq!(
+            preprocessed.sticker().map(|sticker| sticker.name),
+            Some("ChromaticCat".to_string())
+        );
+
+        // Other tags and empty values are not colon-form markup.
+        for text in ["[emoticon:]", "[url:https://kether.pl]"] {
+            assert!(matches!(
+                MessagePreprocessor::parse_bbcode(text).as_slice(),
+                [BBCodeContent::String(kept)] if kept == text
+            ));
+            assert!(MessagePreprocessor::validate_only(text).is_err());
         }
+    }
 
-        struct SpoilerFormatter;
-        struct CodeFormatter;
-        struct UrlFormatter;
-        struct EmoticonFormatter;
-        struct DefaultFormatter;
+    #[test]
+    fn test_sticker_message_parses_into_node() {
+        let message = helpers::create_sticker_message("ChromaticCat").expect("valid sticker");
+        assert_eq!(
+            message,
+            r#"[sticker type="ChromaticCat" limit="0"][/sticker]"#
+        );
 
-        impl BBCodeFormatter for SpoilerFormatter {
-            fn format(&self, message: &str, _value: &str) -> String {
-                format!("[spoiler]{}[/spoiler]", message)
+        let parsed = MessagePreprocessor::parse_bbcode(&message);
+        match &parsed[0] {
+            BBCodeContent::Node(node) => {
+                assert_eq!(node.tag, BBCODE_TYPE_STICKER);
+                assert_eq!(
+                    node.attrs.get("type").map(String::as_str),
+                    Some("ChromaticCat")
+                );
+                assert_eq!(node.attrs.get("limit").map(String::as_str), Some("0"));
             }
+            other => panic!("expected sticker node, got {:?}", other),
         }
+    }
 
-        impl BBCodeFormatter for CodeFormatter {
-            fn format(&self, message: &str, _value: &str) -> String {
-                format!("[code]{}[/code]", message)
+    #[test]
+    fn test_tag_attributes_from_real_traffic() {
+        fn attrs(message: &str) -> HashMap<String, String> {
+            match &MessagePreprocessor::parse_bbcode(message)[0] {
+                BBCodeContent::Node(node) => node.attrs.clone(),
+                other => panic!("expected a node for {:?}, got {:?}", message, other),
             }
         }
-
-        impl BBCodeFormatter for UrlFormatter {
-            fn format(&self, message: &str, value: &str) -> String {
-                format!("[url={}]{}[/url]", value, message)
-            }
+        fn pairs(entries: &[(&str, &str)]) -> HashMap<String, String> {
+            entries
+                .iter()
+                .map(|(key, value)| (key.to_string(), value.to_string()))
+                .collect()
         }
 
-        impl BBCodeFormatter for EmoticonFormatter {
-            fn format(&self, _message: &str, value: &str) -> String {
-                format!("[emoticon:{}]", value)
-            }
-        }
+        assert_eq!(
+            attrs(r#"[sticker type=":steamhappy:" limit="0"][/sticker]"#),
+            pairs(&[("type", ":steamhappy:"), ("limit", "0")])
+        );
+        assert_eq!(
+            attrs(
+                r#"[gameinvite appid="440" lobbyid="109775241089155077" owner="76561198000000000"][/gameinvite]"#
+            ),
+            pairs(&[
+                ("appid", "440"),
+                ("lobbyid", "109775241089155077"),
+                ("owner", "76561198000000000"),
+            ])
+        );
+        assert_eq!(
+            attrs(
+                r#"[og url="https://kether.pl/" img="https://kether.pl/a.png" title="Kether – Home"][/og]"#
+            ),
+            pairs(&[
+                ("url", "https://kether.pl/"),
+                ("img", "https://kether.pl/a.png"),
+                ("title", "Kether – Home"),
+            ])
+        );
+        assert_eq!(
+            attrs("[tradeofferlink partner=12345 token=AbCdEf12][/tradeofferlink]"),
+            pairs(&[("partner", "12345"), ("token", "AbCdEf12")])
+        );
+        assert_eq!(
+            attrs("[tradeoffer sender=12345 state=2 tradeofferid='4711'][/tradeoffer]"),
+            pairs(&[
+                ("sender", "12345"),
+                ("state", "2"),
+                ("tradeofferid", "4711")
+            ])
+        );
+        assert_eq!(
+            attrs("[url=https://kether.pl/?a=1&b=2]site[/url]"),
+            pairs(&[("value", "https://kether.pl/?a=1&b=2")])
+        );
+        assert_eq!(
+            attrs("[quote=Some Name]hi[/quote]"),
+            pairs(&[("value", "Some Name")])
+        );
+        assert_eq!(
+            attrs(r#"[quote="Some Name" date=1700000000]hi[/quote]"#),
+            pairs(&[("value", "Some Name"), ("date", "1700000000")])
+        );
+        assert_eq!(
+            attrs(r#"[sticker type="Cat" limit="0][/sticker]"#),
+            pairs(&[("type", "Cat")])
+        );
+    }
 
-        impl BBCodeFormatter for DefaultFormatter {
-            fn format(&self, message: &str, _value: &str) -> String {
-                message.to_string()
-            }
-        }
+    #[test]
+    fn test_sticker_info_from_message() {
+        let message = r#"[sticker type="Winter 2019 Cat" limit="0"][/sticker]"#;
+        let sticker = MessagePreprocessor::preprocess_message(message)
+            .sticker()
+            .expect("sticker message");
+        assert_eq!(sticker.name, "Winter 2019 Cat");
+        assert_eq!(
+            sticker.raw.attrs.get("limit").map(String::as_str),
+            Some("0")
+        );
 
-        /// Get the appropriate formatter for a BBCode type
-        fn get_formatter(bbcode_type: &str) -> Box<dyn BBCodeFormatter> {
-            match bbcode_type {
-                BBCODE_TYPE_SPOILER => Box::new(SpoilerFormatter),
-                BBCODE_TYPE_CODE => Box::new(CodeFormatter),
-                BBCODE_TYPE_URL => Box::new(UrlFormatter),
-                BBCODE_TYPE_EMOTICON => Box::new(EmoticonFormatter),
-                _ => Box::new(DefaultFormatter),
-            }
-        }
+        let reordered = r#"[sticker limit="0" type="ChromaticCat"][/sticker]"#;
+        assert_eq!(
+            MessagePreprocessor::preprocess_message(reordered)
+                .sticker()
+                .map(|sticker| sticker.name),
+            Some("ChromaticCat".to_string())
+        );
 
-        /// Format a message with BBCode using the strategy pattern
-        pub fn format_with_bbcode(message: &str, bbcode_type: &str, value: &str) -> String {
-            let formatter = get_formatter(bbcode_type);
-            formatter.format(message, value)
+        for message in [
+            "no sticker here",
+            "[sticker][/sticker]",
+            r#"[sticker type=""][/sticker]"#,
+        ] {
+            assert!(
+                MessagePreprocessor::preprocess_message(message)
+                    .sticker()
+                    .is_none(),
+                "{}",
+                message
+            );
         }
     }
 
-    pub struct Parser<'a> {
-        allowed_tags: &'a [&'a str],
+    #[test]
+    fn test_extract_trade_offers() {
+        let message = concat!(
+            r#"[tradeoffer sender="1531059355" state="2" tradeofferid="4711"][/tradeoffer] "#,
+            r#"[tradeofferlink url="https://steamcommunity.com/tradeoffer/4712/?partner=76561199491325083"][/tradeofferlink] "#,
+            r#"[tradeofferlink url="https://steamcommunity.com/tradeoffer/new/?partner=1&token=x"][/tradeofferlink] "#,
+            r#"[tradeoffer sender="oops" tradeofferid="4713"][/tradeoffer] "#,
+            r#"[tradeoffer tradeofferid="not-a-number"][/tradeoffer]"#,
+        );
+        let offers = MessagePreprocessor::preprocess_message(message).trade_offers();
+
+        assert_eq!(
+            offers
+                .iter()
+                .map(|offer| offer.offer_id)
+                .collect::<Vec<_>>(),
+            vec![4711, 4712, 4713]
+        );
+        assert_eq!(
+            offers[0].partner.map(|partner| partner.account_id()),
+            Some(1531059355)
+        );
+        assert_eq!(offers[0].url, None);
+        assert_eq!(offers[1].partner.map(u64::from), Some(76561199491325083));
+        assert!(
+            offers[1]
+                .url
+                .as_deref()
+                .is_some_and(|url| url.contains("/4712/"))
+        );
+        assert_eq!(offers[2].partner, None);
     }
 
-    enum TagPosition<'a> {
-        Found {
-            text_before: &'a str,
-            tag_content: &'a str,
-            tag_length: usize,
-        },
-        EndOfText(&'a str),
+    #[test]
+    fn test_dice_and_coin_flips() {
+        let rolled = |message: &str| MessagePreprocessor::preprocess_message(message).dice;
+        let flipped = |message: &str| MessagePreprocessor::preprocess_message(message).coin_flip;
+        let roll = |min, max, result| Some(RandomResult { min, max, result });
+
+        assert_eq!(
+            rolled(r#"[random min="1" max="20" result="17"][/random]"#),
+            roll(1, 20, 17)
+        );
+        assert_eq!(rolled("[random max=6]4[/random]"), roll(1, 6, 4));
+        assert_eq!(
+            rolled(r#"[random min="1" max="6" result="9"][/random]"#),
+            None
+        );
+        assert_eq!(rolled("Random number from 5 to 10: 7"), roll(5, 10, 7));
+        assert_eq!(rolled("kether rolled 42 (1-100)"), roll(1, 100, 42));
+        assert_eq!(rolled("/random 100"), None);
+        assert_eq!(rolled("see you at 10"), None);
+
+        assert_eq!(flipped(r#"[flip result="heads"][/flip]"#), Some(true));
+        assert_eq!(flipped("[flip]0[/flip]"), Some(false));
+        assert_eq!(flipped("kether flipped a coin: Tails"), Some(false));
+        assert_eq!(flipped("/flip"), None);
+        assert_eq!(flipped("heads or tails? flip it"), None);
+
+        let notification = MessagePreprocessor::preprocess_message_with_plain_text(
+            "[random][/random]",
+            "Random number from 1 to 100: 3",
+        );
+        assert_eq!(notification.dice, roll(1, 100, 3));
     }
 
-    impl<'a> Parser<'a> {
-        pub fn new(allowed_tags: &'a [&'a str]) -> Self {
-            Self { allowed_tags }
-        }
+    #[test]
+    fn test_parse_limits() {
+        let limited = |limits: ParseLimits, message: &str| {
+            let processed = MessagePreprocessor::default()
+                .with_limits(limits)
+                .preprocess(message);
+            (
+                MessagePreprocessor::render_bbcode(&processed.message_bbcode_parsed) == message,
+                processed
+                    .message_bbcode_parsed
+                    .iter()
+                    .flat_map(BBCodeContent::iter_nodes)
+                    .count(),
+                processed.limits_hit,
+            )
+        };
+        let bytes = |max_input_bytes| ParseLimits {
+            max_input_bytes,
+            ..ParseLimits::default()
+        };
+        let nodes = |max_nodes| ParseLimits {
+            max_nodes,
+            ..ParseLimits::default()
+        };
+        let depth = |max_depth| ParseLimits {
+            max_depth,
+            ..ParseLimits::default()
+        };
 
-        fn slice_from<'b>(text: &'b str, start: usize) -> &'b str {
-            let safe_start = text.ceil_char_boundary(start);
-            &text[safe_start..]
-        }
+        let spoiler = "[spoiler]x[/spoiler]";
+        assert_eq!(limited(bytes(20), spoiler), (true, 1, false));
+        assert_eq!(limited(bytes(19), spoiler), (true, 0, true));
+        let code = "[code]x[/code]";
+        assert_eq!(limited(bytes(14), code), (true, 1, false));
+        assert_eq!(limited(bytes(13), code), (true, 0, true));
 
-        fn slice_range<'b>(text: &'b str, start: usize, end: usize) -> &'b str {
-            let safe_start = text.ceil_char_boundary(start);
-            let safe_end = text.floor_char_boundary(end.min(text.len()));
+        let emoticons = "[emoticon:a] [emoticon:b] [emoticon:c]";
+        assert_eq!(limited(nodes(3), emoticons), (true, 3, false));
+        assert_eq!(limited(nodes(2), emoticons), (true, 2, true));
 
-            if safe_start >= safe_end {
-                ""
-            } else {
-                &text[safe_start..safe_end]
-            }
-        }
+        let nested = "[spoiler][quote]x[/quote][/spoiler]";
+        assert_eq!(limited(depth(2), nested), (true, 2, false));
+        assert_eq!(limited(depth(1), nested).2, true);
+        assert_eq!(limited(depth(1), nested).1, 1);
 
-        pub fn parse(&self, message: &str) -> Vec<BBCodeContent> {
-            if message.is_empty() {
-                return vec![BBCodeContent::String(message.to_string())];
-            }
-
-            let mut parsed = Vec::new();
-            let mut current_text = String::new();
-            let mut i = 0;
-
-            while i < message.len() {
-                match self.find_next_tag(Self::slice_from(message, i)) {
-                    TagPosition::Found {
-                        text_before,
-                        tag_content,
-                        tag_length,
-                    } => {
-                        if !text_before.is_empty() {
-                            current_text.push_str(text_before);
-                        }
+        // Tags left open at the cut are text, not errors, under every policy.
+        let strict = MessagePreprocessor::default()
+            .with_malformed_tag_policy(MalformedTagPolicy::Error)
+            .with_limits(bytes(12));
+        assert_eq!(
+            strict.try_parse("[spoiler]ab [/spoiler]"),
+            Ok(vec![text("[spoiler]ab [/spoiler]")])
+        );
 
-                        if let Some(node) = self.parse_tag(tag_content) {
-                            if !current_text.is_empty() {
-                                parsed
-                                    .push(BBCodeContent::String(std::mem::take(&mut current_text)));
-                            }
-                            parsed.push(BBCodeContent::Node(node));
-                        } else {
-                            current_text.push_str(Self::slice_range(message, i, i + tag_length));
-                        }
+        let long = "[spoiler]gg[/spoiler] ".repeat(GROUP_CHAT_MESSAGE_LIMIT / 20);
+        assert!(!MessagePreprocessor::preprocess_message(&long).limits_hit);
+    }
 
-                        i += tag_length;
-                    }
-                    TagPosition::EndOfText(remaining) => {
-                        current_text.push_str(remaining);
-                        break;
-                    }
-                }
-            }
+    #[test]
+    fn test_multiline_whitespace_is_preserved() {
+        let message = concat!(
+            "steps:\n  1. [b]build[/b]\n\t2. [spoiler]ship\n   it[/spoiler]\n\n",
+            "[code]fn main() {\n    run();\n}[/code]",
+        );
+        let parsed = MessagePreprocessor::parse_bbcode(message);
 
-            if !current_text.is_empty() {
-                parsed.push(BBCodeContent::String(current_text));
-            }
+        assert_eq!(MessagePreprocessor::render_bbcode(&parsed), message);
+        assert_eq!(parsed[0], text("steps:\n  1. [b]build[/b]\n\t2. "));
+        assert_eq!(
+            MessagePreprocessor::render_html(&parsed),
+            concat!(
+                "steps:<br>  1. [b]build[/b]<br>\t2. ",
+                "<span class=\"spoiler\">ship<br>   it</span><br><br>",
+                "<pre><code>fn main() {\n    run();\n}</code></pre>",
+            )
+        );
+        assert_eq!(
+            MessagePreprocessor::render_markdown(&parsed),
+            concat!(
+                "steps:\\\n&nbsp;&nbsp;1\\. \\[b\\]build\\[/b\\]\\\n",
+                "\t2\\. ||ship\\\n&nbsp;&nbsp;&nbsp;it||\\\n\\\n",
+                "```\nfn main() {\n    run();\n}\n```\n",
+            )
+        );
+        assert_eq!(
+            MessagePreprocessor::render_markdown(&MessagePreprocessor::parse_bbcode(
+                "[quote]a\nb[/quote]"
+            )),
+            "> a\\\n> b\n"
+        );
+    }
 
-            parsed
-        }
+    #[test]
+    fn test_images() {
+        let message = concat!(
+            "[spoiler]cat [img]https://x.example/cat.png[/img][/spoiler] ",
+            "[img src=\"https://x.example/dog.png\"] ",
+            "[img=data:image/png;base64,AAAA] [img]javascript:alert(1)[/img] [img][/img]",
+        );
+        let processed = MessagePreprocessor::preprocess_message(message);
 
-        fn find_next_tag<'b>(&self, text: &'b str) -> TagPosition<'b> {
-            if let Some(tag_start) = text.find('[')
-                && let Some(tag_end) = text[tag_start..].find(']')
-            {
-                let tag_content = Self::slice_range(text, tag_start + 1, tag_start + tag_end);
-                TagPosition::Found {
-                    text_before: Self::slice_range(text, 0, tag_start),
-                    tag_content,
-                    tag_length: tag_start + tag_end + 1,
-                }
-            } else {
-                TagPosition::EndOfText(text)
+        assert_eq!(
+            processed
+                .images
+                .iter()
+                .map(|image| (image.url.as_str(), image.from_attr, image.is_valid()))
+                .collect::<Vec<_>>(),
+            [
+                ("https://x.example/cat.png", false, true),
+                ("https://x.example/dog.png", true, true),
+                ("data:image/png;base64,AAAA", true, false),
+                ("javascript:alert(1)", false, false),
+            ]
+        );
+        assert!(
+            !ImageRef {
+                url: "https://x.example/a.png\" onerror=\"x".to_string(),
+                from_attr: true,
             }
-        }
+            .is_valid()
+        );
 
-        fn parse_tag(&self, tag_content: &str) -> Option<BBCodeNode> {
-            let parts: Vec<&str> = tag_content.splitn(2, '=').collect();
-            let tag_name = parts[0].trim();
+        assert_eq!(
+            MessagePreprocessor::render_html(&processed.message_bbcode_parsed),
+            concat!(
+                "<span class=\"spoiler\">cat <img src=\"https://x.example/cat.png\" alt=\"\">",
+                "</span> <img src=\"https://x.example/dog.png\" alt=\"\"> ",
+                "data:image/png;base64,AAAA javascript:alert(1) ",
+            )
+        );
+    }
 
-            if !self.allowed_tags.contains(&tag_name) {
-                return None;
-            }
+    #[test]
+    fn test_render_html_links() {
+        let parsed = MessagePreprocessor::parse_bbcode(
+            "[url=https://kether.pl/?a=1&b=2]site[/url] [url=javascript:x]bad[/url] \\[b\\]",
+        );
+        assert_eq!(
+            MessagePreprocessor::render_html(&parsed),
+            "<a href=\"https://kether.pl/?a=1&amp;b=2\">site</a> bad [b]"
+        );
+    }
 
-            let attrs = Self::extract_tag_attributes(&parts);
+    #[test]
+    fn test_extract_item_links() {
+        let message = concat!(
+            "https://steamcommunity.com/market/listings/730/AK-47%20%7C%20Redline%20%28FT%29 ",
+            "https://steamcommunity.com/profiles/76561198084749846/inventory/#730_2_698323590 ",
+            "https://steamcommunity.com/id/kether/inventory ",
+            "[url=steam://rungame/730/76561202255233023/+csgo_econ_action_preview%20",
+            "S76561198084749846A698323590D7935523998312483177]inspect[/url] ",
+            "[url=steam://rungame/730/76561202255233023/%2Bcsgo_econ_action_preview%20",
+            "M4982475631204312A31263512D7935523998312483177]listed[/url] ",
+            "https://steamcommunity.com/market/listings/730/AK-47%20%7C%20Redline%20%28FT%29 ",
+            "https://steamcommunity.com/market/ https://kether.pl/inventory/#1_2_3",
+        );
+        let links = extract_item_links(&MessagePreprocessor::preprocess_message(message));
 
-            Some(BBCodeNode {
-                tag: tag_name.to_string(),
-                attrs,
-                content: None,
-            })
-        }
+        assert_eq!(links.len(), 5);
+        assert!(matches!(
+            &links[0],
+            ItemLink::MarketListing { app_id: 730, market_hash_name, .. }
+                if market_hash_name == "AK-47 | Redline (FT)"
+        ));
+        assert!(matches!(
+            &links[1],
+            ItemLink::Inventory {
+                owner: Some(owner),
+                vanity_name: None,
+                app_id: Some(730),
+                context_id: Some(2),
+                asset_id: Some(698323590),
+                ..
+            } if u64::from(*owner) == 76561198084749846
+        ));
+        assert!(matches!(
+            &links[2],
+            ItemLink::Inventory { owner: None, vanity_name: Some(name), app_id: None, .. }
+                if name == "kether"
+        ));
+        assert!(matches!(
+            &links[3],
+            ItemLink::Inspect {
+                owner: Some(_),
+                market_listing_id: None,
+                asset_id: 698323590,
+                d: 7935523998312483177,
+                ..
+            }
+        ));
+        assert!(matches!(
+            &links[4],
+            ItemLink::Inspect {
+                owner: None,
+                market_listing_id: Some(4982475631204312),
+                asset_id: 31263512,
+                ..
+            }
+        ));
+    }
+
+    #[test]
+    fn test_game_invites() {
+        let message = concat!(
+            r#"join [gameinvite appid="440" lobbyid="109775241089155077"][/gameinvite] "#,
+            r#"or [gameinvite lobbyid="109775241089155078" appid="730"][/gameinvite] "#,
+            r#"[gameinvite appid="570"][/gameinvite] [gameinvite lobbyid="1"][/gameinvite]"#,
+        );
+        let invites = MessagePreprocessor::preprocess_message(message).game_invites;
 
-        fn extract_tag_attributes(parts: &[&str]) -> HashMap<String, String> {
-            let mut attrs = HashMap::new();
+        assert_eq!(
+            invites
+                .iter()
+                .map(|invite| (invite.app_id, invite.lobby_id))
+                .collect::<Vec<_>>(),
+            vec![
+                (440, Some(109775241089155077)),
+                (730, Some(109775241089155078)),
+                (570, None),
+            ]
+        );
+        assert_eq!(invites[0].raw.tag, BBCODE_TYPE_GAMEINVITE);
+    }
 
-            let value = parts
-                .get(1)
-                .map(|s| s.trim())
-                .filter(|value| !value.is_empty());
+    #[test]
+    fn test_quotes_with_author_metadata() {
+        let message = "[quote=76561198012345678;1699999999;3]gg [quote]first[/quote][/quote] \
+                       [quote=76561199491325083;12]hi[/quote] [quote=Some Name]x[/quote] \
+                       [quote=42;nope]y[/quote] [quote]never closed";
+        let quotes = MessagePreprocessor::preprocess_message(message).quotes;
 
-            if let Some(value) = value {
-                attrs.insert("value".to_string(), value.to_string());
+        assert_eq!(
+            quotes[0],
+            QuoteInfo {
+                author: Some(SteamID::from(76561198012345678)),
+                timestamp: Some(1699999999),
+                ordinal: Some(3),
+                text: "gg first".to_string(),
             }
+        );
+        assert_eq!((quotes[1].author, quotes[1].text.as_str()), (None, "first"));
+        assert_eq!(
+            (quotes[2].author, quotes[2].timestamp, quotes[2].ordinal),
+            (Some(SteamID::from(76561199491325083)), Some(12), None)
+        );
+        // Names and account ids that aren't individual SteamIDs leave `author` empty.
+        assert_eq!((quotes[3].author, quotes[3].text.as_str()), (None, "x"));
+        assert_eq!((quotes[4].author, quotes[4].timestamp), (None, None));
+        assert_eq!(quotes.len(), 5);
+    }
 
-            attrs
-        }
+    #[test]
+    fn test_room_effects() {
+        let message =
+            helpers::create_room_effect_message(&RoomEffect::Confetti, "happy [b]new[/b] year")
+                .expect("known effect");
+        assert_eq!(
+            message,
+            r#"[roomeffect type="confetti"]happy [b]new[/b] year[/roomeffect]"#
+        );
+        let preprocessed = MessagePreprocessor::preprocess_message(&message);
+        assert_eq!(preprocessed.room_effect, Some(RoomEffect::Confetti));
+        assert_eq!(preprocessed.plain_text(), "happy [b]new[/b] year");
+
+        let custom = RoomEffect::from_name("Hearts");
+        assert_eq!(custom, RoomEffect::Other("Hearts".to_string()));
+        let message = helpers::create_room_effect_message(&custom, "").expect("safe name");
+        assert_eq!(
+            MessagePreprocessor::preprocess_message(&message).room_effect,
+            Some(custom.clone())
+        );
+        let json = serde_json::to_string(&custom).expect("serialize effect");
+        assert_eq!(json, r#""Hearts""#);
+        assert_eq!(serde_json::from_str::<RoomEffect>(&json).unwrap(), custom);
+        assert!(
+            helpers::create_room_effect_message(&RoomEffect::Other("x\"]".to_string()), "")
+                .is_none()
+        );
+
+        assert_eq!(
+            MessagePreprocessor::preprocess_message("/effect FIREWORKS woo").room_effect,
+            Some(RoomEffect::Fireworks)
+        );
+        assert_eq!(
+            MessagePreprocessor::preprocess_message("/effects").room_effect,
+            None
+        );
+        assert_eq!(
+            MessagePreprocessor::preprocess_message("no effect").room_effect,
+            None
+        );
     }
-}
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-    use serde_json;
+    #[test]
+    fn test_sticker_name_validation() {
+        assert!(helpers::is_valid_sticker_name("Winter 2019 Cat"));
+        assert!(helpers::create_sticker_message("").is_none());
+        assert!(helpers::create_sticker_message(" padded").is_none());
+        assert!(helpers::create_sticker_message("bad\"][img]").is_none());
+        assert!(helpers::create_sticker_message(&"x".repeat(MAX_STICKER_NAME_LEN + 1)).is_none());
+    }
 
     #[test]
-    fn test_bbcode_parsing() {
-        let message = "Hello [b]world[/b] and [i]italic[/i] text";
-        let parsed = MessagePreprocessor::parse_bbcode(message);
+    fn test_suppress_link_previews_wraps_bare_links_only() {
+        let message = "see https://kether.pl\nand [url=https://a.b]https://a.b[/url] now";
+        assert_eq!(
+            helpers::suppress_link_previews(message),
+            "see [noembed]https://kether.pl[/noembed]\nand [url=https://a.b]https://a.b[/url] now"
+        );
+    }
 
-        assert!(!parsed.is_empty());
-        // Basic test - in practice you'd want more detailed assertions
+    #[test]
+    fn test_og_nodes_populate_link_previews() {
+        let message = r#"look [og url="https://example.com/post" title="Post"]"#;
+        let preprocessed = MessagePreprocessor::preprocess_message(message);
+        assert_eq!(preprocessed.link_previews, vec!["https://example.com/post"]);
+
+        let plain = MessagePreprocessor::preprocess_message("https://example.com/post");
+        assert!(plain.link_previews.is_empty());
     }
 
     #[test]
-    fn test_mention_extraction() {
-        let message = "Hello @all and @here users!";
-        let mentions = MessagePreprocessor::extract_mentions(message);
+    fn test_urls_from_tags_previews_and_bare_links() {
+        let message = concat!(
+            "see https://kether.pl/a_(b)). and (https://kether.pl/x) ",
+            "[url=https://steamcommunity.com]Steam[/url] [url]https://store.steampowered.com[/url] ",
+            r#"[og url="https://kether.pl/a_(b)" title="Kether"][/og] nothttps://x.y"#,
+        );
+        let urls = MessagePreprocessor::preprocess_message(message).urls;
 
-        assert!(mentions.is_some());
-        let mentions = mentions.unwrap();
-        assert!(mentions.mention_all);
-        assert!(mentions.mention_here);
+        assert_eq!(
+            urls,
+            vec![
+                ExtractedUrl {
+                    url: "https://kether.pl/a_(b)".to_string(),
+                    from_og: false,
+                    display_text: None,
+                },
+                ExtractedUrl {
+                    url: "https://kether.pl/x".to_string(),
+                    from_og: false,
+                    display_text: None,
+                },
+                ExtractedUrl {
+                    url: "https://steamcommunity.com".to_string(),
+                    from_og: false,
+                    display_text: Some("Steam".to_string()),
+                },
+                ExtractedUrl {
+                    url: "https://store.steampowered.com".to_string(),
+                    from_og: false,
+                    display_text: None,
+                },
+                ExtractedUrl {
+                    url: "https://kether.pl/a_(b)".to_string(),
+                    from_og: true,
+                    display_text: Some("Kether".to_string()),
+                },
+            ]
+        );
     }
 
     #[test]
-    fn test_message_preprocessing() {
-        let message = "Hello @all with [b]bold[/b] text";
-        let preprocessed = MessagePreprocessor::preprocess_message(message);
+    fn test_at_prefixed_steam_id_mentions() {
+        let mention = helpers::create_mention(SteamID::from(76561199491325083));
+        let preprocessed = MessagePreprocessor::preprocess_message(&format!("hi {}!", mention));
+        let mentions = preprocessed.mentions.expect("mention detected");
+        assert_eq!(
+            u64::from(SteamID::from(mentions.mention_steamids[0])),
+            76561199491325083
+        );
+    }
 
-        assert_eq!(preprocessed.original_message, message);
-        assert!(preprocessed.mentions.is_some());
-        assert!(!preprocessed.message_bbcode_parsed.is_empty());
+    #[test]
+    fn test_slash_commands() {
+        let command = |message: &str| MessagePreprocessor::preprocess_message(message).command;
+        assert_eq!(command("/flip"), Some(ChatCommand::Flip));
+        assert_eq!(command("  /FLIP please"), Some(ChatCommand::Flip));
+        assert_eq!(
+            command("/random"),
+            Some(ChatCommand::Random { min: 1, max: 100 })
+        );
+        assert_eq!(
+            command("/random 6"),
+            Some(ChatCommand::Random { min: 1, max: 6 })
+        );
+        assert_eq!(
+            command("/random 10 - 20"),
+            Some(ChatCommand::Random { min: 10, max: 20 })
+        );
+        assert_eq!(command("/random 20-10"), None);
+        assert_eq!(command("/random lots"), None);
+        assert_eq!(
+            command("/giphy  happy\ncat "),
+            Some(ChatCommand::Giphy {
+                query: "happy cat".to_string()
+            })
+        );
+        assert_eq!(command("/giphy"), None);
+        assert_eq!(
+            command("/code\n    indented"),
+            Some(ChatCommand::Code {
+                text: "    indented".to_string()
+            })
+        );
+        assert_eq!(
+            command("/effect snow"),
+            Some(ChatCommand::Effect {
+                effect: RoomEffect::Snow
+            })
+        );
+        let emote = MessagePreprocessor::preprocess_message("/me waves");
+        assert!(emote.is_emote);
+        assert_eq!(
+            emote.command,
+            Some(ChatCommand::Me {
+                text: "waves".to_string()
+            })
+        );
+        assert_eq!(command("/meow"), None);
+        assert_eq!(command("/unknown"), None);
+        assert_eq!(command("a /flip"), None);
+
+        assert_eq!(command("//notacommand"), None);
+        assert_eq!(command("//flip"), None);
+        assert_eq!(helpers::escape_leading_slash("/flip"), "//flip");
+        assert_eq!(helpers::escape_leading_slash("  /flip"), "  //flip");
+        assert_eq!(helpers::escape_leading_slash("no slash"), "no slash");
+        assert_eq!(command(&helpers::escape_leading_slash("/flip")), None);
+
+        let commands = [
+            ChatCommand::Me {
+                text: "waves".to_string(),
+            },
+            ChatCommand::Random { min: 3, max: 3 },
+            ChatCommand::Flip,
+            ChatCommand::Code {
+                text: "\tfn main() {}\n".to_string(),
+            },
+            ChatCommand::Spoiler {
+                text: "the ending".to_string(),
+            },
+            ChatCommand::Effect {
+                effect: RoomEffect::Other("hearts".to_string()),
+            },
+        ];
+        for expected in commands {
+            let message = helpers::create_command_message(&expected).expect("valid command");
+            assert_eq!(ChatCommand::parse(&message), Some(expected));
+        }
+        assert_eq!(
+            helpers::create_command_message(&ChatCommand::Random { min: 1, max: 6 }).as_deref(),
+            Some("/random 1-6")
+        );
+        assert_eq!(
+            helpers::create_command_message(&ChatCommand::Giphy {
+                query: " a\n b ".to_string()
+            })
+            .as_deref(),
+            Some("/giphy a b")
+        );
+        assert!(helpers::create_command_message(&ChatCommand::Random { min: 2, max: 1 }).is_none());
+        assert!(
+            helpers::create_command_message(&ChatCommand::Spoiler {
+                text: " padded".to_string()
+            })
+            .is_none()
+        );
+        assert!(
+            helpers::create_command_message(&ChatCommand::Effect {
+                effect: RoomEffect::Other("x y".to_string())
+            })
+            .is_none()
+        );
+
+        let json = serde_json::to_string(&ChatCommand::Random { min: 1, max: 6 }).unwrap();
+        assert_eq!(json, r#"{"type":"random","min":1,"max":6}"#);
     }
 
+    const GOLDEN_MESSAGE: &str = "/me [spoiler]hi @[U:1:1531059355][/spoiler] [url=https://kether.pl]site[/url] ːsteamhappyː [quote=76561198012345678;1699999999;3]q[/quote]";
+
     #[test]
-    fn test_prepare_message_for_sending_preserves_brackets() {
-        let message = r"Look at \[b\]escaped brackets\[/b\]";
-        let prepared = MessagePreprocessor::prepare_message_for_sending(message);
-        assert_eq!(prepared, "Look at [b]escaped brackets[/b]");
+    fn test_json_schema_matches_golden_file() {
+        let golden: serde_json::Value =
+            serde_json::from_str(include_str!("../tests/golden/preprocessed_message_v1.json"))
+                .unwrap();
+        let message = MessagePreprocessor::preprocess_message(GOLDEN_MESSAGE);
+        let json = message.to_json().unwrap();
+        assert_eq!(
+            serde_json::from_str::<serde_json::Value>(&json).unwrap(),
+            golden
+        );
+
+        let restored = PreprocessedMessage::from_json(&golden.to_string()).unwrap();
+        assert_eq!(restored, message);
     }
 
     #[test]
-    fn test_process_response_round_trips_metadata() {
-        let original = "hello";
-        let modified = "hello world";
-        let processed = MessagePreprocessor::process_response(original, modified, 42, 7);
+    fn test_json_schema_reads_legacy_blobs() {
+        let legacy = include_str!("../tests/golden/preprocessed_message_legacy.json");
+        let restored = PreprocessedMessage::from_json(legacy).unwrap();
+        let current = MessagePreprocessor::preprocess_message(GOLDEN_MESSAGE);
+        assert_eq!(
+            restored.message_bbcode_parsed,
+            current.message_bbcode_parsed
+        );
+        assert_eq!(restored.plain_text(), current.plain_text());
+        assert!(restored.is_emote);
+        assert!(restored.quotes.is_empty() && restored.command.is_none());
 
-        assert_eq!(processed.original_message, original);
-        assert_eq!(processed.modified_message, modified);
-        assert_eq!(processed.server_timestamp, Some(42));
-        assert_eq!(processed.ordinal, Some(7));
+        let upgraded: serde_json::Value =
+            serde_json::from_str(&restored.to_json().unwrap()).unwrap();
+        assert_eq!(upgraded["schema_version"], 1);
+        assert_eq!(upgraded["message_bbcode_parsed"][0]["type"], "text");
+
+        let newer = r#"{"schema_version": 2, "original_message": "x"}"#;
+        assert!(matches!(
+            PreprocessedMessage::from_json(newer),
+            Err(SchemaError::UnsupportedVersion {
+                found: 2,
+                supported: 1
+            })
+        ));
+        assert!(matches!(
+            PreprocessedMessage::from_json("[]"),
+            Err(SchemaError::Json(_))
+        ));
     }
 
     #[test]
-    fn test_bbcode_parser_rejects_unknown_tags() {
-        let parser = bbcode::Parser::new(&["b"]);
-        let parsed = parser.parse("[unknown]value[/unknown]");
+    fn test_ast_iterators_and_visitor() {
+        let parsed = MessagePreprocessor::parse_bbcode(
+            "a [quote=1]b [spoiler]c [url=x]d[/url][/spoiler][/quote] e [code]f[/code]",
+        );
+        let tags: Vec<&str> = parsed
+            .iter()
+            .flat_map(BBCodeContent::iter_nodes)
+            .map(|node| node.tag.as_str())
+            .collect();
+        assert_eq!(tags, ["quote", "spoiler", "url", "code"]);
+        let texts: Vec<&str> = parsed.iter().flat_map(BBCodeContent::iter_text).collect();
+        assert_eq!(texts, ["a ", "b ", "c ", "d", " e ", "f"]);
+
+        struct Rewrite;
+        impl Visitor for Rewrite {
+            fn visit_node(&mut self, node: &BBCodeNode) -> Vec<BBCodeContent> {
+                match node.tag.as_str() {
+                    "url" => vec![],
+                    "spoiler" => self.visit_all(node.content.as_deref().unwrap_or_default()),
+                    _ => vec![BBCodeContent::Node(self.walk_node(node))],
+                }
+            }
 
-        assert_eq!(parsed.len(), 1);
-        assert!(matches!(parsed[0], BBCodeContent::String(_)));
+            fn visit_text(&mut self, text: &str) -> Vec<BBCodeContent> {
+                vec![BBCodeContent::String(text.to_uppercase())]
+            }
+        }
+        assert_eq!(
+            Rewrite.visit_all(&parsed),
+            [
+                text("A "),
+                node("quote", &[("value", "1")], Some(vec![text("B C ")])),
+                text(" E "),
+                node("code", &[], Some(vec![text("F")])),
+            ]
+        );
+
+        struct Identity;
+        impl Visitor for Identity {}
+        assert_eq!(Identity.visit_all(&parsed), parsed);
     }
 
     #[test]
-    fn test_bbcode_parser_handles_unicode_near_tags() {
-        let parser = bbcode::Parser::new(&["spoiler"]);
-        let parsed = parser.parse("zażółć🙂[spoiler]gęślą jaźń[/spoiler]世界");
+    fn test_emote_detection() {
+        assert!(MessagePreprocessor::preprocess_message("/me waves").is_emote);
+        assert!(MessagePreprocessor::preprocess_message("  /me").is_emote);
+        assert!(!MessagePreprocessor::preprocess_message("/meow").is_emote);
+        assert!(!MessagePreprocessor::preprocess_message("say /me waves").is_emote);
+        assert_eq!(
+            MessagePreprocessor::strip_emote_prefix("/me  waves hello"),
+            Some("waves hello")
+        );
+    }
 
-        assert!(parsed.len() >= 2);
-        assert!(matches!(&parsed[0], BBCodeContent::String(text) if text == "zażółć🙂"));
-        assert!(parsed.iter().any(|content| {
-            matches!(content, BBCodeContent::Node(node) if node.tag == "spoiler")
-        }));
-        assert!(parsed.iter().any(|content| {
-            matches!(content, BBCodeContent::String(text) if text.contains("世界"))
-        }));
+    #[test]
+    fn test_create_emote_message_is_idempotent() {
+        assert_eq!(helpers::create_emote_message("waves"), "/me waves");
+        assert_eq!(helpers::create_emote_message("/me waves"), "/me waves");
     }
 
     #[test]
@@ -605,13 +7599,13 @@ mod tests {
             mention_all: true,
             mention_here: false,
             mention_steamids: vec![MentionSteamId::from(steam_id)],
+            ..Default::default()
         };
 
         let json = serde_json::to_string(&mentions).expect("serialize mentions");
         let decoded: ChatMentions = serde_json::from_str(&json).expect("deserialize mentions");
 
-        assert_eq!(decoded.mention_steamids.len(), 1);
-        assert_eq!(SteamID::from(decoded.mention_steamids[0]), steam_id);
+        assert_eq!(decoded, mentions);
     }
 
     #[test]
@@ -619,67 +7613,541 @@ mod tests {
         struct Case<'a> {
             name: &'a str,
             message: &'a str,
-            min_node_count: usize,
-            expect_all: bool,
-            expect_here: bool,
-            expected_steam_ids: usize,
+            parsed: Vec<BBCodeContent>,
+            mentions: Option<ChatMentions>,
         }
 
         let cases = [
             Case {
                 name: "nested_bbcode",
                 message: "Nested [spoiler]outer [code]inner[/code][/spoiler] tags",
-                min_node_count: 1,
-                expect_all: false,
-                expect_here: false,
-                expected_steam_ids: 0,
+                parsed: vec![
+                    text("Nested "),
+                    node(
+                        "spoiler",
+                        &[],
+                        Some(vec![
+                            text("outer "),
+                            node("code", &[], Some(vec![text("inner")])),
+                        ]),
+                    ),
+                    text(" tags"),
+                ],
+                mentions: None,
             },
             Case {
                 name: "invalid_mention_inside_word",
                 message: "email@all.com should not ping everyone",
-                min_node_count: 0,
-                expect_all: false,
-                expect_here: false,
-                expected_steam_ids: 0,
+                parsed: vec![text("email@all.com should not ping everyone")],
+                mentions: None,
             },
             Case {
                 name: "multilingual_mentions",
                 message: "こんにちは @here друзья [U:1:1531059355]",
-                min_node_count: 0,
-                expect_all: false,
-                expect_here: true,
-                expected_steam_ids: 1,
+                parsed: vec![text("こんにちは @here друзья [U:1:1531059355]")],
+                mentions: Some(ChatMentions {
+                    mention_all: false,
+                    mention_here: true,
+                    mention_steamids: vec![MentionSteamId::from(SteamID::from(
+                        76561199491325083u64,
+                    ))],
+                    spans: vec![
+                        MentionSpan {
+                            kind: MentionKind::Here,
+                            byte_range: 16..21,
+                        },
+                        MentionSpan {
+                            kind: MentionKind::SteamId(MentionSteamId::from(SteamID::from(
+                                76561199491325083u64,
+                            ))),
+                            byte_range: 35..51,
+                        },
+                    ],
+                    ..Default::default()
+                }),
             },
         ];
 
         for case in cases {
             let preprocessed = MessagePreprocessor::preprocess_message(case.message);
-            let node_count = preprocessed
+            assert_eq!(
+                preprocessed.message_bbcode_parsed, case.parsed,
+                "{}",
+                case.name
+            );
+            assert_eq!(preprocessed.mentions, case.mentions, "{}", case.name);
+        }
+    }
+
+    fn template_samples() -> HashMap<&'static str, String> {
+        HashMap::from([
+            ("mention_all", "@all".to_string()),
+            ("title", "Weekly Cup".to_string()),
+            ("time", "18:00 UTC".to_string()),
+            ("link", "https://kether.pl/cup".to_string()),
+        ])
+    }
+
+    #[test]
+    fn test_template_renders_and_escapes_values() {
+        let template = MessageTemplate::new(
+            "{mention_all} {title} starts at {time} [url={link}]details[/url] {{raw}}",
+            &template_samples(),
+        )
+        .expect("valid template");
+        assert_eq!(
+            template.placeholders(),
+            vec!["mention_all", "title", "time", "link"]
+        );
+
+        let mut values = template_samples();
+        values.insert("title", "[img]x[/img] by @[U:1:1531059355]".to_string());
+        let rendered = template.render(&values).unwrap();
+        assert_eq!(
+            rendered,
+            "@\u{2060}all \\[img\\]x\\[/img\\] by @[U:1:1531059355] starts at 18:00 UTC \
+             [url=https://kether.pl/cup]details[/url] {raw}"
+        );
+        let mentions = MessagePreprocessor::extract_mentions(&rendered).expect("user mention");
+        assert!(!mentions.mention_all);
+        assert_eq!(mentions.mention_steamids.len(), 1);
+
+        values.insert("title", "@Here, @HERE and mail@here.example".to_string());
+        let rendered = template.render(&values).unwrap();
+        assert!(rendered.contains("@\u{2060}Here, @\u{2060}HERE and mail@\u{2060}here.example"));
+        assert_eq!(MessagePreprocessor::extract_mentions(&rendered), None);
+
+        values.remove("time");
+        assert_eq!(
+            template.render(&values),
+            Err(TemplateError::MissingValue("time".to_string()))
+        );
+    }
+
+    #[test]
+    fn test_escape_bbcode_keeps_mentions() {
+        let text = "@[U:1:1531059355] [img]x[/img] [url=https://x]y[/url] \\[ @all";
+        let escaped = helpers::escape_bbcode(text);
+        assert_eq!(
+            escaped,
+            "@[U:1:1531059355] \\[img\\]x\\[/img\\] \\[url=https://x\\]y\\[/url\\] \\\\[ @all"
+        );
+        assert_eq!(helpers::unescape_bbcode(&escaped), text);
+
+        let preprocessed = MessagePreprocessor::preprocess_message(&escaped);
+        let mentions = preprocessed
+            .mentions
+            .clone()
+            .expect("mentions survive escaping");
+        assert!(mentions.mention_all);
+        assert_eq!(mentions.mention_steamids.len(), 1);
+        assert!(
+            !preprocessed
                 .message_bbcode_parsed
                 .iter()
-                .filter(|content| matches!(content, BBCodeContent::Node(_)))
-                .count();
-            assert!(node_count >= case.min_node_count, "{}", case.name);
-
-            match preprocessed.mentions {
-                Some(ref mentions) => {
-                    assert_eq!(mentions.mention_all, case.expect_all, "{}", case.name);
-                    assert_eq!(mentions.mention_here, case.expect_here, "{}", case.name);
-                    assert_eq!(
-                        mentions.mention_steamids.len(),
-                        case.expected_steam_ids,
-                        "{}",
-                        case.name
-                    );
-                }
-                None => {
-                    assert!(
-                        !case.expect_all && !case.expect_here && case.expected_steam_ids == 0,
-                        "{} expected mentions",
-                        case.name
-                    );
-                }
+                .any(|content| matches!(content, BBCodeContent::Node(_)))
+        );
+        assert_eq!(preprocessed.plain_text(), text);
+        assert_eq!(MessagePreprocessor::validate_only(&escaped), Ok(()));
+
+        assert_eq!(
+            helpers::format_with_bbcode("[/spoiler]@[U:1:1531059355]", BBCODE_TYPE_SPOILER, ""),
+            "[spoiler]\\[/spoiler\\]@[U:1:1531059355][/spoiler]"
+        );
+        assert_eq!(
+            helpers::format_with_bbcode_unescaped("[code]x[/code]", BBCODE_TYPE_SPOILER, ""),
+            "[spoiler][code]x[/code][/spoiler]"
+        );
+    }
+
+    #[test]
+    fn test_format_with_bbcode_types_and_layers() {
+        assert_eq!(
+            helpers::format_with_bbcode("well said", BBCODE_TYPE_QUOTE, "76561198012345678;1;2"),
+            "[quote=76561198012345678;1;2]well said[/quote]"
+        );
+        assert_eq!(
+            helpers::format_with_bbcode("plain", BBCODE_TYPE_QUOTE, ""),
+            "[quote]plain[/quote]"
+        );
+        assert_eq!(
+            helpers::format_with_bbcode("https://kether.pl/a.png", BBCODE_TYPE_IMG, ""),
+            "[img]https://kether.pl/a.png[/img]"
+        );
+        assert_eq!(
+            helpers::format_with_bbcode("let v = a[0];", BBCODE_TYPE_PRE, ""),
+            "[pre]let v = a[0];[/pre]"
+        );
+        assert_eq!(
+            helpers::format_with_bbcode("x [/pre]", BBCODE_TYPE_PRE, ""),
+            "[pre]x \\[/pre\\][/pre]"
+        );
+        assert_eq!(
+            helpers::format_with_bbcode("", BBCODE_TYPE_RANDOM, "2-12"),
+            r#"[random min="2" max="12"][/random]"#
+        );
+        assert_eq!(
+            helpers::format_with_bbcode("", BBCODE_TYPE_RANDOM, ""),
+            r#"[random min="1" max="100"][/random]"#
+        );
+        assert_eq!(
+            helpers::format_with_bbcode("", BBCODE_TYPE_FLIP, ""),
+            "[flip][/flip]"
+        );
+
+        let quote = helpers::format_with_bbcode("hi", BBCODE_TYPE_QUOTE, "76561198012345678");
+        let quotes = MessagePreprocessor::preprocess_message(&quote).quotes;
+        assert_eq!(quotes[0].author.map(u64::from), Some(76561198012345678));
+
+        assert_eq!(
+            helpers::try_format_with_bbcode("x", "sub", ""),
+            Err(FormatError::UnsupportedType("sub".to_string()))
+        );
+        assert!(matches!(
+            helpers::try_format_with_bbcode("x", BBCODE_TYPE_URL, "a]b"),
+            Err(FormatError::InvalidValue { .. })
+        ));
+        assert!(helpers::try_format_with_bbcode("", BBCODE_TYPE_RANDOM, "9-1").is_err());
+        assert_eq!(helpers::format_with_bbcode("[x]", "sub", ""), "\\[x\\]");
+
+        assert_eq!(
+            helpers::format_with_bbcode_layers(
+                "fn main() {}",
+                &[(BBCODE_TYPE_SPOILER, ""), (BBCODE_TYPE_CODE, "")]
+            ),
+            Ok("[spoiler][code]fn main() {}[/code][/spoiler]".to_string())
+        );
+        assert_eq!(
+            helpers::format_with_bbcode_layers(
+                "[b]",
+                &[
+                    (BBCODE_TYPE_URL, "https://kether.pl"),
+                    (BBCODE_TYPE_SPOILER, "")
+                ]
+            ),
+            Ok("[url=https://kether.pl][spoiler]\\[b\\][/spoiler][/url]".to_string())
+        );
+        assert_eq!(
+            helpers::format_with_bbcode_layers("x", &[("sub", ""), (BBCODE_TYPE_CODE, "")]),
+            Err(FormatError::UnsupportedType("sub".to_string()))
+        );
+    }
+
+    #[test]
+    fn test_spoiler_helpers() {
+        let message = "see [spoiler]the [url=https://x.example]link[/url] [spoiler]twist[/spoiler][/spoiler] and [code][spoiler]no[/spoiler][/code]";
+        let preprocessed = MessagePreprocessor::preprocess_message(message);
+        assert!(preprocessed.has_spoilers());
+        assert_eq!(preprocessed.spoiler_texts(), ["the link twist", "twist"]);
+        assert!(
+            !MessagePreprocessor::preprocess_message("[code][spoiler]x[/spoiler][/code]")
+                .has_spoilers()
+        );
+
+        let wrapped = helpers::spoiler_wrap("[b]nsfw[/b]");
+        assert_eq!(wrapped, "[spoiler]\\[b\\]nsfw\\[/b\\][/spoiler]");
+        assert_eq!(
+            MessagePreprocessor::preprocess_message(&wrapped).spoiler_texts(),
+            ["[b]nsfw[/b]"]
+        );
+
+        let long = "[".repeat(GROUP_CHAT_MESSAGE_LIMIT);
+        let wrapped = helpers::spoiler_wrap(&long);
+        assert!(MessagePreprocessor::validate_length(&wrapped).is_ok());
+        assert!(wrapped.ends_with("\\[…[/spoiler]"));
+        assert_eq!(MessagePreprocessor::validate_only(&wrapped), Ok(()));
+
+        let mention = helpers::create_mention(SteamID::try_from("[U:1:1531059355]").unwrap());
+        let long = format!("{}{}", "x".repeat(GROUP_CHAT_MESSAGE_LIMIT - 25), mention);
+        let wrapped = helpers::spoiler_wrap(&long);
+        assert!(MessagePreprocessor::validate_length(&wrapped).is_ok());
+        assert!(wrapped.ends_with("x@…[/spoiler]"), "{wrapped}");
+    }
+
+    #[test]
+    fn test_message_builder() {
+        let user = SteamID::try_from("[U:1:1531059355]").unwrap();
+        let builder = MessageBuilder::new()
+            .text("hi")
+            .mention(user)
+            .text("and")
+            .mention_all()
+            .text("! see [b]")
+            .url("https://kether.pl/a b]", "docs")
+            .newline()
+            .code("let x = v[0];")
+            .spoiler("[/spoiler] twist")
+            .emoticon("steamhappy")
+            .emoticon("not ok");
+        assert_eq!(
+            builder.as_str(),
+            "hi @[U:1:1531059355] and @all! see \\[b\\][url=https://kether.pl/a%20b%5D]docs[/url]\n\
+             [code]let x = v[0];[/code][spoiler]\\[/spoiler\\] twist[/spoiler]\
+             [emoticon:steamhappy]:not ok:"
+        );
+
+        let preview = builder.preview();
+        let mentions = MessagePreprocessor::extract_mentions(builder.as_str()).expect("mentions");
+        assert!(mentions.mention_all);
+        assert_eq!(mentions.mention_steamids, vec![MentionSteamId::from(user)]);
+        let tags: Vec<&str> = preview
+            .iter()
+            .filter_map(|content| match content {
+                BBCodeContent::Node(node) => Some(node.tag.as_str()),
+                BBCodeContent::String(_) => None,
+            })
+            .collect();
+        assert_eq!(tags, ["url", "code", "spoiler", "emoticon"]);
+        assert_eq!(
+            MessagePreprocessor::strip_bbcode(builder.as_str()),
+            "hi @[U:1:1531059355] and @all! see [b]docs let x = v[0];[/spoiler] twist:steamhappy::not ok:"
+        );
+
+        let code = MessageBuilder::new().code("a [/code] b").build().unwrap();
+        let parsed = MessagePreprocessor::parse_bbcode(&code);
+        assert_eq!(
+            parsed.len(),
+            1,
+            "closing tag inside code is escaped: {code}"
+        );
+
+        let err = MessageBuilder::new()
+            .with_length_limit(3)
+            .text("four")
+            .build()
+            .unwrap_err();
+        assert_eq!(err.excess(), 1);
+    }
+
+    #[test]
+    fn test_template_value_escaping_opt_out() {
+        let template = MessageTemplate::new("{title} starts soon", &template_samples())
+            .unwrap()
+            .with_value_escaping(false);
+        let values = HashMap::from([("title", "[spoiler]Finals[/spoiler]".to_string())]);
+        assert_eq!(
+            template.render(&values).unwrap(),
+            "[spoiler]Finals[/spoiler] starts soon"
+        );
+    }
+
+    #[test]
+    fn test_validate_length_counts_utf16_code_units() {
+        let at_limit = "x".repeat(GROUP_CHAT_MESSAGE_LIMIT);
+        assert_eq!(MessagePreprocessor::validate_length(&at_limit), Ok(()));
+
+        let err = MessagePreprocessor::validate_length(&format!("{at_limit}é")).unwrap_err();
+        assert_eq!(
+            (err.actual, err.excess()),
+            (GROUP_CHAT_MESSAGE_LIMIT + 1, 1)
+        );
+
+        let emoji = "🙂".repeat(GROUP_CHAT_MESSAGE_LIMIT / 2 + 1);
+        let err = MessagePreprocessor::validate_length(&emoji).unwrap_err();
+        assert_eq!(
+            err,
+            LengthError {
+                limit: GROUP_CHAT_MESSAGE_LIMIT,
+                actual: GROUP_CHAT_MESSAGE_LIMIT + 2,
+                counted_in: LengthUnit::Utf16CodeUnits,
             }
+        );
+        assert_eq!(
+            err.to_string(),
+            "message is 5002 UTF-16 code units long, 2 over the limit of 5000"
+        );
+
+        assert!(MessagePreprocessor::validate_length_within("hello", 4).is_err());
+    }
+
+    #[test]
+    fn test_template_validation_errors() {
+        let samples = template_samples();
+        assert_eq!(
+            MessageTemplate::new("{title", &samples),
+            Err(TemplateError::UnclosedPlaceholder { position: 0 })
+        );
+        assert_eq!(
+            MessageTemplate::new("a } b", &samples),
+            Err(TemplateError::UnmatchedBrace { position: 2 })
+        );
+        assert!(matches!(
+            MessageTemplate::new("{bad name}", &samples),
+            Err(TemplateError::InvalidPlaceholder { .. })
+        ));
+        assert_eq!(
+            MessageTemplate::new("[sub]{title}[/sub]", &samples),
+            Err(TemplateError::DisallowedTag("sub".to_string()))
+        );
+        assert_eq!(
+            MessageTemplate::new("{unknown}", &samples),
+            Err(TemplateError::MissingValue("unknown".to_string()))
+        );
+
+        let long = HashMap::from([("title", "x".repeat(MAX_MESSAGE_LEN))]);
+        assert_eq!(
+            MessageTemplate::new("! {title}", &long),
+            Err(TemplateError::TooLong {
+                limit: MAX_MESSAGE_LEN,
+                actual: MAX_MESSAGE_LEN + 2,
+            })
+        );
+    }
+
+    #[test]
+    fn test_validate_only_accepts_supported_markup() {
+        for message in [
+            "plain text",
+            "[url=https://kether.pl]site[/url] and [spoiler]secret[/spoiler]",
+            "[sticker type=\"ChromaticCat\" limit=\"0\"][/sticker]",
+            "[tradeofferlink partner=12345 token=AbCdEf12][/tradeofferlink]",
+            "[code][sub]not markup[/code]",
+            "hi @[U:1:1531059355]",
+            r"escaped \[sub\]",
+        ] {
+            assert_eq!(
+                MessagePreprocessor::validate_only(message),
+                Ok(()),
+                "{}",
+                message
+            );
         }
     }
+
+    #[test]
+    fn test_validate_only_reports_position_and_reason() {
+        let err = MessagePreprocessor::validate_only("hi [sub]x[/sub]").unwrap_err();
+        assert_eq!(err.position, 3);
+        assert!(err.reason.contains("unsupported tag [sub]"));
+
+        let err = MessagePreprocessor::validate_only("see [url=https://x").unwrap_err();
+        assert_eq!((err.position, err.reason.as_str()), (4, "unterminated tag"));
+
+        let err = MessagePreprocessor::validate_only("[spoiler]x").unwrap_err();
+        assert_eq!(
+            (err.position, err.reason.as_str()),
+            (0, "[spoiler] is not closed")
+        );
+
+        let err = MessagePreprocessor::validate_only("x[/url]").unwrap_err();
+        assert_eq!(err.position, 1);
+
+        let err = MessagePreprocessor::validate_only("[url=]x[/url]").unwrap_err();
+        assert_eq!(err.reason, "missing attribute value");
+
+        let err = MessagePreprocessor::validate_only("[sticker type=\"Cat][/sticker]").unwrap_err();
+        assert!(err.reason.starts_with("unterminated value"));
+    }
+
+    #[test]
+    fn test_validate_bbcode_reports_every_problem() {
+        let message = "żółw [url=]ok[/url] [spoiler]🙂 [sub] [/quote] [code][x][/code] [quote]";
+        let diagnostics = MessagePreprocessor::validate_bbcode(message);
+        let found: Vec<_> = diagnostics
+            .iter()
+            .map(|diagnostic| (diagnostic.kind, &message[diagnostic.byte_range.clone()]))
+            .collect();
+        assert_eq!(
+            found,
+            [
+                (BBCodeDiagnosticKind::BadAttribute, "[url=]"),
+                (BBCodeDiagnosticKind::UnclosedTag, "[spoiler]"),
+                (BBCodeDiagnosticKind::UnknownTag, "[sub]"),
+                (BBCodeDiagnosticKind::StrayCloser, "[/quote]"),
+                (BBCodeDiagnosticKind::UnclosedTag, "[quote]"),
+            ]
+        );
+        assert_eq!(
+            MessagePreprocessor::validate_only(message),
+            Err(BBCodeError::from(diagnostics[0].clone()))
+        );
+
+        let diagnostics = MessagePreprocessor::validate_bbcode("ok [url=https://ż");
+        assert_eq!(diagnostics[0].byte_range, 3..18);
+        assert_eq!(diagnostics[0].kind, BBCodeDiagnosticKind::UnclosedTag);
+        assert!(MessagePreprocessor::validate_bbcode("[url=https://x]y[/url]").is_empty());
+    }
+
+    #[test]
+    fn test_server_changes() {
+        let original = "hi :steamhappy: see https://kether.pl darn";
+        let modified = "hi ːsteamhappyː see https://kether.pl ♥♥♥♥ \
+                        [og url=\"https://kether.pl\" title=\"Kether\"][/og]";
+        let processed = MessagePreprocessor::process_response(original, modified, 0, 0);
+        let found: Vec<_> = processed
+            .server_changes()
+            .into_iter()
+            .map(|change| {
+                (
+                    change.kind,
+                    &original[change.original_span],
+                    &modified[change.modified_span],
+                )
+            })
+            .collect();
+        assert_eq!(
+            found,
+            [
+                (
+                    MessageChangeKind::EmoticonNormalized,
+                    ":steamhappy:",
+                    "ːsteamhappyː"
+                ),
+                (MessageChangeKind::TextAltered, "darn", "♥♥♥♥"),
+                (
+                    MessageChangeKind::LinkExpanded,
+                    "",
+                    " [og url=\"https://kether.pl\" title=\"Kether\"][/og]"
+                ),
+            ]
+        );
+
+        let processed = MessagePreprocessor::process_response(
+            "https://kether.pl",
+            "[url=https://kether.pl]https://kether.pl[/url]",
+            0,
+            0,
+        );
+        assert!(
+            processed
+                .server_changes()
+                .iter()
+                .all(|change| change.kind == MessageChangeKind::LinkExpanded)
+        );
+        assert!(
+            MessagePreprocessor::preprocess_message("same")
+                .server_changes()
+                .is_empty()
+        );
+    }
+
+    #[test]
+    fn test_tag_contexts() {
+        assert!(!FRIEND_CHAT_TAGS.contains(&BBCODE_TYPE_ROOMEFFECT));
+        assert_eq!(
+            MessagePreprocessor::for_context(TagContext::GroupChat),
+            MessagePreprocessor::default()
+        );
+        let custom = MessagePreprocessor::for_context(TagContext::Custom(&["URL"]));
+        assert_eq!(custom.allowed_tags(), ["url"]);
+
+        let message = "[wip] [roomeffect type=\"confetti\"][/roomeffect] [sub]x[/sub]";
+        let found: Vec<_> =
+            MessagePreprocessor::validate_for_context(message, TagContext::FriendChat)
+                .into_iter()
+                .map(|diagnostic| &message[diagnostic.byte_range])
+                .collect();
+        assert_eq!(found, ["[roomeffect type=\"confetti\"]", "[/roomeffect]"]);
+        assert!(
+            MessagePreprocessor::validate_for_context(message, TagContext::GroupChat).is_empty()
+        );
+        assert_eq!(
+            MessagePreprocessor::validate_for_context(
+                "[spoiler]x[/spoiler]",
+                TagContext::Custom(&["url"])
+            )
+            .len(),
+            2
+        );
+    }
 }
@@ -0,0 +1,230 @@
+// SPDX-License-Identifier: LGPL-3.0-only
+
+use crate::catchup::MessagePosition;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Mutex, MutexGuard};
+use tokio::sync::Notify;
+
+/// Unread messages remembered per room; older ones are forgotten past this limit.
+pub const MAX_TRACKED_UNREAD: usize = 10_000;
+
+/// Per-room read cursors and unread counts.
+///
+/// Install one with `ChatRoomClient::with_read_state`. Group messages from other users
+/// seen by the client's listeners and streams are recorded as unread, and
+/// `ack_chat_message` marks everything up to the acknowledged timestamp as read, as do the
+/// automatic acks of `NotificationOptions::auto_ack`. Steam acknowledges by timestamp, so
+/// all messages sharing that timestamp are read together.
+///
+/// The tracker is serde-serializable so counts survive restarts.
+///
+/// # Example
+///
+/// ```
+/// use SC_Sub_Poster::{MessagePosition, ReadState};
+///
+/// let state = ReadState::new();
+/// state.observe(1, 2, MessagePosition { timestamp: 10, ordinal: 0 });
+/// state.observe(1, 2, MessagePosition { timestamp: 11, ordinal: 0 });
+/// state.acknowledge(1, 2, 10);
+/// assert_eq!(state.unread_count(1, 2), 1);
+///
+/// let saved = serde_json::to_string(&state).unwrap();
+/// let restored: ReadState = serde_json::from_str(&saved).unwrap();
+/// assert_eq!(restored.unread_count(1, 2), 1);
+/// ```
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct ReadState {
+    rooms: Mutex<BTreeMap<u64, BTreeMap<u64, RoomReadState>>>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+struct RoomReadState {
+    acked_timestamp: Option<u32>,
+    unread: BTreeSet<MessagePosition>,
+}
+
+impl ReadState {
+    /// Create a tracker with no rooms.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Record a message seen in a room; it counts as unread until acknowledged.
+    ///
+    /// Messages at or before the room's acknowledged timestamp are ignored.
+    pub fn observe(&self, chat_group_id: u64, chat_id: u64, position: MessagePosition) {
+        let mut rooms = self.rooms();
+        let room = rooms
+            .entry(chat_group_id)
+            .or_default()
+            .entry(chat_id)
+            .or_default();
+        if room
+            .acked_timestamp
+            .is_some_and(|acked| position.timestamp <= acked)
+        {
+            return;
+        }
+        room.unread.insert(position);
+        if room.unread.len() > MAX_TRACKED_UNREAD {
+            room.unread.pop_first();
+        }
+    }
+
+    /// Mark every message in a room up to and including `timestamp` as read.
+    ///
+    /// Acknowledgements never move backwards.
+    pub fn acknowledge(&self, chat_group_id: u64, chat_id: u64, timestamp: u32) {
+        let mut rooms = self.rooms();
+        let room = rooms
+            .entry(chat_group_id)
+            .or_default()
+            .entry(chat_id)
+            .or_default();
+        let acked = room
+            .acked_timestamp
+            .map_or(timestamp, |acked| acked.max(timestamp));
+        room.acked_timestamp = Some(acked);
+        room.unread.retain(|position| position.timestamp > acked);
+    }
+
+    /// Newest acknowledged timestamp in a room, if any.
+    pub fn last_acknowledged(&self, chat_group_id: u64, chat_id: u64) -> Option<u32> {
+        self.rooms()
+            .get(&chat_group_id)
+            .and_then(|rooms| rooms.get(&chat_id))
+            .and_then(|room| room.acked_timestamp)
+    }
+
+    /// Number of observed messages newer than the last acknowledgement in a room.
+    ///
+    /// Capped at `MAX_TRACKED_UNREAD`.
+    pub fn unread_count(&self, chat_group_id: u64, chat_id: u64) -> usize {
+        self.rooms()
+            .get(&chat_group_id)
+            .and_then(|rooms| rooms.get(&chat_id))
+            .map_or(0, |room| room.unread.len())
+    }
+
+    fn rooms(&self) -> MutexGuard<'_, BTreeMap<u64, BTreeMap<u64, RoomReadState>>> {
+        self.rooms
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
+
+/// Handled group messages waiting to be acknowledged, newest timestamp per room.
+///
+/// Filled by listeners when `NotificationOptions::auto_ack` is set and drained by a
+/// background task, so a busy room costs one ack per batch instead of one per message.
+#[derive(Debug, Default)]
+pub(crate) struct PendingAcks {
+    newest: Mutex<HashMap<(u64, u64), u32>>,
+    wake: Notify,
+    closed: AtomicBool,
+}
+
+impl PendingAcks {
+    /// Queue an acknowledgement of a room up to `timestamp`.
+    pub(crate) fn record(&self, chat_group_id: u64, chat_id: u64, timestamp: u32) {
+        self.newest()
+            .entry((chat_group_id, chat_id))
+            .and_modify(|newest| *newest = (*newest).max(timestamp))
+            .or_insert(timestamp);
+        self.wake.notify_one();
+    }
+
+    /// Wait for queued acknowledgements and take them all.
+    ///
+    /// # Returns
+    ///
+    /// `None` once `close` was called and everything queued before it was taken.
+    pub(crate) async fn next_batch(&self) -> Option<HashMap<(u64, u64), u32>> {
+        loop {
+            // Read before taking, so nothing recorded ahead of `close` is left behind.
+            let closed = self.closed.load(Ordering::Acquire);
+            let batch = std::mem::take(&mut *self.newest());
+            if !batch.is_empty() {
+                return Some(batch);
+            }
+            if closed {
+                return None;
+            }
+            self.wake.notified().await;
+        }
+    }
+
+    /// Stop accepting work; `next_batch` hands out what is left, then returns `None`.
+    pub(crate) fn close(&self) {
+        self.closed.store(true, Ordering::Release);
+        self.wake.notify_one();
+    }
+
+    fn newest(&self) -> MutexGuard<'_, HashMap<(u64, u64), u32>> {
+        self.newest
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn at(timestamp: u32, ordinal: u32) -> MessagePosition {
+        MessagePosition { timestamp, ordinal }
+    }
+
+    #[test]
+    fn acknowledging_clears_older_messages_only() {
+        let state = ReadState::new();
+        state.observe(1, 2, at(10, 0));
+        state.observe(1, 2, at(10, 1));
+        state.observe(1, 2, at(12, 0));
+        state.observe(1, 3, at(10, 0));
+        assert_eq!(state.unread_count(1, 2), 3);
+
+        state.acknowledge(1, 2, 10);
+        assert_eq!(state.unread_count(1, 2), 1);
+        assert_eq!(state.unread_count(1, 3), 1);
+        assert_eq!(state.last_acknowledged(1, 2), Some(10));
+
+        state.acknowledge(1, 2, 5);
+        assert_eq!(state.last_acknowledged(1, 2), Some(10));
+        state.observe(1, 2, at(9, 0));
+        assert_eq!(state.unread_count(1, 2), 1);
+    }
+
+    #[test]
+    fn repeated_observations_count_once() {
+        let state = ReadState::new();
+        state.observe(1, 2, at(10, 0));
+        state.observe(1, 2, at(10, 0));
+        assert_eq!(state.unread_count(1, 2), 1);
+        assert_eq!(state.unread_count(9, 9), 0);
+    }
+
+    #[tokio::test]
+    async fn pending_acks_keep_the_newest_timestamp_per_room() {
+        let pending = PendingAcks::default();
+        pending.record(1, 2, 10);
+        pending.record(1, 2, 12);
+        pending.record(1, 2, 11);
+        pending.record(1, 3, 5);
+        assert_eq!(
+            pending.next_batch().await,
+            Some(HashMap::from([((1, 2), 12), ((1, 3), 5)]))
+        );
+
+        pending.record(1, 2, 13);
+        pending.close();
+        assert_eq!(
+            pending.next_batch().await,
+            Some(HashMap::from([((1, 2), 13)]))
+        );
+        assert_eq!(pending.next_batch().await, None);
+    }
+}
@@ -0,0 +1,193 @@
+// SPDX-License-Identifier: LGPL-3.0-only
+
+use crate::errors::KetherError;
+use crate::preprocessing::PreprocessedMessage;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU8, Ordering};
+use std::task::{Context, Poll};
+use std::time::{Duration, SystemTime};
+use thiserror::Error;
+use tokio::sync::Notify;
+use tokio::task::JoinHandle;
+use tracing::debug;
+
+/// Longest single sleep before the wall clock is consulted again.
+///
+/// Sleeping in slices keeps scheduled sends close to their target even when the system
+/// clock is adjusted while the task is waiting.
+const MAX_SLEEP_SLICE: Duration = Duration::from_secs(30);
+
+const STATE_PENDING: u8 = 0;
+const STATE_SENDING: u8 = 1;
+const STATE_FINISHED: u8 = 2;
+const STATE_CANCELLED: u8 = 3;
+
+/// Errors reported by a [`ScheduledHandle`].
+#[derive(Debug, Error)]
+pub enum ScheduleError {
+    /// The message was cancelled before it was dispatched.
+    #[error("scheduled message was cancelled")]
+    Cancelled,
+    /// The send itself failed.
+    #[error("scheduled message failed to send: {0}")]
+    Send(#[source] KetherError),
+    /// The background task panicked or was aborted.
+    #[error("scheduled message task failed: {0}")]
+    Task(#[from] tokio::task::JoinError),
+}
+
+/// Handle to a message scheduled with `ChatRoomClient::schedule_message`.
+///
+/// Awaiting the handle yields the send result once the target time has passed.
+#[derive(Debug)]
+pub struct ScheduledHandle {
+    state: Arc<AtomicU8>,
+    cancel: Arc<Notify>,
+    task: JoinHandle<Result<PreprocessedMessage, ScheduleError>>,
+}
+
+impl ScheduledHandle {
+    pub(crate) fn spawn<F, Fut>(at: SystemTime, send: F) -> Self
+    where
+        F: FnOnce() -> Fut + Send + 'static,
+        Fut: Future<Output = Result<PreprocessedMessage, KetherError>> + Send + 'static,
+    {
+        let state = Arc::new(AtomicU8::new(STATE_PENDING));
+        let cancel = Arc::new(Notify::new());
+        let task = tokio::spawn(run_scheduled(at, send, state.clone(), cancel.clone()));
+
+        Self {
+            state,
+            cancel,
+            task,
+        }
+    }
+
+    /// Cancel the message if it has not been dispatched yet.
+    ///
+    /// # Returns
+    ///
+    /// `true` if the message was cancelled, `false` if it is already being sent or finished.
+    pub fn cancel(&self) -> bool {
+        let cancelled = self
+            .state
+            .compare_exchange(
+                STATE_PENDING,
+                STATE_CANCELLED,
+                Ordering::AcqRel,
+                Ordering::Acquire,
+            )
+            .is_ok();
+        if cancelled {
+            self.cancel.notify_one();
+        }
+        cancelled
+    }
+
+    /// Whether the message is still waiting for its target time.
+    pub fn is_pending(&self) -> bool {
+        self.state.load(Ordering::Acquire) == STATE_PENDING
+    }
+}
+
+impl Future for ScheduledHandle {
+    type Output = Result<PreprocessedMessage, ScheduleError>;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        Pin::new(&mut self.task)
+            .poll(cx)
+            .map(|joined| joined.unwrap_or_else(|err| Err(ScheduleError::Task(err))))
+    }
+}
+
+async fn run_scheduled<F, Fut>(
+    at: SystemTime,
+    send: F,
+    state: Arc<AtomicU8>,
+    cancel: Arc<Notify>,
+) -> Result<PreprocessedMessage, ScheduleError>
+where
+    F: FnOnce() -> Fut,
+    Fut: Future<Output = Result<PreprocessedMessage, KetherError>>,
+{
+    loop {
+        let remaining = at
+            .duration_since(SystemTime::now())
+            .unwrap_or(Duration::ZERO);
+        if remaining.is_zero() {
+            break;
+        }
+
+        tokio::select! {
+            _ = tokio::time::sleep(remaining.min(MAX_SLEEP_SLICE)) => {}
+            _ = cancel.notified() => return Err(ScheduleError::Cancelled),
+        }
+    }
+
+    if state
+        .compare_exchange(
+            STATE_PENDING,
+            STATE_SENDING,
+            Ordering::AcqRel,
+            Ordering::Acquire,
+        )
+        .is_err()
+    {
+        return Err(ScheduleError::Cancelled);
+    }
+
+    debug!("dispatching scheduled message");
+    let result = send().await.map_err(ScheduleError::Send);
+    state.store(STATE_FINISHED, Ordering::Release);
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chatroom::ChatError;
+    use crate::errors::RetryDisposition;
+    use crate::preprocessing::MessagePreprocessor;
+
+    #[tokio::test]
+    async fn past_target_sends_immediately() {
+        let handle = ScheduledHandle::spawn(SystemTime::UNIX_EPOCH, || async {
+            Ok(MessagePreprocessor::preprocess_message("!sub"))
+        });
+
+        let sent = handle.await.expect("scheduled send");
+        assert_eq!(sent.original_message, "!sub");
+    }
+
+    #[tokio::test]
+    async fn cancel_before_target_skips_send() {
+        let at = SystemTime::now() + Duration::from_secs(3600);
+        let handle =
+            ScheduledHandle::spawn(at, || async { Err(KetherError::other("must not be sent")) });
+
+        assert!(handle.is_pending());
+        assert!(handle.cancel());
+        assert!(!handle.is_pending());
+        assert!(!handle.cancel());
+        assert!(matches!(handle.await, Err(ScheduleError::Cancelled)));
+    }
+
+    #[tokio::test]
+    async fn send_errors_are_reported() {
+        let handle = ScheduledHandle::spawn(SystemTime::UNIX_EPOCH, || async {
+            Err(KetherError::from(ChatError::InvalidInput(
+                "bad sticker".to_string(),
+            )))
+        });
+
+        match handle.await {
+            Err(ScheduleError::Send(err)) => {
+                assert!(matches!(err, KetherError::Chat(ChatError::InvalidInput(_))));
+                assert_eq!(err.inventory().disposition, RetryDisposition::Fatal);
+            }
+            other => panic!("unexpected result: {:?}", other),
+        }
+    }
+}
@@ -0,0 +1,183 @@
+// SPDX-License-Identifier: LGPL-3.0-only
+
+use crate::catchup::MessagePosition;
+use std::time::Duration;
+use tokio::sync::mpsc;
+
+/// Messages in a room appear to be missing between two received messages.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct GapDetected {
+    /// The room, as `(chat_group_id, chat_id)`.
+    pub room: (u64, u64),
+    /// The last message received before the gap.
+    pub from: MessagePosition,
+    /// The first message received after the gap.
+    pub to: MessagePosition,
+}
+
+/// Options for `ChatRoomClient::listen_for_group_messages_sequential`.
+///
+/// Steam numbers messages sharing a server timestamp with consecutive ordinals starting
+/// at 0, so a gap is reported when the ordinal jumps within one timestamp or a newer
+/// timestamp starts above ordinal 0. Messages missed across whole seconds cannot be
+/// detected this way.
+///
+/// Each room's queue holds up to `NotificationOptions::buffer_capacity` messages and
+/// handles overflow per `NotificationOptions::overflow`. A room's worker stops once the
+/// room has been quiet for the idle timeout and a new one starts with its next message;
+/// gap detection then starts afresh for that room.
+#[derive(Debug, Clone)]
+pub struct SequentialDispatch {
+    pub(crate) gap_events: Option<mpsc::UnboundedSender<GapDetected>>,
+    pub(crate) catch_up_gaps: bool,
+    pub(crate) idle_timeout: Duration,
+}
+
+impl Default for SequentialDispatch {
+    fn default() -> Self {
+        Self {
+            gap_events: None,
+            catch_up_gaps: false,
+            idle_timeout: Duration::from_secs(60),
+        }
+    }
+}
+
+impl SequentialDispatch {
+    /// Dispatch sequentially without reporting gaps anywhere but the log.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Send a `GapDetected` event to `sender` for every detected gap.
+    ///
+    /// # Returns
+    ///
+    /// `Self` for method chaining (builder pattern).
+    pub fn with_gap_events(mut self, sender: mpsc::UnboundedSender<GapDetected>) -> Self {
+        self.gap_events = Some(sender);
+        self
+    }
+
+    /// Fetch the missing messages from history and dispatch them before the message
+    /// that revealed the gap.
+    ///
+    /// # Returns
+    ///
+    /// `Self` for method chaining (builder pattern).
+    pub fn with_gap_catch_up(mut self, catch_up: bool) -> Self {
+        self.catch_up_gaps = catch_up;
+        self
+    }
+
+    /// Stop a room's worker after `timeout` without messages (60 seconds by default).
+    ///
+    /// # Returns
+    ///
+    /// `Self` for method chaining (builder pattern).
+    pub fn with_idle_timeout(mut self, timeout: Duration) -> Self {
+        self.idle_timeout = timeout;
+        self
+    }
+}
+
+/// Where a message falls relative to the last one a room handled.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Placement {
+    /// At or before the last handled position, e.g. a late copy of a caught-up message.
+    Seen,
+    /// Directly after the last handled position, or the room's first message.
+    Next,
+    /// After a gap following the given last handled position.
+    AfterGap(MessagePosition),
+}
+
+/// Tracks the last handled position of one room and spots gaps in its ordinals.
+#[derive(Debug, Default)]
+pub(crate) struct GapDetector {
+    last: Option<MessagePosition>,
+}
+
+impl GapDetector {
+    /// Place `next` relative to the last handled position, without recording it.
+    pub(crate) fn place(&self, next: MessagePosition) -> Placement {
+        let Some(last) = self.last else {
+            return Placement::Next;
+        };
+        if next <= last {
+            return Placement::Seen;
+        }
+
+        let missing = if next.timestamp == last.timestamp {
+            next.ordinal > last.ordinal + 1
+        } else {
+            next.ordinal > 0
+        };
+        if missing {
+            Placement::AfterGap(last)
+        } else {
+            Placement::Next
+        }
+    }
+
+    /// Record `handled` as handled; positions before the last one never move it back.
+    pub(crate) fn record(&mut self, handled: MessagePosition) {
+        self.last = Some(self.last.map_or(handled, |last| last.max(handled)));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn at(timestamp: u32, ordinal: u32) -> MessagePosition {
+        MessagePosition { timestamp, ordinal }
+    }
+
+    fn handle(detector: &mut GapDetector, position: MessagePosition) -> Placement {
+        let placement = detector.place(position);
+        detector.record(position);
+        placement
+    }
+
+    #[test]
+    fn ordinal_jumps_are_gaps() {
+        let mut detector = GapDetector::default();
+        assert_eq!(handle(&mut detector, at(10, 0)), Placement::Next);
+        assert_eq!(handle(&mut detector, at(10, 1)), Placement::Next);
+        assert_eq!(
+            handle(&mut detector, at(10, 3)),
+            Placement::AfterGap(at(10, 1))
+        );
+        assert_eq!(handle(&mut detector, at(12, 0)), Placement::Next);
+        assert_eq!(
+            handle(&mut detector, at(13, 2)),
+            Placement::AfterGap(at(12, 0))
+        );
+    }
+
+    #[test]
+    fn repeated_and_older_positions_are_seen() {
+        let mut detector = GapDetector::default();
+        detector.record(at(10, 2));
+        assert_eq!(detector.place(at(10, 2)), Placement::Seen);
+        assert_eq!(detector.place(at(9, 0)), Placement::Seen);
+        assert_eq!(detector.place(at(10, 3)), Placement::Next);
+    }
+
+    #[test]
+    fn caught_up_positions_are_seen_when_they_arrive_late() {
+        let mut detector = GapDetector::default();
+        detector.record(at(10, 0));
+        assert_eq!(detector.place(at(10, 3)), Placement::AfterGap(at(10, 0)));
+
+        // Catching up handles the missing messages before the one that revealed the gap.
+        detector.record(at(10, 1));
+        detector.record(at(10, 2));
+        detector.record(at(10, 3));
+        assert_eq!(detector.place(at(10, 2)), Placement::Seen);
+
+        detector.record(at(9, 0));
+        assert_eq!(detector.place(at(10, 4)), Placement::Next);
+    }
+}
@@ -0,0 +1,91 @@
+// SPDX-License-Identifier: LGPL-3.0-only
+
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tokio::sync::mpsc;
+
+/// Reported when a listener's notification stream stayed silent for the idle window.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct StreamStalled {
+    /// How long the stream had been silent.
+    pub idle: Duration,
+    /// Whether the connection answered the probe request.
+    ///
+    /// When `false` the listener ends with the probe's network error, so restartable
+    /// listeners resubscribe and others return it to the caller.
+    pub connection_alive: bool,
+}
+
+/// Idle detection for notification listeners.
+///
+/// Install one with `ChatRoomClient::with_watchdog`. When a listener receives nothing for
+/// `idle_window`, it sends a cheap request over the connection. If the request succeeds
+/// the listener keeps waiting; otherwise it ends with the network error. Either way a
+/// `StreamStalled` event is logged and sent to the configured channel.
+#[derive(Debug, Clone)]
+pub struct Watchdog {
+    pub(crate) idle_window: Duration,
+    pub(crate) events: Option<mpsc::UnboundedSender<StreamStalled>>,
+}
+
+impl Watchdog {
+    /// Probe the connection after `idle_window` without notifications.
+    pub fn new(idle_window: Duration) -> Self {
+        Self {
+            idle_window,
+            events: None,
+        }
+    }
+
+    /// Send a `StreamStalled` event to `sender` for every probe.
+    ///
+    /// # Returns
+    ///
+    /// `Self` for method chaining (builder pattern).
+    pub fn with_events(mut self, sender: mpsc::UnboundedSender<StreamStalled>) -> Self {
+        self.events = Some(sender);
+        self
+    }
+
+    /// The configured idle window.
+    pub fn idle_window(&self) -> Duration {
+        self.idle_window
+    }
+}
+
+/// Wall-clock time of the last notification received by a client.
+#[derive(Debug, Default)]
+pub(crate) struct Activity {
+    last_millis: AtomicU64,
+}
+
+impl Activity {
+    pub(crate) fn touch(&self) {
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |elapsed| elapsed.as_millis() as u64);
+        self.last_millis.fetch_max(now, Ordering::Relaxed);
+    }
+
+    pub(crate) fn last(&self) -> Option<SystemTime> {
+        match self.last_millis.load(Ordering::Relaxed) {
+            0 => None,
+            millis => Some(UNIX_EPOCH + Duration::from_millis(millis)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn activity_records_the_latest_touch() {
+        let activity = Activity::default();
+        assert!(activity.last().is_none());
+
+        let before = SystemTime::now() - Duration::from_millis(1);
+        activity.touch();
+        assert!(activity.last().is_some_and(|last| last >= before));
+    }
+}
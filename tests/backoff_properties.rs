@@ -0,0 +1,74 @@
+// SPDX-License-Identifier: LGPL-3.0-only
+
+//! Properties of `Backoff` delays over arbitrary parameters.
+
+use SC_Sub_Poster::{Backoff, JitterMode};
+use proptest::prelude::*;
+use std::time::Duration;
+
+fn backoff() -> impl Strategy<Value = Backoff> {
+    (0u64..10_000, 0u64..600_000, 0u32..8, any::<bool>()).prop_map(
+        |(initial, max, multiplier, linear)| {
+            let (initial, max) = (Duration::from_millis(initial), Duration::from_millis(max));
+            if linear {
+                Backoff::linear(initial, max)
+            } else {
+                Backoff::new(initial, max).with_multiplier(multiplier)
+            }
+        },
+    )
+}
+
+proptest! {
+    #[test]
+    fn delays_never_decrease_and_stop_at_the_cap(backoff in backoff()) {
+        let delays: Vec<_> = backoff.delays().take(64).collect();
+        for pair in delays.windows(2) {
+            prop_assert!(pair[0] <= pair[1], "{:?} then {:?}", pair[0], pair[1]);
+        }
+        prop_assert!(delays.iter().all(|delay| *delay <= backoff.max));
+    }
+
+    #[test]
+    fn full_jitter_stays_within_the_base_delay(
+        backoff in backoff(),
+        seed in any::<u64>(),
+        attempt in 1u32..64,
+    ) {
+        let jittered = backoff.with_jitter(JitterMode::Full).with_seed(seed);
+        let delay = jittered.delay_for(attempt);
+        prop_assert!(delay <= backoff.base_delay(attempt));
+        prop_assert_eq!(delay, jittered.delay_for(attempt), "seeded jitter is deterministic");
+
+        let unseeded = backoff.with_jitter(JitterMode::Full).delay_for(attempt);
+        prop_assert!(unseeded <= backoff.base_delay(attempt));
+    }
+
+    #[test]
+    fn equal_jitter_keeps_at_least_half(
+        backoff in backoff(),
+        seed in any::<u64>(),
+        attempt in 1u32..64,
+    ) {
+        let base = backoff.base_delay(attempt);
+        let delay = backoff
+            .with_jitter(JitterMode::Equal)
+            .with_seed(seed)
+            .delay_for(attempt);
+        prop_assert!(delay >= base / 2 && delay <= base, "{delay:?} outside {base:?}");
+    }
+}
+
+#[test]
+fn seeds_give_reproducible_sequences() {
+    let backoff = Backoff::new(Duration::from_millis(100), Duration::from_secs(10))
+        .with_jitter(JitterMode::Full)
+        .with_seed(7);
+    let first: Vec<_> = backoff.delays().take(8).collect();
+    let second: Vec<_> = backoff.delays().take(8).collect();
+    assert_eq!(first, second);
+    assert_ne!(
+        first,
+        backoff.with_seed(8).delays().take(8).collect::<Vec<_>>()
+    );
+}
@@ -0,0 +1,189 @@
+// SPDX-License-Identifier: LGPL-3.0-only
+
+//! Invariants documented on `MessagePreprocessor::parse`, checked on generated input and
+//! on the seed corpus of the `preprocess` fuzz target.
+
+use SC_Sub_Poster::preprocessing::{
+    BBCodeContent, MAX_NESTING_DEPTH, MalformedTagPolicy, MessagePreprocessor, MessageSegment,
+};
+use proptest::prelude::*;
+use std::time::{Duration, Instant};
+
+/// Message fragments already in canonical form, including broken and unknown markup.
+const PIECES: &[&str] = &[
+    "gg ",
+    "zaż ",
+    "🙂",
+    " ",
+    "\n",
+    "[",
+    "]",
+    "\\[",
+    "\\]",
+    "[]",
+    "[url=https://kether.pl]",
+    "[/url]",
+    "[spoiler]",
+    "[/spoiler]",
+    "[code]",
+    "[/code]",
+    "[img]",
+    "[/img]",
+    "[emoticon:steamhappy]",
+    "[quote=Some Name]",
+    "[/quote]",
+    "[sub]",
+    "[/sub]",
+    "@[U:1:1531059355]",
+    "@all ",
+    "[mention=here]",
+    "[/mention]",
+    "https://kether.pl/a_(b)).",
+];
+
+fn depth(parsed: &[BBCodeContent]) -> usize {
+    parsed
+        .iter()
+        .map(|content| match content {
+            BBCodeContent::Node(node) => 1 + node.content.as_deref().map_or(0, depth),
+            BBCodeContent::String(_) => 0,
+        })
+        .max()
+        .unwrap_or_default()
+}
+
+/// The message text covered by `segments`, in order.
+fn source_of(segments: &[MessageSegment]) -> String {
+    segments.iter().map(MessageSegment::source).collect()
+}
+
+fn corpus() -> Vec<(String, String)> {
+    let dir = concat!(env!("CARGO_MANIFEST_DIR"), "/fuzz/corpus/preprocess");
+    let mut inputs: Vec<_> = std::fs::read_dir(dir)
+        .expect("fuzz corpus is checked in")
+        .map(|entry| {
+            let path = entry.unwrap().path();
+            let data = std::fs::read(&path).unwrap();
+            (
+                path.file_name().unwrap().to_string_lossy().into_owned(),
+                String::from_utf8_lossy(&data).into_owned(),
+            )
+        })
+        .collect();
+    inputs.sort();
+    inputs
+}
+
+proptest! {
+    #[test]
+    fn preprocessing_never_panics(message in ".*") {
+        let preprocessed = MessagePreprocessor::preprocess_message(&message);
+        prop_assert_eq!(source_of(&preprocessed.segments()), message.as_str());
+        prop_assert_eq!(preprocessed.original_message, message.as_str());
+        let _ = MessagePreprocessor::validate_only(&message);
+    }
+
+    #[test]
+    fn invalid_utf8_never_panics(bytes in proptest::collection::vec(any::<u8>(), 0..512)) {
+        let message = String::from_utf8_lossy(&bytes);
+        MessagePreprocessor::preprocess_message(&message);
+    }
+
+    #[test]
+    fn keep_as_text_renders_back_to_the_input(
+        pieces in proptest::collection::vec(proptest::sample::select(PIECES), 0..48),
+    ) {
+        let message = pieces.concat();
+        let preprocessor = MessagePreprocessor::default()
+            .with_malformed_tag_policy(MalformedTagPolicy::KeepAsText);
+        let parsed = preprocessor.parse(&message);
+
+        prop_assert_eq!(MessagePreprocessor::render_bbcode(&parsed), message);
+        prop_assert!(depth(&parsed) <= MAX_NESTING_DEPTH + 1);
+    }
+
+    #[test]
+    fn every_policy_terminates(
+        pieces in proptest::collection::vec(proptest::sample::select(PIECES), 0..48),
+    ) {
+        let message = pieces.concat();
+        for policy in [
+            MalformedTagPolicy::KeepAsText,
+            MalformedTagPolicy::Drop,
+            MalformedTagPolicy::Error,
+        ] {
+            let preprocessor = MessagePreprocessor::default().with_malformed_tag_policy(policy);
+            let _ = preprocessor.try_parse(&message);
+            preprocessor.preprocess(&message);
+        }
+    }
+
+    #[test]
+    fn segments_concatenate_to_the_message(
+        pieces in proptest::collection::vec(proptest::sample::select(PIECES), 0..48),
+    ) {
+        let message = pieces.concat();
+        for policy in [
+            MalformedTagPolicy::KeepAsText,
+            MalformedTagPolicy::Drop,
+            MalformedTagPolicy::Error,
+        ] {
+            let preprocessed = MessagePreprocessor::default()
+                .with_malformed_tag_policy(policy)
+                .preprocess(&message);
+            prop_assert_eq!(source_of(&preprocessed.segments()), message.as_str());
+        }
+    }
+}
+
+#[test]
+fn corpus_inputs_round_trip() {
+    for (name, message) in corpus() {
+        let preprocessed = MessagePreprocessor::preprocess_message(&message);
+        let parsed = &preprocessed.message_bbcode_parsed;
+        assert!(
+            depth(parsed) <= MAX_NESTING_DEPTH + 1,
+            "{name} nests too deep"
+        );
+
+        let rendered = MessagePreprocessor::render_bbcode(parsed);
+        let reparsed = MessagePreprocessor::parse_bbcode(&rendered);
+        assert_eq!(
+            MessagePreprocessor::render_bbcode(&reparsed),
+            rendered,
+            "{name} does not render stably"
+        );
+    }
+}
+
+/// Quadratic behaviour on these inputs takes minutes at this size; linear parsing takes
+/// milliseconds, so the budget leaves room for slow debug builds and busy machines.
+#[test]
+fn pathological_inputs_parse_in_linear_time() {
+    const REPEAT: usize = 20_000;
+    let inputs = [
+        "[".repeat(REPEAT * 10),
+        format!("{}]", "[".repeat(REPEAT * 10)),
+        format!("[code]{}]", "[".repeat(REPEAT * 10)),
+        "[code]".repeat(REPEAT),
+        "[img]".repeat(REPEAT),
+        format!(
+            "{}{}",
+            "[spoiler]".repeat(REPEAT),
+            "[/quote]".repeat(REPEAT)
+        ),
+        format!("https://x.example/{}", ")".repeat(REPEAT * 10)),
+    ];
+
+    for message in inputs {
+        let started = Instant::now();
+        MessagePreprocessor::preprocess_message(&message);
+        let _ = MessagePreprocessor::validate_only(&message);
+        assert!(
+            started.elapsed() < Duration::from_secs(5),
+            "{}... took {:?}",
+            &message[..16],
+            started.elapsed()
+        );
+    }
+}